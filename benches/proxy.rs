@@ -0,0 +1,191 @@
+//! Proxy-layer benchmarks: the gateway's routes served over HTTP in front of
+//! a mock tapd on loopback, so the numbers move with the gateway's own
+//! overhead (routing, middleware, JSON handling) rather than with tapd.
+//!
+//! ```bash
+//! cargo bench --bench proxy
+//! ```
+//!
+//! For load against a real deployment, including the WebSocket proxy, use
+//! `taproot-assets-rest-gateway load`.
+
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use reqwest::Client;
+use serde_json::json;
+use std::net::SocketAddr;
+use taproot_assets_rest_gateway::api::routes::configure;
+use taproot_assets_rest_gateway::crypto::canonical_json;
+use taproot_assets_rest_gateway::types::{BaseUrl, MacaroonHex};
+
+/// Assets in the mock `ListAssets` answer; enough for a realistic body.
+const MOCK_ASSETS: usize = 50;
+
+/// Requests per iteration of the concurrency benchmarks; divisible by every
+/// concurrency level.
+const BATCH: usize = 64;
+
+fn mock_assets() -> serde_json::Value {
+    let assets: Vec<_> = (0..MOCK_ASSETS)
+        .map(|i| {
+            json!({
+                "asset_genesis": {
+                    "genesis_point": format!("{:064x}:0", i),
+                    "name": format!("bench-asset-{i}"),
+                    "asset_id": format!("{:064x}", i),
+                    "asset_type": "NORMAL",
+                    "output_index": 0
+                },
+                "amount": "100000",
+                "script_key": format!("02{:064x}", i),
+                "chain_anchor": {
+                    "anchor_outpoint": format!("{:064x}:1", i),
+                    "block_height": 100 + i
+                }
+            })
+        })
+        .collect();
+    json!({ "assets": assets })
+}
+
+/// Serves `factory` on a free loopback port from its own thread.
+fn serve<F, T>(factory: F) -> SocketAddr
+where
+    F: Fn() -> App<T> + Send + Clone + 'static,
+    T: ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse,
+            Error = actix_web::Error,
+            InitError = (),
+        > + 'static,
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        actix_rt::System::new().block_on(async move {
+            let server = HttpServer::new(factory)
+                .workers(2)
+                .bind(("127.0.0.1", 0))
+                .expect("bind");
+            tx.send(server.addrs()[0]).unwrap();
+            server.run().await.ok();
+        })
+    });
+    rx.recv().unwrap()
+}
+
+/// A tapd stand-in answering the routes the benchmarks hit.
+fn mock_tapd() -> SocketAddr {
+    let assets = mock_assets();
+    serve(move || {
+        let assets = assets.clone();
+        App::new()
+            .route(
+                "/v1/taproot-assets/getinfo",
+                web::get().to(|| async {
+                    HttpResponse::Ok().json(json!({
+                        "version": "0.8.0-bench",
+                        "network": "regtest",
+                        "block_height": 1000
+                    }))
+                }),
+            )
+            .route(
+                "/v1/taproot-assets/assets",
+                web::get().to(move || {
+                    let assets = assets.clone();
+                    async move { HttpResponse::Ok().json(assets) }
+                }),
+            )
+    })
+}
+
+/// The gateway's routes, proxying to `tapd`.
+fn gateway(tapd: SocketAddr) -> SocketAddr {
+    let client = web::Data::new(Client::new());
+    let base_url = web::Data::new(BaseUrl(format!("http://{tapd}")));
+    let macaroon = web::Data::new(MacaroonHex("00".to_string()));
+    serve(move || {
+        App::new()
+            .app_data(client.clone())
+            .app_data(base_url.clone())
+            .app_data(macaroon.clone())
+            .configure(configure)
+    })
+}
+
+fn hot_routes(c: &mut Criterion) {
+    let gateway = gateway(mock_tapd());
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = Client::new();
+
+    let mut group = c.benchmark_group("hot_routes");
+    group.throughput(Throughput::Elements(1));
+    for route in [
+        "/health",
+        "/v1/taproot-assets/getinfo",
+        "/v1/taproot-assets/assets",
+    ] {
+        let url = format!("http://{gateway}{route}");
+        group.bench_with_input(BenchmarkId::from_parameter(route), &url, |b, url| {
+            b.to_async(&runtime).iter(|| async {
+                let res = client.get(url).send().await.unwrap();
+                assert!(res.status().is_success(), "{url}: {}", res.status());
+                res.bytes().await.unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// A fixed batch of requests split across concurrent clients, as a
+/// deployment sees them; criterion reports requests per second.
+fn concurrent_load(c: &mut Criterion) {
+    let gateway = gateway(mock_tapd());
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = Client::new();
+    let url = format!("http://{gateway}/v1/taproot-assets/getinfo");
+
+    let mut group = c.benchmark_group("concurrent_getinfo");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    for concurrency in [1, 8, 32] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(concurrency),
+            &concurrency,
+            |b, &concurrency| {
+                b.to_async(&runtime).iter(|| {
+                    let workers: Vec<_> = (0..concurrency)
+                        .map(|_| {
+                            let client = client.clone();
+                            let url = url.clone();
+                            tokio::spawn(async move {
+                                for _ in 0..BATCH / concurrency {
+                                    let res = client.get(&url).send().await.unwrap();
+                                    assert!(res.status().is_success());
+                                    res.bytes().await.unwrap();
+                                }
+                            })
+                        })
+                        .collect();
+                    async move {
+                        for worker in workers {
+                            worker.await.unwrap();
+                        }
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn canonical_signing_body(c: &mut Criterion) {
+    let assets = mock_assets();
+    c.bench_function("canonical_json/list_assets", |b| {
+        b.iter(|| canonical_json(std::hint::black_box(&assets)))
+    });
+}
+
+criterion_group!(benches, hot_routes, concurrent_load, canonical_signing_body);
+criterion_main!(benches);
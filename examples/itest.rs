@@ -0,0 +1,42 @@
+//! Boots the `itest/` regtest stack, runs the integration suite against it
+//! and tears the stack down again.
+//!
+//! ```bash
+//! cargo run --example itest                                  # the full suite
+//! cargo run --example itest -- --test burn -- --test-threads=1
+//! ```
+
+use std::path::Path;
+use std::process::ExitCode;
+use taproot_assets_rest_gateway::tests::regtest::{run_suite, RegtestStack};
+
+fn main() -> ExitCode {
+    let repo_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let mut stack = match RegtestStack::up(&repo_dir.join("itest")) {
+        Ok(stack) => stack,
+        Err(e) => {
+            eprintln!("Failed to start the regtest stack: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(e) = stack.start_block_producer() {
+        eprintln!("Failed to fund lnd: {e}");
+        return ExitCode::FAILURE;
+    }
+    let status = run_suite(&stack, repo_dir, &args);
+    // Tear the stack down before reporting, also when the suite failed
+    drop(stack);
+    match status {
+        Ok(status) if status.success() => ExitCode::SUCCESS,
+        Ok(status) => {
+            eprintln!("Integration suite failed: {status}");
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("Failed to run cargo test: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Expiry of receive addresses generated with a TTL. The `address_expiry`
+//! job marks addresses whose TTL has passed, publishes `address.expired`
+//! and queues the address's webhook when it was created with one.
+
+use crate::database::{SharedDatabase, WebhookDelivery};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use tracing::info;
+
+/// Event topic, and webhook event name, for an expired address.
+pub const TOPIC_ADDRESS_EXPIRED: &str = "address.expired";
+
+/// Marks every address whose TTL has passed and announces each one once.
+/// Returns how many expired.
+pub async fn expire_addresses(
+    database: &SharedDatabase,
+    events: &SharedEventBus,
+) -> Result<usize, AppError> {
+    let now = chrono::Utc::now().timestamp();
+    let expired = database.expire_receive_addresses(now).await?;
+    for address in &expired {
+        let payload = serde_json::json!({
+            "event": TOPIC_ADDRESS_EXPIRED,
+            "address": address.address,
+            "asset_id": address.asset_id,
+            "amount": address.amount,
+            "expires_at": address.expires_at,
+            "expired_at": address.expired_at,
+        });
+        events.publish(TOPIC_ADDRESS_EXPIRED, payload.clone());
+        if let Some(webhook) = database.address_webhook(&address.address).await? {
+            database
+                .enqueue_webhook(&WebhookDelivery::new(
+                    format!("{TOPIC_ADDRESS_EXPIRED}:{}", address.address),
+                    TOPIC_ADDRESS_EXPIRED,
+                    webhook.callback_url,
+                    webhook.secret,
+                    payload,
+                ))
+                .await?;
+        }
+    }
+    if !expired.is_empty() {
+        info!("Marked {} receive addresses as expired", expired.len());
+    }
+    Ok(expired.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{open_test_database, AddressWebhook, ReceiveAddress};
+    use crate::event_bus::EventBus;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_expired_address_is_announced_once_with_webhook() {
+        let db = open_test_database().await;
+        let bus: SharedEventBus = Arc::new(EventBus::default());
+        let mut events = bus.subscribe();
+        let now = chrono::Utc::now().timestamp();
+        db.insert_receive_address(&ReceiveAddress {
+            address: "taprt1old".to_string(),
+            asset_id: "aa".to_string(),
+            amount: 100,
+            expires_at: Some(now - 1),
+            expired_at: None,
+            rotated_to: None,
+            created_at: now - 60,
+        })
+        .await
+        .unwrap();
+        db.register_address_webhook(&AddressWebhook {
+            address: "taprt1old".to_string(),
+            asset_id: Some("aa".to_string()),
+            callback_url: "https://shop.example/hook".to_string(),
+            secret: None,
+            created_at: now - 60,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(expire_addresses(&db, &bus).await.unwrap(), 1);
+        assert_eq!(events.recv().await.unwrap().topic, TOPIC_ADDRESS_EXPIRED);
+        let due = db.due_webhooks(now + 1).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, "address.expired:taprt1old");
+
+        assert_eq!(expire_addresses(&db, &bus).await.unwrap(), 0);
+    }
+}
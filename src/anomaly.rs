@@ -0,0 +1,521 @@
+//! Anomaly detection over indexed sends. Per-asset rules from
+//! `ANOMALY_RULES` flag three patterns:
+//!
+//! - volume spikes: more sent within the last hour than `spike` times the
+//!   hourly average of the 24 hours before;
+//! - first-time destinations: a send to an address never sent to before;
+//! - off-hours large sends: at least `large` units sent within the
+//!   `off_hours` UTC window.
+//!
+//! Flagged sends the indexer picks up raise an operator notification. For
+//! assets whose rule has `escalate`, sends through the gateway are checked
+//! before they reach tapd as well; flagged ones are refused unless the
+//! caller approves them with [`APPROVAL_HEADER`].
+
+use crate::api::addresses::{decode_address, DecodeAddrRequest};
+use crate::config::Config;
+use crate::database::{SendActivity, SharedDatabase};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::indexer::{normalize_hex_id, TOPIC_UPDATED};
+use crate::notifier::{Notification, NotificationKind, Notifier};
+use actix_web::{web, HttpRequest};
+use chrono::Timelike;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// Request header that lets a flagged send of an escalating asset through.
+pub const APPROVAL_HEADER: &str = "x-approve-anomaly";
+
+/// Rule key applying to assets without a rule of their own.
+pub const DEFAULT_RULE: &str = "*";
+
+/// The current window volume spikes are measured over.
+const SPIKE_WINDOW_SECS: i64 = 3600;
+
+/// Windows before the current one that make up the baseline.
+const BASELINE_WINDOWS: i64 = 24;
+
+/// Send ids remembered to avoid repeating an alert on every status update;
+/// the set is reset when it grows past this.
+const MAX_REMEMBERED_SENDS: usize = 10_000;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnomalyRule {
+    /// Flag a window volume above this multiple of the baseline average.
+    #[serde(default)]
+    pub spike: Option<f64>,
+    #[serde(default)]
+    pub new_destination: bool,
+    /// UTC hours `[start, end)`; wraps past midnight when start > end.
+    #[serde(default)]
+    pub off_hours: Option<(u32, u32)>,
+    /// Sends of at least this many units count as large in `off_hours`.
+    #[serde(default)]
+    pub large: Option<u64>,
+    /// Hold flagged sends for approval instead of only alerting.
+    #[serde(default)]
+    pub escalate: bool,
+}
+
+impl AnomalyRule {
+    fn parse(entry: &str, options: &str) -> Result<Self, AppError> {
+        let invalid = |detail: &str| {
+            AppError::ValidationError(format!("ANOMALY_RULES entry '{entry}': {detail}"))
+        };
+        let mut rule = AnomalyRule::default();
+        for option in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            let (key, value) = match option.split_once(':') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (option, None),
+            };
+            match (key, value) {
+                ("spike", Some(value)) => {
+                    let factor = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|f| f.is_finite() && *f > 1.0)
+                        .ok_or_else(|| invalid("spike must be a factor above 1"))?;
+                    rule.spike = Some(factor);
+                }
+                ("new_destination", None) => rule.new_destination = true,
+                ("off_hours", Some(value)) => {
+                    let hours = value
+                        .split_once('-')
+                        .and_then(|(start, end)| {
+                            Some((start.trim().parse().ok()?, end.trim().parse().ok()?))
+                        })
+                        .filter(|(start, end): &(u32, u32)| {
+                            *start < 24 && *end < 24 && start != end
+                        })
+                        .ok_or_else(|| {
+                            invalid("off_hours must be start-end UTC hours, e.g. 22-6")
+                        })?;
+                    rule.off_hours = Some(hours);
+                }
+                ("large", Some(value)) => {
+                    let amount = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|a| *a > 0)
+                        .ok_or_else(|| invalid("large must be a positive amount"))?;
+                    rule.large = Some(amount);
+                }
+                ("escalate", None) => rule.escalate = true,
+                _ => return Err(invalid(&format!("unknown option '{option}'"))),
+            }
+        }
+        if rule.off_hours.is_some() != rule.large.is_some() {
+            return Err(invalid("off_hours and large go together"));
+        }
+        if rule.spike.is_none() && !rule.new_destination && rule.off_hours.is_none() {
+            return Err(invalid("no checks enabled"));
+        }
+        Ok(rule)
+    }
+}
+
+/// Rules by asset id, with [`DEFAULT_RULE`] for the rest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnomalyRules(HashMap<String, AnomalyRule>);
+
+impl AnomalyRules {
+    /// Parses `ANOMALY_RULES`: `asset_id=option,...;...`, where an option is
+    /// `spike:<factor>`, `new_destination`, `off_hours:<start>-<end>`,
+    /// `large:<amount>` or `escalate`, and `*` stands for every other asset.
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        let mut rules = HashMap::new();
+        for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (asset, options) = entry.split_once('=').ok_or_else(|| {
+                AppError::ValidationError(format!(
+                    "ANOMALY_RULES entry '{entry}' must be 'asset_id=option,...'"
+                ))
+            })?;
+            let asset = match asset.trim() {
+                DEFAULT_RULE => DEFAULT_RULE.to_string(),
+                asset => normalize_hex_id(asset),
+            };
+            if rules
+                .insert(asset.clone(), AnomalyRule::parse(entry, options)?)
+                .is_some()
+            {
+                return Err(AppError::ValidationError(format!(
+                    "ANOMALY_RULES has two entries for {asset}"
+                )));
+            }
+        }
+        Ok(Self(rules))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The rule for `asset_id`: its own, else the default.
+    pub fn rule_for(&self, asset_id: &str) -> Option<&AnomalyRule> {
+        self.0.get(asset_id).or_else(|| self.0.get(DEFAULT_RULE))
+    }
+
+    /// Whether any asset's flagged sends need approval.
+    pub fn escalates(&self) -> bool {
+        self.0.values().any(|rule| rule.escalate)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    VolumeSpike,
+    NewDestination,
+    OffHoursLargeSend,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub message: String,
+}
+
+/// A send to assess, either indexed already (`id` set) or about to be made.
+#[derive(Debug, Clone, Copy)]
+pub struct SendCandidate<'a> {
+    pub id: Option<&'a str>,
+    pub asset_id: &'a str,
+    pub address: Option<&'a str>,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+/// What `rule` flags about `send`, given the asset's earlier sends.
+pub fn detect(rule: &AnomalyRule, send: &SendCandidate, activity: &SendActivity) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    if let Some(factor) = rule.spike {
+        let average = activity.baseline as f64 / BASELINE_WINDOWS as f64;
+        let volume = activity.recent.saturating_add(send.amount);
+        // Without a baseline there is nothing to spike against
+        if average > 0.0 && volume as f64 > factor * average {
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::VolumeSpike,
+                message: format!(
+                    "{volume} units sent in the last hour, {:.1}x the hourly average of {average:.0}",
+                    volume as f64 / average
+                ),
+            });
+        }
+    }
+    if rule.new_destination && !activity.destination_seen {
+        if let Some(address) = send.address {
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::NewDestination,
+                message: format!("first send to {address}"),
+            });
+        }
+    }
+    if let (Some((start, end)), Some(large)) = (rule.off_hours, rule.large) {
+        let hour = chrono::DateTime::from_timestamp(send.timestamp, 0).map(|t| t.hour());
+        let off_hours = hour.is_some_and(|hour| {
+            if start < end {
+                (start..end).contains(&hour)
+            } else {
+                hour >= start || hour < end
+            }
+        });
+        if off_hours && send.amount >= large {
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::OffHoursLargeSend,
+                message: format!(
+                    "{} units sent off hours ({start:02}:00-{end:02}:00 UTC)",
+                    send.amount
+                ),
+            });
+        }
+    }
+    anomalies
+}
+
+/// Checks `send` against its asset's rule and its earlier sends in `db`.
+pub async fn assess(
+    rules: &AnomalyRules,
+    db: &SharedDatabase,
+    send: &SendCandidate<'_>,
+) -> Result<Vec<Anomaly>, AppError> {
+    let Some(rule) = rules.rule_for(send.asset_id) else {
+        return Ok(Vec::new());
+    };
+    let window_start = send.timestamp - SPIKE_WINDOW_SECS;
+    let activity = db
+        .send_activity(
+            send.asset_id,
+            send.address,
+            window_start - BASELINE_WINDOWS * SPIKE_WINDOW_SECS,
+            window_start,
+            send.id,
+        )
+        .await?;
+    Ok(detect(rule, send, &activity))
+}
+
+/// Whether the request carries the approval header set to `true`.
+pub fn approved(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(APPROVAL_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+}
+
+/// Refuses a send whose destinations would be flagged for an escalating
+/// asset, unless the caller approves it. Assessed against the request's own
+/// database, so tenants are measured against their own history.
+pub async fn check_send(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    addresses: &[String],
+) -> Result<(), AppError> {
+    let Some(config) = req.app_data::<web::Data<Config>>() else {
+        return Ok(());
+    };
+    let rules = &config.anomaly_rules;
+    if !rules.escalates() {
+        return Ok(());
+    }
+    let Some(db) = req.app_data::<web::Data<SharedDatabase>>() else {
+        return Ok(());
+    };
+    let approved = approved(req);
+    let now = chrono::Utc::now().timestamp();
+    for address in addresses {
+        let request = DecodeAddrRequest {
+            addr: address.clone(),
+        };
+        let decoded = decode_address(client, base_url, macaroon_hex, request).await?;
+        let Some(asset_id) = decoded.asset_id.as_deref().map(normalize_hex_id) else {
+            continue;
+        };
+        if !rules.rule_for(&asset_id).is_some_and(|rule| rule.escalate) {
+            continue;
+        }
+        let send = SendCandidate {
+            id: None,
+            asset_id: &asset_id,
+            address: Some(address),
+            amount: decoded
+                .amount
+                .as_deref()
+                .and_then(|a| a.parse().ok())
+                .unwrap_or(0),
+            timestamp: now,
+        };
+        let anomalies = assess(rules, db, &send).await?;
+        if anomalies.is_empty() {
+            continue;
+        }
+        let reasons = anomalies
+            .iter()
+            .map(|a| a.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        if approved {
+            warn!(
+                "Sending flagged send to {} on approval: {}",
+                address, reasons
+            );
+            continue;
+        }
+        return Err(AppError::ValidationError(format!(
+            "Send to {address} needs approval: {reasons}; set {APPROVAL_HEADER}: true to send anyway"
+        )));
+    }
+    Ok(())
+}
+
+/// The notification for `anomalies` found in the indexed `transfer`.
+fn notification(transfer: &Value, asset_id: &str, anomalies: Vec<Anomaly>) -> Notification {
+    let message = anomalies
+        .iter()
+        .map(|a| a.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ");
+    let mut data = transfer.clone();
+    if let Some(map) = data.as_object_mut() {
+        map.insert(
+            "anomalies".to_string(),
+            serde_json::to_value(&anomalies).unwrap_or_default(),
+        );
+    }
+    Notification::new(
+        NotificationKind::Anomaly,
+        "Unusual send".to_string(),
+        format!("send of {asset_id}: {message}"),
+        data,
+    )
+}
+
+/// Assesses every send the indexer publishes and notifies operators of the
+/// flagged ones, once per send.
+pub fn start(
+    rules: AnomalyRules,
+    db: SharedDatabase,
+    events: SharedEventBus,
+    notifier: Arc<Notifier>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut alerted = HashSet::new();
+        let mut events = events.subscribe();
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Anomaly detector lagged, {} events skipped", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if event.topic != TOPIC_UPDATED
+                || event.data.get("kind").and_then(Value::as_str) != Some("send")
+            {
+                continue;
+            }
+            let transfer = &event.data;
+            let (Some(id), Some(asset_id)) = (
+                transfer.get("id").and_then(Value::as_str),
+                transfer.get("asset_id").and_then(Value::as_str),
+            ) else {
+                continue;
+            };
+            if alerted.contains(id) {
+                continue;
+            }
+            let send = SendCandidate {
+                id: Some(id),
+                asset_id,
+                address: transfer.get("address").and_then(Value::as_str),
+                amount: transfer.get("amount").and_then(Value::as_u64).unwrap_or(0),
+                timestamp: transfer
+                    .get("timestamp")
+                    .and_then(Value::as_i64)
+                    .unwrap_or(event.timestamp),
+            };
+            let anomalies = match assess(&rules, &db, &send).await {
+                Ok(anomalies) => anomalies,
+                Err(e) => {
+                    warn!("Failed to assess send {}: {}", id, e);
+                    continue;
+                }
+            };
+            if anomalies.is_empty() {
+                continue;
+            }
+            if alerted.len() >= MAX_REMEMBERED_SENDS {
+                alerted.clear();
+            }
+            alerted.insert(id.to_string());
+            notifier
+                .notify(notification(transfer, asset_id, anomalies))
+                .await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(amount: u64, timestamp: i64) -> SendCandidate<'static> {
+        SendCandidate {
+            id: None,
+            asset_id: "aa",
+            address: Some("taprt1dest"),
+            amount,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_rules_parse_per_asset_with_default() {
+        let rules =
+            AnomalyRules::parse("*=new_destination; AA=spike:3,off_hours:22-6,large:1000,escalate")
+                .unwrap();
+        assert!(rules.escalates());
+        let rule = rules.rule_for("aa").unwrap();
+        assert_eq!(rule.spike, Some(3.0));
+        assert_eq!(rule.off_hours, Some((22, 6)));
+        assert!(rule.escalate && !rule.new_destination);
+        assert!(rules.rule_for("bb").unwrap().new_destination);
+        assert!(AnomalyRules::parse("").unwrap().rule_for("bb").is_none());
+
+        for invalid in [
+            "aa",
+            "aa=spike:0.5",
+            "aa=off_hours:22-6",
+            "aa=off_hours:25-6,large:1",
+            "aa=escalate",
+            "aa=loud",
+            "aa=new_destination;aa=escalate,new_destination",
+        ] {
+            assert!(AnomalyRules::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_detect_flags_spikes_new_destinations_and_off_hours() {
+        let rule = AnomalyRule {
+            spike: Some(3.0),
+            new_destination: true,
+            off_hours: Some((22, 6)),
+            large: Some(1000),
+            escalate: false,
+        };
+        let quiet = SendActivity {
+            recent: 0,
+            baseline: 2400,
+            destination_seen: true,
+        };
+        // 2024-01-01 12:00 and 23:00 UTC
+        let (noon, night) = (1_704_110_400, 1_704_150_000);
+        assert!(detect(&rule, &send(200, noon), &quiet).is_empty());
+
+        let spike = SendActivity {
+            recent: 250,
+            ..quiet
+        };
+        let kinds =
+            |anomalies: Vec<Anomaly>| anomalies.into_iter().map(|a| a.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds(detect(&rule, &send(100, noon), &spike)),
+            [AnomalyKind::VolumeSpike]
+        );
+        let unknown = SendActivity {
+            destination_seen: false,
+            ..quiet
+        };
+        assert_eq!(
+            kinds(detect(&rule, &send(1, noon), &unknown)),
+            [AnomalyKind::NewDestination]
+        );
+        let off_hours_only = AnomalyRule {
+            spike: None,
+            new_destination: false,
+            ..rule.clone()
+        };
+        assert_eq!(
+            kinds(detect(&off_hours_only, &send(1000, night), &unknown)),
+            [AnomalyKind::OffHoursLargeSend]
+        );
+        assert!(detect(&off_hours_only, &send(999, night), &quiet).is_empty());
+        assert!(detect(&off_hours_only, &send(1000, noon), &quiet).is_empty());
+
+        // No history means no spike
+        let fresh = SendActivity {
+            baseline: 0,
+            ..quiet
+        };
+        assert!(detect(&rule, &send(100_000, noon), &fresh).is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+//! Virtual sub-accounts over the one tapd wallet. Receives to addresses
+//! generated for an account are credited to it and sends made through it are
+//! charged to it, so its balance is its own slice of the indexed transfers.
+//! A send through an account is refused unless the account alone can cover
+//! it; sends through `/send` are not charged to any account.
+
+use super::send::{index_send, send_assets, SendRequest};
+use super::{
+    addresses, handle_result, public_url, require_database, validate_asset_id, ListEnvelope,
+    PageParams,
+};
+use crate::anomaly;
+use crate::config::Config;
+use crate::database::{SharedDatabase, SubAccount, SubAccountBalance};
+use crate::destination_guard::check_destinations;
+use crate::error::AppError;
+use crate::indexer::normalize_hex_id;
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::{info, instrument, warn};
+
+/// Longest account name, in bytes.
+const MAX_NAME_LEN: usize = 64;
+
+/// Serializes account sends so two of them cannot both spend the same
+/// balance before either is indexed.
+static SEND_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAccountRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccountAddressRequest {
+    pub asset_id: String,
+    pub amt: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountView {
+    #[serde(flatten)]
+    pub account: SubAccount,
+    pub balances: Vec<SubAccountBalance>,
+    pub min_confirmations: u32,
+}
+
+fn validate_name(name: &str) -> Result<(), AppError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidInput(format!(
+            "Account names are 1 to {MAX_NAME_LEN} letters, digits, '-' or '_'"
+        )))
+    }
+}
+
+fn require_indexer(req: &HttpRequest, config: &Config) -> Result<SharedDatabase, AppError> {
+    if !config.indexer_enabled {
+        return Err(AppError::ServiceUnavailable(
+            "Sub-accounts require INDEXER_ENABLED".to_string(),
+        ));
+    }
+    require_database(req)
+}
+
+async fn load_account(database: &SharedDatabase, name: &str) -> Result<SubAccount, AppError> {
+    validate_name(name)?;
+    database
+        .sub_account(name)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Sub-account {name} not found")))
+}
+
+/// Refuses a send of `wanted` (amount per asset) that the account's
+/// available balance does not cover.
+pub fn authorize_send(
+    account: &str,
+    balances: &[SubAccountBalance],
+    wanted: &BTreeMap<String, u64>,
+) -> Result<(), AppError> {
+    for (asset_id, amount) in wanted {
+        let available = balances
+            .iter()
+            .find(|b| &b.asset_id == asset_id)
+            .map_or(0, |b| b.available);
+        if *amount > available {
+            return Err(AppError::ValidationError(format!(
+                "Sub-account {account} has {available} of asset {asset_id} available, \
+                 {amount} requested"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Amount per asset a send to `addresses` moves, from tapd's decoding.
+async fn send_amounts(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    addresses: &[String],
+) -> Result<BTreeMap<String, u64>, AppError> {
+    let mut wanted = BTreeMap::new();
+    for address in addresses {
+        let request = addresses::DecodeAddrRequest {
+            addr: address.clone(),
+        };
+        let decoded = addresses::decode_address(client, base_url, macaroon_hex, request).await?;
+        let asset_id = decoded.asset_id.as_deref().map(normalize_hex_id);
+        let amount = decoded
+            .amount
+            .as_deref()
+            .and_then(|a| a.parse::<u64>().ok());
+        match (asset_id, amount) {
+            (Some(asset_id), Some(amount)) if amount > 0 => {
+                *wanted.entry(asset_id).or_insert(0) += amount;
+            }
+            _ => {
+                return Err(AppError::ValidationError(format!(
+                    "Address {address} has no fixed asset and amount to charge to the account"
+                )))
+            }
+        }
+    }
+    Ok(wanted)
+}
+
+async fn create(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    body: web::Json<CreateAccountRequest>,
+) -> HttpResponse {
+    let result = async {
+        let database = require_indexer(&req, &config)?;
+        let body = body.into_inner();
+        validate_name(&body.name)?;
+        let account = SubAccount {
+            name: body.name,
+            description: body.description,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        if !database.create_sub_account(&account).await? {
+            return Err(AppError::ValidationError(format!(
+                "Sub-account {} already exists",
+                account.name
+            )));
+        }
+        info!("Created sub-account {}", account.name);
+        Ok(account)
+    }
+    .await;
+    match result {
+        Ok(account) => HttpResponse::build(StatusCode::CREATED)
+            .insert_header((
+                header::LOCATION,
+                public_url(&req, &format!("/v1/gateway/accounts/{}", account.name)),
+            ))
+            .json(account),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
+}
+
+async fn list(req: HttpRequest, config: web::Data<Config>) -> HttpResponse {
+    let result = async {
+        let database = require_indexer(&req, &config)?;
+        let page = PageParams::from_query(req.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let accounts = database.sub_accounts(limit + 1, offset).await?;
+        Ok(ListEnvelope::from_offset_page(accounts, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn get(req: HttpRequest, config: web::Data<Config>, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let database = require_indexer(&req, &config)?;
+        let account = load_account(&database, &path).await?;
+        let balances = database
+            .sub_account_balances(&account.name, config.min_receive_confirmations)
+            .await?;
+        Ok(AccountView {
+            account,
+            balances,
+            min_confirmations: config.min_receive_confirmations,
+        })
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn new_address(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    body: web::Json<AccountAddressRequest>,
+) -> HttpResponse {
+    let result = async {
+        let database = require_indexer(&req, &config)?;
+        let account = load_account(&database, &path).await?;
+        let body = body.into_inner();
+        validate_asset_id(&body.asset_id)?;
+        if body.amt == 0 {
+            return Err(AppError::ValidationError(
+                "amt must be greater than zero".to_string(),
+            ));
+        }
+        let addr = addresses::create_address(
+            client.as_ref(),
+            &base_url.0,
+            &macaroon_hex.0,
+            addresses::NewAddrRequest {
+                asset_id: body.asset_id.clone(),
+                amt: body.amt.to_string(),
+                script_key: None,
+                internal_key: None,
+                tapscript_sibling: None,
+                proof_courier_addr: None,
+                asset_version: None,
+                address_version: None,
+                callback_url: None,
+                callback_secret: None,
+                ttl_secs: None,
+            },
+        )
+        .await?;
+        let encoded = addr.encoded.as_deref().ok_or_else(|| {
+            AppError::SerializationError("tapd returned an address without encoding".to_string())
+        })?;
+        database
+            .add_sub_account_address(&account.name, encoded, &body.asset_id.to_ascii_lowercase())
+            .await?;
+        Ok(addr)
+    }
+    .await;
+    match result {
+        Ok(addr) => HttpResponse::build(StatusCode::CREATED).json(addr),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
+}
+
+#[instrument(skip(req, client, macaroon_hex, config, request))]
+async fn send_from_account(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    config: &Config,
+    name: &str,
+    mut request: SendRequest,
+) -> Result<serde_json::Value, AppError> {
+    let database = require_indexer(req, config)?;
+    let account = load_account(&database, name).await?;
+    request.validate_tags()?;
+    check_destinations(req, client, base_url, macaroon_hex, &request.tap_addrs).await?;
+    anomaly::check_send(req, client, base_url, macaroon_hex, &request.tap_addrs).await?;
+    let wanted = send_amounts(client, base_url, macaroon_hex, &request.tap_addrs).await?;
+
+    let _guard = SEND_LOCK.lock().await;
+    let balances = database
+        .sub_account_balances(&account.name, config.min_receive_confirmations)
+        .await?;
+    authorize_send(&account.name, &balances, &wanted)?;
+
+    let label = request.label.clone();
+    let tags = std::mem::take(&mut request.tags);
+    let address = match request.tap_addrs.as_slice() {
+        [address] => Some(address.clone()),
+        _ => None,
+    };
+    let response = send_assets(client, base_url, macaroon_hex, request).await?;
+    match index_send(&database, label.as_deref(), &tags, address, &response).await {
+        Some(anchor_txid) => {
+            if let Err(e) = database
+                .record_sub_account_send(&account.name, &anchor_txid)
+                .await
+            {
+                warn!(
+                    "Failed to charge send {} to sub-account {}: {}",
+                    anchor_txid, account.name, e
+                );
+            }
+        }
+        None => warn!(
+            "Send from sub-account {} has no anchor transaction to charge",
+            account.name
+        ),
+    }
+    Ok(response)
+}
+
+async fn send(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    body: web::Json<SendRequest>,
+) -> HttpResponse {
+    handle_result(
+        send_from_account(
+            &req,
+            client.as_ref(),
+            &base_url.0,
+            &macaroon_hex.0,
+            config.as_ref(),
+            &path,
+            body.into_inner(),
+        )
+        .await,
+    )
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/accounts")
+            .route(web::get().to(list))
+            .route(web::post().to(create)),
+    )
+    .service(web::resource("/accounts/{name}").route(web::get().to(get)))
+    .service(web::resource("/accounts/{name}/addrs").route(web::post().to(new_address)))
+    .service(web::resource("/accounts/{name}/send").route(web::post().to(send)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::AssetPosition;
+
+    fn balance(asset_id: &str, available: u64) -> SubAccountBalance {
+        SubAccountBalance {
+            asset_id: asset_id.to_string(),
+            position: AssetPosition {
+                settled_in: available,
+                ..Default::default()
+            },
+            available,
+        }
+    }
+
+    #[test]
+    fn test_authorize_send_needs_every_asset_covered() {
+        let balances = [balance("aa", 100), balance("bb", 5)];
+        let wanted = |pairs: &[(&str, u64)]| -> BTreeMap<String, u64> {
+            pairs.iter().map(|(a, n)| (a.to_string(), *n)).collect()
+        };
+        assert!(authorize_send("sales", &balances, &wanted(&[("aa", 100), ("bb", 5)])).is_ok());
+        let err = authorize_send("sales", &balances, &wanted(&[("aa", 10), ("bb", 6)]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("has 5 of asset bb available, 6 requested"));
+        assert!(authorize_send("sales", &balances, &wanted(&[("cc", 1)])).is_err());
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("treasury_eu-1").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("a/b").is_err());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+    }
+}
@@ -1,11 +1,20 @@
-use super::{handle_result, parse_upstream};
+use super::info::get_info;
+use super::supply::cached_supply;
+use super::{
+    backend, handle_result, parse_upstream, require_database, validate_callback_url,
+    validate_taproot_address, ListEnvelope, PageParams,
+};
+use crate::config::Config;
+use crate::database::{AddressWebhook, ReceiveAddress, SharedDatabase};
+use crate::destination_guard::DestinationGuard;
 use crate::error::AppError;
+use crate::indexer::normalize_hex_id;
 use crate::types::{BaseUrl, MacaroonHex};
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tracing::{debug, instrument, warn};
+use tracing::{debug, info, instrument, warn};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Addr {
@@ -21,6 +30,13 @@ pub struct Addr {
     pub proof_courier_addr: Option<String>,
     pub asset_version: Option<String>,
     pub address_version: Option<String>,
+    /// Gateway-only: when the address's TTL runs out, for addresses
+    /// generated with one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    /// Gateway-only: whether the TTL has run out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expired: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,6 +55,17 @@ pub struct NewAddrRequest {
     pub asset_version: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub address_version: Option<String>,
+    /// Gateway-only: URL notified once a receive to this address settles.
+    /// Never forwarded to tapd.
+    #[serde(default, skip_serializing)]
+    pub callback_url: Option<String>,
+    /// Gateway-only: HMAC key for the callback's `X-Gateway-Signature`.
+    #[serde(default, skip_serializing)]
+    pub callback_secret: Option<String>,
+    /// Gateway-only: seconds until the address is flagged as expired.
+    /// Never forwarded to tapd.
+    #[serde(default, skip_serializing)]
+    pub ttl_secs: Option<u64>,
 }
 
 impl NewAddrRequest {
@@ -86,6 +113,20 @@ impl NewAddrRequest {
             }
         }
 
+        if self.ttl_secs == Some(0) {
+            return Err(AppError::ValidationError(
+                "ttl_secs must be greater than zero".to_string(),
+            ));
+        }
+
+        if let Some(callback_url) = &self.callback_url {
+            validate_callback_url(callback_url)?;
+        } else if self.callback_secret.is_some() {
+            return Err(AppError::ValidationError(
+                "callback_secret requires callback_url".to_string(),
+            ));
+        }
+
         Ok(())
     }
 }
@@ -118,7 +159,7 @@ pub async fn list_addresses(
 ) -> Result<Vec<Addr>, AppError> {
     debug!("Fetching taproot asset addresses");
 
-    let mut url = format!("{base_url}/v1/taproot-assets/addrs");
+    let mut request = backend(client, base_url, macaroon_hex).get("/v1/taproot-assets/addrs");
 
     // Build query parameters if provided
     if let Some(query_params) = params {
@@ -137,20 +178,10 @@ pub async fn list_addresses(
             query_parts.push(format!("offset={offset}"));
         }
 
-        if !query_parts.is_empty() {
-            url.push('?'); // Changed from push_str("?")
-            url.push_str(&query_parts.join("&"));
-        }
+        request = request.query(&query_parts.join("&"));
     }
 
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
-        .await
-        .map_err(AppError::RequestError)?;
-
-    let json = parse_upstream::<HashMap<String, Vec<Addr>>>(response).await?;
+    let json = request.fetch::<HashMap<String, Vec<Addr>>>().await?;
 
     let mut addresses = json.get("addrs").cloned().unwrap_or_default();
 
@@ -181,14 +212,11 @@ pub async fn create_address(
 
     debug!("Creating new address for asset: {}", request.asset_id);
 
-    let url = format!("{base_url}/v1/taproot-assets/addrs");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    let response = backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/addrs")
         .json(&request)
         .send()
-        .await
-        .map_err(AppError::RequestError)?;
+        .await?;
 
     let addr = parse_upstream::<Addr>(response).await?;
 
@@ -212,16 +240,11 @@ pub async fn decode_address(
         ));
     }
 
-    let url = format!("{base_url}/v1/taproot-assets/addrs/decode");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/addrs/decode")
         .json(&request)
-        .send()
+        .fetch::<Addr>()
         .await
-        .map_err(AppError::RequestError)?;
-
-    parse_upstream::<Addr>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -233,14 +256,11 @@ pub async fn receive_events(
 ) -> Result<serde_json::Value, AppError> {
     debug!("Subscribing to receive events");
 
-    let url = format!("{base_url}/v1/taproot-assets/addrs/receives");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    let response = backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/addrs/receives")
         .json(&request)
         .send()
-        .await
-        .map_err(AppError::RequestError)?;
+        .await?;
 
     if !response.status().is_success() {
         warn!(
@@ -252,36 +272,501 @@ pub async fn receive_events(
     parse_upstream::<serde_json::Value>(response).await
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// The check could not be run, e.g. because tapd or the universe did
+    /// not answer.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AddressCheck {
+    pub check: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl AddressCheck {
+    fn new(check: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            check,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// A decoded address with the gateway's checks on it. `safe_to_pay` holds
+/// only when every check passed.
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressValidation {
+    pub address: String,
+    /// tapd's decoding, absent when it rejected the address.
+    pub decoded: Option<Addr>,
+    /// Network the address is for, from its prefix.
+    pub network: Option<&'static str>,
+    /// Network tapd runs on.
+    pub node_network: Option<String>,
+    /// Whether this node's tapd created the address, i.e. paying it would
+    /// pay ourselves. `None` when the address list could not be read.
+    pub generated_here: Option<bool>,
+    pub checks: Vec<AddressCheck>,
+    pub safe_to_pay: bool,
+}
+
+/// Network named by an address's human-readable part. Testnet and signet
+/// share `taptb`, so both read as `testnet`.
+pub fn address_network(address: &str) -> Option<&'static str> {
+    match address.rsplit_once('1')?.0 {
+        "tapbc" => Some("mainnet"),
+        "taptb" => Some("testnet"),
+        "taprt" => Some("regtest"),
+        "tapsb" => Some("simnet"),
+        _ => None,
+    }
+}
+
+fn network_check(address_network: Option<&str>, node_network: Option<&str>) -> AddressCheck {
+    // tapd reports testnet3/testnet4/signet, which all share an address prefix
+    let node = node_network.map(|n| match n {
+        "signet" | "testnet3" | "testnet4" => "testnet",
+        other => other,
+    });
+    match (address_network, node) {
+        (Some(address), Some(node)) if address == node => {
+            AddressCheck::new("network", CheckStatus::Pass, format!("{address} address"))
+        }
+        (Some(address), Some(node)) => AddressCheck::new(
+            "network",
+            CheckStatus::Fail,
+            format!("{address} address but the node runs on {node}"),
+        ),
+        _ => AddressCheck::new(
+            "network",
+            CheckStatus::Unknown,
+            "tapd did not report its network",
+        ),
+    }
+}
+
+/// Zero-amount addresses (V2) let the sender choose the amount.
+fn amount_check(amount: u64, circulating: u64) -> AddressCheck {
+    if amount == 0 {
+        AddressCheck::new("amount", CheckStatus::Pass, "address accepts any amount")
+    } else if amount > circulating {
+        AddressCheck::new(
+            "amount",
+            CheckStatus::Fail,
+            format!("requests {amount} but only {circulating} are in circulation"),
+        )
+    } else {
+        AddressCheck::new(
+            "amount",
+            CheckStatus::Pass,
+            format!("{amount} of {circulating} in circulation"),
+        )
+    }
+}
+
+/// Decodes `address` through tapd and checks that it is for the node's
+/// network, names an asset the universe knows and asks for no more than
+/// that asset's circulating supply. Problems are reported as failed checks
+/// rather than errors.
+#[instrument(skip(req, client, macaroon_hex))]
+pub async fn validate_address(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    address: &str,
+) -> Result<AddressValidation, AppError> {
+    if address.trim().is_empty() {
+        return Err(AppError::ValidationError(
+            "address cannot be empty".to_string(),
+        ));
+    }
+    let mut validation = AddressValidation {
+        address: address.to_string(),
+        decoded: None,
+        network: address_network(address),
+        node_network: None,
+        generated_here: None,
+        checks: Vec::new(),
+        safe_to_pay: false,
+    };
+
+    if let Err(e) = validate_taproot_address(address) {
+        validation.checks.push(AddressCheck::new(
+            "format",
+            CheckStatus::Fail,
+            e.to_string(),
+        ));
+        return Ok(validation);
+    }
+    validation.checks.push(AddressCheck::new(
+        "format",
+        CheckStatus::Pass,
+        "Taproot Assets address",
+    ));
+
+    validation.node_network = get_info(client, base_url, macaroon_hex)
+        .await
+        .ok()
+        .and_then(|info| info.get("network")?.as_str().map(str::to_string));
+    validation.checks.push(network_check(
+        validation.network,
+        validation.node_network.as_deref(),
+    ));
+
+    let request = DecodeAddrRequest {
+        addr: address.to_string(),
+    };
+    let decoded = match decode_address(client, base_url, macaroon_hex, request).await {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            validation.checks.push(AddressCheck::new(
+                "decode",
+                CheckStatus::Fail,
+                e.to_string(),
+            ));
+            return Ok(validation);
+        }
+    };
+    validation.checks.push(AddressCheck::new(
+        "decode",
+        CheckStatus::Pass,
+        "tapd decoded the address",
+    ));
+    let problems = DestinationGuard::from_request(req).assess(address, &decoded);
+    validation.checks.push(if problems.is_empty() {
+        AddressCheck::new(
+            "destination",
+            CheckStatus::Pass,
+            "not a denylisted or unspendable destination",
+        )
+    } else {
+        AddressCheck::new("destination", CheckStatus::Fail, problems.join("; "))
+    });
+
+    let asset_id = decoded.asset_id.as_deref().map(normalize_hex_id);
+    let amount = decoded
+        .amount
+        .as_deref()
+        .and_then(|a| a.parse::<u64>().ok())
+        .unwrap_or(0);
+    let supply = match &asset_id {
+        Some(asset_id) => Some(cached_supply(req, client, base_url, macaroon_hex, asset_id).await),
+        None => None,
+    };
+    match supply {
+        Some(Ok(supply)) => {
+            validation.checks.push(AddressCheck::new(
+                "known_asset",
+                CheckStatus::Pass,
+                format!("{} issuance(s) in the universe", supply.issuance_count),
+            ));
+            validation
+                .checks
+                .push(amount_check(amount, supply.circulating));
+        }
+        Some(Err(AppError::NotFound(_))) => {
+            validation.checks.push(AddressCheck::new(
+                "known_asset",
+                CheckStatus::Fail,
+                "asset has no issuance proofs in the universe",
+            ));
+        }
+        Some(Err(e)) => {
+            validation.checks.push(AddressCheck::new(
+                "known_asset",
+                CheckStatus::Unknown,
+                format!("supply lookup failed: {e}"),
+            ));
+        }
+        None => {
+            validation.checks.push(AddressCheck::new(
+                "known_asset",
+                CheckStatus::Fail,
+                "address names no asset",
+            ));
+        }
+    }
+
+    validation.generated_here = list_addresses(client, base_url, macaroon_hex, None)
+        .await
+        .ok()
+        .map(|addrs| addrs.iter().any(|a| a.encoded.as_deref() == Some(address)));
+    validation.decoded = Some(decoded);
+    validation.safe_to_pay = validation
+        .checks
+        .iter()
+        .all(|c| c.status == CheckStatus::Pass);
+    debug!("Validated address: safe_to_pay={}", validation.safe_to_pay);
+    Ok(validation)
+}
+
 // Handler functions for actix-web routes
 async fn list(
+    req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     query: web::Query<AddressQueryParams>,
 ) -> HttpResponse {
-    match list_addresses(client.as_ref(), &base_url.0, &macaroon_hex.0, Some(&query)).await {
+    let page = match PageParams::from_query(req.query_string()) {
+        Ok(page) => page,
+        Err(e) => return handle_result::<serde_json::Value>(Err(e)),
+    };
+    if page.wants_envelope() {
+        // Page over the full filtered listing; tapd's own limit/offset would
+        // leave no way to compute the next cursor or total.
+        let params = AddressQueryParams {
+            limit: None,
+            offset: None,
+            ..query.into_inner()
+        };
+        let result = async {
+            let mut addrs =
+                list_addresses(client.as_ref(), &base_url.0, &macaroon_hex.0, Some(&params))
+                    .await?;
+            flag_expiry(&req, &mut addrs).await?;
+            ListEnvelope::from_full_list(addrs, &page).map(|e| e.with_next_link(&req))
+        }
+        .await;
+        return handle_result(result);
+    }
+    let result = async {
+        let mut addrs =
+            list_addresses(client.as_ref(), &base_url.0, &macaroon_hex.0, Some(&query)).await?;
+        flag_expiry(&req, &mut addrs).await?;
+        Ok::<_, AppError>(addrs)
+    }
+    .await;
+    match result {
         Ok(addrs) => HttpResponse::Ok().json(serde_json::json!({ "addrs": addrs })),
         Err(e) => {
             let status = e.status_code();
-            HttpResponse::build(status).json(serde_json::json!({
+            let mut response = HttpResponse::build(status).json(serde_json::json!({
                 "error": e.to_string(),
                 "type": format!("{:?}", e)
-            }))
+            }));
+            crate::error::mark_upstream(&mut response, &e);
+            response
+        }
+    }
+}
+
+/// Sets `expires_at`/`expired` on addresses generated with a TTL. Without
+/// a database there is nothing to flag.
+async fn flag_expiry(req: &HttpRequest, addrs: &mut [Addr]) -> Result<(), AppError> {
+    let Some(database) = req.app_data::<web::Data<SharedDatabase>>() else {
+        return Ok(());
+    };
+    let encoded: Vec<String> = addrs.iter().filter_map(|a| a.encoded.clone()).collect();
+    let tracked = database.receive_addresses(&encoded).await?;
+    let now = chrono::Utc::now().timestamp();
+    for addr in addrs {
+        let Some(entry) = tracked
+            .iter()
+            .find(|t| Some(&t.address) == addr.encoded.as_ref())
+        else {
+            continue;
+        };
+        if entry.expires_at.is_some() {
+            addr.expires_at = entry.expires_at;
+            addr.expired = Some(entry.is_expired(now));
+        }
+    }
+    Ok(())
+}
+
+/// Where and how to notify once a receive to the address settles.
+/// Persistence is checked before tapd is called so a webhook is never
+/// silently dropped.
+fn webhook_registration(
+    req: &HttpRequest,
+    config: &Config,
+    request: &NewAddrRequest,
+) -> Result<Option<(SharedDatabase, String, Option<String>)>, AppError> {
+    let Some(callback_url) = request.callback_url.clone() else {
+        return Ok(None);
+    };
+    request.validate()?;
+    if !config.indexer_enabled {
+        return Err(AppError::ServiceUnavailable(
+            "Address webhooks require INDEXER_ENABLED".to_string(),
+        ));
+    }
+    Ok(Some((
+        require_database(req)?,
+        callback_url,
+        request.callback_secret.clone(),
+    )))
+}
+
+async fn register_webhook(
+    (database, callback_url, secret): (SharedDatabase, String, Option<String>),
+    address: &str,
+    asset_id: &str,
+) -> Result<(), AppError> {
+    database
+        .register_address_webhook(&AddressWebhook {
+            address: address.to_string(),
+            asset_id: Some(asset_id.to_string()),
+            callback_url,
+            secret,
+            created_at: chrono::Utc::now().timestamp(),
+        })
+        .await
+}
+
+/// Creates the address and, when asked to, registers its webhook and
+/// stores its TTL.
+async fn create_with_webhook(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    config: &Config,
+    request: NewAddrRequest,
+) -> Result<Addr, AppError> {
+    let webhook = webhook_registration(req, config, &request)?;
+    let expiry = match request.ttl_secs {
+        Some(ttl) => Some((require_database(req)?, ttl)),
+        None => None,
+    };
+    let asset_id = request.asset_id.clone();
+    let amount = request.amt.parse::<u64>().unwrap_or(0);
+
+    let mut addr = create_address(client, base_url, macaroon_hex, request).await?;
+
+    if webhook.is_none() && expiry.is_none() {
+        return Ok(addr);
+    }
+    let address = addr.encoded.clone().ok_or_else(|| {
+        AppError::SerializationError("tapd returned an address without encoding".to_string())
+    })?;
+    if let Some(webhook) = webhook {
+        register_webhook(webhook, &address, &asset_id).await?;
+    }
+    if let Some((database, ttl)) = expiry {
+        let now = chrono::Utc::now().timestamp();
+        let expires_at = now + ttl as i64;
+        database
+            .insert_receive_address(&ReceiveAddress {
+                address,
+                asset_id: asset_id.to_ascii_lowercase(),
+                amount,
+                expires_at: Some(expires_at),
+                expired_at: None,
+                rotated_to: None,
+                created_at: now,
+            })
+            .await?;
+        addr.expires_at = Some(expires_at);
+        addr.expired = Some(false);
+    }
+
+    Ok(addr)
+}
+
+/// Response of `POST /v1/gateway/addrs/receive`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiveAddressResponse {
+    #[serde(flatten)]
+    pub address: ReceiveAddress,
+    /// Whether an earlier, unexpired address was handed out again.
+    pub reused: bool,
+    /// The expired address this one replaced.
+    pub rotated_from: Option<String>,
+}
+
+/// Hands out the current address for the asset and amount while its TTL
+/// lasts, and rotates to a fresh one once it has expired. The TTL defaults
+/// to `ADDRESS_DEFAULT_TTL_SECS`; without one the address never rotates.
+#[instrument(skip(req, client, macaroon_hex, config, request))]
+async fn receive_address(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    config: &Config,
+    mut request: NewAddrRequest,
+) -> Result<ReceiveAddressResponse, AppError> {
+    request.validate()?;
+    let database = require_database(req)?;
+    let asset_id = request.asset_id.to_ascii_lowercase();
+    let amount = request.amt.parse::<u64>().unwrap_or(0);
+    let ttl = request
+        .ttl_secs
+        .or((config.address_default_ttl_secs > 0).then_some(config.address_default_ttl_secs));
+    let now = chrono::Utc::now().timestamp();
+
+    let latest = database.latest_receive_address(&asset_id, amount).await?;
+    if let Some(current) = latest.clone().filter(|a| !a.is_expired(now)) {
+        if let Some(webhook) = webhook_registration(req, config, &request)? {
+            register_webhook(webhook, &current.address, &asset_id).await?;
         }
+        return Ok(ReceiveAddressResponse {
+            address: current,
+            reused: true,
+            rotated_from: None,
+        });
     }
+
+    // The receive address is stored below whether or not it has a TTL
+    request.ttl_secs = None;
+    let addr = create_with_webhook(req, client, base_url, macaroon_hex, config, request).await?;
+    let address = ReceiveAddress {
+        address: addr.encoded.ok_or_else(|| {
+            AppError::SerializationError("tapd returned an address without encoding".to_string())
+        })?,
+        asset_id,
+        amount,
+        expires_at: ttl.map(|ttl| now + ttl as i64),
+        expired_at: None,
+        rotated_to: None,
+        created_at: now,
+    };
+    database.insert_receive_address(&address).await?;
+    let rotated_from = match latest {
+        Some(previous) => {
+            database
+                .rotate_receive_address(&previous.address, &address.address)
+                .await?;
+            info!(
+                "Rotated receive address for {} from {} to {}",
+                address.asset_id, previous.address, address.address
+            );
+            Some(previous.address)
+        }
+        None => None,
+    };
+    Ok(ReceiveAddressResponse {
+        address,
+        reused: false,
+        rotated_from,
+    })
 }
 
 async fn create(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
     req: web::Json<NewAddrRequest>,
 ) -> HttpResponse {
     handle_result(
-        create_address(
+        create_with_webhook(
+            &http_req,
             client.as_ref(),
             &base_url.0,
             &macaroon_hex.0,
+            config.as_ref(),
             req.into_inner(),
         )
         .await,
@@ -305,6 +790,46 @@ async fn decode(
     )
 }
 
+async fn receive_handler(
+    http_req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    req: web::Json<NewAddrRequest>,
+) -> HttpResponse {
+    handle_result(
+        receive_address(
+            &http_req,
+            client.as_ref(),
+            &base_url.0,
+            &macaroon_hex.0,
+            config.as_ref(),
+            req.into_inner(),
+        )
+        .await,
+    )
+}
+
+async fn validate(
+    http_req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    req: web::Json<DecodeAddrRequest>,
+) -> HttpResponse {
+    handle_result(
+        validate_address(
+            &http_req,
+            client.as_ref(),
+            &base_url.0,
+            &macaroon_hex.0,
+            &req.addr,
+        )
+        .await,
+    )
+}
+
 async fn receive(
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
@@ -332,10 +857,36 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
     .service(web::resource("/addrs/receives").route(web::post().to(receive)));
 }
 
+pub fn configure_gateway(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/addrs/decode").route(web::post().to(validate)))
+        .service(web::resource("/addrs/receive").route(web::post().to(receive_handler)));
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_address_checks() {
+        assert_eq!(address_network("tapbc1qqqsqqspqq"), Some("mainnet"));
+        assert_eq!(address_network("taprt1qqqsqqspqq"), Some("regtest"));
+        assert_eq!(address_network("bc1qqqsqqspqq"), None);
+
+        let check = network_check(Some("testnet"), Some("signet"));
+        assert_eq!(check.status, CheckStatus::Pass);
+        let check = network_check(Some("mainnet"), Some("regtest"));
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert_eq!(check.detail, "mainnet address but the node runs on regtest");
+        assert_eq!(
+            network_check(Some("regtest"), None).status,
+            CheckStatus::Unknown
+        );
+
+        assert_eq!(amount_check(0, 10).status, CheckStatus::Pass);
+        assert_eq!(amount_check(10, 10).status, CheckStatus::Pass);
+        assert_eq!(amount_check(11, 10).status, CheckStatus::Fail);
+    }
+
     #[test]
     fn test_validate_empty_asset_id() {
         let request = NewAddrRequest {
@@ -347,6 +898,9 @@ mod tests {
             proof_courier_addr: None,
             asset_version: None,
             address_version: None,
+            callback_url: None,
+            callback_secret: None,
+            ttl_secs: None,
         };
 
         let result = request.validate();
@@ -368,6 +922,9 @@ mod tests {
             proof_courier_addr: None,
             asset_version: None,
             address_version: None,
+            callback_url: None,
+            callback_secret: None,
+            ttl_secs: None,
         };
 
         let result = request.validate();
@@ -389,6 +946,9 @@ mod tests {
             proof_courier_addr: None,
             asset_version: None,
             address_version: None,
+            callback_url: None,
+            callback_secret: None,
+            ttl_secs: None,
         };
 
         assert!(request.validate().is_err());
@@ -405,6 +965,9 @@ mod tests {
             proof_courier_addr: None,
             asset_version: None,
             address_version: None,
+            callback_url: None,
+            callback_secret: None,
+            ttl_secs: None,
         };
 
         let result = request.validate();
@@ -423,6 +986,9 @@ mod tests {
             proof_courier_addr: None,
             asset_version: None,
             address_version: None,
+            callback_url: None,
+            callback_secret: None,
+            ttl_secs: None,
         };
 
         assert!(request.validate().is_err());
@@ -439,8 +1005,77 @@ mod tests {
             proof_courier_addr: None,
             asset_version: None,
             address_version: None,
+            callback_url: None,
+            callback_secret: None,
+            ttl_secs: None,
         };
 
         assert!(request.validate().is_ok());
     }
+
+    #[test]
+    fn test_validate_ttl() {
+        let request = |ttl_secs| NewAddrRequest {
+            asset_id: "valid_asset_id".to_string(),
+            amt: "1000".to_string(),
+            script_key: None,
+            internal_key: None,
+            tapscript_sibling: None,
+            proof_courier_addr: None,
+            asset_version: None,
+            address_version: None,
+            callback_url: None,
+            callback_secret: None,
+            ttl_secs,
+        };
+
+        assert!(request(Some(60)).validate().is_ok());
+        assert!(matches!(
+            request(Some(0)).validate(),
+            Err(AppError::ValidationError(msg)) if msg == "ttl_secs must be greater than zero"
+        ));
+        // Gateway-only, like the callback fields
+        let forwarded = serde_json::to_value(request(Some(60))).unwrap();
+        assert!(forwarded.get("ttl_secs").is_none());
+    }
+
+    #[test]
+    fn test_validate_callback_url() {
+        let request = |url: Option<&str>, secret: Option<&str>| NewAddrRequest {
+            asset_id: "valid_asset_id".to_string(),
+            amt: "1000".to_string(),
+            script_key: None,
+            internal_key: None,
+            tapscript_sibling: None,
+            proof_courier_addr: None,
+            asset_version: None,
+            address_version: None,
+            callback_url: url.map(str::to_string),
+            callback_secret: secret.map(str::to_string),
+            ttl_secs: None,
+        };
+
+        assert!(request(Some("https://shop.example/hooks/tap"), Some("k"))
+            .validate()
+            .is_ok());
+        assert!(request(Some("ftp://shop.example"), None)
+            .validate()
+            .is_err());
+        assert!(request(Some("not a url"), None).validate().is_err());
+        assert!(request(None, Some("k")).validate().is_err());
+    }
+
+    #[test]
+    fn test_callback_fields_are_not_forwarded_to_tapd() {
+        let request: NewAddrRequest = serde_json::from_value(serde_json::json!({
+            "asset_id": "aa",
+            "amt": "1",
+            "callback_url": "https://shop.example/hook",
+            "callback_secret": "k"
+        }))
+        .unwrap();
+        let forwarded = serde_json::to_value(&request).unwrap();
+        assert!(forwarded.get("callback_url").is_none());
+        assert!(forwarded.get("callback_secret").is_none());
+    }
 }
@@ -0,0 +1,735 @@
+use super::{handle_result, require_database};
+use crate::audit::SharedAuditExporter;
+use crate::config::Config;
+use crate::database::{FeatureFlagRecord, NetworkAclRecord, RouteGroupRecord, SharedDatabase};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::feature_flags::{Feature, SharedFeatureFlags};
+use crate::indexer::Indexer;
+use crate::inflight;
+use crate::lockout::SharedAuthLockouts;
+use crate::macaroon::Macaroon;
+use crate::maintenance::SharedMaintenance;
+use crate::network_acl::{parse_network, AclAction, AclRule, SharedNetworkAcl};
+use crate::route_groups::{RouteGroup, SharedRouteGroups};
+use crate::scheduler::SharedScheduler;
+use crate::shed::SharedShed;
+use crate::timeouts::SharedTimeoutTuner;
+use crate::types::{BaseUrl, MacaroonHex};
+use crate::websocket::connection_manager::WebSocketConnectionManager;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Path exempt from the maintenance guard so the mode can be switched off.
+pub const MAINTENANCE_PATH: &str = "/v1/gateway/admin/maintenance";
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+fn maintenance_mode(req: &HttpRequest) -> Result<SharedMaintenance, AppError> {
+    req.app_data::<web::Data<SharedMaintenance>>()
+        .map(|m| m.get_ref().clone())
+        .ok_or_else(|| {
+            AppError::ServiceUnavailable("Maintenance mode is not configured".to_string())
+        })
+}
+
+async fn get_maintenance(req: HttpRequest) -> HttpResponse {
+    handle_result(maintenance_mode(&req).map(|m| m.status()))
+}
+
+async fn set_maintenance(req: HttpRequest, body: web::Json<MaintenanceRequest>) -> HttpResponse {
+    let result = maintenance_mode(&req).map(|mode| {
+        let body = body.into_inner();
+        let status = mode.set(body.enabled, body.message);
+        warn!(
+            "Maintenance mode {} via admin API",
+            if status.enabled {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        status
+    });
+    handle_result(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RouteGroupRequest {
+    pub enabled: bool,
+}
+
+fn route_group_toggles(req: &HttpRequest) -> Result<SharedRouteGroups, AppError> {
+    req.app_data::<web::Data<SharedRouteGroups>>()
+        .map(|groups| groups.get_ref().clone())
+        .ok_or_else(|| AppError::ServiceUnavailable("Route groups are not configured".to_string()))
+}
+
+async fn route_groups(req: HttpRequest) -> HttpResponse {
+    let result = route_group_toggles(&req)
+        .map(|groups| serde_json::json!({ "route_groups": groups.statuses() }));
+    handle_result(result)
+}
+
+/// Switches a route group for every request that arrives after the change.
+/// The change is stored first, when a database is configured, so it is not
+/// applied unless it will also survive a restart.
+async fn set_route_group(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<RouteGroupRequest>,
+) -> HttpResponse {
+    let result = async {
+        let name = path.into_inner();
+        let group = RouteGroup::parse(&name).ok_or_else(|| {
+            AppError::NotFound(format!(
+                "Unknown route group {name}; expected universe, rfq, channels or mailbox"
+            ))
+        })?;
+        let groups = route_group_toggles(&req)?;
+        let now = chrono::Utc::now().timestamp();
+        if let Some(db) = req.app_data::<web::Data<SharedDatabase>>() {
+            db.save_route_group(&RouteGroupRecord {
+                name: group.name().to_string(),
+                enabled: body.enabled,
+                updated_at: now,
+            })
+            .await?;
+        }
+        warn!(
+            "Route group {} {} via admin API",
+            group.name(),
+            if body.enabled { "enabled" } else { "disabled" }
+        );
+        Ok(groups.set(group, body.enabled, now))
+    }
+    .await;
+    handle_result(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeatureFlagRequest {
+    pub enabled: bool,
+    /// Limits the change to one API key, by its id from `/v1/gateway/usage`.
+    #[serde(default)]
+    pub key_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeatureFlagScope {
+    #[serde(default)]
+    pub key_id: Option<String>,
+}
+
+fn feature_flags(req: &HttpRequest) -> Result<SharedFeatureFlags, AppError> {
+    req.app_data::<web::Data<SharedFeatureFlags>>()
+        .map(|flags| flags.get_ref().clone())
+        .ok_or_else(|| AppError::ServiceUnavailable("Feature flags are not configured".to_string()))
+}
+
+fn parse_feature(name: &str) -> Result<Feature, AppError> {
+    Feature::parse(name).ok_or_else(|| {
+        AppError::NotFound(format!("Unknown feature {name}; expected graphql or swaps"))
+    })
+}
+
+/// Key ids are hashes, so a raw API key is never stored by mistake.
+fn validate_key_id(key_id: Option<&str>) -> Result<(), AppError> {
+    match key_id {
+        Some(key) if key.len() != 16 || !key.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Err(AppError::ValidationError(
+                "key_id must be the 16-character key id from /v1/gateway/usage, not the API key"
+                    .to_string(),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn list_feature_flags(req: HttpRequest) -> HttpResponse {
+    let result =
+        feature_flags(&req).map(|flags| serde_json::json!({ "feature_flags": flags.statuses() }));
+    handle_result(result)
+}
+
+/// Switches a feature for the deployment or one API key. Like route groups,
+/// the change is stored first when a database is configured.
+async fn set_feature_flag(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<FeatureFlagRequest>,
+) -> HttpResponse {
+    let result = async {
+        let feature = parse_feature(&path.into_inner())?;
+        let body = body.into_inner();
+        let key_id = body.key_id.as_deref().map(str::to_ascii_lowercase);
+        validate_key_id(key_id.as_deref())?;
+        let flags = feature_flags(&req)?;
+        let now = chrono::Utc::now().timestamp();
+        if let Some(db) = req.app_data::<web::Data<SharedDatabase>>() {
+            db.save_feature_flag(&FeatureFlagRecord {
+                name: feature.name().to_string(),
+                key_id: key_id.clone(),
+                enabled: body.enabled,
+                updated_at: now,
+            })
+            .await?;
+        }
+        warn!(
+            "Feature {} {} for {} via admin API",
+            feature.name(),
+            if body.enabled { "enabled" } else { "disabled" },
+            key_id
+                .as_deref()
+                .map_or("the deployment".to_string(), |k| format!("key {k}"))
+        );
+        flags.set(feature, key_id.as_deref(), body.enabled, now);
+        Ok(flags.status(feature))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Drops an override, so the flag falls back to the deployment override or
+/// `FEATURE_FLAGS`.
+async fn clear_feature_flag(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<FeatureFlagScope>,
+) -> HttpResponse {
+    let result = async {
+        let feature = parse_feature(&path.into_inner())?;
+        let key_id = query.into_inner().key_id.map(|k| k.to_ascii_lowercase());
+        validate_key_id(key_id.as_deref())?;
+        let flags = feature_flags(&req)?;
+        if let Some(db) = req.app_data::<web::Data<SharedDatabase>>() {
+            db.delete_feature_flag(feature.name(), key_id.as_deref())
+                .await?;
+        }
+        if !flags.clear(feature, key_id.as_deref()) {
+            return Err(AppError::NotFound(format!(
+                "No override of {} to clear",
+                feature.name()
+            )));
+        }
+        info!("Feature {} override cleared via admin API", feature.name());
+        Ok(flags.status(feature))
+    }
+    .await;
+    handle_result(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NetworkAclRuleRequest {
+    pub action: AclAction,
+    pub path_prefix: String,
+    pub networks: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+fn network_acl(req: &HttpRequest) -> Result<SharedNetworkAcl, AppError> {
+    req.app_data::<web::Data<SharedNetworkAcl>>()
+        .map(|acl| acl.get_ref().clone())
+        .ok_or_else(|| AppError::ServiceUnavailable("Network ACLs are not configured".to_string()))
+}
+
+/// Every network access rule, and the address the caller was seen from so
+/// an operator can check a rule before adding it.
+async fn list_network_acl(req: HttpRequest) -> HttpResponse {
+    let result = network_acl(&req).map(|acl| {
+        let client_ip = acl.client_ip(req.peer_addr().map(|addr| addr.ip()), req.headers());
+        serde_json::json!({ "rules": acl.rules(), "client_ip": client_ip })
+    });
+    handle_result(result)
+}
+
+/// Adds a rule for every request that arrives after it. Like route group
+/// changes it is stored first when a database is configured. A rule that
+/// would refuse the caller on this route is rejected, so a typo cannot lock
+/// the operator out of undoing it.
+async fn add_network_acl_rule(
+    req: HttpRequest,
+    body: web::Json<NetworkAclRuleRequest>,
+) -> HttpResponse {
+    let result = async {
+        let acl = network_acl(&req)?;
+        let body = body.into_inner();
+        let rule = AclRule {
+            id: Some(uuid::Uuid::new_v4().to_string()),
+            action: body.action,
+            path_prefix: body.path_prefix.trim().to_string(),
+            networks: body
+                .networks
+                .iter()
+                .map(|n| parse_network(n))
+                .collect::<Result<_, _>>()?,
+            note: body.note.filter(|n| !n.trim().is_empty()),
+        };
+        rule.validate()?;
+        let client_ip = acl.client_ip(req.peer_addr().map(|addr| addr.ip()), req.headers());
+        if !acl.allows_with(&rule, req.path(), client_ip) {
+            return Err(AppError::ValidationError(format!(
+                "Rule would refuse this client ({}) on the network ACL admin route",
+                client_ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+            )));
+        }
+        if let Some(db) = req.app_data::<web::Data<SharedDatabase>>() {
+            db.insert_network_acl_rule(&NetworkAclRecord {
+                id: rule.id.clone().unwrap_or_default(),
+                action: rule.action.name().to_string(),
+                path_prefix: rule.path_prefix.clone(),
+                networks: rule
+                    .networks
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+                note: rule.note.clone(),
+                created_at: chrono::Utc::now().timestamp(),
+            })
+            .await?;
+        }
+        warn!(
+            "Network ACL rule added via admin API: {} {} {:?}",
+            rule.action.name(),
+            rule.path_prefix,
+            rule.networks
+        );
+        acl.add(rule.clone());
+        Ok(rule)
+    }
+    .await;
+    match result {
+        Ok(rule) => HttpResponse::build(StatusCode::CREATED).json(rule),
+        Err(e) => handle_result::<AclRule>(Err(e)),
+    }
+}
+
+/// Removes a rule added through the admin API; `NETWORK_ACL` rules stay
+/// until the configuration changes.
+async fn delete_network_acl_rule(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let acl = network_acl(&req)?;
+        let id = path.into_inner();
+        if let Some(db) = req.app_data::<web::Data<SharedDatabase>>() {
+            db.delete_network_acl_rule(&id).await?;
+        }
+        if !acl.remove(&id) {
+            return Err(AppError::NotFound(format!(
+                "Network ACL rule {id} not found"
+            )));
+        }
+        warn!("Network ACL rule {} removed via admin API", id);
+        Ok(serde_json::json!({ "deleted": id }))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Where audit events go and how many were dropped because the buffer was
+/// full, which means the target has been unreachable for a while.
+async fn audit_status(req: HttpRequest, config: web::Data<Config>) -> HttpResponse {
+    let result = req
+        .app_data::<web::Data<SharedAuditExporter>>()
+        .map(|audit| {
+            serde_json::json!({
+                "target": config.audit_log_target,
+                "format": config.audit_log_format,
+                "buffer": config.audit_log_buffer,
+                "dropped": audit.dropped(),
+            })
+        })
+        .ok_or_else(|| AppError::ServiceUnavailable("Audit log export is off".to_string()));
+    handle_result(result)
+}
+
+fn lockouts(req: &HttpRequest) -> Result<SharedAuthLockouts, AppError> {
+    req.app_data::<web::Data<SharedAuthLockouts>>()
+        .map(|l| l.get_ref().clone())
+        .ok_or_else(|| {
+            AppError::ServiceUnavailable("Authentication lockouts are not enabled".to_string())
+        })
+}
+
+/// Every client address and identity with recent failed authentications,
+/// and whether it is locked out.
+async fn list_lockouts(req: HttpRequest) -> HttpResponse {
+    let result = async {
+        let statuses = lockouts(&req)?.statuses().await?;
+        Ok(serde_json::json!({ "lockouts": statuses }))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Lifts a lockout early and forgets the subject's failures.
+async fn clear_lockout(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let key = path.into_inner();
+        if !lockouts(&req)?.clear(&key).await? {
+            return Err(AppError::NotFound(format!("No lockout for {key}")));
+        }
+        warn!("Authentication lockout for {} cleared via admin API", key);
+        Ok(serde_json::json!({ "cleared": key }))
+    }
+    .await;
+    handle_result(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackfillListQuery {
+    pub limit: Option<u32>,
+}
+
+/// An indexer for the request's node, used to run backfills whether or not
+/// the live indexer is enabled.
+fn backfill_indexer(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &BaseUrl,
+    macaroon_hex: &MacaroonHex,
+    config: &Config,
+) -> Result<Arc<Indexer>, AppError> {
+    let database = require_database(req)?;
+    let unavailable = || AppError::ServiceUnavailable("Indexer is not configured".to_string());
+    let connection_manager = req
+        .app_data::<web::Data<Arc<WebSocketConnectionManager>>>()
+        .ok_or_else(unavailable)?;
+    let events = req
+        .app_data::<web::Data<SharedEventBus>>()
+        .ok_or_else(unavailable)?;
+    Ok(Arc::new(Indexer::new(
+        client.clone(),
+        base_url.0.clone(),
+        macaroon_hex.0.clone(),
+        database,
+        connection_manager.get_ref().clone(),
+        events.get_ref().clone(),
+        config.indexer_backfill_batch_size,
+    )))
+}
+
+/// Starts walking tapd's history and the universe into the index. The run
+/// continues in the background; poll it for progress.
+async fn start_backfill(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let result = async {
+        backfill_indexer(&req, &client, &base_url, &macaroon_hex, &config)?
+            .start_backfill()
+            .await
+    }
+    .await;
+    match result {
+        Ok(run) => HttpResponse::Accepted().json(run),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
+}
+
+async fn list_backfills(req: HttpRequest, query: web::Query<BackfillListQuery>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        database.list_backfill_runs(query.limit).await
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn get_backfill(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let id = path.into_inner();
+        database
+            .get_backfill_run(&id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Backfill {id} not found")))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Continues a partial backfill from its last saved step.
+async fn resume_backfill(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let result = async {
+        backfill_indexer(&req, &client, &base_url, &macaroon_hex, &config)?
+            .resume_backfill(&path.into_inner())
+            .await
+    }
+    .await;
+    match result {
+        Ok(run) => HttpResponse::Accepted().json(run),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
+}
+
+/// Open backend sockets with their age and idle time, per-endpoint counts
+/// and lifetime connect/reconnect counters.
+async fn websockets(req: HttpRequest) -> HttpResponse {
+    match req.app_data::<web::Data<Arc<WebSocketConnectionManager>>>() {
+        Some(manager) => handle_result(Ok::<_, AppError>(manager.introspect().await)),
+        None => handle_result::<()>(Err(AppError::ServiceUnavailable(
+            "WebSocket proxy is not configured".to_string(),
+        ))),
+    }
+}
+
+/// Backend calls waiting on tapd, longest running first, with the gateway
+/// request behind each, plus the open backend sockets.
+async fn inflight(req: HttpRequest) -> HttpResponse {
+    let websockets = match req.app_data::<web::Data<Arc<WebSocketConnectionManager>>>() {
+        Some(manager) => Some(manager.introspect().await),
+        None => None,
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "requests": inflight::registry().list(),
+        "websockets": websockets,
+    }))
+}
+
+/// Aborts a stuck backend call; the client waiting on it gets a 503.
+async fn cancel_inflight(path: web::Path<String>) -> HttpResponse {
+    let result = (|| {
+        let id = path.into_inner();
+        let parsed = id
+            .parse::<u64>()
+            .map_err(|_| AppError::InvalidInput(format!("Invalid request id: {id}")))?;
+        if !inflight::registry().cancel(parsed) {
+            return Err(AppError::NotFound(format!(
+                "Backend request {id} is not in flight"
+            )));
+        }
+        info!("Cancelled backend request {}", parsed);
+        Ok(serde_json::json!({ "cancelled": parsed }))
+    })();
+    handle_result(result)
+}
+
+/// Longest lifetime a delegated macaroon may be issued for.
+const MAX_MACAROON_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+/// Operation actions tapd's permissions are expressed in.
+const MACAROON_ACTIONS: [&str; 2] = ["read", "write"];
+
+#[derive(Debug, Deserialize)]
+pub struct DelegateMacaroonRequest {
+    pub ttl_secs: u64,
+    /// Restricts the macaroon to operations with these actions, e.g.
+    /// `["read"]` for a read-only credential.
+    #[serde(default)]
+    pub actions: Option<Vec<String>>,
+    /// Locks the macaroon to a single client IP.
+    #[serde(default)]
+    pub ip: Option<String>,
+}
+
+/// Builds the first-party caveats for a delegation request. The conditions
+/// are the ones tapd's (lnd-derived) macaroon checker understands.
+fn delegation_caveats(
+    request: &DelegateMacaroonRequest,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(Vec<String>, chrono::DateTime<chrono::Utc>), AppError> {
+    if request.ttl_secs == 0 || request.ttl_secs > MAX_MACAROON_TTL_SECS {
+        return Err(AppError::ValidationError(format!(
+            "ttl_secs must be between 1 and {MAX_MACAROON_TTL_SECS}"
+        )));
+    }
+    let expires_at = now + chrono::Duration::seconds(request.ttl_secs as i64);
+    let mut caveats = vec![format!(
+        "time-before {}",
+        expires_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    )];
+
+    if let Some(actions) = &request.actions {
+        if actions.is_empty()
+            || actions
+                .iter()
+                .any(|a| !MACAROON_ACTIONS.contains(&a.as_str()))
+        {
+            return Err(AppError::ValidationError(
+                "actions must be a non-empty subset of [\"read\", \"write\"]".to_string(),
+            ));
+        }
+        caveats.push(format!("allow {}", actions.join(" ")));
+    }
+
+    if let Some(ip) = &request.ip {
+        let ip: IpAddr = ip
+            .parse()
+            .map_err(|_| AppError::ValidationError(format!("Invalid ip: {ip}")))?;
+        caveats.push(format!("ipaddr {ip}"));
+    }
+    Ok((caveats, expires_at))
+}
+
+/// Derives an attenuated copy of the gateway's tapd macaroon. Caveats can only
+/// narrow what the original allows, so callers get least-privilege
+/// credentials without the gateway sharing its own.
+async fn delegate_macaroon(
+    macaroon_hex: web::Data<MacaroonHex>,
+    body: web::Json<DelegateMacaroonRequest>,
+) -> HttpResponse {
+    let result = (|| {
+        let (caveats, expires_at) = delegation_caveats(&body, chrono::Utc::now())?;
+        let bytes = hex::decode(&macaroon_hex.0)
+            .map_err(|_| AppError::ValidationError("Gateway macaroon is not hex".to_string()))?;
+        let mut macaroon = Macaroon::decode(&bytes)?;
+        for caveat in &caveats {
+            macaroon.add_first_party_caveat(caveat);
+        }
+        info!("Issued delegated macaroon with caveats {:?}", caveats);
+        Ok(serde_json::json!({
+            "macaroon": hex::encode(macaroon.encode()),
+            "expires_at": expires_at.to_rfc3339(),
+            "caveats": caveats,
+        }))
+    })();
+    match result {
+        Ok(body) => HttpResponse::build(StatusCode::CREATED).json(body),
+        Err(e) => handle_result::<serde_json::Value>(Err(e)),
+    }
+}
+
+/// Every scheduled job with its schedule, last outcome, counters and next
+/// fire time.
+async fn jobs(req: HttpRequest) -> HttpResponse {
+    let result = req
+        .app_data::<web::Data<SharedScheduler>>()
+        .map(|scheduler| serde_json::json!({ "jobs": scheduler.statuses() }))
+        .ok_or_else(|| AppError::ServiceUnavailable("Scheduler is not configured".to_string()));
+    handle_result(result)
+}
+
+/// Whether the gateway is shedding load, why, and the last resource sample
+/// against its thresholds.
+async fn shed_status(req: HttpRequest) -> HttpResponse {
+    let result = req
+        .app_data::<web::Data<SharedShed>>()
+        .map(|shed| shed.status())
+        .ok_or_else(|| {
+            AppError::ServiceUnavailable("Resource limits are not configured".to_string())
+        });
+    handle_result(result)
+}
+
+/// Observed tapd latency and the timeout in force for every route called
+/// since startup.
+async fn route_timeouts(req: HttpRequest) -> HttpResponse {
+    let routes = req
+        .app_data::<web::Data<SharedTimeoutTuner>>()
+        .map(|tuner| tuner.statuses())
+        .unwrap_or_default();
+    HttpResponse::Ok().json(serde_json::json!({ "routes": routes }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/admin/maintenance")
+            .route(web::get().to(get_maintenance))
+            .route(web::put().to(set_maintenance)),
+    )
+    .service(web::resource("/admin/route-groups").route(web::get().to(route_groups)))
+    .service(web::resource("/admin/route-groups/{group}").route(web::put().to(set_route_group)))
+    .service(web::resource("/admin/feature-flags").route(web::get().to(list_feature_flags)))
+    .service(
+        web::resource("/admin/feature-flags/{feature}")
+            .route(web::put().to(set_feature_flag))
+            .route(web::delete().to(clear_feature_flag)),
+    )
+    .service(
+        web::resource("/admin/backfills")
+            .route(web::get().to(list_backfills))
+            .route(web::post().to(start_backfill)),
+    )
+    .service(
+        web::resource("/admin/network-acl")
+            .route(web::get().to(list_network_acl))
+            .route(web::post().to(add_network_acl_rule)),
+    )
+    .service(
+        web::resource("/admin/network-acl/{id}").route(web::delete().to(delete_network_acl_rule)),
+    )
+    .service(web::resource("/admin/audit").route(web::get().to(audit_status)))
+    .service(web::resource("/admin/lockouts").route(web::get().to(list_lockouts)))
+    .service(web::resource("/admin/lockouts/{key}").route(web::delete().to(clear_lockout)))
+    .service(web::resource("/admin/backfills/{id}").route(web::get().to(get_backfill)))
+    .service(web::resource("/admin/backfills/{id}/resume").route(web::post().to(resume_backfill)))
+    .service(web::resource("/admin/macaroons").route(web::post().to(delegate_macaroon)))
+    .service(web::resource("/admin/websockets").route(web::get().to(websockets)))
+    .service(web::resource("/admin/inflight").route(web::get().to(inflight)))
+    .service(web::resource("/admin/inflight/{id}").route(web::delete().to(cancel_inflight)))
+    .service(web::resource("/admin/jobs").route(web::get().to(jobs)))
+    .service(web::resource("/admin/shed").route(web::get().to(shed_status)))
+    .service(web::resource("/admin/timeouts").route(web::get().to(route_timeouts)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn request(ttl_secs: u64) -> DelegateMacaroonRequest {
+        DelegateMacaroonRequest {
+            ttl_secs,
+            actions: None,
+            ip: None,
+        }
+    }
+
+    #[test]
+    fn test_delegation_caveats_bound_time_actions_and_ip() {
+        let now = chrono::Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let req = DelegateMacaroonRequest {
+            actions: Some(vec!["read".to_string()]),
+            ip: Some("10.0.0.5".to_string()),
+            ..request(3600)
+        };
+        let (caveats, expires_at) = delegation_caveats(&req, now).unwrap();
+        assert_eq!(
+            caveats,
+            vec![
+                "time-before 2030-01-01T01:00:00Z",
+                "allow read",
+                "ipaddr 10.0.0.5"
+            ]
+        );
+        assert_eq!(expires_at.timestamp() - now.timestamp(), 3600);
+    }
+
+    #[test]
+    fn test_delegation_caveats_reject_invalid_requests() {
+        let now = chrono::Utc::now();
+        assert!(delegation_caveats(&request(0), now).is_err());
+        assert!(delegation_caveats(&request(MAX_MACAROON_TTL_SECS + 1), now).is_err());
+        let req = DelegateMacaroonRequest {
+            actions: Some(vec!["admin".to_string()]),
+            ..request(60)
+        };
+        assert!(delegation_caveats(&req, now).is_err());
+        let req = DelegateMacaroonRequest {
+            ip: Some("not-an-ip".to_string()),
+            ..request(60)
+        };
+        assert!(delegation_caveats(&req, now).is_err());
+    }
+}
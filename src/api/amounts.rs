@@ -0,0 +1,396 @@
+//! Display formatting of asset amounts, so thin clients such as POS
+//! terminals show the same figure the wallet does without carrying their
+//! own number formatting. Amounts are in base units; the asset's
+//! `decimal_display` from its tapd metadata places the decimal point.
+
+use super::{assets, handle_result, validate_asset_id};
+use crate::error::AppError;
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::Engine;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tracing::{debug, instrument};
+
+/// tapd refuses to mint assets with more decimal places than this.
+pub const MAX_DECIMAL_DISPLAY: u32 = 12;
+
+/// Most fraction digits a formatted amount is padded to.
+pub const MAX_FRACTION_DIGITS: u32 = 18;
+
+/// Locale used when the request names none and sends no
+/// `Accept-Language`, and for locales not in [`LOCALES`].
+const DEFAULT_LOCALE: &str = "en";
+
+/// Assets whose decimal display is remembered; it is fixed at genesis, so
+/// entries never go stale.
+const MAX_CACHED_ASSETS: usize = 1024;
+
+static DECIMALS: Mutex<BTreeMap<String, u32>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Grouping {
+    /// Groups of three: 1,234,567.
+    Thousands,
+    /// Three, then twos: 12,34,567.
+    Indian,
+}
+
+/// Separators per locale tag, matched on the full tag and then on the
+/// language alone.
+const LOCALES: &[(&str, &str, &str, Grouping)] = &[
+    ("en", ",", ".", Grouping::Thousands),
+    ("en-in", ",", ".", Grouping::Indian),
+    ("hi", ",", ".", Grouping::Indian),
+    ("ja", ",", ".", Grouping::Thousands),
+    ("ko", ",", ".", Grouping::Thousands),
+    ("zh", ",", ".", Grouping::Thousands),
+    ("th", ",", ".", Grouping::Thousands),
+    ("es-mx", ",", ".", Grouping::Thousands),
+    ("de", ".", ",", Grouping::Thousands),
+    ("de-ch", "\u{2019}", ".", Grouping::Thousands),
+    ("es", ".", ",", Grouping::Thousands),
+    ("it", ".", ",", Grouping::Thousands),
+    ("nl", ".", ",", Grouping::Thousands),
+    ("pt", ".", ",", Grouping::Thousands),
+    ("id", ".", ",", Grouping::Thousands),
+    ("tr", ".", ",", Grouping::Thousands),
+    ("da", ".", ",", Grouping::Thousands),
+    ("fr", "\u{202f}", ",", Grouping::Thousands),
+    ("ru", "\u{a0}", ",", Grouping::Thousands),
+    ("uk", "\u{a0}", ",", Grouping::Thousands),
+    ("pl", "\u{a0}", ",", Grouping::Thousands),
+    ("cs", "\u{a0}", ",", Grouping::Thousands),
+    ("sv", "\u{a0}", ",", Grouping::Thousands),
+    ("nb", "\u{a0}", ",", Grouping::Thousands),
+    ("fi", "\u{a0}", ",", Grouping::Thousands),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// Halves round away from zero.
+    #[default]
+    HalfUp,
+    /// Halves round to the even neighbour.
+    HalfEven,
+    /// Extra digits are dropped.
+    Down,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FormatQuery {
+    /// Amount in base units.
+    pub amount: u64,
+    /// Asset whose metadata supplies the decimal display.
+    pub asset_id: Option<String>,
+    /// Decimal places of the asset; overrides the metadata.
+    pub decimal_display: Option<u32>,
+    /// BCP 47 tag such as `de-DE`; defaults to the `Accept-Language`
+    /// header.
+    pub locale: Option<String>,
+    /// Fraction digits to show; defaults to the decimal display.
+    pub fraction_digits: Option<u32>,
+    #[serde(default)]
+    pub rounding: Rounding,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FormattedAmount {
+    pub amount: u64,
+    pub asset_id: Option<String>,
+    pub decimal_display: u32,
+    /// The locale whose conventions were applied.
+    pub locale: String,
+    pub formatted: String,
+    pub group_separator: String,
+    pub decimal_separator: String,
+    pub fraction_digits: u32,
+    pub rounding: Rounding,
+    /// Whether digits were dropped to fit `fraction_digits`.
+    pub rounded: bool,
+}
+
+/// The known locale closest to `tag`, with its separators.
+fn resolve_locale(tag: &str) -> (&'static str, &'static str, &'static str, Grouping) {
+    let tag = tag.trim().replace('_', "-").to_ascii_lowercase();
+    let language = tag.split('-').next().unwrap_or_default();
+    let find = |wanted: &str| LOCALES.iter().find(|(name, ..)| *name == wanted).copied();
+    find(&tag)
+        .or_else(|| find(language))
+        .or_else(|| find(DEFAULT_LOCALE))
+        .expect("default locale is listed")
+}
+
+/// The first language of an `Accept-Language` header.
+fn preferred_language(header: &str) -> Option<&str> {
+    header
+        .split(',')
+        .filter_map(|entry| entry.split(';').next())
+        .map(str::trim)
+        .find(|tag| !tag.is_empty() && *tag != "*")
+}
+
+fn group_digits(digits: &str, separator: &str, grouping: Grouping) -> String {
+    let mut groups = Vec::new();
+    let mut rest = digits;
+    let mut size = 3;
+    while rest.len() > size {
+        let (head, tail) = rest.split_at(rest.len() - size);
+        groups.push(tail);
+        rest = head;
+        if grouping == Grouping::Indian {
+            size = 2;
+        }
+    }
+    groups.push(rest);
+    groups.reverse();
+    groups.join(separator)
+}
+
+/// Formats `amount` base units of an asset with `decimals` places,
+/// showing `fraction_digits` of them. Returns the text and whether digits
+/// were rounded away.
+pub fn format_amount(
+    amount: u64,
+    decimals: u32,
+    fraction_digits: u32,
+    rounding: Rounding,
+    locale: &str,
+) -> (String, bool) {
+    let (_, group, decimal, grouping) = resolve_locale(locale);
+    let (scaled, rounded) = if fraction_digits >= decimals {
+        (
+            amount as u128 * 10u128.pow(fraction_digits - decimals),
+            false,
+        )
+    } else {
+        let divisor = 10u128.pow(decimals - fraction_digits);
+        let (quotient, remainder) = (amount as u128 / divisor, amount as u128 % divisor);
+        let round_up = match rounding {
+            Rounding::Down => false,
+            Rounding::HalfUp => remainder * 2 >= divisor,
+            Rounding::HalfEven => {
+                remainder * 2 > divisor || (remainder * 2 == divisor && quotient % 2 == 1)
+            }
+        };
+        (quotient + u128::from(round_up), remainder != 0)
+    };
+
+    let unit = 10u128.pow(fraction_digits);
+    let integer = group_digits(&(scaled / unit).to_string(), group, grouping);
+    if fraction_digits == 0 {
+        return (integer, rounded);
+    }
+    let fraction = format!(
+        "{:0width$}",
+        scaled % unit,
+        width = fraction_digits as usize
+    );
+    (format!("{integer}{decimal}{fraction}"), rounded)
+}
+
+/// Reads the decimal display from a `/assets/meta` response: a top-level
+/// field in newer tapd releases, otherwise a key of the JSON metadata.
+pub fn meta_decimal_display(meta: &Value) -> u32 {
+    let field = |value: &Value| {
+        value
+            .as_u64()
+            .or_else(|| value["decimal_display"].as_u64())
+            .and_then(|decimals| u32::try_from(decimals).ok())
+    };
+    if let Some(decimals) = field(&meta["decimal_display"]) {
+        return decimals;
+    }
+    meta["data"]
+        .as_str()
+        .and_then(|data| base64::engine::general_purpose::STANDARD.decode(data).ok())
+        .and_then(|data| serde_json::from_slice::<Value>(&data).ok())
+        .and_then(|data| field(&data["decimal_display"]))
+        .unwrap_or(0)
+}
+
+async fn asset_decimal_display(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    asset_id: &str,
+) -> Result<u32, AppError> {
+    if let Some(decimals) = DECIMALS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(asset_id)
+    {
+        return Ok(*decimals);
+    }
+    let meta = assets::get_meta(client, base_url, macaroon_hex, asset_id, "").await?;
+    let decimals = meta_decimal_display(&meta);
+    let mut cached = DECIMALS.lock().unwrap_or_else(|e| e.into_inner());
+    if cached.len() < MAX_CACHED_ASSETS {
+        cached.insert(asset_id.to_string(), decimals);
+    }
+    Ok(decimals)
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex))]
+async fn format(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    query: FormatQuery,
+) -> Result<FormattedAmount, AppError> {
+    let asset_id = match query.asset_id {
+        Some(asset_id) => {
+            validate_asset_id(&asset_id)?;
+            Some(asset_id.to_ascii_lowercase())
+        }
+        None => None,
+    };
+    let decimal_display = match (query.decimal_display, &asset_id) {
+        (Some(decimals), _) => decimals,
+        (None, Some(asset_id)) => {
+            asset_decimal_display(client, base_url, macaroon_hex, asset_id).await?
+        }
+        (None, None) => {
+            return Err(AppError::InvalidInput(
+                "Either asset_id or decimal_display is required".to_string(),
+            ))
+        }
+    };
+    if decimal_display > MAX_DECIMAL_DISPLAY {
+        return Err(AppError::InvalidInput(format!(
+            "decimal_display must be at most {MAX_DECIMAL_DISPLAY}"
+        )));
+    }
+    let fraction_digits = query.fraction_digits.unwrap_or(decimal_display);
+    if fraction_digits > MAX_FRACTION_DIGITS {
+        return Err(AppError::InvalidInput(format!(
+            "fraction_digits must be at most {MAX_FRACTION_DIGITS}"
+        )));
+    }
+
+    let requested = query.locale.or_else(|| {
+        req.headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(preferred_language)
+            .map(str::to_string)
+    });
+    let (locale, group, decimal, _) =
+        resolve_locale(requested.as_deref().unwrap_or(DEFAULT_LOCALE));
+    let (formatted, rounded) = format_amount(
+        query.amount,
+        decimal_display,
+        fraction_digits,
+        query.rounding,
+        locale,
+    );
+    debug!("Formatted {} as {formatted} for {locale}", query.amount);
+    Ok(FormattedAmount {
+        amount: query.amount,
+        asset_id,
+        decimal_display,
+        locale: locale.to_string(),
+        formatted,
+        group_separator: group.to_string(),
+        decimal_separator: decimal.to_string(),
+        fraction_digits,
+        rounding: query.rounding,
+        rounded,
+    })
+}
+
+async fn format_handler(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    query: web::Query<FormatQuery>,
+) -> HttpResponse {
+    handle_result(
+        format(
+            &req,
+            client.as_ref(),
+            &base_url.0,
+            &macaroon_hex.0,
+            query.into_inner(),
+        )
+        .await,
+    )
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/amounts/format").route(web::get().to(format_handler)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_amounts_follow_locale_separators() {
+        let amount = 123_456_789;
+        let cases = [
+            ("en-US", "1,234,567.89"),
+            ("de-DE", "1.234.567,89"),
+            ("fr_FR", "1\u{202f}234\u{202f}567,89"),
+            ("de-CH", "1\u{2019}234\u{2019}567.89"),
+            ("en-IN", "12,34,567.89"),
+            ("xx", "1,234,567.89"),
+        ];
+        for (locale, expected) in cases {
+            let (formatted, rounded) = format_amount(amount, 2, 2, Rounding::HalfUp, locale);
+            assert_eq!(formatted, expected, "{locale}");
+            assert!(!rounded);
+        }
+        assert_eq!(resolve_locale("pt-BR").0, "pt");
+        assert_eq!(format_amount(999, 0, 0, Rounding::HalfUp, "en").0, "999");
+        assert_eq!(format_amount(5, 3, 3, Rounding::HalfUp, "en").0, "0.005");
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        // 12.345 shown with two fraction digits
+        let round = |amount, rounding| format_amount(amount, 3, 2, rounding, "en");
+        assert_eq!(round(12_345, Rounding::HalfUp), ("12.35".to_string(), true));
+        assert_eq!(
+            round(12_345, Rounding::HalfEven),
+            ("12.34".to_string(), true)
+        );
+        assert_eq!(round(12_355, Rounding::HalfEven).0, "12.36");
+        assert_eq!(round(12_349, Rounding::Down).0, "12.34");
+        assert_eq!(
+            round(12_340, Rounding::HalfUp),
+            ("12.34".to_string(), false)
+        );
+        assert_eq!(
+            format_amount(999_999, 3, 0, Rounding::HalfUp, "en").0,
+            "1,000"
+        );
+        // More digits than the asset has pads with zeros
+        assert_eq!(
+            format_amount(u64::MAX, 0, 2, Rounding::HalfUp, "en").0,
+            "18,446,744,073,709,551,615.00"
+        );
+    }
+
+    #[test]
+    fn test_decimal_display_read_from_meta() {
+        let data = base64::engine::general_purpose::STANDARD
+            .encode(serde_json::to_vec(&json!({ "decimal_display": 6, "name": "USD" })).unwrap());
+        assert_eq!(meta_decimal_display(&json!({ "data": data })), 6);
+        assert_eq!(
+            meta_decimal_display(&json!({ "decimal_display": { "decimal_display": 2 } })),
+            2
+        );
+        let opaque = base64::engine::general_purpose::STANDARD.encode(b"plain text");
+        assert_eq!(meta_decimal_display(&json!({ "data": opaque })), 0);
+        assert_eq!(preferred_language("de-CH;q=0.9, en;q=0.8"), Some("de-CH"));
+    }
+}
@@ -1,6 +1,9 @@
-use super::{handle_result, parse_upstream, validate_hex_param, with_query};
+use super::{
+    backend, handle_result, list_response, parse_upstream, split_list_query, validate_hex_param,
+    ListEnvelope, PageParams,
+};
 use crate::error::AppError;
-use crate::types::{BaseUrl, MacaroonHex};
+use crate::types::{AssetId, BaseUrl, MacaroonHex, Outpoint};
 use actix_web::{web, HttpRequest, HttpResponse};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
@@ -155,13 +158,11 @@ pub async fn list_assets(
     query: &str,
 ) -> Result<Vec<Asset>, AppError> {
     info!("Listing assets");
-    let url = with_query(format!("{base_url}/v1/taproot-assets/assets"), query);
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    let response = backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/assets")
+        .query(query)
         .send()
-        .await
-        .map_err(AppError::RequestError)?;
+        .await?;
 
     let asset_response: AssetResponse = parse_upstream(response).await?;
 
@@ -176,15 +177,11 @@ pub async fn mint_asset(
     request: MintAssetRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Minting asset: {}", request.asset.name);
-    let url = format!("{base_url}/v1/taproot-assets/assets");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/assets")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -195,17 +192,11 @@ pub async fn get_balance(
     query: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Fetching asset balance");
-    let url = with_query(
-        format!("{base_url}/v1/taproot-assets/assets/balance"),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/assets/balance")
+        .query(query)
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -215,14 +206,10 @@ pub async fn get_groups(
     macaroon_hex: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Fetching asset groups");
-    let url = format!("{base_url}/v1/taproot-assets/assets/groups");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/assets/groups")
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -234,17 +221,13 @@ pub async fn get_meta(
     query: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Fetching meta for asset ID: {}", asset_id);
-    let url = with_query(
-        format!("{base_url}/v1/taproot-assets/assets/meta/asset-id/{asset_id}"),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/assets/meta/asset-id/{asset_id}"
+        ))
+        .query(query)
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -256,17 +239,13 @@ pub async fn get_mint_batches(
     query: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Fetching mint batches for batch key: {}", batch_key);
-    let url = with_query(
-        format!("{base_url}/v1/taproot-assets/assets/mint/batches/{batch_key}"),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/assets/mint/batches/{batch_key}"
+        ))
+        .query(query)
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -276,13 +255,10 @@ pub async fn list_all_mint_batches(
     macaroon_hex: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Fetching all mint batches");
-    let url = format!("{base_url}/v1/taproot-assets/assets/mint/batches");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    let response = backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/assets/mint/batches")
         .send()
-        .await
-        .map_err(AppError::RequestError)?;
+        .await?;
 
     // Handle empty response or return empty batches array
     if response.status() == 404 {
@@ -299,15 +275,11 @@ pub async fn cancel_mint(
     macaroon_hex: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Canceling mint");
-    let url = format!("{base_url}/v1/taproot-assets/assets/mint/cancel");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/assets/mint/cancel")
         .json(&serde_json::json!({}))
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -318,15 +290,11 @@ pub async fn fund_mint(
     request: MintFundRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Funding mint with fee rate: {}", request.fee_rate);
-    let url = format!("{base_url}/v1/taproot-assets/assets/mint/fund");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/assets/mint/fund")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -337,15 +305,11 @@ pub async fn finalize_mint(
     request: MintFinalizeRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Finalizing mint with fee rate: {}", request.fee_rate);
-    let url = format!("{base_url}/v1/taproot-assets/assets/mint/finalize");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/assets/mint/finalize")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -356,15 +320,11 @@ pub async fn seal_mint(
     request: MintSealRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Sealing mint");
-    let url = format!("{base_url}/v1/taproot-assets/assets/mint/seal");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/assets/mint/seal")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -375,17 +335,11 @@ pub async fn get_transfers(
     query: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Fetching asset transfers");
-    let url = with_query(
-        format!("{base_url}/v1/taproot-assets/assets/transfers"),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/assets/transfers")
+        .query(query)
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -396,15 +350,11 @@ pub async fn register_transfer(
     request: TransferRegisterRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Registering transfer for asset ID: {}", request.asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/assets/transfers/register");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/assets/transfers/register")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -415,14 +365,11 @@ pub async fn get_utxos(
     query: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Fetching asset UTXOs");
-    let url = with_query(format!("{base_url}/v1/taproot-assets/assets/utxos"), query);
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/assets/utxos")
+        .query(query)
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 async fn list_handler(
@@ -431,14 +378,21 @@ async fn list_handler(
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
 ) -> HttpResponse {
+    let (page, query) = match split_list_query(http_req.query_string()) {
+        Ok(split) => split,
+        Err(e) => return handle_result::<serde_json::Value>(Err(e)),
+    };
     match list_assets(
         client.as_ref(),
         base_url.0.as_str(),
         macaroon_hex.0.as_str(),
-        http_req.query_string(),
+        &query,
     )
     .await
     {
+        Ok(assets) if page.wants_envelope() => handle_result(
+            ListEnvelope::from_full_list(assets, &page).map(|e| e.with_next_link(&http_req)),
+        ),
         Ok(assets) => {
             // The API expects a response with assets, unconfirmed_transfers, and unconfirmed_mints
             let response = serde_json::json!({
@@ -450,8 +404,10 @@ async fn list_handler(
         }
         Err(e) => {
             let status = e.status_code();
-            HttpResponse::build(status)
-                .json(serde_json::json!({"error": e.to_string(), "type": format!("{:?}", e)}))
+            let mut response = HttpResponse::build(status)
+                .json(serde_json::json!({"error": e.to_string(), "type": format!("{:?}", e)}));
+            crate::error::mark_upstream(&mut response, &e);
+            response
         }
     }
 }
@@ -513,7 +469,7 @@ async fn meta_handler(
     path: web::Path<String>,
 ) -> HttpResponse {
     let asset_id = path.into_inner();
-    if let Err(e) = validate_hex_param(&asset_id) {
+    if let Err(e) = asset_id.parse::<AssetId>() {
         return handle_result::<serde_json::Value>(Err(e));
     }
     handle_result(
@@ -552,17 +508,25 @@ async fn mint_batches_handler(
 }
 
 async fn list_mint_batches_handler(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
 ) -> HttpResponse {
-    handle_result(
+    let page = match PageParams::from_query(http_req.query_string()) {
+        Ok(page) => page,
+        Err(e) => return handle_result::<serde_json::Value>(Err(e)),
+    };
+    list_response(
+        &http_req,
+        &page,
         list_all_mint_batches(
             client.as_ref(),
             base_url.0.as_str(),
             macaroon_hex.0.as_str(),
         )
         .await,
+        "batches",
     )
 }
 
@@ -638,14 +602,21 @@ async fn transfers_handler(
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
 ) -> HttpResponse {
-    handle_result(
+    let (page, query) = match split_list_query(http_req.query_string()) {
+        Ok(split) => split,
+        Err(e) => return handle_result::<serde_json::Value>(Err(e)),
+    };
+    list_response(
+        &http_req,
+        &page,
         get_transfers(
             client.as_ref(),
             base_url.0.as_str(),
             macaroon_hex.0.as_str(),
-            http_req.query_string(),
+            &query,
         )
         .await,
+        "transfers",
     )
 }
 
@@ -655,12 +626,17 @@ async fn register_transfer_handler(
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<TransferRegisterRequest>,
 ) -> HttpResponse {
+    let mut request = req.into_inner();
+    request.outpoint = match Outpoint::normalize(&request.outpoint) {
+        Ok(outpoint) => outpoint,
+        Err(e) => return handle_result::<serde_json::Value>(Err(e)),
+    };
     handle_result(
         register_transfer(
             client.as_ref(),
             base_url.0.as_str(),
             macaroon_hex.0.as_str(),
-            req.into_inner(),
+            request,
         )
         .await,
     )
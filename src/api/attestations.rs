@@ -0,0 +1,335 @@
+//! Issuer attestations: signed statements an issuer publishes about its
+//! asset, such as the legal entity behind it, where its documentation lives
+//! and its redemption policy. The signature is BIP-340 Schnorr over the
+//! SHA-256 of the attestation's canonical JSON (keys sorted, no whitespace),
+//! made with the asset's group key, so only the holder of that key can
+//! publish. The gateway checks it against the group key in the universe
+//! before storing, and serves the signed document unchanged so clients can
+//! check it again.
+
+use super::universe::get_leaves;
+use super::{handle_result, require_database, validate_asset_id};
+use crate::crypto::{canonical_json, verify_group_key_signature};
+use crate::database::AttestationRecord;
+use crate::error::AppError;
+use crate::indexer::normalize_hex_id;
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, instrument};
+
+/// Upper bound on the canonical attestation document.
+const MAX_DOCUMENT_BYTES: usize = 16 * 1024;
+const MAX_LEGAL_ENTITY_CHARS: usize = 256;
+const MAX_POLICY_CHARS: usize = 4096;
+/// Tolerated clock skew for `issued_at` in the future.
+const MAX_FUTURE_SKEW_SECS: i64 = 300;
+
+/// The fields the gateway understands. Issuers may add others; they are
+/// covered by the signature and served as submitted.
+#[derive(Debug, Deserialize)]
+struct AttestationDocument {
+    asset_id: String,
+    legal_entity: String,
+    docs_url: Option<String>,
+    policy: Option<String>,
+    /// Unix seconds.
+    issued_at: i64,
+    expires_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublishAttestationRequest {
+    /// The signed document.
+    pub attestation: Value,
+    /// Hex BIP-340 signature over the SHA-256 of the document's canonical
+    /// JSON.
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Attestation {
+    pub id: String,
+    pub attestation: Value,
+    pub signature: String,
+    pub algorithm: &'static str,
+    /// Group key the signature verifies against.
+    pub group_key: String,
+    pub issued_at: i64,
+    pub expires_at: Option<i64>,
+    /// When the gateway stored it.
+    pub created_at: i64,
+    pub expired: bool,
+}
+
+impl Attestation {
+    fn from_record(record: AttestationRecord, now: i64) -> Self {
+        Self {
+            expired: record.expires_at.is_some_and(|at| at <= now),
+            id: record.id,
+            attestation: record.document,
+            signature: record.signature,
+            algorithm: "bip340-schnorr-sha256",
+            group_key: record.group_key,
+            issued_at: record.issued_at,
+            expires_at: record.expires_at,
+            created_at: record.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetAttestations {
+    pub asset_id: String,
+    /// Most recently issued first.
+    pub attestations: Vec<Attestation>,
+}
+
+/// Checks the document against the asset it is published for and returns
+/// its issue and expiry times.
+fn validate_document(
+    asset_id: &str,
+    document: &Value,
+    now: i64,
+) -> Result<AttestationDocument, AppError> {
+    if !document.is_object() {
+        return Err(AppError::ValidationError(
+            "attestation must be a JSON object".to_string(),
+        ));
+    }
+    if canonical_json(document).len() > MAX_DOCUMENT_BYTES {
+        return Err(AppError::ValidationError(format!(
+            "attestation must be at most {MAX_DOCUMENT_BYTES} bytes"
+        )));
+    }
+    let parsed: AttestationDocument = serde_json::from_value(document.clone())
+        .map_err(|e| AppError::ValidationError(format!("Invalid attestation: {e}")))?;
+    if !parsed.asset_id.eq_ignore_ascii_case(asset_id) {
+        return Err(AppError::ValidationError(format!(
+            "attestation.asset_id must be {asset_id}"
+        )));
+    }
+    let legal_entity = parsed.legal_entity.trim();
+    if legal_entity.is_empty() || legal_entity.chars().count() > MAX_LEGAL_ENTITY_CHARS {
+        return Err(AppError::ValidationError(format!(
+            "legal_entity must be 1 to {MAX_LEGAL_ENTITY_CHARS} characters"
+        )));
+    }
+    if let Some(docs_url) = &parsed.docs_url {
+        let valid = url::Url::parse(docs_url)
+            .map(|u| matches!(u.scheme(), "http" | "https") && u.host().is_some())
+            .unwrap_or(false);
+        if !valid {
+            return Err(AppError::ValidationError(
+                "docs_url must be an absolute http(s) URL".to_string(),
+            ));
+        }
+    }
+    if parsed
+        .policy
+        .as_ref()
+        .is_some_and(|p| p.chars().count() > MAX_POLICY_CHARS)
+    {
+        return Err(AppError::ValidationError(format!(
+            "policy must be at most {MAX_POLICY_CHARS} characters"
+        )));
+    }
+    if parsed.issued_at > now + MAX_FUTURE_SKEW_SECS {
+        return Err(AppError::ValidationError(
+            "issued_at is in the future".to_string(),
+        ));
+    }
+    if let Some(expires_at) = parsed.expires_at {
+        if expires_at <= parsed.issued_at {
+            return Err(AppError::ValidationError(
+                "expires_at must be after issued_at".to_string(),
+            ));
+        }
+        if expires_at <= now {
+            return Err(AppError::ValidationError(
+                "attestation has already expired".to_string(),
+            ));
+        }
+    }
+    Ok(parsed)
+}
+
+/// The group key of the asset's issuance leaves, if it is grouped.
+fn group_key_of(leaves: &Value) -> Option<String> {
+    leaves
+        .get("leaves")
+        .and_then(Value::as_array)?
+        .iter()
+        .filter_map(|leaf| {
+            leaf.get("asset")?
+                .get("asset_group")?
+                .get("tweaked_group_key")?
+                .as_str()
+        })
+        .find(|key| !key.is_empty())
+        .map(normalize_hex_id)
+}
+
+fn has_leaves(leaves: &Value) -> bool {
+    leaves
+        .get("leaves")
+        .and_then(Value::as_array)
+        .is_some_and(|leaves| !leaves.is_empty())
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, body))]
+async fn publish(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    path: web::Path<String>,
+    body: web::Json<PublishAttestationRequest>,
+) -> HttpResponse {
+    let result = async {
+        let asset_id = path.into_inner().to_ascii_lowercase();
+        validate_asset_id(&asset_id)?;
+        let database = require_database(&req)?;
+        let request = body.into_inner();
+        let now = chrono::Utc::now().timestamp();
+        let document = validate_document(&asset_id, &request.attestation, now)?;
+        let signature = request.signature.trim().to_ascii_lowercase();
+        if signature.len() != 128 || !signature.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(AppError::ValidationError(
+                "signature must be a 64-byte hex Schnorr signature".to_string(),
+            ));
+        }
+
+        let leaves = get_leaves(&client, &base_url.0, &macaroon_hex.0, &asset_id, "").await?;
+        if !has_leaves(&leaves) {
+            return Err(AppError::NotFound(format!(
+                "Asset {asset_id} is not in the universe"
+            )));
+        }
+        let group_key = group_key_of(&leaves).ok_or_else(|| {
+            AppError::ValidationError(format!(
+                "Asset {asset_id} has no group key to verify attestations against"
+            ))
+        })?;
+        let message = canonical_json(&request.attestation);
+        if !verify_group_key_signature(&message, &signature, &group_key)? {
+            return Err(AppError::ValidationError(format!(
+                "signature does not verify against group key {group_key}"
+            )));
+        }
+
+        let record = AttestationRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            asset_id: asset_id.clone(),
+            group_key,
+            document: request.attestation,
+            signature,
+            issued_at: document.issued_at,
+            expires_at: document.expires_at,
+            created_at: now,
+        };
+        if !database.save_attestation(&record).await? {
+            return Err(AppError::Conflict(
+                "This attestation was already published".to_string(),
+            ));
+        }
+        info!(
+            "Stored attestation {} for asset {} by {}",
+            record.id, asset_id, document.legal_entity
+        );
+        Ok(Attestation::from_record(record, now))
+    }
+    .await;
+    match result {
+        Ok(attestation) => HttpResponse::build(StatusCode::CREATED).json(attestation),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
+}
+
+async fn list(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let asset_id = path.into_inner().to_ascii_lowercase();
+        validate_asset_id(&asset_id)?;
+        let database = require_database(&req)?;
+        let now = chrono::Utc::now().timestamp();
+        let attestations = database
+            .attestations(&asset_id)
+            .await?
+            .into_iter()
+            .map(|record| Attestation::from_record(record, now))
+            .collect();
+        Ok(AssetAttestations {
+            asset_id,
+            attestations,
+        })
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/assets/{asset_id}/attestations")
+            .route(web::get().to(list))
+            .route(web::post().to(publish)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ASSET: &str = "abababababababababababababababababababababababababababababababab";
+
+    fn document() -> Value {
+        json!({
+            "asset_id": ASSET,
+            "legal_entity": "Example Issuer Ltd.",
+            "docs_url": "https://example.com/asset",
+            "policy": "Redeemable 1:1",
+            "issued_at": 1_000,
+            "expires_at": 5_000,
+            "jurisdiction": "CH"
+        })
+    }
+
+    #[test]
+    fn test_document_validation() {
+        let parsed = validate_document(ASSET, &document(), 2_000).unwrap();
+        assert_eq!(parsed.expires_at, Some(5_000));
+
+        let invalid = |patch: Value, now: i64| {
+            let mut doc = document();
+            for (k, v) in patch.as_object().unwrap() {
+                doc[k] = v.clone();
+            }
+            validate_document(ASSET, &doc, now).is_err()
+        };
+        assert!(invalid(json!({ "asset_id": "cd".repeat(32) }), 2_000));
+        assert!(invalid(json!({ "legal_entity": " " }), 2_000));
+        assert!(invalid(json!({ "docs_url": "ftp://example.com" }), 2_000));
+        assert!(invalid(json!({ "expires_at": 900 }), 2_000));
+        assert!(invalid(json!({}), 6_000));
+        assert!(invalid(
+            json!({ "issued_at": 9_000, "expires_at": null }),
+            2_000
+        ));
+        assert!(validate_document(ASSET, &json!([]), 2_000).is_err());
+    }
+
+    #[test]
+    fn test_group_key_from_issuance_leaves() {
+        let leaves = json!({ "leaves": [
+            { "asset": { "asset_group": null } },
+            { "asset": { "asset_group": { "tweaked_group_key": "02".repeat(33) } } }
+        ]});
+        assert!(has_leaves(&leaves));
+        assert_eq!(group_key_of(&leaves), Some("02".repeat(33)));
+        assert_eq!(group_key_of(&json!({ "leaves": [{ "asset": {} }] })), None);
+        assert!(!has_leaves(&json!({ "leaves": [] })));
+    }
+}
@@ -1,12 +1,29 @@
-use super::{handle_result, parse_upstream, validate_asset_id, validate_group_key};
+use super::indexer::{paged_query, validate_query};
+use super::universe::{asset_roots_request, get_leaves};
+use super::{
+    backend, handle_result, list_response, require_database, split_list_query, validate_asset_id,
+    validate_group_key, ListEnvelope,
+};
+use crate::crypto::{canonical_json, SharedResponseSigner};
+use crate::database::{
+    BurnTotal, ChainStatus, IndexedTransfer, SharedDatabase, TransferKind, TransferQuery,
+};
 use crate::error::AppError;
+use crate::indexer::{normalize_burn_response, normalize_hex_id};
+use crate::pdf;
 use crate::types::{BaseUrl, MacaroonHex};
 use actix_web::{web, HttpRequest, HttpResponse};
+use bitcoin::Txid;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use tracing::{info, instrument};
+use serde_json::Value;
+use std::str::FromStr;
+use tracing::{info, instrument, warn};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Version of the burn certificate document, bumped on incompatible changes.
+const CERTIFICATE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssetSpecifier {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub asset_id_str: Option<String>,
@@ -29,7 +46,7 @@ impl AssetSpecifier {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BurnRequest {
     pub asset_specifier: AssetSpecifier,
     pub amount_to_burn: String,
@@ -51,15 +68,11 @@ pub async fn burn_assets(
         amount_to_burn = %request.amount_to_burn,
         "Burning assets"
     );
-    let url = format!("{base_url}/v1/taproot-assets/burn");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/burn")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -70,35 +83,54 @@ pub async fn list_burns(
     query: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Listing burns");
-    let mut url = format!("{base_url}/v1/taproot-assets/burns");
-    if !query.is_empty() {
-        url.push('?');
-        url.push_str(query);
-    }
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/burns")
+        .query(query)
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
+}
+
+/// Records a completed burn in the index right away instead of waiting for
+/// the next backfill. Failing to index never fails the burn itself.
+async fn index_burn(
+    database: &SharedDatabase,
+    request: &BurnRequest,
+    response: &serde_json::Value,
+) {
+    let row = normalize_burn_response(
+        request.asset_specifier.asset_id_str.as_deref(),
+        request.amount_to_burn.parse().ok(),
+        request.note.as_deref(),
+        response,
+    );
+    if let Some(row) = row {
+        if let Err(e) = database.upsert_indexed_transfers(&[row]).await {
+            warn!("Failed to index burn: {}", e);
+        }
+    }
 }
 
 async fn burn(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<BurnRequest>,
 ) -> HttpResponse {
-    handle_result(
-        burn_assets(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
-        )
-        .await,
+    let request = req.into_inner();
+    let result = burn_assets(
+        client.as_ref(),
+        &base_url.0,
+        &macaroon_hex.0,
+        request.clone(),
     )
+    .await;
+    if let (Ok(response), Some(database)) =
+        (&result, http_req.app_data::<web::Data<SharedDatabase>>())
+    {
+        index_burn(database, &request, response).await;
+    }
+    handle_result(result)
 }
 
 async fn list(
@@ -107,15 +139,431 @@ async fn list(
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
 ) -> HttpResponse {
-    handle_result(
-        list_burns(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.query_string(),
+    let (page, query) = match split_list_query(req.query_string()) {
+        Ok(split) => split,
+        Err(e) => return handle_result::<serde_json::Value>(Err(e)),
+    };
+    list_response(
+        &req,
+        &page,
+        list_burns(client.as_ref(), &base_url.0, &macaroon_hex.0, &query).await,
+        "burns",
+    )
+}
+
+/// Filters accepted by the burn history endpoint. Time bounds are inclusive
+/// unix seconds.
+#[derive(Debug, Default, Deserialize)]
+pub struct BurnHistoryQuery {
+    pub asset_id: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+/// One indexed burn as reported by the history endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct BurnRecord {
+    pub txid: Option<String>,
+    pub asset_id: Option<String>,
+    pub amount: Option<u64>,
+    pub note: Option<String>,
+    /// `confirmed`, `unconfirmed`, or the reconciler's `reorged`/`replaced`.
+    pub confirmation_status: String,
+    pub confirmations: Option<u32>,
+    pub block_height: Option<u32>,
+    pub timestamp: i64,
+}
+
+impl From<IndexedTransfer> for BurnRecord {
+    fn from(burn: IndexedTransfer) -> Self {
+        let confirmation_status = match (burn.chain_status, burn.block_height) {
+            (Some(status), _) => status.as_str(),
+            (None, Some(_)) => ChainStatus::Confirmed.as_str(),
+            (None, None) => ChainStatus::Unconfirmed.as_str(),
+        };
+        BurnRecord {
+            note: burn
+                .raw
+                .get("note")
+                .and_then(|n| n.as_str())
+                .filter(|n| !n.is_empty())
+                .map(str::to_string),
+            txid: burn.anchor_txid,
+            asset_id: burn.asset_id,
+            amount: burn.amount,
+            confirmation_status: confirmation_status.to_string(),
+            confirmations: burn.confirmations,
+            block_height: burn.block_height,
+            timestamp: burn.timestamp,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BurnHistory {
+    #[serde(flatten)]
+    pub page: ListEnvelope<BurnRecord>,
+    /// Everything burned per asset up to `to`, regardless of `from` and the
+    /// page.
+    pub cumulative: Vec<BurnTotal>,
+}
+
+#[instrument(skip(req))]
+async fn burn_history(req: &HttpRequest, query: BurnHistoryQuery) -> Result<BurnHistory, AppError> {
+    let query = TransferQuery {
+        kind: Some(TransferKind::Burn),
+        asset_id: query.asset_id,
+        from: query.from,
+        to: query.to,
+        ..Default::default()
+    };
+    let (mut query, offset, limit) = paged_query(req, query)?;
+    validate_query(&mut query)?;
+    let database = require_database(req)?;
+    let burns = database.query_indexed_transfers(&query).await?;
+    let cumulative = database
+        .burn_totals(query.asset_id.as_deref(), query.to)
+        .await?;
+    let burns = burns.into_iter().map(BurnRecord::from).collect();
+    Ok(BurnHistory {
+        page: ListEnvelope::from_offset_page(burns, offset, limit).with_next_link(req),
+        cumulative,
+    })
+}
+
+async fn list_history(req: HttpRequest, query: web::Query<BurnHistoryQuery>) -> HttpResponse {
+    handle_result(burn_history(&req, query.into_inner()).await)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CertificateQuery {
+    /// Which burn, when the transaction burned more than one asset.
+    pub asset_id: Option<String>,
+    /// `json` (the default) or `pdf`.
+    pub format: Option<String>,
+}
+
+/// The burn being attested: its history record and the confirming block.
+#[derive(Debug, Clone, Serialize)]
+pub struct CertifiedBurn {
+    #[serde(flatten)]
+    pub record: BurnRecord,
+    pub block_hash: Option<String>,
+}
+
+/// The burned asset, as its issuance leaves in the local universe describe it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CertifiedAsset {
+    pub asset_id: String,
+    pub name: Option<String>,
+    pub asset_type: Option<String>,
+    pub genesis_point: Option<String>,
+    pub meta_hash: Option<String>,
+    pub group_key: Option<String>,
+    /// Sum of the issuance leaves.
+    pub minted: u64,
+}
+
+/// An MS-SMT root of one of the asset's universe trees.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UniverseRootRef {
+    pub root_hash: String,
+    pub root_sum: Option<u64>,
+}
+
+/// The asset's universe roots when the certificate was issued.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UniverseReference {
+    pub issuance_root: Option<UniverseRootRef>,
+    pub transfer_root: Option<UniverseRootRef>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BurnCertificate {
+    pub version: u32,
+    pub issued_at: i64,
+    pub burn: CertifiedBurn,
+    pub asset: CertifiedAsset,
+    pub universe: UniverseReference,
+    /// Everything indexed as burned of the asset, this burn included.
+    pub total_burned: u64,
+}
+
+/// Schnorr signature over the SHA-256 of the certificate's canonical JSON,
+/// made with the response signing key.
+#[derive(Debug, Clone, Serialize)]
+pub struct CertificateSignature {
+    pub algorithm: &'static str,
+    pub kid: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SignedBurnCertificate {
+    pub certificate: BurnCertificate,
+    pub signature: CertificateSignature,
+}
+
+/// tapd encodes uint64 fields as JSON strings.
+fn u64_of(value: Option<&Value>) -> Option<u64> {
+    match value? {
+        Value::String(s) => s.parse().ok(),
+        Value::Number(n) => n.as_u64(),
+        _ => None,
+    }
+}
+
+fn str_of(value: Option<&Value>) -> Option<String> {
+    value
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// Reads the asset's details from its universe issuance leaves.
+fn certified_asset(asset_id: &str, leaves: &Value) -> CertifiedAsset {
+    let assets: Vec<&Value> = leaves
+        .get("leaves")
+        .and_then(Value::as_array)
+        .map(|leaves| leaves.iter().filter_map(|leaf| leaf.get("asset")).collect())
+        .unwrap_or_default();
+    let mut asset = CertifiedAsset {
+        asset_id: asset_id.to_string(),
+        minted: assets.iter().filter_map(|a| u64_of(a.get("amount"))).sum(),
+        ..Default::default()
+    };
+    if let Some(first) = assets.first() {
+        let genesis = first.get("asset_genesis");
+        asset.name = str_of(genesis.and_then(|g| g.get("name")));
+        asset.genesis_point = str_of(genesis.and_then(|g| g.get("genesis_point")));
+        asset.meta_hash =
+            str_of(genesis.and_then(|g| g.get("meta_hash"))).map(|h| normalize_hex_id(&h));
+        asset.asset_type = str_of(first.get("asset_type"))
+            .or_else(|| str_of(genesis.and_then(|g| g.get("asset_type"))));
+        asset.group_key = str_of(
+            first
+                .get("asset_group")
+                .and_then(|g| g.get("tweaked_group_key")),
         )
-        .await,
+        .map(|k| normalize_hex_id(&k));
+    }
+    asset
+}
+
+/// Reads the issuance and transfer roots out of tapd's QueryAssetRoots.
+fn universe_reference(roots: &Value) -> UniverseReference {
+    let root = |name: &str| {
+        let mssmt = roots.get(name)?.get("mssmt_root")?;
+        Some(UniverseRootRef {
+            root_hash: normalize_hex_id(&str_of(mssmt.get("root_hash"))?),
+            root_sum: u64_of(mssmt.get("root_sum")),
+        })
+    };
+    UniverseReference {
+        issuance_root: root("issuance_root"),
+        transfer_root: root("transfer_root"),
+    }
+}
+
+#[instrument(skip(req, client, macaroon_hex))]
+async fn burn_certificate(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    txid: &str,
+    asset_id: Option<&str>,
+) -> Result<SignedBurnCertificate, AppError> {
+    let txid = Txid::from_str(txid)
+        .map_err(|_| AppError::InvalidInput(format!("Invalid txid: {txid}")))?
+        .to_string();
+    if let Some(asset_id) = asset_id {
+        validate_asset_id(asset_id)?;
+    }
+    let signer = req
+        .app_data::<web::Data<SharedResponseSigner>>()
+        .ok_or_else(|| {
+            AppError::ServiceUnavailable(
+                "Burn certificates are signed; configure RESPONSE_SIGNING_KEY".to_string(),
+            )
+        })?;
+    let database = require_database(req)?;
+
+    let burns = database.burns_by_txid(&txid).await?;
+    let burn = match asset_id {
+        Some(asset_id) => {
+            let asset_id = asset_id.to_ascii_lowercase();
+            burns
+                .into_iter()
+                .find(|b| b.asset_id.as_deref() == Some(asset_id.as_str()))
+        }
+        None if burns.len() > 1 => {
+            return Err(AppError::InvalidInput(format!(
+                "Transaction {txid} burned {} assets; pass asset_id",
+                burns.len()
+            )));
+        }
+        None => burns.into_iter().next(),
+    }
+    .ok_or_else(|| AppError::NotFound(format!("No indexed burn in transaction {txid}")))?;
+    let block_hash = burn.block_hash.clone();
+    let record = BurnRecord::from(burn);
+    if record.confirmation_status != ChainStatus::Confirmed.as_str() {
+        return Err(AppError::Conflict(format!(
+            "Burn {txid} is {}; certificates are issued once it confirms",
+            record.confirmation_status
+        )));
+    }
+    let asset_id = record
+        .asset_id
+        .clone()
+        .ok_or_else(|| AppError::NotFound(format!("Burn {txid} has no asset id")))?;
+
+    let leaves = get_leaves(client, base_url, macaroon_hex, &asset_id, "").await?;
+    let roots = asset_roots_request(client, base_url, macaroon_hex, &asset_id, "")
+        .fetch::<Value>()
+        .await?;
+    let total_burned = database
+        .burn_totals(Some(&asset_id), None)
+        .await?
+        .first()
+        .map_or(0, |total| total.total_burned);
+
+    let certificate = BurnCertificate {
+        version: CERTIFICATE_VERSION,
+        issued_at: chrono::Utc::now().timestamp(),
+        burn: CertifiedBurn { record, block_hash },
+        asset: certified_asset(&asset_id, &leaves),
+        universe: universe_reference(&roots),
+        total_burned,
+    };
+    let signature = signer.sign(&canonical_json(&serde_json::to_value(&certificate)?));
+    Ok(SignedBurnCertificate {
+        certificate,
+        signature: CertificateSignature {
+            algorithm: "bip340-schnorr-sha256",
+            kid: signer.kid().to_string(),
+            public_key: signer.public_key_hex(),
+            signature,
+        },
+    })
+}
+
+/// The human-readable certificate, ending with the signed JSON so the PDF
+/// can be verified on its own.
+fn certificate_lines(signed: &SignedBurnCertificate) -> Result<Vec<String>, AppError> {
+    let c = &signed.certificate;
+    let time = |secs: i64| {
+        chrono::DateTime::from_timestamp(secs, 0)
+            .map_or_else(|| secs.to_string(), |t| t.to_rfc3339())
+    };
+    let opt = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    let num = |value: Option<u64>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+    let root = |root: &Option<UniverseRootRef>| {
+        root.as_ref().map_or_else(
+            || "-".to_string(),
+            |r| format!("{} (sum {})", r.root_hash, num(r.root_sum)),
+        )
+    };
+    let burn = &c.burn.record;
+    Ok(vec![
+        format!(
+            "Issued {} (certificate version {})",
+            time(c.issued_at),
+            c.version
+        ),
+        String::new(),
+        "Burn".to_string(),
+        format!("  Transaction:    {}", opt(&burn.txid)),
+        format!("  Amount burned:  {}", num(burn.amount)),
+        format!("  Note:           {}", opt(&burn.note)),
+        format!("  Burned at:      {}", time(burn.timestamp)),
+        format!(
+            "  Block:          {} {}",
+            num(burn.block_height.map(u64::from)),
+            opt(&c.burn.block_hash)
+        ),
+        format!(
+            "  Confirmations:  {}",
+            num(burn.confirmations.map(u64::from))
+        ),
+        String::new(),
+        "Asset".to_string(),
+        format!("  Asset id:       {}", c.asset.asset_id),
+        format!("  Name:           {}", opt(&c.asset.name)),
+        format!("  Type:           {}", opt(&c.asset.asset_type)),
+        format!("  Genesis point:  {}", opt(&c.asset.genesis_point)),
+        format!("  Meta hash:      {}", opt(&c.asset.meta_hash)),
+        format!("  Group key:      {}", opt(&c.asset.group_key)),
+        format!("  Minted:         {}", c.asset.minted),
+        format!("  Total burned:   {}", c.total_burned),
+        String::new(),
+        "Universe".to_string(),
+        format!("  Issuance root:  {}", root(&c.universe.issuance_root)),
+        format!("  Transfer root:  {}", root(&c.universe.transfer_root)),
+        String::new(),
+        "Signature".to_string(),
+        format!("  Algorithm:      {}", signed.signature.algorithm),
+        format!("  Key id:         {}", signed.signature.kid),
+        format!("  Public key:     {}", signed.signature.public_key),
+        format!("  Signature:      {}", signed.signature.signature),
+        String::new(),
+        "Signed document (canonical JSON; the signature covers its SHA-256):".to_string(),
+        canonical_json(&serde_json::to_value(c)?),
+    ])
+}
+
+async fn certificate(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    path: web::Path<String>,
+    query: web::Query<CertificateQuery>,
+) -> HttpResponse {
+    let query = query.into_inner();
+    let pdf = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("pdf") => true,
+        Some(other) => {
+            return handle_result::<()>(Err(AppError::InvalidInput(format!(
+                "Unknown format {other}; expected json or pdf"
+            ))))
+        }
+    };
+    let txid = path.into_inner();
+    let result = burn_certificate(
+        &req,
+        &client,
+        &base_url.0,
+        &macaroon_hex.0,
+        &txid,
+        query.asset_id.as_deref(),
     )
+    .await;
+    match result {
+        Ok(signed) if pdf => match certificate_lines(&signed) {
+            Ok(lines) => HttpResponse::Ok()
+                .content_type("application/pdf")
+                .insert_header((
+                    "Content-Disposition",
+                    format!(
+                        "attachment; filename=\"burn-{}.pdf\"",
+                        signed
+                            .certificate
+                            .burn
+                            .record
+                            .txid
+                            .as_deref()
+                            .unwrap_or(&txid)
+                    ),
+                ))
+                .body(pdf::text_document("Taproot Assets Proof of Burn", &lines)),
+            Err(e) => handle_result::<()>(Err(e)),
+        },
+        result => handle_result(result),
+    }
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
@@ -123,6 +571,12 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         .service(web::resource("/burns").route(web::get().to(list)));
 }
 
+/// Gateway-owned burn routes, served from the indexer's database.
+pub fn configure_gateway(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/burns").route(web::get().to(list_history)))
+        .service(web::resource("/burns/{txid}/certificate").route(web::get().to(certificate)));
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -157,4 +611,81 @@ mod tests {
         assert!(specifier(Some("deadbeef"), None).validate().is_err());
         assert!(specifier(Some(&"z".repeat(64)), None).validate().is_err());
     }
+
+    #[test]
+    fn test_certificate_reads_tapd_documents() {
+        let asset_id = "11".repeat(32);
+        let leaves = serde_json::json!({ "leaves": [
+            { "asset": {
+                "amount": "700",
+                "asset_type": "NORMAL",
+                "asset_genesis": { "name": "bond", "genesis_point": "ab:0", "meta_hash": "q6urqw==" },
+                "asset_group": { "tweaked_group_key": "02".repeat(33) }
+            } },
+            { "asset": { "amount": "300" } }
+        ] });
+        let asset = certified_asset(&asset_id, &leaves);
+        assert_eq!(asset.name.as_deref(), Some("bond"));
+        assert_eq!(asset.asset_type.as_deref(), Some("NORMAL"));
+        assert_eq!(asset.meta_hash.as_deref(), Some("abababab"));
+        assert_eq!(asset.group_key, Some("02".repeat(33)));
+        assert_eq!(asset.minted, 1000);
+
+        let roots = serde_json::json!({
+            "issuance_root": { "mssmt_root": { "root_hash": "q6urqw==", "root_sum": "1000" } }
+        });
+        let universe = universe_reference(&roots);
+        assert_eq!(
+            universe.issuance_root,
+            Some(UniverseRootRef {
+                root_hash: "abababab".to_string(),
+                root_sum: Some(1000)
+            })
+        );
+        assert!(universe.transfer_root.is_none());
+    }
+
+    #[test]
+    fn test_pdf_lines_carry_the_signed_document() {
+        let signer = crate::crypto::ResponseSigner::from_hex(&"03".repeat(32)).unwrap();
+        let certificate = BurnCertificate {
+            version: CERTIFICATE_VERSION,
+            issued_at: 1_700_000_000,
+            burn: CertifiedBurn {
+                record: BurnRecord {
+                    txid: Some("cd".repeat(32)),
+                    asset_id: Some("11".repeat(32)),
+                    amount: Some(40),
+                    note: None,
+                    confirmation_status: "confirmed".to_string(),
+                    confirmations: Some(6),
+                    block_height: Some(800_000),
+                    timestamp: 1_699_999_000,
+                },
+                block_hash: None,
+            },
+            asset: CertifiedAsset::default(),
+            universe: universe_reference(&Value::Null),
+            total_burned: 40,
+        };
+        let document = canonical_json(&serde_json::to_value(&certificate).unwrap());
+        let signed = SignedBurnCertificate {
+            signature: CertificateSignature {
+                algorithm: "bip340-schnorr-sha256",
+                kid: signer.kid().to_string(),
+                public_key: signer.public_key_hex(),
+                signature: signer.sign(&document),
+            },
+            certificate,
+        };
+        let lines = certificate_lines(&signed).unwrap();
+        assert_eq!(lines.last(), Some(&document));
+        assert!(lines.contains(&"  Block:          800000 -".to_string()));
+        assert!(crate::crypto::verify_schnorr_signature(
+            &document,
+            &signed.signature.signature,
+            &signed.signature.public_key
+        )
+        .unwrap());
+    }
 }
@@ -1,6 +1,8 @@
-use super::{handle_result, parse_upstream};
+use super::{backend, handle_result};
 use crate::error::AppError;
 use crate::types::{BaseUrl, MacaroonHex};
+use crate::websocket::correlation::CorrelationStrategy;
+use crate::websocket::policy::EndpointGroup;
 use crate::websocket::proxy_handler::WebSocketProxyHandler;
 use actix_web::{web, HttpRequest, HttpResponse};
 use reqwest::Client;
@@ -80,15 +82,11 @@ pub async fn encode_custom_data(
     request: EncodeCustomDataRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Encoding custom data");
-    let url = format!("{base_url}/v1/taproot-assets/channels/encode-custom-data");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/channels/encode-custom-data")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -99,15 +97,11 @@ pub async fn fund_channel(
     request: FundChannelRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Funding channel for asset ID: {}", request.asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/channels/fund");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/channels/fund")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -118,15 +112,11 @@ pub async fn create_invoice(
     request: InvoiceRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Creating invoice for asset ID: {}", request.asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/channels/invoice");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/channels/invoice")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -137,15 +127,11 @@ pub async fn decode_invoice(
     request: DecodeInvoiceRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Decoding invoice for asset ID: {}", request.asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/channels/invoice/decode");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/channels/invoice/decode")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -156,15 +142,11 @@ pub async fn send_payment(
     request: SendPaymentRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Sending payment for asset ID: {}", request.asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/channels/send-payment");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/channels/send-payment")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(req, stream, ws_proxy_handler))]
@@ -190,7 +172,13 @@ async fn send_payment_websocket_handler(
     // Handle the WebSocket connection with correlation tracking enabled
     // This allows us to track request/response pairs for payment streaming
     ws_proxy_handler
-        .handle_websocket(req, stream, backend_endpoint, true)
+        .handle_websocket(
+            req,
+            stream,
+            backend_endpoint,
+            CorrelationStrategy::Sequence,
+            EndpointGroup::Channels,
+        )
         .await
 }
 
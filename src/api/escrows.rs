@@ -0,0 +1,229 @@
+use super::{
+    handle_result, public_url, require_database, validate_asset_id, ListEnvelope, PageParams,
+};
+use crate::database::{Escrow, EscrowQuery, SharedDatabase};
+use crate::error::AppError;
+use crate::escrows::{EscrowManager, EscrowScript, MAX_TIMEOUT_BLOCKS};
+use crate::event_bus::SharedEventBus;
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::instrument;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateEscrowRequest {
+    pub asset_id: String,
+    pub amount: u64,
+    /// Hex SHA-256 hash of the release preimage. When omitted the gateway
+    /// picks the preimage and reveals it on release.
+    pub payment_hash: Option<String>,
+    /// Hex key the beneficiary signs the release leaf with.
+    pub beneficiary_key: String,
+    /// Blocks from now until the deposit can be refunded.
+    pub timeout_blocks: u32,
+}
+
+impl CreateEscrowRequest {
+    fn validate(&self) -> Result<(), AppError> {
+        validate_asset_id(&self.asset_id)?;
+        if self.amount == 0 {
+            return Err(AppError::ValidationError(
+                "amount must be greater than zero".to_string(),
+            ));
+        }
+        if self.timeout_blocks == 0 || self.timeout_blocks > MAX_TIMEOUT_BLOCKS {
+            return Err(AppError::ValidationError(format!(
+                "timeout_blocks must be between 1 and {MAX_TIMEOUT_BLOCKS}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReleaseEscrowRequest {
+    /// Hex preimage of the payment hash; optional when the gateway picked it.
+    pub preimage: Option<String>,
+}
+
+fn manager(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+) -> Result<EscrowManager, AppError> {
+    let events = req
+        .app_data::<web::Data<SharedEventBus>>()
+        .ok_or_else(|| AppError::ServiceUnavailable("Event bus not configured".to_string()))?;
+    Ok(EscrowManager::new(
+        client.clone(),
+        base_url.to_string(),
+        macaroon_hex.to_string(),
+        require_database(req)?,
+        events.get_ref().clone(),
+    ))
+}
+
+async fn load(database: &SharedDatabase, id: &str) -> Result<Escrow, AppError> {
+    database
+        .get_escrow(id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Escrow {id} not found")))
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, body))]
+async fn create(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    body: web::Json<CreateEscrowRequest>,
+) -> HttpResponse {
+    let result = async {
+        let request = body.into_inner();
+        request.validate()?;
+        manager(&req, &client, &base_url.0, &macaroon_hex.0)?
+            .create(
+                &request.asset_id,
+                request.amount,
+                request.payment_hash,
+                &request.beneficiary_key,
+                request.timeout_blocks,
+            )
+            .await
+    }
+    .await;
+    match result {
+        Ok(escrow) => HttpResponse::build(StatusCode::CREATED)
+            .insert_header((
+                header::LOCATION,
+                public_url(&req, &format!("/v1/gateway/escrows/{}", escrow.id)),
+            ))
+            .json(escrow.revealed()),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
+}
+
+async fn list(req: HttpRequest, query: web::Query<EscrowQuery>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let page = PageParams::from_query(req.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let query = EscrowQuery {
+            limit: Some(limit + 1),
+            offset: Some(offset),
+            ..query.into_inner()
+        };
+        let escrows = database
+            .list_escrows(&query)
+            .await?
+            .into_iter()
+            .map(Escrow::revealed)
+            .collect();
+        Ok(ListEnvelope::from_offset_page(escrows, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Returns the escrow after checking tapd for its deposit, so polling sees
+/// it funded.
+async fn get(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let result = async {
+        let manager = manager(&req, &client, &base_url.0, &macaroon_hex.0)?;
+        let escrow = load(&require_database(&req)?, &path.into_inner()).await?;
+        manager.refresh(escrow).await.map(Escrow::revealed)
+    }
+    .await;
+    handle_result(result)
+}
+
+/// The escrow's leaves and control blocks, so either party can check the
+/// lock or spend it with its own tooling.
+async fn script(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let escrow = load(&require_database(&req)?, &path.into_inner()).await?;
+        EscrowScript::of(&escrow)?.describe()
+    }
+    .await;
+    handle_result(result)
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, body))]
+async fn release(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    path: web::Path<String>,
+    body: Option<web::Json<ReleaseEscrowRequest>>,
+) -> HttpResponse {
+    let result = async {
+        let manager = manager(&req, &client, &base_url.0, &macaroon_hex.0)?;
+        let escrow = load(&require_database(&req)?, &path.into_inner()).await?;
+        let preimage = body.map(|b| b.into_inner()).unwrap_or_default().preimage;
+        manager
+            .release(escrow, preimage)
+            .await
+            .map(Escrow::revealed)
+    }
+    .await;
+    handle_result(result)
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex))]
+async fn refund(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let result = async {
+        let manager = manager(&req, &client, &base_url.0, &macaroon_hex.0)?;
+        let escrow = load(&require_database(&req)?, &path.into_inner()).await?;
+        manager.refund(escrow).await.map(Escrow::revealed)
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/escrows")
+            .route(web::get().to(list))
+            .route(web::post().to(create)),
+    )
+    .service(web::resource("/escrows/{id}").route(web::get().to(get)))
+    .service(web::resource("/escrows/{id}/script").route(web::get().to(script)))
+    .service(web::resource("/escrows/{id}/release").route(web::post().to(release)))
+    .service(web::resource("/escrows/{id}/refund").route(web::post().to(refund)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_amount_and_timeout() {
+        let request = |amount, timeout_blocks| CreateEscrowRequest {
+            asset_id: "a".repeat(64),
+            amount,
+            payment_hash: None,
+            beneficiary_key: "02".repeat(33),
+            timeout_blocks,
+        };
+        assert!(request(10, 144).validate().is_ok());
+        assert!(request(0, 144).validate().is_err());
+        assert!(request(10, 0).validate().is_err());
+        assert!(request(10, MAX_TIMEOUT_BLOCKS + 1).validate().is_err());
+    }
+}
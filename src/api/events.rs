@@ -1,12 +1,26 @@
-use super::{handle_result, parse_upstream};
+use super::{backend, handle_result, validate_integer_param, validate_taproot_address};
+use crate::channel_events::CHANNEL_TOPIC_PREFIX;
+use crate::config::Config;
 use crate::error::AppError;
+use crate::event_bus::{EventBus, GatewayEvent, Replay, SharedEventBus};
+use crate::gateway::RouteFilter;
 use crate::types::{BaseUrl, MacaroonHex};
+use crate::websocket::control;
+use crate::websocket::filter::EventFilter;
+use crate::websocket::policy::EndpointGroup;
 use crate::websocket::proxy_handler::WebSocketProxyHandler;
+use crate::websocket::topics::Subscription;
+use actix_web::http::header;
+use actix_web::web::Bytes;
 use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use futures_util::{stream, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
 use tracing::{info, instrument, warn};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,13 +46,183 @@ pub struct AssetSendRequest {
     pub filter_label: Option<String>,
 }
 
-// Create a separate client for event subscriptions with longer timeout
-fn create_event_client() -> Result<Client, AppError> {
-    Client::builder()
-        .danger_accept_invalid_certs(true)
-        .timeout(Duration::from_secs(300)) // 5 minute timeout for event subscriptions
-        .build()
-        .map_err(|e| AppError::ValidationError(format!("Failed to create event client: {e}")))
+#[derive(Debug, Deserialize)]
+pub struct AddrReceivesQuery {
+    pub addr: String,
+    pub start_timestamp: Option<String>,
+}
+
+/// Events one long-poll returns unless it asks for fewer.
+const POLL_DEFAULT_LIMIT: usize = 100;
+const POLL_MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct PollQuery {
+    /// Sequence of the last event the client saw. Without one, only events
+    /// published after the request arrives are returned.
+    pub cursor: Option<u64>,
+    /// Seconds to wait for an event, capped at `EVENT_POLL_MAX_SECS`.
+    pub timeout: Option<u64>,
+    pub limit: Option<usize>,
+    /// Comma-separated topics; `transfer` also matches `transfer.confirmed`.
+    pub topics: Option<String>,
+}
+
+/// Event subscriptions hold the request open until tapd has an event.
+const EVENT_SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Media type clients ask for to get events as they arrive, one JSON object
+/// per line.
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Whether the client asked for an NDJSON event stream.
+fn wants_ndjson(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| {
+            accept
+                .split(',')
+                .any(|media| media.trim().starts_with(NDJSON_CONTENT_TYPE))
+        })
+}
+
+/// Cuts tapd's streamed REST body, newline-separated `{"result": ...}` or
+/// `{"error": ...}` objects, into NDJSON lines of the bare events that pass
+/// the subscriber's filter. Chunks may end mid-object; the remainder waits
+/// for the next chunk.
+#[derive(Debug, Default)]
+pub struct NdjsonSplitter {
+    buffer: Vec<u8>,
+    filter: EventFilter,
+}
+
+impl NdjsonSplitter {
+    pub fn new(filter: EventFilter) -> Self {
+        Self {
+            buffer: Vec::new(),
+            filter,
+        }
+    }
+
+    /// The lines for every event `chunk` completes.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Bytes> {
+        self.buffer.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            lines.extend(self.event_line(&line));
+        }
+        lines
+    }
+
+    /// The last event, when tapd's stream ends without a trailing newline.
+    pub fn finish(&mut self) -> Option<Bytes> {
+        let rest = std::mem::take(&mut self.buffer);
+        self.event_line(&rest)
+    }
+
+    fn event_line(&self, raw: &[u8]) -> Option<Bytes> {
+        let raw = raw.trim_ascii();
+        if raw.is_empty() {
+            return None;
+        }
+        let value = match serde_json::from_slice::<serde_json::Value>(raw) {
+            Ok(value) if !self.filter.matches(&value) => return None,
+            Ok(serde_json::Value::Object(mut object)) if object.contains_key("result") => {
+                object.remove("result").unwrap_or_default()
+            }
+            Ok(value) => value,
+            Err(e) => serde_json::json!({ "error": format!("Unreadable event from tapd: {e}") }),
+        };
+        Some(json_line(&value))
+    }
+}
+
+fn json_line(value: &serde_json::Value) -> Bytes {
+    let mut line = serde_json::to_vec(value).unwrap_or_default();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+fn timeout_line() -> Bytes {
+    json_line(&serde_json::json!({
+        "timeout": true,
+        "message": "No more events within the subscription timeout"
+    }))
+}
+
+/// Subscribes to one of tapd's event streams and relays each event to the
+/// client as its own NDJSON line as soon as it arrives. The stream ends with
+/// a `timeout` line once the subscription timeout passes.
+async fn stream_events<T: Serialize>(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    event_type: &str,
+    request: &T,
+    filter: EventFilter,
+) -> Result<HttpResponse, AppError> {
+    info!("Streaming {} events as NDJSON", event_type);
+    let response = backend(client, base_url, macaroon_hex)
+        .timeout(EVENT_SUBSCRIPTION_TIMEOUT)
+        .post(&format!("/v1/taproot-assets/events/{event_type}"))
+        .json(request)
+        .send()
+        .await;
+    let resp = match response {
+        Ok(resp) => resp,
+        Err(AppError::RequestError(e)) if e.is_timeout() => {
+            return Ok(HttpResponse::Ok()
+                .content_type(NDJSON_CONTENT_TYPE)
+                .body(timeout_line()));
+        }
+        Err(e) => return Err(e),
+    };
+    let status = resp.status();
+    if !status.is_success() {
+        let error_text = resp
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(AppError::ValidationError(format!(
+            "Event subscription failed with status {status}: {error_text}"
+        )));
+    }
+
+    let lines = stream::unfold(
+        (Some(resp), NdjsonSplitter::new(filter), VecDeque::new()),
+        |(mut body, mut splitter, mut pending)| async move {
+            loop {
+                if let Some(line) = pending.pop_front() {
+                    return Some((Ok::<_, actix_web::Error>(line), (body, splitter, pending)));
+                }
+                let source = body.as_mut()?;
+                match source.chunk().await {
+                    Ok(Some(chunk)) => pending.extend(splitter.push(&chunk)),
+                    Err(e) => {
+                        body = None;
+                        pending.extend(splitter.finish());
+                        if e.is_timeout() {
+                            pending.push_back(timeout_line());
+                        } else {
+                            warn!("Event stream from tapd failed: {}", e);
+                            pending.push_back(json_line(&serde_json::json!({
+                                "error": format!("Event stream from tapd failed: {e}")
+                            })));
+                        }
+                    }
+                    Ok(None) => {
+                        body = None;
+                        pending.extend(splitter.finish());
+                    }
+                }
+            }
+        },
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(NDJSON_CONTENT_TYPE)
+        .streaming(lines))
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -49,30 +233,24 @@ pub async fn set_debug_level(
     request: DebugLevelRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Setting debug level: {}", request.level_spec);
-    let url = format!("{base_url}/v1/taproot-assets/debuglevel");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/debuglevel")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
-#[instrument(skip(macaroon_hex, request))]
+#[instrument(skip(client, macaroon_hex, request))]
 pub async fn asset_mint_events(
+    client: &Client,
     base_url: &str,
     macaroon_hex: &str,
     request: AssetMintRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Subscribing to asset mint events");
-    let event_client = create_event_client()?;
-    let url = format!("{base_url}/v1/taproot-assets/events/asset-mint");
-
-    let response = event_client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    let response = backend(client, base_url, macaroon_hex)
+        .timeout(EVENT_SUBSCRIPTION_TIMEOUT)
+        .post("/v1/taproot-assets/events/asset-mint")
         .json(&request)
         .send()
         .await;
@@ -94,7 +272,7 @@ pub async fn asset_mint_events(
                 )))
             }
         }
-        Err(e) if e.is_timeout() => {
+        Err(AppError::RequestError(e)) if e.is_timeout() => {
             warn!("Asset mint event subscription timed out");
             Ok(serde_json::json!({
                 "events": [],
@@ -102,23 +280,21 @@ pub async fn asset_mint_events(
                 "message": "No events received within timeout period"
             }))
         }
-        Err(e) => Err(AppError::RequestError(e)),
+        Err(e) => Err(e),
     }
 }
 
-#[instrument(skip(macaroon_hex, request))]
+#[instrument(skip(client, macaroon_hex, request))]
 pub async fn asset_receive_events(
+    client: &Client,
     base_url: &str,
     macaroon_hex: &str,
     request: AssetReceiveRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Subscribing to asset receive events");
-    let event_client = create_event_client()?;
-    let url = format!("{base_url}/v1/taproot-assets/events/asset-receive");
-
-    let response = event_client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    let response = backend(client, base_url, macaroon_hex)
+        .timeout(EVENT_SUBSCRIPTION_TIMEOUT)
+        .post("/v1/taproot-assets/events/asset-receive")
         .json(&request)
         .send()
         .await;
@@ -140,7 +316,7 @@ pub async fn asset_receive_events(
                 )))
             }
         }
-        Err(e) if e.is_timeout() => {
+        Err(AppError::RequestError(e)) if e.is_timeout() => {
             warn!("Asset receive event subscription timed out");
             Ok(serde_json::json!({
                 "events": [],
@@ -148,23 +324,21 @@ pub async fn asset_receive_events(
                 "message": "No events received within timeout period"
             }))
         }
-        Err(e) => Err(AppError::RequestError(e)),
+        Err(e) => Err(e),
     }
 }
 
-#[instrument(skip(macaroon_hex, request))]
+#[instrument(skip(client, macaroon_hex, request))]
 pub async fn asset_send_events(
+    client: &Client,
     base_url: &str,
     macaroon_hex: &str,
     request: AssetSendRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Subscribing to asset send events");
-    let event_client = create_event_client()?;
-    let url = format!("{base_url}/v1/taproot-assets/events/asset-send");
-
-    let response = event_client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    let response = backend(client, base_url, macaroon_hex)
+        .timeout(EVENT_SUBSCRIPTION_TIMEOUT)
+        .post("/v1/taproot-assets/events/asset-send")
         .json(&request)
         .send()
         .await;
@@ -186,7 +360,7 @@ pub async fn asset_send_events(
                 )))
             }
         }
-        Err(e) if e.is_timeout() => {
+        Err(AppError::RequestError(e)) if e.is_timeout() => {
             warn!("Asset send event subscription timed out");
             Ok(serde_json::json!({
                 "events": [],
@@ -194,7 +368,7 @@ pub async fn asset_send_events(
                 "message": "No events received within timeout period"
             }))
         }
-        Err(e) => Err(AppError::RequestError(e)),
+        Err(e) => Err(e),
     }
 }
 
@@ -207,8 +381,9 @@ async fn generic_event_websocket_handler(
 ) -> ActixResult<HttpResponse> {
     info!("Handling WebSocket connection for {} events", event_type);
 
-    // Extract query parameters and forward them to the backend
-    let query_string = req.query_string();
+    // Gateway-side filters are applied here; the rest of the query goes to
+    // the backend
+    let (filter, query_string) = EventFilter::from_query(req.query_string())?;
     let endpoint = if query_string.is_empty() {
         format!("/v1/taproot-assets/events/{event_type}?method=POST")
     } else {
@@ -216,7 +391,7 @@ async fn generic_event_websocket_handler(
     };
 
     ws_proxy_handler
-        .handle_websocket(req, stream, &endpoint, false)
+        .handle_filtered_websocket(req, stream, &endpoint, EndpointGroup::Events, filter)
         .await
 }
 
@@ -244,6 +419,151 @@ async fn asset_send_websocket_handler(
     generic_event_websocket_handler(req, stream, ws_proxy_handler, "asset-send").await
 }
 
+/// Streams receive events for a single address. The filter is sent to tapd
+/// as the subscription request, so other addresses' events never reach the
+/// client.
+#[instrument(skip(req, stream, ws_proxy_handler))]
+async fn addr_receives_websocket_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<AddrReceivesQuery>,
+    ws_proxy_handler: web::Data<Arc<WebSocketProxyHandler>>,
+) -> ActixResult<HttpResponse> {
+    let (filter, _) = EventFilter::from_query(req.query_string())?;
+    let query = query.into_inner();
+    validate_taproot_address(&query.addr)?;
+    if let Some(ts) = &query.start_timestamp {
+        validate_integer_param(ts)?;
+    }
+    info!(
+        "Handling WebSocket connection for receives to {}",
+        query.addr
+    );
+
+    let request = serde_json::to_string(&AssetReceiveRequest {
+        filter_addr: Some(query.addr),
+        start_timestamp: query.start_timestamp,
+    })
+    .map_err(|e| AppError::SerializationError(e.to_string()))?;
+
+    ws_proxy_handler
+        .handle_subscription(
+            req,
+            stream,
+            "/v1/taproot-assets/events/asset-receive?method=POST",
+            request,
+            EndpointGroup::Events,
+            filter,
+        )
+        .await
+}
+
+/// Path of the generic subscribe route, below any mount prefix.
+const SUBSCRIBE_PATH: &str = "/v1/taproot-assets/subscribe";
+
+/// One WebSocket route for every proxied tapd stream, picked by `topic`.
+/// Sessions get the limits, correlation and event filters of the stream's
+/// own route, and are refused with 404 while that route is filtered out.
+#[instrument(skip(req, stream, ws_proxy_handler))]
+async fn subscribe_websocket_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    ws_proxy_handler: web::Data<Arc<WebSocketProxyHandler>>,
+) -> ActixResult<HttpResponse> {
+    let subscription = Subscription::from_query(req.query_string())?;
+    let topic = subscription.topic;
+    if let Some(filter) = req.app_data::<web::Data<RouteFilter>>() {
+        let mount = req.path().strip_suffix(SUBSCRIBE_PATH).unwrap_or("");
+        if !filter(&format!("{mount}{}", topic.route())) {
+            return Err(
+                AppError::NotFound(format!("Topic {} is not available", topic.name())).into(),
+            );
+        }
+    }
+    info!("Handling WebSocket subscription to {}", topic.name());
+
+    match topic.correlation() {
+        Some(correlation) => {
+            ws_proxy_handler
+                .handle_websocket(
+                    req,
+                    stream,
+                    &subscription.endpoint,
+                    correlation,
+                    topic.group(),
+                )
+                .await
+        }
+        None => {
+            ws_proxy_handler
+                .handle_filtered_websocket(
+                    req,
+                    stream,
+                    &subscription.endpoint,
+                    topic.group(),
+                    subscription.filter,
+                )
+                .await
+        }
+    }
+}
+
+/// Streams `channel.*` events (asset channel lifecycle, HTLCs and RFQ
+/// notifications) as JSON text frames until the client disconnects. The
+/// event filter comes from the query and `filter` control messages.
+async fn channel_events_ws(
+    req: HttpRequest,
+    stream: web::Payload,
+    bus: web::Data<SharedEventBus>,
+) -> ActixResult<HttpResponse> {
+    let (filter, _) = EventFilter::from_query(req.query_string())?;
+    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+    let mut events = bus.subscribe();
+
+    actix_web::rt::spawn(async move {
+        let filter = RwLock::new(filter);
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) if event.topic.starts_with(CHANNEL_TOPIC_PREFIX) => {
+                        if !filter.read().unwrap_or_else(|e| e.into_inner()).matches(&event.data) {
+                            continue;
+                        }
+                        let Ok(text) = serde_json::to_string(&event) else {
+                            continue;
+                        };
+                        if session.text(text).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Channel event subscriber lagged, skipped {} events", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                msg = msg_stream.next() => match msg {
+                    Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Text(text))) => {
+                        if session.text(control::answer(&text, &filter)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
 async fn set_debug_level_handler(
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
@@ -262,12 +582,32 @@ async fn set_debug_level_handler(
 }
 
 async fn asset_mint_handler(
+    http_req: HttpRequest,
+    client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<AssetMintRequest>,
 ) -> HttpResponse {
+    if wants_ndjson(&http_req) {
+        let streamed = match EventFilter::from_query(http_req.query_string()) {
+            Ok((filter, _)) => {
+                stream_events(
+                    client.as_ref(),
+                    &base_url.0,
+                    &macaroon_hex.0,
+                    "asset-mint",
+                    &req.into_inner(),
+                    filter,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        return streamed.unwrap_or_else(|e| handle_result::<()>(Err(e)));
+    }
     handle_result(
         asset_mint_events(
+            client.as_ref(),
             base_url.0.as_str(),
             macaroon_hex.0.as_str(),
             req.into_inner(),
@@ -277,12 +617,32 @@ async fn asset_mint_handler(
 }
 
 async fn asset_receive_handler(
+    http_req: HttpRequest,
+    client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<AssetReceiveRequest>,
 ) -> HttpResponse {
+    if wants_ndjson(&http_req) {
+        let streamed = match EventFilter::from_query(http_req.query_string()) {
+            Ok((filter, _)) => {
+                stream_events(
+                    client.as_ref(),
+                    &base_url.0,
+                    &macaroon_hex.0,
+                    "asset-receive",
+                    &req.into_inner(),
+                    filter,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        return streamed.unwrap_or_else(|e| handle_result::<()>(Err(e)));
+    }
     handle_result(
         asset_receive_events(
+            client.as_ref(),
             base_url.0.as_str(),
             macaroon_hex.0.as_str(),
             req.into_inner(),
@@ -292,12 +652,32 @@ async fn asset_receive_handler(
 }
 
 async fn asset_send_handler(
+    http_req: HttpRequest,
+    client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<AssetSendRequest>,
 ) -> HttpResponse {
+    if wants_ndjson(&http_req) {
+        let streamed = match EventFilter::from_query(http_req.query_string()) {
+            Ok((filter, _)) => {
+                stream_events(
+                    client.as_ref(),
+                    &base_url.0,
+                    &macaroon_hex.0,
+                    "asset-send",
+                    &req.into_inner(),
+                    filter,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        return streamed.unwrap_or_else(|e| handle_result::<()>(Err(e)));
+    }
     handle_result(
         asset_send_events(
+            client.as_ref(),
             base_url.0.as_str(),
             macaroon_hex.0.as_str(),
             req.into_inner(),
@@ -318,17 +698,169 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
                 .route(web::post().to(asset_receive_handler))
                 .route(web::get().to(asset_receive_websocket_handler)),
         )
+        .service(
+            web::resource("/events/addr-receives/ws")
+                .route(web::get().to(addr_receives_websocket_handler)),
+        )
         .service(
             web::resource("/events/asset-send")
                 .route(web::post().to(asset_send_handler))
                 .route(web::get().to(asset_send_websocket_handler)),
-        );
+        )
+        .service(web::resource("/subscribe").route(web::get().to(subscribe_websocket_handler)));
+}
+
+/// Whether `event` is in one of `topics` or below it; any event when empty.
+fn topic_matches(topics: &[&str], event: &GatewayEvent) -> bool {
+    topics.is_empty()
+        || topics.iter().any(|topic| {
+            event
+                .topic
+                .strip_prefix(topic)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+}
+
+/// Waits until `bus` has events after `cursor` passing `filter`, or
+/// `wait` passes, and returns them.
+pub async fn poll_events(
+    bus: &EventBus,
+    cursor: Option<u64>,
+    wait: Duration,
+    limit: usize,
+    filter: impl Fn(&GatewayEvent) -> bool,
+) -> Replay {
+    // Subscribed before reading the buffer so no event slips between them
+    let mut published = bus.subscribe();
+    let deadline = Instant::now() + wait;
+    let mut cursor = cursor.unwrap_or_else(|| bus.last_sequence());
+    let mut missed = false;
+    loop {
+        let replay = bus.replay(cursor, limit, &filter);
+        missed |= replay.missed;
+        cursor = replay.cursor;
+        if !replay.events.is_empty() {
+            return Replay { missed, ..replay };
+        }
+        match tokio::time::timeout_at(deadline, published.recv()).await {
+            // A new or skipped event: look at the buffer again
+            Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) => {}
+            Ok(Err(RecvError::Closed)) | Err(_) => {
+                return Replay {
+                    events: Vec::new(),
+                    cursor,
+                    missed,
+                }
+            }
+        }
+    }
+}
+
+/// Long-poll fallback for clients whose proxies break WebSockets and
+/// streamed responses: answers as soon as gateway events after the cursor
+/// exist, or with none once the timeout passes.
+async fn poll_events_handler(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    bus: web::Data<SharedEventBus>,
+    query: web::Query<PollQuery>,
+) -> HttpResponse {
+    let (filter, _) = match EventFilter::from_query(req.query_string()) {
+        Ok(filter) => filter,
+        Err(e) => return handle_result::<Replay>(Err(e)),
+    };
+    let limit = query.limit.unwrap_or(POLL_DEFAULT_LIMIT);
+    if limit == 0 || limit > POLL_MAX_LIMIT {
+        return handle_result::<Replay>(Err(AppError::InvalidInput(format!(
+            "limit must be between 1 and {POLL_MAX_LIMIT}"
+        ))));
+    }
+    let topics: Vec<&str> = query
+        .topics
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|topic| !topic.is_empty())
+        .collect();
+    let wait = query
+        .timeout
+        .unwrap_or(config.event_poll_max_secs)
+        .min(config.event_poll_max_secs);
+    let replay = poll_events(
+        &bus,
+        query.cursor,
+        Duration::from_secs(wait),
+        limit,
+        |event| topic_matches(&topics, event) && filter.matches(&event.data),
+    )
+    .await;
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(replay)
+}
+
+/// Gateway-produced event streams, mounted under `/v1/gateway`.
+pub fn configure_gateway(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/events/channels/ws").route(web::get().to(channel_events_ws)))
+        .service(web::resource("/events/poll").route(web::get().to(poll_events_handler)));
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_poll_waits_for_matching_events() {
+        let bus = std::sync::Arc::new(EventBus::default());
+        bus.publish("transfer.confirmed", serde_json::json!({ "n": 1 }));
+
+        // Buffered events are answered at once
+        let replay = poll_events(&bus, Some(0), Duration::from_secs(5), 10, |_| true).await;
+        assert_eq!(replay.events.len(), 1);
+        assert_eq!(replay.cursor, 1);
+
+        let publisher = bus.clone();
+        let waiting = tokio::spawn(async move {
+            let topics = ["transfer"];
+            poll_events(&publisher, Some(1), Duration::from_secs(5), 10, |e| {
+                topic_matches(&topics, e)
+            })
+            .await
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        bus.publish("transfers.other", serde_json::json!({}));
+        bus.publish("transfer.reorged", serde_json::json!({ "n": 2 }));
+        let replay = waiting.await.unwrap();
+        assert_eq!(replay.events.len(), 1);
+        assert_eq!(replay.events[0].topic, "transfer.reorged");
+        assert_eq!(replay.cursor, 3);
+        assert!(!replay.missed);
+
+        let empty = poll_events(&bus, None, Duration::from_millis(10), 10, |_| true).await;
+        assert!(empty.events.is_empty());
+        assert_eq!(empty.cursor, 3);
+    }
+
+    #[test]
+    fn test_ndjson_splitter_unwraps_events_across_chunks() {
+        let mut splitter = NdjsonSplitter::default();
+        assert!(splitter.push(b"{\"result\":{\"timest").is_empty());
+        let lines = splitter.push(b"amp\":\"1\"}}\n\n{\"error\":{\"code\":2}}\n{\"res");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(&lines[0][..], b"{\"timestamp\":\"1\"}\n");
+        assert_eq!(&lines[1][..], b"{\"error\":{\"code\":2}}\n");
+
+        assert!(splitter.push(b"ult\":{\"n\":2}}").is_empty());
+        assert_eq!(&splitter.finish().unwrap()[..], b"{\"n\":2}\n");
+        assert!(splitter.finish().is_none());
+
+        let garbled = splitter.push(b"not json\n");
+        assert!(std::str::from_utf8(&garbled[0])
+            .unwrap()
+            .contains("Unreadable event"));
+    }
+
     #[test]
     fn test_websocket_url_format_asset_mint() {
         let base_url = "wss://localhost:8080";
@@ -0,0 +1,230 @@
+//! Anonymous faucet route; see [`crate::faucet`].
+
+use super::addresses::{address_network, decode_address, DecodeAddrRequest};
+use super::info::get_info;
+use super::public::public_error;
+use super::send::{index_send, send_assets, SendRequest};
+use super::validate_taproot_address;
+use crate::config::Config;
+use crate::database::SharedDatabase;
+use crate::destination_guard::check_destinations;
+use crate::error::AppError;
+use crate::faucet::{is_test_network, SharedFaucet};
+use crate::indexer::normalize_hex_id;
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use tracing::{info, instrument};
+
+const FAUCET_LABEL: &str = "faucet";
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimRequest {
+    /// Taproot Assets address for the configured asset, generated by the
+    /// caller's wallet with the amount it wants.
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaimResponse {
+    pub asset_id: String,
+    pub amount: u64,
+    pub address: String,
+    pub anchor_txid: Option<String>,
+}
+
+/// tapd's network, read once: the faucet only pays out on a test network.
+async fn require_test_network(
+    faucet: &SharedFaucet,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+) -> Result<(), AppError> {
+    let network = match faucet.network() {
+        Some(network) => network.to_string(),
+        None => {
+            let info = get_info(client, base_url, macaroon_hex).await?;
+            let network = info["network"].as_str().unwrap_or_default().to_string();
+            faucet.set_network(&network);
+            network
+        }
+    };
+    if !is_test_network(&network) {
+        return Err(AppError::ServiceUnavailable(format!(
+            "The faucet only runs on test networks, not {network}"
+        )));
+    }
+    Ok(())
+}
+
+/// The amount `address` asks for, if it is for the faucet's asset and
+/// within its limit.
+async fn claim_amount(
+    faucet: &SharedFaucet,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    address: &str,
+) -> Result<u64, AppError> {
+    let decoded = decode_address(
+        client,
+        base_url,
+        macaroon_hex,
+        DecodeAddrRequest {
+            addr: address.to_string(),
+        },
+    )
+    .await
+    .map_err(|e| match e {
+        AppError::UpstreamError { .. } => {
+            AppError::ValidationError("address could not be decoded".to_string())
+        }
+        e => e,
+    })?;
+    let asset_id = decoded.asset_id.as_deref().map(normalize_hex_id);
+    if asset_id.as_deref() != Some(faucet.asset_id()) {
+        return Err(AppError::ValidationError(format!(
+            "address must be for asset {}",
+            faucet.asset_id()
+        )));
+    }
+    let amount = decoded
+        .amount
+        .as_deref()
+        .and_then(|a| a.parse::<u64>().ok())
+        .unwrap_or(0);
+    if amount == 0 || amount > faucet.max_amount() {
+        return Err(AppError::ValidationError(format!(
+            "address amount must be between 1 and {}",
+            faucet.max_amount()
+        )));
+    }
+    Ok(amount)
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, body))]
+async fn claim(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    body: web::Json<ClaimRequest>,
+) -> HttpResponse {
+    let Some(faucet) = req.app_data::<web::Data<SharedFaucet>>() else {
+        return public_error(AppError::NotFound("Faucet is not enabled".to_string()));
+    };
+    let faucet = faucet.get_ref().clone();
+    let address = body.into_inner().address.trim().to_string();
+
+    if let Err(e) = validate_taproot_address(&address) {
+        return public_error(e);
+    }
+    if address_network(&address) == Some("mainnet") {
+        return public_error(AppError::ValidationError(
+            "The faucet does not pay mainnet addresses".to_string(),
+        ));
+    }
+
+    // The claim is counted before tapd is asked anything
+    let ip = req.app_data::<web::Data<Config>>().and_then(|config| {
+        crate::network_acl::client_ip(
+            &config.trusted_proxies,
+            req.peer_addr().map(|a| a.ip()),
+            req.headers(),
+        )
+    });
+    let Some(ip) = ip else {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "The client address could not be determined"
+        }));
+    };
+    let reservation = match faucet.reserve(ip, &address, Instant::now()) {
+        Ok(reservation) => reservation,
+        Err(retry_after) => {
+            return HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after.to_string()))
+                .json(serde_json::json!({
+                    "error": "Faucet quota exceeded",
+                    "message": "This address or client has claimed recently. Please try again later.",
+                }));
+        }
+    };
+
+    // Refused claims stay counted: only a failed send is given back
+    let checked = async {
+        require_test_network(&faucet, &client, &base_url.0, &macaroon_hex.0).await?;
+        let amount = claim_amount(&faucet, &client, &base_url.0, &macaroon_hex.0, &address).await?;
+        check_destinations(
+            &req,
+            &client,
+            &base_url.0,
+            &macaroon_hex.0,
+            std::slice::from_ref(&address),
+        )
+        .await?;
+        Ok(amount)
+    }
+    .await;
+    let amount = match checked {
+        Ok(amount) => amount,
+        Err(AppError::ServiceUnavailable(message)) => {
+            return HttpResponse::ServiceUnavailable()
+                .json(serde_json::json!({ "error": message }));
+        }
+        Err(e) => return public_error(e),
+    };
+
+    let sent = send_assets(
+        &client,
+        &base_url.0,
+        &macaroon_hex.0,
+        SendRequest {
+            tap_addrs: vec![address.clone()],
+            fee_rate: None,
+            label: Some(FAUCET_LABEL.to_string()),
+            skip_proof_courier_ping_check: None,
+            tags: vec![FAUCET_LABEL.to_string()],
+        },
+    )
+    .await;
+    let response = match sent {
+        Ok(response) => response,
+        Err(e) => {
+            faucet.release(reservation);
+            return public_error(e);
+        }
+    };
+
+    let anchor_txid = match req.app_data::<web::Data<SharedDatabase>>() {
+        Some(database) => {
+            index_send(
+                database.get_ref(),
+                Some(FAUCET_LABEL),
+                &[FAUCET_LABEL.to_string()],
+                Some(address.clone()),
+                &response,
+            )
+            .await
+        }
+        None => None,
+    };
+    info!(
+        "Faucet sent {} of {} to {} ({:?})",
+        amount,
+        faucet.asset_id(),
+        address,
+        ip
+    );
+    HttpResponse::Ok().json(ClaimResponse {
+        asset_id: faucet.asset_id().to_string(),
+        amount,
+        address,
+        anchor_txid,
+    })
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/faucet").route(web::post().to(claim)));
+}
@@ -0,0 +1,501 @@
+//! Read-only GraphQL facade over the REST handlers, enabled with the
+//! `graphql` cargo feature. Resolvers call the same functions as the REST
+//! routes so upstream error handling and validation stay identical; transfers
+//! are served from the local index.
+
+use super::addresses::{self, Addr, AddressQueryParams};
+use super::assets::{self, Asset};
+use super::indexer::validate_query;
+use super::{handle_result, info, universe};
+use crate::config::Config;
+use crate::database::{IndexedTransfer, SharedDatabase, TransferKind, TransferQuery};
+use crate::error::AppError;
+use crate::feature_flags::{self, Feature};
+use crate::indexer::ReceivePolicy;
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::{web, HttpRequest, HttpResponse};
+use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Enum, Json, Object, Schema, SimpleObject,
+};
+use reqwest::Client;
+use serde_json::Value;
+use std::sync::OnceLock;
+use tracing::{debug, instrument};
+
+/// Nesting and complexity limits keep a single query from fanning out into an
+/// unbounded number of upstream calls.
+const MAX_DEPTH: usize = 8;
+const MAX_COMPLEXITY: usize = 256;
+
+pub type GatewaySchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Per-request handles the resolvers need to reach tapd and the index.
+struct Backend {
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+    database: Option<SharedDatabase>,
+    policy: ReceivePolicy,
+}
+
+impl Backend {
+    fn database(&self) -> Result<&SharedDatabase, AppError> {
+        self.database.as_ref().ok_or_else(|| {
+            AppError::ServiceUnavailable("Gateway database is not configured".to_string())
+        })
+    }
+}
+
+fn schema() -> &'static GatewaySchema {
+    static SCHEMA: OnceLock<GatewaySchema> = OnceLock::new();
+    SCHEMA.get_or_init(|| {
+        Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+            .limit_depth(MAX_DEPTH)
+            .limit_complexity(MAX_COMPLEXITY)
+            .finish()
+    })
+}
+
+fn gql_error(err: AppError) -> async_graphql::Error {
+    async_graphql::Error::new(err.to_string())
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Asset")]
+struct AssetNode {
+    asset_id: Option<String>,
+    name: Option<String>,
+    asset_type: Option<String>,
+    amount: Option<String>,
+    anchor_outpoint: Option<String>,
+    block_height: Option<u32>,
+    is_spent: Option<bool>,
+    /// The full tapd asset document.
+    raw: Json<Value>,
+}
+
+impl From<Asset> for AssetNode {
+    fn from(asset: Asset) -> Self {
+        let raw = Json(serde_json::to_value(&asset).unwrap_or(Value::Null));
+        let genesis = asset.asset_genesis;
+        let anchor = asset.chain_anchor;
+        AssetNode {
+            asset_id: genesis.as_ref().and_then(|g| g.asset_id.clone()),
+            name: genesis.as_ref().and_then(|g| g.name.clone()),
+            asset_type: genesis.and_then(|g| g.asset_type),
+            amount: asset.amount,
+            anchor_outpoint: anchor.as_ref().and_then(|a| a.anchor_outpoint.clone()),
+            block_height: anchor.and_then(|a| a.block_height),
+            is_spent: asset.is_spent,
+            raw,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "AssetBalance")]
+struct BalanceNode {
+    asset_id: String,
+    name: Option<String>,
+    balance: Option<String>,
+}
+
+/// Flattens tapd's `asset_balances` map (keyed by asset id) into a list.
+fn balances_from(value: &Value) -> Vec<BalanceNode> {
+    let Some(balances) = value.get("asset_balances").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+    balances
+        .iter()
+        .map(|(asset_id, entry)| BalanceNode {
+            asset_id: asset_id.clone(),
+            name: entry
+                .pointer("/asset_genesis/name")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            balance: entry.get("balance").and_then(|b| match b {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            }),
+        })
+        .collect()
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Address")]
+struct AddressNode {
+    encoded: Option<String>,
+    asset_id: Option<String>,
+    asset_type: Option<String>,
+    amount: Option<String>,
+    group_key: Option<String>,
+    script_key: Option<String>,
+    internal_key: Option<String>,
+    taproot_output_key: Option<String>,
+    proof_courier_addr: Option<String>,
+    address_version: Option<String>,
+}
+
+impl From<Addr> for AddressNode {
+    fn from(addr: Addr) -> Self {
+        AddressNode {
+            encoded: addr.encoded,
+            asset_id: addr.asset_id,
+            asset_type: addr.asset_type,
+            amount: addr.amount,
+            group_key: addr.group_key,
+            script_key: addr.script_key,
+            internal_key: addr.internal_key,
+            taproot_output_key: addr.taproot_output_key,
+            proof_courier_addr: addr.proof_courier_addr,
+            address_version: addr.address_version,
+        }
+    }
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+enum TransferKindFilter {
+    Send,
+    Receive,
+    Mint,
+    Burn,
+}
+
+impl From<TransferKindFilter> for TransferKind {
+    fn from(kind: TransferKindFilter) -> Self {
+        match kind {
+            TransferKindFilter::Send => TransferKind::Send,
+            TransferKindFilter::Receive => TransferKind::Receive,
+            TransferKindFilter::Mint => TransferKind::Mint,
+            TransferKindFilter::Burn => TransferKind::Burn,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Transfer")]
+struct TransferNode {
+    id: String,
+    kind: String,
+    asset_id: Option<String>,
+    address: Option<String>,
+    /// Amounts are strings, as in tapd, since they can exceed 2^53.
+    amount: Option<String>,
+    anchor_txid: Option<String>,
+    outpoint: Option<String>,
+    block_height: Option<u32>,
+    status: String,
+    timestamp: i64,
+    chain_status: Option<String>,
+    confirmations: Option<u32>,
+    label: Option<String>,
+    tags: Vec<String>,
+}
+
+impl From<IndexedTransfer> for TransferNode {
+    fn from(t: IndexedTransfer) -> Self {
+        TransferNode {
+            id: t.id,
+            kind: t.kind.as_str().to_string(),
+            asset_id: t.asset_id,
+            address: t.address,
+            amount: t.amount.map(|a| a.to_string()),
+            anchor_txid: t.anchor_txid,
+            outpoint: t.outpoint,
+            block_height: t.block_height,
+            status: t.status,
+            timestamp: t.timestamp,
+            chain_status: t
+                .chain_status
+                .and_then(|s| serde_json::to_value(s).ok())
+                .and_then(|v| v.as_str().map(str::to_string)),
+            confirmations: t.confirmations,
+            label: t.label,
+            tags: t.tags,
+        }
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// tapd node information.
+    async fn info(&self, ctx: &Context<'_>) -> async_graphql::Result<Json<Value>> {
+        let backend = ctx.data::<Backend>()?;
+        info::get_info(&backend.client, &backend.base_url, &backend.macaroon_hex)
+            .await
+            .map(Json)
+            .map_err(gql_error)
+    }
+
+    async fn assets(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = false)] include_spent: bool,
+        #[graphql(default = false)] include_leased: bool,
+    ) -> async_graphql::Result<Vec<AssetNode>> {
+        let backend = ctx.data::<Backend>()?;
+        let query = format!("include_spent={include_spent}&include_leased={include_leased}");
+        let assets = assets::list_assets(
+            &backend.client,
+            &backend.base_url,
+            &backend.macaroon_hex,
+            &query,
+        )
+        .await
+        .map_err(gql_error)?;
+        Ok(assets.into_iter().map(AssetNode::from).collect())
+    }
+
+    /// Balances grouped by asset id.
+    async fn balances(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<BalanceNode>> {
+        let backend = ctx.data::<Backend>()?;
+        let value = assets::get_balance(
+            &backend.client,
+            &backend.base_url,
+            &backend.macaroon_hex,
+            "asset_id=true",
+        )
+        .await
+        .map_err(gql_error)?;
+        Ok(balances_from(&value))
+    }
+
+    async fn addresses(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> async_graphql::Result<Vec<AddressNode>> {
+        let backend = ctx.data::<Backend>()?;
+        let params = AddressQueryParams {
+            created_after: None,
+            created_before: None,
+            limit,
+            offset,
+        };
+        let addrs = addresses::list_addresses(
+            &backend.client,
+            &backend.base_url,
+            &backend.macaroon_hex,
+            Some(&params),
+        )
+        .await
+        .map_err(gql_error)?;
+        Ok(addrs.into_iter().map(AddressNode::from).collect())
+    }
+
+    /// Transfers from the gateway index, with the receive confirmation policy
+    /// applied. Requires `DATABASE_URL`.
+    #[allow(clippy::too_many_arguments)]
+    async fn transfers(
+        &self,
+        ctx: &Context<'_>,
+        kind: Option<TransferKindFilter>,
+        asset_id: Option<String>,
+        address: Option<String>,
+        status: Option<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        tag: Option<String>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> async_graphql::Result<Vec<TransferNode>> {
+        let backend = ctx.data::<Backend>()?;
+        let mut query = TransferQuery {
+            kind: kind.map(TransferKind::from),
+            asset_id,
+            address,
+            status,
+            from,
+            to,
+            tag,
+            account: None,
+            limit,
+            offset,
+        };
+        validate_query(&mut query).map_err(gql_error)?;
+        let mut transfers = backend
+            .database()
+            .map_err(gql_error)?
+            .query_indexed_transfers(&query)
+            .await
+            .map_err(gql_error)?;
+        transfers.iter_mut().for_each(|t| backend.policy.apply(t));
+        Ok(transfers.into_iter().map(TransferNode::from).collect())
+    }
+
+    async fn universe_roots(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> async_graphql::Result<Json<Value>> {
+        let backend = ctx.data::<Backend>()?;
+        let mut query = Vec::new();
+        if let Some(limit) = limit {
+            query.push(format!("limit={limit}"));
+        }
+        if let Some(offset) = offset {
+            query.push(format!("offset={offset}"));
+        }
+        universe::get_roots(
+            &backend.client,
+            &backend.base_url,
+            &backend.macaroon_hex,
+            &query.join("&"),
+        )
+        .await
+        .map(Json)
+        .map_err(gql_error)
+    }
+
+    async fn universe_stats(&self, ctx: &Context<'_>) -> async_graphql::Result<Json<Value>> {
+        let backend = ctx.data::<Backend>()?;
+        universe::get_stats(&backend.client, &backend.base_url, &backend.macaroon_hex)
+            .await
+            .map(Json)
+            .map_err(gql_error)
+    }
+}
+
+#[instrument(skip_all)]
+async fn graphql_handler(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    body: web::Json<async_graphql::Request>,
+) -> HttpResponse {
+    if let Err(e) = feature_flags::require(&req, Feature::Graphql) {
+        return handle_result::<()>(Err(e));
+    }
+    let backend = Backend {
+        client: client.get_ref().clone(),
+        base_url: base_url.0.clone(),
+        macaroon_hex: macaroon_hex.0.clone(),
+        database: req
+            .app_data::<web::Data<SharedDatabase>>()
+            .map(|db| db.get_ref().clone()),
+        policy: ReceivePolicy::new(config.min_receive_confirmations),
+    };
+    let request = body.into_inner().data(backend);
+    let response = schema().execute(request).await;
+    debug!(
+        "GraphQL query finished with {} errors",
+        response.errors.len()
+    );
+    HttpResponse::Ok().json(response)
+}
+
+async fn graphql_playground(req: HttpRequest) -> HttpResponse {
+    if let Err(e) = feature_flags::require(&req, Feature::Graphql) {
+        return handle_result::<()>(Err(e));
+    }
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(playground_source(GraphQLPlaygroundConfig::new("/graphql")))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/graphql")
+            .route(web::post().to(graphql_handler))
+            .route(web::get().to(graphql_playground)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn backend(database: Option<SharedDatabase>) -> Backend {
+        Backend {
+            client: Client::new(),
+            base_url: "http://127.0.0.1:1".to_string(),
+            macaroon_hex: String::new(),
+            database,
+            policy: ReceivePolicy::new(1),
+        }
+    }
+
+    fn transfer(id: &str, kind: TransferKind, amount: u64) -> IndexedTransfer {
+        IndexedTransfer {
+            id: id.to_string(),
+            kind,
+            asset_id: Some("aa".repeat(32)),
+            address: None,
+            amount: Some(amount),
+            anchor_txid: None,
+            outpoint: None,
+            block_height: None,
+            status: "completed".to_string(),
+            timestamp: 1_700_000_000,
+            raw: serde_json::json!({}),
+            chain_status: None,
+            confirmations: None,
+            block_hash: None,
+            label: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transfers_query_selects_fields() {
+        let database = open_test_database().await;
+        database
+            .upsert_indexed_transfers(&[
+                transfer("send-1", TransferKind::Send, 10),
+                transfer("recv-1", TransferKind::Receive, 20),
+            ])
+            .await
+            .unwrap();
+
+        let request = async_graphql::Request::new("{ transfers(kind: RECEIVE) { id amount } }")
+            .data(backend(Some(database)));
+        let response = schema().execute(request).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        assert_eq!(
+            data,
+            serde_json::json!({ "transfers": [{ "id": "recv-1", "amount": "20" }] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfers_query_without_database_errors() {
+        let request = async_graphql::Request::new("{ transfers { id } }").data(backend(None));
+        let response = schema().execute(request).await;
+        assert_eq!(response.errors.len(), 1);
+        assert!(response.errors[0].message.contains("not configured"));
+    }
+
+    #[tokio::test]
+    async fn test_depth_limit_is_enforced() {
+        let request = async_graphql::Request::new(
+            "{ __schema { types { fields { type { ofType { ofType { ofType { ofType { ofType { name } } } } } } } } } }",
+        )
+        .data(backend(None));
+        let response = schema().execute(request).await;
+        assert!(!response.errors.is_empty());
+    }
+
+    #[test]
+    fn test_balances_from_flattens_map() {
+        let value = serde_json::json!({
+            "asset_balances": {
+                "abcd": { "asset_genesis": { "name": "usd" }, "balance": "42" }
+            }
+        });
+        let balances = balances_from(&value);
+        assert_eq!(balances.len(), 1);
+        assert_eq!(balances[0].asset_id, "abcd");
+        assert_eq!(balances[0].name.as_deref(), Some("usd"));
+        assert_eq!(balances[0].balance.as_deref(), Some("42"));
+    }
+}
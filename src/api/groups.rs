@@ -0,0 +1,396 @@
+//! Issuance history of re-issuable asset groups. The universe knows every
+//! tranche's asset, amount and anchor outpoint; the event indexer adds when
+//! each mint happened and how deep its anchor is, plus mints the universe
+//! has not seen yet.
+
+use super::universe::{get_group_keys, get_group_leaves};
+use super::{handle_result, validate_group_key};
+use crate::config::Config;
+use crate::database::{ChainStatus, IndexedTransfer, SharedDatabase, TransferKind, TransferQuery};
+use crate::error::AppError;
+use crate::indexer::normalize_hex_id;
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::{debug, instrument};
+
+const ISSUANCE_QUERY: &str = "proof_type=PROOF_TYPE_ISSUANCE";
+
+/// Indexed mints scanned for tranches the universe has not seen.
+const MINT_SCAN_LIMIT: u32 = 1000;
+
+/// Where a tranche was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssuanceSource {
+    Universe,
+    /// Indexed by the gateway but not (yet) in the universe.
+    Indexer,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issuance {
+    pub asset_id: Option<String>,
+    pub name: Option<String>,
+    pub amount: u64,
+    pub anchor_txid: Option<String>,
+    pub anchor_outpoint: Option<String>,
+    pub script_key: Option<String>,
+    /// When the mint was indexed; `None` without an indexed mint.
+    pub timestamp: Option<i64>,
+    pub block_height: Option<u32>,
+    pub confirmations: Option<u32>,
+    pub chain_status: Option<ChainStatus>,
+    /// tapd's batch state for the indexed mint.
+    pub mint_status: Option<String>,
+    pub source: IssuanceSource,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupIssuances {
+    pub group_key: String,
+    pub total_issued: u64,
+    pub issuance_count: usize,
+    /// Whether indexed mints were consulted.
+    pub indexed: bool,
+    /// Oldest first; tranches without a known time come last.
+    pub issuances: Vec<Issuance>,
+}
+
+/// tapd's uint64 fields arrive as strings.
+fn u64_field(value: &Value, field: &str) -> Option<u64> {
+    match value.get(field)? {
+        Value::String(s) => s.parse().ok(),
+        Value::Number(n) => n.as_u64(),
+        _ => None,
+    }
+}
+
+fn str_field<'a>(value: &'a Value, field: &str) -> Option<&'a str> {
+    value
+        .get(field)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+}
+
+/// The x-only part of a key in hex, so 32- and 33-byte encodings compare
+/// equal.
+fn x_only(key: &str) -> String {
+    let hex = normalize_hex_id(key);
+    hex.get(hex.len().saturating_sub(64)..)
+        .unwrap_or(&hex)
+        .to_string()
+}
+
+/// Universe issuance leaves paired with their keys by script key.
+fn universe_issuances(keys: &Value, leaves: &Value) -> Vec<Issuance> {
+    let outpoints: HashMap<String, &str> = keys
+        .get("asset_keys")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|key| {
+            let script_key =
+                str_field(key, "script_key_bytes").or_else(|| str_field(key, "script_key_str"))?;
+            Some((x_only(script_key), str_field(key, "op_str")?))
+        })
+        .collect();
+
+    leaves
+        .get("leaves")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|leaf| {
+            let asset = leaf.get("asset")?;
+            let genesis = asset.get("asset_genesis");
+            let script_key = str_field(asset, "script_key").map(x_only);
+            let anchor_outpoint = script_key
+                .as_ref()
+                .and_then(|key| outpoints.get(key))
+                .map(|op| op.to_string())
+                .or_else(|| {
+                    asset
+                        .get("chain_anchor")
+                        .and_then(|anchor| str_field(anchor, "anchor_outpoint"))
+                        .map(str::to_string)
+                });
+            Some(Issuance {
+                asset_id: genesis
+                    .and_then(|g| str_field(g, "asset_id"))
+                    .map(normalize_hex_id),
+                name: genesis
+                    .and_then(|g| str_field(g, "name"))
+                    .map(str::to_string),
+                amount: u64_field(asset, "amount")?,
+                anchor_txid: anchor_outpoint
+                    .as_deref()
+                    .and_then(|op| op.split_once(':'))
+                    .map(|(txid, _)| txid.to_ascii_lowercase()),
+                anchor_outpoint,
+                script_key,
+                timestamp: None,
+                block_height: None,
+                confirmations: None,
+                chain_status: None,
+                mint_status: None,
+                source: IssuanceSource::Universe,
+            })
+        })
+        .collect()
+}
+
+/// The amount a minting batch issues into `group_key`, if any.
+fn group_amount(mint: &IndexedTransfer, group_key: &str) -> Option<u64> {
+    let batch = mint
+        .raw
+        .get("batch")
+        .filter(|b| b.is_object())
+        .unwrap_or(&mint.raw);
+    let amounts: Vec<u64> = batch
+        .get("assets")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|asset| {
+            ["group_key", "tweaked_group_key"].iter().any(|field| {
+                asset
+                    .get(*field)
+                    .and_then(Value::as_str)
+                    .is_some_and(|key| x_only(key) == group_key)
+            })
+        })
+        .filter_map(|asset| u64_field(asset, "amount"))
+        .collect();
+    (!amounts.is_empty()).then(|| amounts.iter().sum())
+}
+
+/// Joins universe tranches with indexed mints of the same anchor
+/// transaction, and adds indexed mints into the group the universe lacks.
+pub fn assemble(
+    group_key: &str,
+    keys: &Value,
+    leaves: &Value,
+    mints: Option<&[IndexedTransfer]>,
+) -> GroupIssuances {
+    let mut issuances = universe_issuances(keys, leaves);
+    let group = x_only(group_key);
+
+    if let Some(mints) = mints {
+        let by_txid: HashMap<&str, &IndexedTransfer> = mints
+            .iter()
+            .filter_map(|mint| Some((mint.anchor_txid.as_deref()?, mint)))
+            .collect();
+        for issuance in &mut issuances {
+            let Some(mint) = issuance.anchor_txid.as_deref().and_then(|t| by_txid.get(t)) else {
+                continue;
+            };
+            issuance.timestamp = Some(mint.timestamp);
+            issuance.block_height = mint.block_height;
+            issuance.confirmations = mint.confirmations;
+            issuance.chain_status = mint.chain_status;
+            issuance.mint_status = Some(mint.status.clone());
+        }
+        for mint in mints {
+            let known = mint.anchor_txid.as_deref().is_some_and(|txid| {
+                issuances
+                    .iter()
+                    .any(|i| i.anchor_txid.as_deref() == Some(txid))
+            });
+            if known || mint.chain_status == Some(ChainStatus::Replaced) {
+                continue;
+            }
+            if let Some(amount) = group_amount(mint, &group) {
+                issuances.push(Issuance {
+                    asset_id: None,
+                    name: None,
+                    amount,
+                    anchor_txid: mint.anchor_txid.clone(),
+                    anchor_outpoint: None,
+                    script_key: None,
+                    timestamp: Some(mint.timestamp),
+                    block_height: mint.block_height,
+                    confirmations: mint.confirmations,
+                    chain_status: mint.chain_status,
+                    mint_status: Some(mint.status.clone()),
+                    source: IssuanceSource::Indexer,
+                });
+            }
+        }
+    }
+
+    issuances.sort_by_key(|i| {
+        (
+            i.block_height.is_none() && i.timestamp.is_none(),
+            i.block_height.unwrap_or(u32::MAX),
+            i.timestamp.unwrap_or(i64::MAX),
+            i.anchor_outpoint.clone(),
+        )
+    });
+    GroupIssuances {
+        group_key: group_key.to_ascii_lowercase(),
+        total_issued: issuances.iter().map(|i| i.amount).sum(),
+        issuance_count: issuances.len(),
+        indexed: mints.is_some(),
+        issuances,
+    }
+}
+
+#[instrument(skip(req, client, macaroon_hex))]
+async fn group_issuances(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    group_key: &str,
+) -> Result<GroupIssuances, AppError> {
+    validate_group_key(group_key)?;
+    let (keys, leaves) = tokio::try_join!(
+        get_group_keys(client, base_url, macaroon_hex, group_key, ISSUANCE_QUERY),
+        get_group_leaves(client, base_url, macaroon_hex, group_key, ISSUANCE_QUERY),
+    )?;
+
+    let database = req
+        .app_data::<web::Data<Config>>()
+        .filter(|config| config.indexer_enabled)
+        .and_then(|_| req.app_data::<web::Data<SharedDatabase>>());
+    let mints = match database {
+        Some(database) => Some(
+            database
+                .query_indexed_transfers(&TransferQuery {
+                    kind: Some(TransferKind::Mint),
+                    limit: Some(MINT_SCAN_LIMIT),
+                    ..Default::default()
+                })
+                .await?,
+        ),
+        None => None,
+    };
+
+    let issuances = assemble(group_key, &keys, &leaves, mints.as_deref());
+    if issuances.issuances.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "No issuances for group key {group_key}"
+        )));
+    }
+    debug!(
+        "Group {} has {} issuances totalling {}",
+        group_key, issuances.issuance_count, issuances.total_issued
+    );
+    Ok(issuances)
+}
+
+async fn issuances_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+) -> HttpResponse {
+    handle_result(
+        group_issuances(
+            &req,
+            &client,
+            &base_url.0,
+            &macaroon_hex.0,
+            &path.into_inner(),
+        )
+        .await,
+    )
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/groups/{group_key}/issuances").route(web::get().to(issuances_handler)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const GROUP_KEY: &str = "02bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    fn tranche(asset_id: &str, script_key: &str, amount: &str) -> Value {
+        json!({
+            "asset": {
+                "asset_genesis": {"asset_id": asset_id, "name": "tranche"},
+                "amount": amount,
+                "script_key": script_key,
+            }
+        })
+    }
+
+    fn mint(txid: &str, timestamp: i64, group_key: &str, amount: u64) -> IndexedTransfer {
+        IndexedTransfer {
+            id: format!("mint:{txid}"),
+            kind: TransferKind::Mint,
+            asset_id: None,
+            address: None,
+            amount: Some(amount),
+            anchor_txid: Some(txid.to_string()),
+            outpoint: None,
+            block_height: Some(100 + timestamp as u32),
+            status: "finalized".to_string(),
+            timestamp,
+            raw: json!({"assets": [{"amount": amount.to_string(), "group_key": group_key}]}),
+            chain_status: None,
+            confirmations: Some(6),
+            block_hash: None,
+            label: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_universe_tranches_join_indexed_mints_by_anchor() {
+        let (k1, k2) = (
+            "02".to_string() + &"1".repeat(64),
+            "03".to_string() + &"2".repeat(64),
+        );
+        let keys = json!({"asset_keys": [
+            {"op_str": format!("{}:0", "b".repeat(64)), "script_key_bytes": "2".repeat(64)},
+            {"op_str": format!("{}:1", "a".repeat(64)), "script_key_bytes": "1".repeat(64)},
+        ]});
+        let leaves = json!({"leaves": [
+            tranche(&"c".repeat(64), &k1, "1000"),
+            tranche(&"d".repeat(64), &k2, "500"),
+        ]});
+        let mints = [
+            mint(&"a".repeat(64), 1, GROUP_KEY, 1000),
+            mint(&"b".repeat(64), 2, GROUP_KEY, 500),
+        ];
+
+        let group = assemble(GROUP_KEY, &keys, &leaves, Some(&mints));
+        assert_eq!(group.total_issued, 1500);
+        assert_eq!(group.issuance_count, 2);
+        let first = &group.issuances[0];
+        assert_eq!(first.asset_id.as_deref(), Some("c".repeat(64).as_str()));
+        assert_eq!(first.anchor_txid.as_deref(), Some("a".repeat(64).as_str()));
+        assert_eq!(first.timestamp, Some(1));
+        assert_eq!(first.block_height, Some(101));
+        assert_eq!(group.issuances[1].amount, 500);
+    }
+
+    #[test]
+    fn test_indexed_mint_missing_from_universe_is_listed() {
+        let leaves = json!({"leaves": []});
+        let other_group = "02".to_string() + &"e".repeat(64);
+        let mints = [
+            mint(&"a".repeat(64), 1, GROUP_KEY, 70),
+            mint(&"f".repeat(64), 2, &other_group, 5),
+        ];
+        let group = assemble(GROUP_KEY, &json!({}), &leaves, Some(&mints));
+        assert_eq!(group.issuance_count, 1);
+        assert_eq!(group.issuances[0].source, IssuanceSource::Indexer);
+        assert_eq!(group.issuances[0].amount, 70);
+
+        let without_index = assemble(GROUP_KEY, &json!({}), &leaves, None);
+        assert!(!without_index.indexed);
+        assert!(without_index.issuances.is_empty());
+    }
+}
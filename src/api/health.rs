@@ -1,28 +1,63 @@
 use crate::api::info;
+use crate::config::Config;
+use crate::maintenance::{MaintenanceStatus, SharedMaintenance};
 use crate::types::{BaseUrl, MacaroonHex};
-use actix_web::{web, HttpResponse};
+use crate::warmup::SharedWarmup;
+use actix_web::{web, HttpRequest, HttpResponse};
 use reqwest::Client;
 
-pub async fn health() -> HttpResponse {
+fn maintenance_status(req: &HttpRequest) -> Option<MaintenanceStatus> {
+    req.app_data::<web::Data<SharedMaintenance>>()
+        .map(|m| m.status())
+}
+
+/// Liveness stays 200 during maintenance so orchestrators do not restart the
+/// gateway; the status field tells operators why writes are refused.
+pub async fn health(req: HttpRequest) -> HttpResponse {
+    let maintenance = maintenance_status(&req);
+    let status = match &maintenance {
+        Some(m) if m.enabled => "maintenance",
+        _ => "healthy",
+    };
+    let profile = req
+        .app_data::<web::Data<Config>>()
+        .map(|c| c.gateway_profile);
     HttpResponse::Ok().json(serde_json::json!({
-        "status": "healthy",
+        "status": status,
+        "profile": profile,
+        "maintenance": maintenance,
         "timestamp": chrono::Utc::now().to_rfc3339()
     }))
 }
 
 pub async fn readiness(
+    req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
 ) -> HttpResponse {
+    let maintenance = maintenance_status(&req);
+    let warmup = req.app_data::<web::Data<SharedWarmup>>();
+    if let Some(warmup) = warmup.filter(|w| !w.is_ready()) {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "status": "warming_up",
+            "warmup": warmup.status(),
+            "maintenance": maintenance
+        }));
+    }
+    let warmup = warmup.map(|w| w.status());
     match info::get_info(client.as_ref(), &base_url.0, &macaroon_hex.0).await {
         Ok(_) => HttpResponse::Ok().json(serde_json::json!({
             "status": "ready",
-            "services": {"taproot_assets": "up"}
+            "services": {"taproot_assets": "up"},
+            "warmup": warmup,
+            "maintenance": maintenance
         })),
         Err(_) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
             "status": "not_ready",
-            "services": {"taproot_assets": "down"}
+            "services": {"taproot_assets": "down"},
+            "warmup": warmup,
+            "maintenance": maintenance
         })),
     }
 }
@@ -0,0 +1,211 @@
+use super::{handle_result, require_database, validate_asset_id, ListEnvelope, PageParams};
+use crate::config::Config;
+use crate::database::{IndexedTransfer, TransferFilterQuery, TransferQuery};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::indexer::ReceivePolicy;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, instrument, warn};
+
+/// Prefix of the event bus topics streamed by the transfer WebSocket.
+const TRANSFER_TOPIC_PREFIX: &str = "transfer.";
+
+pub(super) fn validate_query(query: &mut TransferQuery) -> Result<(), AppError> {
+    if let Some(asset_id) = query.asset_id.as_mut() {
+        validate_asset_id(asset_id)?;
+        // The index stores lowercase hex.
+        *asset_id = asset_id.to_ascii_lowercase();
+    }
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Err(AppError::InvalidInput(
+                "'from' must not be later than 'to'".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[instrument(skip(req))]
+async fn query_transfers(
+    req: &HttpRequest,
+    mut query: TransferQuery,
+    policy: ReceivePolicy,
+) -> Result<Vec<IndexedTransfer>, AppError> {
+    validate_query(&mut query)?;
+    let database = require_database(req)?;
+    let mut transfers = database.query_indexed_transfers(&query).await?;
+    debug!("Indexed transfer query returned {} rows", transfers.len());
+    transfers.iter_mut().for_each(|t| policy.apply(t));
+    Ok(transfers)
+}
+
+#[instrument(skip(req))]
+async fn query_pending_receives(
+    req: &HttpRequest,
+    mut query: TransferQuery,
+    policy: ReceivePolicy,
+) -> Result<Vec<IndexedTransfer>, AppError> {
+    validate_query(&mut query)?;
+    let database = require_database(req)?;
+    let mut receives = database
+        .pending_receives(&query, policy.min_confirmations)
+        .await?;
+    receives.iter_mut().for_each(|t| policy.apply(t));
+    Ok(receives)
+}
+
+/// Applies the envelope cursor to an index query, asking for one row more
+/// than the page so the envelope can tell whether another page exists.
+pub(super) fn paged_query(
+    req: &HttpRequest,
+    query: TransferQuery,
+) -> Result<(TransferQuery, u32, u32), AppError> {
+    let page = PageParams::from_query(req.query_string())?;
+    let (offset, limit) = (page.offset()?, page.limit()?);
+    let query = TransferQuery {
+        limit: Some(limit + 1),
+        offset: Some(offset),
+        ..query
+    };
+    Ok((query, offset, limit))
+}
+
+async fn list_transfers(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    query: web::Query<TransferQuery>,
+) -> HttpResponse {
+    let policy = ReceivePolicy::new(config.min_receive_confirmations);
+    let result = async {
+        let (query, offset, limit) = paged_query(&req, query.into_inner())?;
+        let transfers = query_transfers(&req, query, policy).await?;
+        Ok(ListEnvelope::from_offset_page(transfers, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn list_pending_receives(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    query: web::Query<TransferQuery>,
+) -> HttpResponse {
+    let policy = ReceivePolicy::new(config.min_receive_confirmations);
+    let result = async {
+        let (query, offset, limit) = paged_query(&req, query.into_inner())?;
+        let receives = query_pending_receives(&req, query, policy).await?;
+        let envelope = ListEnvelope::from_offset_page(receives, offset, limit).with_next_link(&req);
+        Ok(serde_json::json!({
+            "items": envelope.items,
+            "next_cursor": envelope.next_cursor,
+            "total_estimate": envelope.total_estimate,
+            "next": envelope.next,
+            "min_confirmations": policy.min_confirmations,
+        }))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Runs a structured filter against the index. Paging follows the listing
+/// endpoints: `limit` and `cursor` go in the query string, and the `next`
+/// link is re-posted with the same body.
+async fn filter_transfers(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    body: web::Json<TransferFilterQuery>,
+) -> HttpResponse {
+    let policy = ReceivePolicy::new(config.min_receive_confirmations);
+    let result = async {
+        let page = PageParams::from_query(req.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let database = require_database(&req)?;
+        let mut transfers = database
+            .filter_indexed_transfers(&body, limit + 1, offset)
+            .await?;
+        transfers.iter_mut().for_each(|t| policy.apply(t));
+        Ok(ListEnvelope::from_offset_page(transfers, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Streams `transfer.*` events (confirmations, reorgs, replacements) as JSON
+/// text frames until the client disconnects.
+async fn transfer_events_ws(
+    req: HttpRequest,
+    stream: web::Payload,
+    bus: web::Data<SharedEventBus>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+    let mut events = bus.subscribe();
+
+    actix_web::rt::spawn(async move {
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) if event.topic.starts_with(TRANSFER_TOPIC_PREFIX) => {
+                        let Ok(text) = serde_json::to_string(&event) else {
+                            continue;
+                        };
+                        if session.text(text).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Transfer event subscriber lagged, skipped {} events", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                msg = msg_stream.next() => match msg {
+                    Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/transfers").route(web::get().to(list_transfers)))
+        .service(web::resource("/transfers/query").route(web::post().to(filter_transfers)))
+        .service(web::resource("/transfers/ws").route(web::get().to(transfer_events_ws)))
+        .service(web::resource("/receives/pending").route(web::get().to(list_pending_receives)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_query_lowercases_asset_id() {
+        let mut query = TransferQuery {
+            asset_id: Some("A".repeat(64)),
+            ..Default::default()
+        };
+        validate_query(&mut query).unwrap();
+        assert_eq!(query.asset_id, Some("a".repeat(64)));
+    }
+
+    #[test]
+    fn test_validate_query_rejects_inverted_range() {
+        let mut query = TransferQuery {
+            from: Some(200),
+            to: Some(100),
+            ..Default::default()
+        };
+        assert!(validate_query(&mut query).is_err());
+    }
+}
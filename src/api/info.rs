@@ -1,7 +1,8 @@
-use super::{handle_result, parse_upstream};
+use super::{backend, handle_result};
 use crate::error::AppError;
+use crate::onion::SharedOnionService;
 use crate::types::{BaseUrl, MacaroonHex};
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use reqwest::Client;
 use serde_json::Value;
 use tracing::{info, instrument};
@@ -13,22 +14,35 @@ pub async fn get_info(
     macaroon_hex: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching getinfo");
-    let url = format!("{base_url}/v1/taproot-assets/getinfo");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/getinfo")
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
+}
+
+/// Adds the gateway's onion address, when it publishes one, to tapd's
+/// getinfo.
+fn with_onion_address(mut info: Value, onion: Option<&SharedOnionService>) -> Value {
+    if let (Some(onion), Some(fields)) = (onion, info.as_object_mut()) {
+        fields.insert(
+            "gateway_onion_address".to_string(),
+            Value::String(onion.address().to_string()),
+        );
+    }
+    info
 }
 
 async fn get_info_handler(
+    req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
 ) -> HttpResponse {
-    handle_result(get_info(client.as_ref(), &base_url.0, &macaroon_hex.0).await)
+    let onion = req.app_data::<web::Data<SharedOnionService>>();
+    let result = get_info(client.as_ref(), &base_url.0, &macaroon_hex.0)
+        .await
+        .map(|info| with_onion_address(info, onion.map(|o| o.get_ref())));
+    handle_result(result)
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
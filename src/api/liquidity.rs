@@ -0,0 +1,401 @@
+//! Per-peer, per-asset liquidity of the node's asset channels, read from
+//! lnd's channel list. Channels with too much of an asset on one side are
+//! flagged, each with the amount that would bring it back to even, so a
+//! rebalancer or dashboard can act on the report directly.
+
+use super::{handle_result, parse_upstream};
+use crate::config::Config;
+use crate::error::AppError;
+use crate::indexer::normalize_hex_id;
+use crate::types::LndNode;
+use actix_web::{web, HttpResponse};
+use base64::Engine;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tracing::{debug, instrument};
+
+#[derive(Debug, Deserialize)]
+pub struct LiquidityQuery {
+    /// Overrides `LIQUIDITY_IMBALANCE_THRESHOLD` for this report.
+    pub threshold: Option<u8>,
+}
+
+/// Which side of a channel holds too much of an asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Imbalance {
+    /// Mostly local: the node can send but hardly receive.
+    Outbound,
+    /// Mostly remote: the node can receive but hardly send.
+    Inbound,
+}
+
+/// One asset's balance in one channel.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChannelLiquidity {
+    pub channel_point: Option<String>,
+    pub chan_id: Option<String>,
+    pub peer: String,
+    pub peer_alias: Option<String>,
+    pub active: bool,
+    pub asset_id: String,
+    pub asset_name: Option<String>,
+    pub capacity: u64,
+    /// Sendable (local) balance.
+    pub outbound: u64,
+    /// Receivable (remote) balance.
+    pub inbound: u64,
+    /// Share of the balance on the local side, 0.0 to 1.0.
+    pub local_ratio: f64,
+    pub imbalance: Option<Imbalance>,
+    /// Asset units to move to the other side to even the channel out; 0
+    /// when balanced.
+    pub rebalance_amount: u64,
+}
+
+/// Totals for one asset across a peer's channels.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AssetLiquidity {
+    pub asset_id: String,
+    pub asset_name: Option<String>,
+    pub channels: usize,
+    pub capacity: u64,
+    pub outbound: u64,
+    pub inbound: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeerLiquidity {
+    pub peer: String,
+    pub peer_alias: Option<String>,
+    pub assets: Vec<AssetLiquidity>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LiquidityReport {
+    pub threshold_percent: u8,
+    pub peers: Vec<PeerLiquidity>,
+    pub channels: Vec<ChannelLiquidity>,
+    /// The imbalanced entries of `channels`, largest rebalance first.
+    pub imbalanced: Vec<ChannelLiquidity>,
+}
+
+/// lnd's uint64 fields arrive as strings.
+fn u64_field(value: &Value, field: &str) -> u64 {
+    match value.get(field) {
+        Some(Value::String(s)) => s.parse().unwrap_or(0),
+        Some(Value::Number(n)) => n.as_u64().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn str_field(value: &Value, field: &str) -> Option<String> {
+    value
+        .get(field)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// lnd's REST API renders `custom_channel_data` as base64 bytes holding
+/// tapd's JSON; some proxies already decode it.
+fn custom_channel_data(channel: &Value) -> Option<Value> {
+    match channel.get("custom_channel_data")? {
+        data @ Value::Object(_) => Some(data.clone()),
+        Value::String(encoded) if !encoded.is_empty() => {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .unwrap_or_else(|_| encoded.clone().into_bytes());
+            serde_json::from_slice(&bytes).ok()
+        }
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct AssetBalance {
+    name: Option<String>,
+    capacity: u64,
+    local: u64,
+    remote: u64,
+}
+
+/// Per-asset balances from tapd's channel data. Newer tapd versions list
+/// `funding_assets` with separate `local_assets`/`remote_assets`; older ones
+/// a single `assets` list carrying all three amounts.
+fn asset_balances(data: &Value) -> BTreeMap<String, AssetBalance> {
+    let mut balances: BTreeMap<String, AssetBalance> = BTreeMap::new();
+    let entries = |field: &str| {
+        data.get(field)
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    for asset in entries("assets") {
+        let genesis = asset
+            .pointer("/asset_utxo/asset_genesis")
+            .cloned()
+            .unwrap_or(Value::Null);
+        let Some(asset_id) = str_field(&genesis, "asset_id") else {
+            continue;
+        };
+        let balance = balances.entry(normalize_hex_id(&asset_id)).or_default();
+        balance.name = str_field(&genesis, "name");
+        balance.capacity += u64_field(&asset, "capacity");
+        balance.local += u64_field(&asset, "local_balance");
+        balance.remote += u64_field(&asset, "remote_balance");
+    }
+
+    for funding in entries("funding_assets") {
+        let genesis = funding.get("asset_genesis").unwrap_or(&Value::Null);
+        let Some(asset_id) = str_field(genesis, "asset_id") else {
+            continue;
+        };
+        let balance = balances.entry(normalize_hex_id(&asset_id)).or_default();
+        balance.name = str_field(genesis, "name");
+        balance.capacity += u64_field(&funding, "amount");
+    }
+    for (field, local) in [("local_assets", true), ("remote_assets", false)] {
+        for output in entries(field) {
+            let Some(asset_id) = str_field(&output, "asset_id") else {
+                continue;
+            };
+            let balance = balances.entry(normalize_hex_id(&asset_id)).or_default();
+            let amount = u64_field(&output, "amount");
+            if local {
+                balance.local += amount;
+            } else {
+                balance.remote += amount;
+            }
+        }
+    }
+    balances
+}
+
+/// Builds the report from lnd's `/v1/channels` response. `threshold` is the
+/// percent of a channel's asset balance on one side beyond which it counts
+/// as imbalanced.
+pub fn assemble(listing: &Value, threshold: u8) -> LiquidityReport {
+    let mut channels = Vec::new();
+    for channel in listing
+        .get("channels")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let Some(data) = custom_channel_data(channel) else {
+            continue;
+        };
+        let peer = str_field(channel, "remote_pubkey").unwrap_or_default();
+        for (asset_id, balance) in asset_balances(&data) {
+            let total = balance.local + balance.remote;
+            let local_ratio = if total == 0 {
+                0.5
+            } else {
+                balance.local as f64 / total as f64
+            };
+            // Compared in integers so a channel exactly at the threshold
+            // is not flagged
+            let limit = u128::from(total) * u128::from(threshold);
+            let imbalance = if u128::from(balance.local) * 100 > limit {
+                Some(Imbalance::Outbound)
+            } else if u128::from(balance.remote) * 100 > limit {
+                Some(Imbalance::Inbound)
+            } else {
+                None
+            };
+            channels.push(ChannelLiquidity {
+                channel_point: str_field(channel, "channel_point"),
+                chan_id: str_field(channel, "chan_id"),
+                peer: peer.clone(),
+                peer_alias: str_field(channel, "peer_alias"),
+                active: channel.get("active").and_then(Value::as_bool) == Some(true),
+                asset_id,
+                asset_name: balance.name,
+                capacity: balance.capacity.max(total),
+                outbound: balance.local,
+                inbound: balance.remote,
+                local_ratio,
+                imbalance,
+                rebalance_amount: imbalance
+                    .map(|_| balance.local.abs_diff(balance.remote) / 2)
+                    .unwrap_or(0),
+            });
+        }
+    }
+
+    let mut peers: BTreeMap<&str, PeerLiquidity> = BTreeMap::new();
+    for channel in &channels {
+        let peer = peers
+            .entry(channel.peer.as_str())
+            .or_insert_with(|| PeerLiquidity {
+                peer: channel.peer.clone(),
+                peer_alias: channel.peer_alias.clone(),
+                assets: Vec::new(),
+            });
+        match peer
+            .assets
+            .iter_mut()
+            .find(|a| a.asset_id == channel.asset_id)
+        {
+            Some(asset) => {
+                asset.channels += 1;
+                asset.capacity += channel.capacity;
+                asset.outbound += channel.outbound;
+                asset.inbound += channel.inbound;
+            }
+            None => peer.assets.push(AssetLiquidity {
+                asset_id: channel.asset_id.clone(),
+                asset_name: channel.asset_name.clone(),
+                channels: 1,
+                capacity: channel.capacity,
+                outbound: channel.outbound,
+                inbound: channel.inbound,
+            }),
+        }
+    }
+    let peers = peers.into_values().collect();
+
+    let mut imbalanced: Vec<ChannelLiquidity> = channels
+        .iter()
+        .filter(|c| c.imbalance.is_some())
+        .cloned()
+        .collect();
+    imbalanced.sort_by_key(|c| std::cmp::Reverse(c.rebalance_amount));
+
+    LiquidityReport {
+        threshold_percent: threshold,
+        peers,
+        channels,
+        imbalanced,
+    }
+}
+
+#[instrument(skip(client, lnd_macaroon_hex))]
+async fn liquidity_report(
+    client: &Client,
+    lnd_url: &str,
+    lnd_macaroon_hex: &str,
+    threshold: u8,
+) -> Result<LiquidityReport, AppError> {
+    let response = client
+        .get(format!("{lnd_url}/v1/channels"))
+        .header("Grpc-Metadata-macaroon", lnd_macaroon_hex)
+        .send()
+        .await
+        .map_err(AppError::RequestError)?;
+    let listing: Value = parse_upstream(response).await?;
+    let report = assemble(&listing, threshold);
+    debug!(
+        "Liquidity report: {} asset channel balances, {} imbalanced",
+        report.channels.len(),
+        report.imbalanced.len()
+    );
+    Ok(report)
+}
+
+async fn liquidity_handler(
+    query: web::Query<LiquidityQuery>,
+    client: web::Data<Client>,
+    lnd: web::Data<LndNode>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let result = async {
+        let Some((lnd_url, lnd_macaroon_hex)) = &lnd.0 else {
+            return Err(AppError::ServiceUnavailable(
+                "The liquidity report requires LND_URL".to_string(),
+            ));
+        };
+        let threshold = query
+            .threshold
+            .unwrap_or(config.liquidity_imbalance_threshold);
+        if !(51..=99).contains(&threshold) {
+            return Err(AppError::ValidationError(
+                "threshold must be between 51 and 99".to_string(),
+            ));
+        }
+        liquidity_report(&client, lnd_url, lnd_macaroon_hex, threshold).await
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/channels/liquidity").route(web::get().to(liquidity_handler)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ASSET: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+
+    fn channel(point: &str, peer: &str, local: u64, remote: u64) -> Value {
+        let data = json!({
+            "funding_assets": [{
+                "asset_genesis": {"asset_id": ASSET, "name": "usd"},
+                "amount": (local + remote).to_string(),
+            }],
+            "local_assets": [{"asset_id": ASSET, "amount": local.to_string()}],
+            "remote_assets": [{"asset_id": ASSET, "amount": remote.to_string()}],
+        });
+        json!({
+            "channel_point": point,
+            "chan_id": "1",
+            "remote_pubkey": peer,
+            "active": true,
+            "custom_channel_data": base64::engine::general_purpose::STANDARD
+                .encode(data.to_string()),
+        })
+    }
+
+    #[test]
+    fn test_imbalanced_channels_are_flagged_with_rebalance_amount() {
+        let listing = json!({"channels": [
+            channel("a:0", "peer1", 900, 100),
+            channel("b:0", "peer1", 500, 500),
+            channel("c:0", "peer2", 50, 950),
+            {"channel_point": "d:0", "remote_pubkey": "peer3"},
+        ]});
+        let report = assemble(&listing, 80);
+
+        assert_eq!(report.channels.len(), 3);
+        assert_eq!(report.imbalanced.len(), 2);
+        assert_eq!(report.imbalanced[0].channel_point.as_deref(), Some("c:0"));
+        assert_eq!(report.imbalanced[0].imbalance, Some(Imbalance::Inbound));
+        assert_eq!(report.imbalanced[0].rebalance_amount, 450);
+        assert_eq!(report.imbalanced[1].imbalance, Some(Imbalance::Outbound));
+        assert_eq!(report.imbalanced[1].rebalance_amount, 400);
+
+        assert_eq!(report.peers.len(), 2);
+        let peer1 = &report.peers[0].assets[0];
+        assert_eq!(peer1.channels, 2);
+        assert_eq!((peer1.outbound, peer1.inbound), (1400, 600));
+        assert_eq!(peer1.capacity, 2000);
+
+        assert!(assemble(&listing, 95).imbalanced.is_empty());
+        assert_eq!(assemble(&listing, 90).imbalanced.len(), 1);
+    }
+
+    #[test]
+    fn test_legacy_channel_data_is_read() {
+        let listing = json!({"channels": [{
+            "channel_point": "a:0",
+            "remote_pubkey": "peer",
+            "custom_channel_data": {"assets": [{
+                "asset_utxo": {"asset_genesis": {"asset_id": ASSET, "name": "usd"}},
+                "capacity": "1000",
+                "local_balance": "0",
+                "remote_balance": "1000",
+            }]},
+        }]});
+        let report = assemble(&listing, 80);
+        assert_eq!(report.channels[0].asset_name.as_deref(), Some("usd"));
+        assert_eq!(report.channels[0].inbound, 1000);
+        assert_eq!(report.imbalanced[0].imbalance, Some(Imbalance::Inbound));
+    }
+}
@@ -1,17 +1,28 @@
-use super::mailbox_auth::{generate_challenge, validate_authentication};
-use super::{handle_result, parse_upstream};
-use crate::database::SharedDatabase;
+use super::mailbox_auth::{bitcoin_network, generate_challenge, validate_authentication};
+use super::mailbox_proto;
+use super::{
+    backend, handle_result, public_url, require_database, validate_callback_url, ListEnvelope,
+    PageParams,
+};
+use crate::config::Config;
+use crate::database::{OutboxStatus, SharedDatabase};
 use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::lockout::{LockedOutError, SharedAuthLockouts};
+use crate::mailbox_outbox::{MailboxOutbox, RetentionOverride, RetentionPolicy};
 use crate::monitoring::SharedMonitoring;
 use crate::types::{BaseUrl, MacaroonHex};
+use crate::websocket::correlation::CorrelationStrategy;
+use crate::websocket::policy::{EndpointGroup, WsPolicies, WsPolicy};
 use crate::websocket::proxy_handler::WebSocketProxyHandler;
-use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError, Result as ActixResult};
 use actix_ws::{Message, MessageStream, Session};
 use futures_util::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tokio::time::timeout;
 use tracing::{debug, error, info, instrument, warn};
 
@@ -21,12 +32,42 @@ pub struct ReceiveRequest {
     pub auth_sig: serde_json::Value,
 }
 
+/// First step of the REST handshake: the receiver asks for a challenge to
+/// sign.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChallengeRequest {
+    pub init: serde_json::Value,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SendRequest {
     pub receiver_id: String,
     pub encrypted_payload: String,
     pub tx_proof: Option<serde_json::Value>,
     pub expiry_block_height: Option<u32>,
+    /// Receives the message's delivery receipts when it goes through the
+    /// outbox; not sent to tapd.
+    #[serde(default, skip_serializing)]
+    pub callback_url: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub callback_secret: Option<String>,
+}
+
+impl SendRequest {
+    fn validate_callback(&self) -> Result<(), AppError> {
+        match (&self.callback_url, &self.callback_secret) {
+            (Some(url), _) => validate_callback_url(url),
+            (None, Some(_)) => Err(AppError::ValidationError(
+                "callback_secret requires callback_url".to_string(),
+            )),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OutboxQuery {
+    pub status: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,15 +86,6 @@ enum MailboxState {
     Closed,
 }
 
-struct ConnectionLimits {
-    message_count: u32,
-    last_reset: Instant,
-}
-
-const IDLE_TIMEOUT_SECS: u64 = 300;
-const RATE_LIMIT_MESSAGES_PER_MINUTE: u32 = 60;
-const MAX_MESSAGE_SIZE_BYTES: usize = 64 * 1024;
-
 #[derive(Debug, Serialize, Deserialize)]
 struct WebSocketMailboxMessage {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -81,14 +113,10 @@ pub async fn get_mailbox_info(
     macaroon_hex: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Fetching mailbox info");
-    let url = format!("{base_url}/v1/taproot-assets/mailbox/info");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/mailbox/info")
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -99,15 +127,11 @@ pub async fn receive_mail(
     request: ReceiveRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Receiving mail");
-    let url = format!("{base_url}/v1/taproot-assets/mailbox/receive");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/mailbox/receive")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -118,15 +142,11 @@ pub async fn send_mail(
     request: SendRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Sending mail to receiver ID: {}", request.receiver_id);
-    let url = format!("{base_url}/v1/taproot-assets/mailbox/send");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/mailbox/send")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -137,15 +157,11 @@ pub async fn remove_message(
     request: RemoveMessageRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Removing mailbox message");
-    let url = format!("{base_url}/v1/taproot-assets/mailbox/remove");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/mailbox/remove")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 async fn info(
@@ -156,31 +172,259 @@ async fn info(
     handle_result(get_mailbox_info(&client, &base_url.0, &macaroon_hex.0).await)
 }
 
+async fn challenge(req: web::Json<ChallengeRequest>) -> HttpResponse {
+    let receiver_id = req
+        .init
+        .get("receiver_id")
+        .and_then(|v| v.as_str())
+        .filter(|id| !id.is_empty());
+    let Some(receiver_id) = receiver_id else {
+        return handle_result::<()>(Err(AppError::ValidationError(
+            "init.receiver_id is required".to_string(),
+        )));
+    };
+    handle_result(generate_challenge(Some(receiver_id)).await)
+}
+
+/// Polls the receiver's mailbox. An `auth_sig` answering a challenge from
+/// `/mailbox/challenge` is checked the same way as on the WebSocket before
+/// the request reaches tapd; the challenge is then spent, so each poll
+/// signs a fresh one. Requests without a gateway challenge pass through.
+/// Failed answers count towards a lockout of both the client address and
+/// the receiver.
 async fn receive(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<ReceiveRequest>,
 ) -> HttpResponse {
-    handle_result(receive_mail(&client, &base_url.0, &macaroon_hex.0, req.into_inner()).await)
+    let request = req.into_inner();
+    if request.auth_sig.get("challenge_id").is_some() {
+        let lockouts = http_req
+            .app_data::<web::Data<SharedAuthLockouts>>()
+            .map(|l| l.get_ref().clone());
+        let keys = lockouts.as_ref().map_or_else(Vec::new, |lockouts| {
+            let receiver_id = request
+                .init
+                .get("receiver_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            vec![
+                lockouts.ip_key(
+                    http_req.peer_addr().map(|addr| addr.ip()),
+                    http_req.headers(),
+                ),
+                format!("mailbox:{receiver_id}"),
+            ]
+        });
+        if let Some(secs) = lockouts.as_ref().and_then(|l| l.retry_after(&keys)) {
+            return LockedOutError(secs).error_response();
+        }
+        let database = http_req
+            .app_data::<web::Data<SharedDatabase>>()
+            .map(|d| d.get_ref().clone());
+        match validate_authentication(
+            &request.init,
+            &request.auth_sig,
+            &client,
+            &base_url.0,
+            &macaroon_hex.0,
+            database.as_ref(),
+            bitcoin_network(&http_req),
+        )
+        .await
+        {
+            Ok(true) => {
+                if let Some(lockouts) = &lockouts {
+                    lockouts.record_success(&keys).await;
+                }
+            }
+            Ok(false) => {
+                // Counted like a WebSocket handshake failure; there is no
+                // connection to mark.
+                if let Some(monitoring) = http_req.app_data::<web::Data<SharedMonitoring>>() {
+                    monitoring.record_auth_failure("").await;
+                }
+                if let Some(lockouts) = &lockouts {
+                    lockouts.record_failure(&keys).await;
+                }
+                return HttpResponse::Unauthorized().json(serde_json::json!({
+                    "error": "Mailbox authentication failed",
+                    "auth_success": false
+                }));
+            }
+            Err(e) => return handle_result::<()>(Err(e)),
+        }
+    }
+    handle_result(receive_mail(&client, &base_url.0, &macaroon_hex.0, request).await)
+}
+
+/// The request's outbox, when `MAILBOX_OUTBOX_ENABLED` is set.
+pub(super) fn outbox(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+) -> Result<Option<MailboxOutbox>, AppError> {
+    let Some(config) = req
+        .app_data::<web::Data<Config>>()
+        .filter(|c| c.mailbox_outbox_enabled)
+    else {
+        return Ok(None);
+    };
+    let events = req
+        .app_data::<web::Data<SharedEventBus>>()
+        .ok_or_else(|| AppError::ServiceUnavailable("Event bus not configured".to_string()))?;
+    Ok(Some(MailboxOutbox::new(
+        client.clone(),
+        base_url.to_string(),
+        macaroon_hex.to_string(),
+        require_database(req)?,
+        events.get_ref().clone(),
+        config.mailbox_outbox_max_attempts,
+    )))
+}
+
+fn require_outbox(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+) -> Result<MailboxOutbox, AppError> {
+    outbox(req, client, base_url, macaroon_hex)?.ok_or_else(|| {
+        AppError::ServiceUnavailable(
+            "The mailbox outbox requires MAILBOX_OUTBOX_ENABLED".to_string(),
+        )
+    })
 }
 
+/// Passes the message straight to tapd, or with the outbox enabled queues
+/// it and answers `202` with its outbox entry.
 async fn send(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<SendRequest>,
 ) -> HttpResponse {
-    handle_result(send_mail(&client, &base_url.0, &macaroon_hex.0, req.into_inner()).await)
+    let request = req.into_inner();
+    let outbox = match outbox(&http_req, &client, &base_url.0, &macaroon_hex.0) {
+        Ok(Some(outbox)) => outbox,
+        Ok(None) => {
+            return handle_result(send_mail(&client, &base_url.0, &macaroon_hex.0, request).await)
+        }
+        Err(e) => return handle_result::<()>(Err(e)),
+    };
+    let result = async {
+        request.validate_callback()?;
+        let body = serde_json::to_value(&request)?;
+        outbox
+            .enqueue(
+                body,
+                request.receiver_id,
+                request.callback_url,
+                request.callback_secret,
+            )
+            .await
+    }
+    .await;
+    match result {
+        Ok(message) => HttpResponse::build(StatusCode::ACCEPTED)
+            .insert_header((
+                header::LOCATION,
+                public_url(
+                    &http_req,
+                    &format!("/v1/gateway/mailbox/outbox/{}", message.id),
+                ),
+            ))
+            .json(message),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
 }
 
+/// Removes messages from tapd and acknowledges the outbox entries they
+/// were sent as.
 async fn remove(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<RemoveMessageRequest>,
 ) -> HttpResponse {
-    handle_result(remove_message(&client, &base_url.0, &macaroon_hex.0, req.into_inner()).await)
+    let request = req.into_inner();
+    let (receiver_id, message_ids) = (request.receiver_id.clone(), request.message_ids.clone());
+    let result = remove_message(&client, &base_url.0, &macaroon_hex.0, request).await;
+    if result.is_ok() {
+        match outbox(&http_req, &client, &base_url.0, &macaroon_hex.0) {
+            Ok(Some(outbox)) => {
+                if let Err(e) = outbox.removed(&receiver_id, &message_ids).await {
+                    warn!("Failed to acknowledge removed mailbox messages: {}", e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to acknowledge removed mailbox messages: {}", e),
+        }
+    }
+    handle_result(result)
+}
+
+async fn list_outbox(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    query: web::Query<OutboxQuery>,
+) -> HttpResponse {
+    let result = async {
+        require_outbox(&req, &client, &base_url.0, &macaroon_hex.0)?;
+        let database = require_database(&req)?;
+        let status = query
+            .status
+            .as_deref()
+            .map(OutboxStatus::parse)
+            .transpose()?;
+        let page = PageParams::from_query(req.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let messages = database.outbox_messages(status, limit + 1, offset).await?;
+        Ok(ListEnvelope::from_offset_page(messages, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn get_outbox_message(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let result = async {
+        require_outbox(&req, &client, &base_url.0, &macaroon_hex.0)?;
+        require_database(&req)?
+            .outbox_message(&path)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Mailbox message {path} not found")))
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn acknowledge_outbox_message(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let result = async {
+        require_outbox(&req, &client, &base_url.0, &macaroon_hex.0)?
+            .acknowledge(&path)
+            .await
+    }
+    .await;
+    handle_result(result)
 }
 
 async fn receive_websocket(
@@ -190,6 +434,14 @@ async fn receive_websocket(
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
 ) -> ActixResult<HttpResponse> {
+    // A locked-out client gets no connection to answer challenges on
+    if let Some(lockouts) = req.app_data::<web::Data<SharedAuthLockouts>>() {
+        let key = lockouts.ip_key(req.peer_addr().map(|addr| addr.ip()), req.headers());
+        if let Some(secs) = lockouts.retry_after(&[key]) {
+            return Ok(LockedOutError(secs).error_response());
+        }
+    }
+
     // Check if WebSocketProxyHandler is available and clone it before using req
     let maybe_proxy_handler = req
         .app_data::<web::Data<Arc<WebSocketProxyHandler>>>()
@@ -219,6 +471,13 @@ async fn receive_websocket(
     // Generate connection ID
     let connection_id = uuid::Uuid::new_v4().to_string();
 
+    let policy = req.app_data::<web::Data<Config>>().map_or_else(
+        || WsPolicies::default_for(EndpointGroup::Mailbox),
+        |c| c.ws_policies.mailbox,
+    );
+
+    let network = bitcoin_network(&req);
+
     // Fall back to custom implementation
     let (response, session, msg_stream) = actix_ws::handle(&req, stream)?;
 
@@ -240,8 +499,10 @@ async fn receive_websocket(
         base_url.0.clone(),
         macaroon_hex.0.clone(),
         database,
+        network,
         monitoring,
         connection_id,
+        policy,
     ));
 
     Ok(response)
@@ -255,20 +516,18 @@ async fn handle_mailbox_websocket_connection(
     base_url: String,
     macaroon_hex: String,
     database: Option<SharedDatabase>,
+    network: bitcoin::Network,
     monitoring: Option<SharedMonitoring>,
     connection_id: String,
+    policy: WsPolicy,
 ) {
     let mut state = MailboxState::AwaitingInit;
     let mut pending_init: Option<serde_json::Value> = None;
-    let mut limits = ConnectionLimits {
-        message_count: 0,
-        last_reset: Instant::now(),
-    };
+    let mut rate_limiter = policy.rate_limiter();
 
     // Main message loop with idle timeout
     loop {
-        let timeout_result =
-            timeout(Duration::from_secs(IDLE_TIMEOUT_SECS), msg_stream.next()).await;
+        let timeout_result = timeout(policy.idle_timeout(), msg_stream.next()).await;
 
         let msg = match timeout_result {
             Ok(Some(msg)) => msg,
@@ -289,7 +548,7 @@ async fn handle_mailbox_websocket_connection(
         };
 
         // Check rate limiting
-        if !check_rate_limit(&mut limits) {
+        if !rate_limiter.allow() {
             warn!("Rate limit exceeded, closing connection");
 
             // Record rate limit hit
@@ -305,92 +564,94 @@ async fn handle_mailbox_websocket_connection(
                 .await;
             break;
         }
-        match msg {
-            Ok(Message::Text(text)) => {
-                // Validate message size before processing
-                if text.len() > MAX_MESSAGE_SIZE_BYTES {
-                    warn!(
-                        "Message too large: {} bytes, max: {} bytes",
-                        text.len(),
-                        MAX_MESSAGE_SIZE_BYTES
-                    );
-                    let _ = session
-                        .close(Some(actix_ws::CloseReason {
-                            code: actix_ws::CloseCode::Size,
-                            description: Some("Message too large".to_string()),
-                        }))
-                        .await;
+        let (frame_type, payload) = match msg {
+            Ok(Message::Text(text)) => ("text", text.into_bytes()),
+            Ok(Message::Binary(data)) => ("binary", data),
+            Ok(Message::Close(_)) => {
+                info!("Mailbox WebSocket connection closed");
+                break;
+            }
+            Ok(Message::Ping(bytes)) => {
+                if let Err(e) = session.pong(&bytes).await {
+                    error!("Failed to send pong: {}", e);
                     break;
                 }
+                continue;
+            }
+            Ok(_) => continue,
+            Err(e) => {
+                error!("WebSocket message error: {}", e);
+                break;
+            }
+        };
 
-                debug!("Received mailbox WebSocket message: {}", text);
-                info!(
-                    "Received mailbox WebSocket message: type=text, len={}",
-                    text.len()
-                );
+        // Validate message size before processing
+        if payload.len() > policy.max_message_bytes {
+            warn!(
+                "Message too large: {} bytes, max: {} bytes",
+                payload.len(),
+                policy.max_message_bytes
+            );
+            let _ = session
+                .close(Some(actix_ws::CloseReason {
+                    code: actix_ws::CloseCode::Size,
+                    description: Some("Message too large".to_string()),
+                }))
+                .await;
+            break;
+        }
 
-                // Record message received in monitoring
-                if let Some(ref mon) = monitoring {
-                    mon.record_message_received(&connection_id, text.len())
-                        .await;
-                }
+        info!(
+            "Received mailbox WebSocket message: type={}, len={}",
+            frame_type,
+            payload.len()
+        );
 
-                let parsed_msg: Result<WebSocketMailboxMessage, _> = serde_json::from_str(&text);
-                match parsed_msg {
-                    Ok(ws_msg) => {
-                        match handle_mailbox_message(
-                            &mut state,
-                            ws_msg,
-                            &mut pending_init,
-                            &client,
-                            &base_url,
-                            &macaroon_hex,
-                            &mut session,
-                            database.as_ref(),
-                            monitoring.as_ref(),
-                            &connection_id,
-                        )
-                        .await
-                        {
-                            Ok(should_continue) => {
-                                if !should_continue {
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                error!("Error handling mailbox message: {}", e);
-                                let error_response = MailboxResponse {
-                                    challenge: None,
-                                    auth_success: Some(false),
-                                    messages: None,
-                                    eos: None,
-                                };
-                                if let Ok(error_json) = serde_json::to_string(&error_response) {
-                                    let _ = session.text(error_json).await;
-                                }
-                                break;
-                            }
+        // Record message received in monitoring
+        if let Some(ref mon) = monitoring {
+            mon.record_message_received(&connection_id, payload.len())
+                .await;
+        }
+
+        match parse_client_frame(frame_type, &payload) {
+            Ok(ws_msg) => {
+                match handle_mailbox_message(
+                    &mut state,
+                    ws_msg,
+                    &mut pending_init,
+                    &client,
+                    &base_url,
+                    &macaroon_hex,
+                    &mut session,
+                    database.as_ref(),
+                    network,
+                    monitoring.as_ref(),
+                    &connection_id,
+                )
+                .await
+                {
+                    Ok(should_continue) => {
+                        if !should_continue {
+                            break;
                         }
                     }
                     Err(e) => {
-                        error!("Failed to parse WebSocket message: {}", e);
+                        error!("Error handling mailbox message: {}", e);
+                        let error_response = MailboxResponse {
+                            challenge: None,
+                            auth_success: Some(false),
+                            messages: None,
+                            eos: None,
+                        };
+                        if let Ok(error_json) = serde_json::to_string(&error_response) {
+                            let _ = session.text(error_json).await;
+                        }
                         break;
                     }
                 }
             }
-            Ok(Message::Close(_)) => {
-                info!("Mailbox WebSocket connection closed");
-                break;
-            }
-            Ok(Message::Ping(bytes)) => {
-                if let Err(e) = session.pong(&bytes).await {
-                    error!("Failed to send pong: {}", e);
-                    break;
-                }
-            }
-            Ok(_) => {}
             Err(e) => {
-                error!("WebSocket message error: {}", e);
+                error!("Failed to parse WebSocket message: {}", e);
                 break;
             }
         }
@@ -407,17 +668,20 @@ async fn handle_mailbox_websocket_connection(
     }
 }
 
-fn check_rate_limit(limits: &mut ConnectionLimits) -> bool {
-    let now = Instant::now();
-
-    // Reset counter every minute
-    if now.duration_since(limits.last_reset) >= Duration::from_secs(60) {
-        limits.message_count = 0;
-        limits.last_reset = now;
+/// Text frames carry JSON. Binary frames carry either JSON bytes or a
+/// protobuf-encoded message; both decode to the same message.
+fn parse_client_frame(
+    frame_type: &str,
+    payload: &[u8],
+) -> Result<WebSocketMailboxMessage, AppError> {
+    match serde_json::from_slice(payload) {
+        Ok(msg) => Ok(msg),
+        Err(_) if frame_type == "binary" => {
+            let decoded = mailbox_proto::decode_client_message(payload)?;
+            serde_json::from_value(decoded).map_err(|e| AppError::InvalidInput(e.to_string()))
+        }
+        Err(e) => Err(AppError::InvalidInput(e.to_string())),
     }
-
-    limits.message_count += 1;
-    limits.message_count <= RATE_LIMIT_MESSAGES_PER_MINUTE
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -430,6 +694,7 @@ async fn handle_mailbox_message(
     macaroon_hex: &str,
     session: &mut Session,
     database: Option<&SharedDatabase>,
+    network: bitcoin::Network,
     monitoring: Option<&SharedMonitoring>,
     connection_id: &str,
 ) -> Result<bool, AppError> {
@@ -437,10 +702,10 @@ async fn handle_mailbox_message(
         MailboxState::AwaitingInit => {
             if let Some(init) = msg.init {
                 info!("Received init message, sending challenge");
+                let receiver_id = init.get("receiver_id").and_then(|v| v.as_str());
+                let challenge_response = generate_challenge(receiver_id).await?;
                 *pending_init = Some(init);
                 *state = MailboxState::ChallengeSent;
-
-                let challenge_response = generate_challenge().await?;
                 let response = MailboxResponse {
                     challenge: Some(challenge_response),
                     auth_success: None,
@@ -480,6 +745,7 @@ async fn handle_mailbox_message(
                         base_url,
                         macaroon_hex,
                         database,
+                        network,
                     )
                     .await?;
 
@@ -754,19 +1020,101 @@ async fn receive_websocket_with_proxy(
 
     // Handle the WebSocket connection with correlation tracking enabled
     ws_proxy_handler
-        .handle_websocket(req, stream, backend_endpoint, true)
+        .handle_websocket(
+            req,
+            stream,
+            backend_endpoint,
+            CorrelationStrategy::Sequence,
+            EndpointGroup::Mailbox,
+        )
         .await
         .map_err(actix_web::error::ErrorInternalServerError)
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/mailbox/info").route(web::get().to(info)))
+        .service(web::resource("/mailbox/challenge").route(web::post().to(challenge)))
         .service(web::resource("/mailbox/receive").route(web::post().to(receive)))
         .service(web::resource("/mailbox/receive").route(web::get().to(receive_websocket)))
         .service(web::resource("/mailbox/remove").route(web::post().to(remove)))
         .service(web::resource("/mailbox/send").route(web::post().to(send)));
 }
 
+/// The policy the `mailbox_gc` job applies to a receiver once `custom` is
+/// stored for it.
+fn effective_retention(req: &HttpRequest, custom: RetentionOverride) -> RetentionPolicy {
+    let default = req
+        .app_data::<web::Data<Config>>()
+        .map(|c| RetentionPolicy {
+            max_age_secs: c.mailbox_retention_max_age_secs,
+            max_messages: c.mailbox_retention_max_messages,
+        })
+        .unwrap_or_default();
+    default.with_override(custom)
+}
+
+async fn set_retention(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<RetentionOverride>,
+) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let receiver_id = path.into_inner();
+        let custom = body.into_inner();
+        let value = serde_json::to_value(custom)?;
+        if !database
+            .set_receiver_retention(&receiver_id, Some(&value))
+            .await?
+        {
+            return Err(AppError::NotFound(format!(
+                "Receiver {receiver_id} not found"
+            )));
+        }
+        info!("Set mailbox retention for receiver {}", receiver_id);
+        Ok(serde_json::json!({
+            "receiver_id": receiver_id,
+            "retention": custom,
+            "effective": effective_retention(&req, custom),
+        }))
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn clear_retention(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let receiver_id = path.into_inner();
+        if !database.set_receiver_retention(&receiver_id, None).await? {
+            return Err(AppError::NotFound(format!(
+                "Receiver {receiver_id} not found"
+            )));
+        }
+        Ok(serde_json::json!({
+            "receiver_id": receiver_id,
+            "effective": effective_retention(&req, RetentionOverride::default()),
+        }))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// The outbox's delivery receipts and retention, mounted under `/v1/gateway`.
+pub fn configure_gateway(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/mailbox/outbox").route(web::get().to(list_outbox)))
+        .service(web::resource("/mailbox/outbox/{id}").route(web::get().to(get_outbox_message)))
+        .service(
+            web::resource("/mailbox/outbox/{id}/ack")
+                .route(web::post().to(acknowledge_outbox_message)),
+        )
+        .service(
+            web::resource("/mailbox/receivers/{receiver_id}/retention")
+                .route(web::put().to(set_retention))
+                .route(web::delete().to(clear_retention)),
+        );
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -784,6 +1132,22 @@ mod tests {
         assert!(!serialized.contains("auth_sig"));
     }
 
+    #[test]
+    fn test_binary_frames_accept_json_and_protobuf() {
+        let json_frame = br#"{"init": {"receiver_id": "abc"}}"#;
+        let msg = parse_client_frame("binary", json_frame).unwrap();
+        assert_eq!(msg.init.unwrap()["receiver_id"], "abc");
+
+        // MailboxClientMessage { auth_sig: { challenge_id: "c1" } }
+        let proto_frame = [0x12, 0x04, 0x12, 0x02, b'c', b'1'];
+        let msg = parse_client_frame("binary", &proto_frame).unwrap();
+        assert!(msg.init.is_none());
+        assert_eq!(msg.auth_sig.unwrap()["challenge_id"], "c1");
+
+        // Text frames are JSON only
+        assert!(parse_client_frame("text", &proto_frame).is_err());
+    }
+
     #[test]
     fn test_websocket_message_deserialization() {
         let json_str = r#"{"init": {"receiver_id": "test"}, "auth_sig": {"signature": "abc123"}}"#;
@@ -846,7 +1210,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_challenge() {
-        let challenge = generate_challenge().await.unwrap();
+        let challenge = generate_challenge(None).await.unwrap();
 
         assert!(challenge.get("challenge_id").is_some());
         assert!(challenge.get("timestamp").is_some());
@@ -874,6 +1238,7 @@ mod tests {
             "http://localhost:8289",
             "test_macaroon",
             None,
+            bitcoin::Network::Bitcoin,
         )
         .await;
         assert!(result.is_err()); // Should fail due to missing required fields
@@ -895,11 +1260,34 @@ mod tests {
             "http://localhost:8289",
             "test_macaroon",
             None,
+            bitcoin::Network::Bitcoin,
         )
         .await;
         assert!(result.is_err()); // Should fail due to invalid challenge_id
     }
 
+    #[tokio::test]
+    async fn test_bound_challenge_rejects_other_receivers() {
+        let challenge = generate_challenge(Some("receiver_a")).await.unwrap();
+        let auth_sig = json!({
+            "signature": "ab".repeat(64),
+            "challenge_id": challenge["challenge_id"],
+            "timestamp": challenge["timestamp"],
+        });
+
+        let result = validate_authentication(
+            &json!({"receiver_id": "receiver_b"}),
+            &auth_sig,
+            &reqwest::Client::new(),
+            "http://localhost:8289",
+            "test_macaroon",
+            None,
+            bitcoin::Network::Bitcoin,
+        )
+        .await;
+        assert!(!result.unwrap());
+    }
+
     #[test]
     fn test_websocket_url_format() {
         let base_url = "wss://localhost:8080";
@@ -1,10 +1,13 @@
+use crate::config::Config;
 use crate::crypto::{
-    derive_public_key_from_receiver_id, verify_schnorr_signature, verify_signature,
+    derive_public_key_from_receiver_id, key_controls_address, verify_bip322_signature,
+    verify_schnorr_signature, verify_signature,
 };
 use crate::database::{ReceiverInfo, SharedDatabase};
 use crate::error::AppError;
+use actix_web::{web, HttpRequest};
 use base64::Engine;
-use bitcoin::bech32;
+use bitcoin::{bech32, Network};
 use chrono::Utc;
 use reqwest::Client;
 use std::collections::HashMap;
@@ -12,6 +15,7 @@ use std::sync::Mutex;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tracing::{debug, error, info, warn};
 
+use super::backend;
 use super::mailbox::ReceiveRequest;
 
 const CHALLENGE_EXPIRY_SECS: u64 = 300;
@@ -24,13 +28,18 @@ pub(crate) struct ChallengeData {
     pub timestamp: i64,
     pub nonce: String,
     pub issued_at: Instant,
+    /// The receiver (or, for session logins, the key) the challenge was
+    /// issued to, when the client named one up front; only it can answer.
+    pub receiver_id: Option<String>,
 }
 
 lazy_static::lazy_static! {
     static ref ACTIVE_CHALLENGES: Mutex<HashMap<String, ChallengeData>> = Mutex::new(HashMap::new());
 }
 
-pub(crate) async fn generate_challenge() -> Result<serde_json::Value, AppError> {
+pub(crate) async fn generate_challenge(
+    receiver_id: Option<&str>,
+) -> Result<serde_json::Value, AppError> {
     let challenge_id = uuid::Uuid::new_v4().to_string();
     let timestamp = chrono::Utc::now().timestamp();
     let nonce = base64::engine::general_purpose::STANDARD.encode(uuid::Uuid::new_v4().as_bytes());
@@ -40,6 +49,7 @@ pub(crate) async fn generate_challenge() -> Result<serde_json::Value, AppError>
         timestamp,
         nonce: nonce.clone(),
         issued_at: Instant::now(),
+        receiver_id: receiver_id.map(str::to_string),
     };
 
     {
@@ -64,6 +74,34 @@ pub(crate) async fn generate_challenge() -> Result<serde_json::Value, AppError>
     }))
 }
 
+/// Spends challenge `challenge_id` on behalf of `subject` and returns the
+/// message that had to be signed; `None` if it is unknown, expired or bound
+/// to someone else. The challenge is gone either way once found, so a wrong
+/// signature cannot be retried against it.
+pub(crate) fn redeem_challenge(challenge_id: &str, subject: &str) -> Option<String> {
+    let mut challenges = ACTIVE_CHALLENGES.lock().unwrap_or_else(|e| e.into_inner());
+    challenges.retain(|_, data| data.issued_at.elapsed().as_secs() < CHALLENGE_EXPIRY_SECS);
+    let data = challenges.remove(challenge_id)?;
+    if data
+        .receiver_id
+        .as_deref()
+        .is_some_and(|bound| bound != subject)
+    {
+        return None;
+    }
+    Some(format!(
+        "Sign this challenge: {}-{}-{}",
+        data.challenge_id, data.timestamp, data.nonce
+    ))
+}
+
+/// The network BIP-322 addresses are checked against: the gateway's
+/// configured network, or mainnet.
+pub(crate) fn bitcoin_network(req: &HttpRequest) -> Network {
+    req.app_data::<web::Data<Config>>()
+        .map_or(Network::Bitcoin, |config| config.bitcoin_network)
+}
+
 pub(crate) async fn validate_authentication(
     init: &serde_json::Value,
     auth_sig: &serde_json::Value,
@@ -71,6 +109,7 @@ pub(crate) async fn validate_authentication(
     base_url: &str,
     macaroon_hex: &str,
     database: Option<&SharedDatabase>,
+    network: Network,
 ) -> Result<bool, AppError> {
     let receiver_id = init
         .get("receiver_id")
@@ -126,6 +165,18 @@ pub(crate) async fn validate_authentication(
         data
     };
 
+    if challenge_data
+        .receiver_id
+        .as_deref()
+        .is_some_and(|bound| bound != receiver_id)
+    {
+        warn!(
+            "Challenge {} was issued to a different receiver than {}",
+            challenge_id, receiver_id
+        );
+        return Ok(false);
+    }
+
     let current_time = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map_err(|_| AppError::InvalidInput("System time error".to_string()))?
@@ -154,7 +205,19 @@ pub(crate) async fn validate_authentication(
         challenge_data.challenge_id, challenge_data.timestamp, challenge_data.nonce
     );
 
-    if !verify_signature_with_receiver(&expected_message, signature, receiver_id, database).await? {
+    // Wallets that sign with BIP-322 name the address they signed for.
+    let address = auth_sig.get("address").and_then(|v| v.as_str());
+
+    if !verify_signature_with_receiver(
+        &expected_message,
+        signature,
+        receiver_id,
+        address,
+        database,
+        network,
+    )
+    .await?
+    {
         warn!("Cryptographic signature verification failed");
         return Ok(false);
     }
@@ -215,23 +278,46 @@ async fn verify_signature_with_receiver(
     message: &str,
     signature: &str,
     receiver_id: &str,
+    address: Option<&str>,
     database: Option<&SharedDatabase>,
+    network: Network,
 ) -> Result<bool, AppError> {
-    if let Some(public_key) = derive_public_key_from_receiver_id(receiver_id)? {
-        if public_key.len() == 64 {
-            return verify_schnorr_signature(message, signature, &public_key);
-        } else {
-            return verify_signature(message, signature, &public_key);
+    // A rotated receiver signs with its stored key, even when its id is a
+    // key itself, or with a replaced key still in its overlap window.
+    if let Some(db) = database {
+        if let Some(keys) = db
+            .rotated_receiver_keys(receiver_id, Utc::now().timestamp())
+            .await?
+        {
+            if verify_signature_with_key(message, signature, &keys.current, address, network)? {
+                return Ok(true);
+            }
+            for (public_key, until) in &keys.deprecated {
+                if verify_signature_with_key(message, signature, public_key, address, network)? {
+                    warn!(
+                        "Receiver {} authenticated with deprecated key {}, accepted until {}",
+                        receiver_id, public_key, until
+                    );
+                    return Ok(true);
+                }
+            }
+            return Ok(false);
         }
     }
 
+    if let Some(public_key) = derive_public_key_from_receiver_id(receiver_id)? {
+        return verify_signature_with_key(message, signature, &public_key, address, network);
+    }
+
     if let Some(db) = database {
         if let Some(receiver_info) = db.get_receiver_info(receiver_id).await? {
-            if receiver_info.public_key.len() == 64 {
-                return verify_schnorr_signature(message, signature, &receiver_info.public_key);
-            } else {
-                return verify_signature(message, signature, &receiver_info.public_key);
-            }
+            return verify_signature_with_key(
+                message,
+                signature,
+                &receiver_info.public_key,
+                address,
+                network,
+            );
         }
     }
 
@@ -239,23 +325,46 @@ async fn verify_signature_with_receiver(
     Ok(false)
 }
 
+/// Checks a raw ECDSA or Schnorr signature against `public_key`, or, when
+/// the client named an address, a BIP-322 signature for an address that
+/// `public_key` controls on `network`.
+pub(crate) fn verify_signature_with_key(
+    message: &str,
+    signature: &str,
+    public_key: &str,
+    address: Option<&str>,
+    network: Network,
+) -> Result<bool, AppError> {
+    if let Some(address) = address {
+        if !key_controls_address(public_key, address, network)? {
+            warn!(
+                "Address {} is not controlled by the receiver's key",
+                address
+            );
+            return Ok(false);
+        }
+        return verify_bip322_signature(message, signature, address, network);
+    }
+
+    if public_key.len() == 64 {
+        verify_schnorr_signature(message, signature, public_key)
+    } else {
+        verify_signature(message, signature, public_key)
+    }
+}
+
 async fn validate_macaroon_permissions(
     client: &Client,
     base_url: &str,
     macaroon_hex: &str,
     receiver_id: &str,
 ) -> Result<bool, AppError> {
-    let info_url = format!("{base_url}/v1/taproot-assets/mailbox/info");
-    let info_response = client
-        .get(&info_url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    let info_response = backend(client, base_url, macaroon_hex)
         .timeout(Duration::from_secs(5))
+        .get("/v1/taproot-assets/mailbox/info")
         .send()
         .await
-        .map_err(|e| {
-            error!("Failed to validate macaroon with backend: {}", e);
-            AppError::RequestError(e)
-        })?;
+        .inspect_err(|e| error!("Failed to validate macaroon with backend: {}", e))?;
 
     if !info_response.status().is_success() {
         warn!(
@@ -287,12 +396,10 @@ async fn validate_macaroon_permissions(
         }),
     };
 
-    let receive_url = format!("{base_url}/v1/taproot-assets/mailbox/receive");
-    let receive_response = client
-        .post(&receive_url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .json(&test_receive)
+    let receive_response = backend(client, base_url, macaroon_hex)
         .timeout(Duration::from_secs(2))
+        .post("/v1/taproot-assets/mailbox/receive")
+        .json(&test_receive)
         .send()
         .await;
 
@@ -303,7 +410,7 @@ async fn validate_macaroon_permissions(
                 return Ok(false);
             }
         }
-        Err(e) if e.is_timeout() => {
+        Err(AppError::RequestError(e)) if e.is_timeout() => {
             debug!("Permission check timed out, assuming permissions are valid");
         }
         Err(e) => {
@@ -382,16 +489,14 @@ async fn validate_receiver_id(
         }
     }
 
-    let decode_url = format!("{base_url}/v1/taproot-assets/addrs/decode");
     let test_address = format!("taprt1{receiver_id}");
 
     match validate_taproot_address_format(&test_address) {
         Ok(true) => {
-            let response = client
-                .post(&decode_url)
-                .header("Grpc-Metadata-macaroon", macaroon_hex)
-                .json(&serde_json::json!({"addr": test_address}))
+            let response = backend(client, base_url, macaroon_hex)
                 .timeout(Duration::from_secs(2))
+                .post("/v1/taproot-assets/addrs/decode")
+                .json(&serde_json::json!({"addr": test_address}))
                 .send()
                 .await;
 
@@ -422,3 +527,76 @@ async fn validate_receiver_id(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{open_test_database, KeyRotation};
+    use bitcoin::hashes::{sha256, Hash};
+    use secp256k1::{Keypair, Message, Secp256k1};
+
+    fn sign(keypair: &Keypair, message: &str) -> String {
+        let digest = sha256::Hash::hash(message.as_bytes()).to_byte_array();
+        let signature =
+            Secp256k1::new().sign_schnorr_no_aux_rand(&Message::from_digest(digest), keypair);
+        hex::encode(signature.as_ref())
+    }
+
+    #[tokio::test]
+    async fn test_rotated_receiver_accepts_deprecated_key_until_revoked() {
+        let secp = Secp256k1::new();
+        let old = Keypair::from_seckey_slice(&secp, &[0x11; 32]).unwrap();
+        let new = Keypair::from_seckey_slice(&secp, &[0x22; 32]).unwrap();
+        let old_key = old.x_only_public_key().0.to_string();
+        let new_key = new.x_only_public_key().0.to_string();
+        let db = open_test_database().await;
+        db.store_receiver_info(&ReceiverInfo {
+            receiver_id: "receiver-1".to_string(),
+            public_key: old_key.clone(),
+            address: None,
+            created_at: 1,
+            last_seen: 1,
+            is_active: true,
+            metadata: None,
+        })
+        .await
+        .unwrap();
+        let now = Utc::now().timestamp();
+        db.rotate_receiver_key(&KeyRotation {
+            id: "r1".to_string(),
+            receiver_id: "receiver-1".to_string(),
+            old_public_key: old_key,
+            new_public_key: new_key,
+            reason: None,
+            rotated_at: now,
+            deprecated_until: now + 3600,
+        })
+        .await
+        .unwrap();
+
+        let message = "Sign this challenge: c-1-n";
+        let verify = |signature: String| {
+            let db = db.clone();
+            async move {
+                verify_signature_with_receiver(
+                    message,
+                    &signature,
+                    "receiver-1",
+                    None,
+                    Some(&db),
+                    Network::Bitcoin,
+                )
+                .await
+                .unwrap()
+            }
+        };
+        assert!(verify(sign(&new, message)).await);
+        assert!(verify(sign(&old, message)).await);
+
+        db.revoke_deprecated_receiver_keys("receiver-1", now)
+            .await
+            .unwrap();
+        assert!(!verify(sign(&old, message)).await);
+        assert!(verify(sign(&new, message)).await);
+    }
+}
@@ -0,0 +1,245 @@
+//! Decoder for protobuf-encoded mailbox WebSocket frames. Clients built on
+//! gRPC stubs send binary frames with this schema instead of JSON:
+//!
+//! ```protobuf
+//! message MailboxClientMessage {
+//!     oneof msg {
+//!         InitReceive init = 1;
+//!         AuthSignature auth_sig = 2;
+//!     }
+//! }
+//! message InitReceive {
+//!     bytes receiver_id = 1;
+//!     uint64 start_message_id_exclusive = 2;
+//!     uint32 start_block_height_inclusive = 3;
+//!     int64 start_timestamp_exclusive = 4;
+//! }
+//! message AuthSignature {
+//!     string signature = 1;
+//!     string challenge_id = 2;
+//!     int64 timestamp = 3;
+//!     string public_key = 4;
+//!     string address = 5;
+//! }
+//! ```
+//!
+//! Frames are converted to the JSON shape of text frames, so the rest of the
+//! handler is unchanged. Unknown fields are skipped.
+
+use crate::error::AppError;
+use serde_json::{json, Map, Value};
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+const WIRE_FIXED32: u64 = 5;
+
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Skipped,
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Result<u64, AppError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self
+                .buf
+                .split_first()
+                .ok_or_else(|| malformed("truncated varint"))?;
+            self.buf = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(malformed("varint too long"))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], AppError> {
+        if len > self.buf.len() {
+            return Err(malformed("field length exceeds frame"));
+        }
+        let (head, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(head)
+    }
+
+    /// The next `(field number, value)`, or `None` at the end of the buffer.
+    fn field(&mut self) -> Result<Option<(u64, Field<'a>)>, AppError> {
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 0x7 {
+            WIRE_VARINT => Field::Varint(self.varint()?),
+            WIRE_LEN => {
+                let len = usize::try_from(self.varint()?)
+                    .map_err(|_| malformed("field length exceeds frame"))?;
+                Field::Bytes(self.take(len)?)
+            }
+            WIRE_FIXED64 => {
+                self.take(8)?;
+                Field::Skipped
+            }
+            WIRE_FIXED32 => {
+                self.take(4)?;
+                Field::Skipped
+            }
+            wire => return Err(malformed(&format!("unsupported wire type {wire}"))),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+fn malformed(reason: &str) -> AppError {
+    AppError::InvalidInput(format!("Malformed protobuf mailbox frame: {reason}"))
+}
+
+fn utf8(bytes: &[u8]) -> Result<String, AppError> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| malformed("string field is not UTF-8"))
+}
+
+fn decode_init(buf: &[u8]) -> Result<Value, AppError> {
+    let mut reader = Reader { buf };
+    let mut init = Map::new();
+    while let Some((number, value)) = reader.field()? {
+        match (number, value) {
+            (1, Field::Bytes(id)) => {
+                init.insert("receiver_id".to_string(), json!(hex::encode(id)));
+            }
+            (2, Field::Varint(v)) => {
+                // uint64 travels as a string in tapd's JSON
+                init.insert(
+                    "start_message_id_exclusive".to_string(),
+                    json!(v.to_string()),
+                );
+            }
+            (3, Field::Varint(v)) => {
+                init.insert("start_block_height_inclusive".to_string(), json!(v as u32));
+            }
+            (4, Field::Varint(v)) => {
+                init.insert(
+                    "start_timestamp_exclusive".to_string(),
+                    json!((v as i64).to_string()),
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(Value::Object(init))
+}
+
+fn decode_auth_sig(buf: &[u8]) -> Result<Value, AppError> {
+    let mut reader = Reader { buf };
+    let mut auth_sig = Map::new();
+    while let Some((number, value)) = reader.field()? {
+        match (number, value) {
+            (1, Field::Bytes(s)) => {
+                auth_sig.insert("signature".to_string(), json!(utf8(s)?));
+            }
+            (2, Field::Bytes(s)) => {
+                auth_sig.insert("challenge_id".to_string(), json!(utf8(s)?));
+            }
+            (3, Field::Varint(v)) => {
+                auth_sig.insert("timestamp".to_string(), json!(v as i64));
+            }
+            (4, Field::Bytes(s)) => {
+                auth_sig.insert("public_key".to_string(), json!(utf8(s)?));
+            }
+            (5, Field::Bytes(s)) => {
+                auth_sig.insert("address".to_string(), json!(utf8(s)?));
+            }
+            _ => {}
+        }
+    }
+    Ok(Value::Object(auth_sig))
+}
+
+/// Decodes a `MailboxClientMessage` into `{"init": ...}` or
+/// `{"auth_sig": ...}`.
+pub(crate) fn decode_client_message(frame: &[u8]) -> Result<Value, AppError> {
+    let mut reader = Reader { buf: frame };
+    let mut message = Map::new();
+    while let Some((number, value)) = reader.field()? {
+        match (number, value) {
+            (1, Field::Bytes(init)) => {
+                message.insert("init".to_string(), decode_init(init)?);
+            }
+            (2, Field::Bytes(auth_sig)) => {
+                message.insert("auth_sig".to_string(), decode_auth_sig(auth_sig)?);
+            }
+            _ => {}
+        }
+    }
+    if message.is_empty() {
+        return Err(malformed("neither init nor auth_sig is set"));
+    }
+    Ok(Value::Object(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut v: u64, out: &mut Vec<u8>) {
+        while v >= 0x80 {
+            out.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    fn len_field(number: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        varint(number << 3 | WIRE_LEN, out);
+        varint(bytes.len() as u64, out);
+        out.extend_from_slice(bytes);
+    }
+
+    #[test]
+    fn test_decodes_init_and_auth_sig() {
+        let mut init = Vec::new();
+        len_field(1, &[0x02; 33], &mut init);
+        varint(2 << 3 | WIRE_VARINT, &mut init);
+        varint(300, &mut init);
+        // An unknown fixed64 field is skipped
+        varint(9 << 3 | WIRE_FIXED64, &mut init);
+        init.extend_from_slice(&[0; 8]);
+        let mut frame = Vec::new();
+        len_field(1, &init, &mut frame);
+
+        let decoded = decode_client_message(&frame).unwrap();
+        assert_eq!(decoded["init"]["receiver_id"], "02".repeat(33));
+        assert_eq!(decoded["init"]["start_message_id_exclusive"], "300");
+
+        let mut auth = Vec::new();
+        len_field(1, b"deadbeef", &mut auth);
+        len_field(2, b"challenge-1", &mut auth);
+        varint(3 << 3 | WIRE_VARINT, &mut auth);
+        varint(1_700_000_000, &mut auth);
+        len_field(5, b"bcrt1qexample", &mut auth);
+        let mut frame = Vec::new();
+        len_field(2, &auth, &mut frame);
+
+        let decoded = decode_client_message(&frame).unwrap();
+        assert_eq!(decoded["auth_sig"]["challenge_id"], "challenge-1");
+        assert_eq!(decoded["auth_sig"]["timestamp"], 1_700_000_000);
+        assert_eq!(decoded["auth_sig"]["address"], "bcrt1qexample");
+    }
+
+    #[test]
+    fn test_rejects_malformed_frames() {
+        // Length prefix longer than the frame
+        assert!(decode_client_message(&[0x0a, 0x05, 0x01]).is_err());
+        // Truncated varint
+        assert!(decode_client_message(&[0x10, 0x80]).is_err());
+        // Valid encoding but nothing the handler understands
+        assert!(decode_client_message(&[0x18, 0x01]).is_err());
+        assert!(decode_client_message(&[]).is_err());
+    }
+}
@@ -1,23 +1,56 @@
+pub mod accounts;
 pub mod addresses;
+pub mod admin;
+pub mod amounts;
 pub mod assets;
+pub mod attestations;
 pub mod burn;
 pub mod channels;
+pub mod escrows;
 pub mod events;
+pub mod faucet;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod groups;
 pub mod health;
+pub mod indexer;
 pub mod info;
+pub mod liquidity;
 pub mod mailbox;
 pub mod mailbox_auth;
+pub mod mailbox_proto;
+pub mod monitor;
+pub mod payment_requests;
 pub mod proofs;
+pub mod psbt_flows;
+pub mod public;
+pub mod rate_limit;
+pub mod receiver_keys;
+pub mod recurring_payments;
 pub mod rfq;
 pub mod routes;
 pub mod send;
+pub mod sessions;
+pub mod simulate;
 pub mod stop;
+pub mod supply;
+pub mod swaps;
 pub mod universe;
+pub mod usage;
+pub mod utxos;
 pub mod wallet;
+pub mod webhooks;
 
+use crate::backend::BackendClient;
+use crate::crypto::{canonical_json, SharedResponseSigner};
+use crate::database::SharedDatabase;
 use crate::error::AppError;
-use actix_web::http::StatusCode;
-use actix_web::HttpResponse;
+use crate::middleware::MountPath;
+use crate::types::{AssetId, GroupKey};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use base64::Engine;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
 
 pub fn validate_hex_param(value: &str) -> Result<(), AppError> {
     if value.is_empty()
@@ -55,46 +88,63 @@ pub fn validate_path_param(value: &str) -> Result<(), AppError> {
     Ok(())
 }
 
-const ASSET_ID_HEX_LEN: usize = 64;
-const GROUP_KEY_HEX_LENS: [usize; 2] = [64, 66];
-
 pub fn validate_asset_id(value: &str) -> Result<(), AppError> {
-    validate_hex_param(value)?;
-    if value.len() != ASSET_ID_HEX_LEN {
+    value.parse::<AssetId>().map(|_| ())
+}
+
+pub fn validate_group_key(value: &str) -> Result<(), AppError> {
+    value.parse::<GroupKey>().map(|_| ())
+}
+
+pub fn validate_integer_param(value: &str) -> Result<(), AppError> {
+    if value.parse::<u64>().is_err() {
         return Err(AppError::InvalidInput(format!(
-            "Invalid asset ID: expected {ASSET_ID_HEX_LEN} hex characters, got {}",
-            value.len()
+            "Invalid integer parameter: {}",
+            value
         )));
     }
     Ok(())
 }
 
-/// tapd accepts a group key as either a 32-byte x-only or a 33-byte
-/// compressed public key, so both hex lengths are valid.
-pub fn validate_group_key(value: &str) -> Result<(), AppError> {
-    validate_hex_param(value)?;
-    if !GROUP_KEY_HEX_LENS.contains(&value.len()) {
+const TAPROOT_ADDRESS_HRPS: [&str; 4] = ["tapbc", "taptb", "taprt", "tapsb"];
+
+/// Checks that a value looks like a Taproot Assets address on any network:
+/// a known human-readable part followed by bech32 data characters. The
+/// checksum is left to tapd.
+pub fn validate_taproot_address(value: &str) -> Result<(), AppError> {
+    const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    let valid = value.rsplit_once('1').is_some_and(|(hrp, data)| {
+        TAPROOT_ADDRESS_HRPS.contains(&hrp)
+            && data.len() >= 6
+            && data.chars().all(|c| BECH32_CHARSET.contains(c))
+    });
+    if !valid {
         return Err(AppError::InvalidInput(format!(
-            "Invalid group key: expected 64 or 66 hex characters, got {}",
-            value.len()
+            "Invalid Taproot Assets address: {value}"
         )));
     }
     Ok(())
 }
 
-pub fn validate_integer_param(value: &str) -> Result<(), AppError> {
-    if value.parse::<u64>().is_err() {
-        return Err(AppError::InvalidInput(format!(
-            "Invalid integer parameter: {}",
-            value
-        )));
+/// Callback URLs are supplied by API callers and later POSTed to by the
+/// gateway, so only absolute http(s) URLs are accepted.
+pub fn validate_callback_url(value: &str) -> Result<(), AppError> {
+    let valid = url::Url::parse(value)
+        .map(|u| matches!(u.scheme(), "http" | "https") && u.host().is_some())
+        .unwrap_or(false);
+    if !valid {
+        return Err(AppError::ValidationError(
+            "callback_url must be an absolute http(s) URL".to_string(),
+        ));
     }
     Ok(())
 }
 
 /// Appends the caller's query string to an upstream URL. tapd exposes filters,
 /// pagination and required parameters such as `group_by` this way, so dropping
-/// the query silently returns unfiltered results.
+/// the query silently returns unfiltered results. Api modules use
+/// [`crate::backend::BackendRequest::query`]; this remains for embedders.
+#[allow(dead_code)]
 pub fn with_query(mut url: String, query: &str) -> String {
     if !query.is_empty() {
         url.push('?');
@@ -103,6 +153,217 @@ pub fn with_query(mut url: String, query: &str) -> String {
     url
 }
 
+/// Page size used by list envelopes when the caller does not pass `limit`.
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+/// Upper bound on `limit` for list envelopes.
+pub const MAX_PAGE_SIZE: u32 = 500;
+/// Query parameters consumed by the gateway and never forwarded to tapd when a
+/// list envelope is requested.
+const PAGE_PARAMS: [&str; 3] = ["cursor", "limit", "envelope"];
+
+/// Uniform list response so SDKs need a single pagination abstraction,
+/// whatever scheme the backing store uses. `next_cursor` is opaque and absent
+/// on the last page; `total_estimate` is only set when the full result size is
+/// known cheaply. `next` is the absolute URL of the following page.
+#[derive(Debug, Serialize)]
+pub struct ListEnvelope<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub total_estimate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+/// `cursor`/`limit` parameters shared by every list envelope. Cursors encode
+/// an offset into the underlying listing, base64url so callers treat them as
+/// opaque.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct PageParams {
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+    pub envelope: Option<bool>,
+}
+
+impl PageParams {
+    pub fn from_query(query: &str) -> Result<Self, AppError> {
+        web::Query::<PageParams>::from_query(query)
+            .map(web::Query::into_inner)
+            .map_err(|e| AppError::InvalidInput(format!("Invalid pagination parameters: {e}")))
+    }
+
+    /// tapd passthrough listings keep their native shape unless the caller
+    /// opts in with `envelope=true` or by following a cursor.
+    pub fn wants_envelope(&self) -> bool {
+        self.envelope.unwrap_or(false) || self.cursor.is_some()
+    }
+
+    pub fn limit(&self) -> Result<u32, AppError> {
+        match self.limit {
+            None => Ok(DEFAULT_PAGE_SIZE),
+            Some(limit) if (1..=MAX_PAGE_SIZE).contains(&limit) => Ok(limit),
+            Some(_) => Err(AppError::InvalidInput(format!(
+                "limit must be between 1 and {MAX_PAGE_SIZE}"
+            ))),
+        }
+    }
+
+    pub fn offset(&self) -> Result<u32, AppError> {
+        let Some(cursor) = self.cursor.as_deref() else {
+            return Ok(0);
+        };
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .and_then(|s| s.strip_prefix("o:").and_then(|o| o.parse().ok()))
+            .ok_or_else(|| AppError::InvalidInput("Invalid cursor".to_string()))
+    }
+
+    /// The caller's query string minus the gateway's paging parameters, for
+    /// forwarding to tapd.
+    pub fn upstream_query(query: &str) -> String {
+        url::form_urlencoded::parse(query.as_bytes())
+            .filter(|(key, _)| !PAGE_PARAMS.contains(&key.as_ref()))
+            .fold(
+                url::form_urlencoded::Serializer::new(String::new()),
+                |mut out, (key, value)| {
+                    out.append_pair(&key, &value);
+                    out
+                },
+            )
+            .finish()
+    }
+}
+
+fn encode_cursor(offset: u32) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(format!("o:{offset}"))
+}
+
+impl<T> ListEnvelope<T> {
+    /// Builds a page from a backend queried with `limit + 1` rows at `offset`;
+    /// the extra row only signals that another page exists.
+    pub fn from_offset_page(mut items: Vec<T>, offset: u32, limit: u32) -> Self {
+        let has_more = items.len() > limit as usize;
+        items.truncate(limit as usize);
+        let next_cursor = has_more.then(|| encode_cursor(offset + limit));
+        ListEnvelope {
+            items,
+            next_cursor,
+            total_estimate: None,
+            next: None,
+        }
+    }
+
+    /// Sets `next` to the URL of the following page: the request's own URL
+    /// with its `cursor` replaced.
+    pub fn with_next_link(mut self, req: &HttpRequest) -> Self {
+        self.next = self.next_cursor.as_deref().map(|cursor| {
+            let mut query = url::form_urlencoded::Serializer::new(String::new());
+            for (key, value) in url::form_urlencoded::parse(req.query_string().as_bytes()) {
+                if key != "cursor" {
+                    query.append_pair(&key, &value);
+                }
+            }
+            query.append_pair("cursor", cursor);
+            public_url(req, &format!("{}?{}", req.path(), query.finish()))
+        });
+        self
+    }
+
+    /// Pages a listing the backend returns in full (tapd list calls without
+    /// native paging), so the total is exact.
+    pub fn from_full_list(items: Vec<T>, params: &PageParams) -> Result<Self, AppError> {
+        let offset = params.offset()?;
+        let limit = params.limit()?;
+        let total = items.len() as u64;
+        let page = items
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize + 1)
+            .collect();
+        let mut envelope = Self::from_offset_page(page, offset, limit);
+        envelope.total_estimate = Some(total);
+        Ok(envelope)
+    }
+}
+
+/// Absolute URL of a gateway path as the client addressed it. Scheme and
+/// host honor `Forwarded`/`X-Forwarded-Proto`/`X-Forwarded-Host`, and the
+/// path is prefixed with `X-Forwarded-Prefix` (the part a path-routing proxy
+/// stripped) and the gateway's own `BASE_PATH`. The headers only shape links
+/// returned to the caller that sent them, so they are not authenticated.
+pub fn public_url(req: &HttpRequest, path: &str) -> String {
+    let info = req.connection_info();
+    let forwarded_prefix = req
+        .headers()
+        .get("X-Forwarded-Prefix")
+        .and_then(|v| v.to_str().ok())
+        .map(|p| p.trim_end_matches('/'))
+        .filter(|p| {
+            p.starts_with('/')
+                && p.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "/-._~".contains(c))
+        })
+        .unwrap_or("");
+    let mount = req
+        .extensions()
+        .get::<MountPath>()
+        .map(|m| m.0.clone())
+        .unwrap_or_default();
+    format!(
+        "{}://{}{forwarded_prefix}{mount}{path}",
+        info.scheme(),
+        info.host()
+    )
+}
+
+/// Splits a passthrough list request into the gateway's paging parameters and
+/// the query string to forward to tapd.
+pub fn split_list_query(query: &str) -> Result<(PageParams, String), AppError> {
+    let page = PageParams::from_query(query)?;
+    let upstream = if page.wants_envelope() {
+        PageParams::upstream_query(query)
+    } else {
+        query.to_string()
+    };
+    Ok((page, upstream))
+}
+
+/// Responds with a tapd listing, converted to a [`ListEnvelope`] over the
+/// array under `field` when the caller asked for one.
+pub fn list_response(
+    req: &HttpRequest,
+    params: &PageParams,
+    result: Result<serde_json::Value, AppError>,
+    field: &str,
+) -> HttpResponse {
+    if !params.wants_envelope() {
+        return handle_result(result);
+    }
+    handle_result(result.and_then(|mut value| {
+        let items = match value.get_mut(field).map(serde_json::Value::take) {
+            Some(serde_json::Value::Array(items)) => items,
+            _ => Vec::new(),
+        };
+        ListEnvelope::from_full_list(items, params).map(|e| e.with_next_link(req))
+    }))
+}
+
+/// Returns the gateway database, which is only registered when DATABASE_URL is
+/// set. Gateway-owned endpoints that need persistence answer 503 without it.
+pub fn require_database(req: &HttpRequest) -> Result<SharedDatabase, AppError> {
+    req.app_data::<web::Data<SharedDatabase>>()
+        .map(|db| db.get_ref().clone())
+        .ok_or_else(|| {
+            AppError::ServiceUnavailable("Gateway database is not configured".to_string())
+        })
+}
+
+/// A [`BackendClient`] for the tapd the handlers were configured with.
+pub fn backend(client: &Client, base_url: &str, macaroon_hex: &str) -> BackendClient {
+    BackendClient::new(client.clone(), base_url, macaroon_hex)
+}
+
 /// Deserializes a tapd response, surfacing non-2xx statuses as errors instead
 /// of relaying the upstream error body with a 200.
 pub async fn parse_upstream<T: serde::de::DeserializeOwned>(
@@ -119,17 +380,78 @@ pub async fn parse_upstream<T: serde::de::DeserializeOwned>(
     response.json::<T>().await.map_err(AppError::RequestError)
 }
 
+/// Relays a successful tapd answer byte for byte: the body streams from the
+/// upstream connection straight into the response with tapd's content type
+/// and length, so multi-megabyte proofs and lists are never parsed or held
+/// whole. Non-2xx answers become [`AppError::UpstreamError`] as in
+/// [`parse_upstream`].
+///
+/// The response middlewares that rewrite bodies (redaction, field case,
+/// version translation and shadow traffic) still buffer it when they apply
+/// to the request; otherwise they hand the stream on untouched.
+pub async fn relay(response: Result<reqwest::Response, AppError>) -> HttpResponse {
+    let response = match response {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            let status = response.status().as_u16();
+            let body = response.text().await.unwrap_or_default();
+            return handle_result::<()>(Err(AppError::UpstreamError { status, body }));
+        }
+        Err(e) => return handle_result::<()>(Err(e)),
+    };
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/json")
+        .to_string();
+    let mut builder = HttpResponse::Ok();
+    builder.content_type(content_type);
+    if let Some(length) = response.content_length() {
+        builder.no_chunking(length);
+    }
+    builder.streaming(response.bytes_stream())
+}
+
+/// Detached signature headers set by [`signed_result`].
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+pub const SIGNATURE_KEY_HEADER: &str = "X-Signature-Key";
+/// Id of the signing key, as listed at `/v1/gateway/signing-keys`.
+pub const SIGNATURE_KEY_ID_HEADER: &str = "X-Signature-Key-Id";
+
+/// Like [`handle_result`], but when response signing is configured the body
+/// is sent as canonical JSON with a Schnorr signature over it in
+/// `X-Signature`, and the signing key and its id in `X-Signature-Key` and
+/// `X-Signature-Key-Id`. Errors are not signed.
+pub fn signed_result<T: serde::Serialize>(
+    req: &HttpRequest,
+    result: Result<T, AppError>,
+) -> HttpResponse {
+    let Some(signer) = req.app_data::<web::Data<SharedResponseSigner>>() else {
+        return handle_result(result);
+    };
+    let value = match result.and_then(|v| {
+        serde_json::to_value(v).map_err(|e| AppError::SerializationError(e.to_string()))
+    }) {
+        Ok(value) => value,
+        Err(e) => return handle_result::<()>(Err(e)),
+    };
+    let body = canonical_json(&value);
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((SIGNATURE_HEADER, signer.sign(&body)))
+        .insert_header((SIGNATURE_KEY_HEADER, signer.public_key_hex()))
+        .insert_header((SIGNATURE_KEY_ID_HEADER, signer.kid()))
+        .body(body)
+}
+
 pub fn handle_result<T: serde::Serialize>(result: Result<T, AppError>) -> HttpResponse {
     match result {
         Ok(value) => HttpResponse::Ok().json(value),
         // Relay tapd's own error document unchanged so callers keep reading the
         // `code`/`message` fields it defines; only the status is corrected.
         Err(AppError::UpstreamError { status, body }) => {
-            let status = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY);
-            match serde_json::from_str::<serde_json::Value>(&body) {
-                Ok(json) => HttpResponse::build(status).json(json),
-                Err(_) => HttpResponse::build(status).json(serde_json::json!({ "error": body })),
-            }
+            crate::error::upstream_response(status, &body)
         }
         Err(e) => {
             let status = e.status_code();
@@ -143,6 +465,7 @@ pub fn handle_result<T: serde::Serialize>(result: Result<T, AppError>) -> HttpRe
 #[cfg(test)]
 mod tests {
     use super::*;
+    use actix_web::http::StatusCode;
 
     fn hex_of(len: usize) -> String {
         "a".repeat(len)
@@ -171,6 +494,16 @@ mod tests {
         assert!(validate_group_key("%2e%2e%2fadmin").is_err());
     }
 
+    #[test]
+    fn test_validate_taproot_address_checks_hrp_and_charset() {
+        assert!(validate_taproot_address("taprt1qqqsqqspqqzzpe6l6jwkmqnfcq").is_ok());
+        assert!(validate_taproot_address("tapbc1qqqsqqspqqzzpe6l6jwkmqnfcq").is_ok());
+        assert!(validate_taproot_address("bc1qqqsqqspqqzzpe6l6jwkmqnfcq").is_err());
+        assert!(validate_taproot_address("TAPRT1QQQSQQSPQQZZPE6L6JWKMQNFCQ").is_err());
+        assert!(validate_taproot_address("taprt1qqqb../admin").is_err());
+        assert!(validate_taproot_address("taprt1").is_err());
+    }
+
     #[test]
     fn test_validate_hex_param_rejects_non_hex_and_traversal() {
         assert!(validate_hex_param(&hex_of(64)).is_ok());
@@ -229,4 +562,185 @@ mod tests {
         assert_eq!(resp.status(), StatusCode::OK);
         assert_eq!(body_of(resp).await["ok"], true);
     }
+
+    #[test]
+    fn test_full_list_envelope_pages_with_opaque_cursor() {
+        let page = PageParams::from_query("limit=2").unwrap();
+        let first = ListEnvelope::from_full_list(vec![1, 2, 3, 4, 5], &page).unwrap();
+        assert_eq!(first.items, vec![1, 2]);
+        assert_eq!(first.total_estimate, Some(5));
+        let cursor = first.next_cursor.expect("more pages");
+
+        let page = PageParams::from_query(&format!("limit=2&cursor={cursor}")).unwrap();
+        let second = ListEnvelope::from_full_list(vec![1, 2, 3, 4, 5], &page).unwrap();
+        assert_eq!(second.items, vec![3, 4]);
+
+        let page =
+            PageParams::from_query(&format!("limit=2&cursor={}", second.next_cursor.unwrap()))
+                .unwrap();
+        let last = ListEnvelope::from_full_list(vec![1, 2, 3, 4, 5], &page).unwrap();
+        assert_eq!(last.items, vec![5]);
+        assert!(last.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_offset_page_uses_probe_row() {
+        let page = ListEnvelope::from_offset_page(vec!["a", "b", "c"], 10, 2);
+        assert_eq!(page.items, vec!["a", "b"]);
+        assert!(page.next_cursor.is_some());
+        assert!(page.total_estimate.is_none());
+        let page = ListEnvelope::from_offset_page(vec!["a", "b"], 10, 2);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_page_params_reject_bad_cursor_and_limit() {
+        assert!(PageParams::from_query("cursor=bm9wZQ")
+            .unwrap()
+            .offset()
+            .is_err());
+        assert!(PageParams::from_query("limit=0").unwrap().limit().is_err());
+        assert!(PageParams::from_query("limit=501")
+            .unwrap()
+            .limit()
+            .is_err());
+        assert!(PageParams::from_query("limit=abc").is_err());
+    }
+
+    #[test]
+    fn test_split_list_query_only_strips_when_enveloped() {
+        let (page, upstream) = split_list_query("limit=5&anchor_txid=ab").unwrap();
+        assert!(!page.wants_envelope());
+        assert_eq!(upstream, "limit=5&anchor_txid=ab");
+
+        let (page, upstream) = split_list_query("envelope=true&limit=5&anchor_txid=ab").unwrap();
+        assert!(page.wants_envelope());
+        assert_eq!(upstream, "anchor_txid=ab");
+    }
+
+    #[actix_rt::test]
+    async fn test_list_response_wraps_field_in_envelope() {
+        let value = serde_json::json!({ "burns": [{"id": 1}, {"id": 2}] });
+        let req = actix_web::test::TestRequest::get()
+            .uri("/v1/taproot-assets/burns?envelope=true&limit=1")
+            .to_http_request();
+        let page = PageParams::from_query(req.query_string()).unwrap();
+        let body = body_of(list_response(&req, &page, Ok(value.clone()), "burns")).await;
+        assert_eq!(body["items"], serde_json::json!([{"id": 1}]));
+        assert_eq!(body["total_estimate"], 2);
+        let cursor = body["next_cursor"].as_str().unwrap();
+        assert_eq!(
+            body["next"],
+            format!("http://localhost:8080/v1/taproot-assets/burns?envelope=true&limit=1&cursor={cursor}")
+        );
+
+        let page = PageParams::default();
+        let body = body_of(list_response(&req, &page, Ok(value.clone()), "burns")).await;
+        assert_eq!(body, value);
+    }
+
+    #[test]
+    fn test_public_url_honors_forwarded_headers_and_base_path() {
+        let req = actix_web::test::TestRequest::get()
+            .uri("/v1/gateway/transfers")
+            .insert_header(("X-Forwarded-Proto", "https"))
+            .insert_header(("X-Forwarded-Host", "api.example.com"))
+            .insert_header(("X-Forwarded-Prefix", "/edge/"))
+            .to_http_request();
+        req.extensions_mut()
+            .insert(MountPath("/taproot".to_string()));
+        assert_eq!(
+            public_url(&req, "/v1/gateway/transfers?cursor=x"),
+            "https://api.example.com/edge/taproot/v1/gateway/transfers?cursor=x"
+        );
+
+        let req = actix_web::test::TestRequest::get()
+            .insert_header(("X-Forwarded-Prefix", "/\"><script>"))
+            .to_http_request();
+        assert_eq!(public_url(&req, "/health"), "http://localhost:8080/health");
+    }
+
+    #[actix_rt::test]
+    async fn test_signed_result_signs_canonical_body() {
+        let signer: SharedResponseSigner =
+            std::sync::Arc::new(crate::crypto::ResponseSigner::from_hex(&"02".repeat(32)).unwrap());
+        let req = actix_web::test::TestRequest::post()
+            .app_data(web::Data::new(signer.clone()))
+            .to_http_request();
+        let res = signed_result(&req, Ok(serde_json::json!({"valid": true, "asset": "a"})));
+        let signature = res
+            .headers()
+            .get(SIGNATURE_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            res.headers().get(SIGNATURE_KEY_HEADER).unwrap(),
+            signer.public_key_hex().as_str()
+        );
+        assert_eq!(
+            res.headers().get(SIGNATURE_KEY_ID_HEADER).unwrap(),
+            signer.kid()
+        );
+        let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert_eq!(body, r#"{"asset":"a","valid":true}"#);
+        assert!(crate::crypto::verify_schnorr_signature(
+            body,
+            &signature,
+            &signer.public_key_hex()
+        )
+        .unwrap());
+
+        let unsigned = actix_web::test::TestRequest::post().to_http_request();
+        let res = signed_result(&unsigned, Ok(serde_json::json!({})));
+        assert!(!res.headers().contains_key(SIGNATURE_HEADER));
+    }
+
+    #[actix_rt::test]
+    async fn test_relay_passes_bytes_through_and_maps_errors() {
+        use actix_web::{App, HttpServer};
+        let server = HttpServer::new(|| {
+            App::new()
+                .route(
+                    "/ok",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("application/json")
+                            .body(r#"{"b": 1,  "a": 2}"#)
+                    }),
+                )
+                .route(
+                    "/fail",
+                    web::get().to(|| async {
+                        HttpResponse::NotFound().body(r#"{"code":5,"message":"no leaves"}"#)
+                    }),
+                )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_rt::spawn(server);
+
+        let client = reqwest::Client::new();
+        let base_url = format!("http://{addr}");
+        let res = relay(backend(&client, &base_url, "00").get("/ok").send().await).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+        let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+        // Untouched: neither re-ordered nor re-serialized
+        assert_eq!(&body[..], br#"{"b": 1,  "a": 2}"#);
+
+        let res = relay(backend(&client, &base_url, "00").get("/fail").send().await).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body_of(res).await["message"], "no leaves");
+        handle.stop(false).await;
+    }
 }
@@ -0,0 +1,182 @@
+use super::handle_result;
+use crate::backend;
+use crate::cache_audit::{AuditReport, SharedCacheAudit};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::latency::{self, BUCKETS_MS};
+use crate::lifetime_stats::{boot_counters, SharedLifetimeStats};
+use crate::monitor::{prometheus_metrics, SharedMonitor, TOPIC_SNAPSHOT};
+use crate::priority::{PrioritySnapshot, SharedPriorityLimiter};
+use crate::proof_cache;
+use crate::webhooks;
+use crate::websocket::connection_manager::WebSocketConnectionManager;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+fn monitor(req: &HttpRequest) -> Result<SharedMonitor, AppError> {
+    req.app_data::<web::Data<SharedMonitor>>()
+        .map(|m| m.get_ref().clone())
+        .ok_or_else(|| AppError::ServiceUnavailable("Monitoring is not configured".to_string()))
+}
+
+/// The latest snapshot, for callers that poll.
+async fn snapshot(req: HttpRequest) -> HttpResponse {
+    let result = monitor(&req).and_then(|m| {
+        m.latest().ok_or_else(|| {
+            AppError::ServiceUnavailable("No monitor snapshot taken yet".to_string())
+        })
+    });
+    handle_result(result)
+}
+
+fn priority_snapshot(req: &HttpRequest) -> Option<PrioritySnapshot> {
+    req.app_data::<web::Data<SharedPriorityLimiter>>()
+        .map(|limiter| limiter.snapshot())
+}
+
+fn audit_report(req: &HttpRequest) -> Option<AuditReport> {
+    req.app_data::<web::Data<SharedCacheAudit>>()
+        .map(|audit| audit.report())
+}
+
+/// Concurrency slots and per-class queue depths.
+async fn priority(req: HttpRequest) -> HttpResponse {
+    let result = priority_snapshot(&req).ok_or_else(|| {
+        AppError::ServiceUnavailable("MAX_CONCURRENT_REQUESTS is not set".to_string())
+    });
+    handle_result(result)
+}
+
+/// Prometheus scrape target.
+async fn metrics(req: HttpRequest) -> HttpResponse {
+    let Some(manager) = req.app_data::<web::Data<Arc<WebSocketConnectionManager>>>() else {
+        return handle_result::<()>(Err(AppError::ServiceUnavailable(
+            "Metrics are not configured".to_string(),
+        )));
+    };
+    let stats = req
+        .app_data::<web::Data<SharedMonitor>>()
+        .map(|m| m.stats());
+    let websockets = manager.introspect().await;
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(prometheus_metrics(
+            stats.as_deref(),
+            &backend::stats().snapshot(),
+            &proof_cache::stats().snapshot(),
+            &webhooks::stats().snapshot(),
+            &websockets,
+            priority_snapshot(&req).as_ref(),
+            &latency::histograms().snapshot(),
+            &audit_report(&req)
+                .map(|report| report.caches)
+                .unwrap_or_default(),
+        ))
+}
+
+/// Backend latency per tapd endpoint, bucketed as a heatmap.
+async fn latency_heatmap() -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "buckets_ms": BUCKETS_MS,
+        "endpoints": latency::histograms().snapshot(),
+    }))
+}
+
+/// Outcomes of the sampled cache audit, and its latest divergences.
+async fn cache_audit_report(req: HttpRequest) -> HttpResponse {
+    let result = audit_report(&req)
+        .ok_or_else(|| AppError::ServiceUnavailable("Cache audit is not configured".to_string()));
+    handle_result(result)
+}
+
+/// Key counters since boot and, with a database, since install.
+async fn stats(req: HttpRequest) -> HttpResponse {
+    let result = async {
+        let monitor = monitor(&req)?;
+        let manager = req
+            .app_data::<web::Data<Arc<WebSocketConnectionManager>>>()
+            .ok_or_else(|| {
+                AppError::ServiceUnavailable("Monitoring is not configured".to_string())
+            })?;
+        let since_boot = boot_counters(&monitor.stats(), &manager.introspect().await);
+        let since_install = match req.app_data::<web::Data<SharedLifetimeStats>>() {
+            Some(lifetime) => {
+                let totals = lifetime.totals(&since_boot).await?;
+                Some(json!({ "since": totals.since, "counters": totals.values }))
+            }
+            None => None,
+        };
+        Ok(json!({
+            "uptime_secs": monitor.uptime().as_secs(),
+            "since_boot": since_boot,
+            "since_install": since_install,
+        }))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Pushes every monitor snapshot as a JSON text frame, starting with the
+/// latest one so dashboards render immediately.
+async fn monitor_ws(
+    req: HttpRequest,
+    stream: web::Payload,
+    bus: web::Data<SharedEventBus>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let latest = monitor(&req)?.latest();
+    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+    let mut events = bus.subscribe();
+
+    actix_web::rt::spawn(async move {
+        if let Some(text) = latest.and_then(|s| serde_json::to_string(&s).ok()) {
+            if session.text(text).await.is_err() {
+                return;
+            }
+        }
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) if event.topic == TOPIC_SNAPSHOT => {
+                        let Ok(text) = serde_json::to_string(&event.data) else {
+                            continue;
+                        };
+                        if session.text(text).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Monitor subscriber lagged, skipped {} events", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                msg = msg_stream.next() => match msg {
+                    Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/monitor").route(web::get().to(snapshot)))
+        .service(web::resource("/monitor/ws").route(web::get().to(monitor_ws)))
+        .service(web::resource("/monitor/priority").route(web::get().to(priority)))
+        .service(web::resource("/monitor/latency").route(web::get().to(latency_heatmap)))
+        .service(web::resource("/monitor/cache-audit").route(web::get().to(cache_audit_report)))
+        .service(web::resource("/stats").route(web::get().to(stats)))
+        .service(web::resource("/metrics").route(web::get().to(metrics)));
+}
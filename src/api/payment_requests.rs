@@ -0,0 +1,245 @@
+use super::{
+    addresses, handle_result, public_url, require_database, validate_asset_id,
+    validate_callback_url, ListEnvelope, PageParams,
+};
+use crate::config::Config;
+use crate::database::{PaymentRequest, PaymentRequestQuery, PaymentStatus, SharedDatabase};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::indexer::ReceivePolicy;
+use crate::payment_requests::PaymentRequestTracker;
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::{info, instrument};
+
+/// Longest TTL a payment request may be created with (7 days)
+const MAX_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePaymentRequest {
+    pub asset_id: String,
+    pub amount: u64,
+    /// Defaults to `PAYMENT_REQUEST_DEFAULT_TTL_SECS`.
+    pub ttl_secs: Option<u64>,
+    pub callback_url: Option<String>,
+    pub callback_secret: Option<String>,
+    /// Opaque caller data (order ids, ...) echoed back unchanged.
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl CreatePaymentRequest {
+    fn validate(&self) -> Result<(), AppError> {
+        validate_asset_id(&self.asset_id)?;
+        if self.amount == 0 {
+            return Err(AppError::ValidationError(
+                "amount must be greater than zero".to_string(),
+            ));
+        }
+        if let Some(ttl) = self.ttl_secs {
+            if ttl == 0 || ttl > MAX_TTL_SECS {
+                return Err(AppError::ValidationError(format!(
+                    "ttl_secs must be between 1 and {MAX_TTL_SECS}"
+                )));
+            }
+        }
+        match (&self.callback_url, &self.callback_secret) {
+            (Some(url), _) => validate_callback_url(url),
+            (None, Some(_)) => Err(AppError::ValidationError(
+                "callback_secret requires callback_url".to_string(),
+            )),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+fn require_indexer(req: &HttpRequest, config: &Config) -> Result<SharedDatabase, AppError> {
+    if !config.indexer_enabled {
+        return Err(AppError::ServiceUnavailable(
+            "Payment requests require INDEXER_ENABLED".to_string(),
+        ));
+    }
+    require_database(req)
+}
+
+#[instrument(skip(req, client, macaroon_hex, config, request))]
+async fn create_payment_request(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    config: &Config,
+    request: CreatePaymentRequest,
+) -> Result<PaymentRequest, AppError> {
+    request.validate()?;
+    let database = require_indexer(req, config)?;
+
+    let addr = addresses::create_address(
+        client,
+        base_url,
+        macaroon_hex,
+        addresses::NewAddrRequest {
+            asset_id: request.asset_id.clone(),
+            amt: request.amount.to_string(),
+            script_key: None,
+            internal_key: None,
+            tapscript_sibling: None,
+            proof_courier_addr: None,
+            asset_version: None,
+            address_version: None,
+            callback_url: None,
+            callback_secret: None,
+            ttl_secs: None,
+        },
+    )
+    .await?;
+    let address = addr.encoded.ok_or_else(|| {
+        AppError::SerializationError("tapd returned an address without encoding".to_string())
+    })?;
+
+    let now = chrono::Utc::now().timestamp();
+    let ttl = request
+        .ttl_secs
+        .unwrap_or(config.payment_request_default_ttl_secs);
+    let payment_request = PaymentRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        asset_id: request.asset_id.to_ascii_lowercase(),
+        amount: request.amount,
+        address,
+        status: PaymentStatus::Pending,
+        received: 0,
+        unconfirmed: 0,
+        expires_at: now + ttl as i64,
+        callback_url: request.callback_url,
+        callback_secret: request.callback_secret,
+        metadata: request.metadata,
+        created_at: now,
+        updated_at: now,
+    };
+    database.insert_payment_request(&payment_request).await?;
+    info!(
+        "Created payment request {} for {} units",
+        payment_request.id, payment_request.amount
+    );
+    Ok(payment_request)
+}
+
+async fn create(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    body: web::Json<CreatePaymentRequest>,
+) -> HttpResponse {
+    match create_payment_request(
+        &req,
+        client.as_ref(),
+        &base_url.0,
+        &macaroon_hex.0,
+        config.as_ref(),
+        body.into_inner(),
+    )
+    .await
+    {
+        Ok(request) => HttpResponse::build(StatusCode::CREATED)
+            .insert_header((
+                header::LOCATION,
+                public_url(
+                    &req,
+                    &format!("/v1/gateway/payment-requests/{}", request.id),
+                ),
+            ))
+            .json(request),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
+}
+
+async fn list(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    query: web::Query<PaymentRequestQuery>,
+) -> HttpResponse {
+    let result = async {
+        let database = require_indexer(&req, &config)?;
+        let page = PageParams::from_query(req.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let query = PaymentRequestQuery {
+            limit: Some(limit + 1),
+            offset: Some(offset),
+            ..query.into_inner()
+        };
+        let requests = database.list_payment_requests(&query).await?;
+        Ok(ListEnvelope::from_offset_page(requests, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Returns the request re-evaluated against the index, so polling sees expiry
+/// and new payments without waiting for the background tracker.
+async fn get(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    events: web::Data<SharedEventBus>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let result = async {
+        let database = require_indexer(&req, &config)?;
+        let id = path.into_inner();
+        let request = database
+            .get_payment_request(&id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Payment request {id} not found")))?;
+        let tracker = PaymentRequestTracker::new(
+            database,
+            events.get_ref().clone(),
+            ReceivePolicy::new(config.min_receive_confirmations),
+        );
+        tracker.refresh(request).await
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/payment-requests")
+            .route(web::get().to(list))
+            .route(web::post().to(create)),
+    )
+    .service(web::resource("/payment-requests/{id}").route(web::get().to(get)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(amount: u64, ttl: Option<u64>) -> CreatePaymentRequest {
+        CreatePaymentRequest {
+            asset_id: "a".repeat(64),
+            amount,
+            ttl_secs: ttl,
+            callback_url: None,
+            callback_secret: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_amount_and_ttl() {
+        assert!(request(10, Some(60)).validate().is_ok());
+        assert!(request(0, None).validate().is_err());
+        assert!(request(10, Some(0)).validate().is_err());
+        assert!(request(10, Some(MAX_TTL_SECS + 1)).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_secret_without_callback() {
+        let mut req = request(10, None);
+        req.callback_secret = Some("k".to_string());
+        assert!(req.validate().is_err());
+    }
+}
@@ -1,10 +1,23 @@
-use super::{handle_result, parse_upstream};
+use super::{backend, handle_result, public_url, relay, require_database, signed_result};
+use crate::backend::BackendRequest;
+use crate::config::Config;
+use crate::crypto::SharedResponseSigner;
+use crate::database::{Database, ProofFile, SharedDatabase};
 use crate::error::AppError;
-use crate::types::{BaseUrl, MacaroonHex};
-use actix_web::{web, HttpResponse};
+use crate::proof_cache;
+use crate::types::{BaseUrl, MacaroonHex, Outpoint};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::Engine;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use tracing::{info, instrument};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tracing::{debug, info, instrument};
+
+/// Where stored proof files are downloaded from, followed by the file hash.
+/// Role API keys are refused there: raw proof bytes cannot be redacted.
+pub const PROOF_FILES_PATH: &str = "/v1/gateway/proofs/files";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DecodeProofRequest {
@@ -40,15 +53,23 @@ pub async fn decode_proof(
     request: DecodeProofRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Decoding proof");
-    let url = format!("{base_url}/v1/taproot-assets/proofs/decode");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/proofs/decode")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
+}
+
+fn export_request(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    request: ExportProofRequest,
+) -> BackendRequest {
+    info!("Exporting proof for asset ID: {}", request.asset_id);
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/proofs/export")
+        .json(&request)
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -58,35 +79,21 @@ pub async fn export_proof(
     macaroon_hex: &str,
     request: ExportProofRequest,
 ) -> Result<serde_json::Value, AppError> {
-    info!("Exporting proof for asset ID: {}", request.asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/proofs/export");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .json(&request)
-        .send()
+    export_request(client, base_url, macaroon_hex, request)
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
-#[instrument(skip(client, macaroon_hex, request))]
-pub async fn unpack_proof_file(
+pub fn unpack_file_request(
     client: &Client,
     base_url: &str,
     macaroon_hex: &str,
     request: UnpackFileRequest,
-) -> Result<serde_json::Value, AppError> {
+) -> BackendRequest {
     info!("Unpacking proof file");
-    let url = format!("{base_url}/v1/taproot-assets/proofs/unpack-file");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/proofs/unpack-file")
         .json(&request)
-        .send()
-        .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -100,48 +107,81 @@ pub async fn verify_proof(
         "Verifying proof with genesis point: {}",
         request.genesis_point
     );
-    let url = format!("{base_url}/v1/taproot-assets/proofs/verify");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/proofs/verify")
         .json(&request)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
+}
+
+/// Proof cache lifetime and the database holding it, if caching is on.
+fn proof_cache(req: &HttpRequest) -> (Option<&Database>, Duration) {
+    let database = req
+        .app_data::<web::Data<SharedDatabase>>()
+        .map(|db| db.get_ref().as_ref());
+    let ttl = req
+        .app_data::<web::Data<Config>>()
+        .map_or(Duration::ZERO, |c| {
+            Duration::from_secs(c.proof_cache_ttl_secs)
+        });
+    (database, ttl)
 }
 
 async fn decode(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<DecodeProofRequest>,
 ) -> HttpResponse {
+    let request = req.into_inner();
+    let (database, ttl) = proof_cache(&http_req);
+    let operation = format!(
+        "decode:{}:{}:{}",
+        request.proof_at_depth.unwrap_or(0),
+        request.with_prev_witnesses,
+        request.with_meta_reveal
+    );
+    let raw_proof = request.raw_proof.clone();
     handle_result(
-        decode_proof(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
+        proof_cache::cached_or_fetch(
+            database,
+            ttl,
+            &raw_proof,
+            &operation,
+            decode_proof(client.as_ref(), &base_url.0, &macaroon_hex.0, request),
         )
         .await,
     )
 }
 
 async fn export(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<ExportProofRequest>,
 ) -> HttpResponse {
-    handle_result(
-        export_proof(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
+    let mut request = req.into_inner();
+    request.outpoint = match Outpoint::normalize(&request.outpoint) {
+        Ok(outpoint) => outpoint,
+        Err(e) => return handle_result::<serde_json::Value>(Err(e)),
+    };
+    // Signing needs the parsed document; unsigned exports pass straight through
+    if http_req
+        .app_data::<web::Data<SharedResponseSigner>>()
+        .is_none()
+    {
+        return relay(
+            export_request(client.as_ref(), &base_url.0, &macaroon_hex.0, request)
+                .send()
+                .await,
         )
-        .await,
+        .await;
+    }
+    signed_result(
+        &http_req,
+        export_proof(client.as_ref(), &base_url.0, &macaroon_hex.0, request).await,
     )
 }
 
@@ -151,37 +191,315 @@ async fn unpack_file(
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<UnpackFileRequest>,
 ) -> HttpResponse {
-    handle_result(
-        unpack_proof_file(
+    relay(
+        unpack_file_request(
             client.as_ref(),
             &base_url.0,
             &macaroon_hex.0,
             req.into_inner(),
         )
+        .send()
         .await,
     )
+    .await
 }
 
 async fn verify(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<VerifyProofRequest>,
 ) -> HttpResponse {
-    handle_result(
-        verify_proof(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
+    let request = req.into_inner();
+    let (database, ttl) = proof_cache(&http_req);
+    let operation = format!("verify:{}", request.genesis_point);
+    let raw_proof = request.raw_proof_file.clone();
+    signed_result(
+        &http_req,
+        proof_cache::cached_or_fetch(
+            database,
+            ttl,
+            &raw_proof,
+            &operation,
+            verify_proof(client.as_ref(), &base_url.0, &macaroon_hex.0, request),
         )
         .await,
     )
 }
 
+/// The database holding exported proof files and the oldest creation time
+/// still served.
+fn proof_files(req: &HttpRequest) -> Result<(SharedDatabase, i64), AppError> {
+    let ttl = req
+        .app_data::<web::Data<Config>>()
+        .map_or(0, |c| c.proof_file_ttl_secs);
+    if ttl == 0 {
+        return Err(AppError::ServiceUnavailable(
+            "Proof file downloads are disabled; set PROOF_FILE_TTL_SECS".to_string(),
+        ));
+    }
+    let database = require_database(req)?;
+    Ok((database, chrono::Utc::now().timestamp() - ttl as i64))
+}
+
+/// Exports a proof file once and stores it, returning where to download it.
+/// Asking again for the same proof reuses the stored file, so a client that
+/// lost its download can resume against the same hash.
+async fn prepare_download(
+    http_req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    req: web::Json<ExportProofRequest>,
+) -> HttpResponse {
+    let result = async {
+        let (database, not_before) = proof_files(&http_req)?;
+        let mut request = req.into_inner();
+        let anchor = Outpoint::from_any(&request.outpoint)?;
+        request.outpoint = anchor.to_tapd();
+        let outpoint = anchor.to_string();
+        let stored = database
+            .exported_proof_file(
+                &request.asset_id,
+                &request.script_key,
+                &outpoint,
+                not_before,
+            )
+            .await?;
+        let file = match stored {
+            Some(file) => file,
+            None => {
+                let asset_id = request.asset_id.clone();
+                let script_key = request.script_key.clone();
+                let exported =
+                    export_proof(client.as_ref(), &base_url.0, &macaroon_hex.0, request).await?;
+                let data = exported["raw_proof_file"]
+                    .as_str()
+                    .and_then(|raw| base64::engine::general_purpose::STANDARD.decode(raw).ok())
+                    .ok_or_else(|| {
+                        AppError::SerializationError(
+                            "tapd export response has no base64 raw_proof_file".to_string(),
+                        )
+                    })?;
+                let file = ProofFile {
+                    file_hash: hex::encode(Sha256::digest(&data)),
+                    asset_id,
+                    script_key,
+                    outpoint,
+                    size: data.len() as u64,
+                    created_at: chrono::Utc::now().timestamp(),
+                };
+                database.store_proof_file(&file, &data, not_before).await?;
+                info!("Stored proof file {} ({} bytes)", file.file_hash, file.size);
+                file
+            }
+        };
+        let download_url = public_url(&http_req, &format!("{PROOF_FILES_PATH}/{}", file.file_hash));
+        let mut body = serde_json::to_value(&file)?;
+        body["download_url"] = serde_json::Value::String(download_url);
+        Ok(body)
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Which part of a file a `Range` header asks for.
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    Full,
+    /// Inclusive byte offsets.
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Interprets a single `bytes=` range against a file of `size` bytes.
+/// Other units, multiple ranges and malformed headers get the full file,
+/// which RFC 9110 allows.
+fn byte_range(range: Option<&str>, size: u64) -> ByteRange {
+    let Some(spec) = range.and_then(|r| r.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    let Some((first, last)) = spec.trim().split_once('-').filter(|_| !spec.contains(',')) else {
+        return ByteRange::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let parse = |s: &str| s.parse::<u64>().ok();
+    match (first.is_empty(), last.is_empty()) {
+        // bytes=-N: the last N bytes
+        (true, false) => match parse(last) {
+            Some(0) => ByteRange::Unsatisfiable,
+            Some(_) if size == 0 => ByteRange::Unsatisfiable,
+            Some(n) => ByteRange::Partial(size.saturating_sub(n), size - 1),
+            None => ByteRange::Full,
+        },
+        (false, _) => {
+            let (Some(start), end) = (parse(first), parse(last)) else {
+                return ByteRange::Full;
+            };
+            match end {
+                None if !last.is_empty() => ByteRange::Full,
+                Some(end) if end < start => ByteRange::Full,
+                _ if start >= size => ByteRange::Unsatisfiable,
+                end => ByteRange::Partial(start, end.map_or(size - 1, |e| e.min(size - 1))),
+            }
+        }
+        (true, true) => ByteRange::Full,
+    }
+}
+
+/// Serves a stored proof file, honoring `Range`, `If-Range` and
+/// `If-None-Match` so interrupted downloads resume where they stopped.
+async fn download(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let (database, not_before) = proof_files(&req)?;
+        let file_hash = path.into_inner();
+        let file = database
+            .proof_file(&file_hash, not_before)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Proof file {file_hash} not found")))?;
+        let etag = format!("\"{}\"", file.file_hash);
+        let header_str = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+
+        if header_str(header::IF_NONE_MATCH).is_some_and(|tags| {
+            tags.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        }) {
+            return Ok(HttpResponse::NotModified()
+                .insert_header((header::ETAG, etag))
+                .finish());
+        }
+        // A stale If-Range means the client's partial copy is of other
+        // bytes, so it gets the whole file.
+        let range = match header_str(header::IF_RANGE) {
+            Some(tag) if tag.trim() != etag => ByteRange::Full,
+            _ => byte_range(header_str(header::RANGE), file.size),
+        };
+
+        let mut response = HttpResponse::Ok();
+        response
+            .insert_header((header::ACCEPT_RANGES, "bytes"))
+            .insert_header((header::ETAG, etag))
+            .insert_header((header::CONTENT_TYPE, "application/octet-stream"))
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.proof\"", file.file_hash),
+            ));
+        let (start, end) = match range {
+            ByteRange::Full if file.size == 0 => return Ok(response.finish()),
+            ByteRange::Full => (0, file.size - 1),
+            ByteRange::Partial(start, end) => {
+                response.status(StatusCode::PARTIAL_CONTENT).insert_header((
+                    header::CONTENT_RANGE,
+                    format!("bytes {start}-{end}/{}", file.size),
+                ));
+                (start, end)
+            }
+            ByteRange::Unsatisfiable => {
+                return Ok(response
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .insert_header((header::CONTENT_RANGE, format!("bytes */{}", file.size)))
+                    .finish());
+            }
+        };
+        let len = end - start + 1;
+        debug!(
+            "Serving bytes {}-{} of proof file {}",
+            start, end, file.file_hash
+        );
+        if req.method() == Method::HEAD {
+            return Ok(response.no_chunking(len).finish());
+        }
+        let data = database
+            .proof_file_range(&file.file_hash, start, len)
+            .await?;
+        Ok(response.body(data))
+    }
+    .await;
+    result.unwrap_or_else(|e| handle_result::<()>(Err(e)))
+}
+
+/// The key proof and ownership responses are signed with, so consumers can
+/// pin it.
+async fn signing_key(req: HttpRequest) -> HttpResponse {
+    let result = signer(&req).map(|signer| {
+        serde_json::json!({
+            "kid": signer.kid(),
+            "public_key": signer.public_key_hex(),
+            "algorithm": "bip340-schnorr-sha256",
+            "encoding": "canonical-json",
+        })
+    });
+    handle_result(result)
+}
+
+/// Every published signing key, so consumers verify signatures by either key
+/// while keys rotate.
+async fn signing_keys(req: HttpRequest) -> HttpResponse {
+    handle_result(signer(&req).map(|signer| signer.jwks()))
+}
+
+fn signer(req: &HttpRequest) -> Result<&SharedResponseSigner, AppError> {
+    req.app_data::<web::Data<SharedResponseSigner>>()
+        .map(|signer| signer.get_ref())
+        .ok_or_else(|| AppError::NotFound("Response signing is not enabled".to_string()))
+}
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/proofs/decode").route(web::post().to(decode)))
         .service(web::resource("/proofs/export").route(web::post().to(export)))
         .service(web::resource("/proofs/unpack-file").route(web::post().to(unpack_file)))
         .service(web::resource("/proofs/verify").route(web::post().to(verify)));
 }
+
+/// Gateway-owned routes for checking signed responses.
+pub fn configure_gateway(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/signing-key").route(web::get().to(signing_key)))
+        .service(web::resource("/signing-keys").route(web::get().to(signing_keys)))
+        .service(web::resource("/proofs/files").route(web::post().to(prepare_download)))
+        .service(
+            web::resource("/proofs/files/{file_hash}")
+                .route(web::get().to(download))
+                .route(web::head().to(download)),
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_range_forms() {
+        assert_eq!(byte_range(None, 100), ByteRange::Full);
+        assert_eq!(byte_range(Some("bytes=0-9"), 100), ByteRange::Partial(0, 9));
+        assert_eq!(
+            byte_range(Some("bytes=90-"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            byte_range(Some("bytes=90-500"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            byte_range(Some("bytes=-10"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            byte_range(Some("bytes=-500"), 100),
+            ByteRange::Partial(0, 99)
+        );
+    }
+
+    #[test]
+    fn test_byte_range_rejects_or_ignores_bad_ranges() {
+        assert_eq!(
+            byte_range(Some("bytes=100-"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(byte_range(Some("bytes=-0"), 100), ByteRange::Unsatisfiable);
+        assert_eq!(byte_range(Some("bytes=9-0"), 100), ByteRange::Full);
+        assert_eq!(byte_range(Some("bytes=0-1,5-6"), 100), ByteRange::Full);
+        assert_eq!(byte_range(Some("items=0-9"), 100), ByteRange::Full);
+        assert_eq!(byte_range(Some("bytes=a-9"), 100), ByteRange::Full);
+    }
+}
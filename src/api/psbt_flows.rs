@@ -0,0 +1,50 @@
+use super::{handle_result, ListEnvelope, PageParams};
+use crate::error::AppError;
+use crate::psbt_flows::PsbtFlowGuard;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+fn require_guard(req: &HttpRequest) -> Result<PsbtFlowGuard, AppError> {
+    PsbtFlowGuard::from_request(req).ok_or_else(|| {
+        AppError::ServiceUnavailable(
+            "PSBT flow tracking requires PSBT_FLOW_GUARD_ENABLED and DATABASE_URL".to_string(),
+        )
+    })
+}
+
+/// Outputs reserved by in-progress PSBT flows, oldest first.
+async fn list(req: HttpRequest) -> HttpResponse {
+    let result = async {
+        require_guard(&req)?;
+        let database = super::require_database(&req)?;
+        let page = PageParams::from_query(req.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let now = chrono::Utc::now().timestamp();
+        let reservations = database
+            .list_psbt_reservations(now, limit + 1, offset)
+            .await?;
+        Ok(ListEnvelope::from_offset_page(reservations, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Frees a flow's outputs for other flows, e.g. after abandoning it. tapd's
+/// own leases are not touched.
+async fn release(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let flow_id = path.into_inner();
+        let released = require_guard(&req)?.release(&flow_id).await?;
+        if released == 0 {
+            return Err(AppError::NotFound(format!("PSBT flow {flow_id} not found")));
+        }
+        Ok(json!({ "flow_id": flow_id, "released": released }))
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/psbt-flows").route(web::get().to(list)))
+        .service(web::resource("/psbt-flows/{flow_id}").route(web::delete().to(release)));
+}
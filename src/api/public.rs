@@ -0,0 +1,354 @@
+//! Anonymous read-only routes for block-explorer style sites. They live in
+//! their own `/public/v1` scope with a stricter rate limit and only expose
+//! public asset data; nothing here reaches wallet or admin functionality.
+//! Responses are cached in memory and marked cacheable for downstream proxies.
+//! Each gateway has its own cache and rate limit buckets, shared by all of its
+//! workers.
+
+use super::supply::compute_supply;
+use super::{assets, universe, validate_asset_id};
+use crate::cache_audit::SharedCacheAudit;
+use crate::config::Config;
+use crate::database::SharedDatabase;
+use crate::error::AppError;
+use crate::middleware::RateLimiter;
+use crate::rate_limit::{RateLimits, SharedRateLimits};
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Path prefix of the anonymous routes; API key authentication skips it.
+pub const PUBLIC_PATH_PREFIX: &str = "/public/v1";
+
+/// Bounds memory use when callers walk many distinct asset IDs.
+const MAX_CACHED_RESPONSES: usize = 1000;
+
+/// The public scope's rate limit buckets and response cache.
+#[derive(Debug)]
+pub struct PublicApi {
+    limits: SharedRateLimits,
+    cache: Mutex<HashMap<String, (Instant, Value)>>,
+}
+
+pub type SharedPublicApi = Arc<PublicApi>;
+
+impl PublicApi {
+    pub fn new(rate_limit_per_minute: usize) -> Self {
+        Self {
+            limits: Arc::new(RateLimits::new(rate_limit_per_minute)),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A live entry and how long it has been cached.
+    fn cached(&self, key: &str, ttl: Duration) -> Option<(Duration, Value)> {
+        let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .get(key)
+            .filter(|(at, _)| at.elapsed() < ttl)
+            .map(|(at, value)| (at.elapsed(), value.clone()))
+    }
+
+    /// Responses held in the cache, expired ones included until the next store.
+    pub fn cache_len(&self) -> usize {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    fn store(&self, key: &str, value: &Value, ttl: Duration) {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.retain(|_, (at, _)| at.elapsed() < ttl);
+        if cache.len() < MAX_CACHED_RESPONSES {
+            cache.insert(key.to_string(), (Instant::now(), value.clone()));
+        }
+    }
+}
+
+fn cache_ttl(req: &HttpRequest) -> Duration {
+    req.app_data::<web::Data<Config>>()
+        .map_or(Duration::from_secs(300), |c| {
+            Duration::from_secs(c.public_cache_ttl_secs)
+        })
+}
+
+/// Anonymous callers get a generic error instead of tapd's error document.
+pub(super) fn public_error(error: AppError) -> HttpResponse {
+    let (status, message) = match &error {
+        AppError::InvalidInput(_) | AppError::ValidationError(_) => {
+            (StatusCode::BAD_REQUEST, error.to_string())
+        }
+        AppError::NotFound(_) => (StatusCode::NOT_FOUND, "Not found".to_string()),
+        AppError::UpstreamError { status: 404, .. } => {
+            (StatusCode::NOT_FOUND, "Not found".to_string())
+        }
+        _ => {
+            warn!("Public request failed: {}", error);
+            (
+                StatusCode::BAD_GATEWAY,
+                "Backend temporarily unavailable".to_string(),
+            )
+        }
+    };
+    HttpResponse::build(status).json(serde_json::json!({ "error": message }))
+}
+
+/// Serves `key` from cache, or computes and caches it. A sampled hit also
+/// runs `compute` in the background to audit the cached value.
+async fn cached_response<F>(req: &HttpRequest, key: &str, compute: F) -> HttpResponse
+where
+    F: std::future::Future<Output = Result<Value, AppError>> + 'static,
+{
+    let ttl = cache_ttl(req);
+    let Some(public) = req.app_data::<web::Data<SharedPublicApi>>() else {
+        return match compute.await {
+            Ok(value) => HttpResponse::Ok().json(value),
+            Err(e) => public_error(e),
+        };
+    };
+    let value = match public.cached(key, ttl) {
+        Some((age, value)) => {
+            let audit = req.app_data::<web::Data<SharedCacheAudit>>();
+            if let Some(audit) = audit.filter(|audit| audit.should_sample()) {
+                audit.check("public", key.to_string(), value.clone(), age, compute);
+            }
+            value
+        }
+        None => match compute.await {
+            Ok(value) => {
+                if !ttl.is_zero() {
+                    public.store(key, &value, ttl);
+                }
+                value
+            }
+            Err(e) => return public_error(e),
+        },
+    };
+    HttpResponse::Ok()
+        .insert_header((
+            "Cache-Control",
+            format!("public, max-age={}", ttl.as_secs()),
+        ))
+        .json(value)
+}
+
+fn asset_id_param(path: web::Path<String>) -> Result<String, AppError> {
+    let asset_id = path.into_inner();
+    validate_asset_id(&asset_id)?;
+    Ok(asset_id.to_ascii_lowercase())
+}
+
+async fn asset_meta(
+    req: HttpRequest,
+    path: web::Path<String>,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+) -> HttpResponse {
+    let asset_id = match asset_id_param(path) {
+        Ok(asset_id) => asset_id,
+        Err(e) => return public_error(e),
+    };
+    let key = format!("meta:{asset_id}");
+    cached_response(&req, &key, async move {
+        assets::get_meta(&client, &base_url.0, &macaroon_hex.0, &asset_id, "").await
+    })
+    .await
+}
+
+async fn asset_supply(
+    req: HttpRequest,
+    path: web::Path<String>,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+) -> HttpResponse {
+    let asset_id = match asset_id_param(path) {
+        Ok(asset_id) => asset_id,
+        Err(e) => return public_error(e),
+    };
+    let database = req
+        .app_data::<web::Data<SharedDatabase>>()
+        .map(|db| db.get_ref().clone());
+    let key = format!("supply:{asset_id}");
+    cached_response(&req, &key, async move {
+        let supply = compute_supply(
+            &client,
+            &base_url.0,
+            &macaroon_hex.0,
+            database.as_ref(),
+            &asset_id,
+        )
+        .await?;
+        Ok(serde_json::to_value(supply)?)
+    })
+    .await
+}
+
+async fn universe_stats(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+) -> HttpResponse {
+    cached_response(&req, "universe_stats", async move {
+        universe::get_stats(&client, &base_url.0, &macaroon_hex.0).await
+    })
+    .await
+}
+
+/// Registers the public scope, rate limited and cached by `public`. Build
+/// `public` once per gateway, outside the app factory, so every worker
+/// counts against the same buckets.
+pub fn configure(cfg: &mut web::ServiceConfig, public: SharedPublicApi) {
+    cfg.service(
+        web::scope(PUBLIC_PATH_PREFIX)
+            .app_data(web::Data::new(public.clone()))
+            .wrap(RateLimiter::shared(public.limits.clone()))
+            .route("/assets/{asset_id}/meta", web::get().to(asset_meta))
+            .route("/assets/{asset_id}/supply", web::get().to(asset_supply))
+            .route("/universe/stats", web::get().to(universe_stats)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    #[actix_rt::test]
+    async fn test_public_errors_hide_backend_details() {
+        let res = public_error(AppError::UpstreamError {
+            status: 500,
+            body: "{\"message\": \"db locked at /var/lib/tapd\"}".to_string(),
+        });
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+        let res = public_error(AppError::UpstreamError {
+            status: 404,
+            body: String::new(),
+        });
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_public_scope_is_rate_limited_and_cacheable() {
+        let public = Arc::new(PublicApi::new(2));
+        public.store(
+            "universe_stats",
+            &serde_json::json!({ "num_assets": "3" }),
+            Duration::from_secs(300),
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Client::new()))
+                .app_data(web::Data::new(BaseUrl("https://127.0.0.1:1".to_string())))
+                .app_data(web::Data::new(MacaroonHex(String::new())))
+                .configure(|cfg| configure(cfg, public)),
+        )
+        .await;
+
+        for _ in 0..2 {
+            let req = test::TestRequest::get()
+                .uri("/public/v1/universe/stats")
+                .peer_addr("10.0.0.1:1000".parse().unwrap())
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(
+                res.headers().get("Cache-Control").unwrap(),
+                "public, max-age=300"
+            );
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/public/v1/universe/stats")
+            .peer_addr("10.0.0.1:1000".parse().unwrap())
+            .to_request();
+        let res = test::try_call_service(&app, req).await;
+        let status = match res {
+            Ok(res) => res.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+        let req = test::TestRequest::get()
+            .uri("/public/v1/assets/nothex/meta")
+            .peer_addr("10.0.0.2:1000".parse().unwrap())
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_workers_share_the_gateway_limit_and_cache() {
+        use crate::network_acl::{parse_trusted_proxies, NetworkAcl, SharedNetworkAcl};
+        let acl: SharedNetworkAcl = Arc::new(NetworkAcl::new(
+            Vec::new(),
+            parse_trusted_proxies("10.0.0.1").unwrap(),
+        ));
+        let public = Arc::new(PublicApi::new(2));
+        public.store(
+            "universe_stats",
+            &serde_json::json!({ "num_assets": "3" }),
+            Duration::from_secs(300),
+        );
+        // Two app instances stand in for two workers of one gateway
+        let worker = || {
+            let public = public.clone();
+            App::new()
+                .app_data(web::Data::new(acl.clone()))
+                .app_data(web::Data::new(Client::new()))
+                .app_data(web::Data::new(BaseUrl("https://127.0.0.1:1".to_string())))
+                .app_data(web::Data::new(MacaroonHex(String::new())))
+                .configure(move |cfg| configure(cfg, public))
+        };
+        let first = test::init_service(worker()).await;
+        let second = test::init_service(worker()).await;
+        let other_gateway = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Client::new()))
+                .app_data(web::Data::new(BaseUrl("https://127.0.0.1:1".to_string())))
+                .app_data(web::Data::new(MacaroonHex(String::new())))
+                .configure(|cfg| configure(cfg, Arc::new(PublicApi::new(2)))),
+        )
+        .await;
+
+        // Behind the trusted proxy each forwarded client has its own bucket
+        let req = |client: &str| {
+            test::TestRequest::get()
+                .uri("/public/v1/universe/stats")
+                .peer_addr("10.0.0.1:1000".parse().unwrap())
+                .insert_header(("X-Forwarded-For", client.to_string()))
+                .to_request()
+        };
+        let status = |res: Result<actix_web::dev::ServiceResponse, actix_web::Error>| match res {
+            Ok(res) => res.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+        assert_eq!(
+            status(test::try_call_service(&first, req("192.0.2.1")).await),
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(test::try_call_service(&second, req("192.0.2.1")).await),
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(test::try_call_service(&first, req("192.0.2.1")).await),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            status(test::try_call_service(&second, req("192.0.2.2")).await),
+            StatusCode::OK
+        );
+
+        // The cached stats were only stored in this gateway's cache
+        assert_eq!(public.cache_len(), 1);
+        let res = test::call_service(&other_gateway, req("192.0.2.1")).await;
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    }
+}
@@ -0,0 +1,23 @@
+use super::handle_result;
+use crate::error::AppError;
+use crate::middleware::rate_limit_client;
+use crate::rate_limit::SharedRateLimits;
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::time::Instant;
+
+/// Exempt from the limit it reports.
+pub const RATE_LIMIT_PATH: &str = "/v1/gateway/rate-limit";
+
+/// The caller's bucket: the same numbers as the `X-RateLimit-*` headers,
+/// without spending a request.
+pub async fn get_rate_limit(req: HttpRequest) -> HttpResponse {
+    let result = req
+        .app_data::<web::Data<SharedRateLimits>>()
+        .map(|limits| limits.status(&rate_limit_client(&req), Instant::now()))
+        .ok_or_else(|| AppError::ServiceUnavailable("Rate limiting is not configured".to_string()));
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/rate-limit").route(web::get().to(get_rate_limit)));
+}
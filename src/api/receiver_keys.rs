@@ -0,0 +1,244 @@
+//! Key rotation for mailbox receivers known to the gateway; see
+//! [`crate::receiver_keys`].
+
+use super::mailbox::{outbox, send_mail};
+use super::{handle_result, require_database};
+use crate::config::Config;
+use crate::database::KeyRotation;
+use crate::error::AppError;
+use crate::receiver_keys::{
+    notice_request, validate_public_key, validate_reason, MAX_OVERLAP_SECS,
+};
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+
+#[derive(Debug, Deserialize)]
+pub struct RotateKeyRequest {
+    pub new_public_key: String,
+    /// How long the replaced key keeps working; `RECEIVER_KEY_OVERLAP_SECS`
+    /// when omitted, `0` revokes it at once.
+    pub overlap_secs: Option<u64>,
+    pub reason: Option<String>,
+    /// Send the receiver a notice through its mailbox (default true).
+    pub notify: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeprecatedKey {
+    pub public_key: String,
+    pub deprecated_until: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiverKeysResponse {
+    pub receiver_id: String,
+    pub public_key: String,
+    pub deprecated_keys: Vec<DeprecatedKey>,
+    /// Newest first.
+    pub rotations: Vec<KeyRotation>,
+}
+
+/// Outcome of the mailbox notice.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    /// `sent`, `queued` or `failed`.
+    pub status: String,
+    /// Outbox entry, when `MAILBOX_OUTBOX_ENABLED` is set.
+    pub outbox_id: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RotateKeyResponse {
+    pub rotation: KeyRotation,
+    pub notification: Option<Notification>,
+}
+
+async fn notify(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    rotation: &KeyRotation,
+) -> Notification {
+    let request = notice_request(rotation);
+    let failed = |e: AppError| {
+        warn!(
+            "Failed to notify receiver {} of its key rotation: {}",
+            rotation.receiver_id, e
+        );
+        Notification {
+            status: "failed".to_string(),
+            outbox_id: None,
+            error: Some(e.to_string()),
+        }
+    };
+    match outbox(req, client, base_url, macaroon_hex) {
+        Ok(Some(outbox)) => {
+            let body = match serde_json::to_value(&request) {
+                Ok(body) => body,
+                Err(e) => return failed(e.into()),
+            };
+            match outbox
+                .enqueue(body, rotation.receiver_id.clone(), None, None)
+                .await
+            {
+                Ok(message) => Notification {
+                    status: message.status.as_str().to_string(),
+                    outbox_id: Some(message.id),
+                    error: message.last_error,
+                },
+                Err(e) => failed(e),
+            }
+        }
+        Ok(None) => match send_mail(client, base_url, macaroon_hex, request).await {
+            Ok(_) => Notification {
+                status: "sent".to_string(),
+                outbox_id: None,
+                error: None,
+            },
+            Err(e) => failed(e),
+        },
+        Err(e) => failed(e),
+    }
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, body))]
+async fn rotate(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    path: web::Path<String>,
+    body: web::Json<RotateKeyRequest>,
+) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let receiver_id = path.into_inner();
+        let request = body.into_inner();
+        let new_public_key = request.new_public_key.trim().to_ascii_lowercase();
+        validate_public_key(&new_public_key)?;
+        validate_reason(request.reason.as_deref())?;
+        let overlap_secs = request.overlap_secs.unwrap_or_else(|| {
+            req.app_data::<web::Data<Config>>()
+                .map_or(86_400, |c| c.receiver_key_overlap_secs)
+        });
+        if overlap_secs > MAX_OVERLAP_SECS {
+            return Err(AppError::ValidationError(format!(
+                "overlap_secs must be at most {MAX_OVERLAP_SECS}"
+            )));
+        }
+
+        let info = database
+            .get_receiver_info(&receiver_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Receiver {receiver_id} not found")))?;
+        if info.public_key.eq_ignore_ascii_case(&new_public_key) {
+            return Err(AppError::ValidationError(
+                "new_public_key is already the receiver's key".to_string(),
+            ));
+        }
+        let now = chrono::Utc::now().timestamp();
+        let rotation = KeyRotation {
+            id: uuid::Uuid::new_v4().to_string(),
+            receiver_id: receiver_id.clone(),
+            old_public_key: info.public_key,
+            new_public_key,
+            reason: request.reason,
+            rotated_at: now,
+            deprecated_until: now + overlap_secs as i64,
+        };
+        if !database.rotate_receiver_key(&rotation).await? {
+            return Err(AppError::Conflict(format!(
+                "Receiver {receiver_id}'s key changed during the rotation; retry"
+            )));
+        }
+        warn!(
+            "Rotated key of receiver {} from {} to {}; the old key is accepted until {}",
+            receiver_id,
+            rotation.old_public_key,
+            rotation.new_public_key,
+            rotation.deprecated_until
+        );
+
+        let notification = if request.notify.unwrap_or(true) {
+            Some(notify(&req, &client, &base_url.0, &macaroon_hex.0, &rotation).await)
+        } else {
+            None
+        };
+        Ok(RotateKeyResponse {
+            rotation,
+            notification,
+        })
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn keys(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let receiver_id = path.into_inner();
+        let info = database
+            .get_receiver_info(&receiver_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Receiver {receiver_id} not found")))?;
+        let now = chrono::Utc::now().timestamp();
+        let rotations = database.receiver_key_rotations(&receiver_id).await?;
+        Ok(ReceiverKeysResponse {
+            deprecated_keys: rotations
+                .iter()
+                .filter(|r| r.deprecated_until > now)
+                .map(|r| DeprecatedKey {
+                    public_key: r.old_public_key.clone(),
+                    deprecated_until: r.deprecated_until,
+                })
+                .collect(),
+            receiver_id,
+            public_key: info.public_key,
+            rotations,
+        })
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Ends the overlap window, for when the receiver has switched keys.
+async fn revoke_deprecated(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let receiver_id = path.into_inner();
+        if database.get_receiver_info(&receiver_id).await?.is_none() {
+            return Err(AppError::NotFound(format!(
+                "Receiver {receiver_id} not found"
+            )));
+        }
+        let revoked = database
+            .revoke_deprecated_receiver_keys(&receiver_id, chrono::Utc::now().timestamp())
+            .await?;
+        if revoked > 0 {
+            warn!(
+                "Revoked {} deprecated key(s) of receiver {}",
+                revoked, receiver_id
+            );
+        }
+        Ok(serde_json::json!({ "receiver_id": receiver_id, "revoked": revoked }))
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/mailbox/receivers/{receiver_id}/keys").route(web::get().to(keys)))
+        .service(
+            web::resource("/mailbox/receivers/{receiver_id}/keys/rotate")
+                .route(web::post().to(rotate)),
+        )
+        .service(
+            web::resource("/mailbox/receivers/{receiver_id}/keys/deprecated")
+                .route(web::delete().to(revoke_deprecated)),
+        );
+}
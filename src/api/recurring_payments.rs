@@ -0,0 +1,319 @@
+use super::addresses::{decode_address, DecodeAddrRequest};
+use super::{
+    handle_result, public_url, require_database, validate_asset_id, validate_callback_url,
+    ListEnvelope, PageParams,
+};
+use crate::config::Config;
+use crate::database::{RecurringPayment, RecurringPaymentQuery, RecurringStatus, SharedDatabase};
+use crate::destination_guard::DestinationGuard;
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::recurring_payments::{mismatch, RecurringPayer};
+use crate::scheduler::Schedule;
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::instrument;
+
+#[derive(Debug, Deserialize)]
+pub struct SpendLimit {
+    /// Most units sent within `window_secs`.
+    pub amount: u64,
+    pub window_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRecurringPaymentRequest {
+    pub asset_id: String,
+    /// Units paid on every run.
+    pub amount: u64,
+    /// Address paid on every run; must be for `asset_id` and `amount`.
+    pub address: Option<String>,
+    /// URL the gateway POSTs to for a fresh address on every run, instead
+    /// of `address`.
+    pub address_url: Option<String>,
+    /// `@every` or cron expression of the cadence.
+    pub schedule: String,
+    /// Runs that would send more are held for approval.
+    pub spend_limit: Option<SpendLimit>,
+    /// Complete the payment after this many sent runs.
+    pub max_payments: Option<u64>,
+    /// Unix time of the first run; defaults to the scheduler's next tick.
+    pub start_at: Option<i64>,
+    pub label: Option<String>,
+}
+
+impl CreateRecurringPaymentRequest {
+    fn validate(&self) -> Result<(), AppError> {
+        validate_asset_id(&self.asset_id)?;
+        if self.amount == 0 {
+            return Err(AppError::ValidationError(
+                "amount must be greater than zero".to_string(),
+            ));
+        }
+        match (&self.address, &self.address_url) {
+            (Some(_), None) => {}
+            (None, Some(url)) => validate_callback_url(url).map_err(|_| {
+                AppError::ValidationError("address_url must be an absolute http(s) URL".to_string())
+            })?,
+            _ => {
+                return Err(AppError::ValidationError(
+                    "Exactly one of address and address_url is required".to_string(),
+                ))
+            }
+        }
+        Schedule::parse(&self.schedule)?;
+        if let Some(limit) = &self.spend_limit {
+            if limit.amount < self.amount || limit.window_secs == 0 {
+                return Err(AppError::ValidationError(
+                    "spend_limit must allow at least one payment over a non-empty window"
+                        .to_string(),
+                ));
+            }
+        }
+        if self.max_payments == Some(0) {
+            return Err(AppError::ValidationError(
+                "max_payments must be greater than zero".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn require_enabled(config: &Config) -> Result<(), AppError> {
+    if !config.recurring_payments_enabled {
+        return Err(AppError::ServiceUnavailable(
+            "Recurring payments require RECURRING_PAYMENTS_ENABLED".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn load(database: &SharedDatabase, id: &str) -> Result<RecurringPayment, AppError> {
+    database
+        .get_recurring_payment(id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Recurring payment {id} not found")))
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, config, body))]
+async fn create(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    body: web::Json<CreateRecurringPaymentRequest>,
+) -> HttpResponse {
+    let result = async {
+        require_enabled(&config)?;
+        let request = body.into_inner();
+        request.validate()?;
+        let database = require_database(&req)?;
+        let now = chrono::Utc::now().timestamp();
+        let payment = RecurringPayment {
+            id: uuid::Uuid::new_v4().to_string(),
+            asset_id: request.asset_id.to_ascii_lowercase(),
+            amount: request.amount,
+            address: request.address,
+            address_url: request.address_url,
+            schedule: request.schedule.trim().to_string(),
+            status: RecurringStatus::Active,
+            spend_limit_amount: request.spend_limit.as_ref().map(|l| l.amount),
+            spend_limit_window_secs: request.spend_limit.as_ref().map(|l| l.window_secs),
+            max_payments: request.max_payments,
+            runs: 0,
+            payments_made: 0,
+            next_run_at: request.start_at.unwrap_or(now),
+            label: request.label,
+            created_at: now,
+            updated_at: now,
+        };
+        // A static address that can never be paid is refused up front
+        if let Some(address) = &payment.address {
+            let request = DecodeAddrRequest {
+                addr: address.clone(),
+            };
+            let decoded = decode_address(&client, &base_url.0, &macaroon_hex.0, request).await?;
+            if let Some(problem) = mismatch(
+                &payment,
+                decoded.asset_id.as_deref(),
+                decoded.amount.as_deref(),
+            ) {
+                return Err(AppError::ValidationError(problem));
+            }
+        }
+        database.insert_recurring_payment(&payment).await?;
+        Ok(payment)
+    }
+    .await;
+    match result {
+        Ok(payment) => HttpResponse::build(StatusCode::CREATED)
+            .insert_header((
+                header::LOCATION,
+                public_url(
+                    &req,
+                    &format!("/v1/gateway/recurring-payments/{}", payment.id),
+                ),
+            ))
+            .json(payment),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
+}
+
+async fn list(req: HttpRequest, query: web::Query<RecurringPaymentQuery>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let page = PageParams::from_query(req.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let query = RecurringPaymentQuery {
+            limit: Some(limit + 1),
+            offset: Some(offset),
+            ..query.into_inner()
+        };
+        let payments = database.list_recurring_payments(&query).await?;
+        Ok(ListEnvelope::from_offset_page(payments, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn get(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async { load(&require_database(&req)?, &path.into_inner()).await }.await;
+    handle_result(result)
+}
+
+/// Stops future runs. Runs already held for approval can no longer be
+/// approved.
+async fn cancel(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let mut payment = load(&database, &path.into_inner()).await?;
+        if payment.status != RecurringStatus::Active {
+            return Err(AppError::ValidationError(format!(
+                "Recurring payment {} is already {}",
+                payment.id,
+                payment.status.as_str()
+            )));
+        }
+        payment.status = RecurringStatus::Cancelled;
+        payment.updated_at = chrono::Utc::now().timestamp();
+        if !database
+            .advance_recurring_payment(&payment, RecurringStatus::Active)
+            .await?
+        {
+            // A run completed it meanwhile
+            return load(&database, &payment.id).await;
+        }
+        Ok(payment)
+    }
+    .await;
+    handle_result(result)
+}
+
+/// The payment's runs, newest first.
+async fn runs(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let payment = load(&database, &path.into_inner()).await?;
+        let page = PageParams::from_query(req.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let runs = database
+            .list_recurring_runs(&payment.id, limit + 1, offset)
+            .await?;
+        Ok(ListEnvelope::from_offset_page(runs, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, config))]
+async fn approve(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let result = async {
+        require_enabled(&config)?;
+        let (payment_id, run_id) = path.into_inner();
+        let events = req
+            .app_data::<web::Data<SharedEventBus>>()
+            .ok_or_else(|| AppError::ServiceUnavailable("Event bus not configured".to_string()))?;
+        let payer = RecurringPayer::new(
+            client.get_ref().clone(),
+            base_url.0.to_string(),
+            macaroon_hex.0.to_string(),
+            require_database(&req)?,
+            events.get_ref().clone(),
+            DestinationGuard::from_request(&req),
+            config.anomaly_rules.clone(),
+        );
+        payer.approve(&payment_id, &run_id).await
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/recurring-payments")
+            .route(web::get().to(list))
+            .route(web::post().to(create)),
+    )
+    .service(web::resource("/recurring-payments/{id}").route(web::get().to(get)))
+    .service(web::resource("/recurring-payments/{id}/cancel").route(web::post().to(cancel)))
+    .service(web::resource("/recurring-payments/{id}/runs").route(web::get().to(runs)))
+    .service(
+        web::resource("/recurring-payments/{id}/runs/{run_id}/approve")
+            .route(web::post().to(approve)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(address: Option<&str>, address_url: Option<&str>) -> CreateRecurringPaymentRequest {
+        CreateRecurringPaymentRequest {
+            asset_id: "a".repeat(64),
+            amount: 10,
+            address: address.map(str::to_string),
+            address_url: address_url.map(str::to_string),
+            schedule: "@every 24h".to_string(),
+            spend_limit: None,
+            max_payments: None,
+            start_at: None,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_destination_and_schedule() {
+        assert!(request(Some("taprt1..."), None).validate().is_ok());
+        assert!(request(None, Some("https://payee.example/addr"))
+            .validate()
+            .is_ok());
+        assert!(request(None, None).validate().is_err());
+        assert!(
+            request(Some("taprt1..."), Some("https://payee.example/addr"))
+                .validate()
+                .is_err()
+        );
+
+        let mut bad_schedule = request(Some("taprt1..."), None);
+        bad_schedule.schedule = "daily".to_string();
+        assert!(bad_schedule.validate().is_err());
+
+        let mut tight_limit = request(Some("taprt1..."), None);
+        tight_limit.spend_limit = Some(SpendLimit {
+            amount: 5,
+            window_secs: 3600,
+        });
+        assert!(tight_limit.validate().is_err());
+    }
+}
@@ -1,6 +1,8 @@
-use super::{handle_result, parse_upstream, validate_hex_param};
+use super::{backend, handle_result};
 use crate::error::AppError;
-use crate::types::{BaseUrl, MacaroonHex};
+use crate::types::{AssetId, BaseUrl, MacaroonHex};
+use crate::websocket::control;
+use crate::websocket::filter::EventFilter;
 use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
@@ -48,15 +50,13 @@ pub async fn buy_offer(
     asset_id: &str,
 ) -> Result<Value, AppError> {
     info!("Creating buy offer for asset ID: {}", asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/rfq/buyoffer/asset-id/{asset_id}");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post(&format!(
+            "/v1/taproot-assets/rfq/buyoffer/asset-id/{asset_id}"
+        ))
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -68,15 +68,13 @@ pub async fn buy_order(
     asset_id: &str,
 ) -> Result<Value, AppError> {
     info!("Creating buy order for asset ID: {}", asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/rfq/buyorder/asset-id/{asset_id}");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post(&format!(
+            "/v1/taproot-assets/rfq/buyorder/asset-id/{asset_id}"
+        ))
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -86,15 +84,11 @@ pub async fn get_notifications(
     macaroon_hex: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching RFQ notifications");
-    let url = format!("{base_url}/v1/taproot-assets/rfq/ntfs");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/rfq/ntfs")
         .json(&serde_json::json!({}))
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -104,14 +98,10 @@ pub async fn get_peer_quotes(
     macaroon_hex: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching peer-accepted quotes");
-    let url = format!("{base_url}/v1/taproot-assets/rfq/quotes/peeraccepted");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/rfq/quotes/peeraccepted")
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -123,15 +113,13 @@ pub async fn sell_offer(
     asset_id: &str,
 ) -> Result<Value, AppError> {
     info!("Creating sell offer for asset ID: {}", asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/rfq/selloffer/asset-id/{asset_id}");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post(&format!(
+            "/v1/taproot-assets/rfq/selloffer/asset-id/{asset_id}"
+        ))
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -143,15 +131,13 @@ pub async fn sell_order(
     asset_id: &str,
 ) -> Result<Value, AppError> {
     info!("Creating sell order for asset ID: {}", asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/rfq/sellorder/asset-id/{asset_id}");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post(&format!(
+            "/v1/taproot-assets/rfq/sellorder/asset-id/{asset_id}"
+        ))
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 async fn buy_offer_handler(
@@ -162,7 +148,7 @@ async fn buy_offer_handler(
     req: web::Json<BuyOfferRequest>,
 ) -> HttpResponse {
     let asset_id = path.into_inner();
-    if let Err(e) = validate_hex_param(&asset_id) {
+    if let Err(e) = asset_id.parse::<AssetId>() {
         return handle_result::<serde_json::Value>(Err(e));
     }
     handle_result(
@@ -185,7 +171,7 @@ async fn buy_order_handler(
     req: web::Json<BuyOrderRequest>,
 ) -> HttpResponse {
     let asset_id = path.into_inner();
-    if let Err(e) = validate_hex_param(&asset_id) {
+    if let Err(e) = asset_id.parse::<AssetId>() {
         return handle_result::<serde_json::Value>(Err(e));
     }
     handle_result(
@@ -239,6 +225,7 @@ async fn rfq_events_ws_handler(
         use tokio::time::{interval, Duration};
 
         let session = Arc::new(Mutex::new(session));
+        let filter = Arc::new(std::sync::RwLock::new(EventFilter::default()));
 
         // Send initial empty request body to start streaming
         {
@@ -256,6 +243,7 @@ async fn rfq_events_ws_handler(
         let poll_client = client_clone.clone();
         let poll_base_url = base_url_clone.clone();
         let poll_macaroon = macaroon_clone.clone();
+        let poll_filter = filter.clone();
 
         let poll_interval = config.rfq_poll_interval_secs;
         let poll_task = actix_web::rt::spawn(async move {
@@ -264,6 +252,7 @@ async fn rfq_events_ws_handler(
                 &poll_base_url,
                 &poll_macaroon,
                 poll_session,
+                poll_filter,
                 poll_interval,
             )
             .await;
@@ -274,10 +263,15 @@ async fn rfq_events_ws_handler(
                 // Handle incoming messages from client
                 msg = msg_stream.next() => {
                     match msg {
-                        Some(Ok(Message::Text(_text))) => {
-                            // For RFQ notifications, we typically just need to maintain the connection
-                            // The streaming is handled by the initial POST request to tapd
-                            tracing::debug!("Received client message for RFQ notifications");
+                        Some(Ok(Message::Text(text))) => {
+                            // Notifications are polled, so client frames can
+                            // only be control messages
+                            let reply = control::answer(&text, &filter);
+                            let mut session_lock = session.lock().await;
+                            if let Err(e) = session_lock.text(reply).await {
+                                tracing::error!("Failed to send control reply: {}", e);
+                                break;
+                            }
                         },
                         Some(Ok(Message::Close(_))) => {
                             tracing::info!("WebSocket connection closed by client");
@@ -324,12 +318,21 @@ async fn poll_rfq_events(
     base_url: &str,
     macaroon_hex: &str,
     session: std::sync::Arc<tokio::sync::Mutex<actix_ws::Session>>,
+    filter: std::sync::Arc<std::sync::RwLock<EventFilter>>,
     poll_interval_secs: u64,
 ) {
     use tokio::time::{sleep, Duration};
 
     loop {
         match get_notifications(client, base_url, macaroon_hex).await {
+            Ok(events)
+                if !filter
+                    .read()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .matches(&events) =>
+            {
+                tracing::debug!("RFQ notifications filtered out");
+            }
             Ok(events) => {
                 let event_json =
                     serde_json::to_string(&events).unwrap_or_else(|_| "{}".to_string());
@@ -382,7 +385,7 @@ async fn sell_offer_handler(
     req: web::Json<SellOfferRequest>,
 ) -> HttpResponse {
     let asset_id = path.into_inner();
-    if let Err(e) = validate_hex_param(&asset_id) {
+    if let Err(e) = asset_id.parse::<AssetId>() {
         return handle_result::<serde_json::Value>(Err(e));
     }
     handle_result(
@@ -405,7 +408,7 @@ async fn sell_order_handler(
     req: web::Json<SellOrderRequest>,
 ) -> HttpResponse {
     let asset_id = path.into_inner();
-    if let Err(e) = validate_hex_param(&asset_id) {
+    if let Err(e) = asset_id.parse::<AssetId>() {
         return handle_result::<serde_json::Value>(Err(e));
     }
     handle_result(
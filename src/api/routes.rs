@@ -1,22 +1,46 @@
+use super::accounts;
 use super::addresses;
+use super::admin;
+use super::amounts;
 use super::assets;
+use super::attestations;
 use super::burn;
 use super::channels;
+use super::escrows;
 use super::events;
+use super::faucet;
+use super::groups;
 use super::health;
+use super::indexer;
 use super::info;
+use super::liquidity;
 use super::mailbox;
+use super::monitor;
+use super::payment_requests;
 use super::proofs;
+use super::psbt_flows;
+use super::rate_limit;
+use super::receiver_keys;
+use super::recurring_payments;
 use super::rfq;
 use super::send;
+use super::sessions;
+use super::simulate;
 use super::stop;
+use super::supply;
+use super::swaps;
 use super::universe;
+use super::usage;
+use super::utxos;
 use super::wallet;
+use super::webhooks;
+use crate::middleware::StrictStatus;
 use actix_web::web;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/v1/taproot-assets")
+            .wrap(StrictStatus)
             .configure(addresses::configure)
             .configure(assets::configure)
             .configure(burn::configure)
@@ -31,5 +55,41 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .configure(universe::configure)
             .configure(wallet::configure),
     )
+    .service(
+        web::scope("/v1/gateway")
+            .wrap(StrictStatus)
+            .configure(accounts::configure)
+            .configure(addresses::configure_gateway)
+            .configure(admin::configure)
+            .configure(amounts::configure)
+            .configure(attestations::configure)
+            .configure(burn::configure_gateway)
+            .configure(escrows::configure)
+            .configure(events::configure_gateway)
+            .configure(faucet::configure)
+            .configure(groups::configure)
+            .configure(indexer::configure)
+            .configure(liquidity::configure)
+            .configure(mailbox::configure_gateway)
+            .configure(monitor::configure)
+            .configure(payment_requests::configure)
+            .configure(proofs::configure_gateway)
+            .configure(psbt_flows::configure)
+            .configure(rate_limit::configure)
+            .configure(receiver_keys::configure)
+            .configure(recurring_payments::configure)
+            .configure(send::configure_gateway)
+            .configure(sessions::configure)
+            .configure(simulate::configure)
+            .configure(supply::configure)
+            .configure(swaps::configure)
+            .configure(universe::configure_gateway)
+            .configure(usage::configure)
+            .configure(utxos::configure)
+            .configure(webhooks::configure),
+    )
     .configure(health::configure);
+
+    #[cfg(feature = "graphql")]
+    cfg.configure(super::graphql::configure);
 }
@@ -1,10 +1,25 @@
-use super::{handle_result, parse_upstream};
+use super::addresses::{decode_address, DecodeAddrRequest};
+use super::{backend, handle_result};
+use crate::anomaly;
+use crate::database::SharedDatabase;
+use crate::destination_guard::{self, check_destinations, DestinationGuard};
 use crate::error::AppError;
+use crate::indexer::{normalize_hex_id, normalize_transfer};
 use crate::types::{BaseUrl, MacaroonHex};
-use actix_web::{web, HttpResponse};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use tracing::{info, instrument};
+use serde_json::Value;
+use std::collections::HashSet;
+use tracing::{info, instrument, warn};
+
+/// Most tags one send may carry.
+const MAX_TAGS: usize = 16;
+/// Longest tag accepted, in bytes.
+const MAX_TAG_LEN: usize = 64;
+/// Most addresses one batch send may pay.
+const MAX_BATCH_OUTPUTS: usize = 64;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SendRequest {
@@ -15,6 +30,28 @@ pub struct SendRequest {
     pub label: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub skip_proof_courier_ping_check: Option<bool>,
+    /// Gateway-side tags for finding the transfer in the index later; not
+    /// sent to tapd.
+    #[serde(default, skip_serializing)]
+    pub tags: Vec<String>,
+}
+
+impl SendRequest {
+    pub(super) fn validate_tags(&self) -> Result<(), AppError> {
+        if self.tags.len() > MAX_TAGS {
+            return Err(AppError::InvalidInput(format!(
+                "A send may carry at most {MAX_TAGS} tags"
+            )));
+        }
+        for tag in &self.tags {
+            if tag.trim().is_empty() || tag.len() > MAX_TAG_LEN {
+                return Err(AppError::InvalidInput(format!(
+                    "Tags must be non-empty and at most {MAX_TAG_LEN} bytes"
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[instrument(skip(client))]
@@ -25,34 +62,351 @@ pub async fn send_assets(
     req: SendRequest,
 ) -> Result<serde_json::Value, AppError> {
     info!("Sending assets");
-    let url = format!("{base_url}/v1/taproot-assets/send");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/send")
         .json(&req)
-        .send()
+        .fetch::<serde_json::Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<serde_json::Value>(response).await
+}
+
+/// Records a completed send in the index right away, with its label and
+/// tags, so it can be found by tag before the next backfill. Failing to
+/// index never fails the send itself. Returns the anchor txid, if tapd
+/// reported one.
+pub(crate) async fn index_send(
+    database: &SharedDatabase,
+    label: Option<&str>,
+    tags: &[String],
+    address: Option<String>,
+    response: &serde_json::Value,
+) -> Option<String> {
+    let rows = normalize_transfer(&response["transfer"], None, address);
+    let anchor_txid = rows.first().and_then(|r| r.anchor_txid.clone())?;
+    if label.is_some() || !tags.is_empty() {
+        if let Err(e) = database
+            .save_transfer_labels(&anchor_txid, label, tags)
+            .await
+        {
+            warn!("Failed to store labels of send {}: {}", anchor_txid, e);
+        }
+    }
+    if let Err(e) = database.upsert_indexed_transfers(&rows).await {
+        warn!("Failed to index send {}: {}", anchor_txid, e);
+    }
+    Some(anchor_txid)
 }
 
 async fn send_handler(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<SendRequest>,
 ) -> HttpResponse {
-    handle_result(
-        send_assets(
+    let result = async {
+        let mut req = req.into_inner();
+        req.validate_tags()?;
+        check_destinations(
+            &http_req,
+            client.as_ref(),
+            &base_url.0,
+            &macaroon_hex.0,
+            &req.tap_addrs,
+        )
+        .await?;
+        anomaly::check_send(
+            &http_req,
+            client.as_ref(),
+            &base_url.0,
+            &macaroon_hex.0,
+            &req.tap_addrs,
+        )
+        .await?;
+        let label = req.label.clone();
+        let tags = std::mem::take(&mut req.tags);
+        let address = match req.tap_addrs.as_slice() {
+            [address] => Some(address.clone()),
+            _ => None,
+        };
+        let response = send_assets(client.as_ref(), &base_url.0, &macaroon_hex.0, req).await?;
+        if let Some(database) = http_req.app_data::<web::Data<SharedDatabase>>() {
+            index_send(database, label.as_deref(), &tags, address, &response).await;
+        }
+        Ok(response)
+    }
+    .await;
+    handle_result(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchSendRequest {
+    pub tap_addrs: Vec<String>,
+    pub fee_rate: Option<u32>,
+    pub label: Option<String>,
+    pub skip_proof_courier_ping_check: Option<bool>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl BatchSendRequest {
+    fn into_send(self) -> Result<SendRequest, AppError> {
+        if self.tap_addrs.is_empty() || self.tap_addrs.len() > MAX_BATCH_OUTPUTS {
+            return Err(AppError::InvalidInput(format!(
+                "A batch send pays between 1 and {MAX_BATCH_OUTPUTS} addresses"
+            )));
+        }
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = self.tap_addrs.iter().find(|a| !seen.insert(a.trim())) {
+            return Err(AppError::InvalidInput(format!(
+                "Address {duplicate} appears more than once"
+            )));
+        }
+        let request = SendRequest {
+            tap_addrs: self.tap_addrs,
+            fee_rate: self.fee_rate,
+            label: self.label,
+            skip_proof_courier_ping_check: self.skip_proof_courier_ping_check,
+            tags: self.tags,
+        };
+        request.validate_tags()?;
+        Ok(request)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStatus {
+    Sent,
+    /// tapd could not decode the address.
+    Invalid,
+    /// The destination guard refused the address.
+    Refused,
+    /// The address is fine, but the batch was not sent.
+    NotSent,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchOutput {
+    pub address: String,
+    pub status: OutputStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asset_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script_key: Option<String>,
+    /// Index of the output in tapd's transfer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anchor_outpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl BatchOutput {
+    fn new(address: &str, status: OutputStatus) -> Self {
+        Self {
+            address: address.to_string(),
+            status,
+            asset_id: None,
+            amount: None,
+            script_key: None,
+            output_index: None,
+            anchor_outpoint: None,
+            reason: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchSendResponse {
+    pub sent: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anchor_txid: Option<String>,
+    pub outputs: Vec<BatchOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<Value>,
+}
+
+/// The x-only part of a hex or base64 key, so tapd's 33-byte transfer keys
+/// match the keys addresses decode to whatever their parity byte.
+fn key_tail(key: &str) -> String {
+    let key = normalize_hex_id(key);
+    key[key.len().saturating_sub(64)..].to_string()
+}
+
+/// Marks every output of a sent batch sent, with its place in tapd's
+/// `transfer` found by script key.
+fn match_outputs(outputs: &mut [BatchOutput], transfer: &Value) {
+    let transfer_outputs = transfer["outputs"].as_array().cloned().unwrap_or_default();
+    for output in outputs.iter_mut() {
+        output.status = OutputStatus::Sent;
+        let Some(script_key) = output.script_key.as_deref().map(key_tail) else {
+            continue;
+        };
+        let found = transfer_outputs.iter().enumerate().find(|(_, o)| {
+            o["script_key"]
+                .as_str()
+                .is_some_and(|k| key_tail(k) == script_key)
+        });
+        if let Some((index, found)) = found {
+            output.output_index = Some(index);
+            output.anchor_outpoint = found["anchor"]["outpoint"].as_str().map(str::to_string);
+        }
+    }
+}
+
+/// Decodes and checks every address of a batch, one output each. Problems
+/// are reported per output rather than failing on the first.
+async fn check_batch(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    addresses: &[String],
+) -> Vec<BatchOutput> {
+    let guard = DestinationGuard::from_request(req);
+    let overridden = destination_guard::overridden(req);
+    let mut outputs = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        let request = DecodeAddrRequest {
+            addr: address.clone(),
+        };
+        let decoded = match decode_address(client, base_url, macaroon_hex, request).await {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                let mut output = BatchOutput::new(address, OutputStatus::Invalid);
+                output.reason = Some(e.to_string());
+                outputs.push(output);
+                continue;
+            }
+        };
+        let mut output = BatchOutput::new(address, OutputStatus::NotSent);
+        output.asset_id = decoded.asset_id.as_deref().map(normalize_hex_id);
+        output.amount = decoded.amount.as_deref().and_then(|a| a.parse().ok());
+        output.script_key = decoded.script_key.as_deref().map(normalize_hex_id);
+        if let Err(e) = guard.enforce(address, &guard.assess(address, &decoded), overridden) {
+            output.status = OutputStatus::Refused;
+            output.reason = Some(e.to_string());
+        }
+        outputs.push(output);
+    }
+    outputs
+}
+
+/// Pays every address in one tapd transfer, so the batch shares a single
+/// anchor transaction and its fee. Nothing is sent unless every address
+/// passes; the response then says which ones did not.
+#[instrument(skip(http_req, client, base_url, macaroon_hex, req))]
+async fn batch_send_handler(
+    http_req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    req: web::Json<BatchSendRequest>,
+) -> HttpResponse {
+    let result = async {
+        let mut req = req.into_inner().into_send()?;
+        let mut outputs = check_batch(
+            &http_req,
+            client.as_ref(),
+            &base_url.0,
+            &macaroon_hex.0,
+            &req.tap_addrs,
+        )
+        .await;
+        if outputs.iter().any(|o| o.status != OutputStatus::NotSent) {
+            return Ok((StatusCode::BAD_REQUEST, outputs, None, None));
+        }
+        anomaly::check_send(
+            &http_req,
             client.as_ref(),
             &base_url.0,
             &macaroon_hex.0,
-            req.into_inner(),
+            &req.tap_addrs,
         )
-        .await,
-    )
+        .await?;
+        info!("Sending batch to {} addresses", req.tap_addrs.len());
+        let label = req.label.clone();
+        let tags = std::mem::take(&mut req.tags);
+        let response = send_assets(client.as_ref(), &base_url.0, &macaroon_hex.0, req).await?;
+        let anchor_txid = match http_req.app_data::<web::Data<SharedDatabase>>() {
+            Some(database) => index_send(database, label.as_deref(), &tags, None, &response).await,
+            None => None,
+        };
+        match_outputs(&mut outputs, &response["transfer"]);
+        Ok((StatusCode::OK, outputs, anchor_txid, Some(response)))
+    }
+    .await;
+    match result {
+        Ok((status, outputs, anchor_txid, response)) => {
+            HttpResponse::build(status).json(BatchSendResponse {
+                sent: response.is_some(),
+                anchor_txid,
+                outputs,
+                transfer: response.map(|mut r| r["transfer"].take()),
+            })
+        }
+        Err(e) => handle_result::<()>(Err(e)),
+    }
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/send").route(web::post().to(send_handler)));
 }
+
+pub fn configure_gateway(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/send/batch").route(web::post().to(batch_send_handler)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn batch(tap_addrs: &[&str]) -> BatchSendRequest {
+        BatchSendRequest {
+            tap_addrs: tap_addrs.iter().map(|a| a.to_string()).collect(),
+            fee_rate: None,
+            label: None,
+            skip_proof_courier_ping_check: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_batch_needs_distinct_addresses() {
+        assert!(batch(&["taprt1a", "taprt1b"]).into_send().is_ok());
+        assert!(batch(&[]).into_send().is_err());
+        assert!(batch(&["taprt1a", " taprt1a"]).into_send().is_err());
+        let many: Vec<String> = (0..=MAX_BATCH_OUTPUTS)
+            .map(|i| format!("taprt1{i}"))
+            .collect();
+        let many: Vec<&str> = many.iter().map(String::as_str).collect();
+        assert!(batch(&many).into_send().is_err());
+    }
+
+    #[test]
+    fn test_match_outputs_by_script_key() {
+        let key = "ab".repeat(32);
+        let mut output = BatchOutput::new("taprt1a", OutputStatus::NotSent);
+        output.script_key = Some(format!("02{key}"));
+        let mut unmatched = BatchOutput::new("taprt1b", OutputStatus::NotSent);
+        unmatched.script_key = Some("cd".repeat(32));
+        let mut outputs = vec![output, unmatched];
+
+        let transfer = json!({
+            "outputs": [
+                { "script_key": format!("03{}", "ee".repeat(32)), "anchor": { "outpoint": "tx:0" } },
+                { "script_key": format!("03{key}"), "anchor": { "outpoint": "tx:1" } },
+            ]
+        });
+        match_outputs(&mut outputs, &transfer);
+        assert_eq!(outputs[0].status, OutputStatus::Sent);
+        assert_eq!(outputs[0].output_index, Some(1));
+        assert_eq!(outputs[0].anchor_outpoint.as_deref(), Some("tx:1"));
+        assert_eq!(outputs[1].status, OutputStatus::Sent);
+        assert_eq!(outputs[1].output_index, None);
+    }
+}
@@ -0,0 +1,247 @@
+//! Cookie sessions for browser frontends. See [`crate::sessions`] for the
+//! tokens themselves; these routes open, refresh and close sessions.
+
+use super::handle_result;
+use super::mailbox_auth::{
+    bitcoin_network, generate_challenge, redeem_challenge, verify_signature_with_key,
+};
+use crate::error::AppError;
+use crate::lockout::{LockedOutError, SharedAuthLockouts};
+use crate::middleware::{CallerRole, SessionCaller};
+use crate::oidc::{looks_like_jwt, TokenRole};
+use crate::sessions::{IssuedSession, SharedSessionManager, CSRF_HEADER, REFRESH_COOKIE};
+use crate::tenants::TenantRequest;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+/// Routes under [`crate::sessions::SESSIONS_PATH`] that need no
+/// credentials: they are how a browser without an access token gets one.
+pub const SESSION_PUBLIC_PATHS: [&str; 4] = ["/challenge", "/login", "/refresh", "/logout"];
+
+#[derive(Debug, Deserialize)]
+pub struct SessionChallengeRequest {
+    pub public_key: String,
+}
+
+/// A signed answer to a challenge from `/sessions/challenge`, in any of the
+/// forms mailbox authentication accepts.
+#[derive(Debug, Deserialize)]
+pub struct KeyLoginRequest {
+    pub public_key: String,
+    pub challenge_id: String,
+    pub signature: String,
+    #[serde(default)]
+    pub address: Option<String>,
+}
+
+fn session_manager(req: &HttpRequest) -> Result<SharedSessionManager, AppError> {
+    req.app_data::<web::Data<SharedSessionManager>>()
+        .map(|s| s.get_ref().clone())
+        .ok_or_else(|| AppError::ServiceUnavailable("Sessions are not enabled".to_string()))
+}
+
+fn unauthorized(sessions: &SharedSessionManager, message: &str) -> HttpResponse {
+    let mut res = HttpResponse::Unauthorized();
+    for cookie in sessions.removal_cookies() {
+        res.cookie(cookie);
+    }
+    res.json(serde_json::json!({ "error": message }))
+}
+
+fn with_session(
+    mut res: HttpResponseBuilder,
+    sessions: &SharedSessionManager,
+    session: &IssuedSession,
+) -> HttpResponse {
+    for cookie in sessions.cookies(session) {
+        res.cookie(cookie);
+    }
+    res.json(session)
+}
+
+/// Opens a session for a caller the auth middleware already let through
+/// with the API key, a role key or an OIDC token. Session cookies cannot
+/// open further sessions, and tenants have no sessions.
+async fn open_session(req: HttpRequest) -> HttpResponse {
+    let result = async {
+        let sessions = session_manager(&req)?;
+        if req.extensions().contains::<TenantRequest>() {
+            return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Tenant API keys cannot open sessions"
+            })));
+        }
+        let Some(token) = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        else {
+            return Err(AppError::ValidationError(
+                "Log in with a bearer API key or token, or sign a challenge".to_string(),
+            ));
+        };
+        let role = req.extensions().get::<CallerRole>().cloned();
+        let subject = match &role {
+            _ if looks_like_jwt(token) => "oidc".to_string(),
+            Some(role) => format!("role_key:{}", role.0),
+            None => "api_key".to_string(),
+        };
+        let role = role.map_or(TokenRole::Admin, |r| TokenRole::ReadOnly(r.0));
+        let session = sessions.open(&subject, &role).await?;
+        info!("Opened session {} for {}", session.session_id, subject);
+        Ok(with_session(HttpResponse::Created(), &sessions, &session))
+    }
+    .await;
+    result.unwrap_or_else(|e| handle_result::<()>(Err(e)))
+}
+
+/// First step of a key login: a challenge bound to one of the
+/// `SESSION_LOGIN_KEYS`.
+async fn challenge(req: HttpRequest, body: web::Json<SessionChallengeRequest>) -> HttpResponse {
+    let result = async {
+        let sessions = session_manager(&req)?;
+        let public_key = body.public_key.trim().to_ascii_lowercase();
+        if sessions.login_role(&public_key).is_none() {
+            return Ok(unauthorized(&sessions, "Unknown login key"));
+        }
+        let challenge = generate_challenge(Some(&public_key)).await?;
+        Ok(HttpResponse::Ok().json(challenge))
+    }
+    .await;
+    result.unwrap_or_else(|e| handle_result::<()>(Err(e)))
+}
+
+/// Second step of a key login. Failed attempts count towards a lockout of
+/// both the client address and the key.
+async fn key_login(req: HttpRequest, body: web::Json<KeyLoginRequest>) -> HttpResponse {
+    let result = async {
+        let sessions = session_manager(&req)?;
+        let login = body.into_inner();
+        let public_key = login.public_key.trim().to_ascii_lowercase();
+        let lockouts = req
+            .app_data::<web::Data<SharedAuthLockouts>>()
+            .map(|l| l.get_ref().clone());
+        let keys = lockouts.as_ref().map_or_else(Vec::new, |lockouts| {
+            vec![
+                lockouts.ip_key(req.peer_addr().map(|addr| addr.ip()), req.headers()),
+                format!("login:{public_key}"),
+            ]
+        });
+        if let Some(secs) = lockouts.as_ref().and_then(|l| l.retry_after(&keys)) {
+            return Ok(LockedOutError(secs).error_response());
+        }
+        let verified = match sessions.login_role(&public_key) {
+            None => Err("Unknown login key"),
+            Some(role) => match redeem_challenge(&login.challenge_id, &public_key) {
+                None => Err("Unknown or expired challenge"),
+                Some(message) => {
+                    if verify_signature_with_key(
+                        &message,
+                        &login.signature,
+                        &public_key,
+                        login.address.as_deref(),
+                        bitcoin_network(&req),
+                    )? {
+                        Ok(role)
+                    } else {
+                        warn!("Rejected session login signature for key {}", public_key);
+                        Err("Invalid signature")
+                    }
+                }
+            },
+        };
+        let role = match verified {
+            Ok(role) => {
+                if let Some(lockouts) = &lockouts {
+                    lockouts.record_success(&keys).await;
+                }
+                role
+            }
+            Err(message) => {
+                if let Some(lockouts) = &lockouts {
+                    lockouts.record_failure(&keys).await;
+                }
+                return Ok(unauthorized(&sessions, message));
+            }
+        };
+        let subject = format!("key:{public_key}");
+        let session = sessions.open(&subject, &role).await?;
+        info!("Opened session {} for {}", session.session_id, subject);
+        Ok(with_session(HttpResponse::Created(), &sessions, &session))
+    }
+    .await;
+    result.unwrap_or_else(|e| handle_result::<()>(Err(e)))
+}
+
+/// The CSRF token of the caller's session, which cookie-authenticated
+/// writes must send in `X-CSRF-Token`. Also sets it as the CSRF cookie
+/// again, for a page that lost it.
+async fn csrf_token(req: HttpRequest) -> HttpResponse {
+    let result = async {
+        let sessions = session_manager(&req)?;
+        if !sessions.csrf_protection() {
+            return Err(AppError::ServiceUnavailable(
+                "CSRF protection is off".to_string(),
+            ));
+        }
+        let Some(SessionCaller(session_id)) = req.extensions().get::<SessionCaller>().cloned()
+        else {
+            return Err(AppError::ValidationError(
+                "CSRF tokens belong to sessions; authenticate with the session cookie".to_string(),
+            ));
+        };
+        Ok(HttpResponse::Ok()
+            .cookie(sessions.csrf_cookie_for(&session_id))
+            .json(serde_json::json!({
+                "csrf_token": sessions.csrf_token(&session_id),
+                "header": CSRF_HEADER,
+            })))
+    }
+    .await;
+    result.unwrap_or_else(|e| handle_result::<()>(Err(e)))
+}
+
+/// Swaps the refresh cookie for new access and refresh cookies. A spent,
+/// revoked or expired refresh token clears both cookies.
+async fn refresh(req: HttpRequest) -> HttpResponse {
+    let result = async {
+        let sessions = session_manager(&req)?;
+        let Some(cookie) = req.cookie(REFRESH_COOKIE) else {
+            return Ok(unauthorized(&sessions, "No refresh token"));
+        };
+        match sessions.refresh(cookie.value()).await? {
+            Some(session) => Ok(with_session(HttpResponse::Ok(), &sessions, &session)),
+            None => Ok(unauthorized(&sessions, "Session expired")),
+        }
+    }
+    .await;
+    result.unwrap_or_else(|e| handle_result::<()>(Err(e)))
+}
+
+/// Revokes the session and clears its cookies. Succeeds without a session,
+/// so a browser can always log out.
+async fn logout(req: HttpRequest) -> HttpResponse {
+    let result = async {
+        let sessions = session_manager(&req)?;
+        if let Some(cookie) = req.cookie(REFRESH_COOKIE) {
+            sessions.close(cookie.value()).await?;
+        }
+        let mut res = HttpResponse::NoContent();
+        for cookie in sessions.removal_cookies() {
+            res.cookie(cookie);
+        }
+        Ok(res.finish())
+    }
+    .await;
+    result.unwrap_or_else(|e| handle_result::<()>(Err(e)))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/sessions").route(web::post().to(open_session)))
+        .service(web::resource("/sessions/csrf").route(web::get().to(csrf_token)))
+        .service(web::resource("/sessions/challenge").route(web::post().to(challenge)))
+        .service(web::resource("/sessions/login").route(web::post().to(key_login)))
+        .service(web::resource("/sessions/refresh").route(web::post().to(refresh)))
+        .service(web::resource("/sessions/logout").route(web::post().to(logout)));
+}
@@ -0,0 +1,261 @@
+//! Pre-flight checks for sends, burns and mints against the gateway's own
+//! indexed state. Nothing is sent to tapd, so the prediction is only as
+//! fresh as the index: it catches the common failures (insufficient settled
+//! balance, unconfirmed receives, maintenance mode, a missing burn
+//! confirmation) before a wallet UI commits to the real call.
+
+use super::{handle_result, require_database, validate_asset_id};
+use crate::config::Config;
+use crate::database::AssetPosition;
+use crate::error::AppError;
+use crate::maintenance::SharedMaintenance;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+/// Reachable during maintenance mode, which it reports as a failure.
+pub const SIMULATE_PATH: &str = "/v1/gateway/simulate";
+
+/// tapd refuses burns whose `confirmation_text` is anything else.
+const BURN_CONFIRMATION_TEXT: &str = "assets will be destroyed";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulatedOperation {
+    Send,
+    Burn,
+    Mint,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimulationRequest {
+    pub operation: SimulatedOperation,
+    /// Required for sends and burns; for a mint, the asset being reissued.
+    pub asset_id: Option<String>,
+    pub amount: u64,
+    pub confirmation_text: Option<String>,
+}
+
+/// Gateway policy the operation is checked against.
+#[derive(Debug, Clone, Copy)]
+pub struct SimulationPolicy {
+    pub maintenance: bool,
+    pub min_confirmations: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationResult {
+    pub operation: SimulatedOperation,
+    pub asset_id: Option<String>,
+    pub amount: u64,
+    pub would_succeed: bool,
+    /// Why the operation is expected to fail; empty when it should succeed.
+    pub failures: Vec<String>,
+    /// Conditions that could change the outcome once tapd sees the call.
+    pub warnings: Vec<String>,
+    pub position: AssetPosition,
+    pub available_before: u64,
+    pub remaining_balance: u64,
+    pub min_confirmations: u32,
+}
+
+/// Predicts the outcome of `request` from the indexed `position`.
+pub fn evaluate(
+    request: &SimulationRequest,
+    position: AssetPosition,
+    policy: SimulationPolicy,
+) -> SimulationResult {
+    let mut failures = Vec::new();
+    let mut warnings = Vec::new();
+    let available = position.available();
+
+    if policy.maintenance {
+        failures.push("Gateway is in maintenance mode and rejects mutating requests".to_string());
+    }
+    if request.amount == 0 {
+        failures.push("Amount must be greater than 0".to_string());
+    }
+
+    let remaining = match request.operation {
+        SimulatedOperation::Mint => available.saturating_add(request.amount),
+        SimulatedOperation::Send | SimulatedOperation::Burn => {
+            if request.operation == SimulatedOperation::Burn
+                && request.confirmation_text.as_deref() != Some(BURN_CONFIRMATION_TEXT)
+            {
+                failures.push(format!(
+                    "Burns require confirmation_text \"{BURN_CONFIRMATION_TEXT}\""
+                ));
+            }
+            if request.amount > available {
+                failures.push(format!(
+                    "Insufficient settled balance: {available} available, {} requested",
+                    request.amount
+                ));
+                if request.amount <= available.saturating_add(position.pending_in) {
+                    warnings.push(format!(
+                        "{} is in receives below {} confirmations and becomes spendable once \
+                         they settle",
+                        position.pending_in, policy.min_confirmations
+                    ));
+                }
+            }
+            available.saturating_sub(request.amount)
+        }
+    };
+    if position.pending_operations > 0 {
+        warnings.push(format!(
+            "{} outgoing operations totalling {} are unconfirmed; if they fail the balance is \
+             higher",
+            position.pending_operations, position.pending_out
+        ));
+    }
+
+    SimulationResult {
+        operation: request.operation,
+        asset_id: request.asset_id.clone(),
+        amount: request.amount,
+        would_succeed: failures.is_empty(),
+        failures,
+        warnings,
+        position,
+        available_before: available,
+        remaining_balance: remaining,
+        min_confirmations: policy.min_confirmations,
+    }
+}
+
+#[instrument(skip(req, config, request))]
+async fn run_simulation(
+    req: &HttpRequest,
+    config: &Config,
+    mut request: SimulationRequest,
+) -> Result<SimulationResult, AppError> {
+    if !config.indexer_enabled {
+        return Err(AppError::ServiceUnavailable(
+            "Simulation needs the event indexer; set INDEXER_ENABLED=true".to_string(),
+        ));
+    }
+    if let Some(asset_id) = request.asset_id.as_mut() {
+        validate_asset_id(asset_id)?;
+        // The index stores lowercase hex.
+        *asset_id = asset_id.to_ascii_lowercase();
+    } else if request.operation != SimulatedOperation::Mint {
+        return Err(AppError::InvalidInput(
+            "asset_id is required to simulate a send or burn".to_string(),
+        ));
+    }
+
+    let database = require_database(req)?;
+    let position = match &request.asset_id {
+        Some(asset_id) => {
+            database
+                .asset_position(asset_id, config.min_receive_confirmations)
+                .await?
+        }
+        None => AssetPosition::default(),
+    };
+    let policy = SimulationPolicy {
+        maintenance: req
+            .app_data::<web::Data<SharedMaintenance>>()
+            .is_some_and(|mode| mode.is_enabled()),
+        min_confirmations: config.min_receive_confirmations,
+    };
+    let result = evaluate(&request, position, policy);
+    debug!(
+        "Simulated {:?} of {}: would_succeed={}",
+        result.operation, result.amount, result.would_succeed
+    );
+    Ok(result)
+}
+
+async fn simulate(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    body: web::Json<SimulationRequest>,
+) -> HttpResponse {
+    handle_result(run_simulation(&req, &config, body.into_inner()).await)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/simulate").route(web::post().to(simulate)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: SimulationPolicy = SimulationPolicy {
+        maintenance: false,
+        min_confirmations: 3,
+    };
+
+    fn request(operation: SimulatedOperation, amount: u64) -> SimulationRequest {
+        SimulationRequest {
+            operation,
+            asset_id: Some("aa".repeat(32)),
+            amount,
+            confirmation_text: None,
+        }
+    }
+
+    fn position(settled_in: u64, pending_in: u64, outgoing: u64) -> AssetPosition {
+        AssetPosition {
+            settled_in,
+            pending_in,
+            outgoing,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_send_within_settled_balance_succeeds() {
+        let result = evaluate(
+            &request(SimulatedOperation::Send, 30),
+            position(100, 0, 20),
+            POLICY,
+        );
+        assert!(result.would_succeed);
+        assert_eq!(result.available_before, 80);
+        assert_eq!(result.remaining_balance, 50);
+    }
+
+    #[test]
+    fn test_send_relying_on_pending_receives_fails_with_warning() {
+        let result = evaluate(
+            &request(SimulatedOperation::Send, 120),
+            position(100, 50, 0),
+            POLICY,
+        );
+        assert!(!result.would_succeed);
+        assert!(result.failures[0].contains("Insufficient settled balance"));
+        assert!(result.warnings[0].contains("below 3 confirmations"));
+        assert_eq!(result.remaining_balance, 0);
+    }
+
+    #[test]
+    fn test_burn_requires_confirmation_text() {
+        let mut burn = request(SimulatedOperation::Burn, 10);
+        let result = evaluate(&burn, position(100, 0, 0), POLICY);
+        assert!(!result.would_succeed);
+
+        burn.confirmation_text = Some(BURN_CONFIRMATION_TEXT.to_string());
+        let result = evaluate(&burn, position(100, 0, 0), POLICY);
+        assert!(result.would_succeed);
+        assert_eq!(result.remaining_balance, 90);
+    }
+
+    #[test]
+    fn test_maintenance_mode_fails_every_operation() {
+        let policy = SimulationPolicy {
+            maintenance: true,
+            ..POLICY
+        };
+        let result = evaluate(
+            &request(SimulatedOperation::Mint, 10),
+            AssetPosition::default(),
+            policy,
+        );
+        assert!(!result.would_succeed);
+        assert_eq!(result.remaining_balance, 10);
+    }
+}
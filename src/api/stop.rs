@@ -1,3 +1,4 @@
+use super::backend;
 use crate::error::AppError;
 use crate::types::{BaseUrl, MacaroonHex};
 use actix_web::{web, HttpResponse};
@@ -11,14 +12,11 @@ pub async fn stop_daemon(
     macaroon_hex: &str,
 ) -> Result<serde_json::Value, AppError> {
     info!("Stopping daemon");
-    let url = format!("{base_url}/v1/taproot-assets/stop");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    let response = backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/stop")
         .json(&serde_json::json!({}))
         .send()
-        .await
-        .map_err(AppError::RequestError)?;
+        .await?;
 
     // Check the status and properly propagate errors
     if response.status().is_success() {
@@ -44,9 +42,11 @@ async fn stop_handler(
         Ok(response) => HttpResponse::Ok().json(response),
         Err(e) => {
             let status = e.status_code();
-            HttpResponse::build(status).json(serde_json::json!({
+            let mut response = HttpResponse::build(status).json(serde_json::json!({
                 "error": e.to_string()
-            }))
+            }));
+            crate::error::mark_upstream(&mut response, &e);
+            response
         }
     }
 }
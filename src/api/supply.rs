@@ -0,0 +1,303 @@
+use super::universe::get_leaves;
+use super::{burn, handle_result, validate_asset_id};
+use crate::cache_audit::SharedCacheAudit;
+use crate::config::Config;
+use crate::database::SharedDatabase;
+use crate::error::AppError;
+use crate::indexer::normalize_hex_id;
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::{web, HttpRequest, HttpResponse};
+use lazy_static::lazy_static;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument};
+
+/// Used when no [`Config`] is registered, as in the integration test apps.
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref SUPPLY_CACHE: Mutex<HashMap<String, (Instant, AssetSupply)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Where the burned amount was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BurnSource {
+    /// The gateway's burn index, which also drops replaced burns.
+    Indexer,
+    /// tapd's ListBurns, when the gateway has no database.
+    Tapd,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetSupply {
+    pub asset_id: String,
+    /// Sum of the asset's issuance leaves in the universe.
+    pub minted: u64,
+    pub burned: u64,
+    pub circulating: u64,
+    pub issuance_count: u64,
+    pub burn_count: u64,
+    pub burn_source: BurnSource,
+    /// Unix seconds when the figures were computed; responses may be served
+    /// from cache until the TTL passes.
+    pub computed_at: i64,
+}
+
+fn leaf_amounts(leaves: &Value) -> Vec<u64> {
+    leaves
+        .get("leaves")
+        .and_then(|l| l.as_array())
+        .map(|leaves| {
+            leaves
+                .iter()
+                .filter_map(|leaf| amount_field(leaf.get("asset")?, "amount"))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// tapd encodes uint64 fields as JSON strings.
+fn amount_field(value: &Value, field: &str) -> Option<u64> {
+    match value.get(field)? {
+        Value::String(s) => s.parse().ok(),
+        Value::Number(n) => n.as_u64(),
+        _ => None,
+    }
+}
+
+/// Totals the ListBurns entries for `asset_id`.
+fn tapd_burn_total(burns: &Value, asset_id: &str) -> (u64, u64) {
+    burns
+        .get("burns")
+        .and_then(|b| b.as_array())
+        .map(|burns| {
+            burns
+                .iter()
+                .filter(|b| {
+                    b.get("asset_id")
+                        .and_then(|id| id.as_str())
+                        .is_some_and(|id| normalize_hex_id(id) == asset_id)
+                })
+                .fold((0, 0), |(total, count), b| {
+                    (total + amount_field(b, "amount").unwrap_or(0), count + 1)
+                })
+        })
+        .unwrap_or((0, 0))
+}
+
+#[instrument(skip(client, macaroon_hex, database))]
+pub async fn compute_supply(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    database: Option<&SharedDatabase>,
+    asset_id: &str,
+) -> Result<AssetSupply, AppError> {
+    let leaves = get_leaves(
+        client,
+        base_url,
+        macaroon_hex,
+        asset_id,
+        "proof_type=PROOF_TYPE_ISSUANCE",
+    )
+    .await?;
+    let issuances = leaf_amounts(&leaves);
+    if issuances.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "No issuance proofs for asset {asset_id} in the universe"
+        )));
+    }
+    let minted: u64 = issuances.iter().sum();
+
+    let (burned, burn_count, burn_source) = match database {
+        Some(database) => {
+            let totals = database.burn_totals(Some(asset_id), None).await?;
+            let total = totals.first();
+            (
+                total.map_or(0, |t| t.total_burned),
+                total.map_or(0, |t| t.burn_count),
+                BurnSource::Indexer,
+            )
+        }
+        None => {
+            let burns = burn::list_burns(client, base_url, macaroon_hex, "").await?;
+            let (burned, count) = tapd_burn_total(&burns, asset_id);
+            (burned, count, BurnSource::Tapd)
+        }
+    };
+
+    Ok(AssetSupply {
+        asset_id: asset_id.to_string(),
+        minted,
+        burned,
+        circulating: minted.saturating_sub(burned),
+        issuance_count: issuances.len() as u64,
+        burn_count,
+        burn_source,
+        computed_at: chrono::Utc::now().timestamp(),
+    })
+}
+
+/// A live entry and how long it has been cached.
+fn cached(asset_id: &str, ttl: Duration) -> Option<(Duration, AssetSupply)> {
+    let cache = SUPPLY_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    cache
+        .get(asset_id)
+        .filter(|(at, _)| at.elapsed() < ttl)
+        .map(|(at, supply)| (at.elapsed(), supply.clone()))
+}
+
+/// The supply as the cache audit compares it; `computed_at` always
+/// differs.
+fn audited_value(supply: &AssetSupply) -> Result<Value, AppError> {
+    let mut value = serde_json::to_value(supply)?;
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("computed_at");
+    }
+    Ok(value)
+}
+
+/// Supply figures held in the cache, expired ones included until the next
+/// store.
+pub fn cache_len() -> usize {
+    SUPPLY_CACHE.lock().unwrap_or_else(|e| e.into_inner()).len()
+}
+
+fn store(supply: &AssetSupply, ttl: Duration) {
+    let mut cache = SUPPLY_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    cache.retain(|_, (at, _)| at.elapsed() < ttl);
+    cache.insert(supply.asset_id.clone(), (Instant::now(), supply.clone()));
+}
+
+/// [`compute_supply`] through the cache, with the TTL and database taken from
+/// the request's app data. `asset_id` must be lowercase hex.
+pub async fn cached_supply(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    asset_id: &str,
+) -> Result<AssetSupply, AppError> {
+    let ttl = req
+        .app_data::<web::Data<Config>>()
+        .map_or(DEFAULT_CACHE_TTL, |c| {
+            Duration::from_secs(c.supply_cache_ttl_secs)
+        });
+    let database = req
+        .app_data::<web::Data<SharedDatabase>>()
+        .map(|db| db.get_ref());
+    if let Some((age, supply)) = cached(asset_id, ttl) {
+        debug!("Serving cached supply for {}", asset_id);
+        let audit = req.app_data::<web::Data<SharedCacheAudit>>();
+        if let Some(audit) = audit.filter(|audit| audit.should_sample()) {
+            let cached = audited_value(&supply)?;
+            let (client, base_url, macaroon_hex, database, asset_id) = (
+                client.clone(),
+                base_url.to_string(),
+                macaroon_hex.to_string(),
+                database.cloned(),
+                asset_id.to_string(),
+            );
+            audit.check("supply", asset_id.clone(), cached, age, async move {
+                let fresh = compute_supply(
+                    &client,
+                    &base_url,
+                    &macaroon_hex,
+                    database.as_ref(),
+                    &asset_id,
+                )
+                .await?;
+                audited_value(&fresh)
+            });
+        }
+        return Ok(supply);
+    }
+
+    let supply = compute_supply(client, base_url, macaroon_hex, database, asset_id).await?;
+    if !ttl.is_zero() {
+        store(&supply, ttl);
+    }
+    Ok(supply)
+}
+
+async fn supply_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+) -> HttpResponse {
+    let result = async {
+        let asset_id = path.into_inner();
+        validate_asset_id(&asset_id)?;
+        let asset_id = asset_id.to_ascii_lowercase();
+        cached_supply(&req, &client, &base_url.0, &macaroon_hex.0, &asset_id).await
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/assets/{asset_id}/supply").route(web::get().to(supply_handler)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use serde_json::json;
+
+    const ASSET_HEX: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+
+    #[test]
+    fn test_leaf_amounts_reads_issuance_leaves() {
+        let leaves = json!({
+            "leaves": [
+                { "asset": { "amount": "1000" } },
+                { "asset": { "amount": 5 } },
+                { "proof": "..." }
+            ]
+        });
+        assert_eq!(leaf_amounts(&leaves), vec![1000, 5]);
+        assert!(leaf_amounts(&json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_tapd_burn_total_matches_base64_asset_ids() {
+        let b64 = base64::engine::general_purpose::STANDARD.encode([0x11u8; 32]);
+        let burns = json!({
+            "burns": [
+                { "asset_id": b64, "amount": "10" },
+                { "asset_id": ASSET_HEX, "amount": "15" },
+                { "asset_id": "22".repeat(32), "amount": "99" }
+            ]
+        });
+        assert_eq!(tapd_burn_total(&burns, ASSET_HEX), (25, 2));
+    }
+
+    #[test]
+    fn test_cache_expires_after_ttl() {
+        let supply = AssetSupply {
+            asset_id: "cache-test".to_string(),
+            minted: 10,
+            burned: 1,
+            circulating: 9,
+            issuance_count: 1,
+            burn_count: 1,
+            burn_source: BurnSource::Tapd,
+            computed_at: 0,
+        };
+        store(&supply, Duration::from_secs(60));
+        assert_eq!(
+            cached("cache-test", Duration::from_secs(60)).map(|(_, s)| s.circulating),
+            Some(9)
+        );
+        assert!(cached("cache-test", Duration::ZERO).is_none());
+    }
+}
@@ -0,0 +1,465 @@
+use super::mailbox::outbox;
+use super::{
+    handle_result, public_url, require_database, validate_asset_id, validate_callback_url,
+    ListEnvelope, PageParams,
+};
+use crate::config::Config;
+use crate::database::{Swap, SwapQuery, SwapRole, SwapStatus};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::feature_flags::{self, Feature};
+use crate::swaps::{SwapCoordinator, SwapLeg, SwapMessage};
+use crate::types::{BaseUrl, LndNode, MacaroonHex};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::instrument;
+
+/// Longest timeout a swap may be proposed or accepted with (7 days)
+const MAX_TIMEOUT_SECS: u64 = 7 * 24 * 60 * 60;
+
+fn validate_leg(name: &str, leg: &SwapLeg) -> Result<(), AppError> {
+    if let Some(asset_id) = &leg.asset_id {
+        validate_asset_id(asset_id)?;
+    }
+    if leg.amount == 0 {
+        return Err(AppError::ValidationError(format!(
+            "{name}.amount must be greater than zero"
+        )));
+    }
+    Ok(())
+}
+
+fn validate_callback(url: &Option<String>, secret: &Option<String>) -> Result<(), AppError> {
+    match (url, secret) {
+        (Some(url), _) => validate_callback_url(url),
+        (None, Some(_)) => Err(AppError::ValidationError(
+            "callback_secret requires callback_url".to_string(),
+        )),
+        (None, None) => Ok(()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSwapRequest {
+    /// Mailbox receiver id of the counterparty.
+    pub counterparty_id: String,
+    /// Mailbox receiver id the counterparty answers to.
+    pub reply_to: String,
+    /// What this node gives; satoshis when `asset_id` is absent.
+    pub offer: SwapLeg,
+    /// What this node receives; must be an asset.
+    pub ask: SwapLeg,
+    /// This node's funded virtual PSBT for the offer, from
+    /// `wallet/virtual-psbt/fund`. Required when offering an asset.
+    pub funded_psbt: Option<String>,
+    #[serde(default)]
+    pub passive_asset_psbts: Vec<String>,
+    /// Defaults to `SWAP_DEFAULT_TIMEOUT_SECS`.
+    pub timeout_secs: Option<u64>,
+    /// Proof of a transaction paying the counterparty's mailbox fee, if it
+    /// asks for one.
+    pub tx_proof: Option<serde_json::Value>,
+    pub callback_url: Option<String>,
+    pub callback_secret: Option<String>,
+}
+
+impl CreateSwapRequest {
+    fn validate(&self) -> Result<(), AppError> {
+        if self.counterparty_id.trim().is_empty() || self.reply_to.trim().is_empty() {
+            return Err(AppError::ValidationError(
+                "counterparty_id and reply_to are required".to_string(),
+            ));
+        }
+        validate_leg("offer", &self.offer)?;
+        validate_leg("ask", &self.ask)?;
+        if self.ask.asset_id.is_none() {
+            return Err(AppError::ValidationError(
+                "ask must be an asset; only the initiator may give satoshis".to_string(),
+            ));
+        }
+        if self.offer.asset_id.is_some() != self.funded_psbt.is_some() {
+            return Err(AppError::ValidationError(
+                "funded_psbt is required to offer an asset, and only then".to_string(),
+            ));
+        }
+        if let Some(timeout) = self.timeout_secs {
+            if timeout == 0 || timeout > MAX_TIMEOUT_SECS {
+                return Err(AppError::ValidationError(format!(
+                    "timeout_secs must be between 1 and {MAX_TIMEOUT_SECS}"
+                )));
+            }
+        }
+        validate_callback(&self.callback_url, &self.callback_secret)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptSwapRequest {
+    /// The `proposal` payload from the mailbox.
+    pub payload: String,
+    /// This node's funded virtual PSBT for the asset the proposal asks for.
+    pub funded_psbt: String,
+    #[serde(default)]
+    pub passive_asset_psbts: Vec<String>,
+    pub tx_proof: Option<serde_json::Value>,
+    pub callback_url: Option<String>,
+    pub callback_secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SwapMessageRequest {
+    /// A swap message payload from the mailbox.
+    pub payload: String,
+}
+
+/// The swap record a proposal leaves with the responder: the legs swap
+/// sides, and answers go to the initiator's `reply_to`.
+fn responder_swap(proposal: SwapMessage, request: AcceptSwapRequest) -> Result<Swap, AppError> {
+    let SwapMessage::Proposal {
+        swap_id,
+        reply_to,
+        offer,
+        ask,
+        expires_at,
+        ..
+    } = proposal
+    else {
+        return Err(AppError::ValidationError(
+            "payload is not a swap proposal".to_string(),
+        ));
+    };
+    if uuid::Uuid::parse_str(&swap_id).is_err() || reply_to.trim().is_empty() {
+        return Err(AppError::ValidationError(
+            "Proposal has an invalid swap_id or reply_to".to_string(),
+        ));
+    }
+    validate_leg("offer", &offer)?;
+    validate_leg("ask", &ask)?;
+    if ask.asset_id.is_none() {
+        return Err(AppError::ValidationError(
+            "Proposal asks for satoshis, which a responder cannot give".to_string(),
+        ));
+    }
+    let now = chrono::Utc::now().timestamp();
+    if expires_at <= now || expires_at > now + MAX_TIMEOUT_SECS as i64 {
+        return Err(AppError::ValidationError(
+            "Proposal has expired or runs longer than 7 days".to_string(),
+        ));
+    }
+    validate_callback(&request.callback_url, &request.callback_secret)?;
+
+    Ok(Swap {
+        id: swap_id,
+        role: SwapRole::Responder,
+        counterparty_id: reply_to,
+        offer_asset_id: ask.asset_id.map(|id| id.to_ascii_lowercase()),
+        offer_amount: ask.amount,
+        ask_asset_id: offer.asset_id.map(|id| id.to_ascii_lowercase()),
+        ask_amount: offer.amount,
+        status: SwapStatus::Accepted,
+        own_psbt: Some(request.funded_psbt),
+        passive_asset_psbts: request.passive_asset_psbts,
+        counterparty_psbt: None,
+        btc_address: None,
+        anchor: None,
+        anchor_txid: None,
+        tx_proof: request.tx_proof,
+        last_error: None,
+        expires_at,
+        callback_url: request.callback_url,
+        callback_secret: request.callback_secret,
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+/// Refuses the request unless `SWAPS_ENABLED` is set and the `swaps` flag
+/// is on for the caller.
+fn require_swaps(req: &HttpRequest, config: &Config) -> Result<(), AppError> {
+    feature_flags::require(req, Feature::Swaps)?;
+    if !config.swaps_enabled {
+        return Err(AppError::ServiceUnavailable(
+            "Swaps require SWAPS_ENABLED".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The swap coordinator for this request's node, when `SWAPS_ENABLED` is set.
+fn coordinator(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    config: &Config,
+) -> Result<SwapCoordinator, AppError> {
+    require_swaps(req, config)?;
+    let events = req
+        .app_data::<web::Data<SharedEventBus>>()
+        .ok_or_else(|| AppError::ServiceUnavailable("Event bus not configured".to_string()))?;
+    let lnd = req
+        .app_data::<web::Data<LndNode>>()
+        .and_then(|lnd| lnd.0.clone());
+    Ok(SwapCoordinator::new(
+        client.clone(),
+        base_url.to_string(),
+        macaroon_hex.to_string(),
+        lnd,
+        require_database(req)?,
+        events.get_ref().clone(),
+    )
+    .with_outbox(outbox(req, client, base_url, macaroon_hex)?))
+}
+
+fn created(req: &HttpRequest, result: Result<Swap, AppError>) -> HttpResponse {
+    match result {
+        Ok(swap) => HttpResponse::build(StatusCode::CREATED)
+            .insert_header((
+                header::LOCATION,
+                public_url(req, &format!("/v1/gateway/swaps/{}", swap.id)),
+            ))
+            .json(swap),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, config, body))]
+async fn create(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    body: web::Json<CreateSwapRequest>,
+) -> HttpResponse {
+    let result = async {
+        let coordinator = coordinator(&req, &client, &base_url.0, &macaroon_hex.0, &config)?;
+        let request = body.into_inner();
+        request.validate()?;
+        let now = chrono::Utc::now().timestamp();
+        let timeout = request
+            .timeout_secs
+            .unwrap_or(config.swap_default_timeout_secs);
+        let swap = Swap {
+            id: uuid::Uuid::new_v4().to_string(),
+            role: SwapRole::Initiator,
+            counterparty_id: request.counterparty_id,
+            offer_asset_id: request.offer.asset_id.map(|id| id.to_ascii_lowercase()),
+            offer_amount: request.offer.amount,
+            ask_asset_id: request.ask.asset_id.map(|id| id.to_ascii_lowercase()),
+            ask_amount: request.ask.amount,
+            status: SwapStatus::Proposed,
+            own_psbt: request.funded_psbt,
+            passive_asset_psbts: request.passive_asset_psbts,
+            counterparty_psbt: None,
+            btc_address: None,
+            anchor: None,
+            anchor_txid: None,
+            tx_proof: request.tx_proof,
+            last_error: None,
+            expires_at: now + timeout as i64,
+            callback_url: request.callback_url,
+            callback_secret: request.callback_secret,
+            created_at: now,
+            updated_at: now,
+        };
+        coordinator.propose(swap, request.reply_to).await
+    }
+    .await;
+    created(&req, result)
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, config, body))]
+async fn accept(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    body: web::Json<AcceptSwapRequest>,
+) -> HttpResponse {
+    let result = async {
+        let request = body.into_inner();
+        let proposal = SwapMessage::decode(&request.payload)?;
+        let swap = responder_swap(proposal, request)?;
+        coordinator(&req, &client, &base_url.0, &macaroon_hex.0, &config)?
+            .accept(swap)
+            .await
+    }
+    .await;
+    created(&req, result)
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, config, body))]
+async fn message(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    body: web::Json<SwapMessageRequest>,
+) -> HttpResponse {
+    let result = async {
+        let message = SwapMessage::decode(&body.payload)?;
+        if let SwapMessage::Proposal { .. } = message {
+            return Err(AppError::ValidationError(
+                "Proposals are accepted through /v1/gateway/swaps/accept".to_string(),
+            ));
+        }
+        coordinator(&req, &client, &base_url.0, &macaroon_hex.0, &config)?
+            .receive(&path.into_inner(), message)
+            .await
+    }
+    .await;
+    handle_result(result)
+}
+
+#[instrument(skip(req, client, base_url, macaroon_hex, config))]
+async fn cancel(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let result = async {
+        coordinator(&req, &client, &base_url.0, &macaroon_hex.0, &config)?
+            .cancel(&path.into_inner())
+            .await
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn list(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    query: web::Query<SwapQuery>,
+) -> HttpResponse {
+    let result = async {
+        require_swaps(&req, &config)?;
+        let database = require_database(&req)?;
+        let page = PageParams::from_query(req.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let query = SwapQuery {
+            limit: Some(limit + 1),
+            offset: Some(offset),
+            ..query.into_inner()
+        };
+        let swaps = database.list_swaps(&query).await?;
+        Ok(ListEnvelope::from_offset_page(swaps, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn get(req: HttpRequest, config: web::Data<Config>, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        require_swaps(&req, &config)?;
+        let id = path.into_inner();
+        require_database(&req)?
+            .get_swap(&id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Swap {id} not found")))
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/swaps")
+            .route(web::get().to(list))
+            .route(web::post().to(create)),
+    )
+    .service(web::resource("/swaps/accept").route(web::post().to(accept)))
+    .service(web::resource("/swaps/{id}").route(web::get().to(get)))
+    .service(web::resource("/swaps/{id}/messages").route(web::post().to(message)))
+    .service(web::resource("/swaps/{id}/cancel").route(web::post().to(cancel)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leg(asset: bool, amount: u64) -> SwapLeg {
+        SwapLeg {
+            asset_id: asset.then(|| "a".repeat(64)),
+            amount,
+        }
+    }
+
+    fn request(offer: SwapLeg, ask: SwapLeg, funded: bool) -> CreateSwapRequest {
+        CreateSwapRequest {
+            counterparty_id: "02ab".to_string(),
+            reply_to: "03cd".to_string(),
+            offer,
+            ask,
+            funded_psbt: funded.then(|| "cHNidP8=".to_string()),
+            passive_asset_psbts: Vec::new(),
+            timeout_secs: None,
+            tx_proof: None,
+            callback_url: None,
+            callback_secret: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_legs() {
+        assert!(request(leg(true, 5), leg(true, 10), true)
+            .validate()
+            .is_ok());
+        assert!(request(leg(false, 5000), leg(true, 10), false)
+            .validate()
+            .is_ok());
+        // Asking for satoshis, a zero leg, and a mismatched funded leg
+        assert!(request(leg(true, 5), leg(false, 10), true)
+            .validate()
+            .is_err());
+        assert!(request(leg(true, 0), leg(true, 10), true)
+            .validate()
+            .is_err());
+        assert!(request(leg(true, 5), leg(true, 10), false)
+            .validate()
+            .is_err());
+        assert!(request(leg(false, 5), leg(true, 10), true)
+            .validate()
+            .is_err());
+
+        let mut req = request(leg(true, 5), leg(true, 10), true);
+        req.timeout_secs = Some(MAX_TIMEOUT_SECS + 1);
+        assert!(req.validate().is_err());
+    }
+
+    #[test]
+    fn test_responder_swap_mirrors_the_proposal() {
+        let proposal = SwapMessage::Proposal {
+            swap_id: uuid::Uuid::new_v4().to_string(),
+            reply_to: "03cd".to_string(),
+            offer: leg(false, 5000),
+            ask: leg(true, 10),
+            funded_psbt: None,
+            expires_at: chrono::Utc::now().timestamp() + 600,
+        };
+        let accept = AcceptSwapRequest {
+            payload: String::new(),
+            funded_psbt: "cHNidP8=".to_string(),
+            passive_asset_psbts: Vec::new(),
+            tx_proof: None,
+            callback_url: None,
+            callback_secret: None,
+        };
+        let swap = responder_swap(proposal, accept).unwrap();
+        assert_eq!(swap.role, SwapRole::Responder);
+        assert_eq!(swap.counterparty_id, "03cd");
+        assert_eq!(swap.offer_asset_id, Some("a".repeat(64)));
+        assert_eq!(swap.offer_amount, 10);
+        assert_eq!(swap.ask_asset_id, None);
+        assert_eq!(swap.ask_amount, 5000);
+        assert_eq!(swap.status, SwapStatus::Accepted);
+    }
+}
@@ -1,9 +1,16 @@
 use super::{
-    handle_result, parse_upstream, validate_group_key, validate_hex_param, validate_integer_param,
-    with_query,
+    backend, handle_result, list_response, relay, require_database, split_list_query,
+    validate_asset_id, validate_group_key, validate_hex_param, validate_integer_param,
+    ListEnvelope, PageParams,
 };
+use crate::backend::BackendRequest;
+use crate::config::Config;
+use crate::database::{SharedDatabase, UniverseSyncStatus};
 use crate::error::AppError;
-use crate::types::{BaseUrl, MacaroonHex};
+use crate::types::{AssetId, BaseUrl, MacaroonHex, Outpoint, ScriptKey};
+use crate::universe_sync::{
+    sync_response, UniverseSyncRunner, DEFAULT_MAX_ATTEMPTS, SYNC_ID_HEADER,
+};
 use actix_web::{web, HttpRequest, HttpResponse};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
@@ -76,14 +83,10 @@ pub async fn delete_universe(
     macaroon_hex: &str,
 ) -> Result<Value, AppError> {
     info!("Deleting universe");
-    let url = format!("{base_url}/v1/taproot-assets/universe/delete");
-    let response = client
-        .delete(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .delete("/v1/taproot-assets/universe/delete")
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -93,14 +96,10 @@ pub async fn delete_federation(
     macaroon_hex: &str,
 ) -> Result<Value, AppError> {
     info!("Deleting federation");
-    let url = format!("{base_url}/v1/taproot-assets/universe/federation");
-    let response = client
-        .delete(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .delete("/v1/taproot-assets/universe/federation")
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -111,15 +110,11 @@ pub async fn add_federation(
     request: FederationRequest,
 ) -> Result<Value, AppError> {
     info!("Adding federation");
-    let url = format!("{base_url}/v1/taproot-assets/universe/federation");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/universe/federation")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -129,14 +124,10 @@ pub async fn get_federation(
     macaroon_hex: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching federation info");
-    let url = format!("{base_url}/v1/taproot-assets/universe/federation");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/universe/federation")
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -146,14 +137,25 @@ pub async fn get_universe_info(
     macaroon_hex: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching universe info");
-    let url = format!("{base_url}/v1/taproot-assets/universe/info");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/universe/info")
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
+}
+
+fn keys_request(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    asset_id: &str,
+    query: &str,
+) -> BackendRequest {
+    info!("Fetching keys for asset ID: {}", asset_id);
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/universe/keys/asset-id/{asset_id}"
+        ))
+        .query(query)
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -164,18 +166,24 @@ pub async fn get_keys(
     asset_id: &str,
     query: &str,
 ) -> Result<Value, AppError> {
-    info!("Fetching keys for asset ID: {}", asset_id);
-    let url = with_query(
-        format!("{base_url}/v1/taproot-assets/universe/keys/asset-id/{asset_id}"),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    keys_request(client, base_url, macaroon_hex, asset_id, query)
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
+}
+
+fn leaves_request(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    asset_id: &str,
+    query: &str,
+) -> BackendRequest {
+    info!("Fetching leaves for asset ID: {}", asset_id);
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/universe/leaves/asset-id/{asset_id}"
+        ))
+        .query(query)
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -186,42 +194,61 @@ pub async fn get_leaves(
     asset_id: &str,
     query: &str,
 ) -> Result<Value, AppError> {
-    info!("Fetching leaves for asset ID: {}", asset_id);
-    let url = with_query(
-        format!("{base_url}/v1/taproot-assets/universe/leaves/asset-id/{asset_id}"),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    leaves_request(client, base_url, macaroon_hex, asset_id, query)
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
-#[instrument(skip(client, macaroon_hex, request))]
-pub async fn get_multiverse(
+#[instrument(skip(client, macaroon_hex))]
+pub async fn get_group_keys(
     client: &Client,
     base_url: &str,
     macaroon_hex: &str,
-    request: MultiverseRequest,
+    group_key: &str,
+    query: &str,
+) -> Result<Value, AppError> {
+    info!("Fetching keys for group key: {}", group_key);
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/universe/keys/group-key/{group_key}"
+        ))
+        .query(query)
+        .fetch::<Value>()
+        .await
+}
+
+#[instrument(skip(client, macaroon_hex))]
+pub async fn get_group_leaves(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    group_key: &str,
+    query: &str,
 ) -> Result<Value, AppError> {
+    info!("Fetching leaves for group key: {}", group_key);
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/universe/leaves/group-key/{group_key}"
+        ))
+        .query(query)
+        .fetch::<Value>()
+        .await
+}
+
+pub fn multiverse_request(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    request: MultiverseRequest,
+) -> BackendRequest {
     info!("Fetching multiverse data");
-    let url = format!("{base_url}/v1/taproot-assets/universe/multiverse");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/universe/multiverse")
         .json(&request)
-        .send()
-        .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[allow(clippy::too_many_arguments)]
-#[instrument(skip(client, macaroon_hex))]
-pub async fn get_proofs(
+pub fn proofs_request(
     client: &Client,
     base_url: &str,
     macaroon_hex: &str,
@@ -230,21 +257,13 @@ pub async fn get_proofs(
     index: &str,
     script_key: &str,
     query: &str,
-) -> Result<Value, AppError> {
+) -> BackendRequest {
     info!("Fetching proofs for asset ID: {}", asset_id);
-    let url = with_query(
-        format!(
-            "{base_url}/v1/taproot-assets/universe/proofs/asset-id/{asset_id}/{hash_str}/{index}/{script_key}"
-        ),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
-        .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/universe/proofs/asset-id/{asset_id}/{hash_str}/{index}/{script_key}"
+        ))
+        .query(query)
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -260,17 +279,25 @@ pub async fn push_proof(
     script_key: &str,
 ) -> Result<Value, AppError> {
     info!("Pushing proof for asset ID: {}", asset_id);
-    let url = format!(
-        "{base_url}/v1/taproot-assets/universe/proofs/push/asset-id/{asset_id}/{hash_str}/{index}/{script_key}"
-    );
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post(&format!(
+            "/v1/taproot-assets/universe/proofs/push/asset-id/{asset_id}/{hash_str}/{index}/{script_key}"
+        ))
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
+}
+
+fn roots_request(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    query: &str,
+) -> BackendRequest {
+    info!("Fetching universe roots");
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/universe/roots")
+        .query(query)
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -280,40 +307,24 @@ pub async fn get_roots(
     macaroon_hex: &str,
     query: &str,
 ) -> Result<Value, AppError> {
-    info!("Fetching universe roots");
-    let url = with_query(
-        format!("{base_url}/v1/taproot-assets/universe/roots"),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    roots_request(client, base_url, macaroon_hex, query)
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
-#[instrument(skip(client, macaroon_hex))]
-pub async fn get_asset_roots(
+pub fn asset_roots_request(
     client: &Client,
     base_url: &str,
     macaroon_hex: &str,
     asset_id: &str,
     query: &str,
-) -> Result<Value, AppError> {
+) -> BackendRequest {
     info!("Fetching asset roots for asset ID: {}", asset_id);
-    let url = with_query(
-        format!("{base_url}/v1/taproot-assets/universe/roots/asset-id/{asset_id}"),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
-        .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/universe/roots/asset-id/{asset_id}"
+        ))
+        .query(query)
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -323,14 +334,10 @@ pub async fn get_stats(
     macaroon_hex: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching universe stats");
-    let url = format!("{base_url}/v1/taproot-assets/universe/stats");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/universe/stats")
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -341,17 +348,11 @@ pub async fn get_asset_stats(
     query: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching asset stats");
-    let url = with_query(
-        format!("{base_url}/v1/taproot-assets/universe/stats/assets"),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/universe/stats/assets")
+        .query(query)
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -362,17 +363,11 @@ pub async fn get_event_stats(
     query: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching event stats");
-    let url = with_query(
-        format!("{base_url}/v1/taproot-assets/universe/stats/events"),
-        query,
-    );
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/universe/stats/events")
+        .query(query)
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -383,15 +378,11 @@ pub async fn sync_universe(
     request: SyncRequest,
 ) -> Result<Value, AppError> {
     info!("Syncing universe with host: {}", request.universe_host);
-    let url = format!("{base_url}/v1/taproot-assets/universe/sync");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/universe/sync")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -402,15 +393,11 @@ pub async fn set_sync_config(
     request: SyncConfigRequest,
 ) -> Result<Value, AppError> {
     info!("Setting sync configuration");
-    let url = format!("{base_url}/v1/taproot-assets/universe/sync/config");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/universe/sync/config")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -420,14 +407,10 @@ pub async fn get_sync_config(
     macaroon_hex: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching sync configuration");
-    let url = format!("{base_url}/v1/taproot-assets/universe/sync/config");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get("/v1/taproot-assets/universe/sync/config")
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -442,18 +425,13 @@ pub async fn fetch_supply_commit(
         "Fetching supply commitment for group key: {}",
         group_key_str
     );
-    let mut url = format!("{base_url}/v1/taproot-assets/universe/supply/{group_key_str}");
-    if !query.is_empty() {
-        url.push('?');
-        url.push_str(query);
-    }
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/universe/supply/{group_key_str}"
+        ))
+        .query(query)
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -468,15 +446,13 @@ pub async fn insert_supply_commit(
         "Inserting supply commitment for group key: {}",
         group_key_str
     );
-    let url = format!("{base_url}/v1/taproot-assets/universe/supply/{group_key_str}");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post(&format!(
+            "/v1/taproot-assets/universe/supply/{group_key_str}"
+        ))
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -487,15 +463,11 @@ pub async fn ignore_asset_outpoint(
     request: IgnoreAssetOutPointRequest,
 ) -> Result<Value, AppError> {
     info!("Ignoring asset outpoint");
-    let url = format!("{base_url}/v1/taproot-assets/universe/supply/ignore");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/universe/supply/ignore")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -507,18 +479,13 @@ pub async fn fetch_supply_leaves(
     query: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching supply leaves for group key: {}", group_key_str);
-    let mut url = format!("{base_url}/v1/taproot-assets/universe/supply/leaves/{group_key_str}");
-    if !query.is_empty() {
-        url.push('?');
-        url.push_str(query);
-    }
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/universe/supply/leaves/{group_key_str}"
+        ))
+        .query(query)
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -533,15 +500,13 @@ pub async fn update_supply_commit(
         "Updating supply commitment for group key: {}",
         group_key_str
     );
-    let url = format!("{base_url}/v1/taproot-assets/universe/supply/update/{group_key_str}");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post(&format!(
+            "/v1/taproot-assets/universe/supply/update/{group_key_str}"
+        ))
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 async fn delete_handler(
@@ -601,18 +566,39 @@ async fn keys_handler(
     path: web::Path<String>,
 ) -> HttpResponse {
     let asset_id = path.into_inner();
-    if let Err(e) = validate_hex_param(&asset_id) {
+    if let Err(e) = asset_id.parse::<AssetId>() {
         return handle_result::<serde_json::Value>(Err(e));
     }
-    handle_result(
+    let (page, query) = match split_list_query(http_req.query_string()) {
+        Ok(split) => split,
+        Err(e) => return handle_result::<serde_json::Value>(Err(e)),
+    };
+    if !page.wants_envelope() {
+        return relay(
+            keys_request(
+                client.as_ref(),
+                &base_url.0,
+                &macaroon_hex.0,
+                &asset_id,
+                &query,
+            )
+            .send()
+            .await,
+        )
+        .await;
+    }
+    list_response(
+        &http_req,
+        &page,
         get_keys(
             client.as_ref(),
             &base_url.0,
             &macaroon_hex.0,
             &asset_id,
-            http_req.query_string(),
+            &query,
         )
         .await,
+        "asset_keys",
     )
 }
 
@@ -624,18 +610,39 @@ async fn leaves_handler(
     path: web::Path<String>,
 ) -> HttpResponse {
     let asset_id = path.into_inner();
-    if let Err(e) = validate_hex_param(&asset_id) {
+    if let Err(e) = asset_id.parse::<AssetId>() {
         return handle_result::<serde_json::Value>(Err(e));
     }
-    handle_result(
+    let (page, query) = match split_list_query(http_req.query_string()) {
+        Ok(split) => split,
+        Err(e) => return handle_result::<serde_json::Value>(Err(e)),
+    };
+    if !page.wants_envelope() {
+        return relay(
+            leaves_request(
+                client.as_ref(),
+                &base_url.0,
+                &macaroon_hex.0,
+                &asset_id,
+                &query,
+            )
+            .send()
+            .await,
+        )
+        .await;
+    }
+    list_response(
+        &http_req,
+        &page,
         get_leaves(
             client.as_ref(),
             &base_url.0,
             &macaroon_hex.0,
             &asset_id,
-            http_req.query_string(),
+            &query,
         )
         .await,
+        "leaves",
     )
 }
 
@@ -691,14 +698,15 @@ async fn ignore_asset_outpoint_handler(
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<IgnoreAssetOutPointRequest>,
 ) -> HttpResponse {
+    let mut request = req.into_inner();
+    if let Some(anchor) = request.asset_out_point.get_mut("anchor_out_point") {
+        *anchor = match Outpoint::normalize(anchor) {
+            Ok(outpoint) => outpoint,
+            Err(e) => return handle_result::<serde_json::Value>(Err(e)),
+        };
+    }
     handle_result(
-        ignore_asset_outpoint(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
-        )
-        .await,
+        ignore_asset_outpoint(client.as_ref(), &base_url.0, &macaroon_hex.0, request).await,
     )
 }
 
@@ -754,15 +762,17 @@ async fn multiverse_handler(
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<MultiverseRequest>,
 ) -> HttpResponse {
-    handle_result(
-        get_multiverse(
+    relay(
+        multiverse_request(
             client.as_ref(),
             &base_url.0,
             &macaroon_hex.0,
             req.into_inner(),
         )
+        .send()
         .await,
     )
+    .await
 }
 
 async fn proofs_handler(
@@ -773,15 +783,16 @@ async fn proofs_handler(
     macaroon_hex: web::Data<MacaroonHex>,
 ) -> HttpResponse {
     let (asset_id, hash_str, index, script_key) = path.into_inner();
-    if let Err(e) = validate_hex_param(&asset_id)
+    if let Err(e) = asset_id
+        .parse::<AssetId>()
         .and_then(|_| validate_hex_param(&hash_str))
         .and_then(|_| validate_integer_param(&index))
-        .and_then(|_| validate_hex_param(&script_key))
+        .and_then(|_| script_key.parse::<ScriptKey>())
     {
         return handle_result::<serde_json::Value>(Err(e));
     }
-    handle_result(
-        get_proofs(
+    relay(
+        proofs_request(
             client.as_ref(),
             &base_url.0,
             &macaroon_hex.0,
@@ -791,8 +802,10 @@ async fn proofs_handler(
             &script_key,
             http_req.query_string(),
         )
+        .send()
         .await,
     )
+    .await
 }
 
 async fn push_proof_handler(
@@ -803,10 +816,11 @@ async fn push_proof_handler(
     req: web::Json<PushProofRequest>,
 ) -> HttpResponse {
     let (asset_id, hash_str, index, script_key) = path.into_inner();
-    if let Err(e) = validate_hex_param(&asset_id)
+    if let Err(e) = asset_id
+        .parse::<AssetId>()
         .and_then(|_| validate_hex_param(&hash_str))
         .and_then(|_| validate_integer_param(&index))
-        .and_then(|_| validate_hex_param(&script_key))
+        .and_then(|_| script_key.parse::<ScriptKey>())
     {
         return handle_result::<serde_json::Value>(Err(e));
     }
@@ -831,15 +845,17 @@ async fn roots_handler(
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
 ) -> HttpResponse {
-    handle_result(
-        get_roots(
+    relay(
+        roots_request(
             client.as_ref(),
             &base_url.0,
             &macaroon_hex.0,
             http_req.query_string(),
         )
+        .send()
         .await,
     )
+    .await
 }
 
 async fn asset_roots_handler(
@@ -850,19 +866,21 @@ async fn asset_roots_handler(
     path: web::Path<String>,
 ) -> HttpResponse {
     let asset_id = path.into_inner();
-    if let Err(e) = validate_hex_param(&asset_id) {
+    if let Err(e) = asset_id.parse::<AssetId>() {
         return handle_result::<serde_json::Value>(Err(e));
     }
-    handle_result(
-        get_asset_roots(
+    relay(
+        asset_roots_request(
             client.as_ref(),
             &base_url.0,
             &macaroon_hex.0,
             &asset_id,
             http_req.query_string(),
         )
+        .send()
         .await,
     )
+    .await
 }
 
 async fn stats_handler(
@@ -907,12 +925,49 @@ async fn event_stats_handler(
     )
 }
 
+#[derive(Debug, Deserialize)]
+pub struct UniverseSyncListQuery {
+    pub status: Option<UniverseSyncStatus>,
+    pub limit: Option<u32>,
+}
+
+fn sync_runner(
+    http: &HttpRequest,
+    client: &Client,
+    base_url: &BaseUrl,
+    macaroon_hex: &MacaroonHex,
+    database: SharedDatabase,
+) -> UniverseSyncRunner {
+    let max_attempts = http
+        .app_data::<web::Data<Config>>()
+        .map(|c| c.universe_sync_max_attempts)
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS);
+    UniverseSyncRunner::new(
+        client.clone(),
+        base_url.0.clone(),
+        macaroon_hex.0.clone(),
+        database,
+        max_attempts,
+    )
+}
+
+/// With a gateway database the sync is tracked per target and can be resumed
+/// after a failure; the sync id is returned in `X-Universe-Sync-Id`. A sync
+/// that stops partway answers 502 with its progress.
 async fn sync_handler(
+    http: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<SyncRequest>,
 ) -> HttpResponse {
+    if let Ok(database) = require_database(&http) {
+        let runner = sync_runner(&http, &client, &base_url, &macaroon_hex, database);
+        return match runner.begin(req.into_inner()).await {
+            Ok(sync) => sync_result_response(&sync),
+            Err(e) => handle_result::<Value>(Err(e)),
+        };
+    }
     handle_result(
         sync_universe(
             client.as_ref(),
@@ -949,6 +1004,117 @@ async fn get_sync_config_handler(
     handle_result(get_sync_config(client.as_ref(), &base_url.0, &macaroon_hex.0).await)
 }
 
+fn sync_result_response(sync: &crate::database::UniverseSync) -> HttpResponse {
+    if sync.status == UniverseSyncStatus::Completed {
+        HttpResponse::Ok()
+            .insert_header((SYNC_ID_HEADER, sync.id.as_str()))
+            .json(sync_response(sync))
+    } else {
+        HttpResponse::BadGateway()
+            .insert_header((SYNC_ID_HEADER, sync.id.as_str()))
+            .json(sync)
+    }
+}
+
+async fn list_syncs_handler(
+    http: HttpRequest,
+    query: web::Query<UniverseSyncListQuery>,
+) -> HttpResponse {
+    let result = async {
+        let database = require_database(&http)?;
+        database
+            .list_universe_syncs(query.status, query.limit)
+            .await
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn get_sync_handler(http: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&http)?;
+        let id = path.into_inner();
+        database
+            .get_universe_sync(&id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Universe sync {id} not found")))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Runs the targets a partial or failed sync has not synced yet.
+async fn resume_sync_handler(
+    http: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let database = match require_database(&http) {
+        Ok(database) => database,
+        Err(e) => return handle_result::<Value>(Err(e)),
+    };
+    let runner = sync_runner(&http, &client, &base_url, &macaroon_hex, database);
+    match runner.resume(&path.into_inner()).await {
+        Ok(sync) => sync_result_response(&sync),
+        Err(e) => handle_result::<Value>(Err(e)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IndexedLeavesQuery {
+    pub asset_id: Option<String>,
+    pub group_key: Option<String>,
+    /// Include each leaf's proof.
+    #[serde(default)]
+    pub proofs: bool,
+}
+
+/// Universe leaves copied into the index by an admin backfill.
+async fn indexed_leaves_handler(
+    http: HttpRequest,
+    query: web::Query<IndexedLeavesQuery>,
+) -> HttpResponse {
+    let result = async {
+        let query = query.into_inner();
+        if let Some(asset_id) = &query.asset_id {
+            validate_asset_id(asset_id)?;
+        }
+        if let Some(group_key) = &query.group_key {
+            validate_group_key(group_key)?;
+        }
+        let page = PageParams::from_query(http.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let database = require_database(&http)?;
+        let leaves = database
+            .query_universe_leaves(
+                query.asset_id.map(|id| id.to_ascii_lowercase()).as_deref(),
+                query
+                    .group_key
+                    .map(|key| key.to_ascii_lowercase())
+                    .as_deref(),
+                query.proofs,
+                Some(limit + 1),
+                Some(offset),
+            )
+            .await?;
+        Ok(ListEnvelope::from_offset_page(leaves, offset, limit).with_next_link(&http))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Tracked universe syncs and indexed leaves, mounted under `/v1/gateway`.
+pub fn configure_gateway(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/universe/syncs").route(web::get().to(list_syncs_handler)))
+        .service(web::resource("/universe/syncs/{id}").route(web::get().to(get_sync_handler)))
+        .service(
+            web::resource("/universe/syncs/{id}/resume").route(web::post().to(resume_sync_handler)),
+        )
+        .service(web::resource("/universe/leaves").route(web::get().to(indexed_leaves_handler)));
+}
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/universe/delete").route(web::delete().to(delete_handler)))
         .service(
@@ -0,0 +1,207 @@
+use super::handle_result;
+use crate::database::UsageRecord;
+use crate::error::AppError;
+use crate::usage::{self, SharedUsageMeter};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    /// First day, `YYYY-MM-DD`; defaults to the first of the current month.
+    pub from: Option<String>,
+    /// Last day, inclusive; defaults to today.
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub errors: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += record.requests;
+        self.errors += record.errors;
+        self.request_bytes += record.request_bytes;
+        self.response_bytes += record.response_bytes;
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyUsage {
+    pub key_id: String,
+    pub from: String,
+    pub to: String,
+    pub totals: UsageTotals,
+    pub days: Vec<UsageRecord>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyTotals {
+    pub key_id: String,
+    pub label: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub from: String,
+    pub to: String,
+    pub keys: Vec<KeyTotals>,
+}
+
+fn meter(req: &HttpRequest) -> Result<SharedUsageMeter, AppError> {
+    req.app_data::<web::Data<SharedUsageMeter>>()
+        .map(|m| m.get_ref().clone())
+        .ok_or_else(|| {
+            AppError::ServiceUnavailable(
+                "Usage accounting requires DATABASE_URL and USAGE_ACCOUNTING_ENABLED".to_string(),
+            )
+        })
+}
+
+fn parse_day(value: &str, name: &str) -> Result<NaiveDate, AppError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| AppError::InvalidInput(format!("{name} must be a date (YYYY-MM-DD)")))
+}
+
+fn date_range(query: &UsageQuery) -> Result<(String, String), AppError> {
+    let today = Utc::now().date_naive();
+    let from = match &query.from {
+        Some(from) => parse_day(from, "from")?,
+        None => today.with_day(1).unwrap_or(today),
+    };
+    let to = match &query.to {
+        Some(to) => parse_day(to, "to")?,
+        None => today,
+    };
+    if from > to {
+        return Err(AppError::InvalidInput(
+            "from must not be after to".to_string(),
+        ));
+    }
+    Ok((from.to_string(), to.to_string()))
+}
+
+/// The caller's own usage, per day.
+async fn own_usage(req: &HttpRequest, query: UsageQuery) -> Result<KeyUsage, AppError> {
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| {
+            AppError::InvalidInput(
+                "Usage is tracked per API key; send one as a bearer token".to_string(),
+            )
+        })?;
+    let (from, to) = date_range(&query)?;
+    let key_id = usage::key_id(token);
+    let days = meter(req)?.report(Some(&key_id), &from, &to).await?;
+    let mut totals = UsageTotals::default();
+    for day in &days {
+        totals.add(day);
+    }
+    Ok(KeyUsage {
+        key_id,
+        from,
+        to,
+        totals,
+        days,
+    })
+}
+
+/// Totals of every key over the range, for billing.
+async fn all_usage(req: &HttpRequest, query: UsageQuery) -> Result<UsageReport, AppError> {
+    let (from, to) = date_range(&query)?;
+    let records = meter(req)?.report(None, &from, &to).await?;
+    Ok(UsageReport {
+        from,
+        to,
+        keys: roll_up(records),
+    })
+}
+
+/// Sums daily records per key, keeping each key's latest label.
+fn roll_up(records: Vec<UsageRecord>) -> Vec<KeyTotals> {
+    let mut keys: BTreeMap<String, KeyTotals> = BTreeMap::new();
+    for record in records {
+        let entry = keys
+            .entry(record.key_id.clone())
+            .or_insert_with(|| KeyTotals {
+                key_id: record.key_id.clone(),
+                label: String::new(),
+                totals: UsageTotals::default(),
+            });
+        entry.totals.add(&record);
+        entry.label = record.label;
+    }
+    keys.into_values().collect()
+}
+
+async fn get_own_usage(req: HttpRequest, query: web::Query<UsageQuery>) -> HttpResponse {
+    handle_result(own_usage(&req, query.into_inner()).await)
+}
+
+async fn get_all_usage(req: HttpRequest, query: web::Query<UsageQuery>) -> HttpResponse {
+    handle_result(all_usage(&req, query.into_inner()).await)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/usage").route(web::get().to(get_own_usage)))
+        .service(web::resource("/admin/usage").route(web::get().to(get_all_usage)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(key_id: &str, day: &str, label: &str) -> UsageRecord {
+        UsageRecord {
+            key_id: key_id.to_string(),
+            day: day.to_string(),
+            label: label.to_string(),
+            requests: 2,
+            errors: 1,
+            request_bytes: 10,
+            response_bytes: 100,
+        }
+    }
+
+    #[test]
+    fn test_date_range_validation() {
+        let query = |from: Option<&str>, to: Option<&str>| UsageQuery {
+            from: from.map(str::to_string),
+            to: to.map(str::to_string),
+        };
+        assert_eq!(
+            date_range(&query(Some("2026-10-01"), Some("2026-10-31"))).unwrap(),
+            ("2026-10-01".to_string(), "2026-10-31".to_string())
+        );
+        assert!(date_range(&query(Some("2026-10-31"), Some("2026-10-01"))).is_err());
+        assert!(date_range(&query(Some("10/01/2026"), None)).is_err());
+        let (from, to) = date_range(&query(None, None)).unwrap();
+        assert!(from.ends_with("-01"));
+        assert!(from <= to);
+    }
+
+    #[test]
+    fn test_roll_up_sums_days_per_key() {
+        let keys = roll_up(vec![
+            record("a", "2026-10-01", "primary"),
+            record("b", "2026-10-01", "tenant:acme"),
+            record("a", "2026-10-02", "primary"),
+        ]);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].key_id, "a");
+        assert_eq!(keys[0].totals.requests, 4);
+        assert_eq!(keys[0].totals.response_bytes, 200);
+        assert_eq!(keys[1].label, "tenant:acme");
+    }
+}
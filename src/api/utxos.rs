@@ -0,0 +1,65 @@
+use super::{handle_result, validate_asset_id};
+use crate::config::Config;
+use crate::error::AppError;
+use crate::types::{BaseUrl, MacaroonHex};
+use crate::utxo_analytics::{self, FragmentationThresholds};
+use actix_web::{web, HttpRequest, HttpResponse};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::instrument;
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsQuery {
+    pub asset_id: Option<String>,
+    /// Outputs below this many units count as dust; defaults to
+    /// `UTXO_DUST_BELOW`.
+    pub dust_below: Option<u64>,
+}
+
+/// How the wallet's asset outputs are spread, per asset.
+#[instrument(skip(req, client, base_url, macaroon_hex))]
+async fn analytics(
+    req: HttpRequest,
+    client: web::Data<Client>,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    query: web::Query<AnalyticsQuery>,
+) -> HttpResponse {
+    let result = async {
+        let query = query.into_inner();
+        let asset_id = query
+            .asset_id
+            .map(|id| validate_asset_id(&id).map(|_| id.to_ascii_lowercase()))
+            .transpose()?;
+        let config = req.app_data::<web::Data<Config>>();
+        let dust_below = query
+            .dust_below
+            .or(config.map(|c| c.utxo_dust_below))
+            .unwrap_or(0);
+        let thresholds = config
+            .map(|c| FragmentationThresholds::from_config(c))
+            .unwrap_or_default();
+        let mut assets = utxo_analytics::fetch(
+            client.as_ref(),
+            &base_url.0,
+            &macaroon_hex.0,
+            dust_below,
+            thresholds,
+        )
+        .await?;
+        if let Some(asset_id) = &asset_id {
+            assets.retain(|a| &a.asset_id == asset_id);
+        }
+        Ok::<_, AppError>(json!({
+            "dust_below": dust_below,
+            "assets": assets,
+        }))
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/utxos/analytics").route(web::get().to(analytics)));
+}
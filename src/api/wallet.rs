@@ -1,7 +1,15 @@
-use super::{handle_result, parse_upstream, validate_hex_param};
+use super::addresses::{decode_address, DecodeAddrRequest};
+use super::assets::get_utxos;
+use super::{backend, handle_result, relay, signed_result, validate_hex_param};
+use crate::backend::BackendRequest;
+use crate::coin_selection::{self, Coin, CoinSelectionPolicy};
+use crate::config::Config;
+use crate::database::FlowStage;
 use crate::error::AppError;
-use crate::types::{BaseUrl, MacaroonHex};
-use actix_web::{web, HttpResponse};
+use crate::indexer::normalize_hex_id;
+use crate::psbt_flows::{self, PsbtFlowGuard, FLOW_ID_HEADER};
+use crate::types::{BaseUrl, MacaroonHex, Outpoint, ScriptKey};
+use actix_web::{web, HttpRequest, HttpResponse};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -110,15 +118,11 @@ pub async fn next_internal_key(
         "Fetching next internal key for family: {}",
         request.key_family
     );
-    let url = format!("{base_url}/v1/taproot-assets/wallet/internal-key/next");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/internal-key/next")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -129,14 +133,12 @@ pub async fn get_internal_key(
     internal_key: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching internal key: {}", internal_key);
-    let url = format!("{base_url}/v1/taproot-assets/wallet/internal-key/{internal_key}");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/wallet/internal-key/{internal_key}"
+        ))
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -147,15 +149,11 @@ pub async fn prove_ownership(
     request: OwnershipProveRequest,
 ) -> Result<Value, AppError> {
     info!("Proving ownership for asset ID: {}", request.asset_id);
-    let url = format!("{base_url}/v1/taproot-assets/wallet/ownership/prove");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/ownership/prove")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -166,15 +164,11 @@ pub async fn verify_ownership(
     request: OwnershipVerifyRequest,
 ) -> Result<Value, AppError> {
     info!("Verifying ownership");
-    let url = format!("{base_url}/v1/taproot-assets/wallet/ownership/verify");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/ownership/verify")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -185,15 +179,11 @@ pub async fn declare_script_key(
     request: ScriptKeyRequest,
 ) -> Result<Value, AppError> {
     info!("Declaring script key");
-    let url = format!("{base_url}/v1/taproot-assets/wallet/script-key/declare");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/script-key/declare")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -207,15 +197,11 @@ pub async fn next_script_key(
         "Fetching next script key for family: {}",
         request.key_family
     );
-    let url = format!("{base_url}/v1/taproot-assets/wallet/script-key/next");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/script-key/next")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex))]
@@ -226,14 +212,12 @@ pub async fn get_script_key(
     tweaked_script_key: &str,
 ) -> Result<Value, AppError> {
     info!("Fetching script key: {}", tweaked_script_key);
-    let url = format!("{base_url}/v1/taproot-assets/wallet/script-key/{tweaked_script_key}");
-    let response = client
-        .get(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
-        .send()
+    backend(client, base_url, macaroon_hex)
+        .get(&format!(
+            "/v1/taproot-assets/wallet/script-key/{tweaked_script_key}"
+        ))
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -244,15 +228,11 @@ pub async fn delete_utxo_lease(
     request: UtxoLeaseDeleteRequest,
 ) -> Result<Value, AppError> {
     info!("Deleting UTXO lease");
-    let url = format!("{base_url}/v1/taproot-assets/wallet/utxo-lease/delete");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/utxo-lease/delete")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -263,15 +243,11 @@ pub async fn anchor_virtual_psbt(
     request: VirtualPsbtAnchorRequest,
 ) -> Result<Value, AppError> {
     info!("Anchoring virtual PSBT");
-    let url = format!("{base_url}/v1/taproot-assets/wallet/virtual-psbt/anchor");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/virtual-psbt/anchor")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -282,15 +258,11 @@ pub async fn commit_virtual_psbt(
     request: VirtualPsbtCommitRequest,
 ) -> Result<Value, AppError> {
     info!("Committing virtual PSBT");
-    let url = format!("{base_url}/v1/taproot-assets/wallet/virtual-psbt/commit");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/virtual-psbt/commit")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -301,15 +273,11 @@ pub async fn fund_virtual_psbt(
     request: VirtualPsbtFundRequest,
 ) -> Result<Value, AppError> {
     info!("Funding virtual PSBT");
-    let url = format!("{base_url}/v1/taproot-assets/wallet/virtual-psbt/fund");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/virtual-psbt/fund")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -320,15 +288,11 @@ pub async fn log_virtual_psbt_transfer(
     request: VirtualPsbtLogTransferRequest,
 ) -> Result<Value, AppError> {
     info!("Logging virtual PSBT transfer");
-    let url = format!("{base_url}/v1/taproot-assets/wallet/virtual-psbt/log-transfer");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/virtual-psbt/log-transfer")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -339,34 +303,23 @@ pub async fn sign_virtual_psbt(
     request: VirtualPsbtSignRequest,
 ) -> Result<Value, AppError> {
     info!("Signing virtual PSBT");
-    let url = format!("{base_url}/v1/taproot-assets/wallet/virtual-psbt/sign");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/virtual-psbt/sign")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
-#[instrument(skip(client, macaroon_hex, request))]
-pub async fn export_wallet_backup(
+pub fn export_backup_request(
     client: &Client,
     base_url: &str,
     macaroon_hex: &str,
     request: ExportBackupRequest,
-) -> Result<Value, AppError> {
+) -> BackendRequest {
     info!("Exporting asset wallet backup");
-    let url = format!("{base_url}/v1/taproot-assets/wallet/backup/export");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/backup/export")
         .json(&request)
-        .send()
-        .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 #[instrument(skip(client, macaroon_hex, request))]
@@ -377,15 +330,11 @@ pub async fn import_wallet_backup(
     request: ImportBackupRequest,
 ) -> Result<Value, AppError> {
     info!("Importing assets from backup");
-    let url = format!("{base_url}/v1/taproot-assets/wallet/backup/import");
-    let response = client
-        .post(&url)
-        .header("Grpc-Metadata-macaroon", macaroon_hex)
+    backend(client, base_url, macaroon_hex)
+        .post("/v1/taproot-assets/wallet/backup/import")
         .json(&request)
-        .send()
+        .fetch::<Value>()
         .await
-        .map_err(AppError::RequestError)?;
-    parse_upstream::<Value>(response).await
 }
 
 async fn export_backup_handler(
@@ -394,15 +343,17 @@ async fn export_backup_handler(
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<ExportBackupRequest>,
 ) -> HttpResponse {
-    handle_result(
-        export_wallet_backup(
+    relay(
+        export_backup_request(
             client.as_ref(),
             &base_url.0,
             &macaroon_hex.0,
             req.into_inner(),
         )
+        .send()
         .await,
     )
+    .await
 }
 
 async fn import_backup_handler(
@@ -455,29 +406,32 @@ async fn get_internal_key_handler(
 }
 
 async fn prove_ownership_handler(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<OwnershipProveRequest>,
 ) -> HttpResponse {
-    handle_result(
-        prove_ownership(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
-        )
-        .await,
+    let mut request = req.into_inner();
+    request.outpoint = match Outpoint::normalize(&request.outpoint) {
+        Ok(outpoint) => outpoint,
+        Err(e) => return handle_result::<Value>(Err(e)),
+    };
+    signed_result(
+        &http_req,
+        prove_ownership(client.as_ref(), &base_url.0, &macaroon_hex.0, request).await,
     )
 }
 
 async fn verify_ownership_handler(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<OwnershipVerifyRequest>,
 ) -> HttpResponse {
-    handle_result(
+    signed_result(
+        &http_req,
         verify_ownership(
             client.as_ref(),
             &base_url.0,
@@ -529,7 +483,7 @@ async fn get_script_key_handler(
     path: web::Path<String>,
 ) -> HttpResponse {
     let tweaked_script_key = path.into_inner();
-    if let Err(e) = validate_hex_param(&tweaked_script_key) {
+    if let Err(e) = tweaked_script_key.parse::<ScriptKey>() {
         return handle_result::<serde_json::Value>(Err(e));
     }
     handle_result(
@@ -543,106 +497,299 @@ async fn get_script_key_handler(
     )
 }
 
+/// A released lease also frees the output from the PSBT flow holding it.
 async fn delete_utxo_lease_handler(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<UtxoLeaseDeleteRequest>,
 ) -> HttpResponse {
-    handle_result(
-        delete_utxo_lease(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
-        )
-        .await,
-    )
+    let result = async {
+        let mut request = req.into_inner();
+        request.outpoint = Outpoint::normalize(&request.outpoint)?;
+        let outpoint = psbt_flows::outpoint_key(&request.outpoint);
+        let response =
+            delete_utxo_lease(client.as_ref(), &base_url.0, &macaroon_hex.0, request).await?;
+        if let (Some(guard), Some(outpoint)) = (PsbtFlowGuard::from_request(&http_req), outpoint) {
+            guard.release_outpoint(&outpoint).await?;
+        }
+        Ok(response)
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Reserves the outputs `psbts` spend for the request's PSBT flow at
+/// `stage`; `None` when flows are not tracked.
+async fn claim_flow(
+    guard: Option<&PsbtFlowGuard>,
+    req: &HttpRequest,
+    psbts: &[String],
+    stage: FlowStage,
+) -> Result<Option<String>, AppError> {
+    let Some(guard) = guard else {
+        return Ok(None);
+    };
+    let outpoints = psbt_flows::psbt_outpoints(psbts)?;
+    let flow_id = PsbtFlowGuard::requested_flow(req);
+    Ok(Some(
+        guard.claim(flow_id.as_deref(), &outpoints, stage).await?,
+    ))
+}
+
+/// Like [`handle_result`], naming the PSBT flow of a successful call.
+fn flow_response(result: Result<(Value, Option<String>), AppError>) -> HttpResponse {
+    match result {
+        Ok((value, Some(flow_id))) => HttpResponse::Ok()
+            .insert_header((FLOW_ID_HEADER, flow_id))
+            .json(value),
+        Ok((value, None)) => handle_result(Ok(value)),
+        Err(e) => handle_result::<()>(Err(e)),
+    }
 }
 
 async fn anchor_virtual_psbt_handler(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<VirtualPsbtAnchorRequest>,
 ) -> HttpResponse {
-    handle_result(
-        anchor_virtual_psbt(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
+    let guard = PsbtFlowGuard::from_request(&http_req);
+    let result = async {
+        let request = req.into_inner();
+        let flow_id = claim_flow(
+            guard.as_ref(),
+            &http_req,
+            &request.virtual_psbts,
+            FlowStage::Committed,
         )
-        .await,
-    )
+        .await?;
+        let response =
+            anchor_virtual_psbt(client.as_ref(), &base_url.0, &macaroon_hex.0, request).await?;
+        if let (Some(guard), Some(flow_id)) = (&guard, &flow_id) {
+            guard.advance(flow_id, FlowStage::Anchored).await?;
+        }
+        Ok((response, flow_id))
+    }
+    .await;
+    flow_response(result)
 }
 
 async fn commit_virtual_psbt_handler(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<VirtualPsbtCommitRequest>,
 ) -> HttpResponse {
-    handle_result(
-        commit_virtual_psbt(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
+    let guard = PsbtFlowGuard::from_request(&http_req);
+    let result = async {
+        let request = req.into_inner();
+        let flow_id = claim_flow(
+            guard.as_ref(),
+            &http_req,
+            &request.virtual_psbts,
+            FlowStage::Committed,
         )
-        .await,
-    )
+        .await?;
+        let response =
+            commit_virtual_psbt(client.as_ref(), &base_url.0, &macaroon_hex.0, request).await?;
+        Ok((response, flow_id))
+    }
+    .await;
+    flow_response(result)
+}
+
+/// Anchor outpoints a fund request names as inputs, either in `raw.inputs`
+/// or in the PSBT to fund.
+fn requested_outpoints(request: &VirtualPsbtFundRequest) -> Result<Vec<String>, AppError> {
+    if !request.psbt.is_empty() {
+        return psbt_flows::psbt_outpoints(std::slice::from_ref(&request.psbt));
+    }
+    let mut outpoints: Vec<String> = request.raw["inputs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|input| psbt_flows::outpoint_key(&input["outpoint"]))
+        .collect();
+    outpoints.sort();
+    outpoints.dedup();
+    Ok(outpoints)
+}
+
+/// Picks the inputs of a template fund request under the configured coin
+/// selection policy. Requests with a PSBT, with inputs of their own or
+/// paying several assets are left to tapd.
+async fn apply_coin_selection(
+    policy: &CoinSelectionPolicy,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    request: &mut VirtualPsbtFundRequest,
+) -> Result<(), AppError> {
+    if policy.is_default() || !request.psbt.is_empty() {
+        return Ok(());
+    }
+    let has_inputs = request.raw["inputs"]
+        .as_array()
+        .is_some_and(|inputs| !inputs.is_empty());
+    let Some(recipients) = request.raw["recipients"].as_object() else {
+        return Ok(());
+    };
+    if has_inputs || recipients.is_empty() {
+        return Ok(());
+    }
+
+    let mut asset_id: Option<String> = None;
+    let mut target = 0u64;
+    for address in recipients.keys() {
+        let request = DecodeAddrRequest {
+            addr: address.clone(),
+        };
+        let decoded = decode_address(client, base_url, macaroon_hex, request).await?;
+        let Some(id) = decoded.asset_id.as_deref().map(normalize_hex_id) else {
+            return Ok(());
+        };
+        if asset_id.get_or_insert_with(|| id.clone()) != &id {
+            return Ok(());
+        }
+        target += decoded
+            .amount
+            .as_deref()
+            .and_then(|a| a.parse::<u64>().ok())
+            .unwrap_or(0);
+    }
+    let Some(asset_id) = asset_id else {
+        return Ok(());
+    };
+
+    let utxos = get_utxos(client, base_url, macaroon_hex, "").await?;
+    let now = chrono::Utc::now().timestamp();
+    let coins = coin_selection::coins_from_utxos(&utxos, &asset_id, now);
+    let selected = coin_selection::select(policy, &coins, target)?;
+    info!(
+        "Selected {} of {} outputs of {} for {} units",
+        selected.len(),
+        coins.len(),
+        asset_id,
+        target
+    );
+    let inputs = selected
+        .iter()
+        .map(Coin::prev_id)
+        .collect::<Result<Vec<_>, _>>()?;
+    request.raw["inputs"] = Value::Array(inputs);
+    Ok(())
 }
 
 async fn fund_virtual_psbt_handler(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<VirtualPsbtFundRequest>,
 ) -> HttpResponse {
-    handle_result(
-        fund_virtual_psbt(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
-        )
-        .await,
-    )
+    let guard = PsbtFlowGuard::from_request(&http_req);
+    let result = async {
+        let mut request = req.into_inner();
+        if let Some(config) = http_req.app_data::<web::Data<Config>>() {
+            let policy = CoinSelectionPolicy::from_config(config);
+            apply_coin_selection(
+                &policy,
+                client.as_ref(),
+                &base_url.0,
+                &macaroon_hex.0,
+                &mut request,
+            )
+            .await?;
+        }
+        let Some(guard) = &guard else {
+            let response =
+                fund_virtual_psbt(client.as_ref(), &base_url.0, &macaroon_hex.0, request).await?;
+            return Ok((response, None));
+        };
+
+        // Inputs the request names are held before tapd sees them, so two
+        // flows funding from the same outputs cannot both get through
+        let requested_flow = PsbtFlowGuard::requested_flow(&http_req);
+        let flow_id = guard
+            .claim(
+                requested_flow.as_deref(),
+                &requested_outpoints(&request)?,
+                FlowStage::Funded,
+            )
+            .await?;
+        let response =
+            match fund_virtual_psbt(client.as_ref(), &base_url.0, &macaroon_hex.0, request).await {
+                Ok(response) => response,
+                Err(e) => {
+                    if requested_flow.is_none() {
+                        guard.release(&flow_id).await?;
+                    }
+                    return Err(e);
+                }
+            };
+        if let Some(funded) = response["funded_psbt"].as_str() {
+            let outpoints = psbt_flows::psbt_outpoints(&[funded.to_string()])?;
+            guard
+                .claim(Some(&flow_id), &outpoints, FlowStage::Funded)
+                .await?;
+        }
+        Ok((response, Some(flow_id)))
+    }
+    .await;
+    flow_response(result)
 }
 
 async fn log_virtual_psbt_transfer_handler(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<VirtualPsbtLogTransferRequest>,
 ) -> HttpResponse {
-    handle_result(
-        log_virtual_psbt_transfer(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
+    let guard = PsbtFlowGuard::from_request(&http_req);
+    let result = async {
+        let request = req.into_inner();
+        let flow_id = claim_flow(
+            guard.as_ref(),
+            &http_req,
+            &request.virtual_psbts,
+            FlowStage::Committed,
         )
-        .await,
-    )
+        .await?;
+        let response =
+            log_virtual_psbt_transfer(client.as_ref(), &base_url.0, &macaroon_hex.0, request)
+                .await?;
+        if let (Some(guard), Some(flow_id)) = (&guard, &flow_id) {
+            guard.advance(flow_id, FlowStage::Anchored).await?;
+        }
+        Ok((response, flow_id))
+    }
+    .await;
+    flow_response(result)
 }
 
 async fn sign_virtual_psbt_handler(
+    http_req: HttpRequest,
     client: web::Data<Client>,
     base_url: web::Data<BaseUrl>,
     macaroon_hex: web::Data<MacaroonHex>,
     req: web::Json<VirtualPsbtSignRequest>,
 ) -> HttpResponse {
-    handle_result(
-        sign_virtual_psbt(
-            client.as_ref(),
-            &base_url.0,
-            &macaroon_hex.0,
-            req.into_inner(),
-        )
-        .await,
-    )
+    let guard = PsbtFlowGuard::from_request(&http_req);
+    let result = async {
+        let request = req.into_inner();
+        let psbts = [request.funded_psbt.clone()];
+        let flow_id = claim_flow(guard.as_ref(), &http_req, &psbts, FlowStage::Signed).await?;
+        let response =
+            sign_virtual_psbt(client.as_ref(), &base_url.0, &macaroon_hex.0, request).await?;
+        Ok((response, flow_id))
+    }
+    .await;
+    flow_response(result)
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
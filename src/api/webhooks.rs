@@ -0,0 +1,93 @@
+//! Dead-letter management for outbound webhooks. Deliveries that spend
+//! their attempt budget stay in the dead-letter queue with their attempt
+//! history until an operator retries or purges them.
+
+use super::{handle_result, require_database, ListEnvelope, PageParams};
+use crate::error::AppError;
+use crate::webhooks;
+use actix_web::{web, HttpRequest, HttpResponse};
+use tracing::info;
+
+async fn list_dead_letters(req: HttpRequest) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let page = PageParams::from_query(req.query_string())?;
+        let (offset, limit) = (page.offset()?, page.limit()?);
+        let dead_letters = database.dead_letter_webhooks(limit + 1, offset).await?;
+        Ok(ListEnvelope::from_offset_page(dead_letters, offset, limit).with_next_link(&req))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Puts the delivery back in the outbox, due now with a fresh attempt
+/// budget.
+async fn retry_dead_letter(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let id = path.into_inner();
+        if !database.retry_dead_letter(&id).await? {
+            return Err(AppError::NotFound(format!("Dead letter {id} not found")));
+        }
+        info!(
+            "Webhook delivery {} re-queued from the dead-letter queue",
+            id
+        );
+        Ok(serde_json::json!({ "id": id, "status": "pending" }))
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn purge_dead_letter(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let result = async {
+        let database = require_database(&req)?;
+        let id = path.into_inner();
+        if database.purge_dead_letters(Some(&id)).await? == 0 {
+            return Err(AppError::NotFound(format!("Dead letter {id} not found")));
+        }
+        Ok(serde_json::json!({ "purged": 1 }))
+    }
+    .await;
+    handle_result(result)
+}
+
+async fn purge_dead_letters(req: HttpRequest) -> HttpResponse {
+    let result = async {
+        let purged = require_database(&req)?.purge_dead_letters(None).await?;
+        info!("Purged {} webhook dead letters", purged);
+        Ok(serde_json::json!({ "purged": purged }))
+    }
+    .await;
+    handle_result(result)
+}
+
+/// Success rates from the persisted outbox, plus this process's counters.
+async fn stats(req: HttpRequest) -> HttpResponse {
+    let result = async {
+        let persisted = require_database(&req)?.webhook_delivery_stats().await?;
+        Ok(serde_json::json!({
+            "persisted": persisted,
+            "process": webhooks::stats().snapshot(),
+        }))
+    }
+    .await;
+    handle_result(result)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/admin/webhooks/dead-letters")
+            .route(web::get().to(list_dead_letters))
+            .route(web::delete().to(purge_dead_letters)),
+    )
+    .service(
+        web::resource("/admin/webhooks/dead-letters/{id}")
+            .route(web::delete().to(purge_dead_letter)),
+    )
+    .service(
+        web::resource("/admin/webhooks/dead-letters/{id}/retry")
+            .route(web::post().to(retry_dead_letter)),
+    )
+    .service(web::resource("/admin/webhooks/stats").route(web::get().to(stats)));
+}
@@ -0,0 +1,187 @@
+//! Per-request versioning of gateway response shapes. Callers pick a version
+//! with `Accept: application/vnd.tapgateway.v2+json`; handlers always build
+//! the v1 shape and [`translate`] rewrites it for later versions, so routes
+//! are never forked per version.
+
+use serde_json::{json, Map, Value};
+
+pub const MEDIA_TYPE_V1: &str = "application/vnd.tapgateway.v1+json";
+pub const MEDIA_TYPE_V2: &str = "application/vnd.tapgateway.v2+json";
+
+const VENDOR_PREFIX: &str = "application/vnd.tapgateway.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiVersion {
+    /// The shape every handler produces, and the default.
+    V1,
+    /// List paging moves under `page`; gateway errors nest under `error`.
+    V2,
+}
+
+impl ApiVersion {
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => MEDIA_TYPE_V1,
+            ApiVersion::V2 => MEDIA_TYPE_V2,
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|v| v.media_type().eq_ignore_ascii_case(media_type))
+    }
+}
+
+/// Picks the version for an `Accept` header. Returns `Ok(None)` when no
+/// gateway media type was asked for (plain JSON, v1), and `Err` with the
+/// requested types when only unsupported gateway versions are acceptable.
+pub fn negotiate(accept: Option<&str>) -> Result<Option<ApiVersion>, String> {
+    let Some(accept) = accept else {
+        return Ok(None);
+    };
+    let mut ranges: Vec<(&str, f32)> = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media_type = parts.next().filter(|m| !m.is_empty())?;
+            let quality = parts
+                .filter_map(|p| p.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((media_type, quality))
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect();
+    // Stable, so equally weighted ranges keep the caller's order
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut unsupported = Vec::new();
+    for (media_type, _) in ranges {
+        let lower = media_type.to_ascii_lowercase();
+        if lower.starts_with(VENDOR_PREFIX) {
+            match ApiVersion::from_media_type(&lower) {
+                Some(version) => return Ok(Some(version)),
+                None => unsupported.push(media_type),
+            }
+        } else if matches!(lower.as_str(), "application/json" | "application/*" | "*/*") {
+            return Ok(None);
+        }
+    }
+    if unsupported.is_empty() {
+        Ok(None)
+    } else {
+        Err(unsupported.join(", "))
+    }
+}
+
+/// Rewrites a v1 response body into `version`'s shape.
+pub fn translate(value: &mut Value, version: ApiVersion) {
+    if version < ApiVersion::V2 {
+        return;
+    }
+    let Value::Object(map) = value else {
+        return;
+    };
+    if is_list_envelope(map) {
+        let mut page = Map::new();
+        for field in ["next_cursor", "total_estimate", "next"] {
+            if let Some(v) = map.remove(field) {
+                page.insert(field.to_string(), v);
+            }
+        }
+        map.insert("page".to_string(), Value::Object(page));
+    } else if is_gateway_error(map) {
+        let message = map.remove("error").unwrap_or(Value::Null);
+        let error_type = map.remove("type").unwrap_or(Value::Null);
+        let mut error = json!({ "message": message, "type": error_type });
+        // Anything else the error carried (e.g. `maintenance`) moves with it
+        for (key, v) in std::mem::take(map) {
+            error[key] = v;
+        }
+        map.insert("error".to_string(), error);
+    }
+}
+
+fn is_list_envelope(map: &Map<String, Value>) -> bool {
+    map.get("items").is_some_and(Value::is_array) && map.contains_key("next_cursor")
+}
+
+/// `{"error": "...", "type": "..."}` as built by `AppError`. tapd's own error
+/// documents are relayed verbatim and never match.
+fn is_gateway_error(map: &Map<String, Value>) -> bool {
+    map.get("error").is_some_and(Value::is_string)
+        && (map.get("type").is_some_and(Value::is_string) || map.contains_key("maintenance"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_accept_header() {
+        assert_eq!(negotiate(None), Ok(None));
+        assert_eq!(negotiate(Some("application/json")), Ok(None));
+        assert_eq!(negotiate(Some(MEDIA_TYPE_V2)), Ok(Some(ApiVersion::V2)));
+        assert_eq!(
+            negotiate(Some(
+                "application/vnd.tapgateway.v1+json;q=0.5, application/vnd.tapgateway.v2+json"
+            )),
+            Ok(Some(ApiVersion::V2))
+        );
+        assert_eq!(
+            negotiate(Some("application/vnd.tapgateway.v9+json, */*;q=0.1")),
+            Ok(None)
+        );
+        assert_eq!(
+            negotiate(Some("application/vnd.tapgateway.v9+json")),
+            Err("application/vnd.tapgateway.v9+json".to_string())
+        );
+        assert_eq!(
+            negotiate(Some(
+                "application/vnd.tapgateway.v2+json;q=0, application/vnd.tapgateway.v1+json"
+            )),
+            Ok(Some(ApiVersion::V1))
+        );
+    }
+
+    #[test]
+    fn test_translate_v2_shapes() {
+        let mut list = json!({
+            "items": [{ "id": 1 }],
+            "next_cursor": "NTA",
+            "total_estimate": null,
+            "next": "/v1/gateway/transfers?cursor=NTA"
+        });
+        translate(&mut list, ApiVersion::V2);
+        assert_eq!(
+            list,
+            json!({
+                "items": [{ "id": 1 }],
+                "page": {
+                    "next_cursor": "NTA",
+                    "total_estimate": null,
+                    "next": "/v1/gateway/transfers?cursor=NTA"
+                }
+            })
+        );
+
+        let mut error = json!({ "error": "Asset not found", "type": "not_found" });
+        translate(&mut error, ApiVersion::V2);
+        assert_eq!(
+            error,
+            json!({ "error": { "message": "Asset not found", "type": "not_found" } })
+        );
+
+        // tapd errors and v1 requests are left alone
+        let tapd = json!({ "code": 5, "message": "not found", "details": [] });
+        let mut value = tapd.clone();
+        translate(&mut value, ApiVersion::V2);
+        assert_eq!(value, tapd);
+        let mut value = json!({ "error": "x", "type": "y" });
+        translate(&mut value, ApiVersion::V1);
+        assert_eq!(value, json!({ "error": "x", "type": "y" }));
+    }
+}
@@ -0,0 +1,323 @@
+//! Request builder for calls to tapd's REST API. Every api module goes
+//! through [`BackendClient`], so macaroon authentication, timeouts, retries,
+//! metrics and error mapping behave the same for every route. It is public
+//! so applications embedding the gateway can issue their own backend calls
+//! the same way.
+//!
+//! ```rust,ignore
+//! use taproot_assets_rest_gateway::backend::BackendClient;
+//!
+//! let backend = BackendClient::new(reqwest::Client::new(), "https://localhost:8089", macaroon_hex);
+//! let info: serde_json::Value = backend
+//!     .get("/v1/taproot-assets/getinfo")
+//!     .fetch()
+//!     .await?;
+//! ```
+
+use crate::api::parse_upstream;
+use crate::error::AppError;
+use crate::inflight;
+use crate::latency;
+use crate::timeouts;
+use reqwest::{Client, Method, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Header tapd reads the hex-encoded macaroon from.
+pub const MACAROON_HEADER: &str = "Grpc-Metadata-macaroon";
+
+/// Extra attempts for idempotent requests that could not reach tapd.
+const DEFAULT_RETRIES: u32 = 1;
+
+/// Delay before the first retry, doubled on each further attempt.
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+static STATS: BackendStats = BackendStats::new();
+
+/// Process-wide counters of backend calls, exported on `/metrics`.
+#[derive(Debug)]
+pub struct BackendStats {
+    requests: AtomicU64,
+    transport_errors: AtomicU64,
+    upstream_errors: AtomicU64,
+    retries: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackendStatsSnapshot {
+    pub requests: u64,
+    /// Requests that never got a response (connect errors, timeouts).
+    pub transport_errors: u64,
+    /// Responses with a non-2xx status.
+    pub upstream_errors: u64,
+    pub retries: u64,
+}
+
+impl BackendStats {
+    const fn new() -> Self {
+        Self {
+            requests: AtomicU64::new(0),
+            transport_errors: AtomicU64::new(0),
+            upstream_errors: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> BackendStatsSnapshot {
+        BackendStatsSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            transport_errors: self.transport_errors.load(Ordering::Relaxed),
+            upstream_errors: self.upstream_errors.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counters of every backend call made through [`BackendClient`].
+pub fn stats() -> &'static BackendStats {
+    &STATS
+}
+
+/// A tapd endpoint plus the credentials and policies applied to each call.
+/// Cheap to clone.
+#[derive(Clone)]
+pub struct BackendClient {
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+    timeout: Option<Duration>,
+    retries: u32,
+}
+
+impl BackendClient {
+    pub fn new(
+        client: Client,
+        base_url: impl Into<String>,
+        macaroon_hex: impl Into<String>,
+    ) -> Self {
+        Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            macaroon_hex: macaroon_hex.into(),
+            timeout: None,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    /// Overrides the route timeout for requests built from here.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Extra attempts for GET requests that fail before reaching tapd.
+    /// Other methods are never retried, since tapd may have acted on them.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Starts a request to `path`, which is relative to the base URL.
+    pub fn request(&self, method: Method, path: &str) -> BackendRequest {
+        BackendRequest {
+            backend: self.clone(),
+            method,
+            path: path.to_string(),
+            query: String::new(),
+            body: None,
+        }
+    }
+
+    pub fn get(&self, path: &str) -> BackendRequest {
+        self.request(Method::GET, path)
+    }
+
+    pub fn post(&self, path: &str) -> BackendRequest {
+        self.request(Method::POST, path)
+    }
+
+    pub fn delete(&self, path: &str) -> BackendRequest {
+        self.request(Method::DELETE, path)
+    }
+}
+
+pub struct BackendRequest {
+    backend: BackendClient,
+    method: Method,
+    path: String,
+    query: String,
+    body: Option<Result<Vec<u8>, String>>,
+}
+
+impl BackendRequest {
+    /// Appends a raw query string. tapd exposes filters, pagination and
+    /// required parameters this way, so callers pass the client's query
+    /// through.
+    pub fn query(mut self, query: &str) -> Self {
+        if !query.is_empty() {
+            if !self.query.is_empty() {
+                self.query.push('&');
+            }
+            self.query.push_str(query);
+        }
+        self
+    }
+
+    /// Sends `body` as JSON.
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        self.body = Some(serde_json::to_vec(body).map_err(|e| e.to_string()));
+        self
+    }
+
+    pub fn url(&self) -> String {
+        let url = format!("{}{}", self.backend.base_url, self.path);
+        if self.query.is_empty() {
+            url
+        } else if url.contains('?') {
+            format!("{url}&{}", self.query)
+        } else {
+            format!("{url}?{}", self.query)
+        }
+    }
+
+    /// Sends the request and returns tapd's response whatever its status.
+    /// The call is listed in [`inflight::registry`] until tapd answers, and
+    /// fails with 503 if an operator cancels it there.
+    pub async fn send(self) -> Result<Response, AppError> {
+        let (_guard, cancelled) = inflight::registry().register(self.method.as_str(), &self.path);
+        tokio::select! {
+            result = self.send_attempts() => result,
+            Ok(()) = cancelled => Err(AppError::ServiceUnavailable(
+                "Backend request was cancelled by an operator".to_string(),
+            )),
+        }
+    }
+
+    async fn send_attempts(self) -> Result<Response, AppError> {
+        let url = self.url();
+        let body = self
+            .body
+            .transpose()
+            .map_err(AppError::SerializationError)?;
+        let attempts = if self.method == Method::GET {
+            self.backend.retries + 1
+        } else {
+            1
+        };
+        let tuner = timeouts::current();
+        let timeout = self
+            .backend
+            .timeout
+            .or_else(|| tuner.as_ref()?.timeout_for(&self.path));
+
+        let mut attempt = 1;
+        loop {
+            let mut builder = self
+                .backend
+                .client
+                .request(self.method.clone(), &url)
+                .header(MACAROON_HEADER, &self.backend.macaroon_hex);
+            if let Some(timeout) = timeout {
+                builder = builder.timeout(timeout);
+            }
+            if let Some(body) = &body {
+                builder = builder
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body.clone());
+            }
+
+            STATS.requests.fetch_add(1, Ordering::Relaxed);
+            let started = Instant::now();
+            match builder.send().await {
+                Ok(response) => {
+                    if let Some(tuner) = &tuner {
+                        tuner.record(&self.path, started.elapsed());
+                    }
+                    latency::histograms().record(&self.path, started.elapsed());
+                    if !response.status().is_success() {
+                        STATS.upstream_errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                    debug!("{} {} -> {}", self.method, self.path, response.status());
+                    return Ok(response);
+                }
+                Err(e) if e.is_connect() && attempt < attempts => {
+                    STATS.retries.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        "{} {} failed to connect (attempt {}/{}), retrying",
+                        self.method, self.path, attempt, attempts
+                    );
+                    tokio::time::sleep(RETRY_BACKOFF * 2u32.pow(attempt - 1)).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    if e.is_timeout() {
+                        if let Some(tuner) = &tuner {
+                            tuner.record(&self.path, started.elapsed());
+                        }
+                        latency::histograms().record(&self.path, started.elapsed());
+                    }
+                    STATS.transport_errors.fetch_add(1, Ordering::Relaxed);
+                    return Err(AppError::RequestError(e));
+                }
+            }
+        }
+    }
+
+    /// Sends the request and deserializes a successful response. Non-2xx
+    /// responses become [`AppError::UpstreamError`] carrying tapd's body.
+    pub async fn fetch<T: DeserializeOwned>(self) -> Result<T, AppError> {
+        parse_upstream(self.send().await?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend() -> BackendClient {
+        BackendClient::new(Client::new(), "https://localhost:8089/", "ab")
+    }
+
+    #[test]
+    fn test_url_joins_path_and_query() {
+        let b = backend();
+        assert_eq!(
+            b.get("/v1/taproot-assets/assets").url(),
+            "https://localhost:8089/v1/taproot-assets/assets"
+        );
+        assert_eq!(
+            b.get("/v1/taproot-assets/assets")
+                .query("include_spent=true")
+                .query("")
+                .query("limit=5")
+                .url(),
+            "https://localhost:8089/v1/taproot-assets/assets?include_spent=true&limit=5"
+        );
+        assert_eq!(
+            b.post("/v1/taproot-assets/events/asset-mint?method=POST")
+                .query("short_response=true")
+                .url(),
+            "https://localhost:8089/v1/taproot-assets/events/asset-mint?method=POST&short_response=true"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_failures_are_retried_for_get_only() {
+        // Nothing listens on port 9 (discard) in the test environment
+        let backend = BackendClient::new(Client::new(), "http://127.0.0.1:9", "ab");
+        let before = stats().snapshot();
+        assert!(backend.get("/x").send().await.is_err());
+        assert!(backend.post("/x").json(&()).send().await.is_err());
+        let after = stats().snapshot();
+        assert!(after.retries > before.retries);
+        assert!(after.transport_errors >= before.transport_errors + 2);
+    }
+}
@@ -0,0 +1,288 @@
+//! Spot checks of the in-memory response caches. A sampled share of cache
+//! hits (`CACHE_AUDIT_SAMPLE_PERCENT`) is also fetched fresh from tapd in
+//! the background, after the cached answer went out, and any difference is
+//! logged and counted. Frequent divergence means a cache TTL is longer than
+//! the data stays stable. Each gateway keeps its own audit, with its own
+//! sample rate and counts.
+
+use crate::error::AppError;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Checks running at once; further sampled hits are skipped meanwhile so a
+/// burst of hits never turns into a burst of backend calls.
+const MAX_IN_FLIGHT: usize = 4;
+
+/// Divergences kept for `/v1/gateway/monitor/cache-audit`.
+const MAX_RECENT: usize = 50;
+
+/// Differing fields listed per divergence.
+const MAX_DIFF_PATHS: usize = 10;
+
+/// Outcome counts of one cache's checks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AuditCounts {
+    pub matched: u64,
+    pub diverged: u64,
+    /// The fresh fetch failed, so nothing was compared.
+    pub failed: u64,
+}
+
+/// A cached answer that no longer matched tapd.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Divergence {
+    pub cache: String,
+    pub key: String,
+    /// How long the entry had been cached.
+    pub age_secs: u64,
+    /// JSON pointers of the fields that differ.
+    pub paths: Vec<String>,
+    pub detected_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditReport {
+    pub sample_percent: f64,
+    pub caches: BTreeMap<String, AuditCounts>,
+    pub recent: Vec<Divergence>,
+}
+
+#[derive(Debug, Default)]
+struct Results {
+    caches: BTreeMap<&'static str, AuditCounts>,
+    recent: VecDeque<Divergence>,
+}
+
+pub type SharedCacheAudit = Arc<CacheAudit>;
+
+#[derive(Debug)]
+pub struct CacheAudit {
+    /// Share of hits checked, in millionths.
+    sample_ppm: AtomicU32,
+    in_flight: AtomicUsize,
+    results: Mutex<Results>,
+}
+
+impl CacheAudit {
+    pub const fn new() -> Self {
+        Self {
+            sample_ppm: AtomicU32::new(0),
+            in_flight: AtomicUsize::new(0),
+            results: Mutex::new(Results {
+                caches: BTreeMap::new(),
+                recent: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn set_sample_percent(&self, percent: f64) {
+        let ppm = (percent.clamp(0.0, 100.0) * 10_000.0).round() as u32;
+        self.sample_ppm.store(ppm, Ordering::Relaxed);
+    }
+
+    pub fn sample_percent(&self) -> f64 {
+        f64::from(self.sample_ppm.load(Ordering::Relaxed)) / 10_000.0
+    }
+
+    /// Whether this cache hit should be checked.
+    pub fn should_sample(&self) -> bool {
+        let ppm = self.sample_ppm.load(Ordering::Relaxed);
+        ppm > 0
+            && self.in_flight.load(Ordering::Relaxed) < MAX_IN_FLIGHT
+            && bitcoin::secp256k1::rand::random::<u32>() % 1_000_000 < ppm
+    }
+
+    /// Compares `cached` with what `fresh` fetches, on a background task.
+    /// Call from an actix worker, after deciding with [`Self::should_sample`].
+    pub fn check<F>(
+        self: &Arc<Self>,
+        cache: &'static str,
+        key: String,
+        cached: Value,
+        age: Duration,
+        fresh: F,
+    ) where
+        F: Future<Output = Result<Value, AppError>> + 'static,
+    {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let audit = Arc::clone(self);
+        actix_web::rt::spawn(async move {
+            let fresh = fresh.await;
+            audit.in_flight.fetch_sub(1, Ordering::Relaxed);
+            audit.record(cache, &key, &cached, fresh, age);
+        });
+    }
+
+    fn record(
+        &self,
+        cache: &'static str,
+        key: &str,
+        cached: &Value,
+        fresh: Result<Value, AppError>,
+        age: Duration,
+    ) {
+        let mut results = self.results.lock().unwrap_or_else(|e| e.into_inner());
+        let counts = results.caches.entry(cache).or_default();
+        let fresh = match fresh {
+            Ok(fresh) => fresh,
+            Err(e) => {
+                counts.failed += 1;
+                debug!("Cache audit of {cache} {key} could not fetch: {e}");
+                return;
+            }
+        };
+        let mut paths = Vec::new();
+        diff_paths(cached, &fresh, String::new(), &mut paths);
+        if paths.is_empty() {
+            counts.matched += 1;
+            return;
+        }
+        counts.diverged += 1;
+        warn!(
+            "Cached {cache} response {key} diverged from tapd after {}s: {}",
+            age.as_secs(),
+            paths.join(", ")
+        );
+        if results.recent.len() == MAX_RECENT {
+            results.recent.pop_front();
+        }
+        results.recent.push_back(Divergence {
+            cache: cache.to_string(),
+            key: key.to_string(),
+            age_secs: age.as_secs(),
+            paths,
+            detected_at: chrono::Utc::now().timestamp(),
+        });
+    }
+
+    /// Counts per cache, and the latest divergences newest first.
+    pub fn report(&self) -> AuditReport {
+        let results = self.results.lock().unwrap_or_else(|e| e.into_inner());
+        AuditReport {
+            sample_percent: self.sample_percent(),
+            caches: results
+                .caches
+                .iter()
+                .map(|(cache, counts)| (cache.to_string(), counts.clone()))
+                .collect(),
+            recent: results.recent.iter().rev().cloned().collect(),
+        }
+    }
+}
+
+impl Default for CacheAudit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Appends the JSON pointers at which `a` and `b` differ to `paths`, up to
+/// [`MAX_DIFF_PATHS`].
+fn diff_paths(a: &Value, b: &Value, path: String, paths: &mut Vec<String>) {
+    if paths.len() >= MAX_DIFF_PATHS || a == b {
+        return;
+    }
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let keys: std::collections::BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                let child = format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"));
+                diff_paths(
+                    a.get(key).unwrap_or(&Value::Null),
+                    b.get(key).unwrap_or(&Value::Null),
+                    child,
+                    paths,
+                );
+            }
+        }
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            for (index, (a, b)) in a.iter().zip(b).enumerate() {
+                diff_paths(a, b, format!("{path}/{index}"), paths);
+            }
+        }
+        _ => paths.push(if path.is_empty() {
+            "/".to_string()
+        } else {
+            path
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_divergent_fields_are_reported() {
+        let audit = CacheAudit::new();
+        audit.set_sample_percent(2.5);
+        assert_eq!(audit.sample_percent(), 2.5);
+
+        let cached = json!({ "supply": 10, "meta": { "name": "a" }, "ids": [1, 2] });
+        audit.record(
+            "public",
+            "meta:x",
+            &cached,
+            Ok(cached.clone()),
+            Duration::ZERO,
+        );
+        let fresh = json!({ "supply": 12, "meta": { "name": "a", "new": 1 }, "ids": [1, 3] });
+        audit.record(
+            "public",
+            "meta:x",
+            &cached,
+            Ok(fresh),
+            Duration::from_secs(90),
+        );
+        audit.record(
+            "supply",
+            "y",
+            &cached,
+            Err(AppError::ServiceUnavailable("down".to_string())),
+            Duration::ZERO,
+        );
+
+        let report = audit.report();
+        assert_eq!(
+            report.caches["public"],
+            AuditCounts {
+                matched: 1,
+                diverged: 1,
+                failed: 0
+            }
+        );
+        assert_eq!(report.caches["supply"].failed, 1);
+        assert_eq!(report.recent.len(), 1);
+        assert_eq!(report.recent[0].age_secs, 90);
+        assert_eq!(report.recent[0].paths, ["/ids/1", "/meta/new", "/supply"]);
+    }
+
+    #[test]
+    fn test_sampling_is_off_by_default() {
+        let audit = CacheAudit::new();
+        assert!(!audit.should_sample());
+        audit.set_sample_percent(100.0);
+        assert!(audit.should_sample());
+    }
+
+    #[test]
+    fn test_audits_do_not_share_state() {
+        let first = CacheAudit::new();
+        let second = CacheAudit::new();
+        first.set_sample_percent(100.0);
+        second.set_sample_percent(1.0);
+        first.record("public", "k", &Value::Null, Ok(Value::Null), Duration::ZERO);
+
+        assert_eq!(first.report().sample_percent, 100.0);
+        assert_eq!(second.report().sample_percent, 1.0);
+        assert_eq!(first.report().caches["public"].matched, 1);
+        assert!(second.report().caches.is_empty());
+    }
+}
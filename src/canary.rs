@@ -0,0 +1,108 @@
+//! Canary routing: requests carrying `X-Canary: true`, or authenticated with
+//! one of the configured canary API keys, are served by an alternate tapd so
+//! new releases can be tried with real traffic from selected clients.
+//!
+//! Handlers resolve the backend from `web::Data<BaseUrl>` and
+//! `web::Data<MacaroonHex>`; the router overrides both for canary requests,
+//! so no handler needs to know about it.
+
+use crate::types::{BaseUrl, MacaroonHex};
+use actix_web::dev::{Extensions, ServiceRequest};
+use actix_web::http::header::HeaderMap;
+use actix_web::{web, HttpMessage};
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::Arc;
+use tracing::info;
+
+pub const CANARY_HEADER: &str = "X-Canary";
+
+/// Request extension marking a request routed to the canary.
+pub struct CanaryRequest;
+
+pub struct CanaryRouter {
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    api_keys: HashSet<String>,
+}
+
+pub type SharedCanaryRouter = Arc<CanaryRouter>;
+
+impl CanaryRouter {
+    pub fn new(base_url: String, macaroon_hex: String, api_keys: HashSet<String>) -> Self {
+        Self {
+            base_url: web::Data::new(BaseUrl(base_url)),
+            macaroon_hex: web::Data::new(MacaroonHex(macaroon_hex)),
+            api_keys,
+        }
+    }
+
+    /// A request goes to the canary when it opts in with `X-Canary: true` or
+    /// its bearer token is a canary key.
+    pub fn is_canary(&self, headers: &HeaderMap) -> bool {
+        let opted_in = headers
+            .get(CANARY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim().eq_ignore_ascii_case("true"));
+        opted_in
+            || headers
+                .get("Authorization")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .is_some_and(|token| self.api_keys.contains(token))
+    }
+
+    /// Points the request's backend data at the canary. App data containers
+    /// added later take precedence over the application's own.
+    pub fn route(&self, req: &mut ServiceRequest) {
+        let mut data = Extensions::new();
+        data.insert(self.base_url.clone());
+        data.insert(self.macaroon_hex.clone());
+        req.add_data_container(Rc::new(data));
+        req.extensions_mut().insert(CanaryRequest);
+    }
+
+    pub fn log_startup(&self) {
+        info!(
+            "Canary backend {} serves X-Canary requests and {} API key(s)",
+            self.base_url.0,
+            self.api_keys.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn router() -> CanaryRouter {
+        CanaryRouter::new(
+            "https://canary:8289".to_string(),
+            "ab".to_string(),
+            HashSet::from(["canary-token".to_string()]),
+        )
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+        map
+    }
+
+    #[test]
+    fn test_canary_selected_by_header_or_key() {
+        let router = router();
+        assert!(router.is_canary(&headers(&[("x-canary", "true")])));
+        assert!(router.is_canary(&headers(&[("x-canary", "TRUE")])));
+        assert!(router.is_canary(&headers(&[("authorization", "Bearer canary-token")])));
+        assert!(!router.is_canary(&headers(&[("x-canary", "false")])));
+        assert!(!router.is_canary(&headers(&[("authorization", "Bearer other")])));
+        assert!(!router.is_canary(&HeaderMap::new()));
+    }
+}
@@ -0,0 +1,81 @@
+//! Chain view used to track anchor transaction confirmations. Backed by the
+//! lnd node tapd runs against, since it already watches every anchor output
+//! tapd creates or imports.
+
+use crate::api::parse_upstream;
+use crate::error::AppError;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::instrument;
+
+/// Where a transaction currently sits according to the chain backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxConfirmation {
+    pub confirmations: u32,
+    /// Empty while the transaction is unconfirmed.
+    pub block_hash: Option<String>,
+    pub block_height: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LndTransactions {
+    #[serde(default)]
+    transactions: Vec<LndTransaction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LndTransaction {
+    tx_hash: String,
+    #[serde(default)]
+    num_confirmations: i64,
+    #[serde(default)]
+    block_hash: String,
+    #[serde(default)]
+    block_height: i64,
+}
+
+pub struct LndChainSource {
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+}
+
+impl LndChainSource {
+    pub fn new(client: Client, base_url: String, macaroon_hex: String) -> Self {
+        Self {
+            client,
+            base_url,
+            macaroon_hex,
+        }
+    }
+
+    /// Confirmation state of every wallet transaction, keyed by txid. A
+    /// transaction missing from the map has been dropped or replaced.
+    #[instrument(skip(self))]
+    pub async fn wallet_transactions(&self) -> Result<HashMap<String, TxConfirmation>, AppError> {
+        let response = self
+            .client
+            .get(format!("{}/v1/transactions", self.base_url))
+            .header("Grpc-Metadata-macaroon", &self.macaroon_hex)
+            .send()
+            .await
+            .map_err(AppError::RequestError)?;
+        let txs: LndTransactions = parse_upstream(response).await?;
+        Ok(txs
+            .transactions
+            .into_iter()
+            .map(|tx| {
+                let confirmed = tx.num_confirmations > 0 && !tx.block_hash.is_empty();
+                (
+                    tx.tx_hash.to_ascii_lowercase(),
+                    TxConfirmation {
+                        confirmations: tx.num_confirmations.max(0) as u32,
+                        block_hash: confirmed.then(|| tx.block_hash.to_ascii_lowercase()),
+                        block_height: confirmed.then_some(tx.block_height as u32),
+                    },
+                )
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,425 @@
+//! Aggregates events that concern taproot asset channels into `channel.*`
+//! topics on the event bus.
+//!
+//! tapd only streams RFQ notifications (quotes and the HTLCs accepted
+//! against them); channel lifecycle and HTLC forwarding events live in lnd.
+//! When `LND_URL` is set the aggregator also follows lnd's channel and HTLC
+//! event streams and keeps only the events of channels that carry assets.
+//! Every source publishes onto the same bus, so subscribers see one stream
+//! ordered by the bus sequence number.
+
+use crate::api::parse_upstream;
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::indexer::{normalize_txid, unwrap_stream_frame};
+use crate::websocket::connection_manager::WebSocketConnectionManager;
+use futures_util::{SinkExt, StreamExt};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+
+/// Prefix shared by every topic this module publishes.
+pub const CHANNEL_TOPIC_PREFIX: &str = "channel.";
+
+/// Initial delay before resubscribing after a stream drops (in seconds)
+const INITIAL_RESUBSCRIBE_DELAY_SECS: u64 = 1;
+
+/// Maximum resubscribe delay (in seconds) - caps exponential backoff
+const MAX_RESUBSCRIBE_DELAY_SECS: u64 = 60;
+
+/// lnd's commitment type for channels with an asset overlay.
+const TAPROOT_OVERLAY_COMMITMENT: &str = "SIMPLE_TAPROOT_OVERLAY";
+
+/// The backend streams the aggregator follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelSource {
+    /// tapd RFQ notifications
+    Rfq,
+    /// lnd SubscribeChannelEvents
+    LndChannels,
+    /// lnd SubscribeHtlcEvents
+    LndHtlcs,
+}
+
+impl ChannelSource {
+    fn endpoint(&self) -> &'static str {
+        match self {
+            ChannelSource::Rfq => "/v1/taproot-assets/rfq/ntfs?method=POST",
+            ChannelSource::LndChannels => "/v1/channels/subscribe?method=GET",
+            ChannelSource::LndHtlcs => "/v2/router/htlcevents?method=GET",
+        }
+    }
+
+    /// The subscription request sent as the first frame. lnd's GET streams
+    /// take none.
+    fn request_body(&self) -> Option<Value> {
+        match self {
+            ChannelSource::Rfq => Some(json!({})),
+            ChannelSource::LndChannels | ChannelSource::LndHtlcs => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ChannelSource::Rfq => "tapd",
+            ChannelSource::LndChannels | ChannelSource::LndHtlcs => "lnd",
+        }
+    }
+}
+
+/// Channel points and short channel IDs of the node's asset channels, used
+/// to filter lnd events down to the ones that matter here.
+#[derive(Debug, Default)]
+pub struct AssetChannels {
+    points: HashSet<String>,
+    chan_ids: HashSet<String>,
+}
+
+impl AssetChannels {
+    fn insert(&mut self, channel_point: Option<String>, chan_id: Option<String>) {
+        self.points.extend(channel_point);
+        self.chan_ids.extend(chan_id);
+    }
+
+    fn remove(&mut self, channel_point: &str) {
+        self.points.remove(channel_point);
+    }
+
+    fn contains_point(&self, channel_point: Option<&str>) -> bool {
+        channel_point.is_some_and(|p| self.points.contains(p))
+    }
+
+    fn contains_chan_id(&self, chan_id: Option<&str>) -> bool {
+        chan_id.is_some_and(|id| id != "0" && self.chan_ids.contains(id))
+    }
+}
+
+/// lnd connection details for the optional lnd streams.
+pub struct LndSource {
+    pub client: Client,
+    pub base_url: String,
+    pub macaroon_hex: String,
+    pub connection_manager: Arc<WebSocketConnectionManager>,
+}
+
+pub struct ChannelEventAggregator {
+    tapd: Arc<WebSocketConnectionManager>,
+    lnd: Option<LndSource>,
+    events: SharedEventBus,
+    channels: RwLock<AssetChannels>,
+}
+
+impl ChannelEventAggregator {
+    pub fn new(
+        tapd: Arc<WebSocketConnectionManager>,
+        lnd: Option<LndSource>,
+        events: SharedEventBus,
+    ) -> Self {
+        Self {
+            tapd,
+            lnd,
+            events,
+            channels: RwLock::new(AssetChannels::default()),
+        }
+    }
+
+    /// Starts one subscription task per available source.
+    pub fn start(self: Arc<Self>) -> Vec<tokio::task::JoinHandle<()>> {
+        let mut sources = vec![ChannelSource::Rfq];
+        if self.lnd.is_some() {
+            sources.extend([ChannelSource::LndChannels, ChannelSource::LndHtlcs]);
+        }
+        sources
+            .into_iter()
+            .map(|source| {
+                let aggregator = self.clone();
+                tokio::spawn(async move { aggregator.run_subscription(source).await })
+            })
+            .collect()
+    }
+
+    /// Follows one stream forever, resubscribing with backoff whenever the
+    /// backend connection drops.
+    async fn run_subscription(&self, source: ChannelSource) {
+        let mut delay = Duration::from_secs(INITIAL_RESUBSCRIBE_DELAY_SECS);
+        loop {
+            match self.consume(source).await {
+                Ok(published) => {
+                    info!("{:?} stream ended after {} events", source, published);
+                    if published > 0 {
+                        delay = Duration::from_secs(INITIAL_RESUBSCRIBE_DELAY_SECS);
+                    }
+                }
+                Err(e) => warn!("{:?} stream failed: {}", source, e),
+            }
+            tokio::time::sleep(delay).await;
+            delay = std::cmp::min(delay * 2, Duration::from_secs(MAX_RESUBSCRIBE_DELAY_SECS));
+        }
+    }
+
+    async fn consume(&self, source: ChannelSource) -> Result<usize, AppError> {
+        let manager = match (source, &self.lnd) {
+            (ChannelSource::Rfq, _) => &self.tapd,
+            (_, Some(lnd)) => {
+                // Events for channels opened while we were disconnected would
+                // otherwise be dropped as unknown
+                if source == ChannelSource::LndChannels {
+                    self.refresh_channels(lnd).await?;
+                }
+                &lnd.connection_manager
+            }
+            (_, None) => return Ok(0),
+        };
+        let (conn_id, mut sink, mut frames) = manager.connect_to_backend(source.endpoint()).await?;
+
+        let result = async {
+            if let Some(body) = source.request_body() {
+                sink.send(Message::Text(body.to_string().into()))
+                    .await
+                    .map_err(|e| AppError::WebSocketError(format!("Failed to subscribe: {e}")))?;
+            }
+
+            let mut published = 0;
+            while let Some(frame) = frames.next().await {
+                let frame =
+                    frame.map_err(|e| AppError::WebSocketError(format!("Stream error: {e}")))?;
+                let text = match frame {
+                    Message::Text(text) => text.to_string(),
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                manager.update_activity(conn_id).await;
+
+                let event = unwrap_stream_frame(&text)?;
+                if let Some((topic, data)) = self.classify(source, event) {
+                    debug!("Publishing {} from {:?}", topic, source);
+                    self.events.publish(&topic, data);
+                    published += 1;
+                }
+            }
+            Ok(published)
+        }
+        .await;
+
+        manager.remove_connection(conn_id).await;
+        result
+    }
+
+    /// Seeds the asset channel set from lnd's channel listing.
+    async fn refresh_channels(&self, lnd: &LndSource) -> Result<(), AppError> {
+        let response = lnd
+            .client
+            .get(format!("{}/v1/channels", lnd.base_url))
+            .header("Grpc-Metadata-macaroon", &lnd.macaroon_hex)
+            .send()
+            .await
+            .map_err(AppError::RequestError)?;
+        let listing: Value = parse_upstream(response).await?;
+        let mut channels = self.channels.write().unwrap_or_else(|e| e.into_inner());
+        for channel in listing
+            .get("channels")
+            .and_then(|c| c.as_array())
+            .into_iter()
+            .flatten()
+            .filter(|c| is_asset_channel(c))
+        {
+            channels.insert(
+                str_field(channel, "channel_point"),
+                str_field(channel, "chan_id"),
+            );
+        }
+        Ok(())
+    }
+
+    /// Maps a backend event to a topic and payload, or `None` when it does
+    /// not concern an asset channel.
+    fn classify(&self, source: ChannelSource, event: Value) -> Option<(String, Value)> {
+        let (kind, channel_point, chan_id) = match source {
+            ChannelSource::Rfq => ("rfq", None, None),
+            ChannelSource::LndChannels => self.classify_channel_event(&event)?,
+            ChannelSource::LndHtlcs => {
+                let channels = self.channels.read().unwrap_or_else(|e| e.into_inner());
+                let incoming = str_field(&event, "incoming_channel_id");
+                let outgoing = str_field(&event, "outgoing_channel_id");
+                if !channels.contains_chan_id(incoming.as_deref())
+                    && !channels.contains_chan_id(outgoing.as_deref())
+                {
+                    return None;
+                }
+                ("htlc", None, None)
+            }
+        };
+        Some((
+            format!("{CHANNEL_TOPIC_PREFIX}{kind}"),
+            json!({
+                "source": source.name(),
+                "channel_point": channel_point,
+                "chan_id": chan_id,
+                "event": event,
+            }),
+        ))
+    }
+
+    fn classify_channel_event(
+        &self,
+        event: &Value,
+    ) -> Option<(&'static str, Option<String>, Option<String>)> {
+        let mut channels = self.channels.write().unwrap_or_else(|e| e.into_inner());
+        match event.get("type").and_then(|t| t.as_str())? {
+            "OPEN_CHANNEL" => {
+                let channel = event.get("open_channel")?;
+                if !is_asset_channel(channel) {
+                    return None;
+                }
+                let point = str_field(channel, "channel_point");
+                let chan_id = str_field(channel, "chan_id");
+                channels.insert(point.clone(), chan_id.clone());
+                Some(("opened", point, chan_id))
+            }
+            "CLOSED_CHANNEL" => {
+                let summary = event.get("closed_channel")?;
+                let point = str_field(summary, "channel_point");
+                if !is_asset_channel(summary) && !channels.contains_point(point.as_deref()) {
+                    return None;
+                }
+                Some(("closed", point, str_field(summary, "chan_id")))
+            }
+            kind @ ("ACTIVE_CHANNEL" | "INACTIVE_CHANNEL" | "FULLY_RESOLVED_CHANNEL") => {
+                let field = match kind {
+                    "ACTIVE_CHANNEL" => "active_channel",
+                    "INACTIVE_CHANNEL" => "inactive_channel",
+                    _ => "fully_resolved_channel",
+                };
+                let point = event.get(field).and_then(channel_point_string)?;
+                if !channels.contains_point(Some(&point)) {
+                    return None;
+                }
+                let topic = match kind {
+                    "ACTIVE_CHANNEL" => "active",
+                    "INACTIVE_CHANNEL" => "inactive",
+                    _ => {
+                        channels.remove(&point);
+                        "resolved"
+                    }
+                };
+                Some((topic, Some(point), None))
+            }
+            // Pending opens do not say yet whether the channel carries assets
+            _ => None,
+        }
+    }
+}
+
+/// Asset channels use the taproot overlay commitment and carry the asset
+/// state in `custom_channel_data`.
+fn is_asset_channel(channel: &Value) -> bool {
+    channel.get("commitment_type").and_then(|t| t.as_str()) == Some(TAPROOT_OVERLAY_COMMITMENT)
+        || str_field(channel, "custom_channel_data").is_some()
+}
+
+/// Renders an lnd `ChannelPoint` message as `txid:index`.
+fn channel_point_string(point: &Value) -> Option<String> {
+    let txid = str_field(point, "funding_txid_str")
+        .or_else(|| str_field(point, "funding_txid_bytes").map(|b| normalize_txid(&b)))?;
+    let index = point
+        .get("output_index")
+        .and_then(|i| i.as_u64())
+        .unwrap_or(0);
+    Some(format!("{txid}:{index}"))
+}
+
+fn str_field(value: &Value, field: &str) -> Option<String> {
+    value
+        .get(field)
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_bus::EventBus;
+    use crate::types::{BaseUrl, MacaroonHex};
+
+    const POINT: &str = "abababababababababababababababababababababababababababababababab:1";
+
+    fn aggregator() -> ChannelEventAggregator {
+        let manager = Arc::new(WebSocketConnectionManager::new(
+            BaseUrl("https://localhost:8089".to_string()),
+            MacaroonHex("00".to_string()),
+            false,
+        ));
+        ChannelEventAggregator::new(manager, None, Arc::new(EventBus::default()))
+    }
+
+    #[test]
+    fn test_only_asset_channels_are_followed() {
+        let aggregator = aggregator();
+        let plain = json!({
+            "type": "OPEN_CHANNEL",
+            "open_channel": { "channel_point": "cd:0", "chan_id": "7", "commitment_type": "ANCHORS" }
+        });
+        assert!(aggregator
+            .classify(ChannelSource::LndChannels, plain)
+            .is_none());
+
+        let asset = json!({
+            "type": "OPEN_CHANNEL",
+            "open_channel": {
+                "channel_point": POINT,
+                "chan_id": "42",
+                "commitment_type": "SIMPLE_TAPROOT_OVERLAY"
+            }
+        });
+        let (topic, data) = aggregator
+            .classify(ChannelSource::LndChannels, asset)
+            .unwrap();
+        assert_eq!(topic, "channel.opened");
+        assert_eq!(data["channel_point"], POINT);
+
+        // HTLCs are kept only when they touch a known asset channel
+        let htlc = json!({ "incoming_channel_id": "42", "outgoing_channel_id": "0" });
+        assert!(aggregator.classify(ChannelSource::LndHtlcs, htlc).is_some());
+        let other = json!({ "incoming_channel_id": "7", "outgoing_channel_id": "0" });
+        assert!(aggregator
+            .classify(ChannelSource::LndHtlcs, other)
+            .is_none());
+    }
+
+    #[test]
+    fn test_channel_point_messages_match_known_channels() {
+        let aggregator = aggregator();
+        aggregator
+            .channels
+            .write()
+            .unwrap()
+            .insert(Some(POINT.to_string()), None);
+
+        // funding_txid_bytes is the txid in internal byte order
+        let active = json!({
+            "type": "ACTIVE_CHANNEL",
+            "active_channel": { "funding_txid_bytes": "q6urq6urq6urq6urq6urq6urq6urq6urq6urq6urq6s=", "output_index": 1 }
+        });
+        let (topic, _) = aggregator
+            .classify(ChannelSource::LndChannels, active)
+            .unwrap();
+        assert_eq!(topic, "channel.active");
+
+        let resolved = json!({
+            "type": "FULLY_RESOLVED_CHANNEL",
+            "fully_resolved_channel": { "funding_txid_str": POINT.split(':').next().unwrap(), "output_index": 1 }
+        });
+        assert!(aggregator
+            .classify(ChannelSource::LndChannels, resolved.clone())
+            .is_some());
+        // Resolved channels are forgotten
+        assert!(aggregator
+            .classify(ChannelSource::LndChannels, resolved)
+            .is_none());
+    }
+}
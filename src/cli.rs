@@ -0,0 +1,413 @@
+//! Command line of the gateway binary. `serve`, the default, runs the
+//! gateway; the other subcommands load the same `.env` configuration, do one
+//! administrative task against tapd, the database or a webhook receiver,
+//! and exit.
+
+use crate::api::info::get_info;
+use crate::api::proofs::{verify_proof, VerifyProofRequest};
+use crate::config::Config;
+use crate::crypto::ResponseSigner;
+use crate::database::{self, WebhookDelivery};
+use crate::gateway::backend_client;
+use crate::loadgen::{self, LoadPlan};
+use crate::outbound_proxy::OutboundProxy;
+use crate::webhooks;
+use base64::Engine;
+use clap::{Parser, Subcommand};
+use reqwest::Client;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Event name of `webhook test` deliveries.
+const EVENT_TEST: &str = "gateway.test";
+
+#[derive(Debug, Parser)]
+#[command(version, about = "REST gateway for Taproot Assets")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the gateway (the default)
+    Serve,
+    /// Validate the configuration and check that tapd, lnd and the database
+    /// are reachable
+    Check,
+    /// Manage signing keys
+    #[command(subcommand)]
+    Keys(KeysCommand),
+    /// Manage API keys
+    #[command(subcommand)]
+    Apikey(ApikeyCommand),
+    /// Work with webhook receivers
+    #[command(subcommand)]
+    Webhook(WebhookCommand),
+    /// Work with proof files
+    #[command(subcommand)]
+    Proof(ProofCommand),
+    /// Generate load against a running gateway and report throughput and
+    /// latency
+    Load {
+        /// Gateway base URL, including any BASE_PATH
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        url: String,
+        /// Route to request; repeat to mix routes. Defaults to the hot read
+        /// routes
+        #[arg(long = "route")]
+        routes: Vec<String>,
+        /// Hold connections to this WebSocket proxy route instead of sending
+        /// HTTP requests
+        #[arg(long)]
+        websocket: Option<String>,
+        /// Concurrent workers (or WebSocket connections)
+        #[arg(long, default_value_t = 16)]
+        concurrency: usize,
+        /// How long to run, in seconds
+        #[arg(long, default_value_t = 10)]
+        duration: u64,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeysCommand {
+    /// Generate a RESPONSE_SIGNING_KEY and print its public key
+    Generate,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ApikeyCommand {
+    /// Generate a random API key
+    Create {
+        /// Print it as a read-only ROLE_API_KEYS entry for this role instead
+        /// of as API_KEY
+        #[arg(long)]
+        role: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WebhookCommand {
+    /// Send a signed `gateway.test` delivery to a callback URL
+    Test {
+        url: String,
+        /// Signs the delivery like address and payment request callbacks
+        #[arg(long)]
+        secret: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProofCommand {
+    /// Have tapd verify a proof file, as written by `tapcli proofs export`
+    /// (raw or hex)
+    Verify {
+        file: PathBuf,
+        /// Genesis point (`txid:vout`) the proof must start from
+        #[arg(long, default_value = "")]
+        genesis_point: String,
+    },
+}
+
+/// Runs every subcommand other than `serve`.
+pub async fn run(command: Command) -> io::Result<()> {
+    match command {
+        Command::Serve => unreachable!("serve is run by main"),
+        Command::Check => check().await,
+        Command::Keys(KeysCommand::Generate) => generate_signing_key(),
+        Command::Apikey(ApikeyCommand::Create { role }) => create_api_key(role.as_deref()),
+        Command::Webhook(WebhookCommand::Test { url, secret }) => test_webhook(url, secret).await,
+        Command::Proof(ProofCommand::Verify {
+            file,
+            genesis_point,
+        }) => verify_proof_file(&file, genesis_point).await,
+        Command::Load {
+            url,
+            routes,
+            websocket,
+            concurrency,
+            duration,
+            json,
+        } => {
+            let routes = match routes.is_empty() {
+                true => loadgen::DEFAULT_ROUTES.map(str::to_string).to_vec(),
+                false => routes,
+            };
+            let plan = LoadPlan {
+                base_url: url.trim_end_matches('/').to_string(),
+                routes,
+                concurrency,
+                duration: std::time::Duration::from_secs(duration),
+                // The key the gateway itself is configured with
+                api_key: std::env::var("API_KEY").ok(),
+            };
+            load(plan, websocket, json).await
+        }
+    }
+}
+
+async fn load(plan: LoadPlan, websocket: Option<String>, json: bool) -> io::Result<()> {
+    let target = match &websocket {
+        Some(route) => format!("WebSocket {route}"),
+        None => plan.routes.join(", "),
+    };
+    if !json {
+        println!(
+            "Loading {} with {} workers for {}s: {target}",
+            plan.base_url,
+            plan.concurrency,
+            plan.duration.as_secs()
+        );
+    }
+    let report = match &websocket {
+        Some(route) => loadgen::run_websocket(&plan, route).await,
+        None => loadgen::run_http(&plan).await,
+    }
+    .map_err(io::Error::other)?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).map_err(io::Error::other)?
+        );
+    } else {
+        report.print();
+    }
+    Ok(())
+}
+
+fn load_config() -> io::Result<Config> {
+    Config::load().map_err(|e| io::Error::other(e.to_string()))
+}
+
+fn client_for(config: &Config) -> io::Result<Client> {
+    let proxy = config
+        .backend_proxy_url
+        .as_deref()
+        .map(OutboundProxy::parse)
+        .transpose()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    backend_client(config, proxy.as_ref())
+}
+
+async fn check_tapd(client: &Client, label: &str, host: &str, macaroon_path: &str) -> bool {
+    let result = match fs::read(macaroon_path) {
+        Ok(macaroon) => get_info(client, &format!("https://{host}"), &hex::encode(macaroon))
+            .await
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(format!("cannot read {macaroon_path}: {e}")),
+    };
+    match result {
+        Ok(info) => {
+            let field = |name: &str| info.get(name).and_then(|v| v.as_str()).unwrap_or("?");
+            println!(
+                "✅ tapd ({label}) at {host}: version {}, network {}",
+                field("version"),
+                field("network")
+            );
+            true
+        }
+        Err(e) => {
+            println!("❌ tapd ({label}) at {host}: {e}");
+            false
+        }
+    }
+}
+
+async fn check() -> io::Result<()> {
+    let config = load_config()?;
+    println!("✅ Configuration is valid");
+    let client = client_for(&config)?;
+    let mut failed = 0;
+
+    if !check_tapd(
+        &client,
+        "primary",
+        &config.taproot_assets_host,
+        &config.macaroon_path,
+    )
+    .await
+    {
+        failed += 1;
+    }
+    for tenant in &config.tenants {
+        if !check_tapd(&client, &tenant.name, &tenant.host, &tenant.macaroon_path).await {
+            failed += 1;
+        }
+    }
+
+    if let Some(lnd_url) = &config.lnd_url {
+        let lnd_url = lnd_url.trim_end_matches('/');
+        let result = match fs::read(&config.lnd_macaroon_path) {
+            Ok(macaroon) => client
+                .get(format!("{lnd_url}/v1/getinfo"))
+                .header("Grpc-Metadata-macaroon", hex::encode(macaroon))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(format!("cannot read {}: {e}", config.lnd_macaroon_path)),
+        };
+        match result {
+            Ok(_) => println!("✅ lnd at {lnd_url}"),
+            Err(e) => {
+                println!("❌ lnd at {lnd_url}: {e}");
+                failed += 1;
+            }
+        }
+    }
+
+    if let Some(url) = &config.database_url {
+        match database::init_database(Some(url), config.redis_url.as_deref()).await {
+            Ok(_) => println!("✅ Database at {url}"),
+            Err(e) => {
+                println!("❌ Database at {url}: {e}");
+                failed += 1;
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        n => Err(io::Error::other(format!("{n} check(s) failed"))),
+    }
+}
+
+/// 32 random bytes, hex-encoded.
+fn random_hex() -> String {
+    let key = secp256k1::SecretKey::new(&mut secp256k1::rand::thread_rng());
+    hex::encode(key.secret_bytes())
+}
+
+fn generate_signing_key() -> io::Result<()> {
+    let secret = random_hex();
+    let signer = ResponseSigner::from_hex(&secret).map_err(|e| io::Error::other(e.to_string()))?;
+    println!("RESPONSE_SIGNING_KEY={secret}");
+    println!(
+        "# Public key for verifying X-Signature: {}",
+        signer.public_key_hex()
+    );
+    println!(
+        "# To rotate, prepend to RESPONSE_SIGNING_KEYS: {}:{secret}",
+        signer.kid()
+    );
+    Ok(())
+}
+
+fn create_api_key(role: Option<&str>) -> io::Result<()> {
+    let key = random_hex();
+    match role {
+        Some(role) if role.is_empty() || role.contains([':', ',']) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "role must be non-empty and contain no ':' or ','",
+        )),
+        Some(role) => {
+            println!("# Append to ROLE_API_KEYS (comma-separated):");
+            println!("{role}:{key}");
+            Ok(())
+        }
+        None => {
+            println!("API_KEY={key}");
+            Ok(())
+        }
+    }
+}
+
+async fn test_webhook(url: String, secret: Option<String>) -> io::Result<()> {
+    crate::api::validate_callback_url(&url).map_err(|e| io::Error::other(e.to_string()))?;
+    let id = format!("{EVENT_TEST}:{}", uuid::Uuid::new_v4());
+    let delivery = WebhookDelivery::new(
+        id.clone(),
+        EVENT_TEST,
+        url.clone(),
+        secret,
+        serde_json::json!({
+            "event": EVENT_TEST,
+            "timestamp": chrono::Utc::now().timestamp(),
+        }),
+    );
+    let client = Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .map_err(io::Error::other)?;
+    // Signed like the dispatcher signs when the gateway config loads
+    let signer = load_config()
+        .ok()
+        .and_then(|config| config.response_signer().ok().flatten());
+    match webhooks::deliver(&client, &delivery, signer.as_ref()).await {
+        Ok(()) => {
+            println!("✅ {url} accepted delivery {id}");
+            Ok(())
+        }
+        Err(e) => Err(io::Error::other(format!("{url}: {e}"))),
+    }
+}
+
+/// Proof files are binary; `tapcli` can also write them hex-encoded.
+fn proof_file_bytes(contents: Vec<u8>) -> Vec<u8> {
+    std::str::from_utf8(&contents)
+        .ok()
+        .map(str::trim)
+        .filter(|text| !text.is_empty() && text.chars().all(|c| c.is_ascii_hexdigit()))
+        .and_then(|text| hex::decode(text).ok())
+        .unwrap_or(contents)
+}
+
+async fn verify_proof_file(file: &PathBuf, genesis_point: String) -> io::Result<()> {
+    let config = load_config()?;
+    let client = client_for(&config)?;
+    let macaroon_hex = hex::encode(fs::read(&config.macaroon_path)?);
+    let proof = proof_file_bytes(fs::read(file)?);
+    let request = VerifyProofRequest {
+        raw_proof_file: base64::engine::general_purpose::STANDARD.encode(proof),
+        genesis_point,
+    };
+    let base_url = format!("https://{}", config.taproot_assets_host);
+    let result = verify_proof(&client, &base_url, &macaroon_hex, request)
+        .await
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&result).map_err(io::Error::other)?
+    );
+    if result.get("valid").and_then(|v| v.as_bool()) == Some(true) {
+        println!("✅ {} is valid", file.display());
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} is not valid", file.display())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_subcommands_parse() {
+        Cli::command().debug_assert();
+        assert!(Cli::parse_from(["gateway"]).command.is_none());
+        assert!(matches!(
+            Cli::parse_from(["gateway", "apikey", "create", "--role", "auditor"]).command,
+            Some(Command::Apikey(ApikeyCommand::Create { role: Some(r) })) if r == "auditor"
+        ));
+        assert!(matches!(
+            Cli::parse_from(["gateway", "proof", "verify", "a.proof"]).command,
+            Some(Command::Proof(ProofCommand::Verify { genesis_point, .. })) if genesis_point.is_empty()
+        ));
+        assert!(Cli::try_parse_from(["gateway", "keys"]).is_err());
+    }
+
+    #[test]
+    fn test_proof_file_accepts_raw_and_hex() {
+        assert_eq!(proof_file_bytes(b"0102ff\n".to_vec()), vec![1, 2, 255]);
+        assert_eq!(
+            proof_file_bytes(vec![0x54, 0x41, 0x50, 0x00]),
+            vec![0x54, 0x41, 0x50, 0x00]
+        );
+    }
+}
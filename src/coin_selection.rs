@@ -0,0 +1,308 @@
+//! Gateway-side coin selection for funding virtual PSBTs. tapd picks
+//! inputs largest-first and leaves whatever change falls out, which over
+//! time splinters the wallet into many small outputs. When a policy is
+//! configured, the gateway picks the inputs of template (`raw`) fund
+//! requests that name none, and hands tapd the explicit list.
+
+use crate::api::assets::Asset;
+use crate::config::Config;
+use crate::error::AppError;
+use crate::indexer::normalize_hex_id;
+use crate::types::Outpoint;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoinSelectionPolicy {
+    /// Also spend the asset's outputs holding less than this many units,
+    /// folding them into the change; 0 disables consolidation.
+    pub consolidate_below: u64,
+    /// Add inputs rather than create change smaller than this; 0 allows any
+    /// change.
+    pub min_change: u64,
+    /// Spend the longest-confirmed outputs first instead of the largest.
+    pub prefer_oldest: bool,
+    /// Most inputs consolidation and change top-ups grow a selection to.
+    pub max_inputs: usize,
+}
+
+impl CoinSelectionPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            consolidate_below: config.coin_selection_consolidate_below,
+            min_change: config.coin_selection_min_change,
+            prefer_oldest: config.coin_selection_prefer_oldest,
+            max_inputs: config.coin_selection_max_inputs,
+        }
+    }
+
+    /// Whether tapd's own selection should be left alone.
+    pub fn is_default(&self) -> bool {
+        self.consolidate_below == 0 && self.min_change == 0 && !self.prefer_oldest
+    }
+}
+
+/// A spendable asset output of the wallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coin {
+    /// `txid:vout` of the anchor output.
+    pub outpoint: String,
+    /// Hex asset id.
+    pub asset_id: String,
+    /// Hex script key.
+    pub script_key: String,
+    pub amount: u64,
+    /// Height the anchor confirmed at; 0 while unconfirmed.
+    pub block_height: u32,
+}
+
+impl Coin {
+    /// The coin as a tapd `PrevId` for `raw.inputs`.
+    pub fn prev_id(&self) -> Result<Value, AppError> {
+        let outpoint = self
+            .outpoint
+            .parse::<Outpoint>()
+            .map_err(|_| AppError::SerializationError(format!("Bad outpoint {}", self.outpoint)))?;
+        Ok(json!({
+            "outpoint": outpoint.to_tapd(),
+            "id": BASE64.encode(hex::decode(&self.asset_id)?),
+            "script_key": BASE64.encode(hex::decode(&self.script_key)?),
+        }))
+    }
+}
+
+/// An unspent, unburned asset output in a `GET /assets/utxos` response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletOutput {
+    pub coin: Coin,
+    /// Leased to a transfer in progress.
+    pub leased: bool,
+    /// Locked to a script path, which tapd cannot sign for on its own.
+    pub script_path: bool,
+}
+
+impl WalletOutput {
+    /// Whether tapd can spend the output right now.
+    pub fn spendable(&self) -> bool {
+        !self.leased && !self.script_path
+    }
+}
+
+/// Every unspent, unburned asset output in a `GET /assets/utxos` response.
+pub fn wallet_outputs(utxos: &Value, now: i64) -> Vec<WalletOutput> {
+    let Some(managed) = utxos["managed_utxos"].as_object() else {
+        return Vec::new();
+    };
+    let mut outputs = Vec::new();
+    for (outpoint, utxo) in managed {
+        let assets = utxo["assets"].as_array().cloned().unwrap_or_default();
+        for asset in assets {
+            let Ok(asset) = serde_json::from_value::<Asset>(asset) else {
+                continue;
+            };
+            if asset.is_spent.unwrap_or(false) || asset.is_burn.unwrap_or(false) {
+                continue;
+            }
+            let id = asset
+                .asset_genesis
+                .as_ref()
+                .and_then(|g| g.asset_id.as_deref())
+                .map(normalize_hex_id);
+            let amount = asset.amount.as_deref().and_then(|a| a.parse().ok());
+            let (Some(id), Some(script_key), Some(amount)) =
+                (id, asset.script_key.as_deref(), amount)
+            else {
+                continue;
+            };
+            let leased = asset.lease_owner.as_deref().is_some_and(|o| !o.is_empty())
+                && asset
+                    .lease_expiry
+                    .as_deref()
+                    .and_then(|e| e.parse::<i64>().ok())
+                    .is_none_or(|expiry| expiry > now);
+            outputs.push(WalletOutput {
+                coin: Coin {
+                    outpoint: outpoint.clone(),
+                    asset_id: id,
+                    script_key: normalize_hex_id(script_key),
+                    amount,
+                    block_height: asset
+                        .chain_anchor
+                        .as_ref()
+                        .and_then(|a| a.block_height)
+                        .unwrap_or(0),
+                },
+                leased,
+                script_path: asset.script_key_has_script_path.unwrap_or(false),
+            });
+        }
+    }
+    outputs
+}
+
+/// The wallet's outputs of `asset_id` that tapd can spend right now:
+/// unspent, not leased to another transfer, not burned and with a key-path
+/// script key tapd can sign for.
+pub fn coins_from_utxos(utxos: &Value, asset_id: &str, now: i64) -> Vec<Coin> {
+    wallet_outputs(utxos, now)
+        .into_iter()
+        .filter(|output| output.coin.asset_id == asset_id && output.spendable())
+        .map(|output| output.coin)
+        .collect()
+}
+
+/// Picks the coins that pay `target` under `policy`: enough to cover it in
+/// the policy's order, then more if the change would be dust, then small
+/// coins to consolidate.
+pub fn select(
+    policy: &CoinSelectionPolicy,
+    coins: &[Coin],
+    target: u64,
+) -> Result<Vec<Coin>, AppError> {
+    let mut candidates = coins.to_vec();
+    if policy.prefer_oldest {
+        // Unconfirmed coins have no age yet, so they go last
+        candidates.sort_by_key(|c| {
+            (
+                c.block_height == 0,
+                c.block_height,
+                std::cmp::Reverse(c.amount),
+            )
+        });
+    } else {
+        candidates.sort_by_key(|c| std::cmp::Reverse(c.amount));
+    }
+
+    let mut selected = Vec::new();
+    let mut total = 0u64;
+    while total < target {
+        if candidates.is_empty() {
+            let available: u64 = coins.iter().map(|c| c.amount).sum();
+            return Err(AppError::ValidationError(format!(
+                "Insufficient balance: {target} units needed, {available} spendable"
+            )));
+        }
+        let coin = candidates.remove(0);
+        total += coin.amount;
+        selected.push(coin);
+    }
+
+    // The smallest remaining coin that lifts the change out of dust
+    let change = total - target;
+    if change > 0 && change < policy.min_change && selected.len() < policy.max_inputs {
+        candidates.sort_by_key(|c| c.amount);
+        let needed = policy.min_change - change;
+        if let Some(index) = candidates.iter().position(|c| c.amount >= needed) {
+            selected.push(candidates.remove(index));
+        }
+    }
+
+    if policy.consolidate_below > 0 {
+        candidates.sort_by_key(|c| c.amount);
+        for coin in candidates {
+            if selected.len() >= policy.max_inputs || coin.amount >= policy.consolidate_below {
+                break;
+            }
+            selected.push(coin);
+        }
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coin(n: u8, amount: u64, block_height: u32) -> Coin {
+        Coin {
+            outpoint: format!("{}:{n}", "ab".repeat(32)),
+            asset_id: "aa".repeat(32),
+            script_key: format!("02{}", "cc".repeat(32)),
+            amount,
+            block_height,
+        }
+    }
+
+    fn policy(consolidate_below: u64, min_change: u64, prefer_oldest: bool) -> CoinSelectionPolicy {
+        CoinSelectionPolicy {
+            consolidate_below,
+            min_change,
+            prefer_oldest,
+            max_inputs: 4,
+        }
+    }
+
+    fn amounts(coins: &[Coin]) -> Vec<u64> {
+        coins.iter().map(|c| c.amount).collect()
+    }
+
+    #[test]
+    fn test_select_order() {
+        let coins = [coin(0, 50, 300), coin(1, 500, 200), coin(2, 80, 100)];
+        let largest = select(&policy(0, 0, false), &coins, 100).unwrap();
+        assert_eq!(amounts(&largest), [500]);
+        let oldest = select(&policy(0, 0, true), &coins, 100).unwrap();
+        assert_eq!(amounts(&oldest), [80, 500]);
+        assert!(select(&policy(0, 0, false), &coins, 1000).is_err());
+    }
+
+    #[test]
+    fn test_select_avoids_dust_change_and_consolidates() {
+        let coins = [
+            coin(0, 105, 1),
+            coin(1, 3, 1),
+            coin(2, 40, 1),
+            coin(3, 2, 1),
+        ];
+        // 5 units of change are dust; the 40 unit coin lifts it to 45
+        let topped = select(&policy(0, 20, false), &coins, 100).unwrap();
+        assert_eq!(amounts(&topped), [105, 40]);
+
+        let consolidated = select(&policy(10, 0, false), &coins, 100).unwrap();
+        assert_eq!(amounts(&consolidated), [105, 2, 3]);
+    }
+
+    #[test]
+    fn test_coins_from_utxos_skips_unspendable() {
+        let asset = |id: &str, amount: &str, extra: Value| {
+            let mut asset = json!({
+                "asset_genesis": { "asset_id": id },
+                "amount": amount,
+                "script_key": format!("02{}", "cc".repeat(32)),
+                "chain_anchor": { "block_height": 120 },
+            });
+            asset
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().cloned().unwrap());
+            asset
+        };
+        let wanted = "aa".repeat(32);
+        let utxos = json!({
+            "managed_utxos": {
+                "ab:0": { "assets": [asset(&wanted, "10", json!({}))] },
+                "ab:1": { "assets": [asset(&"bb".repeat(32), "10", json!({}))] },
+                "ab:2": { "assets": [asset(&wanted, "10", json!({ "lease_owner": "b3duZXI=", "lease_expiry": "2000" }))] },
+                "ab:3": { "assets": [asset(&wanted, "10", json!({ "lease_owner": "b3duZXI=", "lease_expiry": "500" }))] },
+                "ab:4": { "assets": [asset(&wanted, "10", json!({ "script_key_has_script_path": true }))] },
+            }
+        });
+        let coins = coins_from_utxos(&utxos, &wanted, 1000);
+        let outpoints: Vec<&str> = coins.iter().map(|c| c.outpoint.as_str()).collect();
+        assert_eq!(outpoints, ["ab:0", "ab:3"]);
+        assert_eq!(coins[0].block_height, 120);
+    }
+
+    #[test]
+    fn test_prev_id_encodes_txid_in_internal_order() {
+        let mut c = coin(3, 10, 1);
+        c.outpoint = format!("{}01:3", "00".repeat(31));
+        let prev_id = c.prev_id().unwrap();
+        let txid = BASE64
+            .decode(prev_id["outpoint"]["txid"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(txid[0], 1);
+        assert_eq!(prev_id["outpoint"]["output_index"], 3);
+    }
+}
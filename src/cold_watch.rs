@@ -0,0 +1,139 @@
+//! The `cold_watch` profile: a read replica of a node, proxying a watch-only
+//! or public tapd. Every request that could change tapd or gateway state is
+//! refused by [`crate::middleware::ColdWatchGuard`] for the life of the
+//! process; unlike maintenance mode it cannot be lifted at runtime. Reads,
+//! event streams and the POST routes listed in [`VERIFY_PATHS`], which only
+//! decode or verify what the caller sends, keep working. WebSocket upgrades
+//! to the streams in [`WRITE_STREAM_PATHS`] are refused although they are
+//! GETs.
+
+use crate::error::AppError;
+use crate::websocket::topics::Topic;
+use actix_web::http::Method;
+use serde::{Deserialize, Serialize};
+
+/// POST routes that read or verify without changing any state.
+pub const VERIFY_PATHS: &[&str] = &[
+    "/v1/taproot-assets/addrs/decode",
+    "/v1/taproot-assets/proofs/decode",
+    "/v1/taproot-assets/proofs/unpack-file",
+    "/v1/taproot-assets/proofs/verify",
+    "/v1/taproot-assets/universe/multiverse",
+    "/v1/taproot-assets/wallet/ownership/verify",
+    "/v1/taproot-assets/events/asset-mint",
+    "/v1/taproot-assets/events/asset-receive",
+    "/v1/taproot-assets/events/asset-send",
+    "/v1/gateway/addrs/decode",
+    "/v1/gateway/transfers/query",
+    "/v1/gateway/simulate",
+    "/graphql",
+];
+
+/// WebSocket routes that send payments or change state, directly or as a
+/// `subscribe` topic.
+pub const WRITE_STREAM_PATHS: &[&str] = &[
+    "/v1/taproot-assets/channels/send-payment",
+    "/v1/taproot-assets/mailbox/receive",
+];
+
+/// The generic subscribe route, whose stream is picked by `topic`.
+const SUBSCRIBE_PATH: &str = "/v1/taproot-assets/subscribe";
+
+/// Whether an upgrade to `path` with `query` opens a write stream.
+fn is_write_stream(path: &str, query: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    if path == SUBSCRIBE_PATH {
+        return url::form_urlencoded::parse(query.as_bytes())
+            .filter(|(key, _)| key == "topic")
+            .filter_map(|(_, value)| Topic::parse(&value))
+            .any(|topic| WRITE_STREAM_PATHS.contains(&topic.route()));
+    }
+    WRITE_STREAM_PATHS.contains(&path)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GatewayProfile {
+    /// Every route, against a full tapd.
+    #[default]
+    Full,
+    /// Reads and verification only, against a watch-only or public tapd.
+    ColdWatch,
+}
+
+impl GatewayProfile {
+    /// Parses `GATEWAY_PROFILE`.
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        match value.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "" | "full" => Ok(GatewayProfile::Full),
+            "cold_watch" => Ok(GatewayProfile::ColdWatch),
+            other => Err(AppError::ValidationError(format!(
+                "GATEWAY_PROFILE must be 'full' or 'cold_watch', got '{other}'"
+            ))),
+        }
+    }
+
+    pub fn is_cold_watch(&self) -> bool {
+        *self == GatewayProfile::ColdWatch
+    }
+
+    /// Whether a request with `method` to `path` and `query` is served under
+    /// this profile; `upgrade` is set for WebSocket upgrade requests.
+    pub fn allows(&self, method: &Method, path: &str, query: &str, upgrade: bool) -> bool {
+        if !self.is_cold_watch() {
+            return true;
+        }
+        match *method {
+            Method::GET if upgrade => !is_write_stream(path, query),
+            Method::GET | Method::HEAD | Method::OPTIONS => true,
+            Method::POST => VERIFY_PATHS.contains(&path.trim_end_matches('/')),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cold_watch_allows_only_reads_and_verification() {
+        let profile = GatewayProfile::parse("cold-watch").unwrap();
+        assert!(profile.allows(&Method::GET, "/v1/taproot-assets/assets", "", false));
+        assert!(profile.allows(&Method::POST, "/v1/taproot-assets/proofs/verify", "", false));
+        assert!(profile.allows(&Method::POST, "/v1/gateway/transfers/query/", "", false));
+        assert!(!profile.allows(&Method::POST, "/v1/taproot-assets/send", "", false));
+        assert!(!profile.allows(&Method::POST, "/v1/gateway/admin/maintenance", "", false));
+        assert!(!profile.allows(
+            &Method::DELETE,
+            "/v1/gateway/webhooks/dead-letters/1",
+            "",
+            false
+        ));
+
+        // Event streams stay open; streams that pay or write do not
+        let events = "/v1/taproot-assets/events/asset-send";
+        assert!(profile.allows(&Method::GET, events, "", true));
+        let subscribe = "/v1/taproot-assets/subscribe";
+        assert!(profile.allows(&Method::GET, subscribe, "topic=asset-mint", true));
+        assert!(!profile.allows(&Method::GET, subscribe, "topic=send-payment", true));
+        assert!(!profile.allows(
+            &Method::GET,
+            subscribe,
+            "topic=asset-mint&topic=Mailbox-Receive",
+            true
+        ));
+        let send_payment = "/v1/taproot-assets/channels/send-payment";
+        assert!(!profile.allows(&Method::GET, send_payment, "method=POST", true));
+        assert!(!profile.allows(
+            &Method::GET,
+            "/v1/taproot-assets/mailbox/receive/",
+            "",
+            true
+        ));
+
+        let full = GatewayProfile::parse("").unwrap();
+        assert!(full.allows(&Method::POST, "/v1/taproot-assets/send", "", false));
+        assert!(GatewayProfile::parse("replica").is_err());
+    }
+}
@@ -1,7 +1,13 @@
 use crate::error::AppError;
 use base64::Engine;
-use bitcoin::hashes::{sha256, Hash};
-use secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1};
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use bitcoin::key::{CompressedPublicKey, TweakedPublicKey};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::{
+    absolute, opcodes, script, transaction, Amount, Network, OutPoint, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Witness,
+};
+use secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1, XOnlyPublicKey};
 use sha2::{Digest, Sha256};
 use std::str::FromStr;
 use tracing::{debug, error, info};
@@ -125,6 +131,191 @@ pub fn verify_schnorr_signature(
     }
 }
 
+/// Verifies a BIP-340 signature by the holder of an asset group key. Group
+/// keys are published as 33-byte compressed keys; the signature is checked
+/// against the x-only part, so a 32-byte key is accepted as well.
+pub fn verify_group_key_signature(
+    message: &str,
+    signature_str: &str,
+    group_key: &str,
+) -> Result<bool, AppError> {
+    let xonly = match PublicKey::from_str(group_key) {
+        Ok(key) => key.x_only_public_key().0,
+        Err(_) => XOnlyPublicKey::from_str(group_key).map_err(|_| {
+            AppError::InvalidInput(format!("Invalid group key format: {group_key}"))
+        })?,
+    };
+    verify_schnorr_signature(message, signature_str, &xonly.to_string())
+}
+
+/// Tag of the BIP-322 message hash.
+const BIP322_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// Verifies a BIP-322 "simple" signature, the base64 consensus-encoded
+/// witness that spends `address`'s output in the BIP's virtual `to_sign`
+/// transaction. P2WPKH and key-path P2TR addresses are supported, which
+/// covers what common wallets produce for their own receive addresses.
+/// Addresses for another network than `network` are refused.
+pub fn verify_bip322_signature(
+    message: &str,
+    signature_b64: &str,
+    address: &str,
+    network: Network,
+) -> Result<bool, AppError> {
+    let script_pubkey = parse_address(address, network)?;
+    let witness_bytes = base64::engine::general_purpose::STANDARD
+        .decode(signature_b64.trim())
+        .map_err(|e| AppError::InvalidInput(format!("Invalid base64 BIP-322 signature: {e}")))?;
+    let witness: Witness = bitcoin::consensus::deserialize(&witness_bytes)
+        .map_err(|e| AppError::InvalidInput(format!("Invalid BIP-322 witness: {e}")))?;
+
+    let to_spend = bip322_to_spend(message, &script_pubkey);
+    let to_sign = bip322_to_sign(&to_spend, witness.clone());
+    let secp = Secp256k1::verification_only();
+
+    let verified = if script_pubkey.is_p2wpkh() {
+        let (Some(sig), Some(key), 2) = (witness.nth(0), witness.nth(1), witness.len()) else {
+            return Ok(false);
+        };
+        let (Ok(sig), Ok(key)) = (
+            bitcoin::ecdsa::Signature::from_slice(sig),
+            CompressedPublicKey::from_slice(key),
+        ) else {
+            return Ok(false);
+        };
+        if ScriptBuf::new_p2wpkh(&key.wpubkey_hash()) != script_pubkey {
+            return Ok(false);
+        }
+        let sighash = SighashCache::new(&to_sign)
+            .p2wpkh_signature_hash(0, &script_pubkey, Amount::ZERO, sig.sighash_type)
+            .map_err(|e| AppError::InvalidInput(format!("Failed to compute sighash: {e}")))?;
+        let msg = Message::from_digest(sighash.to_byte_array());
+        secp.verify_ecdsa(&msg, &sig.signature, &key.0).is_ok()
+    } else if script_pubkey.is_p2tr() {
+        let (Some(sig), 1) = (witness.nth(0), witness.len()) else {
+            return Ok(false);
+        };
+        let Ok(sig) = bitcoin::taproot::Signature::from_slice(sig) else {
+            return Ok(false);
+        };
+        let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..34])
+            .map_err(|e| AppError::InvalidInput(format!("Invalid taproot output key: {e}")))?;
+        let prevouts = [TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script_pubkey.clone(),
+        }];
+        let sighash = SighashCache::new(&to_sign)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), sig.sighash_type)
+            .map_err(|e| AppError::InvalidInput(format!("Failed to compute sighash: {e}")))?;
+        let msg = Message::from_digest(sighash.to_byte_array());
+        secp.verify_schnorr(&sig.signature, &msg, &output_key)
+            .is_ok()
+    } else {
+        return Err(AppError::InvalidInput(
+            "BIP-322 signatures are only supported for P2WPKH and P2TR addresses".to_string(),
+        ));
+    };
+
+    if verified {
+        info!("BIP-322 signature verification successful");
+    } else {
+        debug!("BIP-322 signature verification failed for {}", address);
+    }
+    Ok(verified)
+}
+
+/// Whether `address` pays to `public_key`: a compressed key's P2WPKH or
+/// BIP-86 P2TR address, or the P2TR address of an x-only key taken either as
+/// the internal key or as the output key itself. Addresses for another
+/// network than `network` are refused.
+pub fn key_controls_address(
+    public_key: &str,
+    address: &str,
+    network: Network,
+) -> Result<bool, AppError> {
+    let script_pubkey = parse_address(address, network)?;
+    let secp = Secp256k1::verification_only();
+    let candidates = if let Ok(key) = PublicKey::from_str(public_key) {
+        let (xonly, _) = key.x_only_public_key();
+        vec![
+            ScriptBuf::new_p2wpkh(&CompressedPublicKey(key).wpubkey_hash()),
+            ScriptBuf::new_p2tr(&secp, xonly, None),
+        ]
+    } else if let Ok(xonly) = XOnlyPublicKey::from_str(public_key) {
+        vec![
+            ScriptBuf::new_p2tr(&secp, xonly, None),
+            ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(xonly)),
+        ]
+    } else {
+        return Err(AppError::InvalidInput(format!(
+            "Invalid public key format: {public_key}"
+        )));
+    };
+    Ok(candidates.contains(&script_pubkey))
+}
+
+fn parse_address(address: &str, network: Network) -> Result<ScriptBuf, AppError> {
+    bitcoin::Address::from_str(address.trim())
+        .map_err(|e| AppError::InvalidInput(format!("Invalid bitcoin address: {e}")))?
+        .require_network(network)
+        .map(|address| address.script_pubkey())
+        .map_err(|_| AppError::ValidationError(format!("Address is not for {network}")))
+}
+
+/// `sha256(tag || tag || message)` with the BIP-322 tag.
+fn bip322_message_hash(message: &str) -> [u8; 32] {
+    let tag = sha256::Hash::hash(BIP322_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_byte_array());
+    engine.input(tag.as_byte_array());
+    engine.input(message.as_bytes());
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// The virtual transaction whose only output the signature spends.
+fn bip322_to_spend(message: &str, script_pubkey: &ScriptBuf) -> Transaction {
+    Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: script::Builder::new()
+                .push_opcode(opcodes::OP_0)
+                .push_slice(bip322_message_hash(message))
+                .into_script(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script_pubkey.clone(),
+        }],
+    }
+}
+
+/// The virtual transaction the signer signs, spending `to_spend`.
+fn bip322_to_sign(to_spend: &Transaction, witness: Witness) -> Transaction {
+    Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: to_spend.compute_txid(),
+                vout: 0,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness,
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script::Builder::new()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .into_script(),
+        }],
+    }
+}
+
 /// Derives a public key from a receiver ID (if receiver ID is a public key)
 pub fn derive_public_key_from_receiver_id(receiver_id: &str) -> Result<Option<String>, AppError> {
     // Check if receiver_id is already a public key (33 or 65 bytes hex encoded)
@@ -150,6 +341,199 @@ pub fn derive_public_key_from_receiver_id(receiver_id: &str) -> Result<Option<St
     Ok(None)
 }
 
+/// HMAC-SHA256 of `message` under `secret`.
+pub fn hmac_sha256(secret: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret);
+    engine.input(message);
+    hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+}
+
+/// Hex-encoded HMAC-SHA256 of `message` under `secret`, used to sign outbound
+/// webhook payloads.
+pub fn hmac_sha256_hex(secret: &[u8], message: &[u8]) -> String {
+    hex::encode(hmac_sha256(secret, message))
+}
+
+/// Compact JSON with object keys sorted, so the signer and a verifier
+/// hash the same bytes whatever key order each of them received.
+pub fn canonical_json(value: &serde_json::Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// Signs response bodies and webhooks with BIP-340 Schnorr keys so consumers
+/// can detect tampering by infrastructure between them and the gateway.
+/// Signatures are over the SHA-256 of the message and check with
+/// [`verify_schnorr_signature`].
+///
+/// The signer holds a key ring: the first key signs, the others are only
+/// published (see [`ResponseSigner::jwks`]) so consumers keep verifying
+/// during a rotation window.
+pub struct ResponseSigner {
+    secp: Secp256k1<secp256k1::All>,
+    keys: Vec<SigningKey>,
+}
+
+struct SigningKey {
+    kid: String,
+    keypair: secp256k1::Keypair,
+}
+
+pub type SharedResponseSigner = std::sync::Arc<ResponseSigner>;
+
+impl ResponseSigner {
+    /// Loads the hex-encoded 32-byte secret key as the only key; its id is
+    /// derived from the public key.
+    pub fn from_hex(secret_hex: &str) -> Result<Self, AppError> {
+        let secp = Secp256k1::new();
+        let keypair = signing_keypair(&secp, secret_hex, "RESPONSE_SIGNING_KEY")?;
+        let kid = default_kid(&keypair);
+        Ok(Self {
+            secp,
+            keys: vec![SigningKey { kid, keypair }],
+        })
+    }
+
+    /// Loads a comma-separated `kid:secret_hex` list, active key first. A
+    /// bare `secret_hex` entry gets an id derived from its public key.
+    pub fn from_keys(spec: &str) -> Result<Self, AppError> {
+        let secp = Secp256k1::new();
+        let mut keys: Vec<SigningKey> = Vec::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (kid, secret) = match entry.split_once(':') {
+                Some((kid, secret)) => (Some(kid.trim()), secret),
+                None => (None, entry),
+            };
+            let keypair = signing_keypair(&secp, secret, "RESPONSE_SIGNING_KEYS")?;
+            let kid = match kid {
+                Some(kid) if kid.is_empty() || !kid.chars().all(is_kid_char) => {
+                    return Err(AppError::ValidationError(format!(
+                        "RESPONSE_SIGNING_KEYS key id '{kid}' must be non-empty and use only \
+                         letters, digits, '-', '_' or '.'"
+                    )));
+                }
+                Some(kid) => kid.to_string(),
+                None => default_kid(&keypair),
+            };
+            if keys.iter().any(|k| k.kid == kid) {
+                return Err(AppError::ValidationError(format!(
+                    "RESPONSE_SIGNING_KEYS lists key id '{kid}' twice"
+                )));
+            }
+            keys.push(SigningKey { kid, keypair });
+        }
+        if keys.is_empty() {
+            return Err(AppError::ValidationError(
+                "RESPONSE_SIGNING_KEYS must list at least one key".to_string(),
+            ));
+        }
+        Ok(Self { secp, keys })
+    }
+
+    fn active(&self) -> &SigningKey {
+        &self.keys[0]
+    }
+
+    /// Id of the key that signs.
+    pub fn kid(&self) -> &str {
+        &self.active().kid
+    }
+
+    /// Hex x-only public key that signatures verify against.
+    pub fn public_key_hex(&self) -> String {
+        self.active().keypair.x_only_public_key().0.to_string()
+    }
+
+    /// Hex Schnorr signature over the SHA-256 of `message`.
+    pub fn sign(&self, message: &str) -> String {
+        let hash = sha256::Hash::hash(message.as_bytes());
+        let msg = Message::from_digest(hash.to_byte_array());
+        hex::encode(
+            self.secp
+                .sign_schnorr_no_aux_rand(&msg, &self.active().keypair)
+                .serialize(),
+        )
+    }
+
+    /// Every public key as a JWKS-style document. The `x` member is the
+    /// base64url x-only key; `public_key` repeats it in hex.
+    pub fn jwks(&self) -> serde_json::Value {
+        let keys: Vec<_> = self
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                let x_only = key.keypair.x_only_public_key().0;
+                serde_json::json!({
+                    "kid": key.kid,
+                    "kty": "EC",
+                    "crv": "secp256k1",
+                    "alg": "BIP340",
+                    "use": "sig",
+                    "x": base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(x_only.serialize()),
+                    "public_key": x_only.to_string(),
+                    "status": if i == 0 { "active" } else { "verify-only" },
+                })
+            })
+            .collect();
+        serde_json::json!({ "keys": keys })
+    }
+}
+
+fn signing_keypair(
+    secp: &Secp256k1<secp256k1::All>,
+    secret_hex: &str,
+    var: &str,
+) -> Result<secp256k1::Keypair, AppError> {
+    let bytes = hex::decode(secret_hex.trim())
+        .map_err(|_| AppError::ValidationError(format!("{var} must be hex")))?;
+    secp256k1::Keypair::from_seckey_slice(secp, &bytes).map_err(|_| {
+        AppError::ValidationError(format!(
+            "{var} must be a valid 32-byte secp256k1 secret key"
+        ))
+    })
+}
+
+/// First 8 bytes of the x-only public key, in hex.
+fn default_kid(keypair: &secp256k1::Keypair) -> String {
+    keypair.x_only_public_key().0.to_string()[..16].to_string()
+}
+
+fn is_kid_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -174,6 +558,52 @@ mod tests {
         (keypair, xonly_pubkey)
     }
 
+    #[test]
+    fn test_canonical_json_sorts_keys() {
+        let a = serde_json::json!({"b": 1, "a": {"d": [1, {"z": true, "y": null}], "c": "x"}});
+        assert_eq!(
+            canonical_json(&a),
+            r#"{"a":{"c":"x","d":[1,{"y":null,"z":true}]},"b":1}"#
+        );
+    }
+
+    #[test]
+    fn test_response_signature_verifies() {
+        let signer = ResponseSigner::from_hex(&"01".repeat(32)).unwrap();
+        let body = canonical_json(&serde_json::json!({"valid": true}));
+        let signature = signer.sign(&body);
+        assert!(verify_schnorr_signature(&body, &signature, &signer.public_key_hex()).unwrap());
+        assert!(!verify_schnorr_signature("{}", &signature, &signer.public_key_hex()).unwrap());
+        assert!(ResponseSigner::from_hex("zz").is_err());
+        assert!(ResponseSigner::from_hex(&"00".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_key_ring_signs_with_first_key_and_publishes_all() {
+        let spec = format!("2026-10:{},2026-04:{}", "03".repeat(32), "01".repeat(32));
+        let signer = ResponseSigner::from_keys(&spec).unwrap();
+        let previous = ResponseSigner::from_hex(&"01".repeat(32)).unwrap();
+        assert_eq!(signer.kid(), "2026-10");
+        assert_ne!(signer.public_key_hex(), previous.public_key_hex());
+
+        let jwks = signer.jwks();
+        let keys = jwks["keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0]["status"], "active");
+        assert_eq!(keys[1]["kid"], "2026-04");
+        assert_eq!(keys[1]["status"], "verify-only");
+        assert_eq!(keys[1]["public_key"], previous.public_key_hex().as_str());
+
+        let signature = signer.sign("{}");
+        assert!(verify_schnorr_signature("{}", &signature, &signer.public_key_hex()).unwrap());
+
+        assert_eq!(previous.kid(), &previous.public_key_hex()[..16]);
+        let dup = format!("a:{},a:{}", "03".repeat(32), "01".repeat(32));
+        assert!(ResponseSigner::from_keys(&dup).is_err());
+        assert!(ResponseSigner::from_keys(&format!("bad id:{}", "03".repeat(32))).is_err());
+        assert!(ResponseSigner::from_keys(" , ").is_err());
+    }
+
     #[test]
     fn test_verify_signature_invalid_pubkey() {
         let result = verify_signature("test message", "abcdef1234567890", "invalid_pubkey");
@@ -296,6 +726,28 @@ mod tests {
         assert!(result, "Valid Schnorr signature should verify successfully");
     }
 
+    #[test]
+    fn test_verify_group_key_signature_accepts_compressed_keys() {
+        let secp = Secp256k1::new();
+        // Find a key with an odd y coordinate, which BIP-340 signing negates
+        let keypair = (1..=255u8)
+            .map(|b| secp256k1::Keypair::from_seckey_slice(&secp, &[b; 32]).unwrap())
+            .find(|k| k.public_key().serialize()[0] == 0x03)
+            .unwrap();
+        let message = "{\"asset_id\":\"aa\"}";
+        let hash = sha256::Hash::hash(message.as_bytes());
+        let signature =
+            secp.sign_schnorr_no_aux_rand(&Message::from_digest(hash.to_byte_array()), &keypair);
+        let sig_hex = hex::encode(signature.as_ref());
+        let compressed = keypair.public_key().to_string();
+        let xonly = keypair.x_only_public_key().0.to_string();
+
+        assert!(verify_group_key_signature(message, &sig_hex, &compressed).unwrap());
+        assert!(verify_group_key_signature(message, &sig_hex, &xonly).unwrap());
+        assert!(!verify_group_key_signature("{}", &sig_hex, &compressed).unwrap());
+        assert!(verify_group_key_signature(message, &sig_hex, "02abc").is_err());
+    }
+
     #[test]
     fn test_verify_schnorr_signature_base64() {
         let secp = Secp256k1::signing_only();
@@ -467,4 +919,119 @@ mod tests {
             "Should return Ok(false) for invalid signature"
         );
     }
+
+    #[test]
+    fn test_hmac_sha256_rfc4231_vector() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    // Test vectors from BIP-322
+    const BIP322_P2WPKH: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const BIP322_P2WPKH_PUBKEY: &str =
+        "02c7f12003196442943d8588e01aee840423cc54fc1521526a3b85c2b0cbd58872";
+
+    #[test]
+    fn test_bip322_message_hash_vectors() {
+        assert_eq!(
+            hex::encode(bip322_message_hash("")),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            hex::encode(bip322_message_hash("Hello World")),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn test_verify_bip322_vectors() {
+        let empty = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        let hello = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        assert!(verify_bip322_signature("", empty, BIP322_P2WPKH, Network::Bitcoin).unwrap());
+        assert!(
+            verify_bip322_signature("Hello World", hello, BIP322_P2WPKH, Network::Bitcoin).unwrap()
+        );
+        assert!(
+            !verify_bip322_signature("Hello World", empty, BIP322_P2WPKH, Network::Bitcoin)
+                .unwrap()
+        );
+
+        let taproot = "bc1ppv609nr0vr25u07u95waq5lucwfm6tde4nydujnu8npg4q75mr5sxq8lt3";
+        let taproot_sig = "AUHd69PrJQEv+oKTfZ8l+WROBHuy9HKrbFCJu7U1iK2iiEy1vMU5EfMtjc+VSHM7aU0SDbak5IUZRVno2P5mjSafAQ==";
+        assert!(
+            verify_bip322_signature("Hello World", taproot_sig, taproot, Network::Bitcoin).unwrap()
+        );
+        assert!(!verify_bip322_signature("Hello", taproot_sig, taproot, Network::Bitcoin).unwrap());
+
+        // A P2WPKH witness presented for a taproot address
+        assert!(!verify_bip322_signature("Hello World", hello, taproot, Network::Bitcoin).unwrap());
+        assert!(
+            verify_bip322_signature("", "not base64!", BIP322_P2WPKH, Network::Bitcoin).is_err()
+        );
+        assert!(verify_bip322_signature(
+            "",
+            empty,
+            "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+            Network::Bitcoin
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_verify_bip322_taproot_roundtrip() {
+        let secp = Secp256k1::new();
+        let (keypair, internal_key) = create_test_schnorr_keypair(7);
+        let address = bitcoin::Address::p2tr(&secp, internal_key, None, bitcoin::Network::Regtest)
+            .to_string();
+        let message = "Sign this challenge: c1-1700000000-bm9uY2U=";
+
+        let script_pubkey = parse_address(&address, Network::Regtest).unwrap();
+        let to_spend = bip322_to_spend(message, &script_pubkey);
+        let to_sign = bip322_to_sign(&to_spend, Witness::new());
+        let prevouts = [to_spend.output[0].clone()];
+        let sighash = SighashCache::new(&to_sign)
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&prevouts),
+                bitcoin::TapSighashType::Default,
+            )
+            .unwrap();
+        let tweaked = bitcoin::key::TapTweak::tap_tweak(keypair, &secp, None).to_keypair();
+        let signature =
+            secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &tweaked);
+        let witness = Witness::from_slice(&[signature.serialize().to_vec()]);
+        let encoded = base64::engine::general_purpose::STANDARD
+            .encode(bitcoin::consensus::serialize(&witness));
+
+        assert!(verify_bip322_signature(message, &encoded, &address, Network::Regtest).unwrap());
+        assert!(!verify_bip322_signature("other", &encoded, &address, Network::Regtest).unwrap());
+        assert!(
+            key_controls_address(&internal_key.to_string(), &address, Network::Regtest).unwrap()
+        );
+
+        // The same address checked against another network is refused
+        assert!(matches!(
+            verify_bip322_signature(message, &encoded, &address, Network::Bitcoin),
+            Err(AppError::ValidationError(_))
+        ));
+        assert!(matches!(
+            key_controls_address(&internal_key.to_string(), &address, Network::Bitcoin),
+            Err(AppError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_key_controls_address() {
+        assert!(
+            key_controls_address(BIP322_P2WPKH_PUBKEY, BIP322_P2WPKH, Network::Bitcoin).unwrap()
+        );
+        let (_, other) = create_test_keypair(3);
+        assert!(
+            !key_controls_address(&other.to_string(), BIP322_P2WPKH, Network::Bitcoin).unwrap()
+        );
+        assert!(key_controls_address("zz", BIP322_P2WPKH, Network::Bitcoin).is_err());
+    }
 }
@@ -8,6 +8,116 @@ use std::sync::Arc;
 use std::time::Duration;
 use tracing::{info, warn};
 
+mod attestations;
+mod auth_failures;
+mod backfills;
+mod escrows;
+mod feature_flags;
+mod gateway_counters;
+mod mailbox_outbox;
+mod network_acl;
+mod payment_requests;
+mod proof_cache;
+mod proof_files;
+mod psbt_reservations;
+mod receive_addresses;
+mod receiver_keys;
+mod recurring_payments;
+mod route_groups;
+mod scheduled_jobs;
+mod sessions;
+mod sub_accounts;
+mod swaps;
+mod transfer_filters;
+mod transfer_labels;
+mod transfers;
+mod universe_leaves;
+mod universe_syncs;
+mod usage;
+mod webhooks;
+
+pub use attestations::AttestationRecord;
+pub use auth_failures::AuthFailureRecord;
+pub use backfills::{BackfillPhase, BackfillProgress, BackfillRun, BackfillStatus};
+pub use escrows::{Escrow, EscrowQuery, EscrowStatus};
+pub use feature_flags::FeatureFlagRecord;
+pub use gateway_counters::GatewayCounters;
+pub use mailbox_outbox::{OutboxMessage, OutboxStatus, RetentionScope};
+pub use network_acl::NetworkAclRecord;
+pub use payment_requests::{PaymentRequest, PaymentRequestQuery, PaymentStatus};
+pub use proof_files::ProofFile;
+pub use psbt_reservations::{FlowStage, PsbtReservation};
+pub use receive_addresses::ReceiveAddress;
+pub use receiver_keys::{KeyRotation, ReceiverKeys};
+pub use recurring_payments::{
+    RecurringPayment, RecurringPaymentQuery, RecurringRun, RecurringStatus, RunStatus,
+};
+pub use route_groups::RouteGroupRecord;
+pub use scheduled_jobs::JobRecord;
+pub use sessions::SessionRecord;
+pub use sub_accounts::{SubAccount, SubAccountBalance};
+pub use swaps::{Swap, SwapQuery, SwapRole, SwapStatus};
+pub use transfer_filters::{FilterCondition, FilterSort, TransferFilter, TransferFilterQuery};
+pub use transfers::{
+    AssetPosition, BurnTotal, ChainState, ChainStatus, IndexedTransfer, SendActivity, TransferKind,
+    TransferQuery,
+};
+pub use universe_leaves::UniverseLeaf;
+pub use universe_syncs::{SyncTargetStatus, UniverseSync, UniverseSyncStatus, UniverseSyncTarget};
+pub use usage::UsageRecord;
+pub use webhooks::{
+    AddressWebhook, DeadLetter, DeliveryAttempt, DeliveryStatus, WebhookDelivery,
+    WebhookDeliveryStats, ADDRESS_RECEIVED_PREFIX,
+};
+
+const RECEIVERS_SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS receivers (
+        receiver_id TEXT PRIMARY KEY,
+        public_key TEXT NOT NULL,
+        address TEXT,
+        created_at INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL,
+        is_active INTEGER NOT NULL DEFAULT 1,
+        metadata TEXT,
+        UNIQUE(public_key)
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_receivers_public_key ON receivers(public_key);
+    CREATE INDEX IF NOT EXISTS idx_receivers_address ON receivers(address);
+    CREATE INDEX IF NOT EXISTS idx_receivers_is_active ON receivers(is_active);
+"#;
+
+/// Schemas applied on startup, in order. Every statement must be idempotent.
+const SCHEMAS: &[&str] = &[
+    RECEIVERS_SCHEMA,
+    transfers::SCHEMA,
+    webhooks::SCHEMA,
+    payment_requests::SCHEMA,
+    universe_syncs::SCHEMA,
+    usage::SCHEMA,
+    proof_cache::SCHEMA,
+    proof_files::SCHEMA,
+    receive_addresses::SCHEMA,
+    scheduled_jobs::SCHEMA,
+    route_groups::SCHEMA,
+    backfills::SCHEMA,
+    universe_leaves::SCHEMA,
+    transfer_labels::SCHEMA,
+    sub_accounts::SCHEMA,
+    mailbox_outbox::SCHEMA,
+    sessions::SCHEMA,
+    network_acl::SCHEMA,
+    auth_failures::SCHEMA,
+    swaps::SCHEMA,
+    escrows::SCHEMA,
+    recurring_payments::SCHEMA,
+    psbt_reservations::SCHEMA,
+    gateway_counters::SCHEMA,
+    feature_flags::SCHEMA,
+    attestations::SCHEMA,
+    receiver_keys::SCHEMA,
+];
+
 #[derive(Clone)]
 pub struct Database {
     sqlite_pool: Option<SqlitePool>,
@@ -70,27 +180,12 @@ impl Database {
             .map_err(|e| AppError::DatabaseError(format!("Failed to connect to database: {e}")))?;
 
         // Run migrations
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS receivers (
-                receiver_id TEXT PRIMARY KEY,
-                public_key TEXT NOT NULL,
-                address TEXT,
-                created_at INTEGER NOT NULL,
-                last_seen INTEGER NOT NULL,
-                is_active INTEGER NOT NULL DEFAULT 1,
-                metadata TEXT,
-                UNIQUE(public_key)
-            );
-            
-            CREATE INDEX IF NOT EXISTS idx_receivers_public_key ON receivers(public_key);
-            CREATE INDEX IF NOT EXISTS idx_receivers_address ON receivers(address);
-            CREATE INDEX IF NOT EXISTS idx_receivers_is_active ON receivers(is_active);
-            "#,
-        )
-        .execute(&pool)
-        .await
-        .map_err(|e| AppError::DatabaseError(format!("Failed to run migrations: {e}")))?;
+        for schema in SCHEMAS {
+            sqlx::query(schema)
+                .execute(&pool)
+                .await
+                .map_err(|e| AppError::DatabaseError(format!("Failed to run migrations: {e}")))?;
+        }
 
         info!("SQLite database initialized successfully");
         Ok(pool)
@@ -109,6 +204,15 @@ impl Database {
         Ok(conn_manager)
     }
 
+    /// Returns the SQLite pool, or an error when the gateway runs without one.
+    /// Subsystems that only make sense with durable storage use this instead of
+    /// silently degrading to Redis.
+    pub(crate) fn sqlite(&self) -> Result<&SqlitePool, AppError> {
+        self.sqlite_pool
+            .as_ref()
+            .ok_or_else(|| AppError::DatabaseError("SQLite backend is not configured".to_string()))
+    }
+
     /// Store receiver info in the database
     pub async fn store_receiver_info(&self, info: &ReceiverInfo) -> Result<(), AppError> {
         // Store in SQLite first if available - this is the persistent store
@@ -177,7 +281,15 @@ impl Database {
             ON CONFLICT(receiver_id) DO UPDATE SET
                 last_seen = excluded.last_seen,
                 is_active = excluded.is_active,
-                metadata = excluded.metadata
+                metadata = CASE
+                    WHEN json_extract(receivers.metadata, '$.retention') IS NULL
+                        THEN excluded.metadata
+                    ELSE json_set(
+                        COALESCE(excluded.metadata, '{}'),
+                        '$.retention',
+                        json(json_extract(receivers.metadata, '$.retention'))
+                    )
+                END
             "#,
         )
         .bind(&info.receiver_id)
@@ -363,6 +475,17 @@ pub async fn init_database(
     Ok(Arc::new(db))
 }
 
+/// Opens a throwaway SQLite database in the system temp directory for unit
+/// tests. In-memory databases are per-connection, which the pool defeats.
+#[cfg(test)]
+pub(crate) async fn open_test_database() -> SharedDatabase {
+    let path = std::env::temp_dir().join(format!("tapgw-test-{}.db", uuid::Uuid::new_v4()));
+    let url = format!("sqlite://{}", path.display());
+    init_database(Some(&url), None)
+        .await
+        .expect("failed to open test database")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,140 @@
+use super::Database;
+use crate::error::AppError;
+use sqlx::Row;
+
+/// Attestations issuers publish about their assets. `document` is the
+/// signed JSON exactly as submitted, so the signature can be re-checked
+/// against it.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS asset_attestations (
+        id TEXT PRIMARY KEY,
+        asset_id TEXT NOT NULL,
+        group_key TEXT NOT NULL,
+        document TEXT NOT NULL,
+        signature TEXT NOT NULL UNIQUE,
+        issued_at INTEGER NOT NULL,
+        expires_at INTEGER,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_asset_attestations_asset
+        ON asset_attestations(asset_id, issued_at);
+"#;
+
+/// A verified issuer attestation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttestationRecord {
+    pub id: String,
+    pub asset_id: String,
+    /// Group key the signature was verified against.
+    pub group_key: String,
+    pub document: serde_json::Value,
+    pub signature: String,
+    pub issued_at: i64,
+    pub expires_at: Option<i64>,
+    pub created_at: i64,
+}
+
+impl Database {
+    /// Stores an attestation. Returns `false` when one with the same
+    /// signature was already stored.
+    pub async fn save_attestation(&self, record: &AttestationRecord) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let document = serde_json::to_string(&record.document)
+            .map_err(|e| AppError::SerializationError(e.to_string()))?;
+        let result = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO asset_attestations
+                (id, asset_id, group_key, document, signature, issued_at, expires_at, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&record.id)
+        .bind(&record.asset_id)
+        .bind(&record.group_key)
+        .bind(document)
+        .bind(&record.signature)
+        .bind(record.issued_at)
+        .bind(record.expires_at)
+        .bind(record.created_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to save attestation: {e}")))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Attestations about `asset_id`, most recently issued first.
+    pub async fn attestations(&self, asset_id: &str) -> Result<Vec<AttestationRecord>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            r#"
+            SELECT id, asset_id, group_key, document, signature, issued_at, expires_at, created_at
+            FROM asset_attestations
+            WHERE asset_id = ?
+            ORDER BY issued_at DESC, created_at DESC
+            "#,
+        )
+        .bind(asset_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to load attestations: {e}")))?;
+        rows.iter()
+            .map(|row| {
+                let document: String = row.get("document");
+                Ok(AttestationRecord {
+                    id: row.get("id"),
+                    asset_id: row.get("asset_id"),
+                    group_key: row.get("group_key"),
+                    document: serde_json::from_str(&document)?,
+                    signature: row.get("signature"),
+                    issued_at: row.get("issued_at"),
+                    expires_at: row.get("expires_at"),
+                    created_at: row.get("created_at"),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_attestations_are_listed_newest_first() {
+        let db = open_test_database().await;
+        let record = |id: &str, asset_id: &str, signature: &str, issued_at| AttestationRecord {
+            id: id.to_string(),
+            asset_id: asset_id.to_string(),
+            group_key: "02".repeat(33),
+            document: serde_json::json!({ "asset_id": asset_id, "issued_at": issued_at }),
+            signature: signature.to_string(),
+            issued_at,
+            expires_at: None,
+            created_at: 500,
+        };
+        assert!(db
+            .save_attestation(&record("a", "aa", "s1", 100))
+            .await
+            .unwrap());
+        assert!(db
+            .save_attestation(&record("b", "aa", "s2", 200))
+            .await
+            .unwrap());
+        assert!(db
+            .save_attestation(&record("c", "bb", "s3", 300))
+            .await
+            .unwrap());
+        assert!(!db
+            .save_attestation(&record("d", "aa", "s1", 100))
+            .await
+            .unwrap());
+
+        let found = db.attestations("aa").await.unwrap();
+        assert_eq!(
+            found.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+            ["b", "a"]
+        );
+        assert_eq!(found[1], record("a", "aa", "s1", 100));
+    }
+}
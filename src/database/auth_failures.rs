@@ -0,0 +1,143 @@
+use super::Database;
+use crate::error::AppError;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+/// Failed authentication attempts per subject (`ip:<address>`,
+/// `mailbox:<receiver id>`, `login:<public key>`), and the lockout they
+/// earned.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS auth_failures (
+        key TEXT PRIMARY KEY,
+        failures INTEGER NOT NULL,
+        last_failure_at INTEGER NOT NULL,
+        locked_until INTEGER
+    );
+"#;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuthFailureRecord {
+    pub key: String,
+    /// Failures since the count last reset.
+    pub failures: u32,
+    pub last_failure_at: i64,
+    pub locked_until: Option<i64>,
+}
+
+impl Database {
+    /// Counts a failure for `key` at `now` and returns the new count. A
+    /// count whose last failure is older than `reset_before` starts over.
+    pub async fn record_auth_failure(
+        &self,
+        key: &str,
+        now: i64,
+        reset_before: i64,
+    ) -> Result<u32, AppError> {
+        let pool = self.sqlite()?;
+        let failures: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO auth_failures (key, failures, last_failure_at)
+            VALUES (?, 1, ?)
+            ON CONFLICT(key) DO UPDATE SET
+                failures = CASE WHEN last_failure_at < ? THEN 1 ELSE failures + 1 END,
+                last_failure_at = excluded.last_failure_at
+            RETURNING failures
+            "#,
+        )
+        .bind(key)
+        .bind(now)
+        .bind(reset_before)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to record auth failure: {e}")))?;
+        Ok(failures as u32)
+    }
+
+    pub async fn set_auth_lockout(&self, key: &str, locked_until: i64) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query("UPDATE auth_failures SET locked_until = ? WHERE key = ?")
+            .bind(locked_until)
+            .bind(key)
+            .execute(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to store lockout: {e}")))?;
+        Ok(())
+    }
+
+    /// Forgets `key`'s failures and lockout; false if it had none.
+    pub async fn clear_auth_failures(&self, key: &str) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query("DELETE FROM auth_failures WHERE key = ?")
+            .bind(key)
+            .execute(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to clear auth failures: {e}")))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes counts whose last failure is before `before` and whose
+    /// lockout, if any, ended by `now`.
+    pub async fn prune_auth_failures(&self, before: i64, now: i64) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            "DELETE FROM auth_failures WHERE last_failure_at < ? \
+             AND (locked_until IS NULL OR locked_until <= ?)",
+        )
+        .bind(before)
+        .bind(now)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to prune auth failures: {e}")))?;
+        Ok(result.rows_affected())
+    }
+
+    /// Every tracked subject, most recent failure first.
+    pub async fn auth_failure_records(&self) -> Result<Vec<AuthFailureRecord>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            "SELECT key, failures, last_failure_at, locked_until FROM auth_failures \
+             ORDER BY last_failure_at DESC, key",
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to load auth failures: {e}")))?;
+        Ok(rows.iter().map(auth_failure_from_row).collect())
+    }
+}
+
+fn auth_failure_from_row(row: &SqliteRow) -> AuthFailureRecord {
+    AuthFailureRecord {
+        key: row.get("key"),
+        failures: row.get::<i64, _>("failures") as u32,
+        last_failure_at: row.get("last_failure_at"),
+        locked_until: row.get("locked_until"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_auth_failures_count_reset_and_prune() {
+        let db = open_test_database().await;
+        assert_eq!(db.record_auth_failure("ip:1", 100, 0).await.unwrap(), 1);
+        assert_eq!(db.record_auth_failure("ip:1", 110, 0).await.unwrap(), 2);
+        // A failure long after the last one starts a new count
+        assert_eq!(db.record_auth_failure("ip:1", 500, 400).await.unwrap(), 1);
+
+        db.set_auth_lockout("ip:1", 600).await.unwrap();
+        assert_eq!(
+            db.auth_failure_records().await.unwrap()[0].locked_until,
+            Some(600)
+        );
+        // Still locked, so kept
+        assert_eq!(db.prune_auth_failures(1000, 550).await.unwrap(), 0);
+        assert_eq!(db.prune_auth_failures(1000, 600).await.unwrap(), 1);
+
+        db.record_auth_failure("ip:2", 100, 0).await.unwrap();
+        assert!(db.clear_auth_failures("ip:2").await.unwrap());
+        assert!(!db.clear_auth_failures("ip:2").await.unwrap());
+    }
+}
@@ -0,0 +1,322 @@
+use super::Database;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS backfill_runs (
+        id TEXT PRIMARY KEY,
+        status TEXT NOT NULL,
+        phase TEXT NOT NULL,
+        cursor INTEGER NOT NULL DEFAULT 0,
+        transfers INTEGER NOT NULL DEFAULT 0,
+        receives INTEGER NOT NULL DEFAULT 0,
+        mints INTEGER NOT NULL DEFAULT 0,
+        burns INTEGER NOT NULL DEFAULT 0,
+        universe_roots INTEGER NOT NULL DEFAULT 0,
+        universe_leaves INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,
+        finished_at INTEGER
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_backfill_runs_status ON backfill_runs(status);
+"#;
+
+const SELECT_COLUMNS: &str = "SELECT id, status, phase, cursor, transfers, receives, mints, \
+     burns, universe_roots, universe_leaves, last_error, created_at, updated_at, finished_at \
+     FROM backfill_runs";
+
+const DEFAULT_LIST_LIMIT: u32 = 20;
+const MAX_LIST_LIMIT: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackfillStatus {
+    Running,
+    /// Stopped by an error or a restart; resuming continues at `phase`.
+    Partial,
+    Completed,
+}
+
+impl BackfillStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackfillStatus::Running => "running",
+            BackfillStatus::Partial => "partial",
+            BackfillStatus::Completed => "completed",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "running" => Ok(BackfillStatus::Running),
+            "partial" => Ok(BackfillStatus::Partial),
+            "completed" => Ok(BackfillStatus::Completed),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown backfill status: {other}"
+            ))),
+        }
+    }
+}
+
+/// The steps of a backfill, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackfillPhase {
+    Transfers,
+    Receives,
+    Mints,
+    Burns,
+    /// Universe roots are walked a page at a time; `cursor` is the offset of
+    /// the next page.
+    Universe,
+    Done,
+}
+
+impl BackfillPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackfillPhase::Transfers => "transfers",
+            BackfillPhase::Receives => "receives",
+            BackfillPhase::Mints => "mints",
+            BackfillPhase::Burns => "burns",
+            BackfillPhase::Universe => "universe",
+            BackfillPhase::Done => "done",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "transfers" => Ok(BackfillPhase::Transfers),
+            "receives" => Ok(BackfillPhase::Receives),
+            "mints" => Ok(BackfillPhase::Mints),
+            "burns" => Ok(BackfillPhase::Burns),
+            "universe" => Ok(BackfillPhase::Universe),
+            "done" => Ok(BackfillPhase::Done),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown backfill phase: {other}"
+            ))),
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            BackfillPhase::Transfers => BackfillPhase::Receives,
+            BackfillPhase::Receives => BackfillPhase::Mints,
+            BackfillPhase::Mints => BackfillPhase::Burns,
+            BackfillPhase::Burns => BackfillPhase::Universe,
+            BackfillPhase::Universe | BackfillPhase::Done => BackfillPhase::Done,
+        }
+    }
+}
+
+/// Rows written so far, per source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackfillProgress {
+    pub transfers: u64,
+    pub receives: u64,
+    pub mints: u64,
+    pub burns: u64,
+    pub universe_roots: u64,
+    pub universe_leaves: u64,
+}
+
+/// An admin-triggered backfill of the index, saved after every step so it
+/// can continue where it stopped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillRun {
+    pub id: String,
+    pub status: BackfillStatus,
+    pub phase: BackfillPhase,
+    pub cursor: u64,
+    pub progress: BackfillProgress,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+impl Database {
+    /// Stores a new running backfill unless another one is running. Returns
+    /// whether it was stored.
+    pub async fn insert_backfill_run(&self, run: &BackfillRun) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            r#"
+            INSERT INTO backfill_runs (id, status, phase, cursor, created_at, updated_at)
+            SELECT ?, 'running', ?, ?, ?, ?
+            WHERE NOT EXISTS (SELECT 1 FROM backfill_runs WHERE status = 'running')
+            "#,
+        )
+        .bind(&run.id)
+        .bind(run.phase.as_str())
+        .bind(run.cursor as i64)
+        .bind(run.created_at)
+        .bind(run.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store backfill: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn get_backfill_run(&self, id: &str) -> Result<Option<BackfillRun>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!("{SELECT_COLUMNS} WHERE id = ?"))
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to query backfill: {e}")))?;
+        row.as_ref().map(backfill_from_row).transpose()
+    }
+
+    /// Backfills, newest first.
+    pub async fn list_backfill_runs(
+        &self,
+        limit: Option<u32>,
+    ) -> Result<Vec<BackfillRun>, AppError> {
+        let pool = self.sqlite()?;
+        let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT);
+        let rows = sqlx::query(&format!(
+            "{SELECT_COLUMNS} ORDER BY created_at DESC, id ASC LIMIT ?"
+        ))
+        .bind(limit as i64)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to list backfills: {e}")))?;
+        rows.iter().map(backfill_from_row).collect()
+    }
+
+    /// Marks a partial backfill running again, unless another backfill is
+    /// running. Returns whether it was claimed.
+    pub async fn claim_backfill_run(&self, id: &str) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            r#"
+            UPDATE backfill_runs SET status = 'running', updated_at = ?
+            WHERE id = ? AND status = 'partial'
+              AND NOT EXISTS (SELECT 1 FROM backfill_runs WHERE status = 'running')
+            "#,
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to claim backfill: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Saves a backfill's status, position and counters.
+    pub async fn save_backfill_run(&self, run: &BackfillRun) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            UPDATE backfill_runs SET
+                status = ?, phase = ?, cursor = ?, transfers = ?, receives = ?, mints = ?,
+                burns = ?, universe_roots = ?, universe_leaves = ?, last_error = ?,
+                updated_at = ?, finished_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(run.status.as_str())
+        .bind(run.phase.as_str())
+        .bind(run.cursor as i64)
+        .bind(run.progress.transfers as i64)
+        .bind(run.progress.receives as i64)
+        .bind(run.progress.mints as i64)
+        .bind(run.progress.burns as i64)
+        .bind(run.progress.universe_roots as i64)
+        .bind(run.progress.universe_leaves as i64)
+        .bind(&run.last_error)
+        .bind(run.updated_at)
+        .bind(run.finished_at)
+        .bind(&run.id)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to save backfill: {e}")))?;
+        Ok(())
+    }
+
+    /// Backfills left running by a gateway that stopped mid-run become
+    /// partial. Returns how many were reset.
+    pub async fn reset_interrupted_backfills(&self) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            "UPDATE backfill_runs SET status = 'partial', updated_at = ? WHERE status = 'running'",
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to reset backfills: {e}")))?;
+        Ok(result.rows_affected())
+    }
+}
+
+fn backfill_from_row(row: &SqliteRow) -> Result<BackfillRun, AppError> {
+    let count = |column: &str| row.get::<i64, _>(column) as u64;
+    Ok(BackfillRun {
+        id: row.get("id"),
+        status: BackfillStatus::parse(row.get("status"))?,
+        phase: BackfillPhase::parse(row.get("phase"))?,
+        cursor: count("cursor"),
+        progress: BackfillProgress {
+            transfers: count("transfers"),
+            receives: count("receives"),
+            mints: count("mints"),
+            burns: count("burns"),
+            universe_roots: count("universe_roots"),
+            universe_leaves: count("universe_leaves"),
+        },
+        last_error: row.get("last_error"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        finished_at: row.get("finished_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn run(id: &str) -> BackfillRun {
+        BackfillRun {
+            id: id.to_string(),
+            status: BackfillStatus::Running,
+            phase: BackfillPhase::Transfers,
+            cursor: 0,
+            progress: BackfillProgress::default(),
+            last_error: None,
+            created_at: 1,
+            updated_at: 1,
+            finished_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_one_backfill_runs_at_a_time_and_resumes_after_restart() {
+        let db = open_test_database().await;
+        assert!(db.insert_backfill_run(&run("a")).await.unwrap());
+        assert!(!db.insert_backfill_run(&run("b")).await.unwrap());
+
+        let mut saved = run("a");
+        saved.phase = BackfillPhase::Universe;
+        saved.cursor = 50;
+        saved.progress.transfers = 7;
+        db.save_backfill_run(&saved).await.unwrap();
+
+        // A running backfill cannot be claimed until a restart resets it
+        assert!(!db.claim_backfill_run("a").await.unwrap());
+        assert_eq!(db.reset_interrupted_backfills().await.unwrap(), 1);
+        assert!(db.claim_backfill_run("a").await.unwrap());
+
+        let loaded = db.get_backfill_run("a").await.unwrap().unwrap();
+        assert_eq!(loaded.status, BackfillStatus::Running);
+        assert_eq!(loaded.phase, BackfillPhase::Universe);
+        assert_eq!(loaded.cursor, 50);
+        assert_eq!(loaded.progress.transfers, 7);
+        assert_eq!(db.list_backfill_runs(None).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,316 @@
+use super::Database;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+/// Assets locked to a hashlock-or-timeout script key. `refund_key_*` locate
+/// this node's key for the refund leaf, so the refund can be signed later.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS escrows (
+        id TEXT PRIMARY KEY,
+        asset_id TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        payment_hash TEXT NOT NULL,
+        preimage TEXT,
+        beneficiary_key TEXT NOT NULL,
+        refund_key TEXT NOT NULL,
+        refund_key_family INTEGER NOT NULL,
+        refund_key_index INTEGER NOT NULL,
+        script_key TEXT NOT NULL,
+        lock_height INTEGER NOT NULL,
+        address TEXT NOT NULL,
+        status TEXT NOT NULL,
+        outpoint TEXT,
+        refund_txid TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_escrows_status ON escrows(status, created_at);
+"#;
+
+const COLUMNS: &str = "id, asset_id, amount, payment_hash, preimage, beneficiary_key, refund_key, \
+     refund_key_family, refund_key_index, script_key, lock_height, address, status, outpoint, \
+     refund_txid, created_at, updated_at";
+
+const DEFAULT_LIST_LIMIT: u32 = 100;
+const MAX_LIST_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EscrowStatus {
+    /// The escrow address is out, waiting for the deposit.
+    Pending,
+    /// The deposit settled to the escrow script key.
+    Funded,
+    /// The preimage was revealed, so the beneficiary can claim the deposit.
+    Released,
+    /// The deposit went back to this node after the timeout.
+    Refunded,
+}
+
+impl EscrowStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EscrowStatus::Pending => "pending",
+            EscrowStatus::Funded => "funded",
+            EscrowStatus::Released => "released",
+            EscrowStatus::Refunded => "refunded",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "pending" => Ok(EscrowStatus::Pending),
+            "funded" => Ok(EscrowStatus::Funded),
+            "released" => Ok(EscrowStatus::Released),
+            "refunded" => Ok(EscrowStatus::Refunded),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown escrow status: {other}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Escrow {
+    pub id: String,
+    pub asset_id: String,
+    pub amount: u64,
+    /// Hex SHA-256 hash the release leaf checks the preimage against.
+    pub payment_hash: String,
+    /// Only serialized once released; see `Escrow::revealed`.
+    pub preimage: Option<String>,
+    /// Hex x-only key that signs the release leaf.
+    pub beneficiary_key: String,
+    /// Hex compressed key of this node that signs the refund leaf.
+    pub refund_key: String,
+    #[serde(skip_serializing, default)]
+    pub refund_key_family: u32,
+    #[serde(skip_serializing, default)]
+    pub refund_key_index: u32,
+    /// Hex tweaked x-only script key the deposit is locked to.
+    pub script_key: String,
+    /// Block height from which the refund leaf can be spent.
+    pub lock_height: u32,
+    /// Taproot Assets address the deposit is paid to.
+    pub address: String,
+    pub status: EscrowStatus,
+    /// `txid:vout` of the anchor output holding the deposit.
+    pub outpoint: Option<String>,
+    pub refund_txid: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Escrow {
+    /// The escrow as callers see it: a preimage the gateway generated stays
+    /// secret until the escrow is released.
+    pub fn revealed(mut self) -> Self {
+        if self.status != EscrowStatus::Released {
+            self.preimage = None;
+        }
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct EscrowQuery {
+    pub status: Option<EscrowStatus>,
+    pub asset_id: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl Database {
+    pub async fn insert_escrow(&self, escrow: &Escrow) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(&format!(
+            "INSERT INTO escrows ({COLUMNS}) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ))
+        .bind(&escrow.id)
+        .bind(&escrow.asset_id)
+        .bind(escrow.amount as i64)
+        .bind(&escrow.payment_hash)
+        .bind(&escrow.preimage)
+        .bind(&escrow.beneficiary_key)
+        .bind(&escrow.refund_key)
+        .bind(escrow.refund_key_family as i64)
+        .bind(escrow.refund_key_index as i64)
+        .bind(&escrow.script_key)
+        .bind(escrow.lock_height as i64)
+        .bind(&escrow.address)
+        .bind(escrow.status.as_str())
+        .bind(&escrow.outpoint)
+        .bind(&escrow.refund_txid)
+        .bind(escrow.created_at)
+        .bind(escrow.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store escrow: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn get_escrow(&self, id: &str) -> Result<Option<Escrow>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!("SELECT {COLUMNS} FROM escrows WHERE id = ?"))
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to fetch escrow: {e}")))?;
+        row.as_ref().map(escrow_from_row).transpose()
+    }
+
+    /// Lists escrows, newest first.
+    pub async fn list_escrows(&self, query: &EscrowQuery) -> Result<Vec<Escrow>, AppError> {
+        let pool = self.sqlite()?;
+        let mut builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new(format!("SELECT {COLUMNS} FROM escrows WHERE 1 = 1"));
+        if let Some(status) = query.status {
+            builder.push(" AND status = ").push_bind(status.as_str());
+        }
+        if let Some(asset_id) = &query.asset_id {
+            builder
+                .push(" AND asset_id = ")
+                .push_bind(asset_id.to_ascii_lowercase());
+        }
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_LIST_LIMIT)
+            .min(MAX_LIST_LIMIT);
+        builder
+            .push(" ORDER BY created_at DESC, id ASC LIMIT ")
+            .push_bind(limit as i64)
+            .push(" OFFSET ")
+            .push_bind(query.offset.unwrap_or(0) as i64);
+
+        let rows = builder
+            .build()
+            .fetch_all(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to list escrows: {e}")))?;
+        rows.iter().map(escrow_from_row).collect()
+    }
+
+    /// Moves `escrow` on from `from`. Returns false when the stored escrow is
+    /// no longer in `from`, so a release and a refund cannot both win.
+    pub async fn advance_escrow(
+        &self,
+        escrow: &Escrow,
+        from: EscrowStatus,
+    ) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            "UPDATE escrows SET status = ?, preimage = ?, outpoint = ?, refund_txid = ?, \
+             updated_at = ? WHERE id = ? AND status = ?",
+        )
+        .bind(escrow.status.as_str())
+        .bind(&escrow.preimage)
+        .bind(&escrow.outpoint)
+        .bind(&escrow.refund_txid)
+        .bind(escrow.updated_at)
+        .bind(&escrow.id)
+        .bind(from.as_str())
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to update escrow: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+fn escrow_from_row(row: &SqliteRow) -> Result<Escrow, AppError> {
+    let status: String = row.get("status");
+    Ok(Escrow {
+        id: row.get("id"),
+        asset_id: row.get("asset_id"),
+        amount: row.get::<i64, _>("amount") as u64,
+        payment_hash: row.get("payment_hash"),
+        preimage: row.get("preimage"),
+        beneficiary_key: row.get("beneficiary_key"),
+        refund_key: row.get("refund_key"),
+        refund_key_family: row.get::<i64, _>("refund_key_family") as u32,
+        refund_key_index: row.get::<i64, _>("refund_key_index") as u32,
+        script_key: row.get("script_key"),
+        lock_height: row.get::<i64, _>("lock_height") as u32,
+        address: row.get("address"),
+        status: EscrowStatus::parse(&status)?,
+        outpoint: row.get("outpoint"),
+        refund_txid: row.get("refund_txid"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn escrow(id: &str, status: EscrowStatus) -> Escrow {
+        Escrow {
+            id: id.to_string(),
+            asset_id: "aa".to_string(),
+            amount: 10,
+            payment_hash: "11".repeat(32),
+            preimage: Some("22".repeat(32)),
+            beneficiary_key: "33".repeat(32),
+            refund_key: format!("02{}", "44".repeat(32)),
+            refund_key_family: 212,
+            refund_key_index: 3,
+            script_key: "55".repeat(32),
+            lock_height: 900,
+            address: "taprt1...".to_string(),
+            status,
+            outpoint: None,
+            refund_txid: None,
+            created_at: 1,
+            updated_at: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_advance_escrow_from_expected_status() {
+        let db = open_test_database().await;
+        db.insert_escrow(&escrow("a", EscrowStatus::Pending))
+            .await
+            .unwrap();
+
+        let mut funded = db.get_escrow("a").await.unwrap().unwrap();
+        assert_eq!(funded.refund_key_index, 3);
+        funded.status = EscrowStatus::Funded;
+        funded.outpoint = Some("ab:1".to_string());
+        assert!(db
+            .advance_escrow(&funded, EscrowStatus::Pending)
+            .await
+            .unwrap());
+        assert!(!db
+            .advance_escrow(&funded, EscrowStatus::Pending)
+            .await
+            .unwrap());
+
+        let listed = db
+            .list_escrows(&EscrowQuery {
+                status: Some(EscrowStatus::Funded),
+                asset_id: Some("AA".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].outpoint.as_deref(), Some("ab:1"));
+    }
+
+    #[test]
+    fn test_preimage_hidden_until_released() {
+        assert!(escrow("a", EscrowStatus::Funded)
+            .revealed()
+            .preimage
+            .is_none());
+        assert!(escrow("a", EscrowStatus::Released)
+            .revealed()
+            .preimage
+            .is_some());
+    }
+}
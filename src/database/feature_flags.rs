@@ -0,0 +1,115 @@
+use super::Database;
+use crate::error::AppError;
+use sqlx::Row;
+
+/// `key_id` is empty for a deployment-wide override.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS feature_flags (
+        name TEXT NOT NULL,
+        key_id TEXT NOT NULL DEFAULT '',
+        enabled INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (name, key_id)
+    );
+"#;
+
+/// A feature flag override set through the admin API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureFlagRecord {
+    pub name: String,
+    /// API key the override applies to, or `None` for the deployment.
+    pub key_id: Option<String>,
+    pub enabled: bool,
+    pub updated_at: i64,
+}
+
+impl Database {
+    pub async fn save_feature_flag(&self, record: &FeatureFlagRecord) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO feature_flags (name, key_id, enabled, updated_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(name, key_id) DO UPDATE SET
+                enabled = excluded.enabled,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&record.name)
+        .bind(record.key_id.as_deref().unwrap_or_default())
+        .bind(record.enabled)
+        .bind(record.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to save feature flag: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn delete_feature_flag(
+        &self,
+        name: &str,
+        key_id: Option<&str>,
+    ) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query("DELETE FROM feature_flags WHERE name = ? AND key_id = ?")
+            .bind(name)
+            .bind(key_id.unwrap_or_default())
+            .execute(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to delete feature flag: {e}")))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn feature_flag_records(&self) -> Result<Vec<FeatureFlagRecord>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            "SELECT name, key_id, enabled, updated_at FROM feature_flags ORDER BY name, key_id",
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to load feature flags: {e}")))?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let key_id: String = row.get("key_id");
+                FeatureFlagRecord {
+                    name: row.get("name"),
+                    key_id: Some(key_id).filter(|k| !k.is_empty()),
+                    enabled: row.get("enabled"),
+                    updated_at: row.get("updated_at"),
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_feature_flag_overrides_round_trip() {
+        let db = open_test_database().await;
+        let record = |key_id: Option<&str>, enabled, updated_at| FeatureFlagRecord {
+            name: "swaps".to_string(),
+            key_id: key_id.map(str::to_string),
+            enabled,
+            updated_at,
+        };
+        db.save_feature_flag(&record(None, false, 10))
+            .await
+            .unwrap();
+        db.save_feature_flag(&record(Some("abcd"), true, 20))
+            .await
+            .unwrap();
+        db.save_feature_flag(&record(None, true, 30)).await.unwrap();
+        assert_eq!(
+            db.feature_flag_records().await.unwrap(),
+            vec![record(None, true, 30), record(Some("abcd"), true, 20)]
+        );
+        assert!(db.delete_feature_flag("swaps", Some("abcd")).await.unwrap());
+        assert!(!db.delete_feature_flag("swaps", Some("abcd")).await.unwrap());
+        assert_eq!(db.feature_flag_records().await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,98 @@
+use super::Database;
+use crate::error::AppError;
+use sqlx::Row;
+use std::collections::BTreeMap;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS gateway_counters (
+        name TEXT PRIMARY KEY,
+        value INTEGER NOT NULL,
+        first_recorded_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+"#;
+
+/// Since-install totals of the monitoring counters.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GatewayCounters {
+    pub values: BTreeMap<String, u64>,
+    /// When the first counter was recorded, i.e. roughly the install time.
+    pub since: Option<i64>,
+}
+
+impl Database {
+    /// Adds `deltas` to the stored totals in one transaction.
+    pub async fn add_gateway_counters(
+        &self,
+        deltas: &BTreeMap<String, u64>,
+        now: i64,
+    ) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        let db_error =
+            |e: sqlx::Error| AppError::DatabaseError(format!("Failed to record counters: {e}"));
+
+        let mut tx = pool.begin().await.map_err(db_error)?;
+        for (name, delta) in deltas {
+            sqlx::query(
+                r#"
+                INSERT INTO gateway_counters (name, value, first_recorded_at, updated_at)
+                VALUES (?, ?, ?, ?)
+                ON CONFLICT(name) DO UPDATE SET
+                    value = value + excluded.value,
+                    updated_at = excluded.updated_at
+                "#,
+            )
+            .bind(name)
+            .bind(*delta as i64)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        }
+        tx.commit().await.map_err(db_error)?;
+        Ok(())
+    }
+
+    pub async fn gateway_counters(&self) -> Result<GatewayCounters, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query("SELECT name, value, first_recorded_at FROM gateway_counters")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to load counters: {e}")))?;
+        let mut counters = GatewayCounters::default();
+        for row in &rows {
+            let first: i64 = row.get("first_recorded_at");
+            counters.since = Some(counters.since.map_or(first, |since| since.min(first)));
+            counters
+                .values
+                .insert(row.get("name"), row.get::<i64, _>("value").max(0) as u64);
+        }
+        Ok(counters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::open_test_database;
+    use std::collections::BTreeMap;
+
+    #[tokio::test]
+    async fn test_gateway_counters_accumulate() {
+        let db = open_test_database().await;
+        assert_eq!(db.gateway_counters().await.unwrap().since, None);
+
+        let deltas = BTreeMap::from([("http_requests".to_string(), 5)]);
+        db.add_gateway_counters(&deltas, 100).await.unwrap();
+        let deltas = BTreeMap::from([
+            ("http_requests".to_string(), 2),
+            ("auth_failures".to_string(), 1),
+        ]);
+        db.add_gateway_counters(&deltas, 200).await.unwrap();
+
+        let counters = db.gateway_counters().await.unwrap();
+        assert_eq!(counters.since, Some(100));
+        assert_eq!(counters.values["http_requests"], 7);
+        assert_eq!(counters.values["auth_failures"], 1);
+    }
+}
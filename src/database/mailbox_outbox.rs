@@ -0,0 +1,454 @@
+use super::Database;
+use crate::error::AppError;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+/// Mailbox messages queued through the gateway. `request` is the JSON body
+/// sent to tapd's `/mailbox/send`; `message_id` is the id tapd assigned it,
+/// which the receiver later removes it by.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS mailbox_outbox (
+        id TEXT PRIMARY KEY,
+        receiver_id TEXT NOT NULL,
+        request TEXT NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT,
+        next_attempt_at INTEGER NOT NULL,
+        message_id INTEGER,
+        callback_url TEXT,
+        callback_secret TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_mailbox_outbox_due ON mailbox_outbox(status, next_attempt_at);
+    CREATE INDEX IF NOT EXISTS idx_mailbox_outbox_receiver ON mailbox_outbox(receiver_id, message_id);
+"#;
+
+const COLUMNS: &str = "id, receiver_id, request, status, attempts, last_error, next_attempt_at, \
+     message_id, callback_url, callback_secret, created_at, updated_at";
+
+/// Lifecycle of an outgoing mailbox message. `Failed` means tapd refused it
+/// or the retry budget ran out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    Queued,
+    Sent,
+    Acknowledged,
+    Failed,
+}
+
+impl OutboxStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutboxStatus::Queued => "queued",
+            OutboxStatus::Sent => "sent",
+            OutboxStatus::Acknowledged => "acknowledged",
+            OutboxStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "queued" => Ok(OutboxStatus::Queued),
+            "sent" => Ok(OutboxStatus::Sent),
+            "acknowledged" => Ok(OutboxStatus::Acknowledged),
+            "failed" => Ok(OutboxStatus::Failed),
+            other => Err(AppError::ValidationError(format!(
+                "Unknown outbox status: {other}"
+            ))),
+        }
+    }
+}
+
+/// Receivers a retention pass applies to.
+#[derive(Debug, Clone, Copy)]
+pub enum RetentionScope<'a> {
+    /// Every receiver except these, which have policies of their own.
+    AllExcept(&'a [String]),
+    Receiver(&'a str),
+}
+
+impl RetentionScope<'_> {
+    /// SQL condition on `receiver_id` with its one bind value.
+    fn condition(&self) -> Result<(&'static str, String), AppError> {
+        match self {
+            RetentionScope::AllExcept(receivers) => Ok((
+                "receiver_id NOT IN (SELECT value FROM json_each(?))",
+                serde_json::to_string(receivers)
+                    .map_err(|e| AppError::SerializationError(e.to_string()))?,
+            )),
+            RetentionScope::Receiver(receiver_id) => {
+                Ok(("receiver_id = ?", receiver_id.to_string()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutboxMessage {
+    pub id: String,
+    pub receiver_id: String,
+    #[serde(skip_serializing)]
+    pub request: serde_json::Value,
+    pub status: OutboxStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: i64,
+    pub message_id: Option<u64>,
+    pub callback_url: Option<String>,
+    #[serde(skip_serializing)]
+    pub callback_secret: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl Database {
+    pub async fn insert_outbox_message(&self, message: &OutboxMessage) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        let request = serde_json::to_string(&message.request)
+            .map_err(|e| AppError::SerializationError(e.to_string()))?;
+        sqlx::query(&format!(
+            "INSERT INTO mailbox_outbox ({COLUMNS}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ))
+        .bind(&message.id)
+        .bind(&message.receiver_id)
+        .bind(request)
+        .bind(message.status.as_str())
+        .bind(message.attempts as i64)
+        .bind(&message.last_error)
+        .bind(message.next_attempt_at)
+        .bind(message.message_id.map(|id| id as i64))
+        .bind(&message.callback_url)
+        .bind(&message.callback_secret)
+        .bind(message.created_at)
+        .bind(message.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to queue mailbox message: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn outbox_message(&self, id: &str) -> Result<Option<OutboxMessage>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM mailbox_outbox WHERE id = ?"
+        ))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to load mailbox message: {e}")))?;
+        row.as_ref().map(outbox_from_row).transpose()
+    }
+
+    /// Outbox messages, newest first, optionally in one status.
+    pub async fn outbox_messages(
+        &self,
+        status: Option<OutboxStatus>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<OutboxMessage>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM mailbox_outbox WHERE (? IS NULL OR status = ?) \
+             ORDER BY created_at DESC, id ASC LIMIT ? OFFSET ?"
+        ))
+        .bind(status.map(|s| s.as_str()))
+        .bind(status.map(|s| s.as_str()))
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to list mailbox messages: {e}")))?;
+        rows.iter().map(outbox_from_row).collect()
+    }
+
+    /// Queued messages whose next attempt is due, oldest first.
+    pub async fn due_outbox_messages(&self, now: i64) -> Result<Vec<OutboxMessage>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM mailbox_outbox \
+             WHERE status = 'queued' AND next_attempt_at <= ? ORDER BY next_attempt_at ASC"
+        ))
+        .bind(now)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query mailbox outbox: {e}")))?;
+        rows.iter().map(outbox_from_row).collect()
+    }
+
+    /// Records the outcome of a delivery attempt and returns the updated
+    /// message. `next_attempt_at` only matters while the message is queued.
+    pub async fn record_outbox_attempt(
+        &self,
+        id: &str,
+        status: OutboxStatus,
+        message_id: Option<u64>,
+        error: Option<&str>,
+        next_attempt_at: i64,
+    ) -> Result<Option<OutboxMessage>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!(
+            "UPDATE mailbox_outbox SET status = ?, attempts = attempts + 1, last_error = ?, \
+             next_attempt_at = ?, message_id = COALESCE(?, message_id), updated_at = ? \
+             WHERE id = ? RETURNING {COLUMNS}"
+        ))
+        .bind(status.as_str())
+        .bind(error)
+        .bind(next_attempt_at)
+        .bind(message_id.map(|id| id as i64))
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to record mailbox attempt: {e}")))?;
+        row.as_ref().map(outbox_from_row).transpose()
+    }
+
+    /// Marks sent messages to `receiver_id` with the given tapd message ids
+    /// as acknowledged and returns the ones that changed.
+    pub async fn acknowledge_outbox_messages(
+        &self,
+        receiver_id: &str,
+        message_ids: &[u64],
+    ) -> Result<Vec<OutboxMessage>, AppError> {
+        let pool = self.sqlite()?;
+        let mut acknowledged = Vec::new();
+        for message_id in message_ids {
+            let rows = sqlx::query(&format!(
+                "UPDATE mailbox_outbox SET status = 'acknowledged', updated_at = ? \
+                 WHERE receiver_id = ? AND message_id = ? AND status = 'sent' \
+                 RETURNING {COLUMNS}"
+            ))
+            .bind(chrono::Utc::now().timestamp())
+            .bind(receiver_id)
+            .bind(*message_id as i64)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| {
+                AppError::DatabaseError(format!("Failed to acknowledge mailbox message: {e}"))
+            })?;
+            for row in &rows {
+                acknowledged.push(outbox_from_row(row)?);
+            }
+        }
+        Ok(acknowledged)
+    }
+
+    /// Marks one sent message as acknowledged; `None` unless it was `sent`.
+    pub async fn acknowledge_outbox_message(
+        &self,
+        id: &str,
+    ) -> Result<Option<OutboxMessage>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!(
+            "UPDATE mailbox_outbox SET status = 'acknowledged', updated_at = ? \
+             WHERE id = ? AND status = 'sent' RETURNING {COLUMNS}"
+        ))
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            AppError::DatabaseError(format!("Failed to acknowledge mailbox message: {e}"))
+        })?;
+        row.as_ref().map(outbox_from_row).transpose()
+    }
+
+    /// Deletes `scope`'s messages that are no longer queued and were
+    /// created before `created_before`, then all but each receiver's newest
+    /// `keep_per_receiver` of the rest. Returns how many were deleted.
+    pub async fn prune_outbox_messages(
+        &self,
+        scope: RetentionScope<'_>,
+        created_before: Option<i64>,
+        keep_per_receiver: Option<u32>,
+    ) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let (condition, receivers) = scope.condition()?;
+        let db_error = |e: sqlx::Error| {
+            AppError::DatabaseError(format!("Failed to prune mailbox outbox: {e}"))
+        };
+        let mut deleted = 0;
+        if let Some(created_before) = created_before {
+            deleted += sqlx::query(&format!(
+                "DELETE FROM mailbox_outbox                  WHERE status != 'queued' AND created_at < ? AND {condition}"
+            ))
+            .bind(created_before)
+            .bind(&receivers)
+            .execute(pool)
+            .await
+            .map_err(db_error)?
+            .rows_affected();
+        }
+        if let Some(keep) = keep_per_receiver {
+            deleted += sqlx::query(&format!(
+                "DELETE FROM mailbox_outbox WHERE id IN ( \
+                     SELECT id FROM ( \
+                         SELECT id, ROW_NUMBER() OVER ( \
+                             PARTITION BY receiver_id ORDER BY created_at DESC, id DESC \
+                         ) AS position \
+                         FROM mailbox_outbox WHERE status != 'queued' AND {condition} \
+                     ) WHERE position > ?)"
+            ))
+            .bind(&receivers)
+            .bind(keep as i64)
+            .execute(pool)
+            .await
+            .map_err(db_error)?
+            .rows_affected();
+        }
+        Ok(deleted)
+    }
+
+    /// Retention overrides stored under `retention` in receivers' metadata.
+    pub async fn receiver_retention_overrides(
+        &self,
+    ) -> Result<Vec<(String, serde_json::Value)>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            "SELECT receiver_id, json_extract(metadata, '$.retention') AS retention \
+             FROM receivers WHERE json_extract(metadata, '$.retention') IS NOT NULL",
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to load retention overrides: {e}")))?;
+        rows.iter()
+            .map(|row| {
+                let retention: String = row.get("retention");
+                let retention = serde_json::from_str(&retention).map_err(|e| {
+                    AppError::DatabaseError(format!("Corrupt retention override: {e}"))
+                })?;
+                Ok((row.get("receiver_id"), retention))
+            })
+            .collect()
+    }
+
+    /// Stores, or with `None` clears, a receiver's retention override.
+    /// Returns false when the receiver is unknown.
+    pub async fn set_receiver_retention(
+        &self,
+        receiver_id: &str,
+        retention: Option<&serde_json::Value>,
+    ) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let retention = retention
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| AppError::SerializationError(e.to_string()))?;
+        let result = sqlx::query(
+            r#"
+            UPDATE receivers SET metadata = CASE
+                WHEN ? IS NULL THEN json_remove(COALESCE(metadata, '{}'), '$.retention')
+                ELSE json_set(COALESCE(metadata, '{}'), '$.retention', json(?))
+            END
+            WHERE receiver_id = ?
+            "#,
+        )
+        .bind(&retention)
+        .bind(&retention)
+        .bind(receiver_id)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store retention override: {e}")))?;
+        // Cached receiver info would hide the change
+        if let Some(redis_conn) = &self.redis_conn {
+            let mut conn = redis_conn.clone();
+            let _: Result<(), _> = conn.del(format!("receiver:{receiver_id}")).await;
+        }
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn outbox_from_row(row: &SqliteRow) -> Result<OutboxMessage, AppError> {
+    let request: String = row.get("request");
+    let status: String = row.get("status");
+    Ok(OutboxMessage {
+        id: row.get("id"),
+        receiver_id: row.get("receiver_id"),
+        request: serde_json::from_str(&request)
+            .map_err(|e| AppError::DatabaseError(format!("Corrupt mailbox request: {e}")))?,
+        status: OutboxStatus::parse(&status)?,
+        attempts: row.get::<i64, _>("attempts") as u32,
+        last_error: row.get("last_error"),
+        next_attempt_at: row.get("next_attempt_at"),
+        message_id: row.get::<Option<i64>, _>("message_id").map(|id| id as u64),
+        callback_url: row.get("callback_url"),
+        callback_secret: row.get("callback_secret"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn queued(id: &str) -> OutboxMessage {
+        OutboxMessage {
+            id: id.to_string(),
+            receiver_id: "02aa".to_string(),
+            request: serde_json::json!({ "receiver_id": "02aa", "encrypted_payload": "x" }),
+            status: OutboxStatus::Queued,
+            attempts: 0,
+            last_error: None,
+            next_attempt_at: 100,
+            message_id: None,
+            callback_url: None,
+            callback_secret: None,
+            created_at: 100,
+            updated_at: 100,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outbox_moves_from_queued_to_acknowledged() {
+        let db = open_test_database().await;
+        db.insert_outbox_message(&queued("m1")).await.unwrap();
+        db.insert_outbox_message(&queued("m2")).await.unwrap();
+        assert_eq!(db.due_outbox_messages(99).await.unwrap().len(), 0);
+        assert_eq!(db.due_outbox_messages(100).await.unwrap().len(), 2);
+
+        let retried = db
+            .record_outbox_attempt("m1", OutboxStatus::Queued, None, Some("unavailable"), 200)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(retried.attempts, 1);
+        assert_eq!(db.due_outbox_messages(150).await.unwrap().len(), 1);
+
+        let sent = db
+            .record_outbox_attempt("m1", OutboxStatus::Sent, Some(7), None, 200)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (sent.status, sent.message_id),
+            (OutboxStatus::Sent, Some(7))
+        );
+        assert_eq!(sent.request["encrypted_payload"], "x");
+
+        assert!(db
+            .acknowledge_outbox_messages("02bb", &[7])
+            .await
+            .unwrap()
+            .is_empty());
+        let acked = db.acknowledge_outbox_messages("02aa", &[7]).await.unwrap();
+        assert_eq!(acked.len(), 1);
+        assert_eq!(acked[0].status, OutboxStatus::Acknowledged);
+        assert!(db.acknowledge_outbox_message("m2").await.unwrap().is_none());
+
+        let listed = db
+            .outbox_messages(Some(OutboxStatus::Queued), 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, "m2");
+        assert_eq!(db.outbox_messages(None, 10, 0).await.unwrap().len(), 2);
+    }
+}
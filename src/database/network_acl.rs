@@ -0,0 +1,107 @@
+use super::Database;
+use crate::error::AppError;
+use sqlx::Row;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS network_acl_rules (
+        id TEXT PRIMARY KEY,
+        action TEXT NOT NULL,
+        path_prefix TEXT NOT NULL,
+        networks TEXT NOT NULL,
+        note TEXT,
+        created_at INTEGER NOT NULL
+    );
+"#;
+
+/// A network access rule added through the admin API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkAclRecord {
+    pub id: String,
+    /// `allow` or `deny`.
+    pub action: String,
+    pub path_prefix: String,
+    /// Comma-separated CIDRs.
+    pub networks: String,
+    pub note: Option<String>,
+    pub created_at: i64,
+}
+
+impl Database {
+    pub async fn insert_network_acl_rule(&self, record: &NetworkAclRecord) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO network_acl_rules (id, action, path_prefix, networks, note, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&record.id)
+        .bind(&record.action)
+        .bind(&record.path_prefix)
+        .bind(&record.networks)
+        .bind(&record.note)
+        .bind(record.created_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to save network ACL rule: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn network_acl_rules(&self) -> Result<Vec<NetworkAclRecord>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            "SELECT id, action, path_prefix, networks, note, created_at \
+             FROM network_acl_rules ORDER BY created_at, id",
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to load network ACL rules: {e}")))?;
+        Ok(rows
+            .iter()
+            .map(|row| NetworkAclRecord {
+                id: row.get("id"),
+                action: row.get("action"),
+                path_prefix: row.get("path_prefix"),
+                networks: row.get("networks"),
+                note: row.get("note"),
+                created_at: row.get("created_at"),
+            })
+            .collect())
+    }
+
+    /// Deletes rule `id`; false if there is none.
+    pub async fn delete_network_acl_rule(&self, id: &str) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query("DELETE FROM network_acl_rules WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| {
+                AppError::DatabaseError(format!("Failed to delete network ACL rule: {e}"))
+            })?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_network_acl_rules_round_trip() {
+        let db = open_test_database().await;
+        let record = NetworkAclRecord {
+            id: "r1".to_string(),
+            action: "allow".to_string(),
+            path_prefix: "/v1/gateway/admin".to_string(),
+            networks: "10.0.0.0/8,fd00::/8".to_string(),
+            note: Some("management network".to_string()),
+            created_at: 100,
+        };
+        db.insert_network_acl_rule(&record).await.unwrap();
+        assert_eq!(db.network_acl_rules().await.unwrap(), vec![record]);
+        assert!(db.delete_network_acl_rule("r1").await.unwrap());
+        assert!(!db.delete_network_acl_rule("r1").await.unwrap());
+    }
+}
@@ -0,0 +1,320 @@
+use super::Database;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS payment_requests (
+        id TEXT PRIMARY KEY,
+        asset_id TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        address TEXT NOT NULL,
+        status TEXT NOT NULL,
+        received INTEGER NOT NULL DEFAULT 0,
+        unconfirmed INTEGER NOT NULL DEFAULT 0,
+        expires_at INTEGER NOT NULL,
+        callback_url TEXT,
+        callback_secret TEXT,
+        metadata TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_payment_requests_status ON payment_requests(status);
+    CREATE INDEX IF NOT EXISTS idx_payment_requests_address ON payment_requests(address);
+"#;
+
+const DEFAULT_LIST_LIMIT: u32 = 100;
+const MAX_LIST_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    /// Nothing settled yet and not expired.
+    Pending,
+    /// Some but not all of the amount has settled.
+    Partial,
+    Paid,
+    Overpaid,
+    /// The TTL passed before the full amount settled.
+    Expired,
+}
+
+impl PaymentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentStatus::Pending => "pending",
+            PaymentStatus::Partial => "partial",
+            PaymentStatus::Paid => "paid",
+            PaymentStatus::Overpaid => "overpaid",
+            PaymentStatus::Expired => "expired",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "pending" => Ok(PaymentStatus::Pending),
+            "partial" => Ok(PaymentStatus::Partial),
+            "paid" => Ok(PaymentStatus::Paid),
+            "overpaid" => Ok(PaymentStatus::Overpaid),
+            "expired" => Ok(PaymentStatus::Expired),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown payment request status: {other}"
+            ))),
+        }
+    }
+}
+
+/// A request for `amount` units of `asset_id`, paid to a fresh address.
+/// `received` counts settled receives only; `unconfirmed` is seen on chain but
+/// below the confirmation policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub id: String,
+    pub asset_id: String,
+    pub amount: u64,
+    pub address: String,
+    pub status: PaymentStatus,
+    pub received: u64,
+    pub unconfirmed: u64,
+    pub expires_at: i64,
+    pub callback_url: Option<String>,
+    #[serde(skip_serializing, default)]
+    pub callback_secret: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct PaymentRequestQuery {
+    pub status: Option<PaymentStatus>,
+    pub asset_id: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+const SELECT_COLUMNS: &str = "SELECT id, asset_id, amount, address, status, received, \
+     unconfirmed, expires_at, callback_url, callback_secret, metadata, created_at, updated_at \
+     FROM payment_requests";
+
+impl Database {
+    pub async fn insert_payment_request(&self, request: &PaymentRequest) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        let metadata = request
+            .metadata
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| AppError::SerializationError(e.to_string()))?;
+        sqlx::query(
+            r#"
+            INSERT INTO payment_requests (
+                id, asset_id, amount, address, status, received, unconfirmed, expires_at,
+                callback_url, callback_secret, metadata, created_at, updated_at
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&request.id)
+        .bind(&request.asset_id)
+        .bind(request.amount as i64)
+        .bind(&request.address)
+        .bind(request.status.as_str())
+        .bind(request.received as i64)
+        .bind(request.unconfirmed as i64)
+        .bind(request.expires_at)
+        .bind(&request.callback_url)
+        .bind(&request.callback_secret)
+        .bind(metadata)
+        .bind(request.created_at)
+        .bind(request.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store payment request: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn get_payment_request(&self, id: &str) -> Result<Option<PaymentRequest>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!("{SELECT_COLUMNS} WHERE id = ?"))
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                AppError::DatabaseError(format!("Failed to fetch payment request: {e}"))
+            })?;
+        row.as_ref().map(payment_request_from_row).transpose()
+    }
+
+    /// Lists payment requests, newest first.
+    pub async fn list_payment_requests(
+        &self,
+        query: &PaymentRequestQuery,
+    ) -> Result<Vec<PaymentRequest>, AppError> {
+        let pool = self.sqlite()?;
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(SELECT_COLUMNS);
+        builder.push(" WHERE 1 = 1");
+        if let Some(status) = query.status {
+            builder.push(" AND status = ").push_bind(status.as_str());
+        }
+        if let Some(asset_id) = &query.asset_id {
+            builder.push(" AND asset_id = ").push_bind(asset_id.clone());
+        }
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_LIST_LIMIT)
+            .min(MAX_LIST_LIMIT);
+        builder
+            .push(" ORDER BY created_at DESC, id ASC LIMIT ")
+            .push_bind(limit as i64)
+            .push(" OFFSET ")
+            .push_bind(query.offset.unwrap_or(0) as i64);
+
+        let rows = builder.build().fetch_all(pool).await.map_err(|e| {
+            AppError::DatabaseError(format!("Failed to list payment requests: {e}"))
+        })?;
+        rows.iter().map(payment_request_from_row).collect()
+    }
+
+    /// Requests that can still change: unpaid ones, and paid ones before
+    /// their expiry (a further payment can still turn them overpaid).
+    pub async fn open_payment_requests(&self, now: i64) -> Result<Vec<PaymentRequest>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "{SELECT_COLUMNS} WHERE status IN ('pending', 'partial') OR expires_at > ?"
+        ))
+        .bind(now)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to list payment requests: {e}")))?;
+        rows.iter().map(payment_request_from_row).collect()
+    }
+
+    pub async fn update_payment_request_progress(
+        &self,
+        id: &str,
+        status: PaymentStatus,
+        received: u64,
+        unconfirmed: u64,
+    ) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            "UPDATE payment_requests SET status = ?, received = ?, unconfirmed = ?, \
+             updated_at = ? WHERE id = ?",
+        )
+        .bind(status.as_str())
+        .bind(received as i64)
+        .bind(unconfirmed as i64)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to update payment request: {e}")))?;
+        Ok(())
+    }
+}
+
+fn payment_request_from_row(row: &SqliteRow) -> Result<PaymentRequest, AppError> {
+    let status: String = row.get("status");
+    let metadata: Option<String> = row.get("metadata");
+    Ok(PaymentRequest {
+        id: row.get("id"),
+        asset_id: row.get("asset_id"),
+        amount: row.get::<i64, _>("amount") as u64,
+        address: row.get("address"),
+        status: PaymentStatus::parse(&status)?,
+        received: row.get::<i64, _>("received") as u64,
+        unconfirmed: row.get::<i64, _>("unconfirmed") as u64,
+        expires_at: row.get("expires_at"),
+        callback_url: row.get("callback_url"),
+        callback_secret: row.get("callback_secret"),
+        metadata: metadata
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()
+            .map_err(|e| AppError::SerializationError(e.to_string()))?,
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn request(id: &str, status: PaymentStatus, expires_at: i64) -> PaymentRequest {
+        PaymentRequest {
+            id: id.to_string(),
+            asset_id: "aa".to_string(),
+            amount: 100,
+            address: format!("taprt1{id}"),
+            status,
+            received: 0,
+            unconfirmed: 0,
+            expires_at,
+            callback_url: None,
+            callback_secret: Some("k".to_string()),
+            metadata: Some(serde_json::json!({ "order": 7 })),
+            created_at: 1,
+            updated_at: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_and_progress() {
+        let db = open_test_database().await;
+        db.insert_payment_request(&request("a", PaymentStatus::Pending, 500))
+            .await
+            .unwrap();
+        db.update_payment_request_progress("a", PaymentStatus::Partial, 40, 10)
+            .await
+            .unwrap();
+
+        let stored = db.get_payment_request("a").await.unwrap().unwrap();
+        assert_eq!(stored.status, PaymentStatus::Partial);
+        assert_eq!(stored.received, 40);
+        assert_eq!(stored.unconfirmed, 10);
+        assert_eq!(stored.metadata.unwrap()["order"], 7);
+        assert_eq!(stored.callback_secret.as_deref(), Some("k"));
+        assert!(db.get_payment_request("missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_open_requests_exclude_settled_after_expiry() {
+        let db = open_test_database().await;
+        db.insert_payment_request(&request("pending", PaymentStatus::Pending, 100))
+            .await
+            .unwrap();
+        db.insert_payment_request(&request("paid", PaymentStatus::Paid, 100))
+            .await
+            .unwrap();
+        db.insert_payment_request(&request("expired", PaymentStatus::Expired, 100))
+            .await
+            .unwrap();
+
+        let open: Vec<String> = db
+            .open_payment_requests(50)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(open.len(), 3);
+
+        let open = db.open_payment_requests(150).await.unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].id, "pending");
+
+        let paid = db
+            .list_payment_requests(&PaymentRequestQuery {
+                status: Some(PaymentStatus::Paid),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(paid.len(), 1);
+    }
+}
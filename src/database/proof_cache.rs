@@ -0,0 +1,142 @@
+use super::Database;
+use crate::error::AppError;
+use sqlx::Row;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS proof_cache (
+        proof_hash TEXT NOT NULL,
+        operation TEXT NOT NULL,
+        response TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        hits INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (proof_hash, operation)
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_proof_cache_created_at ON proof_cache(created_at);
+"#;
+
+impl Database {
+    /// The cached tapd response for `operation` on the proof, unless it was
+    /// stored before `not_before` (unix seconds).
+    pub async fn cached_proof_response(
+        &self,
+        proof_hash: &str,
+        operation: &str,
+        not_before: i64,
+    ) -> Result<Option<serde_json::Value>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(
+            r#"
+            UPDATE proof_cache SET hits = hits + 1
+            WHERE proof_hash = ? AND operation = ? AND created_at >= ?
+            RETURNING response
+            "#,
+        )
+        .bind(proof_hash)
+        .bind(operation)
+        .bind(not_before)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to read proof cache: {e}")))?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let response: String = row.get("response");
+        serde_json::from_str(&response)
+            .map(Some)
+            .map_err(|e| AppError::DatabaseError(format!("Corrupt proof cache entry: {e}")))
+    }
+
+    /// Stores a response and drops entries stored before `not_before`.
+    pub async fn store_proof_response(
+        &self,
+        proof_hash: &str,
+        operation: &str,
+        response: &serde_json::Value,
+        not_before: i64,
+    ) -> Result<(), AppError> {
+        self.prune_proof_cache(not_before).await?;
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO proof_cache (proof_hash, operation, response, created_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(proof_hash, operation) DO UPDATE SET
+                response = excluded.response,
+                created_at = excluded.created_at,
+                hits = 0
+            "#,
+        )
+        .bind(proof_hash)
+        .bind(operation)
+        .bind(response.to_string())
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store proof cache entry: {e}")))?;
+        Ok(())
+    }
+
+    /// Drops entries stored before `not_before` and returns how many.
+    pub async fn prune_proof_cache(&self, not_before: i64) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query("DELETE FROM proof_cache WHERE created_at < ?")
+            .bind(not_before)
+            .execute(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to prune proof cache: {e}")))?;
+        Ok(result.rows_affected())
+    }
+
+    /// Number of cached responses.
+    pub async fn proof_cache_entries(&self) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM proof_cache")
+            .fetch_one(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to count proof cache: {e}")))?;
+        Ok(count as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_proof_cache_round_trip_and_expiry() {
+        let db = open_test_database().await;
+        let response = serde_json::json!({"valid": true});
+        assert!(db
+            .cached_proof_response("h", "verify:g", 0)
+            .await
+            .unwrap()
+            .is_none());
+
+        db.store_proof_response("h", "verify:g", &response, 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.cached_proof_response("h", "verify:g", 0).await.unwrap(),
+            Some(response.clone())
+        );
+        assert!(db
+            .cached_proof_response("h", "decode:0:false:false", 0)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(db.proof_cache_entries().await.unwrap(), 1);
+
+        // Entries older than the cutoff are ignored and pruned on write
+        let later = chrono::Utc::now().timestamp() + 10;
+        assert!(db
+            .cached_proof_response("h", "verify:g", later)
+            .await
+            .unwrap()
+            .is_none());
+        db.store_proof_response("other", "verify:g", &response, later)
+            .await
+            .unwrap();
+        assert_eq!(db.proof_cache_entries().await.unwrap(), 1);
+    }
+}
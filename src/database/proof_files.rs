@@ -0,0 +1,201 @@
+use super::Database;
+use crate::error::AppError;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS proof_files (
+        file_hash TEXT PRIMARY KEY,
+        asset_id TEXT NOT NULL,
+        script_key TEXT NOT NULL,
+        outpoint TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        data BLOB NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_proof_files_export ON proof_files(asset_id, script_key, outpoint);
+    CREATE INDEX IF NOT EXISTS idx_proof_files_created_at ON proof_files(created_at);
+"#;
+
+/// An exported proof file kept for resumable download. The bytes are read
+/// in ranges with [`Database::proof_file_range`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProofFile {
+    /// Hex SHA-256 of the file, also its ETag.
+    pub file_hash: String,
+    pub asset_id: String,
+    pub script_key: String,
+    pub outpoint: String,
+    pub size: u64,
+    pub created_at: i64,
+}
+
+impl Database {
+    /// Stores an exported file, first dropping files created before
+    /// `not_before`. Storing the same bytes again refreshes their age.
+    pub async fn store_proof_file(
+        &self,
+        file: &ProofFile,
+        data: &[u8],
+        not_before: i64,
+    ) -> Result<(), AppError> {
+        self.prune_proof_files(not_before).await?;
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO proof_files (
+                file_hash, asset_id, script_key, outpoint, size, data, created_at
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(file_hash) DO UPDATE SET created_at = excluded.created_at
+            "#,
+        )
+        .bind(&file.file_hash)
+        .bind(&file.asset_id)
+        .bind(&file.script_key)
+        .bind(&file.outpoint)
+        .bind(file.size as i64)
+        .bind(data)
+        .bind(file.created_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store proof file: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn proof_file(
+        &self,
+        file_hash: &str,
+        not_before: i64,
+    ) -> Result<Option<ProofFile>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(
+            r#"
+            SELECT file_hash, asset_id, script_key, outpoint, size, created_at
+            FROM proof_files WHERE file_hash = ? AND created_at >= ?
+            "#,
+        )
+        .bind(file_hash)
+        .bind(not_before)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query proof file: {e}")))?;
+        Ok(row.as_ref().map(file_from_row))
+    }
+
+    /// The newest unexpired file exported for this proof locator.
+    pub async fn exported_proof_file(
+        &self,
+        asset_id: &str,
+        script_key: &str,
+        outpoint: &str,
+        not_before: i64,
+    ) -> Result<Option<ProofFile>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(
+            r#"
+            SELECT file_hash, asset_id, script_key, outpoint, size, created_at
+            FROM proof_files
+            WHERE asset_id = ? AND script_key = ? AND outpoint = ? AND created_at >= ?
+            ORDER BY created_at DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(asset_id)
+        .bind(script_key)
+        .bind(outpoint)
+        .bind(not_before)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query proof file: {e}")))?;
+        Ok(row.as_ref().map(file_from_row))
+    }
+
+    /// `len` bytes of a stored file from byte offset `start`. Only the
+    /// requested slice is read from the database.
+    pub async fn proof_file_range(
+        &self,
+        file_hash: &str,
+        start: u64,
+        len: u64,
+    ) -> Result<Vec<u8>, AppError> {
+        let pool = self.sqlite()?;
+        // substr() on a blob counts bytes from 1
+        let data: Option<Vec<u8>> =
+            sqlx::query_scalar("SELECT substr(data, ?, ?) FROM proof_files WHERE file_hash = ?")
+                .bind(start as i64 + 1)
+                .bind(len as i64)
+                .bind(file_hash)
+                .fetch_optional(pool)
+                .await
+                .map_err(|e| AppError::DatabaseError(format!("Failed to read proof file: {e}")))?;
+        data.ok_or_else(|| AppError::NotFound(format!("Proof file {file_hash} not found")))
+    }
+
+    pub async fn prune_proof_files(&self, not_before: i64) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query("DELETE FROM proof_files WHERE created_at < ?")
+            .bind(not_before)
+            .execute(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to prune proof files: {e}")))?;
+        Ok(result.rows_affected())
+    }
+}
+
+fn file_from_row(row: &SqliteRow) -> ProofFile {
+    ProofFile {
+        file_hash: row.get("file_hash"),
+        asset_id: row.get("asset_id"),
+        script_key: row.get("script_key"),
+        outpoint: row.get("outpoint"),
+        size: row.get::<i64, _>("size") as u64,
+        created_at: row.get("created_at"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_stored_file_is_read_in_ranges_until_expired() {
+        let db = open_test_database().await;
+        let data: Vec<u8> = (0..=255).collect();
+        let file = ProofFile {
+            file_hash: "h".repeat(64),
+            asset_id: "a".repeat(64),
+            script_key: "02".to_string() + &"b".repeat(64),
+            outpoint: format!("{}:0", "c".repeat(64)),
+            size: data.len() as u64,
+            created_at: 100,
+        };
+        db.store_proof_file(&file, &data, 0).await.unwrap();
+
+        assert_eq!(
+            db.proof_file(&file.file_hash, 100).await.unwrap(),
+            Some(file.clone())
+        );
+        let found = db
+            .exported_proof_file(&file.asset_id, &file.script_key, &file.outpoint, 0)
+            .await
+            .unwrap();
+        assert_eq!(found, Some(file.clone()));
+        assert_eq!(
+            db.proof_file_range(&file.file_hash, 10, 4).await.unwrap(),
+            vec![10, 11, 12, 13]
+        );
+        assert_eq!(
+            db.proof_file_range(&file.file_hash, 250, 100)
+                .await
+                .unwrap(),
+            vec![250, 251, 252, 253, 254, 255]
+        );
+
+        assert_eq!(db.proof_file(&file.file_hash, 101).await.unwrap(), None);
+        assert_eq!(db.prune_proof_files(101).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,290 @@
+use super::Database;
+use crate::error::AppError;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS psbt_reservations (
+        outpoint TEXT PRIMARY KEY,
+        flow_id TEXT NOT NULL,
+        stage TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_psbt_reservations_flow ON psbt_reservations(flow_id);
+"#;
+
+const COLUMNS: &str = "outpoint, flow_id, stage, created_at, expires_at";
+
+/// How far the PSBT flow holding a reservation got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowStage {
+    Funded,
+    Signed,
+    Committed,
+    /// The inputs are spent; the reservation outlives the flow so replays
+    /// of its PSBTs are refused by the gateway rather than by tapd.
+    Anchored,
+}
+
+impl FlowStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlowStage::Funded => "funded",
+            FlowStage::Signed => "signed",
+            FlowStage::Committed => "committed",
+            FlowStage::Anchored => "anchored",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "funded" => Some(FlowStage::Funded),
+            "signed" => Some(FlowStage::Signed),
+            "committed" => Some(FlowStage::Committed),
+            "anchored" => Some(FlowStage::Anchored),
+            _ => None,
+        }
+    }
+}
+
+/// An anchor outpoint held by an in-progress PSBT flow.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PsbtReservation {
+    /// `txid:vout` of the anchor output.
+    pub outpoint: String,
+    pub flow_id: String,
+    pub stage: FlowStage,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+fn reservation_from_row(row: &SqliteRow) -> PsbtReservation {
+    PsbtReservation {
+        outpoint: row.get("outpoint"),
+        flow_id: row.get("flow_id"),
+        stage: FlowStage::parse(&row.get::<String, _>("stage")).unwrap_or(FlowStage::Funded),
+        created_at: row.get("created_at"),
+        expires_at: row.get("expires_at"),
+    }
+}
+
+impl Database {
+    /// Reserves `outpoints` for `flow_id` in one transaction, dropping
+    /// expired reservations first. When another flow still holds any of
+    /// them nothing is reserved and its reservations are returned.
+    pub async fn reserve_psbt_outpoints(
+        &self,
+        flow_id: &str,
+        outpoints: &[String],
+        stage: FlowStage,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<Vec<PsbtReservation>, AppError> {
+        let pool = self.sqlite()?;
+        let db_error =
+            |e: sqlx::Error| AppError::DatabaseError(format!("Failed to reserve outpoints: {e}"));
+
+        let mut tx = pool.begin().await.map_err(db_error)?;
+        sqlx::query("DELETE FROM psbt_reservations WHERE expires_at <= ?")
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        let mut conflicts = Vec::new();
+        for outpoint in outpoints {
+            let row = sqlx::query(&format!(
+                "SELECT {COLUMNS} FROM psbt_reservations WHERE outpoint = ? AND flow_id != ?"
+            ))
+            .bind(outpoint)
+            .bind(flow_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?;
+            conflicts.extend(row.as_ref().map(reservation_from_row));
+        }
+        if !conflicts.is_empty() {
+            return Ok(conflicts);
+        }
+        for outpoint in outpoints {
+            sqlx::query(
+                r#"
+                INSERT INTO psbt_reservations (outpoint, flow_id, stage, created_at, expires_at)
+                VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT(outpoint) DO UPDATE SET
+                    stage = excluded.stage,
+                    expires_at = excluded.expires_at
+                "#,
+            )
+            .bind(outpoint)
+            .bind(flow_id)
+            .bind(stage.as_str())
+            .bind(now)
+            .bind(expires_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        }
+        tx.commit().await.map_err(db_error)?;
+        Ok(Vec::new())
+    }
+
+    /// Unexpired reservations of `outpoints`, in outpoint order.
+    pub async fn psbt_reservations_for(
+        &self,
+        outpoints: &[String],
+        now: i64,
+    ) -> Result<Vec<PsbtReservation>, AppError> {
+        let pool = self.sqlite()?;
+        let mut reservations = Vec::new();
+        for outpoint in outpoints {
+            let row = sqlx::query(&format!(
+                "SELECT {COLUMNS} FROM psbt_reservations WHERE outpoint = ? AND expires_at > ?"
+            ))
+            .bind(outpoint)
+            .bind(now)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to load reservation: {e}")))?;
+            reservations.extend(row.as_ref().map(reservation_from_row));
+        }
+        reservations.sort_by(|a, b| a.outpoint.cmp(&b.outpoint));
+        Ok(reservations)
+    }
+
+    /// Unexpired reservations, oldest first.
+    pub async fn list_psbt_reservations(
+        &self,
+        now: i64,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<PsbtReservation>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM psbt_reservations WHERE expires_at > ? \
+             ORDER BY created_at, outpoint LIMIT ? OFFSET ?"
+        ))
+        .bind(now)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to list reservations: {e}")))?;
+        Ok(rows.iter().map(reservation_from_row).collect())
+    }
+
+    /// Moves every reservation of `flow_id` to `stage`; returns how many
+    /// there were.
+    pub async fn advance_psbt_flow(
+        &self,
+        flow_id: &str,
+        stage: FlowStage,
+        expires_at: i64,
+    ) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let result =
+            sqlx::query("UPDATE psbt_reservations SET stage = ?, expires_at = ? WHERE flow_id = ?")
+                .bind(stage.as_str())
+                .bind(expires_at)
+                .bind(flow_id)
+                .execute(pool)
+                .await
+                .map_err(|e| {
+                    AppError::DatabaseError(format!("Failed to advance PSBT flow: {e}"))
+                })?;
+        Ok(result.rows_affected())
+    }
+
+    /// Drops every reservation of `flow_id`; returns how many there were.
+    pub async fn release_psbt_flow(&self, flow_id: &str) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query("DELETE FROM psbt_reservations WHERE flow_id = ?")
+            .bind(flow_id)
+            .execute(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to release PSBT flow: {e}")))?;
+        Ok(result.rows_affected())
+    }
+
+    /// Drops the reservation of `outpoint`; false if there is none.
+    pub async fn release_psbt_outpoint(&self, outpoint: &str) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query("DELETE FROM psbt_reservations WHERE outpoint = ?")
+            .bind(outpoint)
+            .execute(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to release outpoint: {e}")))?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn outpoints(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_reservations_conflict_until_released_or_expired() {
+        let db = open_test_database().await;
+        let a = outpoints(&["aa:0", "bb:1"]);
+        assert!(db
+            .reserve_psbt_outpoints("flow-a", &a, FlowStage::Funded, 100, 700)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // A second flow touching one of them gets nothing
+        let b = outpoints(&["bb:1", "cc:2"]);
+        let conflicts = db
+            .reserve_psbt_outpoints("flow-b", &b, FlowStage::Funded, 200, 800)
+            .await
+            .unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].flow_id, "flow-a");
+        assert!(db
+            .psbt_reservations_for(&outpoints(&["cc:2"]), 200)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // The holder itself may re-reserve
+        assert!(db
+            .reserve_psbt_outpoints("flow-a", &a, FlowStage::Signed, 300, 900)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            db.advance_psbt_flow("flow-a", FlowStage::Anchored, 1000)
+                .await
+                .unwrap(),
+            2
+        );
+        let held = db.psbt_reservations_for(&b, 300).await.unwrap();
+        assert_eq!(held[0].stage, FlowStage::Anchored);
+
+        // Expired reservations no longer conflict
+        assert!(db
+            .reserve_psbt_outpoints("flow-b", &b, FlowStage::Funded, 1000, 1600)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            db.list_psbt_reservations(1000, 10, 0).await.unwrap().len(),
+            2
+        );
+
+        assert!(db.release_psbt_outpoint("bb:1").await.unwrap());
+        assert_eq!(db.release_psbt_flow("flow-b").await.unwrap(), 1);
+        assert!(db
+            .list_psbt_reservations(1000, 10, 0)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
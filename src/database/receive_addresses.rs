@@ -0,0 +1,208 @@
+use super::Database;
+use crate::error::AppError;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS receive_addresses (
+        address TEXT PRIMARY KEY,
+        asset_id TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        expires_at INTEGER,
+        expired_at INTEGER,
+        rotated_to TEXT,
+        created_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_receive_addresses_asset ON receive_addresses(asset_id, amount);
+    CREATE INDEX IF NOT EXISTS idx_receive_addresses_expires_at ON receive_addresses(expires_at);
+"#;
+
+const SELECT_COLUMNS: &str = "SELECT address, asset_id, amount, expires_at, expired_at, \
+                              rotated_to, created_at FROM receive_addresses";
+
+/// An address the gateway generated with a TTL. `expired_at` is set by the
+/// expiry job; `rotated_to` once a receive request replaced it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReceiveAddress {
+    pub address: String,
+    pub asset_id: String,
+    pub amount: u64,
+    pub expires_at: Option<i64>,
+    pub expired_at: Option<i64>,
+    pub rotated_to: Option<String>,
+    pub created_at: i64,
+}
+
+impl ReceiveAddress {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expired_at.is_some() || self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+impl Database {
+    pub async fn insert_receive_address(&self, address: &ReceiveAddress) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO receive_addresses (
+                address, asset_id, amount, expires_at, expired_at, rotated_to, created_at
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&address.address)
+        .bind(&address.asset_id)
+        .bind(address.amount as i64)
+        .bind(address.expires_at)
+        .bind(address.expired_at)
+        .bind(&address.rotated_to)
+        .bind(address.created_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store address: {e}")))?;
+        Ok(())
+    }
+
+    /// The stored entries among `addresses`; addresses generated without a
+    /// TTL are not stored and so not returned.
+    pub async fn receive_addresses(
+        &self,
+        addresses: &[String],
+    ) -> Result<Vec<ReceiveAddress>, AppError> {
+        if addresses.is_empty() {
+            return Ok(Vec::new());
+        }
+        let pool = self.sqlite()?;
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(SELECT_COLUMNS);
+        builder.push(" WHERE address IN (");
+        let mut separated = builder.separated(", ");
+        for address in addresses {
+            separated.push_bind(address.clone());
+        }
+        builder.push(")");
+        let rows = builder
+            .build()
+            .fetch_all(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to query addresses: {e}")))?;
+        Ok(rows.iter().map(address_from_row).collect())
+    }
+
+    /// The newest address for `asset_id` and `amount` that has not been
+    /// rotated away from, expired or not.
+    pub async fn latest_receive_address(
+        &self,
+        asset_id: &str,
+        amount: u64,
+    ) -> Result<Option<ReceiveAddress>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!(
+            "{SELECT_COLUMNS} WHERE asset_id = ? AND amount = ? AND rotated_to IS NULL \
+             ORDER BY created_at DESC LIMIT 1"
+        ))
+        .bind(asset_id)
+        .bind(amount as i64)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query addresses: {e}")))?;
+        Ok(row.as_ref().map(address_from_row))
+    }
+
+    pub async fn rotate_receive_address(&self, from: &str, to: &str) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query("UPDATE receive_addresses SET rotated_to = ? WHERE address = ?")
+            .bind(to)
+            .bind(from)
+            .execute(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to rotate address: {e}")))?;
+        Ok(())
+    }
+
+    /// Marks addresses whose TTL passed by `now` as expired and returns
+    /// them. Each address is returned by one call only.
+    pub async fn expire_receive_addresses(
+        &self,
+        now: i64,
+    ) -> Result<Vec<ReceiveAddress>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            r#"
+            UPDATE receive_addresses SET expired_at = ?
+            WHERE expired_at IS NULL AND expires_at <= ?
+            RETURNING address, asset_id, amount, expires_at, expired_at, rotated_to, created_at
+            "#,
+        )
+        .bind(now)
+        .bind(now)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to expire addresses: {e}")))?;
+        Ok(rows.iter().map(address_from_row).collect())
+    }
+}
+
+fn address_from_row(row: &SqliteRow) -> ReceiveAddress {
+    ReceiveAddress {
+        address: row.get("address"),
+        asset_id: row.get("asset_id"),
+        amount: row.get::<i64, _>("amount") as u64,
+        expires_at: row.get("expires_at"),
+        expired_at: row.get("expired_at"),
+        rotated_to: row.get("rotated_to"),
+        created_at: row.get("created_at"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn address(address: &str, expires_at: Option<i64>, created_at: i64) -> ReceiveAddress {
+        ReceiveAddress {
+            address: address.to_string(),
+            asset_id: "aa".to_string(),
+            amount: 100,
+            expires_at,
+            expired_at: None,
+            rotated_to: None,
+            created_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_addresses_expire_once_and_rotate() {
+        let db = open_test_database().await;
+        db.insert_receive_address(&address("taprt1old", Some(50), 1))
+            .await
+            .unwrap();
+        db.insert_receive_address(&address("taprt1forever", None, 2))
+            .await
+            .unwrap();
+
+        let latest = db.latest_receive_address("aa", 100).await.unwrap().unwrap();
+        assert_eq!(latest.address, "taprt1forever");
+
+        let expired = db.expire_receive_addresses(60).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].address, "taprt1old");
+        assert_eq!(expired[0].expired_at, Some(60));
+        assert!(db.expire_receive_addresses(70).await.unwrap().is_empty());
+
+        db.rotate_receive_address("taprt1forever", "taprt1new")
+            .await
+            .unwrap();
+        let latest = db.latest_receive_address("aa", 100).await.unwrap().unwrap();
+        assert_eq!(latest.address, "taprt1old");
+
+        let found = db
+            .receive_addresses(&["taprt1old".to_string(), "taprt1unknown".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(found[0].is_expired(0));
+    }
+}
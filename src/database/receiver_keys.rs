@@ -0,0 +1,295 @@
+use super::Database;
+use crate::error::AppError;
+use redis::AsyncCommands;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+/// Key rotations of mailbox receivers. The receiver's current key lives in
+/// `receivers.public_key`; each rotation keeps the key it replaced, which is
+/// still accepted, deprecated, until `deprecated_until`.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS receiver_key_rotations (
+        id TEXT PRIMARY KEY,
+        receiver_id TEXT NOT NULL,
+        old_public_key TEXT NOT NULL,
+        new_public_key TEXT NOT NULL,
+        reason TEXT,
+        rotated_at INTEGER NOT NULL,
+        deprecated_until INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_receiver_key_rotations_receiver
+        ON receiver_key_rotations(receiver_id, rotated_at);
+"#;
+
+const COLUMNS: &str =
+    "id, receiver_id, old_public_key, new_public_key, reason, rotated_at, deprecated_until";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct KeyRotation {
+    pub id: String,
+    pub receiver_id: String,
+    pub old_public_key: String,
+    pub new_public_key: String,
+    pub reason: Option<String>,
+    pub rotated_at: i64,
+    /// The old key authenticates, deprecated, until then.
+    pub deprecated_until: i64,
+}
+
+/// The keys a rotated receiver can authenticate with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiverKeys {
+    pub current: String,
+    /// Replaced keys still in their overlap window, with its end.
+    pub deprecated: Vec<(String, i64)>,
+}
+
+fn rotation_from_row(row: &SqliteRow) -> KeyRotation {
+    KeyRotation {
+        id: row.get("id"),
+        receiver_id: row.get("receiver_id"),
+        old_public_key: row.get("old_public_key"),
+        new_public_key: row.get("new_public_key"),
+        reason: row.get("reason"),
+        rotated_at: row.get("rotated_at"),
+        deprecated_until: row.get("deprecated_until"),
+    }
+}
+
+impl Database {
+    /// Replaces the receiver's key and records the rotation in one
+    /// transaction. Overlap windows of earlier rotations end, so at most the
+    /// key just replaced stays valid beside the new one. Returns `false`
+    /// when the receiver is unknown, inactive, or no longer has
+    /// `rotation.old_public_key`.
+    pub async fn rotate_receiver_key(&self, rotation: &KeyRotation) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let db_error =
+            |e: sqlx::Error| AppError::DatabaseError(format!("Failed to rotate receiver key: {e}"));
+
+        let mut tx = pool.begin().await.map_err(db_error)?;
+        let updated = sqlx::query(
+            "UPDATE receivers SET public_key = ? \
+             WHERE receiver_id = ? AND public_key = ? AND is_active = 1",
+        )
+        .bind(&rotation.new_public_key)
+        .bind(&rotation.receiver_id)
+        .bind(&rotation.old_public_key)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => AppError::Conflict(format!(
+                "Public key {} belongs to another receiver",
+                rotation.new_public_key
+            )),
+            e => db_error(e),
+        })?;
+        if updated.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query(
+            "UPDATE receiver_key_rotations SET deprecated_until = ? \
+             WHERE receiver_id = ? AND deprecated_until > ?",
+        )
+        .bind(rotation.rotated_at)
+        .bind(&rotation.receiver_id)
+        .bind(rotation.rotated_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        sqlx::query(&format!(
+            "INSERT INTO receiver_key_rotations ({COLUMNS}) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        ))
+        .bind(&rotation.id)
+        .bind(&rotation.receiver_id)
+        .bind(&rotation.old_public_key)
+        .bind(&rotation.new_public_key)
+        .bind(&rotation.reason)
+        .bind(rotation.rotated_at)
+        .bind(rotation.deprecated_until)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        tx.commit().await.map_err(db_error)?;
+
+        // Cached receiver info and key lookups would still name the old key
+        if let Some(redis_conn) = &self.redis_conn {
+            let mut conn = redis_conn.clone();
+            let _: Result<(), _> = conn
+                .del(&[
+                    format!("receiver:{}", rotation.receiver_id),
+                    format!("pubkey:{}", rotation.old_public_key),
+                ])
+                .await;
+        }
+        Ok(true)
+    }
+
+    /// Ends the overlap window of every deprecated key of the receiver.
+    /// Returns how many keys stopped being accepted.
+    pub async fn revoke_deprecated_receiver_keys(
+        &self,
+        receiver_id: &str,
+        now: i64,
+    ) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            "UPDATE receiver_key_rotations SET deprecated_until = ? \
+             WHERE receiver_id = ? AND deprecated_until > ?",
+        )
+        .bind(now)
+        .bind(receiver_id)
+        .bind(now)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to revoke receiver keys: {e}")))?;
+        Ok(result.rows_affected())
+    }
+
+    /// The receiver's rotations, newest first.
+    pub async fn receiver_key_rotations(
+        &self,
+        receiver_id: &str,
+    ) -> Result<Vec<KeyRotation>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM receiver_key_rotations WHERE receiver_id = ? \
+             ORDER BY rotated_at DESC, rowid DESC"
+        ))
+        .bind(receiver_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to load key rotations: {e}")))?;
+        Ok(rows.iter().map(rotation_from_row).collect())
+    }
+
+    /// The keys of a receiver that was ever rotated; `None` for one that
+    /// was not, or without SQLite, so its key is found the usual way.
+    pub async fn rotated_receiver_keys(
+        &self,
+        receiver_id: &str,
+        now: i64,
+    ) -> Result<Option<ReceiverKeys>, AppError> {
+        let Some(pool) = &self.sqlite_pool else {
+            return Ok(None);
+        };
+        let db_error =
+            |e: sqlx::Error| AppError::DatabaseError(format!("Failed to load receiver keys: {e}"));
+        let rows = sqlx::query(
+            r#"
+            SELECT r.public_key, k.old_public_key, k.deprecated_until
+            FROM receivers r
+            JOIN receiver_key_rotations k ON k.receiver_id = r.receiver_id
+            WHERE r.receiver_id = ? AND r.is_active = 1
+            ORDER BY k.rotated_at DESC, k.rowid DESC
+            "#,
+        )
+        .bind(receiver_id)
+        .fetch_all(pool)
+        .await
+        .map_err(db_error)?;
+        let Some(first) = rows.first() else {
+            return Ok(None);
+        };
+        Ok(Some(ReceiverKeys {
+            current: first.get("public_key"),
+            deprecated: rows
+                .iter()
+                .map(|row| (row.get("old_public_key"), row.get("deprecated_until")))
+                .filter(|(_, until): &(String, i64)| *until > now)
+                .collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{open_test_database, ReceiverInfo};
+
+    fn rotation(id: &str, old: &str, new: &str, at: i64, until: i64) -> KeyRotation {
+        KeyRotation {
+            id: id.to_string(),
+            receiver_id: "receiver-1".to_string(),
+            old_public_key: old.to_string(),
+            new_public_key: new.to_string(),
+            reason: Some("suspected compromise".to_string()),
+            rotated_at: at,
+            deprecated_until: until,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_keeps_one_deprecated_key() {
+        let db = open_test_database().await;
+        for (receiver_id, public_key) in [("receiver-1", "k1"), ("receiver-2", "other")] {
+            db.store_receiver_info(&ReceiverInfo {
+                receiver_id: receiver_id.to_string(),
+                public_key: public_key.to_string(),
+                address: None,
+                created_at: 1,
+                last_seen: 1,
+                is_active: true,
+                metadata: None,
+            })
+            .await
+            .unwrap();
+        }
+        assert_eq!(
+            db.rotated_receiver_keys("receiver-1", 0).await.unwrap(),
+            None
+        );
+
+        assert!(db
+            .rotate_receiver_key(&rotation("r1", "k1", "k2", 100, 1_000))
+            .await
+            .unwrap());
+        // The old key must match, so a stale request changes nothing
+        assert!(!db
+            .rotate_receiver_key(&rotation("r2", "k1", "k3", 200, 1_000))
+            .await
+            .unwrap());
+        assert!(matches!(
+            db.rotate_receiver_key(&rotation("r2", "k2", "other", 200, 1_000))
+                .await,
+            Err(AppError::Conflict(_))
+        ));
+        assert_eq!(
+            db.rotated_receiver_keys("receiver-1", 150).await.unwrap(),
+            Some(ReceiverKeys {
+                current: "k2".to_string(),
+                deprecated: vec![("k1".to_string(), 1_000)],
+            })
+        );
+
+        assert!(db
+            .rotate_receiver_key(&rotation("r3", "k2", "k3", 300, 2_000))
+            .await
+            .unwrap());
+        let keys = db.rotated_receiver_keys("receiver-1", 300).await.unwrap();
+        assert_eq!(keys.unwrap().deprecated, vec![("k2".to_string(), 2_000)]);
+        let history = db.receiver_key_rotations("receiver-1").await.unwrap();
+        assert_eq!(
+            history.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+            ["r3", "r1"]
+        );
+        assert_eq!(history[1].deprecated_until, 300);
+        assert_eq!(
+            db.get_receiver_info("receiver-1")
+                .await
+                .unwrap()
+                .unwrap()
+                .public_key,
+            "k3"
+        );
+
+        assert_eq!(
+            db.revoke_deprecated_receiver_keys("receiver-1", 400)
+                .await
+                .unwrap(),
+            1
+        );
+        let keys = db.rotated_receiver_keys("receiver-1", 400).await.unwrap();
+        assert!(keys.unwrap().deprecated.is_empty());
+    }
+}
@@ -0,0 +1,557 @@
+use super::Database;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+/// Recurring payments and the history of their runs. A payment pays either
+/// a static `address` or one fetched from `address_url` for each run.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS recurring_payments (
+        id TEXT PRIMARY KEY,
+        asset_id TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        address TEXT,
+        address_url TEXT,
+        schedule TEXT NOT NULL,
+        status TEXT NOT NULL,
+        spend_limit_amount INTEGER,
+        spend_limit_window_secs INTEGER,
+        max_payments INTEGER,
+        runs INTEGER NOT NULL DEFAULT 0,
+        payments_made INTEGER NOT NULL DEFAULT 0,
+        next_run_at INTEGER NOT NULL,
+        label TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_recurring_payments_due
+        ON recurring_payments(status, next_run_at);
+
+    CREATE TABLE IF NOT EXISTS recurring_payment_runs (
+        id TEXT PRIMARY KEY,
+        payment_id TEXT NOT NULL,
+        sequence INTEGER NOT NULL,
+        address TEXT,
+        amount INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        reason TEXT,
+        anchor_txid TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_recurring_payment_runs_payment
+        ON recurring_payment_runs(payment_id, created_at);
+"#;
+
+const PAYMENT_COLUMNS: &str = "id, asset_id, amount, address, address_url, schedule, status, \
+     spend_limit_amount, spend_limit_window_secs, max_payments, runs, payments_made, next_run_at, \
+     label, created_at, updated_at";
+
+const RUN_COLUMNS: &str =
+    "id, payment_id, sequence, address, amount, status, reason, anchor_txid, created_at, updated_at";
+
+const DEFAULT_LIST_LIMIT: u32 = 100;
+const MAX_LIST_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecurringStatus {
+    Active,
+    Cancelled,
+    /// `max_payments` have been made.
+    Completed,
+}
+
+impl RecurringStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecurringStatus::Active => "active",
+            RecurringStatus::Cancelled => "cancelled",
+            RecurringStatus::Completed => "completed",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "active" => Ok(RecurringStatus::Active),
+            "cancelled" => Ok(RecurringStatus::Cancelled),
+            "completed" => Ok(RecurringStatus::Completed),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown recurring payment status: {other}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringPayment {
+    pub id: String,
+    pub asset_id: String,
+    pub amount: u64,
+    /// Static destination paid on every run.
+    pub address: Option<String>,
+    /// URL asked for a fresh destination on every run.
+    pub address_url: Option<String>,
+    /// `@every` or cron expression of the cadence.
+    pub schedule: String,
+    pub status: RecurringStatus,
+    /// Most units sent within `spend_limit_window_secs` before runs are held
+    /// for approval.
+    pub spend_limit_amount: Option<u64>,
+    pub spend_limit_window_secs: Option<u64>,
+    pub max_payments: Option<u64>,
+    pub runs: u64,
+    pub payments_made: u64,
+    pub next_run_at: i64,
+    pub label: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Sent,
+    Failed,
+    /// Flagged by the spend limit, destination guard or anomaly rules; sent
+    /// only once approved.
+    AwaitingApproval,
+}
+
+impl RunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Sent => "sent",
+            RunStatus::Failed => "failed",
+            RunStatus::AwaitingApproval => "awaiting_approval",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "sent" => Ok(RunStatus::Sent),
+            "failed" => Ok(RunStatus::Failed),
+            "awaiting_approval" => Ok(RunStatus::AwaitingApproval),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown recurring payment run status: {other}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringRun {
+    pub id: String,
+    pub payment_id: String,
+    pub sequence: u64,
+    pub address: Option<String>,
+    pub amount: u64,
+    pub status: RunStatus,
+    /// Why the run failed or is held.
+    pub reason: Option<String>,
+    pub anchor_txid: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct RecurringPaymentQuery {
+    pub status: Option<RecurringStatus>,
+    pub asset_id: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl Database {
+    pub async fn insert_recurring_payment(
+        &self,
+        payment: &RecurringPayment,
+    ) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(&format!(
+            "INSERT INTO recurring_payments ({PAYMENT_COLUMNS}) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ))
+        .bind(&payment.id)
+        .bind(&payment.asset_id)
+        .bind(payment.amount as i64)
+        .bind(&payment.address)
+        .bind(&payment.address_url)
+        .bind(&payment.schedule)
+        .bind(payment.status.as_str())
+        .bind(payment.spend_limit_amount.map(|v| v as i64))
+        .bind(payment.spend_limit_window_secs.map(|v| v as i64))
+        .bind(payment.max_payments.map(|v| v as i64))
+        .bind(payment.runs as i64)
+        .bind(payment.payments_made as i64)
+        .bind(payment.next_run_at)
+        .bind(&payment.label)
+        .bind(payment.created_at)
+        .bind(payment.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store recurring payment: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn get_recurring_payment(
+        &self,
+        id: &str,
+    ) -> Result<Option<RecurringPayment>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!(
+            "SELECT {PAYMENT_COLUMNS} FROM recurring_payments WHERE id = ?"
+        ))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to fetch recurring payment: {e}")))?;
+        row.as_ref().map(payment_from_row).transpose()
+    }
+
+    /// Lists recurring payments, newest first.
+    pub async fn list_recurring_payments(
+        &self,
+        query: &RecurringPaymentQuery,
+    ) -> Result<Vec<RecurringPayment>, AppError> {
+        let pool = self.sqlite()?;
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+            "SELECT {PAYMENT_COLUMNS} FROM recurring_payments WHERE 1 = 1"
+        ));
+        if let Some(status) = query.status {
+            builder.push(" AND status = ").push_bind(status.as_str());
+        }
+        if let Some(asset_id) = &query.asset_id {
+            builder
+                .push(" AND asset_id = ")
+                .push_bind(asset_id.to_ascii_lowercase());
+        }
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_LIST_LIMIT)
+            .min(MAX_LIST_LIMIT);
+        builder
+            .push(" ORDER BY created_at DESC, id ASC LIMIT ")
+            .push_bind(limit as i64)
+            .push(" OFFSET ")
+            .push_bind(query.offset.unwrap_or(0) as i64);
+
+        let rows = builder.build().fetch_all(pool).await.map_err(|e| {
+            AppError::DatabaseError(format!("Failed to list recurring payments: {e}"))
+        })?;
+        rows.iter().map(payment_from_row).collect()
+    }
+
+    /// Active payments whose next run is due.
+    pub async fn due_recurring_payments(
+        &self,
+        now: i64,
+    ) -> Result<Vec<RecurringPayment>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "SELECT {PAYMENT_COLUMNS} FROM recurring_payments \
+             WHERE status = 'active' AND next_run_at <= ? ORDER BY next_run_at"
+        ))
+        .bind(now)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to list recurring payments: {e}")))?;
+        rows.iter().map(payment_from_row).collect()
+    }
+
+    /// Writes `payment`'s status, run count and next run, if it is still in
+    /// `from`. Returns false otherwise, so a run never starts on a payment
+    /// cancelled meanwhile.
+    pub async fn advance_recurring_payment(
+        &self,
+        payment: &RecurringPayment,
+        from: RecurringStatus,
+    ) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            "UPDATE recurring_payments SET status = ?, runs = ?, next_run_at = ?, updated_at = ? \
+             WHERE id = ? AND status = ?",
+        )
+        .bind(payment.status.as_str())
+        .bind(payment.runs as i64)
+        .bind(payment.next_run_at)
+        .bind(payment.updated_at)
+        .bind(&payment.id)
+        .bind(from.as_str())
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to update recurring payment: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Counts a sent run, completing the payment once it reaches
+    /// `max_payments`.
+    pub async fn record_recurring_payment_sent(&self, id: &str) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            "UPDATE recurring_payments SET payments_made = payments_made + 1, \
+             status = CASE WHEN status = 'active' AND max_payments IS NOT NULL \
+                 AND payments_made + 1 >= max_payments THEN 'completed' ELSE status END, \
+             updated_at = ? WHERE id = ?",
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to update recurring payment: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn insert_recurring_run(&self, run: &RecurringRun) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(&format!(
+            "INSERT INTO recurring_payment_runs ({RUN_COLUMNS}) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ))
+        .bind(&run.id)
+        .bind(&run.payment_id)
+        .bind(run.sequence as i64)
+        .bind(&run.address)
+        .bind(run.amount as i64)
+        .bind(run.status.as_str())
+        .bind(&run.reason)
+        .bind(&run.anchor_txid)
+        .bind(run.created_at)
+        .bind(run.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store payment run: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn get_recurring_run(
+        &self,
+        payment_id: &str,
+        id: &str,
+    ) -> Result<Option<RecurringRun>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!(
+            "SELECT {RUN_COLUMNS} FROM recurring_payment_runs WHERE payment_id = ? AND id = ?"
+        ))
+        .bind(payment_id)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to fetch payment run: {e}")))?;
+        row.as_ref().map(run_from_row).transpose()
+    }
+
+    /// A payment's runs, newest first.
+    pub async fn list_recurring_runs(
+        &self,
+        payment_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<RecurringRun>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "SELECT {RUN_COLUMNS} FROM recurring_payment_runs WHERE payment_id = ? \
+             ORDER BY sequence DESC LIMIT ? OFFSET ?"
+        ))
+        .bind(payment_id)
+        .bind(limit.min(MAX_LIST_LIMIT) as i64)
+        .bind(offset as i64)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to list payment runs: {e}")))?;
+        rows.iter().map(run_from_row).collect()
+    }
+
+    /// Writes `run`'s outcome if it is still in `from`, so one held run is
+    /// approved only once.
+    pub async fn advance_recurring_run(
+        &self,
+        run: &RecurringRun,
+        from: RunStatus,
+    ) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            "UPDATE recurring_payment_runs SET status = ?, reason = ?, anchor_txid = ?, \
+             updated_at = ? WHERE id = ? AND status = ?",
+        )
+        .bind(run.status.as_str())
+        .bind(&run.reason)
+        .bind(&run.anchor_txid)
+        .bind(run.updated_at)
+        .bind(&run.id)
+        .bind(from.as_str())
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to update payment run: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Units a payment sent in runs created at or after `since`.
+    pub async fn recurring_spent_since(
+        &self,
+        payment_id: &str,
+        since: i64,
+    ) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let spent: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(amount), 0) FROM recurring_payment_runs \
+             WHERE payment_id = ? AND status = 'sent' AND created_at >= ?",
+        )
+        .bind(payment_id)
+        .bind(since)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to sum payment runs: {e}")))?;
+        Ok(spent as u64)
+    }
+}
+
+fn payment_from_row(row: &SqliteRow) -> Result<RecurringPayment, AppError> {
+    let status: String = row.get("status");
+    let optional = |name: &str| row.get::<Option<i64>, _>(name).map(|v| v as u64);
+    Ok(RecurringPayment {
+        id: row.get("id"),
+        asset_id: row.get("asset_id"),
+        amount: row.get::<i64, _>("amount") as u64,
+        address: row.get("address"),
+        address_url: row.get("address_url"),
+        schedule: row.get("schedule"),
+        status: RecurringStatus::parse(&status)?,
+        spend_limit_amount: optional("spend_limit_amount"),
+        spend_limit_window_secs: optional("spend_limit_window_secs"),
+        max_payments: optional("max_payments"),
+        runs: row.get::<i64, _>("runs") as u64,
+        payments_made: row.get::<i64, _>("payments_made") as u64,
+        next_run_at: row.get("next_run_at"),
+        label: row.get("label"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+fn run_from_row(row: &SqliteRow) -> Result<RecurringRun, AppError> {
+    let status: String = row.get("status");
+    Ok(RecurringRun {
+        id: row.get("id"),
+        payment_id: row.get("payment_id"),
+        sequence: row.get::<i64, _>("sequence") as u64,
+        address: row.get("address"),
+        amount: row.get::<i64, _>("amount") as u64,
+        status: RunStatus::parse(&status)?,
+        reason: row.get("reason"),
+        anchor_txid: row.get("anchor_txid"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn payment(id: &str, next_run_at: i64, max_payments: Option<u64>) -> RecurringPayment {
+        RecurringPayment {
+            id: id.to_string(),
+            asset_id: "aa".to_string(),
+            amount: 10,
+            address: Some("taprt1...".to_string()),
+            address_url: None,
+            schedule: "@every 1h".to_string(),
+            status: RecurringStatus::Active,
+            spend_limit_amount: Some(25),
+            spend_limit_window_secs: Some(86_400),
+            max_payments,
+            runs: 0,
+            payments_made: 0,
+            next_run_at,
+            label: None,
+            created_at: 1,
+            updated_at: 1,
+        }
+    }
+
+    fn run(id: &str, sequence: u64, status: RunStatus, created_at: i64) -> RecurringRun {
+        RecurringRun {
+            id: id.to_string(),
+            payment_id: "p".to_string(),
+            sequence,
+            address: Some("taprt1...".to_string()),
+            amount: 10,
+            status,
+            reason: None,
+            anchor_txid: None,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_due_payments_and_completion() {
+        let db = open_test_database().await;
+        db.insert_recurring_payment(&payment("p", 100, Some(2)))
+            .await
+            .unwrap();
+        db.insert_recurring_payment(&payment("later", 500, None))
+            .await
+            .unwrap();
+
+        let due = db.due_recurring_payments(200).await.unwrap();
+        assert_eq!(due.len(), 1);
+        let mut p = due[0].clone();
+        p.runs = 1;
+        p.next_run_at = 3700;
+        assert!(db
+            .advance_recurring_payment(&p, RecurringStatus::Active)
+            .await
+            .unwrap());
+        assert!(db.due_recurring_payments(200).await.unwrap().is_empty());
+
+        db.record_recurring_payment_sent("p").await.unwrap();
+        let p = db.get_recurring_payment("p").await.unwrap().unwrap();
+        assert_eq!(p.status, RecurringStatus::Active);
+        db.record_recurring_payment_sent("p").await.unwrap();
+        let p = db.get_recurring_payment("p").await.unwrap().unwrap();
+        assert_eq!(p.status, RecurringStatus::Completed);
+        assert_eq!(p.payments_made, 2);
+        assert_eq!(p.spend_limit_amount, Some(25));
+    }
+
+    #[tokio::test]
+    async fn test_runs_history_and_spend() {
+        let db = open_test_database().await;
+        db.insert_recurring_run(&run("r1", 1, RunStatus::Sent, 100))
+            .await
+            .unwrap();
+        db.insert_recurring_run(&run("r2", 2, RunStatus::Sent, 200))
+            .await
+            .unwrap();
+        db.insert_recurring_run(&run("r3", 3, RunStatus::AwaitingApproval, 300))
+            .await
+            .unwrap();
+
+        assert_eq!(db.recurring_spent_since("p", 0).await.unwrap(), 20);
+        assert_eq!(db.recurring_spent_since("p", 150).await.unwrap(), 10);
+
+        let runs = db.list_recurring_runs("p", 10, 0).await.unwrap();
+        assert_eq!(
+            runs.iter().map(|r| r.sequence).collect::<Vec<_>>(),
+            [3, 2, 1]
+        );
+
+        let mut held = db.get_recurring_run("p", "r3").await.unwrap().unwrap();
+        held.status = RunStatus::Sent;
+        assert!(db
+            .advance_recurring_run(&held, RunStatus::AwaitingApproval)
+            .await
+            .unwrap());
+        assert!(!db
+            .advance_recurring_run(&held, RunStatus::AwaitingApproval)
+            .await
+            .unwrap());
+        assert!(db.get_recurring_run("q", "r3").await.unwrap().is_none());
+    }
+}
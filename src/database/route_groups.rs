@@ -0,0 +1,79 @@
+use super::Database;
+use crate::error::AppError;
+use sqlx::Row;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS route_groups (
+        name TEXT PRIMARY KEY,
+        enabled INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+"#;
+
+/// A route group toggled through the admin API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteGroupRecord {
+    pub name: String,
+    pub enabled: bool,
+    pub updated_at: i64,
+}
+
+impl Database {
+    pub async fn save_route_group(&self, record: &RouteGroupRecord) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO route_groups (name, enabled, updated_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT(name) DO UPDATE SET
+                enabled = excluded.enabled,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&record.name)
+        .bind(record.enabled)
+        .bind(record.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to save route group: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn route_group_records(&self) -> Result<Vec<RouteGroupRecord>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query("SELECT name, enabled, updated_at FROM route_groups ORDER BY name")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to load route groups: {e}")))?;
+        Ok(rows
+            .iter()
+            .map(|row| RouteGroupRecord {
+                name: row.get("name"),
+                enabled: row.get("enabled"),
+                updated_at: row.get("updated_at"),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_route_group_changes_upsert() {
+        let db = open_test_database().await;
+        let record = |enabled, updated_at| RouteGroupRecord {
+            name: "rfq".to_string(),
+            enabled,
+            updated_at,
+        };
+        db.save_route_group(&record(false, 10)).await.unwrap();
+        db.save_route_group(&record(true, 20)).await.unwrap();
+        assert_eq!(
+            db.route_group_records().await.unwrap(),
+            vec![record(true, 20)]
+        );
+    }
+}
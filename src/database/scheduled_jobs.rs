@@ -0,0 +1,133 @@
+use super::Database;
+use crate::error::AppError;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS scheduled_jobs (
+        name TEXT PRIMARY KEY,
+        schedule TEXT NOT NULL,
+        last_started_at INTEGER,
+        last_finished_at INTEGER,
+        last_status TEXT,
+        last_error TEXT,
+        last_duration_ms INTEGER,
+        runs INTEGER NOT NULL DEFAULT 0,
+        failures INTEGER NOT NULL DEFAULT 0,
+        skipped INTEGER NOT NULL DEFAULT 0
+    );
+"#;
+
+/// Outcome of a job's most recent run, and lifetime counters. Survives
+/// restarts so job health reflects runs of earlier processes too.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct JobRecord {
+    pub name: String,
+    pub schedule: String,
+    pub last_started_at: Option<i64>,
+    pub last_finished_at: Option<i64>,
+    /// `ok` or `error`.
+    pub last_status: Option<String>,
+    pub last_error: Option<String>,
+    pub last_duration_ms: Option<u64>,
+    pub runs: u64,
+    pub failures: u64,
+    /// Fire times skipped because the previous run was still going.
+    pub skipped: u64,
+}
+
+impl Database {
+    pub async fn save_job_record(&self, record: &JobRecord) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO scheduled_jobs (
+                name, schedule, last_started_at, last_finished_at, last_status,
+                last_error, last_duration_ms, runs, failures, skipped
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(name) DO UPDATE SET
+                schedule = excluded.schedule,
+                last_started_at = excluded.last_started_at,
+                last_finished_at = excluded.last_finished_at,
+                last_status = excluded.last_status,
+                last_error = excluded.last_error,
+                last_duration_ms = excluded.last_duration_ms,
+                runs = excluded.runs,
+                failures = excluded.failures,
+                skipped = excluded.skipped
+            "#,
+        )
+        .bind(&record.name)
+        .bind(&record.schedule)
+        .bind(record.last_started_at)
+        .bind(record.last_finished_at)
+        .bind(&record.last_status)
+        .bind(&record.last_error)
+        .bind(record.last_duration_ms.map(|d| d as i64))
+        .bind(record.runs as i64)
+        .bind(record.failures as i64)
+        .bind(record.skipped as i64)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to save job status: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn job_records(&self) -> Result<Vec<JobRecord>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query("SELECT * FROM scheduled_jobs ORDER BY name")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to load job status: {e}")))?;
+        Ok(rows.iter().map(record_from_row).collect())
+    }
+}
+
+fn record_from_row(row: &SqliteRow) -> JobRecord {
+    JobRecord {
+        name: row.get("name"),
+        schedule: row.get("schedule"),
+        last_started_at: row.get("last_started_at"),
+        last_finished_at: row.get("last_finished_at"),
+        last_status: row.get("last_status"),
+        last_error: row.get("last_error"),
+        last_duration_ms: row
+            .get::<Option<i64>, _>("last_duration_ms")
+            .map(|d| d as u64),
+        runs: row.get::<i64, _>("runs") as u64,
+        failures: row.get::<i64, _>("failures") as u64,
+        skipped: row.get::<i64, _>("skipped") as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_job_record_round_trip() {
+        let db = open_test_database().await;
+        let mut record = JobRecord {
+            name: "usage_flush".to_string(),
+            schedule: "@every 60s".to_string(),
+            last_started_at: Some(100),
+            last_finished_at: Some(101),
+            last_status: Some("ok".to_string()),
+            last_duration_ms: Some(12),
+            runs: 1,
+            ..Default::default()
+        };
+        db.save_job_record(&record).await.unwrap();
+
+        record.runs = 2;
+        record.failures = 1;
+        record.last_status = Some("error".to_string());
+        record.last_error = Some("database locked".to_string());
+        db.save_job_record(&record).await.unwrap();
+
+        assert_eq!(db.job_records().await.unwrap(), vec![record]);
+    }
+}
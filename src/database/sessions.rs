@@ -0,0 +1,175 @@
+use super::Database;
+use crate::error::AppError;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+/// Browser sessions. Only the SHA-256 of the current refresh token is kept;
+/// refreshing replaces it, so a refresh token works once.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS sessions (
+        id TEXT PRIMARY KEY,
+        subject TEXT NOT NULL,
+        role TEXT,
+        refresh_hash TEXT NOT NULL UNIQUE,
+        created_at INTEGER NOT NULL,
+        refreshed_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL,
+        revoked_at INTEGER
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_sessions_expires ON sessions(expires_at);
+"#;
+
+const COLUMNS: &str = "id, subject, role, created_at, refreshed_at, expires_at, revoked_at";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionRecord {
+    pub id: String,
+    /// Who logged in: `api_key`, `role_key:<role>`, `oidc:<sub>` or
+    /// `key:<public key>`.
+    pub subject: String,
+    /// Read-only role of the session; `None` has full access.
+    pub role: Option<String>,
+    pub created_at: i64,
+    pub refreshed_at: i64,
+    /// When the refresh token stops working.
+    pub expires_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+impl Database {
+    pub async fn insert_session(
+        &self,
+        session: &SessionRecord,
+        refresh_hash: &str,
+    ) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO sessions (id, subject, role, refresh_hash, created_at, refreshed_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&session.id)
+        .bind(&session.subject)
+        .bind(&session.role)
+        .bind(refresh_hash)
+        .bind(session.created_at)
+        .bind(session.refreshed_at)
+        .bind(session.expires_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store session: {e}")))?;
+        Ok(())
+    }
+
+    /// Swaps a live session's refresh token for a new one and extends it to
+    /// `expires_at`; `None` if `refresh_hash` matches no live session.
+    pub async fn rotate_session(
+        &self,
+        refresh_hash: &str,
+        new_refresh_hash: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<Option<SessionRecord>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!(
+            "UPDATE sessions SET refresh_hash = ?, refreshed_at = ?, expires_at = ? \
+             WHERE refresh_hash = ? AND revoked_at IS NULL AND expires_at > ? \
+             RETURNING {COLUMNS}"
+        ))
+        .bind(new_refresh_hash)
+        .bind(now)
+        .bind(expires_at)
+        .bind(refresh_hash)
+        .bind(now)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to refresh session: {e}")))?;
+        Ok(row.as_ref().map(session_from_row))
+    }
+
+    /// Revokes the session holding `refresh_hash`; false if there is none.
+    pub async fn revoke_session(&self, refresh_hash: &str, now: i64) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            "UPDATE sessions SET revoked_at = ? WHERE refresh_hash = ? AND revoked_at IS NULL",
+        )
+        .bind(now)
+        .bind(refresh_hash)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to revoke session: {e}")))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes sessions whose refresh tokens expired before `before`.
+    pub async fn prune_sessions(&self, before: i64) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query("DELETE FROM sessions WHERE expires_at < ?")
+            .bind(before)
+            .execute(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to prune sessions: {e}")))?;
+        Ok(result.rows_affected())
+    }
+}
+
+fn session_from_row(row: &SqliteRow) -> SessionRecord {
+    SessionRecord {
+        id: row.get("id"),
+        subject: row.get("subject"),
+        role: row.get("role"),
+        created_at: row.get("created_at"),
+        refreshed_at: row.get("refreshed_at"),
+        expires_at: row.get("expires_at"),
+        revoked_at: row.get("revoked_at"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_refresh_tokens_rotate_and_revoke() {
+        let db = open_test_database().await;
+        let session = SessionRecord {
+            id: "s1".to_string(),
+            subject: "api_key".to_string(),
+            role: None,
+            created_at: 100,
+            refreshed_at: 100,
+            expires_at: 200,
+            revoked_at: None,
+        };
+        db.insert_session(&session, "h1").await.unwrap();
+
+        let rotated = db.rotate_session("h1", "h2", 150, 250).await.unwrap();
+        assert_eq!(rotated.unwrap().expires_at, 250);
+        // The old token is spent
+        assert!(db
+            .rotate_session("h1", "h3", 160, 260)
+            .await
+            .unwrap()
+            .is_none());
+        // Expired sessions do not refresh
+        assert!(db
+            .rotate_session("h2", "h3", 300, 400)
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(db.revoke_session("h2", 170).await.unwrap());
+        assert!(!db.revoke_session("h2", 171).await.unwrap());
+        assert!(db
+            .rotate_session("h2", "h3", 180, 280)
+            .await
+            .unwrap()
+            .is_none());
+
+        assert_eq!(db.prune_sessions(300).await.unwrap(), 1);
+    }
+}
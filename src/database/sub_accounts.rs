@@ -0,0 +1,271 @@
+use super::transfers::{AssetPosition, POSITIONED_COLUMNS, POSITION_TOTALS};
+use super::Database;
+use crate::error::AppError;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+/// Sub-accounts, the addresses generated for them and the sends made from
+/// them. An account's transfers are the indexed receives to its addresses
+/// and the indexed sends whose anchor transaction it is recorded against.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS sub_accounts (
+        name TEXT PRIMARY KEY,
+        description TEXT,
+        created_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS sub_account_addresses (
+        address TEXT PRIMARY KEY,
+        account TEXT NOT NULL,
+        asset_id TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS sub_account_sends (
+        anchor_txid TEXT PRIMARY KEY,
+        account TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_sub_account_addresses_account ON sub_account_addresses(account);
+    CREATE INDEX IF NOT EXISTS idx_sub_account_sends_account ON sub_account_sends(account);
+"#;
+
+/// Matches an account's indexed rows; binds the account name twice.
+const ACCOUNT_TRANSFERS: &str = "((kind = 'receive' AND address IN \
+     (SELECT address FROM sub_account_addresses WHERE account = ?)) \
+     OR (kind = 'send' AND anchor_txid IN \
+     (SELECT anchor_txid FROM sub_account_sends WHERE account = ?)))";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubAccount {
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: i64,
+}
+
+/// An account's indexed position in one asset.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubAccountBalance {
+    pub asset_id: String,
+    #[serde(flatten)]
+    pub position: AssetPosition,
+    pub available: u64,
+}
+
+impl Database {
+    /// Creates an account; false if the name is taken.
+    pub async fn create_sub_account(&self, account: &SubAccount) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            r#"
+            INSERT INTO sub_accounts (name, description, created_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT(name) DO NOTHING
+            "#,
+        )
+        .bind(&account.name)
+        .bind(&account.description)
+        .bind(account.created_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to create sub-account: {e}")))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn sub_account(&self, name: &str) -> Result<Option<SubAccount>, AppError> {
+        let pool = self.sqlite()?;
+        let row =
+            sqlx::query("SELECT name, description, created_at FROM sub_accounts WHERE name = ?")
+                .bind(name)
+                .fetch_optional(pool)
+                .await
+                .map_err(|e| AppError::DatabaseError(format!("Failed to load sub-account: {e}")))?;
+        Ok(row.as_ref().map(sub_account_from_row))
+    }
+
+    pub async fn sub_accounts(&self, limit: u32, offset: u32) -> Result<Vec<SubAccount>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            "SELECT name, description, created_at FROM sub_accounts \
+             ORDER BY name LIMIT ? OFFSET ?",
+        )
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to list sub-accounts: {e}")))?;
+        Ok(rows.iter().map(sub_account_from_row).collect())
+    }
+
+    /// Attributes receives to `address` to `account`.
+    pub async fn add_sub_account_address(
+        &self,
+        account: &str,
+        address: &str,
+        asset_id: &str,
+    ) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO sub_account_addresses (address, account, asset_id, created_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(address)
+        .bind(account)
+        .bind(asset_id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            AppError::DatabaseError(format!("Failed to store sub-account address: {e}"))
+        })?;
+        Ok(())
+    }
+
+    /// Charges the send anchored in `anchor_txid` to `account`.
+    pub async fn record_sub_account_send(
+        &self,
+        account: &str,
+        anchor_txid: &str,
+    ) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO sub_account_sends (anchor_txid, account, created_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT(anchor_txid) DO NOTHING
+            "#,
+        )
+        .bind(anchor_txid)
+        .bind(account)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to record sub-account send: {e}")))?;
+        Ok(())
+    }
+
+    /// Per-asset positions of `account`, counted like
+    /// [`Database::asset_position`] over the account's own transfers.
+    pub async fn sub_account_balances(
+        &self,
+        account: &str,
+        min_confirmations: u32,
+    ) -> Result<Vec<SubAccountBalance>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "WITH positioned AS (SELECT asset_id, {POSITIONED_COLUMNS} FROM indexed_transfers \
+             WHERE asset_id IS NOT NULL AND {ACCOUNT_TRANSFERS} \
+             AND (chain_status IS NULL OR chain_status != 'replaced')) \
+             SELECT asset_id, {POSITION_TOTALS} FROM positioned \
+             GROUP BY asset_id ORDER BY asset_id"
+        ))
+        .bind(account)
+        .bind(account)
+        .bind(min_confirmations as i64)
+        .bind(min_confirmations as i64)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            AppError::DatabaseError(format!("Failed to total sub-account balances: {e}"))
+        })?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let position = AssetPosition::from_row(row);
+                SubAccountBalance {
+                    asset_id: row.get("asset_id"),
+                    available: position.available(),
+                    position,
+                }
+            })
+            .collect())
+    }
+}
+
+fn sub_account_from_row(row: &SqliteRow) -> SubAccount {
+    SubAccount {
+        name: row.get("name"),
+        description: row.get("description"),
+        created_at: row.get("created_at"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{open_test_database, IndexedTransfer, TransferKind, TransferQuery};
+
+    fn transfer(id: &str, kind: TransferKind, address: &str, anchor: &str) -> IndexedTransfer {
+        IndexedTransfer {
+            id: id.to_string(),
+            kind,
+            asset_id: Some("aa".to_string()),
+            address: Some(address.to_string()),
+            amount: Some(40),
+            anchor_txid: Some(anchor.to_string()),
+            outpoint: None,
+            block_height: None,
+            status: "completed".to_string(),
+            timestamp: 100,
+            raw: serde_json::json!({}),
+            chain_status: None,
+            confirmations: Some(6),
+            block_hash: None,
+            label: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_balances_count_only_the_accounts_own_transfers() {
+        let db = open_test_database().await;
+        let account = |name: &str| SubAccount {
+            name: name.to_string(),
+            description: None,
+            created_at: 1,
+        };
+        assert!(db.create_sub_account(&account("sales")).await.unwrap());
+        assert!(!db.create_sub_account(&account("sales")).await.unwrap());
+        assert!(db.create_sub_account(&account("ops")).await.unwrap());
+        db.add_sub_account_address("sales", "taprt1sales", "aa")
+            .await
+            .unwrap();
+        db.add_sub_account_address("ops", "taprt1ops", "aa")
+            .await
+            .unwrap();
+        db.upsert_indexed_transfers(&[
+            transfer("receive:r1", TransferKind::Receive, "taprt1sales", "r1"),
+            transfer("receive:r2", TransferKind::Receive, "taprt1sales", "r2"),
+            transfer("receive:r3", TransferKind::Receive, "taprt1ops", "r3"),
+            transfer("send:s1:aa", TransferKind::Send, "taprt1elsewhere", "s1"),
+        ])
+        .await
+        .unwrap();
+        db.record_sub_account_send("sales", "s1").await.unwrap();
+
+        let sales = db.sub_account_balances("sales", 1).await.unwrap();
+        assert_eq!(sales.len(), 1);
+        assert_eq!(sales[0].position.settled_in, 80);
+        assert_eq!(sales[0].position.outgoing, 40);
+        assert_eq!(sales[0].available, 40);
+        assert_eq!(
+            db.sub_account_balances("ops", 1).await.unwrap()[0].available,
+            40
+        );
+        assert!(db.sub_account_balances("none", 1).await.unwrap().is_empty());
+
+        let rows = db
+            .query_indexed_transfers(&TransferQuery {
+                account: Some("sales".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(db.sub_accounts(10, 0).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,448 @@
+use super::Database;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+/// Two-party swaps coordinated through the mailbox. Both sides record the
+/// swap under the id the initiator chose. `own_psbt` is this side's funded,
+/// later signed, virtual PSBT; `anchor` holds the committed anchor
+/// transaction and the virtual PSBTs it carries.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS swaps (
+        id TEXT PRIMARY KEY,
+        role TEXT NOT NULL,
+        counterparty_id TEXT NOT NULL,
+        offer_asset_id TEXT,
+        offer_amount INTEGER NOT NULL,
+        ask_asset_id TEXT,
+        ask_amount INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        own_psbt TEXT,
+        passive_asset_psbts TEXT,
+        counterparty_psbt TEXT,
+        btc_address TEXT,
+        anchor TEXT,
+        anchor_txid TEXT,
+        tx_proof TEXT,
+        last_error TEXT,
+        expires_at INTEGER NOT NULL,
+        callback_url TEXT,
+        callback_secret TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_swaps_status ON swaps(status, expires_at);
+"#;
+
+const COLUMNS: &str = "id, role, counterparty_id, offer_asset_id, offer_amount, ask_asset_id, \
+     ask_amount, status, own_psbt, passive_asset_psbts, counterparty_psbt, btc_address, anchor, \
+     anchor_txid, tx_proof, last_error, expires_at, callback_url, callback_secret, created_at, \
+     updated_at";
+
+const DEFAULT_LIST_LIMIT: u32 = 100;
+const MAX_LIST_LIMIT: u32 = 1000;
+
+/// Which side of the swap this gateway's node is. The initiator commits
+/// both legs to the anchor transaction and publishes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapRole {
+    Initiator,
+    Responder,
+}
+
+impl SwapRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SwapRole::Initiator => "initiator",
+            SwapRole::Responder => "responder",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "initiator" => Ok(SwapRole::Initiator),
+            "responder" => Ok(SwapRole::Responder),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown swap role: {other}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapStatus {
+    /// Initiator: the proposal is out, waiting for the responder's leg.
+    Proposed,
+    /// Responder: its signed leg is out, waiting for the anchor transaction.
+    Accepted,
+    /// Initiator: both legs are committed to an anchor transaction that is
+    /// out for the responder's signature.
+    Committed,
+    /// Responder: it signed the anchor transaction.
+    Signed,
+    /// The anchor transaction was published.
+    Settled,
+    Cancelled,
+    /// The timeout passed before settlement.
+    Expired,
+    /// Expired or cancelled, and the leases on this side's inputs were
+    /// released.
+    Refunded,
+    Failed,
+}
+
+impl SwapStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SwapStatus::Proposed => "proposed",
+            SwapStatus::Accepted => "accepted",
+            SwapStatus::Committed => "committed",
+            SwapStatus::Signed => "signed",
+            SwapStatus::Settled => "settled",
+            SwapStatus::Cancelled => "cancelled",
+            SwapStatus::Expired => "expired",
+            SwapStatus::Refunded => "refunded",
+            SwapStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "proposed" => Ok(SwapStatus::Proposed),
+            "accepted" => Ok(SwapStatus::Accepted),
+            "committed" => Ok(SwapStatus::Committed),
+            "signed" => Ok(SwapStatus::Signed),
+            "settled" => Ok(SwapStatus::Settled),
+            "cancelled" => Ok(SwapStatus::Cancelled),
+            "expired" => Ok(SwapStatus::Expired),
+            "refunded" => Ok(SwapStatus::Refunded),
+            "failed" => Ok(SwapStatus::Failed),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown swap status: {other}"
+            ))),
+        }
+    }
+
+    /// Whether the swap can still settle.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self,
+            SwapStatus::Proposed
+                | SwapStatus::Accepted
+                | SwapStatus::Committed
+                | SwapStatus::Signed
+        )
+    }
+}
+
+/// One swap as seen from this gateway's node. An absent asset id means the
+/// leg is paid in satoshis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Swap {
+    pub id: String,
+    pub role: SwapRole,
+    /// Mailbox receiver id messages for the counterparty are sent to.
+    pub counterparty_id: String,
+    pub offer_asset_id: Option<String>,
+    pub offer_amount: u64,
+    pub ask_asset_id: Option<String>,
+    pub ask_amount: u64,
+    pub status: SwapStatus,
+    #[serde(skip_serializing, default)]
+    pub own_psbt: Option<String>,
+    #[serde(skip_serializing, default)]
+    pub passive_asset_psbts: Vec<String>,
+    #[serde(skip_serializing, default)]
+    pub counterparty_psbt: Option<String>,
+    /// Responder: where the initiator pays a satoshi offer.
+    pub btc_address: Option<String>,
+    #[serde(skip_serializing, default)]
+    pub anchor: Option<serde_json::Value>,
+    pub anchor_txid: Option<String>,
+    #[serde(skip_serializing, default)]
+    pub tx_proof: Option<serde_json::Value>,
+    pub last_error: Option<String>,
+    pub expires_at: i64,
+    pub callback_url: Option<String>,
+    #[serde(skip_serializing, default)]
+    pub callback_secret: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct SwapQuery {
+    pub status: Option<SwapStatus>,
+    pub role: Option<SwapRole>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl Database {
+    pub async fn insert_swap(&self, swap: &Swap) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        let passive = serde_json::to_string(&swap.passive_asset_psbts)?;
+        let anchor = swap
+            .anchor
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let tx_proof = swap
+            .tx_proof
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        sqlx::query(&format!(
+            "INSERT INTO swaps ({COLUMNS}) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ))
+        .bind(&swap.id)
+        .bind(swap.role.as_str())
+        .bind(&swap.counterparty_id)
+        .bind(&swap.offer_asset_id)
+        .bind(swap.offer_amount as i64)
+        .bind(&swap.ask_asset_id)
+        .bind(swap.ask_amount as i64)
+        .bind(swap.status.as_str())
+        .bind(&swap.own_psbt)
+        .bind(passive)
+        .bind(&swap.counterparty_psbt)
+        .bind(&swap.btc_address)
+        .bind(anchor)
+        .bind(&swap.anchor_txid)
+        .bind(tx_proof)
+        .bind(&swap.last_error)
+        .bind(swap.expires_at)
+        .bind(&swap.callback_url)
+        .bind(&swap.callback_secret)
+        .bind(swap.created_at)
+        .bind(swap.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                AppError::ValidationError(format!("Swap {} already exists", swap.id))
+            }
+            e => AppError::DatabaseError(format!("Failed to store swap: {e}")),
+        })?;
+        Ok(())
+    }
+
+    pub async fn get_swap(&self, id: &str) -> Result<Option<Swap>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!("SELECT {COLUMNS} FROM swaps WHERE id = ?"))
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to fetch swap: {e}")))?;
+        row.as_ref().map(swap_from_row).transpose()
+    }
+
+    /// Lists swaps, newest first.
+    pub async fn list_swaps(&self, query: &SwapQuery) -> Result<Vec<Swap>, AppError> {
+        let pool = self.sqlite()?;
+        let mut builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new(format!("SELECT {COLUMNS} FROM swaps WHERE 1 = 1"));
+        if let Some(status) = query.status {
+            builder.push(" AND status = ").push_bind(status.as_str());
+        }
+        if let Some(role) = query.role {
+            builder.push(" AND role = ").push_bind(role.as_str());
+        }
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_LIST_LIMIT)
+            .min(MAX_LIST_LIMIT);
+        builder
+            .push(" ORDER BY created_at DESC, id ASC LIMIT ")
+            .push_bind(limit as i64)
+            .push(" OFFSET ")
+            .push_bind(query.offset.unwrap_or(0) as i64);
+
+        let rows = builder
+            .build()
+            .fetch_all(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to list swaps: {e}")))?;
+        rows.iter().map(swap_from_row).collect()
+    }
+
+    /// Open swaps whose timeout has passed.
+    pub async fn expired_swaps(&self, now: i64) -> Result<Vec<Swap>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM swaps \
+             WHERE status IN ('proposed', 'accepted', 'committed', 'signed') AND expires_at <= ?"
+        ))
+        .bind(now)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to list swaps: {e}")))?;
+        rows.iter().map(swap_from_row).collect()
+    }
+
+    /// Moves `swap` on from `from`, writing its mutable fields. Returns
+    /// false when the stored swap is no longer in `from`, so two requests
+    /// racing on one swap cannot both advance it.
+    pub async fn advance_swap(&self, swap: &Swap, from: SwapStatus) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let anchor = swap
+            .anchor
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let result = sqlx::query(
+            "UPDATE swaps SET status = ?, own_psbt = ?, counterparty_psbt = ?, btc_address = ?, \
+             anchor = ?, anchor_txid = ?, last_error = ?, updated_at = ? \
+             WHERE id = ? AND status = ?",
+        )
+        .bind(swap.status.as_str())
+        .bind(&swap.own_psbt)
+        .bind(&swap.counterparty_psbt)
+        .bind(&swap.btc_address)
+        .bind(anchor)
+        .bind(&swap.anchor_txid)
+        .bind(&swap.last_error)
+        .bind(swap.updated_at)
+        .bind(&swap.id)
+        .bind(from.as_str())
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to update swap: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+fn swap_from_row(row: &SqliteRow) -> Result<Swap, AppError> {
+    let role: String = row.get("role");
+    let status: String = row.get("status");
+    let passive: Option<String> = row.get("passive_asset_psbts");
+    let anchor: Option<String> = row.get("anchor");
+    let tx_proof: Option<String> = row.get("tx_proof");
+    Ok(Swap {
+        id: row.get("id"),
+        role: SwapRole::parse(&role)?,
+        counterparty_id: row.get("counterparty_id"),
+        offer_asset_id: row.get("offer_asset_id"),
+        offer_amount: row.get::<i64, _>("offer_amount") as u64,
+        ask_asset_id: row.get("ask_asset_id"),
+        ask_amount: row.get::<i64, _>("ask_amount") as u64,
+        status: SwapStatus::parse(&status)?,
+        own_psbt: row.get("own_psbt"),
+        passive_asset_psbts: passive
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()?
+            .unwrap_or_default(),
+        counterparty_psbt: row.get("counterparty_psbt"),
+        btc_address: row.get("btc_address"),
+        anchor: anchor.as_deref().map(serde_json::from_str).transpose()?,
+        anchor_txid: row.get("anchor_txid"),
+        tx_proof: tx_proof.as_deref().map(serde_json::from_str).transpose()?,
+        last_error: row.get("last_error"),
+        expires_at: row.get("expires_at"),
+        callback_url: row.get("callback_url"),
+        callback_secret: row.get("callback_secret"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn swap(id: &str, status: SwapStatus, expires_at: i64) -> Swap {
+        Swap {
+            id: id.to_string(),
+            role: SwapRole::Initiator,
+            counterparty_id: "02aa".to_string(),
+            offer_asset_id: Some("aa".to_string()),
+            offer_amount: 10,
+            ask_asset_id: None,
+            ask_amount: 5000,
+            status,
+            own_psbt: Some("cHNidP8=".to_string()),
+            passive_asset_psbts: vec!["cA==".to_string()],
+            counterparty_psbt: None,
+            btc_address: None,
+            anchor: None,
+            anchor_txid: None,
+            tx_proof: Some(serde_json::json!({ "block_height": 7 })),
+            last_error: None,
+            expires_at,
+            callback_url: None,
+            callback_secret: None,
+            created_at: 1,
+            updated_at: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_advance_only_from_expected_status() {
+        let db = open_test_database().await;
+        db.insert_swap(&swap("a", SwapStatus::Proposed, 500))
+            .await
+            .unwrap();
+        assert!(db
+            .insert_swap(&swap("a", SwapStatus::Proposed, 500))
+            .await
+            .is_err());
+
+        let mut committed = db.get_swap("a").await.unwrap().unwrap();
+        assert_eq!(committed.passive_asset_psbts, ["cA=="]);
+        assert_eq!(committed.tx_proof.as_ref().unwrap()["block_height"], 7);
+        committed.status = SwapStatus::Committed;
+        committed.anchor = Some(serde_json::json!({ "anchor_psbt": "x" }));
+        assert!(db
+            .advance_swap(&committed, SwapStatus::Proposed)
+            .await
+            .unwrap());
+        // A second request that read the swap as proposed loses
+        assert!(!db
+            .advance_swap(&committed, SwapStatus::Proposed)
+            .await
+            .unwrap());
+
+        let stored = db.get_swap("a").await.unwrap().unwrap();
+        assert_eq!(stored.status, SwapStatus::Committed);
+        assert_eq!(stored.anchor.unwrap()["anchor_psbt"], "x");
+    }
+
+    #[tokio::test]
+    async fn test_expired_swaps_are_open_and_due() {
+        let db = open_test_database().await;
+        db.insert_swap(&swap("due", SwapStatus::Proposed, 100))
+            .await
+            .unwrap();
+        db.insert_swap(&swap("later", SwapStatus::Proposed, 300))
+            .await
+            .unwrap();
+        db.insert_swap(&swap("settled", SwapStatus::Settled, 100))
+            .await
+            .unwrap();
+
+        let due: Vec<String> = db
+            .expired_swaps(200)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(due, ["due"]);
+
+        let settled = db
+            .list_swaps(&SwapQuery {
+                status: Some(SwapStatus::Settled),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(settled.len(), 1);
+    }
+}
@@ -0,0 +1,424 @@
+//! Structured filters over the transfer index. Clients send a tree of
+//! conditions as JSON; field names are resolved against a fixed column list
+//! and every value is bound as a parameter, so nothing from the request is
+//! ever spliced into SQL text.
+
+use super::transfers::{transfer_from_row, SELECT_COLUMNS};
+use super::{Database, IndexedTransfer};
+use crate::error::AppError;
+use serde::Deserialize;
+use sqlx::{QueryBuilder, Sqlite};
+
+/// Deepest nesting of `all`, `any` and `not` accepted.
+const MAX_FILTER_DEPTH: usize = 5;
+/// Most conditions one filter may contain.
+const MAX_FILTER_CONDITIONS: usize = 50;
+/// Most values an `in` or `not_in` list may contain.
+const MAX_IN_VALUES: usize = 100;
+/// Most sort keys accepted, before the implicit `id` tiebreaker.
+const MAX_SORT_KEYS: usize = 3;
+
+/// A node of the filter tree: a combinator over child filters, or one
+/// `field op value` condition.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum TransferFilter {
+    All { all: Vec<TransferFilter> },
+    Any { any: Vec<TransferFilter> },
+    Not { not: Box<TransferFilter> },
+    Condition(FilterCondition),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterCondition {
+    pub field: String,
+    pub op: String,
+    #[serde(default)]
+    pub value: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterSort {
+    pub field: String,
+    /// `asc` or `desc` (the default).
+    #[serde(default)]
+    pub direction: Option<String>,
+}
+
+/// Body of the structured transfer query endpoint.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransferFilterQuery {
+    #[serde(default)]
+    pub filter: Option<TransferFilter>,
+    /// Defaults to newest first.
+    #[serde(default)]
+    pub sort: Vec<FilterSort>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+    Text,
+    Integer,
+}
+
+/// The filterable columns. Only names returned from here reach the SQL text.
+fn column(field: &str) -> Result<(&'static str, ColumnType), AppError> {
+    let column = match field {
+        "id" => ("id", ColumnType::Text),
+        "kind" => ("kind", ColumnType::Text),
+        "asset_id" => ("asset_id", ColumnType::Text),
+        "address" => ("address", ColumnType::Text),
+        "status" => ("status", ColumnType::Text),
+        "anchor_txid" => ("anchor_txid", ColumnType::Text),
+        "outpoint" => ("outpoint", ColumnType::Text),
+        "chain_status" => ("chain_status", ColumnType::Text),
+        "block_hash" => ("block_hash", ColumnType::Text),
+        "amount" => ("amount", ColumnType::Integer),
+        "block_height" => ("block_height", ColumnType::Integer),
+        "timestamp" => ("timestamp", ColumnType::Integer),
+        "confirmations" => ("confirmations", ColumnType::Integer),
+        other => {
+            return Err(AppError::InvalidInput(format!(
+                "Unknown filter field '{other}'"
+            )))
+        }
+    };
+    Ok(column)
+}
+
+enum Bound {
+    Text(String),
+    Integer(i64),
+}
+
+fn bound(field: &str, kind: ColumnType, value: &serde_json::Value) -> Result<Bound, AppError> {
+    match kind {
+        ColumnType::Text => value
+            .as_str()
+            // The index stores lowercase hex.
+            .map(|s| match field {
+                "asset_id" => Bound::Text(s.to_ascii_lowercase()),
+                _ => Bound::Text(s.to_string()),
+            })
+            .ok_or_else(|| {
+                AppError::InvalidInput(format!("Filter value for '{field}' must be a string"))
+            }),
+        ColumnType::Integer => value.as_i64().map(Bound::Integer).ok_or_else(|| {
+            AppError::InvalidInput(format!("Filter value for '{field}' must be an integer"))
+        }),
+    }
+}
+
+fn push_bound(builder: &mut QueryBuilder<'_, Sqlite>, value: Bound) {
+    match value {
+        Bound::Text(s) => builder.push_bind(s),
+        Bound::Integer(n) => builder.push_bind(n),
+    };
+}
+
+fn required_value(condition: &FilterCondition) -> Result<&serde_json::Value, AppError> {
+    condition.value.as_ref().ok_or_else(|| {
+        AppError::InvalidInput(format!(
+            "Filter op '{}' on '{}' requires a value",
+            condition.op, condition.field
+        ))
+    })
+}
+
+fn push_condition(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    condition: &FilterCondition,
+) -> Result<(), AppError> {
+    let field = condition.field.as_str();
+    let (column, kind) = column(field)?;
+    let comparison = match condition.op.as_str() {
+        "eq" => Some(" = "),
+        // `IS NOT` so rows where the field is unset still match.
+        "ne" => Some(" IS NOT "),
+        "gt" => Some(" > "),
+        "gte" => Some(" >= "),
+        "lt" => Some(" < "),
+        "lte" => Some(" <= "),
+        _ => None,
+    };
+    if let Some(comparison) = comparison {
+        let value = bound(field, kind, required_value(condition)?)?;
+        builder.push(column).push(comparison);
+        push_bound(builder, value);
+        return Ok(());
+    }
+
+    match condition.op.as_str() {
+        op @ ("in" | "not_in") => {
+            let values = required_value(condition)?
+                .as_array()
+                .filter(|values| !values.is_empty() && values.len() <= MAX_IN_VALUES)
+                .ok_or_else(|| {
+                    AppError::InvalidInput(format!(
+                        "Filter op '{op}' needs an array of 1 to {MAX_IN_VALUES} values"
+                    ))
+                })?
+                .iter()
+                .map(|value| bound(field, kind, value))
+                .collect::<Result<Vec<_>, _>>()?;
+            builder.push(column);
+            builder.push(if op == "in" { " IN (" } else { " NOT IN (" });
+            for (i, value) in values.into_iter().enumerate() {
+                if i > 0 {
+                    builder.push(", ");
+                }
+                push_bound(builder, value);
+            }
+            builder.push(")");
+        }
+        "is_null" | "not_null" => {
+            if condition.value.is_some() {
+                return Err(AppError::InvalidInput(format!(
+                    "Filter op '{}' takes no value",
+                    condition.op
+                )));
+            }
+            builder.push(column).push(if condition.op == "is_null" {
+                " IS NULL"
+            } else {
+                " IS NOT NULL"
+            });
+        }
+        "prefix" => {
+            if kind != ColumnType::Text {
+                return Err(AppError::InvalidInput(format!(
+                    "Filter op 'prefix' does not apply to '{field}'"
+                )));
+            }
+            let Bound::Text(prefix) = bound(field, kind, required_value(condition)?)? else {
+                return Err(AppError::InvalidInput(format!(
+                    "Filter op 'prefix' does not apply to '{field}'"
+                )));
+            };
+            let escaped = prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            builder
+                .push(column)
+                .push(" LIKE ")
+                .push_bind(format!("{escaped}%"))
+                .push(" ESCAPE '\\'");
+        }
+        other => {
+            return Err(AppError::InvalidInput(format!(
+                "Unknown filter op '{other}'"
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn push_filter(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    filter: &TransferFilter,
+    depth: usize,
+    conditions: &mut usize,
+) -> Result<(), AppError> {
+    if depth > MAX_FILTER_DEPTH {
+        return Err(AppError::InvalidInput(format!(
+            "Filters may nest at most {MAX_FILTER_DEPTH} levels deep"
+        )));
+    }
+    let (children, joiner) = match filter {
+        TransferFilter::All { all } => (all, " AND "),
+        TransferFilter::Any { any } => (any, " OR "),
+        TransferFilter::Not { not } => {
+            builder.push("NOT (");
+            push_filter(builder, not, depth + 1, conditions)?;
+            builder.push(")");
+            return Ok(());
+        }
+        TransferFilter::Condition(condition) => {
+            *conditions += 1;
+            if *conditions > MAX_FILTER_CONDITIONS {
+                return Err(AppError::InvalidInput(format!(
+                    "Filters may contain at most {MAX_FILTER_CONDITIONS} conditions"
+                )));
+            }
+            return push_condition(builder, condition);
+        }
+    };
+    if children.is_empty() {
+        return Err(AppError::InvalidInput(
+            "'all' and 'any' need at least one filter".to_string(),
+        ));
+    }
+    builder.push("(");
+    for (i, child) in children.iter().enumerate() {
+        if i > 0 {
+            builder.push(joiner);
+        }
+        push_filter(builder, child, depth + 1, conditions)?;
+    }
+    builder.push(")");
+    Ok(())
+}
+
+fn push_sort(builder: &mut QueryBuilder<'_, Sqlite>, sort: &[FilterSort]) -> Result<(), AppError> {
+    if sort.len() > MAX_SORT_KEYS {
+        return Err(AppError::InvalidInput(format!(
+            "At most {MAX_SORT_KEYS} sort keys are allowed"
+        )));
+    }
+    builder.push(" ORDER BY ");
+    if sort.is_empty() {
+        builder.push("timestamp DESC, ");
+    }
+    for key in sort {
+        let (column, _) = column(&key.field)?;
+        let direction = match key.direction.as_deref() {
+            None | Some("desc") => " DESC, ",
+            Some("asc") => " ASC, ",
+            Some(other) => {
+                return Err(AppError::InvalidInput(format!(
+                    "Sort direction must be 'asc' or 'desc', not '{other}'"
+                )))
+            }
+        };
+        builder.push(column).push(direction);
+    }
+    builder.push("id ASC");
+    Ok(())
+}
+
+/// Compiles `query` into a parameterized statement over the transfer index.
+fn compile(
+    query: &TransferFilterQuery,
+    limit: u32,
+    offset: u32,
+) -> Result<QueryBuilder<'static, Sqlite>, AppError> {
+    let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(SELECT_COLUMNS);
+    if let Some(filter) = &query.filter {
+        builder.push(" WHERE ");
+        push_filter(&mut builder, filter, 1, &mut 0)?;
+    }
+    push_sort(&mut builder, &query.sort)?;
+    builder
+        .push(" LIMIT ")
+        .push_bind(limit as i64)
+        .push(" OFFSET ")
+        .push_bind(offset as i64);
+    Ok(builder)
+}
+
+impl Database {
+    /// Indexed transfers matching a structured filter. Invalid filters are
+    /// rejected as `InvalidInput` before the database is touched.
+    pub async fn filter_indexed_transfers(
+        &self,
+        query: &TransferFilterQuery,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<IndexedTransfer>, AppError> {
+        let mut builder = compile(query, limit, offset)?;
+        let pool = self.sqlite()?;
+        let rows = builder
+            .build()
+            .fetch_all(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to query transfers: {e}")))?;
+        rows.iter().map(transfer_from_row).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{open_test_database, TransferKind};
+
+    fn query(value: serde_json::Value) -> TransferFilterQuery {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_compile_binds_values_and_rejects_unknown_fields() {
+        let q = query(serde_json::json!({
+            "filter": { "any": [
+                { "field": "address", "op": "eq", "value": "x' OR 1=1 --" },
+                { "not": { "field": "amount", "op": "in", "value": [1, 2] } }
+            ]},
+            "sort": [{ "field": "amount", "direction": "asc" }]
+        }));
+        let sql = compile(&q, 10, 0).unwrap().into_sql();
+        assert!(sql.ends_with(
+            "WHERE (address = ? OR NOT (amount IN (?, ?))) ORDER BY amount ASC, id ASC LIMIT ? OFFSET ?"
+        ));
+        assert!(!sql.contains("OR 1=1"));
+
+        let bad = |value| compile(&query(value), 10, 0).is_err();
+        assert!(bad(serde_json::json!({
+            "filter": { "field": "raw; DROP TABLE indexed_transfers", "op": "eq", "value": "x" }
+        })));
+        assert!(bad(serde_json::json!({
+            "filter": { "field": "amount", "op": "eq", "value": "10" }
+        })));
+        assert!(bad(serde_json::json!({ "filter": { "all": [] } })));
+        assert!(bad(
+            serde_json::json!({ "sort": [{ "field": "amount", "direction": "up" }] })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_filter_matches_nested_conditions() {
+        let db = open_test_database().await;
+        let transfer = |id: &str, kind, amount, address: Option<&str>| IndexedTransfer {
+            id: id.to_string(),
+            kind,
+            asset_id: Some("aa".to_string()),
+            address: address.map(str::to_string),
+            amount: Some(amount),
+            anchor_txid: None,
+            outpoint: None,
+            block_height: None,
+            status: "completed".to_string(),
+            timestamp: amount as i64,
+            raw: serde_json::json!({}),
+            chain_status: None,
+            confirmations: None,
+            block_hash: None,
+            label: None,
+            tags: Vec::new(),
+        };
+        db.upsert_indexed_transfers(&[
+            transfer("send:1", TransferKind::Send, 500, None),
+            transfer("receive:1", TransferKind::Receive, 50, Some("taprt1a_b")),
+            transfer("receive:2", TransferKind::Receive, 700, Some("taprt1ab")),
+        ])
+        .await
+        .unwrap();
+
+        let q = query(serde_json::json!({
+            "filter": { "all": [
+                { "field": "asset_id", "op": "eq", "value": "AA" },
+                { "any": [
+                    { "field": "kind", "op": "eq", "value": "send" },
+                    { "field": "address", "op": "prefix", "value": "taprt1a_" }
+                ]}
+            ]}
+        }));
+        let ids: Vec<_> = db
+            .filter_indexed_transfers(&q, 10, 0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(ids, vec!["send:1", "receive:1"]);
+
+        let q = query(serde_json::json!({
+            "filter": { "field": "address", "op": "ne", "value": "taprt1ab" },
+            "sort": [{ "field": "amount", "direction": "asc" }]
+        }));
+        let rows = db.filter_indexed_transfers(&q, 1, 1).await.unwrap();
+        assert_eq!(rows[0].id, "send:1");
+    }
+}
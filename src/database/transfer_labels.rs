@@ -0,0 +1,118 @@
+use super::Database;
+use crate::error::AppError;
+
+/// Labels and tags clients attach to their sends, keyed by anchor
+/// transaction so they apply to every indexed row of the send. `tags` is a
+/// JSON array of strings.
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS transfer_labels (
+        anchor_txid TEXT PRIMARY KEY,
+        label TEXT,
+        tags TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+"#;
+
+impl Database {
+    /// Records the label and tags of the send anchored in `anchor_txid`,
+    /// replacing any recorded before.
+    pub async fn save_transfer_labels(
+        &self,
+        anchor_txid: &str,
+        label: Option<&str>,
+        tags: &[String],
+    ) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        let tags =
+            serde_json::to_string(tags).map_err(|e| AppError::SerializationError(e.to_string()))?;
+        sqlx::query(
+            r#"
+            INSERT INTO transfer_labels (anchor_txid, label, tags, created_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(anchor_txid) DO UPDATE SET
+                label = excluded.label,
+                tags = excluded.tags
+            "#,
+        )
+        .bind(anchor_txid)
+        .bind(label)
+        .bind(tags)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to save transfer labels: {e}")))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{open_test_database, IndexedTransfer, TransferKind, TransferQuery};
+
+    fn send(id: &str, anchor_txid: &str) -> IndexedTransfer {
+        IndexedTransfer {
+            id: id.to_string(),
+            kind: TransferKind::Send,
+            asset_id: Some("aa".to_string()),
+            address: None,
+            amount: Some(10),
+            anchor_txid: Some(anchor_txid.to_string()),
+            outpoint: None,
+            block_height: None,
+            status: "broadcast".to_string(),
+            timestamp: 100,
+            raw: serde_json::json!({}),
+            chain_status: None,
+            confirmations: None,
+            block_hash: None,
+            label: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_labels_attach_to_sends_and_filter_by_tag() {
+        let db = open_test_database().await;
+        db.upsert_indexed_transfers(&[send("send:t1:aa", "t1"), send("send:t2:aa", "t2")])
+            .await
+            .unwrap();
+        let tags = vec!["order-42".to_string(), "shop".to_string()];
+        db.save_transfer_labels("t1", Some("order 42"), &tags)
+            .await
+            .unwrap();
+        db.save_transfer_labels("t2", None, &["shop".to_string()])
+            .await
+            .unwrap();
+
+        let tagged = |tag: &str| TransferQuery {
+            tag: Some(tag.to_string()),
+            ..Default::default()
+        };
+        let rows = db
+            .query_indexed_transfers(&tagged("order-42"))
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].id, "send:t1:aa");
+        assert_eq!(rows[0].label.as_deref(), Some("order 42"));
+        assert_eq!(rows[0].tags, tags);
+
+        assert_eq!(
+            db.query_indexed_transfers(&tagged("shop"))
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(db
+            .query_indexed_transfers(&tagged("order"))
+            .await
+            .unwrap()
+            .is_empty());
+        let all = db
+            .query_indexed_transfers(&TransferQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 2);
+    }
+}
@@ -0,0 +1,898 @@
+use super::Database;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS indexed_transfers (
+        id TEXT PRIMARY KEY,
+        kind TEXT NOT NULL,
+        asset_id TEXT,
+        address TEXT,
+        amount INTEGER,
+        anchor_txid TEXT,
+        outpoint TEXT,
+        block_height INTEGER,
+        status TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        raw TEXT NOT NULL,
+        chain_status TEXT,
+        confirmations INTEGER,
+        block_hash TEXT,
+        first_seen INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_indexed_transfers_asset_id ON indexed_transfers(asset_id);
+    CREATE INDEX IF NOT EXISTS idx_indexed_transfers_address ON indexed_transfers(address);
+    CREATE INDEX IF NOT EXISTS idx_indexed_transfers_timestamp ON indexed_transfers(timestamp);
+    CREATE INDEX IF NOT EXISTS idx_indexed_transfers_anchor_txid ON indexed_transfers(anchor_txid);
+"#;
+
+pub(super) const SELECT_COLUMNS: &str = "SELECT id, kind, asset_id, address, amount, anchor_txid, \
+     outpoint, block_height, status, timestamp, raw, chain_status, confirmations, block_hash, \
+     (SELECT label FROM transfer_labels l WHERE l.anchor_txid = indexed_transfers.anchor_txid) \
+     AS label, \
+     (SELECT tags FROM transfer_labels l WHERE l.anchor_txid = indexed_transfers.anchor_txid) \
+     AS tags \
+     FROM indexed_transfers";
+
+/// `positioned` rows for [`POSITION_TOTALS`]: each transfer with its
+/// effective confirmation depth.
+pub(super) const POSITIONED_COLUMNS: &str = "kind, COALESCE(amount, 0) AS amount, \
+     COALESCE(confirmations, 0) AS confirmations, \
+     chain_status IS NOT NULL AND chain_status = 'reorged' AS reorged, \
+     MAX(COALESCE(confirmations, 0), \
+     CASE WHEN status IN ('transaction_confirmed', 'proof_received', 'completed') \
+     THEN 1 ELSE 0 END) AS depth";
+
+/// Sums of `positioned` rows making up an [`AssetPosition`]; binds the
+/// minimum confirmations twice.
+pub(super) const POSITION_TOTALS: &str = "\
+     COALESCE(SUM(CASE WHEN kind = 'mint' \
+         OR (kind = 'receive' AND NOT reorged AND depth >= ?) \
+         THEN amount END), 0) AS settled_in, \
+     COALESCE(SUM(CASE WHEN kind = 'receive' AND (reorged OR depth < ?) \
+         THEN amount END), 0) AS pending_in, \
+     COALESCE(SUM(CASE WHEN kind IN ('send', 'burn') THEN amount END), 0) AS outgoing, \
+     COALESCE(SUM(CASE WHEN kind IN ('send', 'burn') AND confirmations = 0 \
+         THEN amount END), 0) AS pending_out, \
+     COUNT(CASE WHEN kind IN ('send', 'burn') AND confirmations = 0 \
+         THEN 1 END) AS pending_operations";
+
+const DEFAULT_QUERY_LIMIT: u32 = 100;
+const MAX_QUERY_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferKind {
+    Send,
+    Receive,
+    Mint,
+    Burn,
+}
+
+impl TransferKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransferKind::Send => "send",
+            TransferKind::Receive => "receive",
+            TransferKind::Mint => "mint",
+            TransferKind::Burn => "burn",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "send" => Ok(TransferKind::Send),
+            "receive" => Ok(TransferKind::Receive),
+            "mint" => Ok(TransferKind::Mint),
+            "burn" => Ok(TransferKind::Burn),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown transfer kind in index: {other}"
+            ))),
+        }
+    }
+}
+
+/// Where a transfer's anchor transaction stands on chain, as tracked by the
+/// reconciler. Independent of tapd's own status, which stops changing once
+/// tapd considers the transfer done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainStatus {
+    Unconfirmed,
+    Confirmed,
+    /// The block that confirmed the anchor transaction was reorganized out.
+    Reorged,
+    /// The anchor transaction disappeared, typically replaced by a conflict.
+    Replaced,
+}
+
+impl ChainStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChainStatus::Unconfirmed => "unconfirmed",
+            ChainStatus::Confirmed => "confirmed",
+            ChainStatus::Reorged => "reorged",
+            ChainStatus::Replaced => "replaced",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "unconfirmed" => Ok(ChainStatus::Unconfirmed),
+            "confirmed" => Ok(ChainStatus::Confirmed),
+            "reorged" => Ok(ChainStatus::Reorged),
+            "replaced" => Ok(ChainStatus::Replaced),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown chain status in index: {other}"
+            ))),
+        }
+    }
+}
+
+/// Chain tracking fields written by the reconciler, never by the indexer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainState {
+    pub status: ChainStatus,
+    pub confirmations: u32,
+    pub block_hash: Option<String>,
+    pub block_height: Option<u32>,
+}
+
+/// A send, receive or mint normalized out of tapd's event streams or its
+/// historical listings. `id` is derived from on-chain identifiers so the live
+/// stream and a backfill of the same operation collapse into one row.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedTransfer {
+    pub id: String,
+    pub kind: TransferKind,
+    pub asset_id: Option<String>,
+    pub address: Option<String>,
+    pub amount: Option<u64>,
+    pub anchor_txid: Option<String>,
+    pub outpoint: Option<String>,
+    pub block_height: Option<u32>,
+    pub status: String,
+    pub timestamp: i64,
+    pub raw: serde_json::Value,
+    #[serde(default)]
+    pub chain_status: Option<ChainStatus>,
+    #[serde(default)]
+    pub confirmations: Option<u32>,
+    #[serde(default)]
+    pub block_hash: Option<String>,
+    /// Label the client gave the send, from `transfer_labels`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// Filters accepted by the transfer query endpoint. Time bounds are inclusive
+/// unix seconds.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct TransferQuery {
+    pub kind: Option<TransferKind>,
+    pub asset_id: Option<String>,
+    pub address: Option<String>,
+    pub status: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    /// Only transfers whose send was tagged with this tag.
+    pub tag: Option<String>,
+    /// Only receives to and sends from this sub-account.
+    pub account: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// Everything burned of one asset across all indexed burns. Replaced burns,
+/// whose anchor transaction never confirmed, are not counted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BurnTotal {
+    pub asset_id: String,
+    pub total_burned: u64,
+    pub burn_count: u64,
+}
+
+/// Recent sends of one asset, what the anomaly detector compares a new send
+/// against. Replaced sends are not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SendActivity {
+    /// Sent since the start of the current window.
+    pub recent: u64,
+    /// Sent in the baseline before the current window.
+    pub baseline: u64,
+    /// Whether the destination address was sent to before.
+    pub destination_seen: bool,
+}
+
+/// The gateway's indexed view of one asset: what it has seen arrive, leave
+/// and still waiting on chain. Replaced transfers are not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct AssetPosition {
+    /// Mints, plus receives meeting the confirmation threshold.
+    pub settled_in: u64,
+    /// Receives still below the confirmation threshold or reorged.
+    pub pending_in: u64,
+    /// Every send and burn, confirmed or not.
+    pub outgoing: u64,
+    /// Sends and burns whose anchor transaction has no confirmation yet.
+    pub pending_out: u64,
+    pub pending_operations: u64,
+}
+
+impl AssetPosition {
+    /// What the index says can be spent right now.
+    pub fn available(&self) -> u64 {
+        self.settled_in.saturating_sub(self.outgoing)
+    }
+
+    pub(super) fn from_row(row: &SqliteRow) -> Self {
+        AssetPosition {
+            settled_in: row.get::<i64, _>("settled_in") as u64,
+            pending_in: row.get::<i64, _>("pending_in") as u64,
+            outgoing: row.get::<i64, _>("outgoing") as u64,
+            pending_out: row.get::<i64, _>("pending_out") as u64,
+            pending_operations: row.get::<i64, _>("pending_operations") as u64,
+        }
+    }
+}
+
+impl Database {
+    /// Insert or refresh indexed transfers in a single transaction. A later
+    /// state for the same id overwrites status, height and the raw document but
+    /// keeps the original `first_seen`.
+    pub async fn upsert_indexed_transfers(
+        &self,
+        transfers: &[IndexedTransfer],
+    ) -> Result<(), AppError> {
+        if transfers.is_empty() {
+            return Ok(());
+        }
+        let pool = self.sqlite()?;
+        let now = chrono::Utc::now().timestamp();
+
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to begin transaction: {e}")))?;
+
+        for transfer in transfers {
+            let raw = serde_json::to_string(&transfer.raw)
+                .map_err(|e| AppError::SerializationError(e.to_string()))?;
+            sqlx::query(
+                r#"
+                INSERT INTO indexed_transfers (
+                    id, kind, asset_id, address, amount, anchor_txid, outpoint,
+                    block_height, status, timestamp, raw, first_seen, updated_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(id) DO UPDATE SET
+                    asset_id = COALESCE(excluded.asset_id, indexed_transfers.asset_id),
+                    address = COALESCE(excluded.address, indexed_transfers.address),
+                    amount = COALESCE(excluded.amount, indexed_transfers.amount),
+                    anchor_txid = COALESCE(excluded.anchor_txid, indexed_transfers.anchor_txid),
+                    outpoint = COALESCE(excluded.outpoint, indexed_transfers.outpoint),
+                    block_height = COALESCE(excluded.block_height, indexed_transfers.block_height),
+                    status = excluded.status,
+                    raw = excluded.raw,
+                    updated_at = excluded.updated_at
+                "#,
+            )
+            .bind(&transfer.id)
+            .bind(transfer.kind.as_str())
+            .bind(&transfer.asset_id)
+            .bind(&transfer.address)
+            .bind(transfer.amount.map(|a| a as i64))
+            .bind(&transfer.anchor_txid)
+            .bind(&transfer.outpoint)
+            .bind(transfer.block_height.map(|h| h as i64))
+            .bind(&transfer.status)
+            .bind(transfer.timestamp)
+            .bind(raw)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                AppError::DatabaseError(format!("Failed to store indexed transfer: {e}"))
+            })?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to commit transaction: {e}")))?;
+        Ok(())
+    }
+
+    /// Query indexed transfers, newest first.
+    pub async fn query_indexed_transfers(
+        &self,
+        query: &TransferQuery,
+    ) -> Result<Vec<IndexedTransfer>, AppError> {
+        self.fetch_transfers(query, None).await
+    }
+
+    /// Receives that tapd may already report as complete but whose anchor
+    /// transaction has fewer than `min_confirmations` confirmations. tapd's
+    /// confirmed statuses count as one confirmation when the reconciler has
+    /// not recorded a depth yet. Replaced receives are excluded.
+    pub async fn pending_receives(
+        &self,
+        query: &TransferQuery,
+        min_confirmations: u32,
+    ) -> Result<Vec<IndexedTransfer>, AppError> {
+        let query = TransferQuery {
+            kind: Some(TransferKind::Receive),
+            ..query.clone()
+        };
+        self.fetch_transfers(&query, Some(min_confirmations)).await
+    }
+
+    async fn fetch_transfers(
+        &self,
+        query: &TransferQuery,
+        confirmations_below: Option<u32>,
+    ) -> Result<Vec<IndexedTransfer>, AppError> {
+        let pool = self.sqlite()?;
+
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(SELECT_COLUMNS);
+        builder.push(" WHERE 1 = 1");
+        if let Some(min) = confirmations_below {
+            builder.push(
+                " AND (chain_status IS NULL OR chain_status != 'replaced') \
+                 AND MAX(COALESCE(confirmations, 0), \
+                 CASE WHEN status IN ('transaction_confirmed', 'proof_received', 'completed') \
+                 THEN 1 ELSE 0 END) < ",
+            );
+            builder.push_bind(min as i64);
+        }
+        if let Some(kind) = query.kind {
+            builder.push(" AND kind = ").push_bind(kind.as_str());
+        }
+        if let Some(asset_id) = &query.asset_id {
+            builder.push(" AND asset_id = ").push_bind(asset_id.clone());
+        }
+        if let Some(address) = &query.address {
+            builder.push(" AND address = ").push_bind(address.clone());
+        }
+        if let Some(status) = &query.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(from) = query.from {
+            builder.push(" AND timestamp >= ").push_bind(from);
+        }
+        if let Some(to) = query.to {
+            builder.push(" AND timestamp <= ").push_bind(to);
+        }
+        if let Some(tag) = &query.tag {
+            builder
+                .push(
+                    " AND anchor_txid IN (SELECT anchor_txid FROM transfer_labels, \
+                     json_each(transfer_labels.tags) WHERE json_each.value = ",
+                )
+                .push_bind(tag.clone())
+                .push(")");
+        }
+        if let Some(account) = &query.account {
+            builder
+                .push(
+                    " AND ((kind = 'receive' AND address IN \
+                     (SELECT address FROM sub_account_addresses WHERE account = ",
+                )
+                .push_bind(account.clone())
+                .push(
+                    ")) OR (kind = 'send' AND anchor_txid IN \
+                     (SELECT anchor_txid FROM sub_account_sends WHERE account = ",
+                )
+                .push_bind(account.clone())
+                .push(")))");
+        }
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_QUERY_LIMIT)
+            .min(MAX_QUERY_LIMIT);
+        builder
+            .push(" ORDER BY timestamp DESC, id ASC LIMIT ")
+            .push_bind(limit as i64)
+            .push(" OFFSET ")
+            .push_bind(query.offset.unwrap_or(0) as i64);
+
+        let rows = builder
+            .build()
+            .fetch_all(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to query transfers: {e}")))?;
+
+        rows.iter().map(transfer_from_row).collect()
+    }
+
+    /// Cumulative burned amount per asset, optionally for one asset and only
+    /// counting burns indexed at or before `to`.
+    pub async fn burn_totals(
+        &self,
+        asset_id: Option<&str>,
+        to: Option<i64>,
+    ) -> Result<Vec<BurnTotal>, AppError> {
+        let pool = self.sqlite()?;
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "SELECT asset_id, COALESCE(SUM(amount), 0) AS total_burned, COUNT(*) AS burn_count \
+             FROM indexed_transfers WHERE kind = 'burn' AND asset_id IS NOT NULL \
+             AND (chain_status IS NULL OR chain_status != 'replaced')",
+        );
+        if let Some(asset_id) = asset_id {
+            builder
+                .push(" AND asset_id = ")
+                .push_bind(asset_id.to_string());
+        }
+        if let Some(to) = to {
+            builder.push(" AND timestamp <= ").push_bind(to);
+        }
+        builder.push(" GROUP BY asset_id ORDER BY asset_id");
+
+        let rows = builder
+            .build()
+            .fetch_all(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to total burns: {e}")))?;
+        Ok(rows
+            .iter()
+            .map(|row| BurnTotal {
+                asset_id: row.get("asset_id"),
+                total_burned: row.get::<i64, _>("total_burned") as u64,
+                burn_count: row.get::<i64, _>("burn_count") as u64,
+            })
+            .collect())
+    }
+
+    /// Totals the index holds for one asset, counting a receive as settled
+    /// once it has `min_confirmations` the same way `pending_receives` does.
+    pub async fn asset_position(
+        &self,
+        asset_id: &str,
+        min_confirmations: u32,
+    ) -> Result<AssetPosition, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!(
+            "WITH positioned AS (SELECT {POSITIONED_COLUMNS} FROM indexed_transfers \
+             WHERE asset_id = ? AND (chain_status IS NULL OR chain_status != 'replaced')) \
+             SELECT {POSITION_TOTALS} FROM positioned"
+        ))
+        .bind(asset_id)
+        .bind(min_confirmations as i64)
+        .bind(min_confirmations as i64)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to total asset position: {e}")))?;
+        Ok(AssetPosition::from_row(&row))
+    }
+
+    /// Sends of `asset_id` since `window_start` and in the baseline from
+    /// `baseline_start` up to it, and whether `address` was sent to before.
+    /// The send `exclude_id`, if indexed already, is left out of all three.
+    pub async fn send_activity(
+        &self,
+        asset_id: &str,
+        address: Option<&str>,
+        baseline_start: i64,
+        window_start: i64,
+        exclude_id: Option<&str>,
+    ) -> Result<SendActivity, AppError> {
+        let pool = self.sqlite()?;
+        let exclude_id = exclude_id.unwrap_or_default();
+        let row = sqlx::query(
+            "SELECT \
+             COALESCE(SUM(CASE WHEN timestamp >= ? THEN amount END), 0) AS recent, \
+             COALESCE(SUM(CASE WHEN timestamp < ? THEN amount END), 0) AS baseline \
+             FROM indexed_transfers WHERE kind = 'send' AND asset_id = ? AND timestamp >= ? \
+             AND id != ? AND (chain_status IS NULL OR chain_status != 'replaced')",
+        )
+        .bind(window_start)
+        .bind(window_start)
+        .bind(asset_id)
+        .bind(baseline_start)
+        .bind(exclude_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to total sends: {e}")))?;
+        let destination_seen = match address {
+            Some(address) => sqlx::query(
+                "SELECT 1 FROM indexed_transfers WHERE kind = 'send' AND address = ? \
+                 AND id != ? LIMIT 1",
+            )
+            .bind(address)
+            .bind(exclude_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to look up destination: {e}")))?
+            .is_some(),
+            None => true,
+        };
+        Ok(SendActivity {
+            recent: row.get::<i64, _>("recent") as u64,
+            baseline: row.get::<i64, _>("baseline") as u64,
+            destination_seen,
+        })
+    }
+
+    /// Indexed burns anchored in `anchor_txid`, one per burned asset.
+    pub async fn burns_by_txid(&self, anchor_txid: &str) -> Result<Vec<IndexedTransfer>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "{SELECT_COLUMNS} WHERE kind = 'burn' AND anchor_txid = ? ORDER BY asset_id"
+        ))
+        .bind(anchor_txid)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query burns: {e}")))?;
+        rows.iter().map(transfer_from_row).collect()
+    }
+
+    /// Transfers whose anchor transaction still needs watching: anything with
+    /// an anchor txid that has not reached `finality_depth` confirmations and
+    /// has not been replaced.
+    pub async fn unsettled_transfers(
+        &self,
+        finality_depth: u32,
+    ) -> Result<Vec<IndexedTransfer>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(&format!(
+            "{SELECT_COLUMNS} WHERE anchor_txid IS NOT NULL \
+             AND (chain_status IS NULL OR chain_status != 'replaced') \
+             AND (confirmations IS NULL OR confirmations < ?) \
+             ORDER BY timestamp ASC"
+        ))
+        .bind(finality_depth as i64)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query transfers: {e}")))?;
+        rows.iter().map(transfer_from_row).collect()
+    }
+
+    pub async fn update_chain_state(&self, id: &str, state: &ChainState) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            UPDATE indexed_transfers
+            SET chain_status = ?, confirmations = ?, block_hash = ?,
+                block_height = COALESCE(?, block_height), updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(state.status.as_str())
+        .bind(state.confirmations as i64)
+        .bind(&state.block_hash)
+        .bind(state.block_height.map(|h| h as i64))
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to update chain state: {e}")))?;
+        Ok(())
+    }
+}
+
+pub(super) fn transfer_from_row(row: &SqliteRow) -> Result<IndexedTransfer, AppError> {
+    let kind: String = row.get("kind");
+    let raw: String = row.get("raw");
+    let chain_status: Option<String> = row.get("chain_status");
+    let tags: Option<String> = row.get("tags");
+    Ok(IndexedTransfer {
+        id: row.get("id"),
+        kind: TransferKind::parse(&kind)?,
+        asset_id: row.get("asset_id"),
+        address: row.get("address"),
+        amount: row.get::<Option<i64>, _>("amount").map(|a| a as u64),
+        anchor_txid: row.get("anchor_txid"),
+        outpoint: row.get("outpoint"),
+        block_height: row.get::<Option<i64>, _>("block_height").map(|h| h as u32),
+        status: row.get("status"),
+        timestamp: row.get("timestamp"),
+        raw: serde_json::from_str(&raw).map_err(|e| AppError::SerializationError(e.to_string()))?,
+        chain_status: chain_status
+            .as_deref()
+            .map(ChainStatus::parse)
+            .transpose()?,
+        confirmations: row.get::<Option<i64>, _>("confirmations").map(|c| c as u32),
+        block_hash: row.get("block_hash"),
+        label: row.get("label"),
+        tags: tags
+            .map(|t| serde_json::from_str(&t))
+            .transpose()
+            .map_err(|e| AppError::SerializationError(e.to_string()))?
+            .unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn transfer(id: &str, kind: TransferKind, asset_id: &str, timestamp: i64) -> IndexedTransfer {
+        IndexedTransfer {
+            id: id.to_string(),
+            kind,
+            asset_id: Some(asset_id.to_string()),
+            address: None,
+            amount: Some(10),
+            anchor_txid: None,
+            outpoint: None,
+            block_height: None,
+            status: "pending".to_string(),
+            timestamp,
+            raw: serde_json::json!({}),
+            chain_status: None,
+            confirmations: None,
+            block_hash: None,
+            label: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upsert_refreshes_status_without_duplicating() {
+        let db = open_test_database().await;
+        let mut t = transfer("send:abc:1", TransferKind::Send, "aa", 100);
+        db.upsert_indexed_transfers(&[t.clone()]).await.unwrap();
+
+        t.status = "completed".to_string();
+        t.block_height = Some(42);
+        db.upsert_indexed_transfers(&[t]).await.unwrap();
+
+        let rows = db
+            .query_indexed_transfers(&TransferQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].status, "completed");
+        assert_eq!(rows[0].block_height, Some(42));
+    }
+
+    #[tokio::test]
+    async fn test_query_filters_by_asset_kind_and_time() {
+        let db = open_test_database().await;
+        db.upsert_indexed_transfers(&[
+            transfer("a", TransferKind::Send, "aa", 100),
+            transfer("b", TransferKind::Receive, "aa", 200),
+            transfer("c", TransferKind::Receive, "bb", 300),
+        ])
+        .await
+        .unwrap();
+
+        let by_asset = db
+            .query_indexed_transfers(&TransferQuery {
+                asset_id: Some("aa".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(by_asset.len(), 2);
+        assert_eq!(by_asset[0].id, "b", "newest first");
+
+        let by_kind_and_time = db
+            .query_indexed_transfers(&TransferQuery {
+                kind: Some(TransferKind::Receive),
+                from: Some(250),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(by_kind_and_time.len(), 1);
+        assert_eq!(by_kind_and_time[0].id, "c");
+    }
+
+    #[tokio::test]
+    async fn test_pending_receives_below_threshold() {
+        let db = open_test_database().await;
+        let mut detected = transfer("r1", TransferKind::Receive, "aa", 100);
+        detected.status = "transaction_detected".to_string();
+        let mut completed = transfer("r2", TransferKind::Receive, "aa", 200);
+        completed.status = "completed".to_string();
+        let send = transfer("s1", TransferKind::Send, "aa", 300);
+        db.upsert_indexed_transfers(&[detected, completed, send])
+            .await
+            .unwrap();
+
+        let pending = db
+            .pending_receives(&TransferQuery::default(), 1)
+            .await
+            .unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, "r1");
+
+        let pending = db
+            .pending_receives(&TransferQuery::default(), 3)
+            .await
+            .unwrap();
+        assert_eq!(pending.len(), 2);
+
+        let state = ChainState {
+            status: ChainStatus::Confirmed,
+            confirmations: 3,
+            block_hash: Some("00ff".to_string()),
+            block_height: Some(10),
+        };
+        db.update_chain_state("r2", &state).await.unwrap();
+        let pending = db
+            .pending_receives(&TransferQuery::default(), 3)
+            .await
+            .unwrap();
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_chain_state_survives_indexer_upserts() {
+        let db = open_test_database().await;
+        let mut t = transfer("send:abc:1", TransferKind::Send, "aa", 100);
+        t.anchor_txid = Some("abc".to_string());
+        db.upsert_indexed_transfers(&[t.clone()]).await.unwrap();
+        assert_eq!(db.unsettled_transfers(6).await.unwrap().len(), 1);
+
+        let state = ChainState {
+            status: ChainStatus::Confirmed,
+            confirmations: 6,
+            block_hash: Some("00ff".to_string()),
+            block_height: Some(101),
+        };
+        db.update_chain_state(&t.id, &state).await.unwrap();
+        t.status = "completed".to_string();
+        db.upsert_indexed_transfers(&[t]).await.unwrap();
+
+        let rows = db
+            .query_indexed_transfers(&TransferQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(rows[0].chain_status, Some(ChainStatus::Confirmed));
+        assert_eq!(rows[0].confirmations, Some(6));
+        assert_eq!(rows[0].block_height, Some(101));
+        assert!(db.unsettled_transfers(6).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_burn_totals_skip_replaced_and_later_burns() {
+        let db = open_test_database().await;
+        db.upsert_indexed_transfers(&[
+            transfer("burn:t1:aa", TransferKind::Burn, "aa", 100),
+            transfer("burn:t2:aa", TransferKind::Burn, "aa", 200),
+            transfer("burn:t3:aa", TransferKind::Burn, "aa", 300),
+            transfer("burn:t4:bb", TransferKind::Burn, "bb", 100),
+            transfer("send:t5:aa", TransferKind::Send, "aa", 100),
+        ])
+        .await
+        .unwrap();
+        let replaced = ChainState {
+            status: ChainStatus::Replaced,
+            confirmations: 0,
+            block_hash: None,
+            block_height: None,
+        };
+        db.update_chain_state("burn:t2:aa", &replaced)
+            .await
+            .unwrap();
+
+        let totals = db.burn_totals(None, None).await.unwrap();
+        assert_eq!(
+            totals,
+            vec![
+                BurnTotal {
+                    asset_id: "aa".to_string(),
+                    total_burned: 20,
+                    burn_count: 2,
+                },
+                BurnTotal {
+                    asset_id: "bb".to_string(),
+                    total_burned: 10,
+                    burn_count: 1,
+                },
+            ]
+        );
+
+        let totals = db.burn_totals(Some("aa"), Some(250)).await.unwrap();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].total_burned, 10);
+    }
+
+    #[tokio::test]
+    async fn test_asset_position_applies_confirmation_threshold() {
+        let db = open_test_database().await;
+        let mut settled = transfer("receive:t1:aa", TransferKind::Receive, "aa", 100);
+        settled.amount = Some(100);
+        settled.status = "completed".to_string();
+        let mut shallow = transfer("receive:t2:aa", TransferKind::Receive, "aa", 100);
+        shallow.amount = Some(40);
+        shallow.status = "completed".to_string();
+        let mut mint = transfer("mint:t3:aa", TransferKind::Mint, "aa", 100);
+        mint.amount = Some(50);
+        let send = transfer("send:t4:aa", TransferKind::Send, "aa", 200);
+        let burn = transfer("burn:t5:aa", TransferKind::Burn, "aa", 200);
+        let replaced = transfer("send:t6:aa", TransferKind::Send, "aa", 200);
+        db.upsert_indexed_transfers(&[settled, shallow, mint, send, burn, replaced])
+            .await
+            .unwrap();
+        let confirmed = |confirmations| ChainState {
+            status: ChainStatus::Confirmed,
+            confirmations,
+            block_hash: None,
+            block_height: None,
+        };
+        db.update_chain_state("receive:t1:aa", &confirmed(3))
+            .await
+            .unwrap();
+        db.update_chain_state("receive:t2:aa", &confirmed(1))
+            .await
+            .unwrap();
+        db.update_chain_state("burn:t5:aa", &confirmed(1))
+            .await
+            .unwrap();
+        db.update_chain_state(
+            "send:t6:aa",
+            &ChainState {
+                status: ChainStatus::Replaced,
+                ..confirmed(0)
+            },
+        )
+        .await
+        .unwrap();
+
+        let position = db.asset_position("aa", 3).await.unwrap();
+        assert_eq!(
+            position,
+            AssetPosition {
+                settled_in: 150,
+                pending_in: 40,
+                outgoing: 20,
+                pending_out: 10,
+                pending_operations: 1,
+            }
+        );
+        assert_eq!(position.available(), 130);
+        assert_eq!(
+            db.asset_position("bb", 1).await.unwrap(),
+            AssetPosition::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_activity_splits_window_and_baseline() {
+        let db = open_test_database().await;
+        let mut old = transfer("send:t1:aa", TransferKind::Send, "aa", 100);
+        old.address = Some("taprt1known".to_string());
+        let recent = transfer("send:t2:aa", TransferKind::Send, "aa", 5000);
+        let other = transfer("send:t3:bb", TransferKind::Send, "bb", 5000);
+        let receive = transfer("receive:aa:0", TransferKind::Receive, "aa", 5000);
+        db.upsert_indexed_transfers(&[old, recent, other, receive])
+            .await
+            .unwrap();
+
+        let activity = db
+            .send_activity("aa", Some("taprt1known"), 0, 1000, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            activity,
+            SendActivity {
+                recent: 10,
+                baseline: 10,
+                destination_seen: true,
+            }
+        );
+        // The send being assessed does not count as its own history
+        let activity = db
+            .send_activity("aa", Some("taprt1known"), 0, 1000, Some("send:t1:aa"))
+            .await
+            .unwrap();
+        assert_eq!(activity.baseline, 0);
+        assert!(!activity.destination_seen);
+        assert!(
+            !db.send_activity("aa", Some("taprt1new"), 0, 1000, None)
+                .await
+                .unwrap()
+                .destination_seen
+        );
+    }
+}
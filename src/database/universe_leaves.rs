@@ -0,0 +1,179 @@
+use super::Database;
+use crate::error::AppError;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS universe_leaves (
+        asset_id TEXT NOT NULL,
+        script_key TEXT NOT NULL,
+        outpoint TEXT NOT NULL,
+        proof_type TEXT NOT NULL,
+        group_key TEXT,
+        amount INTEGER NOT NULL,
+        proof TEXT,
+        indexed_at INTEGER NOT NULL,
+        PRIMARY KEY (asset_id, script_key, outpoint, proof_type)
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_universe_leaves_group_key ON universe_leaves(group_key);
+"#;
+
+const DEFAULT_QUERY_LIMIT: u32 = 100;
+const MAX_QUERY_LIMIT: u32 = 1000;
+
+/// A universe leaf copied into the index by a backfill. Keys are lowercase
+/// hex; `proof` is the leaf's proof as tapd returned it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UniverseLeaf {
+    pub asset_id: String,
+    pub script_key: String,
+    pub outpoint: String,
+    /// `issuance` or `transfer`.
+    pub proof_type: String,
+    pub group_key: Option<String>,
+    pub amount: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof: Option<String>,
+    pub indexed_at: i64,
+}
+
+impl Database {
+    /// Inserts or refreshes leaves in one transaction.
+    pub async fn upsert_universe_leaves(&self, leaves: &[UniverseLeaf]) -> Result<(), AppError> {
+        if leaves.is_empty() {
+            return Ok(());
+        }
+        let pool = self.sqlite()?;
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to begin transaction: {e}")))?;
+        for leaf in leaves {
+            sqlx::query(
+                r#"
+                INSERT INTO universe_leaves (
+                    asset_id, script_key, outpoint, proof_type, group_key, amount, proof,
+                    indexed_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(asset_id, script_key, outpoint, proof_type) DO UPDATE SET
+                    group_key = excluded.group_key,
+                    amount = excluded.amount,
+                    proof = excluded.proof,
+                    indexed_at = excluded.indexed_at
+                "#,
+            )
+            .bind(&leaf.asset_id)
+            .bind(&leaf.script_key)
+            .bind(&leaf.outpoint)
+            .bind(&leaf.proof_type)
+            .bind(&leaf.group_key)
+            .bind(leaf.amount as i64)
+            .bind(&leaf.proof)
+            .bind(leaf.indexed_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to store universe leaf: {e}")))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to commit transaction: {e}")))?;
+        Ok(())
+    }
+
+    /// Indexed leaves of an asset or group, without their proofs unless
+    /// `with_proofs` is set.
+    pub async fn query_universe_leaves(
+        &self,
+        asset_id: Option<&str>,
+        group_key: Option<&str>,
+        with_proofs: bool,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> Result<Vec<UniverseLeaf>, AppError> {
+        let pool = self.sqlite()?;
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "SELECT asset_id, script_key, outpoint, proof_type, group_key, amount, indexed_at",
+        );
+        if with_proofs {
+            builder.push(", proof");
+        }
+        builder.push(" FROM universe_leaves WHERE 1 = 1");
+        if let Some(asset_id) = asset_id {
+            builder
+                .push(" AND asset_id = ")
+                .push_bind(asset_id.to_string());
+        }
+        if let Some(group_key) = group_key {
+            builder
+                .push(" AND group_key = ")
+                .push_bind(group_key.to_string());
+        }
+        builder
+            .push(" ORDER BY asset_id, outpoint, script_key, proof_type LIMIT ")
+            .push_bind(limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as i64)
+            .push(" OFFSET ")
+            .push_bind(offset.unwrap_or(0) as i64);
+        let rows = builder.build().fetch_all(pool).await.map_err(|e| {
+            AppError::DatabaseError(format!("Failed to query universe leaves: {e}"))
+        })?;
+        Ok(rows
+            .iter()
+            .map(|row| leaf_from_row(row, with_proofs))
+            .collect())
+    }
+}
+
+fn leaf_from_row(row: &SqliteRow, with_proof: bool) -> UniverseLeaf {
+    UniverseLeaf {
+        asset_id: row.get("asset_id"),
+        script_key: row.get("script_key"),
+        outpoint: row.get("outpoint"),
+        proof_type: row.get("proof_type"),
+        group_key: row.get("group_key"),
+        amount: row.get::<i64, _>("amount") as u64,
+        proof: with_proof.then(|| row.get("proof")).flatten(),
+        indexed_at: row.get("indexed_at"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_leaves_upsert_and_filter_by_group() {
+        let db = open_test_database().await;
+        let leaf = |asset_id: &str, amount| UniverseLeaf {
+            asset_id: asset_id.to_string(),
+            script_key: "02aa".to_string(),
+            outpoint: "ff:0".to_string(),
+            proof_type: "issuance".to_string(),
+            group_key: Some("gg".to_string()),
+            amount,
+            proof: Some("AAEC".to_string()),
+            indexed_at: 1,
+        };
+        db.upsert_universe_leaves(&[leaf("a1", 10), leaf("a2", 20)])
+            .await
+            .unwrap();
+        db.upsert_universe_leaves(&[leaf("a1", 15)]).await.unwrap();
+
+        let leaves = db
+            .query_universe_leaves(None, Some("gg"), false, None, None)
+            .await
+            .unwrap();
+        assert_eq!(leaves.len(), 2);
+        assert_eq!(leaves[0].amount, 15);
+        assert!(leaves[0].proof.is_none());
+
+        let leaves = db
+            .query_universe_leaves(Some("a2"), None, true, None, None)
+            .await
+            .unwrap();
+        assert_eq!(leaves[0].proof.as_deref(), Some("AAEC"));
+    }
+}
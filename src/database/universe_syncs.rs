@@ -0,0 +1,446 @@
+use super::Database;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS universe_syncs (
+        id TEXT PRIMARY KEY,
+        universe_host TEXT NOT NULL,
+        sync_mode TEXT NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS universe_sync_targets (
+        sync_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        target TEXT NOT NULL,
+        status TEXT NOT NULL,
+        synced_universes TEXT,
+        error TEXT,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (sync_id, position)
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_universe_syncs_status ON universe_syncs(status);
+"#;
+
+const DEFAULT_LIST_LIMIT: u32 = 100;
+const MAX_LIST_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UniverseSyncStatus {
+    /// A runner is working through the targets.
+    Running,
+    /// Stopped partway; the remaining targets can be resumed.
+    Partial,
+    Completed,
+    /// Stopped partway and out of automatic retries. Can still be resumed
+    /// by hand.
+    Failed,
+}
+
+impl UniverseSyncStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UniverseSyncStatus::Running => "running",
+            UniverseSyncStatus::Partial => "partial",
+            UniverseSyncStatus::Completed => "completed",
+            UniverseSyncStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "running" => Ok(UniverseSyncStatus::Running),
+            "partial" => Ok(UniverseSyncStatus::Partial),
+            "completed" => Ok(UniverseSyncStatus::Completed),
+            "failed" => Ok(UniverseSyncStatus::Failed),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown universe sync status: {other}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncTargetStatus {
+    Pending,
+    Synced,
+    Failed,
+}
+
+impl SyncTargetStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncTargetStatus::Pending => "pending",
+            SyncTargetStatus::Synced => "synced",
+            SyncTargetStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "pending" => Ok(SyncTargetStatus::Pending),
+            "synced" => Ok(SyncTargetStatus::Synced),
+            "failed" => Ok(SyncTargetStatus::Failed),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown sync target status: {other}"
+            ))),
+        }
+    }
+}
+
+/// One entry of the request's `sync_targets`. A sync without targets is
+/// stored as a single `null` target standing for "everything".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UniverseSyncTarget {
+    pub position: u32,
+    pub target: Value,
+    pub status: SyncTargetStatus,
+    /// tapd's `synced_universes` for this target once it is synced.
+    pub synced_universes: Option<Value>,
+    pub error: Option<String>,
+    pub updated_at: i64,
+}
+
+/// A `universe/sync` call tracked target by target, so an interrupted sync
+/// can continue where it stopped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UniverseSync {
+    pub id: String,
+    pub universe_host: String,
+    pub sync_mode: String,
+    pub status: UniverseSyncStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub targets: Vec<UniverseSyncTarget>,
+}
+
+const SELECT_COLUMNS: &str = "SELECT id, universe_host, sync_mode, status, attempts, \
+     last_error, created_at, updated_at FROM universe_syncs";
+
+impl Database {
+    /// Stores a sync and its targets in one transaction.
+    pub async fn insert_universe_sync(&self, sync: &UniverseSync) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to begin transaction: {e}")))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO universe_syncs (
+                id, universe_host, sync_mode, status, attempts, last_error, created_at, updated_at
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&sync.id)
+        .bind(&sync.universe_host)
+        .bind(&sync.sync_mode)
+        .bind(sync.status.as_str())
+        .bind(sync.attempts as i64)
+        .bind(&sync.last_error)
+        .bind(sync.created_at)
+        .bind(sync.updated_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store universe sync: {e}")))?;
+
+        for target in &sync.targets {
+            sqlx::query(
+                r#"
+                INSERT INTO universe_sync_targets (
+                    sync_id, position, target, status, synced_universes, error, updated_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&sync.id)
+            .bind(target.position as i64)
+            .bind(target.target.to_string())
+            .bind(target.status.as_str())
+            .bind(target.synced_universes.as_ref().map(Value::to_string))
+            .bind(&target.error)
+            .bind(target.updated_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                AppError::DatabaseError(format!("Failed to store universe sync target: {e}"))
+            })?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to commit transaction: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn get_universe_sync(&self, id: &str) -> Result<Option<UniverseSync>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(&format!("{SELECT_COLUMNS} WHERE id = ?"))
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to fetch universe sync: {e}")))?;
+        match row {
+            Some(row) => Ok(Some(
+                self.with_targets(universe_sync_from_row(&row)?).await?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Lists syncs, newest first, optionally filtered by status.
+    pub async fn list_universe_syncs(
+        &self,
+        status: Option<UniverseSyncStatus>,
+        limit: Option<u32>,
+    ) -> Result<Vec<UniverseSync>, AppError> {
+        let pool = self.sqlite()?;
+        let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT);
+        let rows = sqlx::query(&format!(
+            "{SELECT_COLUMNS} WHERE (? IS NULL OR status = ?) \
+             ORDER BY created_at DESC, id ASC LIMIT ?"
+        ))
+        .bind(status.map(|s| s.as_str()))
+        .bind(status.map(|s| s.as_str()))
+        .bind(limit as i64)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to list universe syncs: {e}")))?;
+
+        let mut syncs = Vec::with_capacity(rows.len());
+        for row in &rows {
+            syncs.push(self.with_targets(universe_sync_from_row(row)?).await?);
+        }
+        Ok(syncs)
+    }
+
+    /// Partial syncs the background retry may pick up.
+    pub async fn resumable_universe_syncs(
+        &self,
+        max_attempts: u32,
+    ) -> Result<Vec<String>, AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query_scalar::<_, String>(
+            "SELECT id FROM universe_syncs WHERE status = 'partial' AND attempts < ? \
+             ORDER BY updated_at ASC",
+        )
+        .bind(max_attempts as i64)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to list universe syncs: {e}")))
+    }
+
+    /// Marks a partial or failed sync as running and counts the attempt.
+    /// Returns false when the sync is not resumable (already running or
+    /// completed), so two runners never work on the same sync.
+    pub async fn claim_universe_sync(&self, id: &str) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            "UPDATE universe_syncs SET status = 'running', attempts = attempts + 1, \
+             updated_at = ? WHERE id = ? AND status IN ('partial', 'failed')",
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to claim universe sync: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Syncs left running by a gateway that stopped mid-sync become partial
+    /// again. Returns how many were reset.
+    pub async fn reset_interrupted_universe_syncs(&self) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let result = sqlx::query(
+            "UPDATE universe_syncs SET status = 'partial', updated_at = ? WHERE status = 'running'",
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to reset universe syncs: {e}")))?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn update_universe_sync_status(
+        &self,
+        id: &str,
+        status: UniverseSyncStatus,
+        last_error: Option<&str>,
+    ) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            "UPDATE universe_syncs SET status = ?, last_error = ?, updated_at = ? WHERE id = ?",
+        )
+        .bind(status.as_str())
+        .bind(last_error)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to update universe sync: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn update_universe_sync_target(
+        &self,
+        sync_id: &str,
+        position: u32,
+        status: SyncTargetStatus,
+        synced_universes: Option<&Value>,
+        error: Option<&str>,
+    ) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            "UPDATE universe_sync_targets SET status = ?, synced_universes = ?, error = ?, \
+             updated_at = ? WHERE sync_id = ? AND position = ?",
+        )
+        .bind(status.as_str())
+        .bind(synced_universes.map(Value::to_string))
+        .bind(error)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(sync_id)
+        .bind(position as i64)
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            AppError::DatabaseError(format!("Failed to update universe sync target: {e}"))
+        })?;
+        Ok(())
+    }
+
+    async fn with_targets(&self, mut sync: UniverseSync) -> Result<UniverseSync, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            "SELECT position, target, status, synced_universes, error, updated_at \
+             FROM universe_sync_targets WHERE sync_id = ? ORDER BY position ASC",
+        )
+        .bind(&sync.id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            AppError::DatabaseError(format!("Failed to fetch universe sync targets: {e}"))
+        })?;
+        sync.targets = rows
+            .iter()
+            .map(sync_target_from_row)
+            .collect::<Result<_, _>>()?;
+        Ok(sync)
+    }
+}
+
+fn universe_sync_from_row(row: &SqliteRow) -> Result<UniverseSync, AppError> {
+    let status: String = row.get("status");
+    Ok(UniverseSync {
+        id: row.get("id"),
+        universe_host: row.get("universe_host"),
+        sync_mode: row.get("sync_mode"),
+        status: UniverseSyncStatus::parse(&status)?,
+        attempts: row.get::<i64, _>("attempts") as u32,
+        last_error: row.get("last_error"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        targets: Vec::new(),
+    })
+}
+
+fn sync_target_from_row(row: &SqliteRow) -> Result<UniverseSyncTarget, AppError> {
+    let status: String = row.get("status");
+    let target: String = row.get("target");
+    let synced: Option<String> = row.get("synced_universes");
+    Ok(UniverseSyncTarget {
+        position: row.get::<i64, _>("position") as u32,
+        target: serde_json::from_str(&target)
+            .map_err(|e| AppError::SerializationError(e.to_string()))?,
+        status: SyncTargetStatus::parse(&status)?,
+        synced_universes: synced
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()
+            .map_err(|e| AppError::SerializationError(e.to_string()))?,
+        error: row.get("error"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn sync(id: &str, targets: usize) -> UniverseSync {
+        UniverseSync {
+            id: id.to_string(),
+            universe_host: "universe.example:10029".to_string(),
+            sync_mode: "SYNC_ISSUANCE_ONLY".to_string(),
+            status: UniverseSyncStatus::Partial,
+            attempts: 0,
+            last_error: None,
+            created_at: 1,
+            updated_at: 1,
+            targets: (0..targets)
+                .map(|i| UniverseSyncTarget {
+                    position: i as u32,
+                    target: serde_json::json!({ "id": { "asset_id_str": format!("{i:064}") } }),
+                    status: SyncTargetStatus::Pending,
+                    synced_universes: None,
+                    error: None,
+                    updated_at: 1,
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_and_target_progress() {
+        let db = open_test_database().await;
+        db.insert_universe_sync(&sync("a", 3)).await.unwrap();
+        db.update_universe_sync_target(
+            "a",
+            1,
+            SyncTargetStatus::Synced,
+            Some(&serde_json::json!([{ "new_asset_root": {} }])),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let stored = db.get_universe_sync("a").await.unwrap().unwrap();
+        assert_eq!(stored.targets.len(), 3);
+        assert_eq!(stored.targets[1].status, SyncTargetStatus::Synced);
+        assert!(stored.targets[1].synced_universes.is_some());
+        assert_eq!(stored.targets[2].status, SyncTargetStatus::Pending);
+        assert!(db.get_universe_sync("missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_claim_is_exclusive_and_counts_attempts() {
+        let db = open_test_database().await;
+        db.insert_universe_sync(&sync("a", 1)).await.unwrap();
+
+        assert_eq!(db.resumable_universe_syncs(1).await.unwrap(), vec!["a"]);
+        assert!(db.claim_universe_sync("a").await.unwrap());
+        assert!(!db.claim_universe_sync("a").await.unwrap());
+
+        // A restart leaves the sync running; it becomes resumable again
+        assert_eq!(db.reset_interrupted_universe_syncs().await.unwrap(), 1);
+        let stored = db.get_universe_sync("a").await.unwrap().unwrap();
+        assert_eq!(stored.status, UniverseSyncStatus::Partial);
+        assert_eq!(stored.attempts, 1);
+        assert!(db.resumable_universe_syncs(1).await.unwrap().is_empty());
+    }
+}
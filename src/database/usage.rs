@@ -0,0 +1,169 @@
+use super::Database;
+use crate::error::AppError;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS api_usage (
+        key_id TEXT NOT NULL,
+        day TEXT NOT NULL,
+        label TEXT NOT NULL,
+        requests INTEGER NOT NULL DEFAULT 0,
+        errors INTEGER NOT NULL DEFAULT 0,
+        request_bytes INTEGER NOT NULL DEFAULT 0,
+        response_bytes INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (key_id, day)
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_api_usage_day ON api_usage(day);
+"#;
+
+/// Request counts and bytes of one API key on one UTC day (`YYYY-MM-DD`).
+/// `key_id` identifies the key without revealing it; `label` says what kind
+/// of key it is (`primary`, `role:<role>`, `tenant:<name>`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UsageRecord {
+    pub key_id: String,
+    pub day: String,
+    pub label: String,
+    pub requests: u64,
+    /// Responses with a 4xx or 5xx status.
+    pub errors: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+impl UsageRecord {
+    /// Adds `other`'s counters to this record.
+    pub fn add(&mut self, other: &UsageRecord) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.request_bytes += other.request_bytes;
+        self.response_bytes += other.response_bytes;
+    }
+}
+
+fn usage_from_row(row: &SqliteRow) -> UsageRecord {
+    UsageRecord {
+        key_id: row.get("key_id"),
+        day: row.get("day"),
+        label: row.get("label"),
+        requests: row.get::<i64, _>("requests") as u64,
+        errors: row.get::<i64, _>("errors") as u64,
+        request_bytes: row.get::<i64, _>("request_bytes") as u64,
+        response_bytes: row.get::<i64, _>("response_bytes") as u64,
+    }
+}
+
+impl Database {
+    /// Adds the counters to the stored totals in one transaction.
+    pub async fn record_usage(&self, records: &[UsageRecord]) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to begin transaction: {e}")))?;
+        for record in records {
+            sqlx::query(
+                r#"
+                INSERT INTO api_usage (
+                    key_id, day, label, requests, errors, request_bytes, response_bytes
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(key_id, day) DO UPDATE SET
+                    label = excluded.label,
+                    requests = requests + excluded.requests,
+                    errors = errors + excluded.errors,
+                    request_bytes = request_bytes + excluded.request_bytes,
+                    response_bytes = response_bytes + excluded.response_bytes
+                "#,
+            )
+            .bind(&record.key_id)
+            .bind(&record.day)
+            .bind(&record.label)
+            .bind(record.requests as i64)
+            .bind(record.errors as i64)
+            .bind(record.request_bytes as i64)
+            .bind(record.response_bytes as i64)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to record usage: {e}")))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| AppError::DatabaseError(format!("Failed to commit transaction: {e}")))?;
+        Ok(())
+    }
+
+    /// Daily usage between `from` and `to` (inclusive days), oldest first,
+    /// for one key or all of them.
+    pub async fn query_usage(
+        &self,
+        key_id: Option<&str>,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<UsageRecord>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            r#"
+            SELECT key_id, day, label, requests, errors, request_bytes, response_bytes
+            FROM api_usage
+            WHERE day >= ? AND day <= ? AND (? IS NULL OR key_id = ?)
+            ORDER BY day, key_id
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .bind(key_id)
+        .bind(key_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query usage: {e}")))?;
+        Ok(rows.iter().map(usage_from_row).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    fn record(key_id: &str, day: &str, requests: u64) -> UsageRecord {
+        UsageRecord {
+            key_id: key_id.to_string(),
+            day: day.to_string(),
+            label: "primary".to_string(),
+            requests,
+            errors: 1,
+            request_bytes: 10,
+            response_bytes: 100,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usage_accumulates_per_key_and_day() {
+        let db = open_test_database().await;
+        db.record_usage(&[record("a", "2026-10-01", 2), record("b", "2026-10-01", 1)])
+            .await
+            .unwrap();
+        db.record_usage(&[record("a", "2026-10-01", 3), record("a", "2026-10-02", 1)])
+            .await
+            .unwrap();
+
+        let usage = db
+            .query_usage(Some("a"), "2026-10-01", "2026-10-01")
+            .await
+            .unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].requests, 5);
+        assert_eq!(usage[0].errors, 2);
+        assert_eq!(usage[0].response_bytes, 200);
+
+        let all = db
+            .query_usage(None, "2026-10-01", "2026-10-31")
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 3);
+    }
+}
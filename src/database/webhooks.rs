@@ -0,0 +1,692 @@
+use super::{transfers, Database, IndexedTransfer};
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+pub(super) const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS address_webhooks (
+        address TEXT PRIMARY KEY,
+        asset_id TEXT,
+        callback_url TEXT NOT NULL,
+        secret TEXT,
+        created_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS webhook_outbox (
+        id TEXT PRIMARY KEY,
+        event TEXT NOT NULL,
+        callback_url TEXT NOT NULL,
+        secret TEXT,
+        payload TEXT NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        last_error TEXT,
+        next_attempt_at INTEGER NOT NULL,
+        delivered_at INTEGER,
+        created_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_webhook_outbox_due ON webhook_outbox(status, next_attempt_at);
+
+    CREATE TABLE IF NOT EXISTS webhook_attempts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        delivery_id TEXT NOT NULL,
+        attempt INTEGER NOT NULL,
+        attempted_at INTEGER NOT NULL,
+        succeeded INTEGER NOT NULL,
+        error TEXT
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_webhook_attempts_delivery ON webhook_attempts(delivery_id);
+
+    CREATE TABLE IF NOT EXISTS webhook_dead_letters (
+        delivery_id TEXT PRIMARY KEY,
+        reason TEXT NOT NULL,
+        dead_at INTEGER NOT NULL
+    );
+
+    INSERT OR IGNORE INTO webhook_dead_letters (delivery_id, reason, dead_at)
+    SELECT id, COALESCE(last_error, 'attempts exhausted'), next_attempt_at
+    FROM webhook_outbox WHERE status = 'failed';
+"#;
+
+/// Prefix of outbox ids for address receive notifications; the rest is the
+/// indexed transfer id, so each receive is enqueued once.
+pub const ADDRESS_RECEIVED_PREFIX: &str = "address.received:";
+
+/// Callback registered for an address at creation time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressWebhook {
+    pub address: String,
+    pub asset_id: Option<String>,
+    pub callback_url: String,
+    #[serde(skip_serializing)]
+    pub secret: Option<String>,
+    pub created_at: i64,
+}
+
+/// Lifecycle of one notification. `Failed` is terminal once the attempt
+/// budget is spent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            other => Err(AppError::DatabaseError(format!(
+                "Unknown delivery status: {other}"
+            ))),
+        }
+    }
+}
+
+/// One queued webhook call. `id` doubles as the idempotency key receivers
+/// see in `X-Gateway-Delivery`; enqueueing an existing id is a no-op.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub event: String,
+    pub callback_url: String,
+    #[serde(skip_serializing)]
+    pub secret: Option<String>,
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: i64,
+    pub created_at: i64,
+}
+
+impl WebhookDelivery {
+    pub fn new(
+        id: String,
+        event: &str,
+        callback_url: String,
+        secret: Option<String>,
+        payload: serde_json::Value,
+    ) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        Self {
+            id,
+            event: event.to_string(),
+            callback_url,
+            secret,
+            payload,
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            last_error: None,
+            next_attempt_at: now,
+            created_at: now,
+        }
+    }
+}
+
+/// One delivery attempt, kept for the dead-letter view.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeliveryAttempt {
+    pub attempt: u32,
+    pub attempted_at: i64,
+    pub succeeded: bool,
+    pub error: Option<String>,
+}
+
+/// A delivery that spent its attempt budget, with why and how it failed.
+/// Its outbox row stays `failed` so the notification is not enqueued again;
+/// retrying puts it back to `pending` with a fresh budget.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    #[serde(flatten)]
+    pub delivery: WebhookDelivery,
+    pub reason: String,
+    pub dead_at: i64,
+    pub history: Vec<DeliveryAttempt>,
+}
+
+/// Delivery outcomes across the outbox and its attempt history.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct WebhookDeliveryStats {
+    pub pending: u64,
+    pub delivered: u64,
+    pub dead_letters: u64,
+    pub attempts: u64,
+    pub successful_attempts: u64,
+    pub failed_attempts: u64,
+    /// Share of delivered notifications among those that finished
+    /// (delivered or dead-lettered); `None` before any finished.
+    pub delivery_success_rate: Option<f64>,
+    /// Share of attempts that succeeded.
+    pub attempt_success_rate: Option<f64>,
+}
+
+impl Database {
+    pub async fn register_address_webhook(&self, webhook: &AddressWebhook) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        sqlx::query(
+            r#"
+            INSERT INTO address_webhooks (address, asset_id, callback_url, secret, created_at)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(address) DO UPDATE SET
+                callback_url = excluded.callback_url,
+                secret = excluded.secret
+            "#,
+        )
+        .bind(&webhook.address)
+        .bind(&webhook.asset_id)
+        .bind(&webhook.callback_url)
+        .bind(&webhook.secret)
+        .bind(webhook.created_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to store webhook: {e}")))?;
+        Ok(())
+    }
+
+    pub async fn address_webhook(&self, address: &str) -> Result<Option<AddressWebhook>, AppError> {
+        let pool = self.sqlite()?;
+        let row = sqlx::query(
+            r#"
+            SELECT address, asset_id, callback_url, secret, created_at
+            FROM address_webhooks WHERE address = ?
+            "#,
+        )
+        .bind(address)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query webhooks: {e}")))?;
+        Ok(row.map(|row| AddressWebhook {
+            address: row.get("address"),
+            asset_id: row.get("asset_id"),
+            callback_url: row.get("callback_url"),
+            secret: row.get("secret"),
+            created_at: row.get("created_at"),
+        }))
+    }
+
+    /// Receives to webhook-enabled addresses that have not been enqueued yet.
+    pub async fn unnotified_address_receives(
+        &self,
+    ) -> Result<Vec<(AddressWebhook, IndexedTransfer)>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            r#"
+            SELECT t.id, t.kind, t.asset_id, t.address, t.amount, t.anchor_txid, t.outpoint,
+                   t.block_height, t.status, t.timestamp, t.raw, t.chain_status,
+                   t.confirmations, t.block_hash, NULL AS label, NULL AS tags,
+                   w.asset_id AS webhook_asset_id, w.callback_url, w.secret,
+                   w.created_at AS webhook_created_at
+            FROM indexed_transfers t
+            JOIN address_webhooks w ON w.address = t.address
+            LEFT JOIN webhook_outbox o ON o.id = ? || t.id
+            WHERE t.kind = 'receive' AND o.id IS NULL
+            ORDER BY t.timestamp ASC
+            "#,
+        )
+        .bind(ADDRESS_RECEIVED_PREFIX)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query webhooks: {e}")))?;
+
+        rows.iter()
+            .map(|row| {
+                let webhook = AddressWebhook {
+                    address: row.get("address"),
+                    asset_id: row.get("webhook_asset_id"),
+                    callback_url: row.get("callback_url"),
+                    secret: row.get("secret"),
+                    created_at: row.get("webhook_created_at"),
+                };
+                Ok((webhook, transfers::transfer_from_row(row)?))
+            })
+            .collect()
+    }
+
+    /// Queues a delivery. Returns false when a delivery with the same id
+    /// already exists.
+    pub async fn enqueue_webhook(&self, delivery: &WebhookDelivery) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let payload = serde_json::to_string(&delivery.payload)
+            .map_err(|e| AppError::SerializationError(e.to_string()))?;
+        let result = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO webhook_outbox (
+                id, event, callback_url, secret, payload, status, attempts,
+                next_attempt_at, created_at
+            )
+            VALUES (?, ?, ?, ?, ?, ?, 0, ?, ?)
+            "#,
+        )
+        .bind(&delivery.id)
+        .bind(&delivery.event)
+        .bind(&delivery.callback_url)
+        .bind(&delivery.secret)
+        .bind(payload)
+        .bind(delivery.status.as_str())
+        .bind(delivery.next_attempt_at)
+        .bind(delivery.created_at)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to enqueue webhook: {e}")))?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Pending deliveries whose next attempt is due, oldest first.
+    pub async fn due_webhooks(&self, now: i64) -> Result<Vec<WebhookDelivery>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            r#"
+            SELECT id, event, callback_url, secret, payload, status, attempts, last_error,
+                   next_attempt_at, created_at
+            FROM webhook_outbox
+            WHERE status = 'pending' AND next_attempt_at <= ?
+            ORDER BY next_attempt_at ASC
+            "#,
+        )
+        .bind(now)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query webhooks: {e}")))?;
+        rows.iter().map(delivery_from_row).collect()
+    }
+
+    /// Records the outcome of a delivery attempt in the outbox and the
+    /// attempt history, dead-lettering the delivery when `status` is
+    /// `Failed`. `next_attempt_at` only matters while the status is pending.
+    pub async fn record_webhook_attempt(
+        &self,
+        id: &str,
+        status: DeliveryStatus,
+        error: Option<&str>,
+        next_attempt_at: i64,
+    ) -> Result<(), AppError> {
+        let pool = self.sqlite()?;
+        let now = chrono::Utc::now().timestamp();
+        let delivered_at = (status == DeliveryStatus::Delivered).then_some(now);
+        let db_error =
+            |e: sqlx::Error| AppError::DatabaseError(format!("Failed to record delivery: {e}"));
+
+        let mut tx = pool.begin().await.map_err(db_error)?;
+        let attempt: Option<i64> = sqlx::query_scalar(
+            r#"
+            UPDATE webhook_outbox
+            SET status = ?, attempts = attempts + 1, last_error = ?,
+                next_attempt_at = ?, delivered_at = ?
+            WHERE id = ?
+            RETURNING attempts
+            "#,
+        )
+        .bind(status.as_str())
+        .bind(error)
+        .bind(next_attempt_at)
+        .bind(delivered_at)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        let Some(attempt) = attempt else {
+            return Ok(());
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_attempts (delivery_id, attempt, attempted_at, succeeded, error)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(id)
+        .bind(attempt)
+        .bind(now)
+        .bind(status == DeliveryStatus::Delivered)
+        .bind(error)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        if status == DeliveryStatus::Failed {
+            sqlx::query(
+                r#"
+                INSERT INTO webhook_dead_letters (delivery_id, reason, dead_at) VALUES (?, ?, ?)
+                ON CONFLICT(delivery_id) DO UPDATE SET
+                    reason = excluded.reason,
+                    dead_at = excluded.dead_at
+                "#,
+            )
+            .bind(id)
+            .bind(error.unwrap_or("attempts exhausted"))
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        }
+        tx.commit().await.map_err(db_error)?;
+        Ok(())
+    }
+
+    /// Dead-lettered deliveries, most recent first, each with its attempt
+    /// history.
+    pub async fn dead_letter_webhooks(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DeadLetter>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            r#"
+            SELECT o.id, o.event, o.callback_url, o.secret, o.payload, o.status, o.attempts,
+                   o.last_error, o.next_attempt_at, o.created_at, d.reason, d.dead_at
+            FROM webhook_dead_letters d
+            JOIN webhook_outbox o ON o.id = d.delivery_id
+            ORDER BY d.dead_at DESC, o.id ASC
+            LIMIT ? OFFSET ?
+            "#,
+        )
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query dead letters: {e}")))?;
+
+        let mut dead_letters = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let delivery = delivery_from_row(row)?;
+            let history = self.webhook_attempts(&delivery.id).await?;
+            dead_letters.push(DeadLetter {
+                delivery,
+                reason: row.get("reason"),
+                dead_at: row.get("dead_at"),
+                history,
+            });
+        }
+        Ok(dead_letters)
+    }
+
+    /// Every recorded attempt of a delivery, oldest first.
+    pub async fn webhook_attempts(&self, id: &str) -> Result<Vec<DeliveryAttempt>, AppError> {
+        let pool = self.sqlite()?;
+        let rows = sqlx::query(
+            "SELECT attempt, attempted_at, succeeded, error FROM webhook_attempts \
+             WHERE delivery_id = ? ORDER BY id ASC",
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Failed to query attempts: {e}")))?;
+        Ok(rows
+            .iter()
+            .map(|row| DeliveryAttempt {
+                attempt: row.get::<i64, _>("attempt") as u32,
+                attempted_at: row.get("attempted_at"),
+                succeeded: row.get("succeeded"),
+                error: row.get("error"),
+            })
+            .collect())
+    }
+
+    /// Moves a dead letter back to the outbox as due now, with a fresh
+    /// attempt budget. Returns false when `id` is not dead-lettered.
+    pub async fn retry_dead_letter(&self, id: &str) -> Result<bool, AppError> {
+        let pool = self.sqlite()?;
+        let db_error =
+            |e: sqlx::Error| AppError::DatabaseError(format!("Failed to retry dead letter: {e}"));
+        let mut tx = pool.begin().await.map_err(db_error)?;
+        let removed = sqlx::query("DELETE FROM webhook_dead_letters WHERE delivery_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?
+            .rows_affected();
+        if removed == 0 {
+            return Ok(false);
+        }
+        sqlx::query(
+            r#"
+            UPDATE webhook_outbox
+            SET status = 'pending', attempts = 0, next_attempt_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        tx.commit().await.map_err(db_error)?;
+        Ok(true)
+    }
+
+    /// Discards dead letters (one, or all when `id` is `None`) and their
+    /// attempt history. The outbox rows stay `failed`, so the notifications
+    /// are not enqueued again. Returns how many were purged.
+    pub async fn purge_dead_letters(&self, id: Option<&str>) -> Result<u64, AppError> {
+        let pool = self.sqlite()?;
+        let db_error =
+            |e: sqlx::Error| AppError::DatabaseError(format!("Failed to purge dead letters: {e}"));
+        let mut tx = pool.begin().await.map_err(db_error)?;
+        sqlx::query(
+            "DELETE FROM webhook_attempts WHERE delivery_id IN \
+             (SELECT delivery_id FROM webhook_dead_letters WHERE ?1 IS NULL OR delivery_id = ?1)",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        let purged =
+            sqlx::query("DELETE FROM webhook_dead_letters WHERE ?1 IS NULL OR delivery_id = ?1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?
+                .rows_affected();
+        tx.commit().await.map_err(db_error)?;
+        Ok(purged)
+    }
+
+    pub async fn webhook_delivery_stats(&self) -> Result<WebhookDeliveryStats, AppError> {
+        let pool = self.sqlite()?;
+        let db_error =
+            |e: sqlx::Error| AppError::DatabaseError(format!("Failed to total deliveries: {e}"));
+        let row = sqlx::query(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM webhook_outbox WHERE status = 'pending') AS pending,
+                (SELECT COUNT(*) FROM webhook_outbox WHERE status = 'delivered') AS delivered,
+                (SELECT COUNT(*) FROM webhook_dead_letters) AS dead_letters,
+                (SELECT COUNT(*) FROM webhook_attempts) AS attempts,
+                (SELECT COUNT(*) FROM webhook_attempts WHERE succeeded) AS successful_attempts
+            "#,
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(db_error)?;
+        let count = |column: &str| row.get::<i64, _>(column) as u64;
+        let (delivered, dead_letters) = (count("delivered"), count("dead_letters"));
+        let (attempts, successful_attempts) = (count("attempts"), count("successful_attempts"));
+        let rate = |part: u64, whole: u64| (whole > 0).then(|| part as f64 / whole as f64);
+        Ok(WebhookDeliveryStats {
+            pending: count("pending"),
+            delivered,
+            dead_letters,
+            attempts,
+            successful_attempts,
+            failed_attempts: attempts - successful_attempts,
+            delivery_success_rate: rate(delivered, delivered + dead_letters),
+            attempt_success_rate: rate(successful_attempts, attempts),
+        })
+    }
+}
+
+fn delivery_from_row(row: &SqliteRow) -> Result<WebhookDelivery, AppError> {
+    let status: String = row.get("status");
+    let payload: String = row.get("payload");
+    Ok(WebhookDelivery {
+        id: row.get("id"),
+        event: row.get("event"),
+        callback_url: row.get("callback_url"),
+        secret: row.get("secret"),
+        payload: serde_json::from_str(&payload)
+            .map_err(|e| AppError::SerializationError(e.to_string()))?,
+        status: DeliveryStatus::parse(&status)?,
+        attempts: row.get::<i64, _>("attempts") as u32,
+        last_error: row.get("last_error"),
+        next_attempt_at: row.get("next_attempt_at"),
+        created_at: row.get("created_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{open_test_database, TransferKind};
+
+    fn receive(id: &str, address: &str) -> IndexedTransfer {
+        IndexedTransfer {
+            id: id.to_string(),
+            kind: TransferKind::Receive,
+            asset_id: None,
+            address: Some(address.to_string()),
+            amount: Some(5),
+            anchor_txid: None,
+            outpoint: None,
+            block_height: None,
+            status: "completed".to_string(),
+            timestamp: 1,
+            raw: serde_json::json!({}),
+            chain_status: None,
+            confirmations: None,
+            block_hash: None,
+            label: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_address_receives_are_enqueued_once() {
+        let db = open_test_database().await;
+        db.register_address_webhook(&AddressWebhook {
+            address: "taprt1a".to_string(),
+            asset_id: None,
+            callback_url: "https://example.com/hook".to_string(),
+            secret: Some("s3cret".to_string()),
+            created_at: 0,
+        })
+        .await
+        .unwrap();
+        db.upsert_indexed_transfers(&[receive("r1", "taprt1a"), receive("r2", "taprt1other")])
+            .await
+            .unwrap();
+
+        let unnotified = db.unnotified_address_receives().await.unwrap();
+        assert_eq!(unnotified.len(), 1);
+        let (webhook, transfer) = &unnotified[0];
+        assert_eq!(transfer.id, "r1");
+        assert_eq!(webhook.secret.as_deref(), Some("s3cret"));
+
+        let delivery = WebhookDelivery::new(
+            format!("{ADDRESS_RECEIVED_PREFIX}{}", transfer.id),
+            "address.received",
+            webhook.callback_url.clone(),
+            webhook.secret.clone(),
+            serde_json::json!({}),
+        );
+        assert!(db.enqueue_webhook(&delivery).await.unwrap());
+        assert!(!db.enqueue_webhook(&delivery).await.unwrap());
+        assert!(db.unnotified_address_receives().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_due_webhooks_respect_retry_schedule() {
+        let db = open_test_database().await;
+        let delivery = WebhookDelivery::new(
+            "d1".to_string(),
+            "test",
+            "https://example.com".to_string(),
+            None,
+            serde_json::json!({ "a": 1 }),
+        );
+        db.enqueue_webhook(&delivery).await.unwrap();
+        let now = delivery.next_attempt_at;
+        assert_eq!(db.due_webhooks(now).await.unwrap().len(), 1);
+
+        db.record_webhook_attempt("d1", DeliveryStatus::Pending, Some("503"), now + 30)
+            .await
+            .unwrap();
+        assert!(db.due_webhooks(now).await.unwrap().is_empty());
+        let due = db.due_webhooks(now + 30).await.unwrap();
+        assert_eq!(due[0].attempts, 1);
+        assert_eq!(due[0].payload["a"], 1);
+
+        db.record_webhook_attempt("d1", DeliveryStatus::Delivered, None, 0)
+            .await
+            .unwrap();
+        assert!(db.due_webhooks(now + 1_000).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_keep_history_and_can_be_retried_or_purged() {
+        let db = open_test_database().await;
+        for id in ["d1", "d2"] {
+            let delivery = WebhookDelivery::new(
+                id.to_string(),
+                "test",
+                "https://example.com".to_string(),
+                None,
+                serde_json::json!({}),
+            );
+            db.enqueue_webhook(&delivery).await.unwrap();
+            db.record_webhook_attempt(id, DeliveryStatus::Pending, Some("timeout"), 0)
+                .await
+                .unwrap();
+            db.record_webhook_attempt(id, DeliveryStatus::Failed, Some("callback returned 500"), 0)
+                .await
+                .unwrap();
+        }
+
+        let dead = db.dead_letter_webhooks(10, 0).await.unwrap();
+        assert_eq!(dead.len(), 2);
+        assert_eq!(dead[0].reason, "callback returned 500");
+        assert_eq!(dead[0].history.len(), 2);
+        assert_eq!(dead[0].history[0].error.as_deref(), Some("timeout"));
+        assert!(!dead[0].history[1].succeeded);
+
+        let stats = db.webhook_delivery_stats().await.unwrap();
+        assert_eq!(stats.dead_letters, 2);
+        assert_eq!(stats.failed_attempts, 4);
+        assert_eq!(stats.delivery_success_rate, Some(0.0));
+
+        assert!(db.retry_dead_letter("d1").await.unwrap());
+        assert!(!db.retry_dead_letter("d1").await.unwrap());
+        let due = db
+            .due_webhooks(chrono::Utc::now().timestamp())
+            .await
+            .unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].attempts, 0);
+
+        assert_eq!(db.purge_dead_letters(None).await.unwrap(), 1);
+        assert!(db.dead_letter_webhooks(10, 0).await.unwrap().is_empty());
+        assert!(db.webhook_attempts("d2").await.unwrap().is_empty());
+        // A purged delivery is not enqueued again
+        let again = WebhookDelivery::new(
+            "d2".to_string(),
+            "test",
+            "https://example.com".to_string(),
+            None,
+            serde_json::json!({}),
+        );
+        assert!(!db.enqueue_webhook(&again).await.unwrap());
+    }
+}
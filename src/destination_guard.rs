@@ -0,0 +1,295 @@
+//! Protection against sends that would destroy assets. Every send
+//! destination is decoded through tapd and checked against
+//! `DESTINATION_DENYLIST` and against keys nobody can sign for: malformed or
+//! off-curve script keys and the well-known NUMS points. Flagged sends are
+//! refused, or with `DESTINATION_GUARD=override` go through only when the
+//! caller sets [`OVERRIDE_HEADER`].
+
+use crate::api::addresses::{decode_address, Addr, DecodeAddrRequest};
+use crate::config::Config;
+use crate::error::AppError;
+use crate::indexer::normalize_hex_id;
+use actix_web::{web, HttpRequest};
+use reqwest::Client;
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Request header that lets a flagged send through in `override` mode.
+pub const OVERRIDE_HEADER: &str = "x-allow-unsafe-destination";
+
+/// x-only keys with no known discrete log: tapd's `NUMSBytes` ("taproot-assets")
+/// and BIP-341's `H`. Assets sent to them can never move again.
+const NUMS_KEYS: &[&str] = &[
+    "7c79b9b26e463895eef5679d8558942c86c4ad2233adef01bc3e6d540b3653fe",
+    "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardMode {
+    /// No checks.
+    Off,
+    /// Flagged sends need the override header.
+    Override,
+    /// Flagged sends are always refused.
+    Refuse,
+}
+
+impl GuardMode {
+    /// Parses `DESTINATION_GUARD`.
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(GuardMode::Off),
+            "" | "override" => Ok(GuardMode::Override),
+            "refuse" => Ok(GuardMode::Refuse),
+            other => Err(AppError::ValidationError(format!(
+                "DESTINATION_GUARD must be 'off', 'override' or 'refuse', got '{other}'"
+            ))),
+        }
+    }
+}
+
+/// Parses `DESTINATION_DENYLIST`: comma-separated Taproot Assets addresses
+/// and script or internal keys (33-byte compressed or 32-byte x-only hex).
+/// Keys are stored x-only, so either parity matches.
+pub fn parse_denylist(value: &str) -> Result<Vec<String>, AppError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            if entry.chars().all(|c| c.is_ascii_hexdigit()) {
+                x_only_hex(entry).ok_or_else(|| {
+                    AppError::ValidationError(format!(
+                        "DESTINATION_DENYLIST key {entry} must be 33-byte or 32-byte hex"
+                    ))
+                })
+            } else if entry.starts_with("tap") {
+                Ok(entry.to_string())
+            } else {
+                Err(AppError::ValidationError(format!(
+                    "DESTINATION_DENYLIST entry {entry} is neither an address nor a key"
+                )))
+            }
+        })
+        .collect()
+}
+
+/// The x-only part of a 33-byte compressed or 32-byte key in hex.
+fn x_only_hex(key: &str) -> Option<String> {
+    let key = key.to_ascii_lowercase();
+    match key.len() {
+        66 if key.starts_with("02") || key.starts_with("03") => Some(key[2..].to_string()),
+        64 => Some(key),
+        _ => None,
+    }
+}
+
+/// Why a key cannot be spent from, if it obviously cannot. `value` is hex
+/// or base64, as tapd renders it.
+pub fn unspendable_key(value: &str) -> Option<String> {
+    let Some(x_only) = x_only_hex(&normalize_hex_id(value)) else {
+        return Some("is not a 32- or 33-byte public key".to_string());
+    };
+    if NUMS_KEYS.contains(&x_only.as_str()) {
+        return Some("is a NUMS point with no known private key".to_string());
+    }
+    let bytes = hex::decode(&x_only).unwrap_or_default();
+    if XOnlyPublicKey::from_slice(&bytes).is_err() {
+        return Some("is not a point on secp256k1".to_string());
+    }
+    None
+}
+
+#[derive(Debug, Clone)]
+pub struct DestinationGuard {
+    mode: GuardMode,
+    denylist: HashSet<String>,
+}
+
+impl DestinationGuard {
+    pub fn new(mode: GuardMode, denylist: &[String]) -> Self {
+        Self {
+            mode,
+            denylist: denylist.iter().cloned().collect(),
+        }
+    }
+
+    /// The guard configured for `req`'s gateway. Without a config (e.g. a
+    /// bare test app) the checks still run in `override` mode.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        match req.app_data::<web::Data<Config>>() {
+            Some(config) => Self::new(config.destination_guard, &config.destination_denylist),
+            None => Self::new(GuardMode::Override, &[]),
+        }
+    }
+
+    pub fn mode(&self) -> GuardMode {
+        self.mode
+    }
+
+    /// Problems with sending to `address`, decoded as `decoded`; empty when
+    /// it looks safe.
+    pub fn assess(&self, address: &str, decoded: &Addr) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.denylist.contains(address.trim()) {
+            problems.push("address is on the destination denylist".to_string());
+        }
+        for (label, key) in [
+            ("script key", &decoded.script_key),
+            ("internal key", &decoded.internal_key),
+        ] {
+            let listed = key
+                .as_deref()
+                .and_then(|k| x_only_hex(&normalize_hex_id(k)))
+                .is_some_and(|k| self.denylist.contains(&k));
+            if listed {
+                problems.push(format!("{label} is on the destination denylist"));
+            }
+        }
+
+        match decoded.script_key.as_deref() {
+            Some(key) => {
+                if let Some(reason) = unspendable_key(key) {
+                    problems.push(format!("script key {reason}"));
+                }
+            }
+            None => problems.push("address has no script key".to_string()),
+        }
+        // The anchor output can only be spent by key path when no tapscript
+        // sibling offers another way
+        let no_sibling = decoded
+            .tapscript_sibling
+            .as_deref()
+            .is_none_or(|s| s.is_empty());
+        if let Some(reason) = decoded
+            .internal_key
+            .as_deref()
+            .filter(|_| no_sibling)
+            .and_then(unspendable_key)
+        {
+            problems.push(format!("internal key {reason}"));
+        }
+        problems
+    }
+
+    /// Refuses a send to `address` with `problems`, unless the mode allows it.
+    pub fn enforce(
+        &self,
+        address: &str,
+        problems: &[String],
+        overridden: bool,
+    ) -> Result<(), AppError> {
+        if problems.is_empty() || self.mode == GuardMode::Off {
+            return Ok(());
+        }
+        if self.mode == GuardMode::Override && overridden {
+            tracing::warn!(
+                "Sending to flagged destination {} on override: {}",
+                address,
+                problems.join("; ")
+            );
+            return Ok(());
+        }
+        let hint = match self.mode {
+            GuardMode::Override => format!("; set {OVERRIDE_HEADER}: true to send anyway"),
+            _ => String::new(),
+        };
+        Err(AppError::ValidationError(format!(
+            "Refusing to send to {address}: {}{hint}",
+            problems.join("; ")
+        )))
+    }
+}
+
+/// Whether the request carries the override header set to `true`.
+pub fn overridden(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(OVERRIDE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+}
+
+/// Decodes every address of a send and refuses the send if any of them is
+/// flagged. Addresses tapd cannot decode are refused too, since their
+/// destination cannot be checked.
+pub async fn check_destinations(
+    req: &HttpRequest,
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    addresses: &[String],
+) -> Result<(), AppError> {
+    let guard = DestinationGuard::from_request(req);
+    if guard.mode() == GuardMode::Off {
+        return Ok(());
+    }
+    let overridden = overridden(req);
+    for address in addresses {
+        let request = DecodeAddrRequest {
+            addr: address.clone(),
+        };
+        let decoded = decode_address(client, base_url, macaroon_hex, request).await?;
+        guard.enforce(address, &guard.assess(address, &decoded), overridden)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The secp256k1 generator, a valid key.
+    const G: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn addr(script_key: &str, internal_key: &str) -> Addr {
+        serde_json::from_value(serde_json::json!({
+            "script_key": script_key,
+            "internal_key": internal_key
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_unspendable_keys() {
+        assert_eq!(unspendable_key(G), None);
+        assert!(unspendable_key(&format!("03{}", NUMS_KEYS[0]))
+            .unwrap()
+            .contains("NUMS"));
+        assert!(unspendable_key(NUMS_KEYS[1]).unwrap().contains("NUMS"));
+        assert!(unspendable_key(&"00".repeat(33))
+            .unwrap()
+            .contains("not a 32- or 33-byte"));
+        assert!(unspendable_key(&format!("02{}", "ff".repeat(32)))
+            .unwrap()
+            .contains("not a point"));
+        assert!(unspendable_key("abcd").is_some());
+    }
+
+    #[test]
+    fn test_assess_flags_nums_and_denylisted_destinations() {
+        let denylist = parse_denylist(&format!("taprt1denied, 03{}", &G[2..])).unwrap();
+        let guard = DestinationGuard::new(GuardMode::Override, &denylist);
+
+        assert!(guard.assess("taprt1fine", &addr(G, G)).len() == 2);
+        let safe_key = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+        assert!(guard
+            .assess("taprt1fine", &addr(safe_key, safe_key))
+            .is_empty());
+
+        let burn = addr(&format!("02{}", NUMS_KEYS[0]), safe_key);
+        let problems = guard.assess("taprt1denied", &burn);
+        assert_eq!(problems.len(), 2);
+        assert!(guard.enforce("taprt1denied", &problems, false).is_err());
+        assert!(guard.enforce("taprt1denied", &problems, true).is_ok());
+
+        let refusing = DestinationGuard::new(GuardMode::Refuse, &denylist);
+        assert!(refusing.enforce("taprt1denied", &problems, true).is_err());
+        let off = DestinationGuard::new(GuardMode::Off, &denylist);
+        assert!(off.enforce("taprt1denied", &problems, false).is_ok());
+
+        assert!(parse_denylist("nonsense").is_err());
+        assert!(parse_denylist("02abcd").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Fixes for environment values the configuration parser would silently
+//! misread: `TLS_VERIFY=1` falls back to the default, and so does
+//! `REQUEST_TIMEOUT_SECS=30s`. Before the configuration is loaded, such
+//! values are rewritten, each with a warning, and when anything was fixed a
+//! copy of `.env` with the corrected values is written to `.env.migrated` so
+//! the fix can be made permanent.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Flags read with `str::parse::<bool>`, which only accepts `true` and
+/// `false`.
+const BOOL_VARS: &[&str] = &[
+    "ADAPTIVE_TIMEOUTS_ENABLED",
+    "ALLOW_INSECURE_NO_AUTH",
+    "BACKEND_HTTP2_PRIOR_KNOWLEDGE",
+    "CHANNEL_EVENTS_ENABLED",
+    "COIN_SELECTION_PREFER_OLDEST",
+    "FAUCET_ENABLED",
+    "INDEXER_ENABLED",
+    "MAILBOX_OUTBOX_ENABLED",
+    "MAINTENANCE_MODE",
+    "PSBT_FLOW_GUARD_ENABLED",
+    "PUBLIC_API_ENABLED",
+    "RECURRING_PAYMENTS_ENABLED",
+    "SESSIONS_ENABLED",
+    "SESSION_COOKIE_SECURE",
+    "SESSION_CSRF_PROTECTION",
+    "STRICT_STATUS",
+    "SWAPS_ENABLED",
+    "TLS_VERIFY",
+    "USAGE_ACCOUNTING_ENABLED",
+];
+
+/// One misread setting and the value it is read as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    pub name: String,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+/// Finds the misread settings in `vars`, ordered by variable name.
+pub fn plan(vars: &BTreeMap<String, String>) -> Vec<Migration> {
+    vars.iter()
+        .filter_map(|(name, value)| {
+            normalize(name, value).map(|new_value| Migration {
+                name: name.clone(),
+                old_value: value.clone(),
+                new_value,
+            })
+        })
+        .collect()
+}
+
+/// The value `name` should have, when `value` is an older spelling of it.
+fn normalize(name: &str, value: &str) -> Option<String> {
+    let trimmed = value.trim();
+    let fixed = if BOOL_VARS.contains(&name) {
+        match trimmed.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "y" | "on" => "true".to_string(),
+            "false" | "0" | "no" | "n" | "off" => "false".to_string(),
+            _ => return None,
+        }
+    } else if name.ends_with("_SECS") {
+        let split = trimmed
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(split);
+        let scale = match unit.trim() {
+            "" => 1,
+            "s" => 1,
+            "m" => 60,
+            "h" => 3_600,
+            "d" => 86_400,
+            _ => return None,
+        };
+        number.parse::<u64>().ok()?.checked_mul(scale)?.to_string()
+    } else {
+        return None;
+    };
+    (fixed != value).then_some(fixed)
+}
+
+/// Applies the migrations to the process environment, logs each one, and
+/// writes the suggested configuration next to `env_file`. Call after
+/// loading `env_file` and before any thread reads the environment.
+pub fn apply(env_file: &Path) -> Vec<Migration> {
+    let vars = std::env::vars().collect();
+    let migrations = plan(&vars);
+    if migrations.is_empty() {
+        return migrations;
+    }
+    for m in &migrations {
+        warn!(
+            "{}={:?} is read as {}={}; update the value",
+            m.name, m.old_value, m.name, m.new_value
+        );
+        std::env::set_var(&m.name, &m.new_value);
+    }
+
+    let path = suggestion_path(env_file);
+    let original = fs::read_to_string(env_file).ok();
+    match fs::write(&path, suggest(original.as_deref(), &migrations)) {
+        Ok(()) => {
+            // The file holds the same secrets as the original
+            if let Ok(metadata) = fs::metadata(env_file) {
+                let _ = fs::set_permissions(&path, metadata.permissions());
+            }
+            info!(
+                "Suggested configuration with corrected values written to {}",
+                path.display()
+            );
+        }
+        Err(e) => warn!("Could not write {}: {}", path.display(), e),
+    }
+    migrations
+}
+
+fn suggestion_path(env_file: &Path) -> PathBuf {
+    let mut name = env_file.as_os_str().to_owned();
+    name.push(".migrated");
+    PathBuf::from(name)
+}
+
+/// `original` with the misread lines rewritten, followed by any fixed
+/// settings that came from the process environment instead.
+fn suggest(original: Option<&str>, migrations: &[Migration]) -> String {
+    let mut out = String::new();
+    let mut written = Vec::new();
+    for line in original.unwrap_or_default().lines() {
+        let name = line
+            .trim_start()
+            .trim_start_matches("export ")
+            .split('=')
+            .next()
+            .unwrap_or_default()
+            .trim();
+        match migrations.iter().find(|m| m.name == name) {
+            Some(m) => {
+                out.push_str(&format!("# migrated from {line}\n"));
+                out.push_str(&format!("{}={}\n", m.name, m.new_value));
+            }
+            None => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        written.push(name);
+    }
+    let rest: Vec<_> = migrations
+        .iter()
+        .filter(|m| !written.contains(&m.name.as_str()))
+        .collect();
+    if !rest.is_empty() {
+        out.push_str("\n# Migrated from the process environment\n");
+        for m in rest {
+            out.push_str(&format!("{}={}\n", m.name, m.new_value));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_misread_values_are_fixed() {
+        let migrations = plan(&vars(&[
+            ("TLS_VERIFY", "1"),
+            ("REQUEST_TIMEOUT_SECS", "2m"),
+            ("INDEXER_ENABLED", "True"),
+            ("PUBLIC_API_ENABLED", "false"),
+            ("CORS_ORIGINS", "*"),
+        ]));
+        let found: Vec<_> = migrations
+            .iter()
+            .map(|m| (m.name.as_str(), m.old_value.as_str(), m.new_value.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("INDEXER_ENABLED", "True", "true"),
+                ("REQUEST_TIMEOUT_SECS", "2m", "120"),
+                ("TLS_VERIFY", "1", "true"),
+            ]
+        );
+        assert!(plan(&vars(&[
+            ("TLS_VERIFY", "maybe"),
+            ("SHED_CHECK_INTERVAL_SECS", "5x")
+        ]))
+        .is_empty());
+    }
+
+    #[test]
+    fn test_suggestion_rewrites_misread_lines() {
+        let migrations = plan(&vars(&[
+            ("TLS_VERIFY", "yes"),
+            ("PROOF_CACHE_TTL_SECS", "1h"),
+            ("RATE_LIMIT_PER_MINUTE", "60"),
+        ]));
+        let original = "# tapd\nTAPROOT_ASSETS_HOST=b:2\nexport TLS_VERIFY=yes\n";
+        assert_eq!(
+            suggest(Some(original), &migrations),
+            "# tapd\nTAPROOT_ASSETS_HOST=b:2\n# migrated from export TLS_VERIFY=yes\nTLS_VERIFY=true\n\
+             \n# Migrated from the process environment\nPROOF_CACHE_TTL_SECS=3600\n"
+        );
+        assert_eq!(
+            suggestion_path(Path::new(".env")),
+            PathBuf::from(".env.migrated")
+        );
+    }
+}
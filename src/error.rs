@@ -2,6 +2,140 @@ use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
 use thiserror::Error;
 
+/// Response header carrying tapd's own status when strict status mapping
+/// answered with a different one.
+pub const UPSTREAM_STATUS_HEADER: &str = "X-Upstream-Status";
+
+/// How a gateway answers tapd errors, registered per gateway as app data and
+/// applied by [`crate::middleware::StrictStatus`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatusMapping {
+    /// tapd's own status.
+    #[default]
+    Relay,
+    /// A status matching the error's cause; see [`classify_upstream`].
+    Strict,
+}
+
+impl StatusMapping {
+    pub fn new(strict: bool) -> Self {
+        if strict {
+            Self::Strict
+        } else {
+            Self::Relay
+        }
+    }
+}
+
+/// A tapd error a response relays, kept in its extensions so
+/// [`crate::middleware::StrictStatus`] can pick the status.
+#[derive(Debug, Clone)]
+pub struct UpstreamFailure {
+    pub status: u16,
+    pub body: String,
+}
+
+/// The status a tapd error is relayed with before any strict mapping.
+fn relayed_status(status: u16) -> StatusCode {
+    StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY)
+}
+
+/// Notes on `response` that it answers `error`, when that came from tapd.
+pub(crate) fn mark_upstream(response: &mut HttpResponse, error: &AppError) {
+    if let AppError::UpstreamError { status, body } = error {
+        response.extensions_mut().insert(UpstreamFailure {
+            status: *status,
+            body: body.clone(),
+        });
+    }
+}
+
+/// Message fragments of tapd errors reported with gRPC code Unknown, and
+/// the status each means.
+const MESSAGE_STATUSES: &[(&str, StatusCode)] = &[
+    ("not found", StatusCode::NOT_FOUND),
+    ("does not exist", StatusCode::NOT_FOUND),
+    ("unknown asset", StatusCode::NOT_FOUND),
+    ("already exists", StatusCode::CONFLICT),
+    ("already in progress", StatusCode::CONFLICT),
+    ("insufficient", StatusCode::CONFLICT),
+    ("not enough", StatusCode::CONFLICT),
+    (
+        "still in the process of starting",
+        StatusCode::SERVICE_UNAVAILABLE,
+    ),
+    ("not yet ready", StatusCode::SERVICE_UNAVAILABLE),
+    // After the macaroon check, so tapd refusing the gateway's own
+    // credential is not blamed on the caller
+    ("invalid", StatusCode::BAD_REQUEST),
+    ("unable to decode", StatusCode::BAD_REQUEST),
+    ("unable to parse", StatusCode::BAD_REQUEST),
+    ("must be", StatusCode::BAD_REQUEST),
+    ("must not", StatusCode::BAD_REQUEST),
+];
+
+/// Message fragments of tapd refusing the gateway's macaroon, a fault of
+/// the gateway's configuration rather than of the request.
+const CREDENTIAL_FRAGMENTS: &[&str] = &["macaroon", "permission denied"];
+
+/// Strict mapping of a tapd error: the gRPC code of `body` when it names a
+/// cause, else its message, else the status with server errors turned into
+/// `502` since the failure was tapd's.
+pub fn classify_upstream(status: u16, body: &str) -> StatusCode {
+    let document = serde_json::from_str::<serde_json::Value>(body).unwrap_or_default();
+    let by_code = match document["code"].as_u64() {
+        Some(3 | 11) => Some(StatusCode::BAD_REQUEST),
+        Some(4) => Some(StatusCode::GATEWAY_TIMEOUT),
+        Some(5) => Some(StatusCode::NOT_FOUND),
+        Some(6 | 9 | 10) => Some(StatusCode::CONFLICT),
+        Some(8 | 14) => Some(StatusCode::SERVICE_UNAVAILABLE),
+        Some(12) => Some(StatusCode::NOT_IMPLEMENTED),
+        // PermissionDenied and Unauthenticated concern the gateway's macaroon
+        Some(7 | 16) => Some(StatusCode::BAD_GATEWAY),
+        _ => None,
+    };
+    if let Some(status) = by_code {
+        return status;
+    }
+    let message = document["message"]
+        .as_str()
+        .unwrap_or(body)
+        .to_ascii_lowercase();
+    if CREDENTIAL_FRAGMENTS
+        .iter()
+        .any(|fragment| message.contains(fragment))
+    {
+        return StatusCode::BAD_GATEWAY;
+    }
+    if let Some((_, status)) = MESSAGE_STATUSES
+        .iter()
+        .find(|(fragment, _)| message.contains(fragment))
+    {
+        return *status;
+    }
+    match StatusCode::from_u16(status) {
+        Ok(status) if status.is_client_error() || status == StatusCode::SERVICE_UNAVAILABLE => {
+            status
+        }
+        _ => StatusCode::BAD_GATEWAY,
+    }
+}
+
+/// Relays tapd's error document and status unchanged, marked for
+/// [`crate::middleware::StrictStatus`].
+pub(crate) fn upstream_response(status: u16, body: &str) -> HttpResponse {
+    let mut builder = HttpResponse::build(relayed_status(status));
+    let mut response = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(json) => builder.json(json),
+        Err(_) => builder.json(serde_json::json!({ "error": body })),
+    };
+    response.extensions_mut().insert(UpstreamFailure {
+        status,
+        body: body.to_string(),
+    });
+    response
+}
+
 #[derive(Error, Debug)]
 #[allow(clippy::enum_variant_names)]
 pub enum AppError {
@@ -31,6 +165,12 @@ pub enum AppError {
     DatabaseError(String),
     #[error("Upstream returned {status}: {body}")]
     UpstreamError { status: u16, body: String },
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
 }
 
 impl ResponseError for AppError {
@@ -41,11 +181,7 @@ impl ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
         // Upstream errors relay tapd's document verbatim, matching handle_result.
         if let AppError::UpstreamError { status, body } = self {
-            let status = StatusCode::from_u16(*status).unwrap_or(StatusCode::BAD_GATEWAY);
-            return match serde_json::from_str::<serde_json::Value>(body) {
-                Ok(json) => HttpResponse::build(status).json(json),
-                Err(_) => HttpResponse::build(status).json(serde_json::json!({ "error": body })),
-            };
+            return upstream_response(*status, body);
         }
         let (message, error_type) = match self {
             AppError::ValidationError(msg) => (msg.clone(), "validation_error"),
@@ -85,6 +221,9 @@ impl ResponseError for AppError {
             AppError::UpstreamError { .. } => {
                 ("Upstream request failed".to_string(), "upstream_error")
             }
+            AppError::ServiceUnavailable(msg) => (msg.clone(), "service_unavailable"),
+            AppError::NotFound(msg) => (msg.clone(), "not_found"),
+            AppError::Conflict(msg) => (msg.clone(), "conflict"),
         };
 
         HttpResponse::build(self.status_code()).json(serde_json::json!({
@@ -103,9 +242,10 @@ impl AppError {
             AppError::WebSocketError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::WebSocketProxyError(_) => StatusCode::BAD_GATEWAY,
             AppError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::UpstreamError { status, .. } => {
-                StatusCode::from_u16(*status).unwrap_or(StatusCode::BAD_GATEWAY)
-            }
+            AppError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::UpstreamError { status, .. } => relayed_status(*status),
             AppError::RequestError(e) => {
                 if e.is_timeout() {
                     StatusCode::GATEWAY_TIMEOUT
@@ -150,4 +290,48 @@ mod tests {
         };
         assert!(err.to_string().contains("invalid confirmation text"));
     }
+
+    #[test]
+    fn test_strict_mapping_reads_grpc_code_then_message() {
+        let error = |code: u64, message: &str| {
+            serde_json::json!({ "code": code, "message": message, "details": [] }).to_string()
+        };
+        assert_eq!(
+            classify_upstream(500, &error(5, "asset missing")),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            classify_upstream(500, &error(2, "unable to find asset: asset not found")),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            classify_upstream(500, &error(2, "batch already exists")),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            classify_upstream(500, &error(2, "invalid asset ID length")),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            classify_upstream(500, &error(14, "connection refused")),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            classify_upstream(500, &error(2, "boom")),
+            StatusCode::BAD_GATEWAY
+        );
+        assert_eq!(
+            classify_upstream(429, "slow down"),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        // The gateway's own credential failing is not the caller's fault
+        assert_eq!(
+            classify_upstream(500, &error(2, "verification failed: invalid macaroon")),
+            StatusCode::BAD_GATEWAY
+        );
+        assert_eq!(
+            classify_upstream(500, &error(16, "missing credentials")),
+            StatusCode::BAD_GATEWAY
+        );
+    }
 }
@@ -0,0 +1,687 @@
+//! Escrow-style conditional transfers. A deposit is locked to a script key
+//! whose internal key is unspendable, with two tapscript leaves:
+//!
+//! - release: `OP_SHA256 <payment_hash> OP_EQUALVERIFY <beneficiary_key> OP_CHECKSIG`
+//! - refund: `<lock_height> OP_CHECKLOCKTIMEVERIFY OP_DROP <refund_key> OP_CHECKSIG`
+//!
+//! The gateway declares the script key to tapd and hands out an address for
+//! it. Releasing reveals the preimage, with which the beneficiary spends the
+//! release leaf under its own key. From `lock_height` on this node signs the
+//! refund leaf through tapd and takes the deposit back.
+
+use crate::api::backend;
+use crate::database::{Escrow, EscrowStatus, SharedDatabase};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint};
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::{OP_CHECKSIG, OP_CLTV, OP_DROP, OP_EQUALVERIFY, OP_SHA256};
+use bitcoin::psbt::{raw, Psbt};
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, XOnlyPublicKey};
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder, TaprootSpendInfo};
+use bitcoin::{OutPoint, ScriptBuf, Txid};
+use reqwest::Client;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use tracing::info;
+
+/// Topic prefix for escrow events; the suffix is the new status.
+pub const TOPIC_PREFIX: &str = "escrow.";
+
+/// BIP-341's provably unspendable internal key, so the deposit can only move
+/// through one of the two leaves.
+const NUMS_KEY: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// lnd key family tapd derives its asset keys from.
+const REFUND_KEY_FAMILY: u32 = 212;
+
+/// Purpose of lnd's BIP-32 key derivation paths.
+const LND_KEY_PURPOSE: u32 = 1017;
+
+/// tappsbt key type of a virtual output's lock time.
+const PSBT_OUT_TAP_LOCK_TIME: u8 = 0x7c;
+
+/// tapd's address event status once the received asset is spendable.
+const ADDR_EVENT_COMPLETED: &str = "ADDR_EVENT_STATUS_COMPLETED";
+
+/// Longest refund timeout, about a year of blocks.
+pub const MAX_TIMEOUT_BLOCKS: u32 = 52_560;
+
+/// Parses a hex x-only key, or a compressed key whose parity is dropped.
+pub fn parse_x_only(value: &str) -> Result<XOnlyPublicKey, AppError> {
+    let bytes = hex::decode(value.trim())
+        .map_err(|e| AppError::InvalidInput(format!("Key is not hex: {e}")))?;
+    let key = match bytes.len() {
+        32 => XOnlyPublicKey::from_slice(&bytes).ok(),
+        33 => PublicKey::from_slice(&bytes)
+            .ok()
+            .map(|key| key.x_only_public_key().0),
+        _ => None,
+    };
+    key.ok_or_else(|| {
+        AppError::InvalidInput("Key must be a 32-byte x-only or 33-byte compressed key".to_string())
+    })
+}
+
+/// Parses a hex SHA-256 payment hash.
+pub fn parse_payment_hash(value: &str) -> Result<[u8; 32], AppError> {
+    hex::decode(value.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| AppError::InvalidInput("payment_hash must be 32 bytes of hex".to_string()))
+}
+
+/// Whether `preimage` (hex) hashes to `payment_hash` (hex).
+pub fn preimage_matches(preimage: &str, payment_hash: &str) -> bool {
+    hex::decode(preimage.trim())
+        .map(|bytes| hex::encode(Sha256::digest(bytes)) == payment_hash.to_ascii_lowercase())
+        .unwrap_or(false)
+}
+
+/// The script tree of one escrow.
+pub struct EscrowScript {
+    pub release: ScriptBuf,
+    pub refund: ScriptBuf,
+    spend_info: TaprootSpendInfo,
+}
+
+impl EscrowScript {
+    pub fn new(
+        payment_hash: [u8; 32],
+        beneficiary_key: XOnlyPublicKey,
+        refund_key: XOnlyPublicKey,
+        lock_height: u32,
+    ) -> Result<Self, AppError> {
+        let release = Builder::new()
+            .push_opcode(OP_SHA256)
+            .push_slice(payment_hash)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_x_only_key(&beneficiary_key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let refund = Builder::new()
+            .push_int(lock_height as i64)
+            .push_opcode(OP_CLTV)
+            .push_opcode(OP_DROP)
+            .push_x_only_key(&refund_key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(1, release.clone())
+            .and_then(|builder| builder.add_leaf(1, refund.clone()))
+            .map_err(|e| AppError::InvalidInput(format!("Invalid escrow script: {e}")))?
+            .finalize(&Secp256k1::verification_only(), nums_key())
+            .map_err(|_| AppError::InvalidInput("Escrow script tree is incomplete".to_string()))?;
+        Ok(Self {
+            release,
+            refund,
+            spend_info,
+        })
+    }
+
+    /// Rebuilds the script tree of a stored escrow.
+    pub fn of(escrow: &Escrow) -> Result<Self, AppError> {
+        Self::new(
+            parse_payment_hash(&escrow.payment_hash)?,
+            parse_x_only(&escrow.beneficiary_key)?,
+            parse_x_only(&escrow.refund_key)?,
+            escrow.lock_height,
+        )
+    }
+
+    /// The tweaked key the deposit is locked to.
+    pub fn script_key(&self) -> XOnlyPublicKey {
+        self.spend_info.output_key().to_x_only_public_key()
+    }
+
+    fn merkle_root(&self) -> [u8; 32] {
+        self.spend_info
+            .merkle_root()
+            .map(|root| root.to_byte_array())
+            .unwrap_or_default()
+    }
+
+    fn control_block(&self, leaf: &ScriptBuf) -> Result<ControlBlock, AppError> {
+        self.spend_info
+            .control_block(&(leaf.clone(), LeafVersion::TapScript))
+            .ok_or_else(|| AppError::InvalidInput("Leaf is not in the escrow tree".to_string()))
+    }
+
+    /// The script key as tapd's `ScriptKey` message, for declaring it and for
+    /// addresses paying to it.
+    pub fn tapd_script_key(&self) -> Value {
+        let internal_key = nums_key().public_key(bitcoin::secp256k1::Parity::Even);
+        json!({
+            "pub_key": BASE64.encode(self.script_key().serialize()),
+            "key_desc": {
+                "raw_key_bytes": BASE64.encode(internal_key.serialize()),
+                "key_loc": { "key_family": 0, "key_index": 0 },
+            },
+            "tap_tweak": BASE64.encode(self.merkle_root()),
+            "type": "SCRIPT_KEY_SCRIPT_PATH_EXTERNAL",
+        })
+    }
+
+    /// Everything needed to spend either leaf outside the gateway.
+    pub fn describe(&self) -> Result<Value, AppError> {
+        Ok(json!({
+            "internal_key": nums_key().to_string(),
+            "script_key": self.script_key().to_string(),
+            "merkle_root": hex::encode(self.merkle_root()),
+            "release": {
+                "script": hex::encode(self.release.as_bytes()),
+                "control_block": hex::encode(self.control_block(&self.release)?.serialize()),
+                "witness": ["<beneficiary signature>", "<preimage>"],
+            },
+            "refund": {
+                "script": hex::encode(self.refund.as_bytes()),
+                "control_block": hex::encode(self.control_block(&self.refund)?.serialize()),
+                "witness": ["<refund signature>"],
+            },
+        }))
+    }
+}
+
+fn nums_key() -> XOnlyPublicKey {
+    XOnlyPublicKey::from_str(NUMS_KEY).expect("NUMS key is a valid x-only key")
+}
+
+/// lnd's derivation path of a key: `m/1017'/<coin>'/<family>'/0/<index>`.
+fn key_path(coin_type: u32, family: u32, index: u32) -> Result<DerivationPath, AppError> {
+    let invalid =
+        |e: bitcoin::bip32::Error| AppError::InvalidInput(format!("Invalid key path: {e}"));
+    Ok(DerivationPath::from(vec![
+        ChildNumber::from_hardened_idx(LND_KEY_PURPOSE).map_err(invalid)?,
+        ChildNumber::from_hardened_idx(coin_type).map_err(invalid)?,
+        ChildNumber::from_hardened_idx(family).map_err(invalid)?,
+        ChildNumber::from_normal_idx(0).map_err(invalid)?,
+        ChildNumber::from_normal_idx(index).map_err(invalid)?,
+    ]))
+}
+
+/// Prepares a funded virtual PSBT spending the escrow for tapd to sign
+/// through the refund leaf: the input gets the leaf, its control block and
+/// the refund key's derivation, and every output the lock time the leaf
+/// checks.
+pub fn refund_psbt(
+    funded_psbt: &str,
+    script: &EscrowScript,
+    escrow: &Escrow,
+    coin_type: u32,
+) -> Result<String, AppError> {
+    let bytes = BASE64
+        .decode(funded_psbt.trim())
+        .map_err(|e| AppError::SerializationError(format!("PSBT is not base64: {e}")))?;
+    let mut psbt = Psbt::deserialize(&bytes)
+        .map_err(|e| AppError::SerializationError(format!("Invalid PSBT: {e}")))?;
+    let input = psbt
+        .inputs
+        .first_mut()
+        .ok_or_else(|| AppError::SerializationError("Funded refund has no inputs".to_string()))?;
+    let refund_key = parse_x_only(&escrow.refund_key)?;
+    let leaf_hash = TapLeafHash::from_script(&script.refund, LeafVersion::TapScript);
+    input.tap_internal_key = Some(nums_key());
+    input.tap_merkle_root = script.spend_info.merkle_root();
+    input.tap_scripts.insert(
+        script.control_block(&script.refund)?,
+        (script.refund.clone(), LeafVersion::TapScript),
+    );
+    input.tap_key_origins.insert(
+        refund_key,
+        (
+            vec![leaf_hash],
+            (
+                Fingerprint::default(),
+                key_path(coin_type, escrow.refund_key_family, escrow.refund_key_index)?,
+            ),
+        ),
+    );
+    for output in &mut psbt.outputs {
+        output.unknown.insert(
+            raw::Key {
+                type_value: PSBT_OUT_TAP_LOCK_TIME,
+                key: Vec::new(),
+            },
+            (escrow.lock_height as u64).to_be_bytes().to_vec(),
+        );
+    }
+    Ok(BASE64.encode(psbt.serialize()))
+}
+
+fn field<'a>(value: &'a Value, pointer: &str) -> Result<&'a Value, AppError> {
+    value
+        .pointer(pointer)
+        .ok_or_else(|| AppError::SerializationError(format!("Response has no {pointer}")))
+}
+
+fn base64_field(value: &Value, pointer: &str) -> Result<Vec<u8>, AppError> {
+    field(value, pointer)?
+        .as_str()
+        .and_then(|encoded| BASE64.decode(encoded).ok())
+        .ok_or_else(|| AppError::SerializationError(format!("{pointer} is not base64")))
+}
+
+pub struct EscrowManager {
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+    database: SharedDatabase,
+    events: SharedEventBus,
+}
+
+impl EscrowManager {
+    pub fn new(
+        client: Client,
+        base_url: String,
+        macaroon_hex: String,
+        database: SharedDatabase,
+        events: SharedEventBus,
+    ) -> Self {
+        Self {
+            client,
+            base_url,
+            macaroon_hex,
+            database,
+            events,
+        }
+    }
+
+    /// Locks `amount` of `asset_id` to a new escrow script key and returns the
+    /// escrow with the address the deposit is paid to. Without a
+    /// `payment_hash`, the gateway picks the preimage and keeps it until
+    /// release.
+    pub async fn create(
+        &self,
+        asset_id: &str,
+        amount: u64,
+        payment_hash: Option<String>,
+        beneficiary_key: &str,
+        timeout_blocks: u32,
+    ) -> Result<Escrow, AppError> {
+        let beneficiary_key = parse_x_only(beneficiary_key)?;
+        let (payment_hash, preimage) = match payment_hash {
+            Some(hash) => (parse_payment_hash(&hash)?, None),
+            None => {
+                let preimage: [u8; 32] = bitcoin::secp256k1::rand::random();
+                (Sha256::digest(preimage).into(), Some(hex::encode(preimage)))
+            }
+        };
+
+        let info = self.tapd_get("/v1/taproot-assets/getinfo").await?;
+        let height = field(&info, "/block_height")?.as_u64().ok_or_else(|| {
+            AppError::SerializationError("block_height is not a number".to_string())
+        })?;
+        let lock_height = u32::try_from(height + timeout_blocks as u64)
+            .map_err(|_| AppError::ValidationError("Lock height is out of range".to_string()))?;
+
+        let key = self
+            .tapd(
+                "/v1/taproot-assets/wallet/internal-key/next",
+                json!({ "key_family": REFUND_KEY_FAMILY }),
+            )
+            .await?;
+        let refund_key = PublicKey::from_slice(&base64_field(&key, "/internal_key/raw_key_bytes")?)
+            .map_err(|e| AppError::SerializationError(format!("Invalid internal key: {e}")))?;
+        let key_loc = field(&key, "/internal_key/key_loc")?;
+        let key_number = |name: &str| {
+            key_loc
+                .get(name)
+                .and_then(Value::as_u64)
+                .unwrap_or_default() as u32
+        };
+
+        let script = EscrowScript::new(
+            payment_hash,
+            beneficiary_key,
+            refund_key.x_only_public_key().0,
+            lock_height,
+        )?;
+        self.tapd(
+            "/v1/taproot-assets/wallet/script-key/declare",
+            json!({ "script_key": script.tapd_script_key() }),
+        )
+        .await?;
+        let addr = self
+            .tapd(
+                "/v1/taproot-assets/addrs",
+                json!({
+                    "asset_id": asset_id,
+                    "amt": amount.to_string(),
+                    "script_key": script.tapd_script_key(),
+                }),
+            )
+            .await?;
+        let address = field(&addr, "/encoded")?
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| AppError::SerializationError("encoded is not a string".to_string()))?;
+
+        let now = chrono::Utc::now().timestamp();
+        let escrow = Escrow {
+            id: uuid::Uuid::new_v4().to_string(),
+            asset_id: asset_id.to_ascii_lowercase(),
+            amount,
+            payment_hash: hex::encode(payment_hash),
+            preimage,
+            beneficiary_key: beneficiary_key.to_string(),
+            refund_key: refund_key.to_string(),
+            refund_key_family: key_number("key_family"),
+            refund_key_index: key_number("key_index"),
+            script_key: script.script_key().to_string(),
+            lock_height,
+            address,
+            status: EscrowStatus::Pending,
+            outpoint: None,
+            refund_txid: None,
+            created_at: now,
+            updated_at: now,
+        };
+        self.database.insert_escrow(&escrow).await?;
+        self.announce(&escrow);
+        info!(
+            "Created escrow {} for {} units, refundable from height {}",
+            escrow.id, amount, lock_height
+        );
+        Ok(escrow)
+    }
+
+    /// Marks a pending escrow funded once tapd has completed a receive to
+    /// its address.
+    pub async fn refresh(&self, mut escrow: Escrow) -> Result<Escrow, AppError> {
+        if escrow.status != EscrowStatus::Pending {
+            return Ok(escrow);
+        }
+        let receives = self
+            .tapd(
+                "/v1/taproot-assets/addrs/receives",
+                json!({ "filter_addr": escrow.address }),
+            )
+            .await?;
+        let outpoint = receives
+            .get("events")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .find(|event| event.get("status").and_then(Value::as_str) == Some(ADDR_EVENT_COMPLETED))
+            .and_then(|event| event.get("outpoint").and_then(Value::as_str));
+        let Some(outpoint) = outpoint else {
+            return Ok(escrow);
+        };
+        escrow.outpoint = Some(outpoint.to_string());
+        escrow.status = EscrowStatus::Funded;
+        self.advance(escrow, EscrowStatus::Pending).await
+    }
+
+    /// Reveals the preimage of a funded escrow, which lets the beneficiary
+    /// claim it. `preimage` is required unless the gateway picked it.
+    pub async fn release(
+        &self,
+        escrow: Escrow,
+        preimage: Option<String>,
+    ) -> Result<Escrow, AppError> {
+        let mut escrow = self.refresh(escrow).await?;
+        if escrow.status != EscrowStatus::Funded {
+            return Err(AppError::ValidationError(format!(
+                "Escrow {} is {} and cannot be released",
+                escrow.id,
+                escrow.status.as_str()
+            )));
+        }
+        let preimage = preimage
+            .or_else(|| escrow.preimage.clone())
+            .ok_or_else(|| AppError::ValidationError("preimage is required".to_string()))?;
+        if !preimage_matches(&preimage, &escrow.payment_hash) {
+            return Err(AppError::ValidationError(
+                "preimage does not match payment_hash".to_string(),
+            ));
+        }
+        escrow.preimage = Some(preimage.trim().to_ascii_lowercase());
+        escrow.status = EscrowStatus::Released;
+        self.advance(escrow, EscrowStatus::Funded).await
+    }
+
+    /// Spends a funded escrow back to this node through the refund leaf,
+    /// once the chain has reached its lock height.
+    pub async fn refund(&self, escrow: Escrow) -> Result<Escrow, AppError> {
+        let mut escrow = self.refresh(escrow).await?;
+        if escrow.status != EscrowStatus::Funded {
+            return Err(AppError::ValidationError(format!(
+                "Escrow {} is {} and cannot be refunded",
+                escrow.id,
+                escrow.status.as_str()
+            )));
+        }
+        let info = self.tapd_get("/v1/taproot-assets/getinfo").await?;
+        let height = field(&info, "/block_height")?.as_u64().unwrap_or_default();
+        if height < escrow.lock_height as u64 {
+            return Err(AppError::ValidationError(format!(
+                "Escrow {} is refundable from height {}; the chain is at {height}",
+                escrow.id, escrow.lock_height
+            )));
+        }
+        let coin_type = match field(&info, "/network")?.as_str() {
+            Some("mainnet") => 0,
+            _ => 1,
+        };
+
+        let outpoint = escrow
+            .outpoint
+            .as_deref()
+            .and_then(|outpoint| OutPoint::from_str(outpoint).ok())
+            .ok_or_else(|| AppError::DatabaseError("Funded escrow has no outpoint".to_string()))?;
+        let script = EscrowScript::of(&escrow)?;
+        let asset_id = hex::decode(&escrow.asset_id)
+            .map_err(|e| AppError::DatabaseError(format!("Invalid asset id: {e}")))?;
+        let mut script_key = vec![0x02];
+        script_key.extend(script.script_key().serialize());
+
+        let destination = self
+            .tapd(
+                "/v1/taproot-assets/addrs",
+                json!({ "asset_id": escrow.asset_id, "amt": escrow.amount.to_string() }),
+            )
+            .await?;
+        let mut recipients = serde_json::Map::new();
+        recipients.insert(
+            field(&destination, "/encoded")?
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            json!(escrow.amount),
+        );
+        let funded = self
+            .tapd(
+                "/v1/taproot-assets/wallet/virtual-psbt/fund",
+                json!({
+                    "raw": {
+                        "inputs": [{
+                            "outpoint": {
+                                "txid": BASE64.encode(outpoint.txid.as_byte_array()),
+                                "output_index": outpoint.vout,
+                            },
+                            "id": BASE64.encode(asset_id),
+                            "script_key": BASE64.encode(script_key),
+                        }],
+                        "recipients": recipients,
+                    },
+                }),
+            )
+            .await?;
+        let funded = field(&funded, "/funded_psbt")?.as_str().unwrap_or_default();
+        let prepared = refund_psbt(funded, &script, &escrow, coin_type)?;
+        let signed = self
+            .tapd(
+                "/v1/taproot-assets/wallet/virtual-psbt/sign",
+                json!({ "funded_psbt": prepared }),
+            )
+            .await?;
+        let anchored = self
+            .tapd(
+                "/v1/taproot-assets/wallet/virtual-psbt/anchor",
+                json!({ "virtual_psbts": [field(&signed, "/signed_psbt")?] }),
+            )
+            .await?;
+        let txid = Txid::from_slice(&base64_field(&anchored, "/transfer/anchor_tx_hash")?)
+            .map_err(|e| AppError::SerializationError(format!("Invalid anchor hash: {e}")))?;
+
+        escrow.refund_txid = Some(txid.to_string());
+        escrow.status = EscrowStatus::Refunded;
+        info!("Refunded escrow {} in {}", escrow.id, txid);
+        self.advance(escrow, EscrowStatus::Funded).await
+    }
+
+    async fn advance(&self, mut escrow: Escrow, from: EscrowStatus) -> Result<Escrow, AppError> {
+        escrow.updated_at = chrono::Utc::now().timestamp();
+        if !self.database.advance_escrow(&escrow, from).await? {
+            return Err(AppError::ValidationError(format!(
+                "Escrow {} changed concurrently",
+                escrow.id
+            )));
+        }
+        self.announce(&escrow);
+        Ok(escrow)
+    }
+
+    async fn tapd(&self, path: &str, body: Value) -> Result<Value, AppError> {
+        backend(&self.client, &self.base_url, &self.macaroon_hex)
+            .post(path)
+            .json(&body)
+            .fetch::<Value>()
+            .await
+    }
+
+    async fn tapd_get(&self, path: &str) -> Result<Value, AppError> {
+        backend(&self.client, &self.base_url, &self.macaroon_hex)
+            .get(path)
+            .fetch::<Value>()
+            .await
+    }
+
+    fn announce(&self, escrow: &Escrow) {
+        let topic = format!("{TOPIC_PREFIX}{}", escrow.status.as_str());
+        match serde_json::to_value(escrow.clone().revealed()) {
+            Ok(payload) => {
+                self.events.publish(&topic, payload);
+            }
+            Err(e) => tracing::warn!("Failed to publish escrow {}: {}", escrow.id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{absolute, transaction, Amount, Sequence, Transaction, TxIn, TxOut, Witness};
+
+    fn key(byte: u8) -> XOnlyPublicKey {
+        let secret = bitcoin::secp256k1::SecretKey::from_slice(&[byte; 32]).unwrap();
+        secret.x_only_public_key(&Secp256k1::new()).0
+    }
+
+    fn escrow(script: &EscrowScript, refund: XOnlyPublicKey) -> Escrow {
+        Escrow {
+            id: "e".to_string(),
+            asset_id: "aa".repeat(32),
+            amount: 10,
+            payment_hash: hex::encode([7u8; 32]),
+            preimage: None,
+            beneficiary_key: key(1).to_string(),
+            refund_key: refund.to_string(),
+            refund_key_family: REFUND_KEY_FAMILY,
+            refund_key_index: 4,
+            script_key: script.script_key().to_string(),
+            lock_height: 800,
+            address: "taprt1...".to_string(),
+            status: EscrowStatus::Funded,
+            outpoint: None,
+            refund_txid: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_script_tree_commits_to_both_leaves() {
+        let script = EscrowScript::new([7u8; 32], key(1), key(2), 800).unwrap();
+        let escrow = escrow(&script, key(2));
+        assert_eq!(
+            EscrowScript::of(&escrow).unwrap().script_key(),
+            script.script_key()
+        );
+        // Another lock height is another script key
+        let later = EscrowScript::new([7u8; 32], key(1), key(2), 801).unwrap();
+        assert_ne!(later.script_key(), script.script_key());
+
+        let secp = Secp256k1::verification_only();
+        for leaf in [&script.release, &script.refund] {
+            let control_block = script.control_block(leaf).unwrap();
+            assert!(control_block.verify_taproot_commitment(&secp, script.script_key(), leaf));
+        }
+        let described = script.describe().unwrap();
+        assert_eq!(described["internal_key"], NUMS_KEY);
+        assert!(described["release"]["script"]
+            .as_str()
+            .unwrap()
+            .contains(&hex::encode([7u8; 32])));
+    }
+
+    #[test]
+    fn test_keys_and_preimages() {
+        let x_only = key(3);
+        let compressed = x_only
+            .public_key(bitcoin::secp256k1::Parity::Odd)
+            .to_string();
+        assert_eq!(parse_x_only(&compressed).unwrap(), x_only);
+        assert_eq!(parse_x_only(&x_only.to_string()).unwrap(), x_only);
+        assert!(parse_x_only("abcd").is_err());
+
+        let preimage = hex::encode([9u8; 32]);
+        let hash = hex::encode(Sha256::digest([9u8; 32]));
+        assert!(preimage_matches(&preimage, &hash));
+        assert!(preimage_matches(&preimage, &hash.to_ascii_uppercase()));
+        assert!(!preimage_matches(&hash, &hash));
+        assert!(parse_payment_hash(&hash).is_ok());
+        assert!(parse_payment_hash("00").is_err());
+    }
+
+    #[test]
+    fn test_refund_psbt_selects_the_refund_leaf() {
+        let script = EscrowScript::new([7u8; 32], key(1), key(2), 800).unwrap();
+        let escrow = escrow(&script, key(2));
+        let psbt = Psbt::from_unsigned_tx(Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        })
+        .unwrap();
+        let prepared = refund_psbt(&BASE64.encode(psbt.serialize()), &script, &escrow, 1).unwrap();
+
+        let prepared = Psbt::deserialize(&BASE64.decode(prepared).unwrap()).unwrap();
+        let input = &prepared.inputs[0];
+        assert_eq!(input.tap_internal_key, Some(nums_key()));
+        assert_eq!(input.tap_scripts.values().next().unwrap().0, script.refund);
+        let (leaves, (_, path)) = &input.tap_key_origins[&key(2)];
+        assert_eq!(leaves.len(), 1);
+        assert_eq!(path.to_string(), "1017'/1'/212'/0/4");
+        let lock_time = prepared.outputs[0]
+            .unknown
+            .iter()
+            .find(|(key, _)| key.type_value == PSBT_OUT_TAP_LOCK_TIME)
+            .unwrap()
+            .1;
+        assert_eq!(lock_time, &800u64.to_be_bytes().to_vec());
+    }
+}
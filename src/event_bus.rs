@@ -0,0 +1,197 @@
+//! In-process publish/subscribe bus for events the gateway itself produces
+//! (transfer confirmations, reorgs, ...), as opposed to events proxied from
+//! tapd. Subscribers that fall behind lose the oldest events rather than
+//! blocking publishers. The most recent events are also kept in a replay
+//! buffer, so clients that cannot hold a connection open can ask for what
+//! happened after the last sequence number they saw.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Number of events buffered per subscriber before it starts lagging, and
+/// kept for replay.
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// Upper bound of `EVENT_POLL_MAX_SECS`.
+pub const MAX_POLL_SECS: u64 = 300;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GatewayEvent {
+    /// Monotonic per-process sequence number.
+    pub sequence: u64,
+    /// Dotted event name, e.g. `transfer.confirmed`.
+    pub topic: String,
+    pub timestamp: i64,
+    pub data: Value,
+}
+
+/// Events after a cursor, from the replay buffer.
+#[derive(Debug, Clone, Serialize)]
+pub struct Replay {
+    pub events: Vec<GatewayEvent>,
+    /// Sequence to resume from: the last event scanned, which may be past
+    /// the last event returned when a filter skipped some.
+    pub cursor: u64,
+    /// Events after the cursor were dropped from the buffer, or the cursor
+    /// is from before a gateway restart.
+    pub missed: bool,
+}
+
+struct Recent {
+    /// Sequence of the last event published.
+    sequence: u64,
+    events: VecDeque<GatewayEvent>,
+}
+
+pub struct EventBus {
+    sender: broadcast::Sender<GatewayEvent>,
+    capacity: usize,
+    recent: Mutex<Recent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            capacity,
+            recent: Mutex::new(Recent {
+                sequence: 0,
+                events: VecDeque::with_capacity(capacity),
+            }),
+        }
+    }
+
+    /// Publishes an event and returns its sequence number. Publishing with no
+    /// subscribers is not an error.
+    pub fn publish(&self, topic: &str, data: Value) -> u64 {
+        // Held while sending so the buffer and subscribers see one order
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        recent.sequence += 1;
+        let event = GatewayEvent {
+            sequence: recent.sequence,
+            topic: topic.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+            data,
+        };
+        if recent.events.len() == self.capacity {
+            recent.events.pop_front();
+        }
+        recent.events.push_back(event.clone());
+        let _ = self.sender.send(event);
+        recent.sequence
+    }
+
+    /// Sequence of the last event published; 0 before the first.
+    pub fn last_sequence(&self) -> u64 {
+        self.recent
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .sequence
+    }
+
+    /// Up to `limit` buffered events after `cursor` that pass `filter`.
+    pub fn replay(
+        &self,
+        cursor: u64,
+        limit: usize,
+        filter: impl Fn(&GatewayEvent) -> bool,
+    ) -> Replay {
+        let recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        // Sequences restart with the process, so a cursor ahead of them is stale
+        let (cursor, restarted) = if cursor > recent.sequence {
+            (0, true)
+        } else {
+            (cursor, false)
+        };
+        let oldest = recent
+            .events
+            .front()
+            .map_or(recent.sequence + 1, |event| event.sequence);
+        let mut replay = Replay {
+            events: Vec::new(),
+            cursor,
+            missed: restarted || cursor + 1 < oldest,
+        };
+        for event in recent.events.iter().filter(|e| e.sequence > cursor) {
+            if replay.events.len() == limit {
+                break;
+            }
+            if filter(event) {
+                replay.events.push(event.clone());
+            }
+            replay.cursor = event.sequence;
+        }
+        replay
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<GatewayEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+pub type SharedEventBus = Arc<EventBus>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribers_receive_events_in_sequence() {
+        let bus = EventBus::default();
+        let mut rx = bus.subscribe();
+        bus.publish("transfer.confirmed", serde_json::json!({ "id": "a" }));
+        bus.publish("transfer.reorged", serde_json::json!({ "id": "b" }));
+
+        let first = rx.recv().await.unwrap();
+        let second = rx.recv().await.unwrap();
+        assert_eq!(first.topic, "transfer.confirmed");
+        assert_eq!(second.sequence, first.sequence + 1);
+    }
+
+    #[test]
+    fn test_publish_without_subscribers() {
+        let bus = EventBus::default();
+        assert_eq!(bus.publish("x", Value::Null), 1);
+    }
+
+    #[test]
+    fn test_replay_resumes_after_cursor() {
+        let bus = EventBus::new(3);
+        for topic in ["a", "b", "a", "b"] {
+            bus.publish(topic, Value::Null);
+        }
+        // Event 1 fell out of the buffer
+        let all = bus.replay(0, 10, |_| true);
+        assert!(all.missed);
+        assert_eq!(all.events.len(), 3);
+        assert_eq!(all.cursor, 4);
+
+        let only_a = bus.replay(1, 10, |e| e.topic == "a");
+        assert!(!only_a.missed);
+        assert_eq!(only_a.events.len(), 1);
+        assert_eq!(only_a.cursor, 4);
+
+        let limited = bus.replay(1, 1, |_| true);
+        assert_eq!(limited.cursor, 2);
+        assert!(bus.replay(4, 10, |_| true).events.is_empty());
+
+        let stale = bus.replay(99, 10, |_| true);
+        assert!(stale.missed);
+        assert_eq!(stale.events.len(), 3);
+    }
+}
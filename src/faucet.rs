@@ -0,0 +1,189 @@
+//! Public faucet for test networks: anyone may ask for a small amount of
+//! one configured test asset at `POST /v1/gateway/faucet`, without an API
+//! key, for hackathons and SDK onboarding. Claims are limited per client IP
+//! and per receiving address over a sliding window, kept in memory, and the
+//! faucet refuses to pay unless tapd reports a test network. A claim is
+//! counted before tapd is asked anything and stays counted when the
+//! address is refused, so quotas also bound the tapd calls anonymous callers
+//! cause; only a failed send gives it back. Callers whose address cannot be
+//! told apart are refused rather than sharing one quota.
+
+use crate::config::Config;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// The faucet route, which skips API key authentication when the faucet is
+/// enabled.
+pub const FAUCET_PATH: &str = "/v1/gateway/faucet";
+
+/// Networks tapd reports that the faucet may run on.
+const TEST_NETWORKS: &[&str] = &[
+    "regtest", "simnet", "signet", "testnet", "testnet3", "testnet4",
+];
+
+pub fn is_test_network(network: &str) -> bool {
+    TEST_NETWORKS.contains(&network)
+}
+
+/// Claims counted against quotas. Dropping it without [`Faucet::release`]
+/// keeps them counted.
+#[derive(Debug)]
+pub struct Reservation {
+    keys: [String; 2],
+    at: Instant,
+}
+
+#[derive(Debug)]
+pub struct Faucet {
+    asset_id: String,
+    max_amount: u64,
+    window: Duration,
+    ip_limit: u32,
+    address_limit: u32,
+    /// Claim times by quota key, oldest first.
+    claims: Mutex<HashMap<String, Vec<Instant>>>,
+    /// tapd's network, once read.
+    network: OnceLock<String>,
+}
+
+pub type SharedFaucet = Arc<Faucet>;
+
+impl Faucet {
+    pub fn new(
+        asset_id: String,
+        max_amount: u64,
+        window: Duration,
+        ip_limit: u32,
+        address_limit: u32,
+    ) -> Self {
+        Self {
+            asset_id: asset_id.to_ascii_lowercase(),
+            max_amount,
+            window,
+            ip_limit,
+            address_limit,
+            claims: Mutex::new(HashMap::new()),
+            network: OnceLock::new(),
+        }
+    }
+
+    /// The faucet `config` enables, if any.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let asset_id = config
+            .faucet_asset_id
+            .clone()
+            .filter(|_| config.faucet_enabled)?;
+        Some(Self::new(
+            asset_id,
+            config.faucet_max_amount,
+            Duration::from_secs(config.faucet_window_secs),
+            config.faucet_ip_limit,
+            config.faucet_address_limit,
+        ))
+    }
+
+    pub fn asset_id(&self) -> &str {
+        &self.asset_id
+    }
+
+    pub fn max_amount(&self) -> u64 {
+        self.max_amount
+    }
+
+    pub fn network(&self) -> Option<&str> {
+        self.network.get().map(String::as_str)
+    }
+
+    /// Remembers tapd's network; a node does not change networks.
+    pub fn set_network(&self, network: &str) {
+        let _ = self.network.set(network.to_string());
+    }
+
+    /// Counts a claim by `ip` for `address`, or returns the seconds until
+    /// both quotas allow one.
+    pub fn reserve(&self, ip: IpAddr, address: &str, now: Instant) -> Result<Reservation, u64> {
+        let keys = [
+            format!("ip:{ip}"),
+            format!("addr:{}", address.trim().to_ascii_lowercase()),
+        ];
+        let mut claims = self.claims.lock().unwrap_or_else(|e| e.into_inner());
+        claims.retain(|_, times| {
+            times.retain(|at| now.saturating_duration_since(*at) < self.window);
+            !times.is_empty()
+        });
+
+        let retry_after = keys
+            .iter()
+            .zip([self.ip_limit, self.address_limit])
+            .filter_map(|(key, limit)| {
+                let times = claims.get(key)?;
+                // The claim that must age out before another fits
+                let oldest = times.len().checked_sub(limit as usize)?;
+                let frees_in = self
+                    .window
+                    .saturating_sub(now.saturating_duration_since(times[oldest]));
+                Some(frees_in.as_secs().max(1))
+            })
+            .max();
+        if let Some(secs) = retry_after {
+            return Err(secs);
+        }
+        for key in &keys {
+            claims.entry(key.clone()).or_default().push(now);
+        }
+        Ok(Reservation { keys, at: now })
+    }
+
+    /// Gives back a claim whose payment failed.
+    pub fn release(&self, reservation: Reservation) {
+        let mut claims = self.claims.lock().unwrap_or_else(|e| e.into_inner());
+        for key in &reservation.keys {
+            if let Some(times) = claims.get_mut(key) {
+                if let Some(index) = times.iter().rposition(|at| *at == reservation.at) {
+                    times.remove(index);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quotas_per_ip_and_address() {
+        let faucet = Faucet::new("AB".repeat(32), 100, Duration::from_secs(60), 2, 1);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+
+        let first = faucet.reserve(ip, "taprt1aaa", start).unwrap();
+        // One claim per address
+        assert_eq!(faucet.reserve(ip, "TAPRT1AAA", start).unwrap_err(), 60);
+        // A released claim no longer counts
+        faucet.release(first);
+        faucet.reserve(ip, "taprt1aaa", start).unwrap();
+        faucet
+            .reserve(ip, "taprt1bbb", start + Duration::from_secs(10))
+            .unwrap();
+        // Two claims per IP, the first freeing up after the window
+        assert_eq!(
+            faucet
+                .reserve(ip, "taprt1ccc", start + Duration::from_secs(20))
+                .unwrap_err(),
+            40
+        );
+        assert!(faucet
+            .reserve("10.0.0.2".parse().unwrap(), "taprt1ccc", start)
+            .is_ok());
+        assert!(faucet
+            .reserve(ip, "taprt1ddd", start + Duration::from_secs(61))
+            .is_ok());
+
+        assert_eq!(faucet.asset_id(), "ab".repeat(32));
+        assert!(is_test_network("regtest"));
+        assert!(!is_test_network("mainnet"));
+    }
+}
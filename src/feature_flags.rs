@@ -0,0 +1,205 @@
+//! Feature flags gating experimental subsystems, so they can ship dark and
+//! be opened to one API key at a time. A flag's state for a request is, in
+//! order of precedence: an override for the caller's API key, an override
+//! for the whole deployment, then `FEATURE_FLAGS`. Overrides are set through
+//! the admin API and stored in the database. A disabled feature's routes
+//! answer `404` as if they were not mounted.
+//!
+//! Flags only gate; a feature with its own switch (`SWAPS_ENABLED`) still
+//! needs it.
+
+use crate::error::AppError;
+use crate::usage;
+use actix_web::{web, HttpRequest};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Feature {
+    /// The `/graphql` facade, when built with the `graphql` cargo feature.
+    Graphql,
+    /// Atomic swaps under `/v1/gateway/swaps`.
+    Swaps,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 2] = [Self::Graphql, Self::Swaps];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Graphql => "graphql",
+            Self::Swaps => "swaps",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|feature| feature.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// A flag set through the admin API, and when.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FlagOverride {
+    pub enabled: bool,
+    /// Unix seconds of the change.
+    pub updated_at: i64,
+}
+
+/// State of one flag, as reported by the admin API.
+#[derive(Debug, Clone, Serialize)]
+pub struct FeatureStatus {
+    pub feature: Feature,
+    /// State for callers without a key override.
+    pub enabled: bool,
+    /// State from `FEATURE_FLAGS`.
+    pub default: bool,
+    pub deployment: Option<FlagOverride>,
+    /// Overrides by API key id (see `/v1/gateway/usage`).
+    pub api_keys: BTreeMap<String, FlagOverride>,
+}
+
+#[derive(Debug, Default)]
+struct Overrides {
+    deployment: HashMap<Feature, FlagOverride>,
+    api_keys: HashMap<(Feature, String), FlagOverride>,
+}
+
+/// Flags shared by the gated handlers and the admin API.
+#[derive(Debug)]
+pub struct FeatureFlags {
+    defaults: HashMap<Feature, bool>,
+    overrides: RwLock<Overrides>,
+}
+
+pub type SharedFeatureFlags = Arc<FeatureFlags>;
+
+impl FeatureFlags {
+    /// Flags not named in `defaults` start enabled.
+    pub fn new(defaults: &[(Feature, bool)]) -> Self {
+        Self {
+            defaults: defaults.iter().copied().collect(),
+            overrides: RwLock::new(Overrides::default()),
+        }
+    }
+
+    fn default_state(&self, feature: Feature) -> bool {
+        self.defaults.get(&feature).copied().unwrap_or(true)
+    }
+
+    /// Whether `feature` is on for the API key `key_id`, or for callers
+    /// without a key override when `None`.
+    pub fn is_enabled(&self, feature: Feature, key_id: Option<&str>) -> bool {
+        let overrides = self.overrides.read().unwrap_or_else(|e| e.into_inner());
+        key_id
+            .and_then(|key| overrides.api_keys.get(&(feature, key.to_string())))
+            .or_else(|| overrides.deployment.get(&feature))
+            .map_or_else(|| self.default_state(feature), |o| o.enabled)
+    }
+
+    /// Sets `feature` for the API key `key_id`, or for the deployment.
+    pub fn set(&self, feature: Feature, key_id: Option<&str>, enabled: bool, updated_at: i64) {
+        let flag = FlagOverride {
+            enabled,
+            updated_at,
+        };
+        let mut overrides = self.overrides.write().unwrap_or_else(|e| e.into_inner());
+        match key_id {
+            Some(key) => overrides.api_keys.insert((feature, key.to_string()), flag),
+            None => overrides.deployment.insert(feature, flag),
+        };
+    }
+
+    /// Drops an override, returning to the next level of precedence.
+    /// Returns whether there was one.
+    pub fn clear(&self, feature: Feature, key_id: Option<&str>) -> bool {
+        let mut overrides = self.overrides.write().unwrap_or_else(|e| e.into_inner());
+        match key_id {
+            Some(key) => overrides
+                .api_keys
+                .remove(&(feature, key.to_string()))
+                .is_some(),
+            None => overrides.deployment.remove(&feature).is_some(),
+        }
+    }
+
+    pub fn status(&self, feature: Feature) -> FeatureStatus {
+        let overrides = self.overrides.read().unwrap_or_else(|e| e.into_inner());
+        let deployment = overrides.deployment.get(&feature).copied();
+        FeatureStatus {
+            feature,
+            enabled: deployment.map_or_else(|| self.default_state(feature), |o| o.enabled),
+            default: self.default_state(feature),
+            deployment,
+            api_keys: overrides
+                .api_keys
+                .iter()
+                .filter(|((f, _), _)| *f == feature)
+                .map(|((_, key), flag)| (key.clone(), *flag))
+                .collect(),
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<FeatureStatus> {
+        Feature::ALL
+            .into_iter()
+            .map(|feature| self.status(feature))
+            .collect()
+    }
+}
+
+/// Refuses the request with `404` unless `feature` is on for its caller.
+/// Without flags configured (an embedder that did not set them up) every
+/// feature is on.
+pub fn require(req: &HttpRequest, feature: Feature) -> Result<(), AppError> {
+    let Some(flags) = req.app_data::<web::Data<SharedFeatureFlags>>() else {
+        return Ok(());
+    };
+    let key_id = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(usage::key_id);
+    if flags.is_enabled(feature, key_id.as_deref()) {
+        Ok(())
+    } else {
+        Err(AppError::NotFound(format!("No route for {}", req.path())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_overrides_win_over_deployment_and_defaults() {
+        let flags = FeatureFlags::new(&[(Feature::Graphql, false)]);
+        assert!(!flags.is_enabled(Feature::Graphql, None));
+        assert!(flags.is_enabled(Feature::Swaps, Some("k1")));
+
+        flags.set(Feature::Graphql, Some("k1"), true, 10);
+        assert!(flags.is_enabled(Feature::Graphql, Some("k1")));
+        assert!(!flags.is_enabled(Feature::Graphql, Some("k2")));
+
+        flags.set(Feature::Swaps, None, false, 20);
+        flags.set(Feature::Swaps, Some("k1"), true, 30);
+        assert!(!flags.is_enabled(Feature::Swaps, Some("k2")));
+        assert!(flags.is_enabled(Feature::Swaps, Some("k1")));
+
+        let status = flags.status(Feature::Swaps);
+        assert!(!status.enabled);
+        assert!(status.default);
+        assert_eq!(status.deployment.map(|o| o.updated_at), Some(20));
+        assert_eq!(status.api_keys.len(), 1);
+
+        assert!(flags.clear(Feature::Swaps, None));
+        assert!(!flags.clear(Feature::Swaps, None));
+        assert!(flags.is_enabled(Feature::Swaps, Some("k2")));
+        assert_eq!(Feature::parse(" GraphQL "), Some(Feature::Graphql));
+        assert_eq!(Feature::parse("ws"), None);
+    }
+}
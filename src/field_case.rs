@@ -0,0 +1,214 @@
+use crate::error::AppError;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Objects keyed by data rather than by field names: asset IDs, group keys
+/// and universe IDs. Their own name is converted, but the keys inside are
+/// passed through unchanged.
+pub const PRESERVED_KEY_FIELDS: &[&str] =
+    &["asset_balances", "asset_group_balances", "universe_roots"];
+
+/// camelCase spellings the generic rule splits wrongly, with their
+/// snake_case names. A plural acronym looks like an acronym followed by a
+/// word (`IDs` would become `i_ds`).
+pub const CAMEL_ALIASES: &[(&str, &str)] = &[
+    ("assetIDs", "asset_ids"),
+    ("anchorTXIDs", "anchor_txids"),
+    ("groupKeyIDs", "group_key_ids"),
+];
+
+/// The naming convention JSON responses are rewritten to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldCase {
+    Snake,
+    Camel,
+}
+
+impl FieldCase {
+    /// Parses `RESPONSE_FIELD_CASE`.
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "snake" | "snake_case" => Ok(FieldCase::Snake),
+            "camel" | "camelcase" => Ok(FieldCase::Camel),
+            other => Err(AppError::ValidationError(format!(
+                "RESPONSE_FIELD_CASE must be 'snake' or 'camel', got '{other}'"
+            ))),
+        }
+    }
+
+    /// Converts one field name.
+    pub fn convert(&self, name: &str) -> String {
+        match self {
+            FieldCase::Snake => to_snake(name),
+            FieldCase::Camel => to_camel(name),
+        }
+    }
+}
+
+/// `assetId`, `assetID` and `asset_id` all become `asset_id`.
+pub fn to_snake(name: &str) -> String {
+    match CAMEL_ALIASES.iter().find(|(camel, _)| *camel == name) {
+        Some((_, snake)) => snake.to_string(),
+        None => generic_snake(name),
+    }
+}
+
+fn generic_snake(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() {
+            let prev = i.checked_sub(1).map(|p| chars[p]);
+            let next = chars.get(i + 1);
+            // A boundary after a lowercase letter or digit, and before the
+            // last capital of an acronym run (`TXIDHash` -> `txid_hash`)
+            let boundary = match prev {
+                Some(p) if p.is_ascii_lowercase() || p.is_ascii_digit() => true,
+                Some(p) if p.is_ascii_uppercase() => next.is_some_and(|n| n.is_ascii_lowercase()),
+                _ => false,
+            };
+            if boundary && !out.ends_with('_') {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// `asset_id` becomes `assetId`. Leading underscores are kept, and names
+/// without underscores are returned unchanged.
+pub fn to_camel(name: &str) -> String {
+    let trimmed = name.trim_start_matches('_');
+    let mut out = name[..name.len() - trimmed.len()].to_string();
+    let mut upper = false;
+    for c in trimmed.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Renames every object key in `value` to `case`, at any depth. When two
+/// keys map to the same name, the first one kept wins.
+pub fn normalize(value: &mut Value, case: FieldCase) {
+    match value {
+        Value::Object(map) => {
+            let mut renamed = Map::with_capacity(map.len());
+            for (key, mut child) in std::mem::take(map) {
+                if PRESERVED_KEY_FIELDS.contains(&to_snake(&key).as_str()) {
+                    preserve_keys(&mut child, case);
+                } else {
+                    normalize(&mut child, case);
+                }
+                renamed.entry(case.convert(&key)).or_insert(child);
+            }
+            *map = renamed;
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| normalize(v, case)),
+        _ => {}
+    }
+}
+
+/// Leaves the keys of a data-keyed object alone but normalizes its values.
+fn preserve_keys(value: &mut Value, case: FieldCase) {
+    match value {
+        Value::Object(map) => map.values_mut().for_each(|v| normalize(v, case)),
+        other => normalize(other, case),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Field names tapd returns, which must survive a round trip.
+    const TAPD_FIELDS: &[&str] = &[
+        "asset_id",
+        "asset_id_str",
+        "tweaked_group_key",
+        "anchor_outpoint",
+        "anchor_tx_hash",
+        "script_key_is_local",
+        "taproot_asset_root",
+        "amt_sat",
+        "funded_psbt",
+        "raw_key_bytes",
+        "proof_courier_addr",
+        "asset_version",
+        "txid",
+    ];
+
+    #[test]
+    fn test_field_names_round_trip() {
+        for name in TAPD_FIELDS {
+            assert_eq!(to_snake(&to_camel(name)), *name, "{name}");
+        }
+        assert_eq!(to_camel("anchor_tx_hash"), "anchorTxHash");
+        assert_eq!(to_snake("assetID"), "asset_id");
+        assert_eq!(to_snake("anchorTXIDHash"), "anchor_txid_hash");
+        assert_eq!(to_camel("_internal"), "_internal");
+        assert_eq!(to_snake("@type"), "@type");
+    }
+
+    #[test]
+    fn test_camel_aliases_are_needed_and_round_trip() {
+        for (camel, snake) in CAMEL_ALIASES {
+            assert_ne!(generic_snake(camel), *snake, "{camel} needs no alias");
+            assert_eq!(to_snake(camel), *snake);
+            assert_eq!(to_snake(&to_camel(snake)), *snake);
+        }
+    }
+
+    #[test]
+    fn test_preserved_fields_are_snake_case() {
+        for field in PRESERVED_KEY_FIELDS {
+            assert_eq!(to_snake(field), *field);
+            assert_ne!(to_camel(field), *field);
+        }
+    }
+
+    #[test]
+    fn test_normalize_nested_and_preserves_data_keys() {
+        let mut value = json!({
+            "transfers": [
+                { "anchor_tx_hash": "ff", "transferTimestamp": "1", "outputs": [
+                    { "scriptKey": "02aa", "script_key_is_local": true }
+                ] }
+            ],
+            "asset_balances": { "aa_bb": { "asset_genesis": { "asset_id": "aa" } } }
+        });
+        normalize(&mut value, FieldCase::Camel);
+        assert_eq!(
+            value,
+            json!({
+                "transfers": [
+                    { "anchorTxHash": "ff", "transferTimestamp": "1", "outputs": [
+                        { "scriptKey": "02aa", "scriptKeyIsLocal": true }
+                    ] }
+                ],
+                "assetBalances": { "aa_bb": { "assetGenesis": { "assetId": "aa" } } }
+            })
+        );
+        normalize(&mut value, FieldCase::Snake);
+        assert_eq!(value["transfers"][0]["transfer_timestamp"], "1");
+        assert!(value["asset_balances"]["aa_bb"]["asset_genesis"]["asset_id"].is_string());
+    }
+
+    #[test]
+    fn test_parse_field_case() {
+        assert_eq!(FieldCase::parse(" Camel ").unwrap(), FieldCase::Camel);
+        assert_eq!(FieldCase::parse("snake_case").unwrap(), FieldCase::Snake);
+        assert!(FieldCase::parse("kebab").is_err());
+    }
+}
@@ -0,0 +1,1274 @@
+//! Library-mode entry point. [`Gateway::builder`] performs the same startup
+//! as the binary (backend client, WebSocket proxy, database, background
+//! tasks) and [`Gateway::configure`] mounts the routes into an actix `App`
+//! owned by the embedding application:
+//!
+//! ```rust,ignore
+//! use taproot_assets_rest_gateway::{config::Config, gateway::Gateway};
+//!
+//! let gateway = Gateway::builder()
+//!     .config(Config::load()?)
+//!     .route_filter(|path| !path.starts_with("/v1/taproot-assets/stop"))
+//!     .build()
+//!     .await?;
+//! HttpServer::new(move || {
+//!     let gateway = gateway.clone();
+//!     App::new().service(web::scope("/tap").configure(move |cfg| gateway.configure(cfg)))
+//! })
+//! ```
+//!
+//! Authentication, CORS and rate limiting stay with the embedding
+//! application; the middleware the binary uses is public in
+//! [`crate::middleware`] and can wrap the mount point.
+
+// The binary uses only part of the embedding API
+#![allow(dead_code)]
+
+use crate::address_expiry;
+use crate::anomaly;
+use crate::api;
+use crate::api::public::{PublicApi, SharedPublicApi};
+use crate::audit::{AuditExporter, AuditTarget, SharedAuditExporter};
+use crate::cache_audit::{CacheAudit, SharedCacheAudit};
+use crate::chain::LndChainSource;
+use crate::channel_events::{ChannelEventAggregator, LndSource};
+use crate::config::Config;
+use crate::crypto::SharedResponseSigner;
+use crate::database::{self, SharedDatabase};
+use crate::destination_guard::DestinationGuard;
+use crate::error::StatusMapping;
+use crate::event_bus::{EventBus, SharedEventBus};
+use crate::faucet::{Faucet, SharedFaucet};
+use crate::feature_flags::{Feature, FeatureFlags, SharedFeatureFlags};
+use crate::indexer::{Indexer, ReceivePolicy};
+use crate::lifetime_stats::{self, LifetimeStats, SharedLifetimeStats};
+use crate::lockout::{AuthLockouts, SharedAuthLockouts};
+use crate::mailbox_outbox::{self, MailboxOutbox, RetentionPolicy};
+use crate::maintenance::{MaintenanceMode, SharedMaintenance};
+use crate::middleware::BackendTimeouts;
+use crate::monitor::{Monitor, MonitorSources, SharedMonitor};
+use crate::network_acl::{AclRule, NetworkAcl, SharedNetworkAcl};
+use crate::notifier::Notifier;
+use crate::oidc::{OidcAuthenticator, SharedOidcAuthenticator};
+use crate::outbound_proxy::OutboundProxy;
+use crate::payment_requests::PaymentRequestTracker;
+use crate::priority::{PriorityLimiter, SharedPriorityLimiter};
+use crate::rate_limit::{RateLimits, SharedRateLimits};
+use crate::recurring_payments::RecurringPayer;
+use crate::route_groups::{RouteGroup, RouteGroups, SharedRouteGroups};
+use crate::scheduler::{
+    self, Schedule, Scheduler, SharedScheduler, JOB_ADDRESS_EXPIRY, JOB_MAILBOX_GC,
+    JOB_MAILBOX_OUTBOX, JOB_PROOF_CACHE_PRUNE, JOB_RECURRING_PAYMENTS, JOB_RESOURCE_CHECK,
+    JOB_STATS_FLUSH, JOB_SWAP_EXPIRY, JOB_UNIVERSE_SYNC_RETRY, JOB_USAGE_FLUSH, JOB_UTXO_CHECK,
+};
+use crate::sessions::{SessionManager, SharedSessionManager};
+use crate::shed::{self, ResourceUsage, SharedShed, ShedMode, ShedThresholds};
+use crate::swaps::SwapCoordinator;
+use crate::tenants::{SharedTenantRouter, Tenant, TenantRouter};
+use crate::timeouts::{SharedTimeoutTuner, TimeoutPolicy, TimeoutTuner};
+use crate::types::{BaseUrl, LndNode, MacaroonHex};
+use crate::universe_sync::UniverseSyncRunner;
+use crate::usage::{SharedUsageMeter, UsageMeter};
+use crate::utxo_analytics::{FragmentationThresholds, FragmentationWatch};
+use crate::warmup::{SharedWarmup, Warmup};
+use crate::webhooks::WebhookDispatcher;
+use crate::websocket::{
+    connection_manager::WebSocketConnectionManager, proxy_handler::WebSocketProxyHandler,
+};
+use actix_web::{guard, web};
+use reqwest::Client;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Largest request body accepted by the gateway's routes.
+pub const MAX_PAYLOAD_SIZE: usize = 10 * 1024 * 1024;
+
+/// Decides whether a request path (as received, including any mount prefix)
+/// is served by the gateway. Rejected paths fall through to the embedding
+/// application's other routes, or 404.
+pub type RouteFilter = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+#[derive(Default)]
+pub struct GatewayBuilder {
+    config: Option<Config>,
+    client: Option<Client>,
+    macaroon_hex: Option<String>,
+    route_filter: Option<RouteFilter>,
+}
+
+impl GatewayBuilder {
+    /// Defaults to [`Config::load`], which reads the environment.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// HTTP client for tapd and lnd. Defaults to one honoring the config's
+    /// request timeout, TLS verification and backend proxy settings.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Hex-encoded tapd macaroon. Defaults to the file at `macaroon_path`.
+    pub fn macaroon_hex(mut self, macaroon_hex: impl Into<String>) -> Self {
+        self.macaroon_hex = Some(macaroon_hex.into());
+        self
+    }
+
+    /// Serves only the request paths `filter` accepts.
+    pub fn route_filter(mut self, filter: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.route_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Opens the database and starts the background tasks the config
+    /// enables. Must run inside the actix (tokio) runtime.
+    pub async fn build(self) -> std::io::Result<Gateway> {
+        let config = match self.config {
+            Some(config) => config,
+            None => Config::load().map_err(|e| std::io::Error::other(e.to_string()))?,
+        };
+        let macaroon_hex = match self.macaroon_hex {
+            Some(hex) => hex,
+            None if config.macaroon_path.is_empty() => String::new(),
+            None => hex::encode(fs::read(&config.macaroon_path)?),
+        };
+        let proxy = config
+            .backend_proxy_url
+            .as_deref()
+            .map(OutboundProxy::parse)
+            .transpose()
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        if let Some(proxy) = &proxy {
+            tracing::info!("Backend connections go through proxy {}", proxy.display());
+        }
+        if config.gateway_profile.is_cold_watch() {
+            tracing::info!("Cold-watch profile: only reads and verification are served");
+        }
+        // BackendClient calls set their route's timeout per request; the
+        // client-wide one covers everything else.
+        let timeout_tuner = Arc::new(TimeoutTuner::new());
+        timeout_tuner.install(TimeoutPolicy::from_config(&config));
+        let cache_audit = Arc::new(CacheAudit::new());
+        cache_audit.set_sample_percent(config.cache_audit_sample_percent);
+        let client = match self.client {
+            Some(client) => client,
+            None => backend_client(&config, proxy.as_ref())?,
+        };
+        let base_url = format!("https://{}", config.taproot_assets_host);
+
+        // Create WebSocket infrastructure
+        let connection_manager = Arc::new(
+            WebSocketConnectionManager::new(
+                BaseUrl(websocket_url(&base_url)),
+                MacaroonHex(macaroon_hex.clone()),
+                config.tls_verify,
+            )
+            .with_proxy(proxy.clone()),
+        );
+        let ws_proxy_handler = Arc::new(WebSocketProxyHandler::new(connection_manager.clone()));
+
+        // Hold proxied routes back until tapd answers, and pre-warm the WebSocket path
+        let warmup = Arc::new(Warmup::new(Duration::from_secs(
+            config.startup_warmup_timeout_secs,
+        )));
+        warmup.clone().start(
+            client.clone(),
+            base_url.clone(),
+            macaroon_hex.clone(),
+            connection_manager.clone(),
+        );
+
+        // Open the gateway database when persistence is configured
+        let database = match &config.database_url {
+            Some(url) => Some(
+                database::init_database(Some(url), config.redis_url.as_deref())
+                    .await
+                    .map_err(|e| std::io::Error::other(e.to_string()))?,
+            ),
+            None => None,
+        };
+
+        let signer = config
+            .response_signer()
+            .map_err(|e| std::io::Error::other(e.to_string()))?
+            .map(Arc::new);
+        let faucet = Faucet::from_config(&config).map(Arc::new);
+
+        // Periodic jobs; their outcomes are kept in the primary database
+        let mut scheduler = Scheduler::new(database.clone());
+
+        // Per-key usage counters, flushed to the primary database (tenants
+        // included) by a scheduled job
+        let usage = match &database {
+            Some(db) if config.usage_accounting_enabled => {
+                let meter = Arc::new(UsageMeter::new(db.clone()));
+                let default = format!("@every {}s", config.usage_flush_interval_secs);
+                if let Some((source, schedule)) = job_schedule(&config, JOB_USAGE_FLUSH, &default)?
+                {
+                    let meter = meter.clone();
+                    scheduler.add(JOB_USAGE_FLUSH, &source, schedule, move || {
+                        let meter = meter.clone();
+                        async move { meter.flush().await }
+                    });
+                }
+                Some(meter)
+            }
+            _ => None,
+        };
+
+        // Expired proof cache entries are otherwise only pruned on writes
+        if let Some(db) = database
+            .as_ref()
+            .filter(|_| config.proof_cache_ttl_secs > 0)
+        {
+            if let Some((source, schedule)) =
+                job_schedule(&config, JOB_PROOF_CACHE_PRUNE, "0 * * * *")?
+            {
+                let db = db.clone();
+                let ttl = config.proof_cache_ttl_secs as i64;
+                scheduler.add(JOB_PROOF_CACHE_PRUNE, &source, schedule, move || {
+                    let db = db.clone();
+                    async move {
+                        let not_before = chrono::Utc::now().timestamp() - ttl;
+                        db.prune_proof_cache(not_before).await.map(|_| ())
+                    }
+                });
+            }
+        }
+
+        // Gateway-produced events (confirmations, reorgs, ...) fan out from here
+        let event_bus = Arc::new(EventBus::default());
+        let maintenance = Arc::new(MaintenanceMode::new(
+            config.maintenance_mode,
+            config.maintenance_message.clone(),
+        ));
+        let route_groups = Arc::new(RouteGroups::new(&config.disabled_route_groups));
+        if let Some(db) = &database {
+            load_route_groups(db, &route_groups).await;
+        }
+        let feature_flags = Arc::new(FeatureFlags::new(&config.feature_flags));
+        if let Some(db) = &database {
+            load_feature_flags(db, &feature_flags).await;
+        }
+        let network_acl = Arc::new(NetworkAcl::new(
+            config.network_acl.clone(),
+            config.trusted_proxies.clone(),
+        ));
+        if let Some(db) = &database {
+            load_network_acl(db, &network_acl).await;
+        }
+        let rate_limits = Arc::new(RateLimits::new(config.rate_limit_per_minute));
+        let public = config
+            .public_api_enabled
+            .then(|| Arc::new(PublicApi::new(config.public_rate_limit_per_minute)));
+        let priority = (config.max_concurrent_requests > 0).then(|| {
+            Arc::new(PriorityLimiter::new(
+                config.max_concurrent_requests,
+                config.priority_queue_depth,
+                Duration::from_secs(config.priority_queue_timeout_secs),
+            ))
+        });
+
+        // Shed load while memory use is over its limits
+        let thresholds = ShedThresholds::from_config(&config);
+        let shed = thresholds
+            .is_enabled()
+            .then(|| Arc::new(ShedMode::new(thresholds)));
+        if let Some(shed) = &shed {
+            let default = format!("@every {}s", config.shed_check_interval_secs);
+            if let Some((source, schedule)) = job_schedule(&config, JOB_RESOURCE_CHECK, &default)? {
+                let shed = shed.clone();
+                let ws_proxy_handler = ws_proxy_handler.clone();
+                let connection_manager = connection_manager.clone();
+                let event_bus = event_bus.clone();
+                let public = public.clone();
+                scheduler.add(JOB_RESOURCE_CHECK, &source, schedule, move || {
+                    let shed = shed.clone();
+                    let ws_proxy_handler = ws_proxy_handler.clone();
+                    let connection_manager = connection_manager.clone();
+                    let event_bus = event_bus.clone();
+                    let public_entries = public.as_ref().map_or(0, |public| public.cache_len());
+                    async move {
+                        shed.update(ResourceUsage {
+                            rss_bytes: shed::rss_bytes(),
+                            proxy_sessions: ws_proxy_handler.active_session_count().await,
+                            backend_websockets: connection_manager.connection_count().await,
+                            event_subscribers: event_bus.subscriber_count(),
+                            cache_entries: public_entries + api::supply::cache_len(),
+                        });
+                        Ok(())
+                    }
+                });
+            }
+        }
+
+        // Periodic metrics snapshots for the monitor endpoints
+        let monitor = Arc::new(Monitor::new(Duration::from_secs(
+            config.monitor_interval_secs,
+        )));
+        monitor.clone().start(MonitorSources {
+            client: client.clone(),
+            base_url: base_url.clone(),
+            macaroon_hex: macaroon_hex.clone(),
+            connection_manager: connection_manager.clone(),
+            events: event_bus.clone(),
+            warmup: warmup.clone(),
+            maintenance: maintenance.clone(),
+        });
+
+        // Since-install totals of the monitor counters for /v1/gateway/stats
+        let lifetime_stats = match &database {
+            Some(db) => {
+                let stats = Arc::new(LifetimeStats::new(db.clone()));
+                let default = format!("@every {}s", config.stats_flush_interval_secs);
+                if let Some((source, schedule)) = job_schedule(&config, JOB_STATS_FLUSH, &default)?
+                {
+                    let stats = stats.clone();
+                    let requests = monitor.stats();
+                    let connection_manager = connection_manager.clone();
+                    scheduler.add(JOB_STATS_FLUSH, &source, schedule, move || {
+                        let stats = stats.clone();
+                        let requests = requests.clone();
+                        let connection_manager = connection_manager.clone();
+                        async move {
+                            let websockets = connection_manager.introspect().await;
+                            stats
+                                .flush(&lifetime_stats::boot_counters(&requests, &websockets))
+                                .await
+                        }
+                    });
+                }
+                Some(stats)
+            }
+            None => None,
+        };
+
+        // Operator notifications for backend outages and large transfers.
+        // Sinks are operator endpoints, so like webhooks they verify TLS.
+        let notify_client = Client::builder()
+            .timeout(Duration::from_secs(config.webhook_timeout_secs))
+            .build()
+            .map_err(std::io::Error::other)?;
+        if let Some(notifier) = Notifier::from_config(&config, notify_client)
+            .map_err(|e| std::io::Error::other(e.to_string()))?
+        {
+            let notifier = Arc::new(notifier);
+            notifier.clone().start(
+                event_bus.clone(),
+                Duration::from_secs(config.notify_digest_interval_secs),
+            );
+            // Unusual sends of the primary node, as the indexer sees them
+            if let Some(db) = database
+                .as_ref()
+                .filter(|_| !config.anomaly_rules.is_empty())
+            {
+                anomaly::start(
+                    config.anomaly_rules.clone(),
+                    db.clone(),
+                    event_bus.clone(),
+                    notifier,
+                );
+            }
+        }
+
+        // SSO tokens; the identity provider is an operator endpoint, so
+        // like webhooks its TLS is verified
+        let oidc = match &config.oidc {
+            Some(settings) => {
+                let client = Client::builder().build().map_err(std::io::Error::other)?;
+                Some(Arc::new(OidcAuthenticator::new(settings.clone(), client)))
+            }
+            None => None,
+        };
+
+        let lockouts = match (&database, config.auth_lockout_threshold) {
+            (Some(db), threshold) if threshold > 0 => {
+                let lockouts = Arc::new(AuthLockouts::new(&config, db.clone()));
+                lockouts.load().await;
+                Some(lockouts)
+            }
+            _ => None,
+        };
+
+        // Audit events for the SIEM, streamed from a buffer
+        let audit = match &config.audit_log_target {
+            Some(target) => Some(AuditExporter::start(
+                AuditTarget::parse(target).map_err(|e| std::io::Error::other(e.to_string()))?,
+                config.audit_log_format,
+                config.audit_log_buffer,
+            )),
+            None => None,
+        };
+
+        // Browser sessions keep their refresh tokens in the database
+        let sessions = match (&database, config.sessions_enabled) {
+            (Some(db), true) => Some(Arc::new(
+                SessionManager::new(&config, db.clone())
+                    .map_err(|e| std::io::Error::other(e.to_string()))?,
+            )),
+            _ => None,
+        };
+
+        let lnd = match &config.lnd_url {
+            Some(lnd_url) => Some((
+                lnd_url.trim_end_matches('/').to_string(),
+                hex::encode(fs::read(&config.lnd_macaroon_path)?),
+            )),
+            None => None,
+        };
+        start_node_tasks(
+            &config,
+            &client,
+            signer.as_ref(),
+            &mut scheduler,
+            Node {
+                label: "the primary node (LND_URL not set)".to_string(),
+                tenant: None,
+                base_url: &base_url,
+                macaroon_hex: &macaroon_hex,
+                connection_manager: &connection_manager,
+                event_bus: &event_bus,
+                database: database.as_ref(),
+                lnd: lnd.clone(),
+            },
+        )?;
+
+        // Each tenant gets its own tapd connections, event bus and database
+        let mut tenants = Vec::new();
+        for tenant in &config.tenants {
+            let tenant_base_url = format!("https://{}", tenant.host);
+            let tenant_macaroon_hex = hex::encode(fs::read(&tenant.macaroon_path)?);
+            let tenant_connections = Arc::new(
+                WebSocketConnectionManager::new(
+                    BaseUrl(websocket_url(&tenant_base_url)),
+                    MacaroonHex(tenant_macaroon_hex.clone()),
+                    config.tls_verify,
+                )
+                .with_proxy(proxy.clone()),
+            );
+            let tenant_events = Arc::new(EventBus::default());
+            // Redis caches receivers under shared keys, so tenants skip it
+            let tenant_database = match &tenant.database_url {
+                Some(url) => Some(
+                    database::init_database(Some(url), None)
+                        .await
+                        .map_err(|e| std::io::Error::other(e.to_string()))?,
+                ),
+                None => None,
+            };
+            let lnd = match (&tenant.lnd_url, &tenant.lnd_macaroon_path) {
+                (Some(lnd_url), Some(path)) => Some((
+                    lnd_url.trim_end_matches('/').to_string(),
+                    hex::encode(fs::read(path)?),
+                )),
+                _ => None,
+            };
+            start_node_tasks(
+                &config,
+                &client,
+                signer.as_ref(),
+                &mut scheduler,
+                Node {
+                    label: format!("tenant {}", tenant.name),
+                    tenant: Some(&tenant.name),
+                    base_url: &tenant_base_url,
+                    macaroon_hex: &tenant_macaroon_hex,
+                    connection_manager: &tenant_connections,
+                    event_bus: &tenant_events,
+                    database: tenant_database.as_ref(),
+                    lnd: lnd.clone(),
+                },
+            )?;
+            tenants.push((
+                tenant.api_key.clone(),
+                Tenant::new(
+                    tenant.name.clone(),
+                    tenant_base_url,
+                    tenant_macaroon_hex,
+                    tenant_connections,
+                    tenant_events,
+                    tenant_database,
+                    lnd,
+                ),
+            ));
+        }
+        let tenants = (!tenants.is_empty()).then(|| Arc::new(TenantRouter::new(tenants)));
+
+        let scheduler = Arc::new(scheduler);
+        scheduler.clone().start();
+
+        Ok(Gateway {
+            config,
+            client,
+            base_url,
+            macaroon_hex,
+            connection_manager,
+            ws_proxy_handler,
+            warmup,
+            database,
+            event_bus,
+            maintenance,
+            route_groups,
+            feature_flags,
+            network_acl,
+            rate_limits,
+            priority,
+            shed,
+            monitor,
+            lifetime_stats,
+            usage,
+            signer,
+            faucet,
+            scheduler,
+            timeout_tuner,
+            cache_audit,
+            public,
+            tenants,
+            oidc,
+            sessions,
+            lockouts,
+            audit,
+            lnd,
+            route_filter: self.route_filter,
+        })
+    }
+}
+
+/// A configured gateway. Cheap to clone; every clone shares the same
+/// backend connections, database and background tasks.
+#[derive(Clone)]
+pub struct Gateway {
+    config: Config,
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+    connection_manager: Arc<WebSocketConnectionManager>,
+    ws_proxy_handler: Arc<WebSocketProxyHandler>,
+    warmup: SharedWarmup,
+    database: Option<SharedDatabase>,
+    event_bus: SharedEventBus,
+    maintenance: SharedMaintenance,
+    route_groups: SharedRouteGroups,
+    feature_flags: SharedFeatureFlags,
+    network_acl: SharedNetworkAcl,
+    rate_limits: SharedRateLimits,
+    priority: Option<SharedPriorityLimiter>,
+    shed: Option<SharedShed>,
+    monitor: SharedMonitor,
+    lifetime_stats: Option<SharedLifetimeStats>,
+    usage: Option<SharedUsageMeter>,
+    signer: Option<SharedResponseSigner>,
+    faucet: Option<SharedFaucet>,
+    scheduler: SharedScheduler,
+    timeout_tuner: SharedTimeoutTuner,
+    cache_audit: SharedCacheAudit,
+    /// The anonymous explorer routes' limiter and cache, when enabled.
+    public: Option<SharedPublicApi>,
+    tenants: Option<SharedTenantRouter>,
+    oidc: Option<SharedOidcAuthenticator>,
+    sessions: Option<SharedSessionManager>,
+    lockouts: Option<SharedAuthLockouts>,
+    audit: Option<SharedAuditExporter>,
+    /// lnd REST URL and hex macaroon of the primary node.
+    lnd: Option<(String, String)>,
+    route_filter: Option<RouteFilter>,
+}
+
+impl Gateway {
+    pub fn builder() -> GatewayBuilder {
+        GatewayBuilder::default()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn macaroon_hex(&self) -> &str {
+        &self.macaroon_hex
+    }
+
+    pub fn database(&self) -> Option<&SharedDatabase> {
+        self.database.as_ref()
+    }
+
+    pub fn event_bus(&self) -> &SharedEventBus {
+        &self.event_bus
+    }
+
+    pub fn warmup(&self) -> &SharedWarmup {
+        &self.warmup
+    }
+
+    pub fn maintenance(&self) -> &SharedMaintenance {
+        &self.maintenance
+    }
+
+    pub fn route_groups(&self) -> &SharedRouteGroups {
+        &self.route_groups
+    }
+
+    pub fn feature_flags(&self) -> &SharedFeatureFlags {
+        &self.feature_flags
+    }
+
+    /// Client address rules; wrap the mount point in
+    /// [`crate::middleware::NetworkAclGuard`] with them.
+    pub fn network_acl(&self) -> &SharedNetworkAcl {
+        &self.network_acl
+    }
+
+    /// Per-IP request buckets; wrap the mount point in
+    /// [`crate::middleware::RateLimiter::shared`] with them.
+    pub fn rate_limits(&self) -> &SharedRateLimits {
+        &self.rate_limits
+    }
+
+    /// The concurrency limiter, when `MAX_CONCURRENT_REQUESTS` is set; wrap
+    /// the mount point in [`crate::middleware::PriorityQueueing`] with it.
+    pub fn priority(&self) -> Option<&SharedPriorityLimiter> {
+        self.priority.as_ref()
+    }
+
+    /// Shed mode, when a `SHED_*` limit is set; wrap the mount point in
+    /// [`crate::middleware::ShedGuard`] with it.
+    pub fn shed(&self) -> Option<&SharedShed> {
+        self.shed.as_ref()
+    }
+
+    pub fn monitor(&self) -> &SharedMonitor {
+        &self.monitor
+    }
+
+    /// Per-key usage counters; wrap the mount point in
+    /// [`crate::middleware::UsageAccounting`] with them.
+    pub fn usage(&self) -> Option<&SharedUsageMeter> {
+        self.usage.as_ref()
+    }
+
+    pub fn scheduler(&self) -> &SharedScheduler {
+        &self.scheduler
+    }
+
+    /// Routes tenant API keys to their nodes; wrap the mount point in
+    /// [`crate::middleware::TenantRouting`] with it.
+    pub fn tenants(&self) -> Option<&SharedTenantRouter> {
+        self.tenants.as_ref()
+    }
+
+    /// Validates SSO bearer tokens when `OIDC_ISSUER` is set; pass it to
+    /// [`crate::middleware::ApiKeyAuth::with_oidc`].
+    pub fn oidc(&self) -> Option<&SharedOidcAuthenticator> {
+        self.oidc.as_ref()
+    }
+
+    /// Issues and checks browser session cookies when `SESSIONS_ENABLED` is
+    /// set; pass it to [`crate::middleware::ApiKeyAuth::with_sessions`].
+    pub fn sessions(&self) -> Option<&SharedSessionManager> {
+        self.sessions.as_ref()
+    }
+
+    /// Locks out clients after repeated failed authentications when
+    /// `AUTH_LOCKOUT_THRESHOLD` is set; pass it to
+    /// [`crate::middleware::ApiKeyAuth::with_lockouts`].
+    pub fn lockouts(&self) -> Option<&SharedAuthLockouts> {
+        self.lockouts.as_ref()
+    }
+
+    /// Pays out test assets when `FAUCET_ENABLED` is set; pass it to
+    /// [`crate::middleware::ApiKeyAuth::with_faucet`].
+    pub fn faucet(&self) -> Option<&SharedFaucet> {
+        self.faucet.as_ref()
+    }
+
+    /// Streams audit events when `AUDIT_LOG_TARGET` is set; pass it to
+    /// [`crate::middleware::AuditLog`].
+    pub fn audit(&self) -> Option<&SharedAuditExporter> {
+        self.audit.as_ref()
+    }
+
+    /// Registers the gateway's shared state and routes. Call it on an `App`
+    /// or on a `web::scope` to mount the gateway under a prefix.
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(web::PayloadConfig::new(MAX_PAYLOAD_SIZE))
+            .app_data(web::JsonConfig::default().limit(MAX_PAYLOAD_SIZE))
+            .app_data(web::Data::new(self.client.clone()))
+            .app_data(web::Data::new(BaseUrl(self.base_url.clone())))
+            .app_data(web::Data::new(MacaroonHex(self.macaroon_hex.clone())))
+            .app_data(web::Data::new(LndNode(self.lnd.clone())))
+            .app_data(web::Data::new(self.config.clone()))
+            .app_data(web::Data::new(StatusMapping::new(
+                self.config.strict_status,
+            )))
+            .app_data(web::Data::new(self.ws_proxy_handler.clone()))
+            .app_data(web::Data::new(self.connection_manager.clone()))
+            .app_data(web::Data::new(self.event_bus.clone()))
+            .app_data(web::Data::new(self.maintenance.clone()))
+            .app_data(web::Data::new(self.route_groups.clone()))
+            .app_data(web::Data::new(self.feature_flags.clone()))
+            .app_data(web::Data::new(self.network_acl.clone()))
+            .app_data(web::Data::new(self.rate_limits.clone()))
+            .app_data(web::Data::new(self.warmup.clone()))
+            .app_data(web::Data::new(self.monitor.clone()))
+            .app_data(web::Data::new(self.scheduler.clone()))
+            .app_data(web::Data::new(self.timeout_tuner.clone()))
+            .app_data(web::Data::new(self.cache_audit.clone()));
+        if let Some(db) = &self.database {
+            cfg.app_data(web::Data::new(db.clone()));
+        }
+        if let Some(lifetime_stats) = &self.lifetime_stats {
+            cfg.app_data(web::Data::new(lifetime_stats.clone()));
+        }
+        if let Some(usage) = &self.usage {
+            cfg.app_data(web::Data::new(usage.clone()));
+        }
+        if let Some(priority) = &self.priority {
+            cfg.app_data(web::Data::new(priority.clone()));
+        }
+        if let Some(shed) = &self.shed {
+            cfg.app_data(web::Data::new(shed.clone()));
+        }
+        if let Some(signer) = &self.signer {
+            cfg.app_data(web::Data::new(signer.clone()));
+        }
+        if let Some(faucet) = &self.faucet {
+            cfg.app_data(web::Data::new(faucet.clone()));
+        }
+        if let Some(sessions) = &self.sessions {
+            cfg.app_data(web::Data::new(sessions.clone()));
+        }
+        if let Some(lockouts) = &self.lockouts {
+            cfg.app_data(web::Data::new(lockouts.clone()));
+        }
+        if let Some(audit) = &self.audit {
+            cfg.app_data(web::Data::new(audit.clone()));
+        }
+
+        let public = self.public.clone();
+        let filter = request_filter(self.route_filter.clone(), self.route_groups.clone());
+        // The subscribe route checks the route of the stream it proxies
+        cfg.app_data(web::Data::new(filter.clone()));
+        cfg.service(web::scope("").wrap(BackendTimeouts).configure(move |cfg| {
+            filtered(cfg, Some(filter), move |cfg| {
+                if let Some(public) = &public {
+                    api::public::configure(cfg, public.clone());
+                }
+                api::routes::configure(cfg);
+            })
+        }));
+    }
+}
+
+/// The backend of one node, the primary or a tenant's, that background
+/// tasks run against.
+struct Node<'a> {
+    /// Names the node in logs.
+    label: String,
+    /// Suffixes the node's job names, so each tenant's jobs are reported
+    /// separately.
+    tenant: Option<&'a str>,
+    base_url: &'a str,
+    macaroon_hex: &'a str,
+    connection_manager: &'a Arc<WebSocketConnectionManager>,
+    event_bus: &'a SharedEventBus,
+    database: Option<&'a SharedDatabase>,
+    /// lnd REST URL and hex-encoded macaroon.
+    lnd: Option<(String, String)>,
+}
+
+/// Channel events, universe sync retries, the indexer and its dependents,
+/// as enabled by the config.
+fn start_node_tasks(
+    config: &Config,
+    client: &Client,
+    signer: Option<&SharedResponseSigner>,
+    scheduler: &mut Scheduler,
+    node: Node,
+) -> std::io::Result<()> {
+    // Merge asset channel events from tapd, and from lnd when it is reachable
+    if config.channel_events_enabled {
+        let lnd = node
+            .lnd
+            .clone()
+            .map(|(lnd_url, lnd_macaroon_hex)| LndSource {
+                client: client.clone(),
+                connection_manager: Arc::new(WebSocketConnectionManager::new(
+                    BaseUrl(lnd_url.clone()),
+                    MacaroonHex(lnd_macaroon_hex.clone()),
+                    config.tls_verify,
+                )),
+                base_url: lnd_url,
+                macaroon_hex: lnd_macaroon_hex,
+            });
+        Arc::new(ChannelEventAggregator::new(
+            node.connection_manager.clone(),
+            lnd,
+            node.event_bus.clone(),
+        ))
+        .start();
+    }
+
+    // Alert when an asset's outputs become too fragmented
+    let thresholds = FragmentationThresholds::from_config(config);
+    if thresholds.is_enabled() {
+        if let Some((source, schedule)) = job_schedule(config, JOB_UTXO_CHECK, "@every 15m")? {
+            let name = match node.tenant {
+                Some(tenant) => format!("{JOB_UTXO_CHECK}:{tenant}"),
+                None => JOB_UTXO_CHECK.to_string(),
+            };
+            let watch = Arc::new(FragmentationWatch::new(
+                client.clone(),
+                node.base_url.to_string(),
+                node.macaroon_hex.to_string(),
+                node.event_bus.clone(),
+                config.utxo_dust_below,
+                thresholds,
+            ));
+            scheduler.add(&name, &source, schedule, move || {
+                let watch = watch.clone();
+                async move { watch.check().await.map(|_| ()) }
+            });
+        }
+    }
+
+    let Some(db) = node.database else {
+        return Ok(());
+    };
+
+    // Backfills a previous process left running become resumable
+    let reset_db = db.clone();
+    tokio::spawn(async move {
+        match reset_db.reset_interrupted_backfills().await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("{} interrupted backfills can be resumed", n),
+            Err(e) => tracing::warn!("Failed to reset interrupted backfills: {}", e),
+        }
+    });
+
+    // Finish universe syncs that were interrupted, including by a restart
+    let default = match config.universe_sync_retry_interval_secs {
+        0 => "off".to_string(),
+        secs => format!("@every {secs}s"),
+    };
+    if let Some((source, schedule)) = job_schedule(config, JOB_UNIVERSE_SYNC_RETRY, &default)? {
+        let runner = Arc::new(UniverseSyncRunner::new(
+            client.clone(),
+            node.base_url.to_string(),
+            node.macaroon_hex.to_string(),
+            db.clone(),
+            config.universe_sync_max_attempts,
+        ));
+        runner.clone().start();
+        let name = match node.tenant {
+            Some(tenant) => format!("{JOB_UNIVERSE_SYNC_RETRY}:{tenant}"),
+            None => JOB_UNIVERSE_SYNC_RETRY.to_string(),
+        };
+        scheduler.add(&name, &source, schedule, move || {
+            let runner = runner.clone();
+            async move { runner.retry_partial().await }
+        });
+    }
+
+    // Flag receive addresses whose TTL has passed
+    if let Some((source, schedule)) = job_schedule(config, JOB_ADDRESS_EXPIRY, "@every 60s")? {
+        let name = match node.tenant {
+            Some(tenant) => format!("{JOB_ADDRESS_EXPIRY}:{tenant}"),
+            None => JOB_ADDRESS_EXPIRY.to_string(),
+        };
+        let (db, events) = (db.clone(), node.event_bus.clone());
+        scheduler.add(&name, &source, schedule, move || {
+            let (db, events) = (db.clone(), events.clone());
+            async move {
+                address_expiry::expire_addresses(&db, &events)
+                    .await
+                    .map(|_| ())
+            }
+        });
+    }
+
+    // Retry mailbox messages tapd could not take yet, and expire old ones
+    if config.mailbox_outbox_enabled {
+        if let Some((source, schedule)) = job_schedule(config, JOB_MAILBOX_OUTBOX, "@every 15s")? {
+            let name = match node.tenant {
+                Some(tenant) => format!("{JOB_MAILBOX_OUTBOX}:{tenant}"),
+                None => JOB_MAILBOX_OUTBOX.to_string(),
+            };
+            let outbox = Arc::new(MailboxOutbox::new(
+                client.clone(),
+                node.base_url.to_string(),
+                node.macaroon_hex.to_string(),
+                db.clone(),
+                node.event_bus.clone(),
+                config.mailbox_outbox_max_attempts,
+            ));
+            scheduler.add(&name, &source, schedule, move || {
+                let outbox = outbox.clone();
+                async move { outbox.retry_due().await }
+            });
+        }
+        if let Some((source, schedule)) = job_schedule(config, JOB_MAILBOX_GC, "@every 1h")? {
+            let name = match node.tenant {
+                Some(tenant) => format!("{JOB_MAILBOX_GC}:{tenant}"),
+                None => JOB_MAILBOX_GC.to_string(),
+            };
+            let retention = RetentionPolicy {
+                max_age_secs: config.mailbox_retention_max_age_secs,
+                max_messages: config.mailbox_retention_max_messages,
+            };
+            let db = db.clone();
+            scheduler.add(&name, &source, schedule, move || {
+                let db = db.clone();
+                async move {
+                    mailbox_outbox::collect_garbage(&db, retention)
+                        .await
+                        .map(|_| ())
+                }
+            });
+        }
+    }
+
+    // Release the leases of swaps that did not settle in time
+    if config.swaps_enabled {
+        if let Some((source, schedule)) = job_schedule(config, JOB_SWAP_EXPIRY, "@every 60s")? {
+            let name = match node.tenant {
+                Some(tenant) => format!("{JOB_SWAP_EXPIRY}:{tenant}"),
+                None => JOB_SWAP_EXPIRY.to_string(),
+            };
+            let outbox = config.mailbox_outbox_enabled.then(|| {
+                MailboxOutbox::new(
+                    client.clone(),
+                    node.base_url.to_string(),
+                    node.macaroon_hex.to_string(),
+                    db.clone(),
+                    node.event_bus.clone(),
+                    config.mailbox_outbox_max_attempts,
+                )
+            });
+            let coordinator = Arc::new(
+                SwapCoordinator::new(
+                    client.clone(),
+                    node.base_url.to_string(),
+                    node.macaroon_hex.to_string(),
+                    node.lnd.clone(),
+                    db.clone(),
+                    node.event_bus.clone(),
+                )
+                .with_outbox(outbox),
+            );
+            scheduler.add(&name, &source, schedule, move || {
+                let coordinator = coordinator.clone();
+                async move { coordinator.expire_due().await.map(|_| ()) }
+            });
+        }
+    }
+
+    // Pay recurring payments as they come due
+    if config.recurring_payments_enabled {
+        if let Some((source, schedule)) =
+            job_schedule(config, JOB_RECURRING_PAYMENTS, "@every 60s")?
+        {
+            let name = match node.tenant {
+                Some(tenant) => format!("{JOB_RECURRING_PAYMENTS}:{tenant}"),
+                None => JOB_RECURRING_PAYMENTS.to_string(),
+            };
+            // Address generators are payee endpoints, so like webhooks they
+            // verify TLS
+            let generator = Client::builder()
+                .timeout(Duration::from_secs(config.webhook_timeout_secs))
+                .build()
+                .map_err(std::io::Error::other)?;
+            let payer = Arc::new(
+                RecurringPayer::new(
+                    client.clone(),
+                    node.base_url.to_string(),
+                    node.macaroon_hex.to_string(),
+                    db.clone(),
+                    node.event_bus.clone(),
+                    DestinationGuard::new(config.destination_guard, &config.destination_denylist),
+                    config.anomaly_rules.clone(),
+                )
+                .with_generator(generator),
+            );
+            scheduler.add(&name, &source, schedule, move || {
+                let payer = payer.clone();
+                async move { payer.run_due().await.map(|_| ()) }
+            });
+        }
+    }
+
+    // Callbacks are queued by the indexer's dependents, the mailbox outbox
+    // and swaps
+    if !config.indexer_enabled && !config.mailbox_outbox_enabled && !config.swaps_enabled {
+        return Ok(());
+    }
+    // Webhooks go to arbitrary merchant endpoints, so they always verify TLS
+    let webhook_client = Client::builder()
+        .timeout(Duration::from_secs(config.webhook_timeout_secs))
+        .build()
+        .map_err(std::io::Error::other)?;
+    Arc::new(
+        WebhookDispatcher::new(
+            webhook_client,
+            db.clone(),
+            node.event_bus.clone(),
+            ReceivePolicy::new(config.min_receive_confirmations),
+            config.webhook_max_attempts,
+        )
+        .with_signer(signer.cloned()),
+    )
+    .start();
+
+    // Start the event indexer, and the confirmation reconciler when lnd is reachable
+    if !config.indexer_enabled {
+        return Ok(());
+    }
+    let indexer = Arc::new(Indexer::new(
+        client.clone(),
+        node.base_url.to_string(),
+        node.macaroon_hex.to_string(),
+        db.clone(),
+        node.connection_manager.clone(),
+        node.event_bus.clone(),
+        config.indexer_backfill_batch_size,
+    ));
+    indexer.clone().start();
+
+    match node.lnd {
+        Some((lnd_url, lnd_macaroon_hex)) => {
+            let chain = LndChainSource::new(client.clone(), lnd_url, lnd_macaroon_hex);
+            indexer.start_reconciler(
+                chain,
+                Duration::from_secs(config.chain_poll_interval_secs),
+                config.finality_depth,
+            );
+        }
+        None => tracing::warn!(
+            "No lnd for {} - indexed transfers will not track confirmations or reorgs",
+            node.label
+        ),
+    }
+
+    Arc::new(PaymentRequestTracker::new(
+        db.clone(),
+        node.event_bus.clone(),
+        ReceivePolicy::new(config.min_receive_confirmations),
+    ))
+    .start();
+    Ok(())
+}
+
+/// HTTP client for tapd and lnd as the config asks: request timeout,
+/// connection reuse, outbound proxy and TLS verification.
+pub fn backend_client(config: &Config, proxy: Option<&OutboundProxy>) -> std::io::Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(config.request_timeout_secs))
+        .pool_max_idle_per_host(config.backend_pool_max_idle_per_host)
+        .pool_idle_timeout(
+            (config.backend_pool_idle_timeout_secs > 0)
+                .then(|| Duration::from_secs(config.backend_pool_idle_timeout_secs)),
+        );
+    if config.backend_keepalive_secs > 0 {
+        let interval = Duration::from_secs(config.backend_keepalive_secs);
+        builder = builder
+            .tcp_keepalive(interval)
+            .http2_keep_alive_interval(interval)
+            .http2_keep_alive_while_idle(true);
+    }
+    if config.backend_http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    if let Some(proxy) = proxy {
+        builder = proxy
+            .apply(builder)
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+    }
+    // Only disable TLS verification if explicitly configured (development only)
+    if !config.tls_verify {
+        tracing::warn!("TLS verification is disabled - this should only be used in development!");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    builder.build().map_err(std::io::Error::other)
+}
+
+/// `name`'s schedule from `JOB_SCHEDULES`, else `default`; `None` when the
+/// job is switched off.
+fn job_schedule(
+    config: &Config,
+    name: &str,
+    default: &str,
+) -> std::io::Result<Option<(String, Schedule)>> {
+    scheduler::configured(&config.job_schedules, name, default)
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Adds the network access rules stored through the admin API. A rule that
+/// no longer parses is skipped, not fatal, so one bad row cannot keep the
+/// gateway down.
+async fn load_network_acl(database: &SharedDatabase, network_acl: &NetworkAcl) {
+    let records = match database.network_acl_rules().await {
+        Ok(records) => records,
+        Err(e) => {
+            tracing::warn!("Failed to load network ACL rules: {}", e);
+            return;
+        }
+    };
+    for record in &records {
+        match AclRule::try_from(record) {
+            Ok(rule) => network_acl.add(rule),
+            Err(e) => tracing::warn!("Skipping network ACL rule {}: {}", record.id, e),
+        }
+    }
+}
+
+/// Applies the route group changes stored by earlier runs.
+async fn load_route_groups(database: &SharedDatabase, route_groups: &RouteGroups) {
+    let records = match database.route_group_records().await {
+        Ok(records) => records,
+        Err(e) => {
+            tracing::warn!("Failed to load route groups: {}", e);
+            return;
+        }
+    };
+    for record in records {
+        match RouteGroup::parse(&record.name) {
+            Some(group) => {
+                route_groups.set(group, record.enabled, record.updated_at);
+                if !record.enabled {
+                    tracing::info!("Route group {} is disabled", group.name());
+                }
+            }
+            None => tracing::warn!("Ignoring unknown stored route group {}", record.name),
+        }
+    }
+}
+
+/// Applies the feature flag overrides stored by earlier runs.
+async fn load_feature_flags(database: &SharedDatabase, flags: &FeatureFlags) {
+    let records = match database.feature_flag_records().await {
+        Ok(records) => records,
+        Err(e) => {
+            tracing::warn!("Failed to load feature flags: {}", e);
+            return;
+        }
+    };
+    for record in records {
+        match Feature::parse(&record.name) {
+            Some(feature) => flags.set(
+                feature,
+                record.key_id.as_deref(),
+                record.enabled,
+                record.updated_at,
+            ),
+            None => tracing::warn!("Ignoring unknown stored feature flag {}", record.name),
+        }
+    }
+}
+
+fn websocket_url(base_url: &str) -> String {
+    base_url
+        .replace("https://", "wss://")
+        .replace("http://", "ws://")
+}
+
+/// Combines the embedder's route filter with the route group toggles. The
+/// toggles are read per request, so a change applies to the next request
+/// without rebuilding the app.
+fn request_filter(filter: Option<RouteFilter>, route_groups: SharedRouteGroups) -> RouteFilter {
+    match filter {
+        Some(filter) => Arc::new(move |path| filter(path) && route_groups.allows(path)),
+        None => Arc::new(move |path| route_groups.allows(path)),
+    }
+}
+
+/// Registers `routes`, behind a guard when a route filter is set.
+fn filtered(
+    cfg: &mut web::ServiceConfig,
+    filter: Option<RouteFilter>,
+    routes: impl FnOnce(&mut web::ServiceConfig),
+) {
+    match filter {
+        Some(filter) => {
+            cfg.service(
+                web::scope("")
+                    .guard(guard::fn_guard(move |ctx| filter(ctx.head().uri.path())))
+                    .configure(routes),
+            );
+        }
+        None => routes(cfg),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App, HttpResponse};
+
+    fn routes(cfg: &mut web::ServiceConfig) {
+        cfg.route("/v1/a", web::get().to(HttpResponse::Ok))
+            .route("/v1/b", web::get().to(HttpResponse::Ok));
+    }
+
+    #[actix_web::test]
+    async fn test_route_filter_hides_rejected_paths() {
+        let filter: RouteFilter = Arc::new(|path: &str| !path.ends_with("/b"));
+        let app =
+            test::init_service(App::new().service(
+                web::scope("/tap").configure(move |cfg| filtered(cfg, Some(filter), routes)),
+            ))
+            .await;
+
+        let ok = test::TestRequest::get().uri("/tap/v1/a").to_request();
+        assert!(test::call_service(&app, ok).await.status().is_success());
+        let hidden = test::TestRequest::get().uri("/tap/v1/b").to_request();
+        assert_eq!(test::call_service(&app, hidden).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_route_group_toggle_applies_to_next_request() {
+        let groups = Arc::new(RouteGroups::new(&[]));
+        let filter = request_filter(None, groups.clone());
+        let app = test::init_service(App::new().configure(move |cfg| {
+            filtered(cfg, Some(filter), |cfg| {
+                cfg.route(
+                    "/v1/taproot-assets/rfq/ntfs",
+                    web::get().to(HttpResponse::Ok),
+                );
+            })
+        }))
+        .await;
+        let request = || {
+            test::TestRequest::get()
+                .uri("/v1/taproot-assets/rfq/ntfs")
+                .to_request()
+        };
+
+        assert!(test::call_service(&app, request())
+            .await
+            .status()
+            .is_success());
+        groups.set(RouteGroup::Rfq, false, 1);
+        assert_eq!(test::call_service(&app, request()).await.status(), 404);
+        groups.set(RouteGroup::Rfq, true, 2);
+        assert!(test::call_service(&app, request())
+            .await
+            .status()
+            .is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_no_filter_serves_everything() {
+        let app = test::init_service(App::new().configure(|cfg| filtered(cfg, None, routes))).await;
+        let req = test::TestRequest::get().uri("/v1/b").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+}
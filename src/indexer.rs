@@ -0,0 +1,870 @@
+//! Event indexer that persists tapd sends, receives, mints and burns.
+//!
+//! The indexer keeps one long-lived backend WebSocket per tapd event stream
+//! (asset-send, asset-receive, asset-mint), normalizes every event into an
+//! [`IndexedTransfer`] and upserts it into SQLite. On startup it backfills
+//! from tapd's historical listings so operations that happened while the
+//! gateway was down are still queryable. tapd has no burn event stream, so
+//! burns come from the backfill and from the gateway's own burn endpoint.
+//!
+//! When a chain backend is configured, a reconciler additionally tracks each
+//! anchor transaction until it is buried `finality_depth` blocks deep.
+
+mod backfill;
+mod policy;
+mod reconcile;
+
+pub use policy::ReceivePolicy;
+
+use crate::api::{addresses, assets, burn};
+use crate::chain::LndChainSource;
+use crate::database::{IndexedTransfer, SharedDatabase, TransferKind};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::websocket::connection_manager::WebSocketConnectionManager;
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+
+/// Published for every row the live event streams insert or update.
+pub const TOPIC_UPDATED: &str = "transfer.updated";
+
+/// Initial delay before resubscribing after a stream drops (in seconds)
+const INITIAL_RESUBSCRIBE_DELAY_SECS: u64 = 1;
+
+/// Maximum resubscribe delay (in seconds) - caps exponential backoff
+const MAX_RESUBSCRIBE_DELAY_SECS: u64 = 60;
+
+/// The tapd event streams the indexer follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventStream {
+    Send,
+    Receive,
+    Mint,
+}
+
+impl EventStream {
+    pub const ALL: [EventStream; 3] = [EventStream::Send, EventStream::Receive, EventStream::Mint];
+
+    fn endpoint(&self) -> &'static str {
+        match self {
+            EventStream::Send => "/v1/taproot-assets/events/asset-send?method=POST",
+            EventStream::Receive => "/v1/taproot-assets/events/asset-receive?method=POST",
+            EventStream::Mint => "/v1/taproot-assets/events/asset-mint?method=POST",
+        }
+    }
+
+    /// The subscription request tapd expects as the first frame.
+    fn request_body(&self) -> Value {
+        match self {
+            EventStream::Send | EventStream::Receive => serde_json::json!({}),
+            EventStream::Mint => serde_json::json!({ "short_response": false }),
+        }
+    }
+
+    /// Normalizes one event from this stream into zero or more rows.
+    pub fn normalize(&self, event: &Value) -> Vec<IndexedTransfer> {
+        match self {
+            EventStream::Send => normalize_send_event(event),
+            EventStream::Receive => normalize_receive(event).into_iter().collect(),
+            EventStream::Mint => normalize_mint_event(event).into_iter().collect(),
+        }
+    }
+}
+
+/// Counts of rows written by a backfill pass.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct BackfillSummary {
+    pub transfers: usize,
+    pub receives: usize,
+    pub mints: usize,
+    pub burns: usize,
+}
+
+pub struct Indexer {
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+    database: SharedDatabase,
+    connection_manager: Arc<WebSocketConnectionManager>,
+    events: SharedEventBus,
+    /// Rows written per transaction.
+    batch_size: usize,
+}
+
+impl Indexer {
+    pub fn new(
+        client: Client,
+        base_url: String,
+        macaroon_hex: String,
+        database: SharedDatabase,
+        connection_manager: Arc<WebSocketConnectionManager>,
+        events: SharedEventBus,
+        batch_size: usize,
+    ) -> Self {
+        Self {
+            client,
+            base_url,
+            macaroon_hex,
+            database,
+            connection_manager,
+            events,
+            batch_size: batch_size.max(1),
+        }
+    }
+
+    /// Starts the backfill and one subscription task per event stream.
+    pub fn start(self: Arc<Self>) -> Vec<tokio::task::JoinHandle<()>> {
+        let mut handles = Vec::new();
+
+        let indexer = self.clone();
+        handles.push(tokio::spawn(async move {
+            match indexer.backfill().await {
+                Ok(summary) => info!(
+                    "Indexer backfill complete: {} transfers, {} receives, {} mints, {} burns",
+                    summary.transfers, summary.receives, summary.mints, summary.burns
+                ),
+                Err(e) => warn!("Indexer backfill failed: {}", e),
+            }
+        }));
+
+        for stream in EventStream::ALL {
+            let indexer = self.clone();
+            handles.push(tokio::spawn(async move {
+                indexer.run_subscription(stream).await;
+            }));
+        }
+
+        handles
+    }
+
+    /// Reads tapd's historical transfer, receive, mint and burn listings and
+    /// stores them in batches of `batch_size` rows per transaction. tapd's
+    /// listings take no offset or limit, so each arrives in one response;
+    /// only the database writes are batched.
+    pub async fn backfill(&self) -> Result<BackfillSummary, AppError> {
+        let mut summary = BackfillSummary::default();
+
+        let transfers = self.transfer_rows().await?;
+        summary.transfers = self.store_batched(&transfers).await?;
+        summary.receives = self.store_batched(&self.receive_rows().await?).await?;
+        summary.mints = self.store_batched(&self.mint_rows().await?).await?;
+        summary.burns = self
+            .store_batched(&self.burn_rows(&transfers).await?)
+            .await?;
+
+        Ok(summary)
+    }
+
+    async fn transfer_rows(&self) -> Result<Vec<IndexedTransfer>, AppError> {
+        let transfers =
+            assets::get_transfers(&self.client, &self.base_url, &self.macaroon_hex, "").await?;
+        Ok(array_field(&transfers, "transfers")
+            .iter()
+            .flat_map(|t| normalize_transfer(t, None, None))
+            .collect())
+    }
+
+    async fn receive_rows(&self) -> Result<Vec<IndexedTransfer>, AppError> {
+        let receives = addresses::receive_events(
+            &self.client,
+            &self.base_url,
+            &self.macaroon_hex,
+            addresses::ReceiveEventsRequest {
+                filter_addr: None,
+                filter_status: None,
+            },
+        )
+        .await?;
+        Ok(array_field(&receives, "events")
+            .iter()
+            .filter_map(normalize_receive)
+            .collect())
+    }
+
+    async fn mint_rows(&self) -> Result<Vec<IndexedTransfer>, AppError> {
+        let batches =
+            assets::list_all_mint_batches(&self.client, &self.base_url, &self.macaroon_hex).await?;
+        Ok(array_field(&batches, "batches")
+            .iter()
+            .filter_map(normalize_mint_batch)
+            .collect())
+    }
+
+    /// Burns, dated by the transfers in `transfers` that anchored them.
+    async fn burn_rows(
+        &self,
+        transfers: &[IndexedTransfer],
+    ) -> Result<Vec<IndexedTransfer>, AppError> {
+        // ListBurns carries neither a timestamp nor a height; both come from
+        // the transfer that anchored the burn.
+        let anchors: HashMap<String, (i64, Option<u32>)> = transfers
+            .iter()
+            .filter_map(|t| Some((t.anchor_txid.clone()?, (t.timestamp, t.block_height))))
+            .collect();
+        let burns = burn::list_burns(&self.client, &self.base_url, &self.macaroon_hex, "").await?;
+        Ok(array_field(&burns, "burns")
+            .iter()
+            .filter_map(normalize_burn)
+            .map(|mut row| {
+                let anchor = row.anchor_txid.as_ref().and_then(|txid| anchors.get(txid));
+                if let Some((timestamp, block_height)) = anchor {
+                    row.timestamp = *timestamp;
+                    row.block_height = *block_height;
+                    row.status = burn_status(*block_height);
+                }
+                row
+            })
+            .collect())
+    }
+
+    async fn store_batched(&self, rows: &[IndexedTransfer]) -> Result<usize, AppError> {
+        for batch in rows.chunks(self.batch_size) {
+            self.database.upsert_indexed_transfers(batch).await?;
+        }
+        Ok(rows.len())
+    }
+
+    /// Follows one event stream forever, resubscribing with backoff whenever
+    /// the backend connection drops.
+    async fn run_subscription(&self, stream: EventStream) {
+        let mut delay = Duration::from_secs(INITIAL_RESUBSCRIBE_DELAY_SECS);
+        loop {
+            match self.consume(stream).await {
+                Ok(indexed) => {
+                    info!("{:?} event stream ended after {} events", stream, indexed);
+                    if indexed > 0 {
+                        delay = Duration::from_secs(INITIAL_RESUBSCRIBE_DELAY_SECS);
+                    }
+                }
+                Err(e) => warn!("{:?} event stream failed: {}", stream, e),
+            }
+            tokio::time::sleep(delay).await;
+            delay = std::cmp::min(delay * 2, Duration::from_secs(MAX_RESUBSCRIBE_DELAY_SECS));
+        }
+    }
+
+    async fn consume(&self, stream: EventStream) -> Result<usize, AppError> {
+        let (conn_id, mut sink, mut events) = self
+            .connection_manager
+            .connect_to_backend(stream.endpoint())
+            .await?;
+
+        let result = async {
+            sink.send(Message::Text(stream.request_body().to_string().into()))
+                .await
+                .map_err(|e| AppError::WebSocketError(format!("Failed to subscribe: {e}")))?;
+
+            let mut indexed = 0;
+            while let Some(frame) = events.next().await {
+                let frame =
+                    frame.map_err(|e| AppError::WebSocketError(format!("Stream error: {e}")))?;
+                let text = match frame {
+                    Message::Text(text) => text.to_string(),
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                self.connection_manager.update_activity(conn_id).await;
+
+                let event = unwrap_stream_frame(&text)?;
+                let rows = stream.normalize(&event);
+                debug!("{:?} event produced {} indexed rows", stream, rows.len());
+                self.database.upsert_indexed_transfers(&rows).await?;
+                for row in &rows {
+                    self.events.publish(TOPIC_UPDATED, event_payload(row)?);
+                }
+                indexed += 1;
+            }
+            Ok(indexed)
+        }
+        .await;
+
+        self.connection_manager.remove_connection(conn_id).await;
+        result
+    }
+}
+
+impl Indexer {
+    /// Polls the chain backend every `interval` and reconciles unsettled
+    /// transfers until the task is dropped.
+    pub fn start_reconciler(
+        self: Arc<Self>,
+        chain: LndChainSource,
+        interval: Duration,
+        finality_depth: u32,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.reconcile_once(&chain, finality_depth).await {
+                    warn!("Transfer reconciliation failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Runs one reconciliation pass and returns the number of transfers whose
+    /// chain state changed. A reorg or replacement re-queries tapd, since the
+    /// transfer it reported may no longer be the one that confirmed.
+    pub async fn reconcile_once(
+        &self,
+        chain: &LndChainSource,
+        finality_depth: u32,
+    ) -> Result<usize, AppError> {
+        let unsettled = self.database.unsettled_transfers(finality_depth).await?;
+        if unsettled.is_empty() {
+            return Ok(0);
+        }
+        let wallet_txs = chain.wallet_transactions().await?;
+
+        let mut changed = 0;
+        let mut requery = false;
+        for mut transfer in unsettled {
+            let Some(txid) = transfer.anchor_txid.as_deref() else {
+                continue;
+            };
+            let Some((state, topics)) = reconcile::reconcile(&transfer, wallet_txs.get(txid))
+            else {
+                continue;
+            };
+            self.database
+                .update_chain_state(&transfer.id, &state)
+                .await?;
+            changed += 1;
+
+            let previous_block_hash = transfer.block_hash.take();
+            transfer.chain_status = Some(state.status);
+            transfer.confirmations = Some(state.confirmations);
+            transfer.block_hash = state.block_hash.clone();
+            transfer.block_height = state.block_height.or(transfer.block_height);
+            let mut payload = event_payload(&transfer)?;
+            if let Some(map) = payload.as_object_mut() {
+                map.insert(
+                    "previous_block_hash".to_string(),
+                    previous_block_hash.into(),
+                );
+            }
+            for topic in topics {
+                requery |= topic != reconcile::TOPIC_CONFIRMED;
+                self.events.publish(topic, payload.clone());
+            }
+        }
+
+        if requery {
+            info!("Reorg or replacement detected, re-querying tapd");
+            self.backfill().await?;
+        }
+        Ok(changed)
+    }
+}
+
+/// Event bus payload for a transfer: the indexed row without tapd's raw
+/// document.
+fn event_payload(transfer: &IndexedTransfer) -> Result<Value, AppError> {
+    let mut payload = serde_json::to_value(transfer)?;
+    if let Some(map) = payload.as_object_mut() {
+        map.remove("raw");
+    }
+    Ok(payload)
+}
+
+/// grpc-gateway wraps each streamed message as `{"result": ...}` and reports
+/// stream failures as `{"error": ...}`.
+pub(crate) fn unwrap_stream_frame(text: &str) -> Result<Value, AppError> {
+    let mut frame: Value = serde_json::from_str(text)?;
+    if let Some(error) = frame.get("error").filter(|e| !e.is_null()) {
+        return Err(AppError::WebSocketProxyError(format!(
+            "Backend stream error: {error}"
+        )));
+    }
+    Ok(match frame.get_mut("result") {
+        Some(result) => result.take(),
+        None => frame,
+    })
+}
+
+fn array_field<'a>(value: &'a Value, field: &str) -> &'a [Value] {
+    value
+        .get(field)
+        .and_then(|v| v.as_array())
+        .map(|v| v.as_slice())
+        .unwrap_or(&[])
+}
+
+fn str_field<'a>(value: &'a Value, field: &str) -> Option<&'a str> {
+    value
+        .get(field)
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+}
+
+/// tapd encodes uint64 fields as JSON strings.
+fn u64_field(value: &Value, field: &str) -> Option<u64> {
+    match value.get(field)? {
+        Value::String(s) => s.parse().ok(),
+        Value::Number(n) => n.as_u64(),
+        _ => None,
+    }
+}
+
+/// tapd's REST layer renders byte fields as base64 in some places and hex in
+/// others; the index always stores lowercase hex so filters match either.
+pub(crate) fn normalize_hex_id(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return value.to_ascii_lowercase();
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(value)
+        .map(hex::encode)
+        .unwrap_or_else(|_| value.to_string())
+}
+
+/// tapd renders `anchor_tx_hash` as the raw chainhash bytes, which are in
+/// internal (reversed) byte order. Txids are stored in display order so they
+/// match outpoints and block explorers.
+pub(crate) fn normalize_txid(value: &str) -> String {
+    if value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return value.to_ascii_lowercase();
+    }
+    match base64::engine::general_purpose::STANDARD.decode(value) {
+        Ok(mut bytes) if bytes.len() == 32 => {
+            bytes.reverse();
+            hex::encode(bytes)
+        }
+        _ => normalize_hex_id(value),
+    }
+}
+
+/// Event timestamps are unix microseconds, transfer timestamps unix seconds.
+/// Everything is stored as seconds.
+fn normalize_timestamp(value: Option<&Value>) -> Option<i64> {
+    let raw = match value? {
+        Value::String(s) => s.parse::<i64>().ok()?,
+        Value::Number(n) => n.as_i64()?,
+        _ => return None,
+    };
+    Some(match raw {
+        r if r > 100_000_000_000_000 => r / 1_000_000,
+        r if r > 100_000_000_000 => r / 1_000,
+        r => r,
+    })
+}
+
+/// Turns `SEND_STATE_COMPLETED` into `completed`, and so on for the other
+/// enum prefixes tapd uses.
+fn normalize_status(raw: &str) -> String {
+    const PREFIXES: [&str; 3] = ["SEND_STATE_", "ADDR_EVENT_STATUS_", "BATCH_STATE_"];
+    let stripped = PREFIXES
+        .iter()
+        .find_map(|p| raw.strip_prefix(p))
+        .unwrap_or(raw);
+    stripped.to_ascii_lowercase()
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Normalizes an `AssetTransfer` into one row per asset moved. `status` and
+/// `address` come from the surrounding send event when there is one.
+pub fn normalize_transfer(
+    transfer: &Value,
+    status: Option<String>,
+    address: Option<String>,
+) -> Vec<IndexedTransfer> {
+    let Some(anchor_txid) = str_field(transfer, "anchor_tx_hash").map(normalize_txid) else {
+        return Vec::new();
+    };
+    let timestamp = normalize_timestamp(transfer.get("transfer_timestamp")).unwrap_or_else(now);
+    let block_height = u64_field(transfer, "anchor_tx_block_height")
+        .filter(|h| *h > 0)
+        .map(|h| h as u32);
+    let status = status.unwrap_or_else(|| {
+        if block_height.is_some() || transfer.get("anchor_tx_block_hash").is_some() {
+            "completed".to_string()
+        } else {
+            "broadcast".to_string()
+        }
+    });
+
+    let inputs = array_field(transfer, "inputs");
+    let input_assets: Vec<String> = inputs
+        .iter()
+        .filter_map(|i| str_field(i, "asset_id"))
+        .map(normalize_hex_id)
+        .collect();
+    let single_asset = match input_assets.as_slice() {
+        [first, rest @ ..] if rest.iter().all(|a| a == first) => Some(first.clone()),
+        _ => None,
+    };
+
+    // Amount leaving the wallet per asset: outputs not locked to a local key.
+    let mut per_asset: BTreeMap<String, u64> = BTreeMap::new();
+    for asset in &input_assets {
+        per_asset.entry(asset.clone()).or_insert(0);
+    }
+    for output in array_field(transfer, "outputs") {
+        let asset = str_field(output, "asset_id")
+            .map(normalize_hex_id)
+            .or_else(|| single_asset.clone());
+        let Some(asset) = asset else { continue };
+        let is_local = output
+            .get("script_key_is_local")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let entry = per_asset.entry(asset).or_insert(0);
+        if !is_local {
+            *entry += u64_field(output, "amount").unwrap_or(0);
+        }
+    }
+
+    per_asset
+        .into_iter()
+        .map(|(asset_id, amount)| IndexedTransfer {
+            id: format!("send:{anchor_txid}:{asset_id}"),
+            kind: TransferKind::Send,
+            asset_id: Some(asset_id),
+            address: address.clone(),
+            amount: Some(amount),
+            anchor_txid: Some(anchor_txid.clone()),
+            outpoint: None,
+            block_height,
+            status: status.clone(),
+            timestamp,
+            raw: transfer.clone(),
+            chain_status: None,
+            confirmations: None,
+            block_hash: None,
+            label: None,
+            tags: Vec::new(),
+        })
+        .collect()
+}
+
+/// Normalizes a `SendEvent`. Events emitted before the anchor transaction
+/// exists carry no transfer and are skipped; the later states update the row.
+pub fn normalize_send_event(event: &Value) -> Vec<IndexedTransfer> {
+    let Some(transfer) = event.get("transfer").filter(|t| t.is_object()) else {
+        return Vec::new();
+    };
+    let status = if str_field(event, "error").is_some() {
+        "failed".to_string()
+    } else {
+        str_field(event, "send_state")
+            .map(normalize_status)
+            .unwrap_or_else(|| "pending".to_string())
+    };
+    let address = match array_field(event, "addresses") {
+        [single] => str_field(single, "encoded").map(str::to_string),
+        _ => None,
+    };
+    normalize_transfer(transfer, Some(status), address)
+}
+
+/// Normalizes both the live `ReceiveEvent` (`address`, `timestamp`) and the
+/// historical `AddrEvent` (`addr`, `creation_time_unix_seconds`) shapes.
+pub fn normalize_receive(event: &Value) -> Option<IndexedTransfer> {
+    let addr = event.get("address").or_else(|| event.get("addr"))?;
+    let outpoint = str_field(event, "outpoint")?.to_string();
+    let asset_id = str_field(addr, "asset_id").map(normalize_hex_id);
+    let status = if str_field(event, "error").is_some() {
+        "failed".to_string()
+    } else {
+        str_field(event, "status")
+            .map(normalize_status)
+            .unwrap_or_else(|| "pending".to_string())
+    };
+    let timestamp = normalize_timestamp(event.get("timestamp"))
+        .or_else(|| normalize_timestamp(event.get("creation_time_unix_seconds")))
+        .unwrap_or_else(now);
+    let anchor_txid = outpoint.split(':').next().map(str::to_string);
+
+    Some(IndexedTransfer {
+        id: format!(
+            "receive:{outpoint}:{}",
+            asset_id.as_deref().unwrap_or_default()
+        ),
+        kind: TransferKind::Receive,
+        asset_id,
+        address: str_field(addr, "encoded").map(str::to_string),
+        amount: u64_field(addr, "amount"),
+        anchor_txid,
+        outpoint: Some(outpoint),
+        block_height: u64_field(event, "confirmation_height")
+            .filter(|h| *h > 0)
+            .map(|h| h as u32),
+        status,
+        timestamp,
+        raw: event.clone(),
+        chain_status: None,
+        confirmations: None,
+        block_hash: None,
+        label: None,
+        tags: Vec::new(),
+    })
+}
+
+/// Normalizes a minting batch, accepting both the bare `MintingBatch` and the
+/// `VerboseBatch` wrapper newer tapd versions return from ListBatches.
+pub fn normalize_mint_batch(batch: &Value) -> Option<IndexedTransfer> {
+    let batch = batch
+        .get("batch")
+        .filter(|b| b.is_object())
+        .unwrap_or(batch);
+    let batch_key = normalize_hex_id(str_field(batch, "batch_key")?);
+    let amount = array_field(batch, "assets")
+        .iter()
+        .filter_map(|a| u64_field(a, "amount"))
+        .sum();
+    let status = str_field(batch, "state")
+        .map(normalize_status)
+        .unwrap_or_else(|| "pending".to_string());
+
+    Some(IndexedTransfer {
+        id: format!("mint:{batch_key}"),
+        kind: TransferKind::Mint,
+        asset_id: None,
+        address: None,
+        amount: Some(amount),
+        anchor_txid: str_field(batch, "batch_txid").map(normalize_hex_id),
+        outpoint: None,
+        block_height: None,
+        status,
+        timestamp: normalize_timestamp(batch.get("created_at")).unwrap_or_else(now),
+        raw: batch.clone(),
+        chain_status: None,
+        confirmations: None,
+        block_hash: None,
+        label: None,
+        tags: Vec::new(),
+    })
+}
+
+/// Normalizes a `MintEvent`, whose batch is the same document ListBatches
+/// returns.
+pub fn normalize_mint_event(event: &Value) -> Option<IndexedTransfer> {
+    let mut row = normalize_mint_batch(event.get("batch")?)?;
+    if str_field(event, "error").is_some() {
+        row.status = "failed".to_string();
+    }
+    if let Some(timestamp) = normalize_timestamp(event.get("timestamp")) {
+        row.timestamp = timestamp;
+    }
+    Some(row)
+}
+
+fn burn_status(block_height: Option<u32>) -> String {
+    match block_height {
+        Some(_) => "completed".to_string(),
+        None => "broadcast".to_string(),
+    }
+}
+
+/// Normalizes an `AssetBurn` from ListBurns. The row is timestamped when first
+/// indexed unless the backfill finds the anchoring transfer.
+pub fn normalize_burn(burn: &Value) -> Option<IndexedTransfer> {
+    let asset_id = normalize_hex_id(str_field(burn, "asset_id")?);
+    let anchor_txid = normalize_txid(str_field(burn, "anchor_txid")?);
+    Some(IndexedTransfer {
+        id: format!("burn:{anchor_txid}:{asset_id}"),
+        kind: TransferKind::Burn,
+        asset_id: Some(asset_id),
+        address: None,
+        amount: u64_field(burn, "amount"),
+        anchor_txid: Some(anchor_txid),
+        outpoint: None,
+        block_height: None,
+        status: burn_status(None),
+        timestamp: now(),
+        raw: burn.clone(),
+        chain_status: None,
+        confirmations: None,
+        block_hash: None,
+        label: None,
+        tags: Vec::new(),
+    })
+}
+
+/// Normalizes the gateway's own `BurnAssetResponse` into the row ListBurns
+/// will later produce for the same burn. `asset_id` is the one the caller
+/// burned, when it named one rather than a group key.
+pub fn normalize_burn_response(
+    asset_id: Option<&str>,
+    amount: Option<u64>,
+    note: Option<&str>,
+    response: &Value,
+) -> Option<IndexedTransfer> {
+    let transfer = response.get("burn_transfer")?;
+    let asset_id = asset_id.map(normalize_hex_id).or_else(|| {
+        array_field(transfer, "inputs")
+            .iter()
+            .find_map(|i| str_field(i, "asset_id"))
+            .map(normalize_hex_id)
+    })?;
+    let burn = serde_json::json!({
+        "note": note.unwrap_or_default(),
+        "asset_id": asset_id,
+        "amount": amount.map(|a| a.to_string()),
+        "anchor_txid": str_field(transfer, "anchor_tx_hash")?,
+    });
+    let mut row = normalize_burn(&burn)?;
+    row.block_height = u64_field(transfer, "anchor_tx_block_height")
+        .filter(|h| *h > 0)
+        .map(|h| h as u32);
+    row.status = burn_status(row.block_height);
+    if let Some(timestamp) = normalize_timestamp(transfer.get("transfer_timestamp")) {
+        row.timestamp = timestamp;
+    }
+    Some(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ASSET_HEX: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+    const TXID: &str = "abababababababababababababababababababababababababababababababab";
+
+    fn transfer_doc() -> Value {
+        json!({
+            "transfer_timestamp": "1700000000",
+            "anchor_tx_hash": TXID,
+            "inputs": [{ "asset_id": ASSET_HEX, "amount": "100" }],
+            "outputs": [
+                { "amount": "30", "script_key_is_local": false },
+                { "amount": "70", "script_key_is_local": true }
+            ]
+        })
+    }
+
+    #[test]
+    fn test_normalize_transfer_counts_only_outgoing_amount() {
+        let rows = normalize_transfer(&transfer_doc(), None, None);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].id, format!("send:{TXID}:{ASSET_HEX}"));
+        assert_eq!(rows[0].amount, Some(30));
+        assert_eq!(rows[0].timestamp, 1_700_000_000);
+        assert_eq!(rows[0].status, "broadcast");
+    }
+
+    #[test]
+    fn test_send_event_and_backfill_share_an_id() {
+        let event = json!({
+            "timestamp": "1700000001000000",
+            "send_state": "SEND_STATE_COMPLETED",
+            "transfer": transfer_doc(),
+            "addresses": [{ "encoded": "taprt1xyz" }]
+        });
+        let live = normalize_send_event(&event);
+        let backfilled = normalize_transfer(&transfer_doc(), None, None);
+        assert_eq!(live[0].id, backfilled[0].id);
+        assert_eq!(live[0].status, "completed");
+        assert_eq!(live[0].address.as_deref(), Some("taprt1xyz"));
+    }
+
+    #[test]
+    fn test_send_event_without_transfer_is_skipped() {
+        let event = json!({ "send_state": "SEND_STATE_VIRTUAL_INPUT_SELECT" });
+        assert!(normalize_send_event(&event).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_receive_handles_both_shapes() {
+        let live = json!({
+            "timestamp": "1700000000000000",
+            "address": { "encoded": "taprt1abc", "asset_id": ASSET_HEX, "amount": "5" },
+            "outpoint": format!("{TXID}:1"),
+            "status": "ADDR_EVENT_STATUS_TRANSACTION_CONFIRMED",
+            "confirmation_height": 120
+        });
+        let historical = json!({
+            "creation_time_unix_seconds": "1700000000",
+            "addr": { "encoded": "taprt1abc", "asset_id": ASSET_HEX, "amount": "5" },
+            "outpoint": format!("{TXID}:1"),
+            "status": "ADDR_EVENT_STATUS_COMPLETED"
+        });
+
+        let live = normalize_receive(&live).unwrap();
+        let historical = normalize_receive(&historical).unwrap();
+        assert_eq!(live.id, historical.id);
+        assert_eq!(live.status, "transaction_confirmed");
+        assert_eq!(historical.status, "completed");
+        assert_eq!(live.timestamp, historical.timestamp);
+        assert_eq!(live.block_height, Some(120));
+        assert_eq!(live.anchor_txid.as_deref(), Some(TXID));
+        assert_eq!(live.amount, Some(5));
+    }
+
+    #[test]
+    fn test_normalize_mint_batch_accepts_verbose_wrapper() {
+        let batch = json!({
+            "batch": {
+                "batch_key": "02aa",
+                "state": "BATCH_STATE_FINALIZED",
+                "assets": [{ "amount": "1000" }, { "amount": "1" }]
+            }
+        });
+        let row = normalize_mint_batch(&batch).unwrap();
+        assert_eq!(row.id, "mint:02aa");
+        assert_eq!(row.amount, Some(1001));
+        assert_eq!(row.status, "finalized");
+    }
+
+    #[test]
+    fn test_normalize_hex_id_decodes_base64() {
+        let bytes = [0x11u8; 32];
+        let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
+        assert_eq!(normalize_hex_id(&b64), ASSET_HEX);
+        assert_eq!(normalize_hex_id(&ASSET_HEX.to_uppercase()), ASSET_HEX);
+    }
+
+    #[test]
+    fn test_normalize_txid_reverses_chainhash_bytes() {
+        let mut bytes = [0u8; 32];
+        bytes[0] = 0x01;
+        let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
+        assert!(normalize_txid(&b64).ends_with("01"));
+        assert_eq!(normalize_txid(TXID), TXID);
+    }
+
+    #[test]
+    fn test_unwrap_stream_frame() {
+        let event = unwrap_stream_frame(r#"{"result": {"send_state": "x"}}"#).unwrap();
+        assert_eq!(event["send_state"], "x");
+        assert!(unwrap_stream_frame(r#"{"error": {"code": 2}}"#).is_err());
+    }
+
+    #[test]
+    fn test_burn_response_and_listing_share_an_id() {
+        let listed = json!({
+            "note": "supply cut",
+            "asset_id": base64::engine::general_purpose::STANDARD.encode([0x11u8; 32]),
+            "amount": "25",
+            "anchor_txid": TXID
+        });
+        let response = json!({
+            "burn_transfer": {
+                "transfer_timestamp": "1700000000",
+                "anchor_tx_hash": TXID,
+                "inputs": [{ "asset_id": ASSET_HEX, "amount": "100" }]
+            }
+        });
+        let listed = normalize_burn(&listed).unwrap();
+        let live = normalize_burn_response(None, Some(25), None, &response).unwrap();
+        assert_eq!(listed.id, format!("burn:{TXID}:{ASSET_HEX}"));
+        assert_eq!(live.id, listed.id);
+        assert_eq!(live.kind, TransferKind::Burn);
+        assert_eq!(live.amount, Some(25));
+        assert_eq!(live.timestamp, 1_700_000_000);
+        assert_eq!(live.status, "broadcast");
+    }
+}
@@ -0,0 +1,311 @@
+//! Admin-triggered backfills. Unlike the startup backfill, a run also copies
+//! the universe's leaves into the index, and it is saved after every step:
+//! each listing phase, and each page of universe roots. A run stopped by a
+//! tapd error or a restart continues from its last saved step when resumed.
+
+use super::{array_field, normalize_hex_id, str_field, u64_field, Indexer};
+use crate::api::universe;
+use crate::database::{BackfillPhase, BackfillProgress, BackfillRun, BackfillStatus, UniverseLeaf};
+use crate::error::AppError;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Universe roots requested per page, and per saved step.
+const UNIVERSE_ROOTS_PAGE_SIZE: u64 = 50;
+
+/// One universe tree listed by `universe/roots`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UniverseId {
+    asset_id: Option<String>,
+    group_key: Option<String>,
+    /// `issuance` or `transfer`.
+    proof_type: &'static str,
+}
+
+impl UniverseId {
+    fn query(&self) -> String {
+        format!(
+            "proof_type=PROOF_TYPE_{}",
+            self.proof_type.to_ascii_uppercase()
+        )
+    }
+}
+
+fn universe_ids(roots: &Value) -> Vec<UniverseId> {
+    roots
+        .get("universe_roots")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flat_map(|roots| roots.values())
+        .filter_map(|root| {
+            let id = root.get("id")?;
+            let proof_type = match str_field(id, "proof_type")? {
+                "PROOF_TYPE_ISSUANCE" => "issuance",
+                "PROOF_TYPE_TRANSFER" => "transfer",
+                _ => return None,
+            };
+            let asset_id = str_field(id, "asset_id")
+                .or_else(|| str_field(id, "asset_id_str"))
+                .map(normalize_hex_id);
+            let group_key = str_field(id, "group_key")
+                .or_else(|| str_field(id, "group_key_str"))
+                .map(normalize_hex_id);
+            (asset_id.is_some() || group_key.is_some()).then_some(UniverseId {
+                asset_id,
+                group_key,
+                proof_type,
+            })
+        })
+        .collect()
+}
+
+fn universe_leaves(leaves: &Value, id: &UniverseId, indexed_at: i64) -> Vec<UniverseLeaf> {
+    array_field(leaves, "leaves")
+        .iter()
+        .filter_map(|leaf| {
+            let asset = leaf.get("asset")?;
+            let group_key = asset
+                .get("asset_group")
+                .and_then(|g| str_field(g, "tweaked_group_key"))
+                .map(normalize_hex_id)
+                .or_else(|| id.group_key.clone());
+            Some(UniverseLeaf {
+                asset_id: asset
+                    .get("asset_genesis")
+                    .and_then(|g| str_field(g, "asset_id"))
+                    .map(normalize_hex_id)
+                    .or_else(|| id.asset_id.clone())?,
+                script_key: normalize_hex_id(str_field(asset, "script_key")?),
+                outpoint: asset
+                    .get("chain_anchor")
+                    .and_then(|a| str_field(a, "anchor_outpoint"))?
+                    .to_ascii_lowercase(),
+                proof_type: id.proof_type.to_string(),
+                group_key,
+                amount: u64_field(asset, "amount")?,
+                proof: str_field(leaf, "proof").map(str::to_string),
+                indexed_at,
+            })
+        })
+        .collect()
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+impl Indexer {
+    /// Records a new backfill and runs it in the background. Only one
+    /// backfill runs at a time.
+    pub async fn start_backfill(self: Arc<Self>) -> Result<BackfillRun, AppError> {
+        let now = now();
+        let run = BackfillRun {
+            id: uuid::Uuid::new_v4().to_string(),
+            status: BackfillStatus::Running,
+            phase: BackfillPhase::Transfers,
+            cursor: 0,
+            progress: BackfillProgress::default(),
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+            finished_at: None,
+        };
+        if !self.database.insert_backfill_run(&run).await? {
+            return Err(AppError::ValidationError(
+                "A backfill is already running".to_string(),
+            ));
+        }
+        tokio::spawn(self.run_backfill(run.clone()));
+        Ok(run)
+    }
+
+    /// Continues a partial backfill from its last saved step, in the
+    /// background.
+    pub async fn resume_backfill(self: Arc<Self>, id: &str) -> Result<BackfillRun, AppError> {
+        if !self.database.claim_backfill_run(id).await? {
+            return match self.database.get_backfill_run(id).await? {
+                Some(run) if run.status == BackfillStatus::Partial => Err(
+                    AppError::ValidationError("Another backfill is running".to_string()),
+                ),
+                Some(run) => Err(AppError::ValidationError(format!(
+                    "Backfill {id} is {} and cannot be resumed",
+                    run.status.as_str()
+                ))),
+                None => Err(AppError::NotFound(format!("Backfill {id} not found"))),
+            };
+        }
+        let run = self
+            .database
+            .get_backfill_run(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Backfill {id} not found")))?;
+        tokio::spawn(self.run_backfill(run.clone()));
+        Ok(run)
+    }
+
+    async fn run_backfill(self: Arc<Self>, mut run: BackfillRun) {
+        info!(
+            "Backfill {} running from phase {}",
+            run.id,
+            run.phase.as_str()
+        );
+        run.last_error = None;
+        while run.phase != BackfillPhase::Done {
+            let step = self.backfill_step(&mut run).await;
+            run.updated_at = now();
+            let saved = match step {
+                Ok(()) => self.database.save_backfill_run(&run).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = saved {
+                warn!(
+                    "Backfill {} stopped in phase {}: {}",
+                    run.id,
+                    run.phase.as_str(),
+                    e
+                );
+                run.status = BackfillStatus::Partial;
+                run.last_error = Some(e.to_string());
+                break;
+            }
+        }
+        if run.phase == BackfillPhase::Done {
+            run.status = BackfillStatus::Completed;
+            run.finished_at = Some(run.updated_at);
+            info!("Backfill {} complete: {:?}", run.id, run.progress);
+        }
+        if let Err(e) = self.database.save_backfill_run(&run).await {
+            warn!("Failed to save backfill {}: {}", run.id, e);
+        }
+    }
+
+    /// Runs one saved step: a whole listing, or one page of universe roots.
+    async fn backfill_step(&self, run: &mut BackfillRun) -> Result<(), AppError> {
+        let stored = match run.phase {
+            BackfillPhase::Transfers => self.store_batched(&self.transfer_rows().await?).await?,
+            BackfillPhase::Receives => self.store_batched(&self.receive_rows().await?).await?,
+            BackfillPhase::Mints => self.store_batched(&self.mint_rows().await?).await?,
+            BackfillPhase::Burns => {
+                // Burns are dated by their anchoring transfer, listed again
+                // so a resumed run does not depend on the first phase
+                let transfers = self.transfer_rows().await?;
+                self.store_batched(&self.burn_rows(&transfers).await?)
+                    .await?
+            }
+            BackfillPhase::Universe => {
+                let roots = self.backfill_universe_page(run).await?;
+                // A short page is the last; a long one means tapd ignored
+                // the paging and returned every root at once
+                if roots != UNIVERSE_ROOTS_PAGE_SIZE {
+                    run.phase = run.phase.next();
+                }
+                return Ok(());
+            }
+            BackfillPhase::Done => return Ok(()),
+        } as u64;
+        match run.phase {
+            BackfillPhase::Transfers => run.progress.transfers = stored,
+            BackfillPhase::Receives => run.progress.receives = stored,
+            BackfillPhase::Mints => run.progress.mints = stored,
+            _ => run.progress.burns = stored,
+        }
+        run.phase = run.phase.next();
+        Ok(())
+    }
+
+    /// Copies the leaves of the universe roots page at `run.cursor` and
+    /// advances the cursor. Returns how many roots the page had.
+    async fn backfill_universe_page(&self, run: &mut BackfillRun) -> Result<u64, AppError> {
+        let query = format!("offset={}&limit={UNIVERSE_ROOTS_PAGE_SIZE}", run.cursor);
+        let roots =
+            universe::get_roots(&self.client, &self.base_url, &self.macaroon_hex, &query).await?;
+        let ids = universe_ids(&roots);
+        let count = roots
+            .get("universe_roots")
+            .and_then(Value::as_object)
+            .map_or(0, |roots| roots.len() as u64);
+
+        // Counted only once the whole page is stored, so a page retried on
+        // resume is not counted twice
+        let mut copied = 0;
+        for id in &ids {
+            let leaves = match (&id.asset_id, &id.group_key) {
+                (_, Some(group_key)) => {
+                    universe::get_group_leaves(
+                        &self.client,
+                        &self.base_url,
+                        &self.macaroon_hex,
+                        group_key,
+                        &id.query(),
+                    )
+                    .await?
+                }
+                (Some(asset_id), None) => {
+                    universe::get_leaves(
+                        &self.client,
+                        &self.base_url,
+                        &self.macaroon_hex,
+                        asset_id,
+                        &id.query(),
+                    )
+                    .await?
+                }
+                (None, None) => continue,
+            };
+            let leaves = universe_leaves(&leaves, id, now());
+            for batch in leaves.chunks(self.batch_size) {
+                self.database.upsert_universe_leaves(batch).await?;
+            }
+            copied += leaves.len() as u64;
+        }
+        run.cursor += count;
+        run.progress.universe_roots += count;
+        run.progress.universe_leaves += copied;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_universe_roots_and_leaves_normalize() {
+        let roots = serde_json::json!({
+            "universe_roots": {
+                "issuance-aa": { "id": { "asset_id": "qqo=", "proof_type": "PROOF_TYPE_ISSUANCE" } },
+                "transfer-gg": { "id": { "group_key": "03bb", "proof_type": "PROOF_TYPE_TRANSFER" } },
+                "unknown": { "id": { "asset_id": "cc", "proof_type": "PROOF_TYPE_UNSPECIFIED" } }
+            }
+        });
+        let mut ids = universe_ids(&roots);
+        ids.sort_by_key(|id| id.proof_type);
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids[0].asset_id.as_deref(), Some("aaaa"));
+        assert_eq!(ids[0].query(), "proof_type=PROOF_TYPE_ISSUANCE");
+        assert_eq!(ids[1].group_key.as_deref(), Some("03bb"));
+
+        let leaves = serde_json::json!({
+            "leaves": [
+                {
+                    "asset": {
+                        "asset_genesis": { "asset_id": "AAAA" },
+                        "script_key": "02CC",
+                        "amount": "1000",
+                        "chain_anchor": { "anchor_outpoint": "FF00:1" }
+                    },
+                    "proof": "cHJvb2Y="
+                },
+                { "asset": { "script_key": "02dd" } }
+            ]
+        });
+        let leaves = universe_leaves(&leaves, &ids[1], 5);
+        assert_eq!(leaves.len(), 1);
+        assert_eq!(leaves[0].asset_id, "aaaa");
+        assert_eq!(leaves[0].outpoint, "ff00:1");
+        assert_eq!(leaves[0].group_key.as_deref(), Some("03bb"));
+        assert_eq!(leaves[0].amount, 1000);
+        assert_eq!(leaves[0].proof_type, "transfer");
+    }
+}
@@ -0,0 +1,108 @@
+//! Confirmation policy applied when reporting receives, so merchant
+//! integrations never see a 0-conf or 1-conf receive as settled when the
+//! operator asked for more.
+
+use crate::database::{ChainStatus, IndexedTransfer, TransferKind};
+
+/// Reported in place of tapd's `completed` while a receive is below the
+/// confirmation threshold.
+pub const STATUS_PENDING_CONFIRMATIONS: &str = "pending_confirmations";
+
+/// tapd receive statuses that imply the anchor transaction has at least one
+/// confirmation. Kept in sync with `Database::pending_receives`.
+const CONFIRMED_RECEIVE_STATUSES: [&str; 3] =
+    ["transaction_confirmed", "proof_received", "completed"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceivePolicy {
+    pub min_confirmations: u32,
+}
+
+impl ReceivePolicy {
+    pub fn new(min_confirmations: u32) -> Self {
+        Self { min_confirmations }
+    }
+
+    /// Best known confirmation depth: the reconciler's count, or one when
+    /// only tapd's status says the transaction confirmed.
+    pub fn confirmations(transfer: &IndexedTransfer) -> u32 {
+        let from_tapd = CONFIRMED_RECEIVE_STATUSES.contains(&transfer.status.as_str()) as u32;
+        transfer.confirmations.unwrap_or(0).max(from_tapd)
+    }
+
+    pub fn is_settled(&self, transfer: &IndexedTransfer) -> bool {
+        if transfer.kind != TransferKind::Receive {
+            return false;
+        }
+        if matches!(
+            transfer.chain_status,
+            Some(ChainStatus::Reorged | ChainStatus::Replaced)
+        ) {
+            return false;
+        }
+        Self::confirmations(transfer) >= self.min_confirmations
+    }
+
+    /// Downgrades a receive tapd reports as complete to
+    /// [`STATUS_PENDING_CONFIRMATIONS`] until it meets the threshold.
+    pub fn apply(&self, transfer: &mut IndexedTransfer) {
+        if transfer.kind == TransferKind::Receive
+            && transfer.status == "completed"
+            && !self.is_settled(transfer)
+        {
+            transfer.status = STATUS_PENDING_CONFIRMATIONS.to_string();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receive(status: &str, confirmations: Option<u32>) -> IndexedTransfer {
+        IndexedTransfer {
+            id: "receive:aa:0:bb".to_string(),
+            kind: TransferKind::Receive,
+            asset_id: None,
+            address: None,
+            amount: Some(1),
+            anchor_txid: None,
+            outpoint: None,
+            block_height: None,
+            status: status.to_string(),
+            timestamp: 0,
+            raw: serde_json::Value::Null,
+            chain_status: confirmations.map(|_| ChainStatus::Confirmed),
+            confirmations,
+            block_hash: None,
+            label: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_single_confirmation_policy_trusts_tapd() {
+        let policy = ReceivePolicy::new(1);
+        assert!(policy.is_settled(&receive("completed", None)));
+        assert!(!policy.is_settled(&receive("transaction_detected", None)));
+    }
+
+    #[test]
+    fn test_completed_receive_is_held_back_below_threshold() {
+        let policy = ReceivePolicy::new(3);
+        let mut shallow = receive("completed", Some(2));
+        policy.apply(&mut shallow);
+        assert_eq!(shallow.status, STATUS_PENDING_CONFIRMATIONS);
+
+        let mut deep = receive("completed", Some(3));
+        policy.apply(&mut deep);
+        assert_eq!(deep.status, "completed");
+    }
+
+    #[test]
+    fn test_reorged_receive_is_not_settled() {
+        let mut transfer = receive("completed", Some(6));
+        transfer.chain_status = Some(ChainStatus::Reorged);
+        assert!(!ReceivePolicy::new(1).is_settled(&transfer));
+    }
+}
@@ -0,0 +1,175 @@
+//! Reconciles indexed transfers against the chain so consumers see
+//! `confirmed(n)`, `reorged` and `replaced` instead of assuming the first
+//! confirmation is final.
+
+use crate::chain::TxConfirmation;
+use crate::database::{ChainState, ChainStatus, IndexedTransfer};
+
+pub const TOPIC_CONFIRMED: &str = "transfer.confirmed";
+pub const TOPIC_REORGED: &str = "transfer.reorged";
+pub const TOPIC_REPLACED: &str = "transfer.replaced";
+
+/// Computes the next chain state for `transfer` given what the chain backend
+/// currently reports for its anchor transaction (`None` when the backend no
+/// longer knows it). Returns `None` when nothing changed, otherwise the new
+/// state and the event topics to publish, in order.
+pub fn reconcile(
+    transfer: &IndexedTransfer,
+    observed: Option<&TxConfirmation>,
+) -> Option<(ChainState, Vec<&'static str>)> {
+    let previous = transfer.chain_status;
+
+    let Some(tx) = observed else {
+        // Only a transaction the backend has seen before can disappear.
+        return match previous {
+            Some(ChainStatus::Replaced) | None => None,
+            Some(_) => Some((
+                ChainState {
+                    status: ChainStatus::Replaced,
+                    confirmations: 0,
+                    block_hash: None,
+                    block_height: None,
+                },
+                vec![TOPIC_REPLACED],
+            )),
+        };
+    };
+
+    let Some(block_hash) = tx.block_hash.as_ref() else {
+        let status = match previous {
+            Some(ChainStatus::Confirmed) => ChainStatus::Reorged,
+            None => ChainStatus::Unconfirmed,
+            // Already unconfirmed or known to be reorged.
+            Some(_) => return None,
+        };
+        let topics = if status == ChainStatus::Reorged {
+            vec![TOPIC_REORGED]
+        } else {
+            Vec::new()
+        };
+        return Some((
+            ChainState {
+                status,
+                confirmations: 0,
+                block_hash: None,
+                block_height: None,
+            },
+            topics,
+        ));
+    };
+
+    let was_confirmed = previous == Some(ChainStatus::Confirmed);
+    let moved_block = was_confirmed
+        && transfer
+            .block_hash
+            .as_ref()
+            .is_some_and(|prev| prev != block_hash);
+
+    let mut topics = Vec::new();
+    if moved_block {
+        topics.push(TOPIC_REORGED);
+    }
+    if !was_confirmed || moved_block || transfer.confirmations != Some(tx.confirmations) {
+        topics.push(TOPIC_CONFIRMED);
+    }
+    if topics.is_empty() {
+        return None;
+    }
+
+    Some((
+        ChainState {
+            status: ChainStatus::Confirmed,
+            confirmations: tx.confirmations,
+            block_hash: Some(block_hash.clone()),
+            block_height: tx.block_height,
+        },
+        topics,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TransferKind;
+
+    fn transfer(
+        status: Option<ChainStatus>,
+        confs: Option<u32>,
+        hash: Option<&str>,
+    ) -> IndexedTransfer {
+        IndexedTransfer {
+            id: "send:aa:bb".to_string(),
+            kind: TransferKind::Send,
+            asset_id: None,
+            address: None,
+            amount: None,
+            anchor_txid: Some("aa".to_string()),
+            outpoint: None,
+            block_height: None,
+            status: "completed".to_string(),
+            timestamp: 0,
+            raw: serde_json::Value::Null,
+            chain_status: status,
+            confirmations: confs,
+            block_hash: hash.map(str::to_string),
+            label: None,
+            tags: Vec::new(),
+        }
+    }
+
+    fn confirmed(n: u32, hash: &str) -> TxConfirmation {
+        TxConfirmation {
+            confirmations: n,
+            block_hash: Some(hash.to_string()),
+            block_height: Some(100),
+        }
+    }
+
+    fn mempool() -> TxConfirmation {
+        TxConfirmation {
+            confirmations: 0,
+            block_hash: None,
+            block_height: None,
+        }
+    }
+
+    #[test]
+    fn test_new_confirmation_emits_confirmed() {
+        let (state, topics) =
+            reconcile(&transfer(None, None, None), Some(&confirmed(1, "h1"))).unwrap();
+        assert_eq!(state.status, ChainStatus::Confirmed);
+        assert_eq!(state.confirmations, 1);
+        assert_eq!(topics, vec![TOPIC_CONFIRMED]);
+    }
+
+    #[test]
+    fn test_unchanged_confirmation_is_a_no_op() {
+        let t = transfer(Some(ChainStatus::Confirmed), Some(2), Some("h1"));
+        assert!(reconcile(&t, Some(&confirmed(2, "h1"))).is_none());
+    }
+
+    #[test]
+    fn test_block_hash_change_is_a_reorg() {
+        let t = transfer(Some(ChainStatus::Confirmed), Some(2), Some("h1"));
+        let (state, topics) = reconcile(&t, Some(&confirmed(1, "h2"))).unwrap();
+        assert_eq!(state.block_hash.as_deref(), Some("h2"));
+        assert_eq!(topics, vec![TOPIC_REORGED, TOPIC_CONFIRMED]);
+    }
+
+    #[test]
+    fn test_confirmed_tx_back_in_mempool_is_reorged() {
+        let t = transfer(Some(ChainStatus::Confirmed), Some(1), Some("h1"));
+        let (state, topics) = reconcile(&t, Some(&mempool())).unwrap();
+        assert_eq!(state.status, ChainStatus::Reorged);
+        assert_eq!(topics, vec![TOPIC_REORGED]);
+    }
+
+    #[test]
+    fn test_missing_tx_is_replaced_only_if_seen_before() {
+        assert!(reconcile(&transfer(None, None, None), None).is_none());
+        let t = transfer(Some(ChainStatus::Unconfirmed), Some(0), None);
+        let (state, topics) = reconcile(&t, None).unwrap();
+        assert_eq!(state.status, ChainStatus::Replaced);
+        assert_eq!(topics, vec![TOPIC_REPLACED]);
+    }
+}
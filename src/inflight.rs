@@ -0,0 +1,163 @@
+//! Registry of backend calls currently waiting on tapd, for debugging stuck
+//! requests. [`crate::backend::BackendClient`] registers every call; the
+//! gateway request that caused it is attached through a task-local set by the
+//! request ID middleware, so calls made by background jobs have no caller.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::oneshot;
+
+static REGISTRY: InflightRegistry = InflightRegistry::new();
+
+tokio::task_local! {
+    static CALLER: Caller;
+}
+
+/// The gateway request a backend call is made for.
+#[derive(Debug, Clone, Serialize)]
+pub struct Caller {
+    pub request_id: String,
+    /// `METHOD /path` as the client sent it.
+    pub route: String,
+    pub client_ip: Option<String>,
+}
+
+/// Runs `fut` with `caller` attached to the backend calls it makes.
+pub fn with_caller<F: Future>(caller: Caller, fut: F) -> impl Future<Output = F::Output> {
+    CALLER.scope(caller, fut)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InflightRequest {
+    pub id: u64,
+    pub method: String,
+    /// tapd path, without its query string.
+    pub path: String,
+    /// Unix seconds.
+    pub started_at: i64,
+    pub elapsed_ms: u64,
+    pub caller: Option<Caller>,
+}
+
+struct Entry {
+    method: String,
+    path: String,
+    started: Instant,
+    started_at: i64,
+    caller: Option<Caller>,
+    cancel: oneshot::Sender<()>,
+}
+
+pub struct InflightRegistry {
+    next_id: AtomicU64,
+    requests: Mutex<BTreeMap<u64, Entry>>,
+}
+
+/// Removes its call from the registry when the call finishes or is dropped.
+pub struct InflightGuard {
+    id: u64,
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        REGISTRY.lock().remove(&self.id);
+    }
+}
+
+impl InflightRegistry {
+    const fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            requests: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, Entry>> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records a call. The receiver fires if an operator cancels it.
+    pub fn register(&self, method: &str, path: &str) -> (InflightGuard, oneshot::Receiver<()>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (cancel, cancelled) = oneshot::channel();
+        let path = path.split('?').next().unwrap_or(path);
+        self.lock().insert(
+            id,
+            Entry {
+                method: method.to_string(),
+                path: path.to_string(),
+                started: Instant::now(),
+                started_at: chrono::Utc::now().timestamp(),
+                caller: CALLER.try_with(Caller::clone).ok(),
+                cancel,
+            },
+        );
+        (InflightGuard { id }, cancelled)
+    }
+
+    /// Calls in flight, longest running first.
+    pub fn list(&self) -> Vec<InflightRequest> {
+        // Ids grow with start time, so map order is oldest first
+        self.lock()
+            .iter()
+            .map(|(id, entry)| InflightRequest {
+                id: *id,
+                method: entry.method.clone(),
+                path: entry.path.clone(),
+                started_at: entry.started_at,
+                elapsed_ms: entry.started.elapsed().as_millis() as u64,
+                caller: entry.caller.clone(),
+            })
+            .collect()
+    }
+
+    /// Aborts a call; its caller gets a 503. Returns false if it already
+    /// finished.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.lock().remove(&id) {
+            Some(entry) => entry.cancel.send(()).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// The process-wide registry.
+pub fn registry() -> &'static InflightRegistry {
+    &REGISTRY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_calls_carry_their_caller_and_can_be_cancelled() {
+        let caller = Caller {
+            request_id: "req-1".to_string(),
+            route: "GET /v1/taproot-assets/assets".to_string(),
+            client_ip: Some("10.0.0.1".to_string()),
+        };
+        let (guard, cancelled) = with_caller(caller, async {
+            registry().register("GET", "/v1/taproot-assets/assets?include_spent=true")
+        })
+        .await;
+        let (background, _) = registry().register("POST", "/v1/taproot-assets/send");
+
+        let listed = registry().list();
+        let call = listed.iter().find(|r| r.id == guard.id).unwrap();
+        assert_eq!(call.path, "/v1/taproot-assets/assets");
+        assert_eq!(call.caller.as_ref().unwrap().request_id, "req-1");
+        let job = listed.iter().find(|r| r.id == background.id).unwrap();
+        assert!(job.caller.is_none());
+
+        assert!(registry().cancel(guard.id));
+        assert!(cancelled.await.is_ok());
+        assert!(!registry().cancel(guard.id));
+        drop(background);
+        assert!(registry().list().iter().all(|r| r.id != guard.id));
+    }
+}
@@ -0,0 +1,168 @@
+//! Latency histograms of backend calls per tapd endpoint, so a degrading
+//! RPC stands out instead of vanishing into a global average. Every call
+//! made through [`crate::backend::BackendClient`] is counted into fixed
+//! buckets under its path with identifier segments collapsed, and exported
+//! on `/metrics` and `/v1/gateway/monitor/latency`.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the buckets, in milliseconds; slower calls fall in a
+/// final unbounded bucket.
+pub const BUCKETS_MS: [u64; 12] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 30000];
+
+/// Endpoints tracked separately; calls to further endpoints are not
+/// counted.
+const MAX_TRACKED_ENDPOINTS: usize = 256;
+
+static HISTOGRAMS: LatencyHistograms = LatencyHistograms::new();
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Histogram {
+    /// Per bucket, plus one for calls slower than the last bound.
+    counts: [u64; BUCKETS_MS.len() + 1],
+    sum_ms: u64,
+}
+
+impl Histogram {
+    fn record(&mut self, elapsed_ms: u64) {
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|bound| elapsed_ms <= *bound)
+            .unwrap_or(BUCKETS_MS.len());
+        self.counts[bucket] += 1;
+        self.sum_ms = self.sum_ms.saturating_add(elapsed_ms);
+    }
+
+    fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Upper bound of the bucket holding quantile `q`; `None` when the
+    /// quantile falls past the last bound.
+    fn quantile_ms(&self, q: f64) -> Option<u64> {
+        let rank = ((self.count() as f64) * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bound, count) in BUCKETS_MS.iter().zip(self.counts) {
+            seen += count;
+            if seen >= rank {
+                return Some(*bound);
+            }
+        }
+        None
+    }
+}
+
+/// One endpoint's row of the heatmap.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EndpointLatency {
+    pub endpoint: String,
+    pub count: u64,
+    pub sum_ms: u64,
+    pub mean_ms: f64,
+    /// Calls per bucket of [`BUCKETS_MS`], not cumulative; the last entry
+    /// counts calls slower than every bound.
+    pub buckets: Vec<u64>,
+    /// Bucket bounds the median and 99th percentile fall under; `null`
+    /// past the last bound.
+    pub p50_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct LatencyHistograms {
+    endpoints: Mutex<BTreeMap<String, Histogram>>,
+}
+
+impl LatencyHistograms {
+    pub const fn new() -> Self {
+        Self {
+            endpoints: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records how long a call to `path` took to answer, or to time out.
+    pub fn record(&self, path: &str, elapsed: Duration) {
+        let path = path.split('?').next().unwrap_or_default();
+        let endpoint = crate::timeouts::normalize_path(path);
+        let mut endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        if !endpoints.contains_key(&endpoint) && endpoints.len() >= MAX_TRACKED_ENDPOINTS {
+            return;
+        }
+        endpoints
+            .entry(endpoint)
+            .or_default()
+            .record(elapsed.as_millis() as u64);
+    }
+
+    /// Every tracked endpoint, by path.
+    pub fn snapshot(&self) -> Vec<EndpointLatency> {
+        let endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        endpoints
+            .iter()
+            .map(|(endpoint, histogram)| {
+                let count = histogram.count();
+                EndpointLatency {
+                    endpoint: endpoint.clone(),
+                    count,
+                    sum_ms: histogram.sum_ms,
+                    mean_ms: if count == 0 {
+                        0.0
+                    } else {
+                        histogram.sum_ms as f64 / count as f64
+                    },
+                    buckets: histogram.counts.to_vec(),
+                    p50_ms: histogram.quantile_ms(0.5),
+                    p99_ms: histogram.quantile_ms(0.99),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for LatencyHistograms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The process-wide histograms backend calls are counted into.
+pub fn histograms() -> &'static LatencyHistograms {
+    &HISTOGRAMS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls_are_bucketed_per_endpoint() {
+        let histograms = LatencyHistograms::new();
+        let asset = "ab".repeat(32);
+        for ms in [3, 40, 40, 700] {
+            histograms.record(
+                &format!("/v1/taproot-assets/assets/meta/asset-id/{asset}?x=1"),
+                Duration::from_millis(ms),
+            );
+        }
+        histograms.record("/v1/taproot-assets/getinfo", Duration::from_secs(45));
+
+        let snapshot = histograms.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        let meta = &snapshot[0];
+        assert_eq!(meta.endpoint, "/v1/taproot-assets/assets/meta/asset-id/*");
+        assert_eq!(meta.count, 4);
+        assert_eq!(meta.sum_ms, 783);
+        assert_eq!(meta.buckets[0], 1);
+        assert_eq!(meta.buckets[3], 2);
+        assert_eq!(meta.buckets[7], 1);
+        assert_eq!(meta.p50_ms, Some(50));
+        assert_eq!(meta.p99_ms, Some(1000));
+
+        let info = &snapshot[1];
+        assert_eq!(info.buckets[BUCKETS_MS.len()], 1);
+        assert_eq!(info.p50_ms, None);
+    }
+}
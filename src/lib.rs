@@ -1,14 +1,72 @@
+pub mod address_expiry;
+pub mod anomaly;
 pub mod api;
+pub mod api_version;
+pub mod audit;
+pub mod backend;
+pub mod cache_audit;
+pub mod canary;
+pub mod chain;
+pub mod channel_events;
+pub mod coin_selection;
+pub mod cold_watch;
 pub mod config;
 pub mod connection_pool;
 pub mod crypto;
 pub mod database;
+pub mod destination_guard;
+pub mod env_migration;
 pub mod error;
+pub mod escrows;
+pub mod event_bus;
+pub mod faucet;
+pub mod feature_flags;
+pub mod field_case;
+pub mod gateway;
+pub mod indexer;
+pub mod inflight;
+pub mod latency;
+pub mod lifetime_stats;
+pub mod loadgen;
+pub mod lockout;
+pub mod macaroon;
+pub mod mailbox_outbox;
+pub mod maintenance;
 pub mod middleware;
+pub mod monitor;
 pub mod monitoring;
+pub mod network_acl;
+pub mod notifier;
+pub mod oidc;
+pub mod onion;
+pub mod outbound_proxy;
+pub mod payment_requests;
+pub mod pdf;
+pub mod priority;
+pub mod proof_cache;
+pub mod psbt_flows;
+pub mod rate_limit;
+pub mod receiver_keys;
+pub mod recurring_payments;
+pub mod redaction;
+pub mod route_groups;
+pub mod scheduler;
+pub mod sessions;
+pub mod shadow;
+pub mod shed;
+pub mod swaps;
+pub mod tenants;
+pub mod timeouts;
 pub mod types;
+pub mod universe_sync;
+pub mod usage;
+pub mod utxo_analytics;
+pub mod warmup;
+pub mod webhooks;
 pub mod websocket;
 
 pub mod tests {
+    pub mod fixtures;
+    pub mod regtest;
     pub mod setup;
 }
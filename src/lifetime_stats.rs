@@ -0,0 +1,142 @@
+//! Since-install totals of the key monitoring counters. The counters live
+//! in memory and start from zero with every process; the `stats_flush` job
+//! adds what they gained since its previous run to the gateway database, so
+//! `/v1/gateway/stats` reports totals that survive restarts. Counts made
+//! after the last flush are lost when the process stops.
+
+use crate::database::{GatewayCounters, SharedDatabase};
+use crate::error::AppError;
+use crate::monitor::RequestStats;
+use crate::websocket::connection_manager::ConnectionManagerState;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Counter values by name.
+pub type Counters = BTreeMap<String, u64>;
+
+/// Values of the persisted counters since boot.
+pub fn boot_counters(requests: &RequestStats, websockets: &ConnectionManagerState) -> Counters {
+    let (total, client_errors, server_errors) = requests.totals();
+    [
+        ("http_requests", total),
+        ("http_client_errors", client_errors),
+        ("http_server_errors", server_errors),
+        ("auth_failures", requests.auth_failures()),
+        ("websocket_connections", websockets.opened_total),
+        ("websocket_messages", websockets.messages_total),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value))
+    .collect()
+}
+
+/// What each counter gained since `flushed`.
+fn gains(current: &Counters, flushed: &Counters) -> Counters {
+    current
+        .iter()
+        .filter_map(|(name, value)| {
+            let gain = value.saturating_sub(flushed.get(name).copied().unwrap_or(0));
+            (gain > 0).then(|| (name.clone(), gain))
+        })
+        .collect()
+}
+
+pub struct LifetimeStats {
+    database: SharedDatabase,
+    /// Boot counters as of the last flush. Held across the database write
+    /// so concurrent flushes never add the same gain twice.
+    flushed: Mutex<Counters>,
+}
+
+pub type SharedLifetimeStats = Arc<LifetimeStats>;
+
+impl LifetimeStats {
+    pub fn new(database: SharedDatabase) -> Self {
+        Self {
+            database,
+            flushed: Mutex::new(Counters::new()),
+        }
+    }
+
+    /// Adds what `current` gained since the last flush to the stored
+    /// totals. On failure the gain is kept for the next attempt.
+    pub async fn flush(&self, current: &Counters) -> Result<(), AppError> {
+        let mut flushed = self.flushed.lock().await;
+        let gains = gains(current, &flushed);
+        if gains.is_empty() {
+            return Ok(());
+        }
+        let now = chrono::Utc::now().timestamp();
+        self.database.add_gateway_counters(&gains, now).await?;
+        flushed.extend(current.iter().map(|(name, value)| (name.clone(), *value)));
+        Ok(())
+    }
+
+    /// Since-install totals, including counts not flushed yet.
+    pub async fn totals(&self, current: &Counters) -> Result<GatewayCounters, AppError> {
+        let flushed = self.flushed.lock().await;
+        let mut totals = self.database.gateway_counters().await?;
+        for name in current.keys() {
+            totals.values.entry(name.clone()).or_insert(0);
+        }
+        for (name, gain) in gains(current, &flushed) {
+            *totals.values.entry(name).or_insert(0) += gain;
+        }
+        Ok(totals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+    use actix_web::http::StatusCode;
+
+    fn websockets(opened_total: u64, messages_total: u64) -> ConnectionManagerState {
+        ConnectionManagerState {
+            open: 0,
+            by_endpoint: BTreeMap::new(),
+            opened_total,
+            failed_total: 0,
+            reconnect_attempts_total: 0,
+            stale_removed_total: 0,
+            messages_total,
+            sockets: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_totals_survive_a_restart() {
+        let db = open_test_database().await;
+        let requests = RequestStats::default();
+        requests.record(StatusCode::OK);
+        requests.record(StatusCode::UNAUTHORIZED);
+
+        let first = LifetimeStats::new(db.clone());
+        first
+            .flush(&boot_counters(&requests, &websockets(1, 10)))
+            .await
+            .unwrap();
+        requests.record(StatusCode::OK);
+        let current = boot_counters(&requests, &websockets(2, 15));
+        // Flushing twice adds the gain once
+        first.flush(&current).await.unwrap();
+        first.flush(&current).await.unwrap();
+
+        // A new process starts counting from zero again
+        let second = LifetimeStats::new(db);
+        let requests = RequestStats::default();
+        requests.record(StatusCode::UNAUTHORIZED);
+        let totals = second
+            .totals(&boot_counters(&requests, &websockets(1, 4)))
+            .await
+            .unwrap();
+        assert_eq!(totals.values["http_requests"], 4);
+        assert_eq!(totals.values["auth_failures"], 2);
+        assert_eq!(totals.values["websocket_connections"], 3);
+        assert_eq!(totals.values["websocket_messages"], 19);
+        assert_eq!(totals.values["http_server_errors"], 0);
+        assert!(totals.since.is_some());
+    }
+}
@@ -0,0 +1,341 @@
+//! Load generation against a running gateway, for `taproot-assets-rest-gateway
+//! load`. Workers hit the configured HTTP routes, or hold WebSocket proxy
+//! connections, for a fixed duration; the report gives throughput and
+//! latency percentiles so proxy-layer regressions show before a release.
+
+use futures_util::StreamExt;
+use reqwest::Client;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+
+/// Read routes most clients poll, used when no `--route` is given.
+pub const DEFAULT_ROUTES: [&str; 4] = [
+    "/health",
+    "/v1/taproot-assets/getinfo",
+    "/v1/taproot-assets/assets",
+    "/v1/taproot-assets/assets/balance",
+];
+
+#[derive(Debug, Clone)]
+pub struct LoadPlan {
+    /// Gateway base URL, e.g. `http://127.0.0.1:8080`.
+    pub base_url: String,
+    /// HTTP routes, requested round-robin by every worker.
+    pub routes: Vec<String>,
+    pub concurrency: usize,
+    pub duration: Duration,
+    /// Sent as `Authorization: Bearer`.
+    pub api_key: Option<String>,
+}
+
+/// Latency percentiles in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct LatencySummary {
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencySummary {
+    pub fn from_samples(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        // Nearest-rank percentile
+        let percentile = |p: f64| {
+            let rank = ((p / 100.0) * samples.len() as f64).ceil() as usize;
+            ms(samples[rank.clamp(1, samples.len()) - 1])
+        };
+        let total: Duration = samples.iter().sum();
+        Self {
+            min_ms: ms(samples[0]),
+            mean_ms: ms(total) / samples.len() as f64,
+            p50_ms: percentile(50.0),
+            p90_ms: percentile(90.0),
+            p99_ms: percentile(99.0),
+            max_ms: ms(samples[samples.len() - 1]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadReport {
+    /// HTTP requests sent, or WebSocket handshakes completed.
+    pub requests: u64,
+    /// Failed requests: transport errors and non-2xx answers.
+    pub errors: u64,
+    /// Messages received over WebSocket connections.
+    pub messages: u64,
+    pub elapsed_secs: f64,
+    /// Successful requests per second.
+    pub throughput: f64,
+    /// Latency of successful requests, or of WebSocket handshakes.
+    pub latency: LatencySummary,
+}
+
+impl LoadReport {
+    fn new(samples: Vec<Duration>, errors: u64, messages: u64, elapsed: Duration) -> Self {
+        let requests = samples.len() as u64 + errors;
+        let elapsed_secs = elapsed.as_secs_f64();
+        Self {
+            requests,
+            errors,
+            messages,
+            elapsed_secs,
+            throughput: samples.len() as f64 / elapsed_secs.max(f64::EPSILON),
+            latency: LatencySummary::from_samples(samples),
+        }
+    }
+
+    pub fn print(&self) {
+        println!(
+            "requests: {} ({} errors) in {:.1}s",
+            self.requests, self.errors, self.elapsed_secs
+        );
+        println!("throughput: {:.1} req/s", self.throughput);
+        if self.messages > 0 {
+            println!(
+                "messages: {} ({:.1}/s)",
+                self.messages,
+                self.messages as f64 / self.elapsed_secs
+            );
+        }
+        let l = &self.latency;
+        println!(
+            "latency ms: min {:.2} mean {:.2} p50 {:.2} p90 {:.2} p99 {:.2} max {:.2}",
+            l.min_ms, l.mean_ms, l.p50_ms, l.p90_ms, l.p99_ms, l.max_ms
+        );
+    }
+}
+
+/// What every worker adds to.
+#[derive(Default)]
+struct Tally {
+    samples: Vec<Duration>,
+    errors: u64,
+    messages: u64,
+}
+
+/// Requests the plan's routes from `concurrency` workers until the
+/// duration is up.
+pub async fn run_http(plan: &LoadPlan) -> Result<LoadReport, String> {
+    if plan.routes.is_empty() {
+        return Err("at least one route is required".to_string());
+    }
+    let client = Client::builder()
+        .pool_max_idle_per_host(plan.concurrency)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let tally = Arc::new(Mutex::new(Tally::default()));
+    let start = Instant::now();
+    let deadline = start + plan.duration;
+
+    let workers: Vec<_> = (0..plan.concurrency.max(1))
+        .map(|worker| {
+            let client = client.clone();
+            let tally = tally.clone();
+            let plan = plan.clone();
+            tokio::spawn(async move {
+                let mut local = Tally::default();
+                let mut next = worker;
+                while Instant::now() < deadline {
+                    let route = &plan.routes[next % plan.routes.len()];
+                    next += 1;
+                    let mut request = client.get(format!("{}{route}", plan.base_url));
+                    if let Some(key) = &plan.api_key {
+                        request = request.bearer_auth(key);
+                    }
+                    let sent = Instant::now();
+                    match request.send().await {
+                        Ok(res) if res.status().is_success() => {
+                            // Read the body so the latency covers the whole answer
+                            if res.bytes().await.is_ok() {
+                                local.samples.push(sent.elapsed());
+                            } else {
+                                local.errors += 1;
+                            }
+                        }
+                        _ => local.errors += 1,
+                    }
+                }
+                let mut tally = tally.lock().await;
+                tally.samples.append(&mut local.samples);
+                tally.errors += local.errors;
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.await.map_err(|e| e.to_string())?;
+    }
+
+    let tally = std::mem::take(&mut *tally.lock().await);
+    Ok(LoadReport::new(
+        tally.samples,
+        tally.errors,
+        0,
+        start.elapsed(),
+    ))
+}
+
+/// Holds `concurrency` connections to a WebSocket proxy route, reconnecting
+/// when one closes, and counts the messages relayed until the duration is up.
+pub async fn run_websocket(plan: &LoadPlan, route: &str) -> Result<LoadReport, String> {
+    let url = websocket_url(&plan.base_url, route)?;
+    let tally = Arc::new(Mutex::new(Tally::default()));
+    let start = Instant::now();
+    let deadline = tokio::time::Instant::from_std(start + plan.duration);
+
+    let workers: Vec<_> = (0..plan.concurrency.max(1))
+        .map(|_| {
+            let url = url.clone();
+            let api_key = plan.api_key.clone();
+            let tally = tally.clone();
+            tokio::spawn(async move {
+                let mut local = Tally::default();
+                while tokio::time::Instant::now() < deadline {
+                    let Ok(mut request) = url.as_str().into_client_request() else {
+                        local.errors += 1;
+                        break;
+                    };
+                    if let Some(key) = &api_key {
+                        if let Ok(value) = HeaderValue::from_str(&format!("Bearer {key}")) {
+                            request.headers_mut().insert("Authorization", value);
+                        }
+                    }
+                    let sent = Instant::now();
+                    let connected = tokio::time::timeout_at(
+                        deadline,
+                        tokio_tungstenite::connect_async(request),
+                    )
+                    .await;
+                    let mut stream = match connected {
+                        Ok(Ok((stream, _))) => stream,
+                        Ok(Err(_)) => {
+                            local.errors += 1;
+                            // Back off so a refusing gateway is not spun on
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            continue;
+                        }
+                        Err(_) => break,
+                    };
+                    local.samples.push(sent.elapsed());
+                    while let Ok(Some(Ok(_))) =
+                        tokio::time::timeout_at(deadline, stream.next()).await
+                    {
+                        local.messages += 1;
+                    }
+                }
+                let mut tally = tally.lock().await;
+                tally.samples.append(&mut local.samples);
+                tally.errors += local.errors;
+                tally.messages += local.messages;
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.await.map_err(|e| e.to_string())?;
+    }
+
+    let tally = std::mem::take(&mut *tally.lock().await);
+    Ok(LoadReport::new(
+        tally.samples,
+        tally.errors,
+        tally.messages,
+        start.elapsed(),
+    ))
+}
+
+/// `ws://` or `wss://` URL of `route` on an `http(s)://` gateway.
+pub fn websocket_url(base_url: &str, route: &str) -> Result<String, String> {
+    let base = base_url.trim_end_matches('/');
+    let base = if let Some(rest) = base.strip_prefix("https://") {
+        format!("wss://{rest}")
+    } else if let Some(rest) = base.strip_prefix("http://") {
+        format!("ws://{rest}")
+    } else {
+        return Err(format!("gateway URL must be http(s): {base_url}"));
+    };
+    Ok(format!("{base}{route}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+
+    #[test]
+    fn test_latency_summary_percentiles() {
+        let samples = (1..=100).map(Duration::from_millis).collect();
+        let summary = LatencySummary::from_samples(samples);
+        assert_eq!(summary.min_ms, 1.0);
+        assert_eq!(summary.p50_ms, 50.0);
+        assert_eq!(summary.p90_ms, 90.0);
+        assert_eq!(summary.p99_ms, 99.0);
+        assert_eq!(summary.max_ms, 100.0);
+        assert_eq!(summary.mean_ms, 50.5);
+        assert_eq!(
+            LatencySummary::from_samples(Vec::new()),
+            LatencySummary::default()
+        );
+    }
+
+    #[test]
+    fn test_websocket_url() {
+        assert_eq!(
+            websocket_url("https://gw.example.com/", "/v1/x").unwrap(),
+            "wss://gw.example.com/v1/x"
+        );
+        assert_eq!(
+            websocket_url("http://127.0.0.1:8080", "/v1/x").unwrap(),
+            "ws://127.0.0.1:8080/v1/x"
+        );
+        assert!(websocket_url("ftp://x", "/").is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_run_http_counts_successes_and_errors() {
+        let server = HttpServer::new(|| {
+            App::new()
+                .route(
+                    "/ok",
+                    web::get().to(|| async { HttpResponse::Ok().body("{}") }),
+                )
+                .route(
+                    "/fail",
+                    web::get().to(|| async { HttpResponse::InternalServerError().finish() }),
+                )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_rt::spawn(server);
+
+        let report = run_http(&LoadPlan {
+            base_url: format!("http://{addr}"),
+            routes: vec!["/ok".to_string(), "/fail".to_string()],
+            concurrency: 2,
+            duration: Duration::from_millis(300),
+            api_key: None,
+        })
+        .await
+        .unwrap();
+        handle.stop(false).await;
+
+        assert!(report.errors > 0);
+        assert!(report.requests > report.errors);
+        assert!(report.throughput > 0.0);
+        assert!(report.latency.max_ms > 0.0);
+    }
+}
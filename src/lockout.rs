@@ -0,0 +1,285 @@
+//! Brute-force protection for the authentication paths. Failed API key
+//! checks, mailbox challenge answers and session logins are counted per
+//! client address and, where there is one, per claimed identity. Once a
+//! subject reaches `AUTH_LOCKOUT_THRESHOLD` failures it is locked out for
+//! `AUTH_LOCKOUT_BASE_SECS`, doubling with each further failure up to
+//! `AUTH_LOCKOUT_MAX_SECS`; locked requests get 429 before any credential
+//! is checked.
+//!
+//! Counts live in the database so lockouts survive restarts. Lookups use an
+//! in-memory copy of the active lockouts, kept by the instance that set
+//! them, so checking costs no query.
+
+use crate::config::Config;
+use crate::database::{AuthFailureRecord, SharedDatabase};
+use crate::network_acl;
+use actix_web::http::header::HeaderMap;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use ipnet::IpNet;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use tracing::warn;
+
+pub struct AuthLockouts {
+    db: SharedDatabase,
+    threshold: u32,
+    base_secs: u64,
+    max_secs: u64,
+    /// Failures older than this no longer count.
+    reset_secs: u64,
+    trusted_proxies: Vec<IpNet>,
+    /// Lockout end by subject key.
+    active: RwLock<HashMap<String, i64>>,
+    /// Subjects with counted failures, so a success without any needs no
+    /// query.
+    failing: RwLock<HashSet<String>>,
+}
+
+pub type SharedAuthLockouts = Arc<AuthLockouts>;
+
+/// A subject's failure count and lockout, as reported by the admin API.
+#[derive(Debug, Clone, Serialize)]
+pub struct LockoutStatus {
+    #[serde(flatten)]
+    pub record: AuthFailureRecord,
+    pub locked: bool,
+}
+
+impl AuthLockouts {
+    pub fn new(config: &Config, db: SharedDatabase) -> Self {
+        Self {
+            db,
+            threshold: config.auth_lockout_threshold,
+            base_secs: config.auth_lockout_base_secs,
+            max_secs: config.auth_lockout_max_secs,
+            reset_secs: config.auth_lockout_reset_secs,
+            trusted_proxies: config.trusted_proxies.clone(),
+            active: RwLock::new(HashMap::new()),
+            failing: RwLock::new(HashSet::new()),
+        }
+    }
+
+    /// Picks up the lockouts still running from earlier runs.
+    pub async fn load(&self) {
+        let now = chrono::Utc::now().timestamp();
+        let records = match self.db.auth_failure_records().await {
+            Ok(records) => records,
+            Err(e) => {
+                warn!("Failed to load auth lockouts: {}", e);
+                return;
+            }
+        };
+        let mut active = self.active.write().unwrap_or_else(|e| e.into_inner());
+        let mut failing = self.failing.write().unwrap_or_else(|e| e.into_inner());
+        for record in records {
+            failing.insert(record.key.clone());
+            if let Some(until) = record.locked_until.filter(|until| *until > now) {
+                active.insert(record.key, until);
+            }
+        }
+    }
+
+    /// The subject key of the client behind `peer`, honouring
+    /// `TRUSTED_PROXIES` like the network access rules do.
+    pub fn ip_key(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> String {
+        self.client_key("ip", peer, headers)
+    }
+
+    /// Like [`Self::ip_key`], under another `prefix`, for counting one kind
+    /// of credential apart from the others.
+    pub fn client_key(&self, prefix: &str, peer: Option<IpAddr>, headers: &HeaderMap) -> String {
+        match network_acl::client_ip(&self.trusted_proxies, peer, headers) {
+            Some(ip) => format!("{prefix}:{ip}"),
+            None => format!("{prefix}:unknown"),
+        }
+    }
+
+    /// Seconds until every one of `keys` is unlocked; `None` if none is
+    /// locked.
+    pub fn retry_after(&self, keys: &[String]) -> Option<u64> {
+        let now = chrono::Utc::now().timestamp();
+        let active = self.active.read().unwrap_or_else(|e| e.into_inner());
+        keys.iter()
+            .filter_map(|key| active.get(key))
+            .filter(|until| **until > now)
+            .map(|until| (until - now) as u64)
+            .max()
+    }
+
+    /// Counts a failure against each of `keys`, locking those that reach
+    /// the threshold. Storage errors are logged, not returned: the request
+    /// has already failed and this only decides the next one.
+    pub async fn record_failure(&self, keys: &[String]) {
+        let now = chrono::Utc::now().timestamp();
+        let reset_before = now - self.reset_secs as i64;
+        for key in keys {
+            let failures = match self.db.record_auth_failure(key, now, reset_before).await {
+                Ok(failures) => failures,
+                Err(e) => {
+                    warn!("Failed to record auth failure for {}: {}", key, e);
+                    continue;
+                }
+            };
+            self.failing
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key.clone());
+            let Some(lock_secs) = self.lock_secs(failures) else {
+                continue;
+            };
+            let until = now + lock_secs as i64;
+            if let Err(e) = self.db.set_auth_lockout(key, until).await {
+                warn!("Failed to store lockout for {}: {}", key, e);
+            }
+            self.active
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key.clone(), until);
+            warn!(
+                "Locked out {} for {}s after {} failed authentication attempts",
+                key, lock_secs, failures
+            );
+        }
+        if let Err(e) = self.db.prune_auth_failures(reset_before, now).await {
+            warn!("Failed to prune auth failures: {}", e);
+        }
+    }
+
+    /// Forgets the failures of `keys` after a successful authentication, so
+    /// only consecutive failures lock a subject out.
+    pub async fn record_success(&self, keys: &[String]) {
+        for key in keys {
+            let was_failing = self
+                .failing
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(key);
+            let was_active = self
+                .active
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(key)
+                .is_some();
+            if !was_failing && !was_active {
+                continue;
+            }
+            if let Err(e) = self.db.clear_auth_failures(key).await {
+                warn!("Failed to clear auth failures for {}: {}", key, e);
+            }
+        }
+    }
+
+    /// How long `failures` consecutive failures lock a subject out for.
+    fn lock_secs(&self, failures: u32) -> Option<u64> {
+        let excess = failures.checked_sub(self.threshold)?;
+        let factor = 1u64.checked_shl(excess.min(63)).unwrap_or(u64::MAX);
+        Some(self.base_secs.saturating_mul(factor).min(self.max_secs))
+    }
+
+    pub async fn statuses(&self) -> Result<Vec<LockoutStatus>, crate::error::AppError> {
+        let now = chrono::Utc::now().timestamp();
+        Ok(self
+            .db
+            .auth_failure_records()
+            .await?
+            .into_iter()
+            .map(|record| LockoutStatus {
+                locked: record.locked_until.is_some_and(|until| until > now),
+                record,
+            })
+            .collect())
+    }
+
+    /// Lifts `key`'s lockout and forgets its failures; false if it had
+    /// none.
+    pub async fn clear(&self, key: &str) -> Result<bool, crate::error::AppError> {
+        self.failing
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+        let was_active = self
+            .active
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key)
+            .is_some();
+        Ok(self.db.clear_auth_failures(key).await? || was_active)
+    }
+}
+
+/// 429 for a locked-out subject.
+#[derive(Debug)]
+pub struct LockedOutError(pub u64);
+
+impl std::fmt::Display for LockedOutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Too many failed authentication attempts")
+    }
+}
+
+impl ResponseError for LockedOutError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", self.0.to_string()))
+            .json(serde_json::json!({
+                "error": self.to_string(),
+                "retry_after_secs": self.0
+            }))
+    }
+}
+
+/// Lockouts on a throwaway database for unit tests: locked after 2
+/// failures for 60s, doubling up to 300s.
+#[cfg(test)]
+pub(crate) async fn open_test_lockouts() -> SharedAuthLockouts {
+    Arc::new(AuthLockouts {
+        db: crate::database::open_test_database().await,
+        threshold: 2,
+        base_secs: 60,
+        max_secs: 300,
+        reset_secs: 900,
+        trusted_proxies: Vec::new(),
+        active: RwLock::new(HashMap::new()),
+        failing: RwLock::new(HashSet::new()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lockouts_back_off_exponentially() {
+        let lockouts = open_test_lockouts().await;
+        assert_eq!(lockouts.lock_secs(1), None);
+        assert_eq!(lockouts.lock_secs(2), Some(60));
+        assert_eq!(lockouts.lock_secs(3), Some(120));
+        assert_eq!(lockouts.lock_secs(5), Some(300));
+        assert_eq!(lockouts.lock_secs(200), Some(300));
+
+        let keys = ["ip:10.0.0.1".to_string(), "login:abc".to_string()];
+        lockouts.record_failure(&keys).await;
+        assert_eq!(lockouts.retry_after(&keys), None);
+        lockouts.record_failure(&keys[1..]).await;
+        // Only the identity reached the threshold
+        assert_eq!(lockouts.retry_after(&keys[..1]), None);
+        assert!(lockouts.retry_after(&keys).is_some_and(|secs| secs > 50));
+
+        let statuses = lockouts.statuses().await.unwrap();
+        assert!(statuses
+            .iter()
+            .any(|s| s.locked && s.record.key == "login:abc" && s.record.failures == 2));
+
+        assert!(lockouts.clear("login:abc").await.unwrap());
+        assert_eq!(lockouts.retry_after(&keys), None);
+        lockouts.record_success(&keys).await;
+        assert!(lockouts.statuses().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,402 @@
+//! Server-side outbox for `/mailbox/send`. With `MAILBOX_OUTBOX_ENABLED`
+//! each message is persisted before it is handed to tapd; transient
+//! failures are retried by the `mailbox_outbox` job with backoff, and every
+//! status change (`queued`, `sent`, `acknowledged`, `failed`) is published
+//! as `mailbox.<status>` and sent to the message's callback URL. A message
+//! is acknowledged when its receiver removes it through the gateway, or
+//! explicitly through `/v1/gateway/mailbox/outbox/{id}/ack`.
+//!
+//! The `mailbox_gc` job keeps the outbox bounded: messages that are no
+//! longer queued are deleted once older than the retention age, and beyond
+//! each receiver's newest retention count. A receiver's own policy, stored
+//! under `retention` in its metadata, replaces the configured one.
+
+use crate::api::backend;
+use crate::database::{
+    OutboxMessage, OutboxStatus, RetentionScope, SharedDatabase, WebhookDelivery,
+};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::webhooks::retry_delay_secs;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+/// Topic prefix for outbox events; the suffix is the new status.
+pub const TOPIC_PREFIX: &str = "mailbox.";
+
+/// Whether a failed send is worth retrying: tapd or the network was down,
+/// as opposed to tapd refusing the message.
+pub fn is_transient(error: &AppError) -> bool {
+    match error {
+        AppError::RequestError(_) | AppError::ServiceUnavailable(_) => true,
+        AppError::UpstreamError { status, .. } => *status >= 500 || *status == 429,
+        _ => false,
+    }
+}
+
+/// The id tapd assigned a sent message; proto `uint64`s arrive as strings.
+fn message_id(response: &serde_json::Value) -> Option<u64> {
+    let id = response.get("message_id")?;
+    id.as_u64().or_else(|| id.as_str()?.parse().ok())
+}
+
+/// How long and how many finished outbox messages are kept per receiver;
+/// 0 means no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub max_age_secs: u64,
+    pub max_messages: u32,
+}
+
+/// A receiver's override; fields it leaves out keep the configured value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionOverride {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_messages: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn with_override(self, custom: RetentionOverride) -> Self {
+        Self {
+            max_age_secs: custom.max_age_secs.unwrap_or(self.max_age_secs),
+            max_messages: custom.max_messages.unwrap_or(self.max_messages),
+        }
+    }
+
+    async fn apply(
+        &self,
+        database: &SharedDatabase,
+        scope: RetentionScope<'_>,
+        now: i64,
+    ) -> Result<u64, AppError> {
+        let created_before =
+            (self.max_age_secs > 0).then(|| now.saturating_sub(self.max_age_secs as i64));
+        let keep = (self.max_messages > 0).then_some(self.max_messages);
+        if created_before.is_none() && keep.is_none() {
+            return Ok(0);
+        }
+        database
+            .prune_outbox_messages(scope, created_before, keep)
+            .await
+    }
+}
+
+/// Applies `default` to every receiver without an override of its own and
+/// each override to its receiver. Returns how many messages were deleted.
+pub async fn collect_garbage(
+    database: &SharedDatabase,
+    default: RetentionPolicy,
+) -> Result<u64, AppError> {
+    let now = chrono::Utc::now().timestamp();
+    let mut overridden = Vec::new();
+    let mut custom = Vec::new();
+    for (receiver_id, value) in database.receiver_retention_overrides().await? {
+        match serde_json::from_value::<RetentionOverride>(value) {
+            Ok(value) => {
+                custom.push((receiver_id.clone(), default.with_override(value)));
+                overridden.push(receiver_id);
+            }
+            Err(e) => warn!(
+                "Ignoring invalid mailbox retention for receiver {}: {}",
+                receiver_id, e
+            ),
+        }
+    }
+    let mut deleted = default
+        .apply(database, RetentionScope::AllExcept(&overridden), now)
+        .await?;
+    for (receiver_id, policy) in &custom {
+        deleted += policy
+            .apply(database, RetentionScope::Receiver(receiver_id), now)
+            .await?;
+    }
+    if deleted > 0 {
+        info!("Deleted {} expired mailbox outbox messages", deleted);
+    }
+    Ok(deleted)
+}
+
+#[derive(Clone)]
+pub struct MailboxOutbox {
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+    database: SharedDatabase,
+    events: SharedEventBus,
+    max_attempts: u32,
+}
+
+impl MailboxOutbox {
+    pub fn new(
+        client: Client,
+        base_url: String,
+        macaroon_hex: String,
+        database: SharedDatabase,
+        events: SharedEventBus,
+        max_attempts: u32,
+    ) -> Self {
+        Self {
+            client,
+            base_url,
+            macaroon_hex,
+            database,
+            events,
+            max_attempts,
+        }
+    }
+
+    /// Persists a new message and makes the first delivery attempt.
+    pub async fn enqueue(
+        &self,
+        request: serde_json::Value,
+        receiver_id: String,
+        callback_url: Option<String>,
+        callback_secret: Option<String>,
+    ) -> Result<OutboxMessage, AppError> {
+        let now = chrono::Utc::now().timestamp();
+        let message = OutboxMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            receiver_id,
+            request,
+            status: OutboxStatus::Queued,
+            attempts: 0,
+            last_error: None,
+            next_attempt_at: now,
+            message_id: None,
+            callback_url,
+            callback_secret,
+            created_at: now,
+            updated_at: now,
+        };
+        self.database.insert_outbox_message(&message).await?;
+        self.announce(&message).await?;
+        self.deliver(message).await
+    }
+
+    /// Retries every queued message that is due.
+    pub async fn retry_due(&self) -> Result<(), AppError> {
+        let due = self
+            .database
+            .due_outbox_messages(chrono::Utc::now().timestamp())
+            .await?;
+        for message in due {
+            self.deliver(message).await?;
+        }
+        Ok(())
+    }
+
+    /// One attempt to hand `message` to tapd, recording the outcome.
+    async fn deliver(&self, message: OutboxMessage) -> Result<OutboxMessage, AppError> {
+        let result = backend(&self.client, &self.base_url, &self.macaroon_hex)
+            .post("/v1/taproot-assets/mailbox/send")
+            .json(&message.request)
+            .fetch::<serde_json::Value>()
+            .await;
+        let now = chrono::Utc::now().timestamp();
+        let attempts = message.attempts + 1;
+        let (status, id, error, next_attempt_at) = match result {
+            Ok(response) => (OutboxStatus::Sent, message_id(&response), None, now),
+            Err(e) if is_transient(&e) && attempts < self.max_attempts => {
+                debug!(
+                    "Mailbox message {} attempt {} failed, retrying: {}",
+                    message.id, attempts, e
+                );
+                let retry_at = now + retry_delay_secs(attempts);
+                (OutboxStatus::Queued, None, Some(e.to_string()), retry_at)
+            }
+            Err(e) => {
+                warn!("Mailbox message {} failed: {}", message.id, e);
+                (OutboxStatus::Failed, None, Some(e.to_string()), now)
+            }
+        };
+        let updated = self
+            .database
+            .record_outbox_attempt(&message.id, status, id, error.as_deref(), next_attempt_at)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Mailbox message {}", message.id)))?;
+        if updated.status != message.status {
+            self.announce(&updated).await?;
+        }
+        Ok(updated)
+    }
+
+    /// Acknowledges messages a receiver removed from its mailbox.
+    pub async fn removed(&self, receiver_id: &str, message_ids: &[u64]) -> Result<(), AppError> {
+        let acknowledged = self
+            .database
+            .acknowledge_outbox_messages(receiver_id, message_ids)
+            .await?;
+        for message in &acknowledged {
+            self.announce(message).await?;
+        }
+        if !acknowledged.is_empty() {
+            info!(
+                "{} mailbox messages to {} acknowledged",
+                acknowledged.len(),
+                receiver_id
+            );
+        }
+        Ok(())
+    }
+
+    /// Acknowledges one sent message by outbox id.
+    pub async fn acknowledge(&self, id: &str) -> Result<OutboxMessage, AppError> {
+        if let Some(message) = self.database.acknowledge_outbox_message(id).await? {
+            self.announce(&message).await?;
+            return Ok(message);
+        }
+        match self.database.outbox_message(id).await? {
+            Some(message) if message.status == OutboxStatus::Acknowledged => Ok(message),
+            Some(message) => Err(AppError::ValidationError(format!(
+                "Mailbox message {id} is {} and cannot be acknowledged",
+                message.status.as_str()
+            ))),
+            None => Err(AppError::NotFound(format!(
+                "Mailbox message {id} not found"
+            ))),
+        }
+    }
+
+    /// Publishes the message's status and queues its callback.
+    async fn announce(&self, message: &OutboxMessage) -> Result<(), AppError> {
+        let topic = format!("{TOPIC_PREFIX}{}", message.status.as_str());
+        let payload = serde_json::to_value(message)?;
+        self.events.publish(&topic, payload.clone());
+        if let Some(callback_url) = message.callback_url.clone() {
+            self.database
+                .enqueue_webhook(&WebhookDelivery::new(
+                    format!("{topic}:{}", message.id),
+                    &topic,
+                    callback_url,
+                    message.callback_secret.clone(),
+                    payload,
+                ))
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{open_test_database, ReceiverInfo};
+
+    fn finished(id: &str, receiver_id: &str, created_at: i64) -> OutboxMessage {
+        OutboxMessage {
+            id: id.to_string(),
+            receiver_id: receiver_id.to_string(),
+            request: serde_json::json!({}),
+            status: OutboxStatus::Acknowledged,
+            attempts: 1,
+            last_error: None,
+            next_attempt_at: created_at,
+            message_id: None,
+            callback_url: None,
+            callback_secret: None,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gc_applies_default_and_receiver_policies() {
+        let db = open_test_database().await;
+        let now = chrono::Utc::now().timestamp();
+        for (id, receiver, age) in [
+            ("a-old", "02aa", 7200),
+            ("a-1", "02aa", 30),
+            ("a-2", "02aa", 20),
+            ("a-3", "02aa", 10),
+            ("b-old", "02bb", 7200),
+            ("b-1", "02bb", 30),
+            ("b-2", "02bb", 20),
+        ] {
+            db.insert_outbox_message(&finished(id, receiver, now - age))
+                .await
+                .unwrap();
+        }
+        let mut queued = finished("a-queued", "02aa", now - 7200);
+        queued.status = OutboxStatus::Queued;
+        db.insert_outbox_message(&queued).await.unwrap();
+
+        db.store_receiver_info(&ReceiverInfo {
+            receiver_id: "02bb".to_string(),
+            public_key: "02bb".to_string(),
+            address: None,
+            created_at: now,
+            last_seen: now,
+            is_active: true,
+            metadata: Some(serde_json::json!({ "auth_method": "mailbox" })),
+        })
+        .await
+        .unwrap();
+        let custom = serde_json::json!({ "max_messages": 1 });
+        assert!(db
+            .set_receiver_retention("02bb", Some(&custom))
+            .await
+            .unwrap());
+        assert!(!db
+            .set_receiver_retention("02cc", Some(&custom))
+            .await
+            .unwrap());
+
+        let default = RetentionPolicy {
+            max_age_secs: 3600,
+            max_messages: 2,
+        };
+        // 02aa: a-old by age, a-1 by count; 02bb keeps only its newest
+        assert_eq!(collect_garbage(&db, default).await.unwrap(), 4);
+        let mut left: Vec<String> = db
+            .outbox_messages(None, 100, 0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.id)
+            .collect();
+        left.sort();
+        assert_eq!(left, ["a-2", "a-3", "a-queued", "b-2"]);
+
+        // Re-authenticating keeps the override
+        db.store_receiver_info(&ReceiverInfo {
+            receiver_id: "02bb".to_string(),
+            public_key: "02bb".to_string(),
+            address: None,
+            created_at: now,
+            last_seen: now,
+            is_active: true,
+            metadata: Some(serde_json::json!({ "auth_method": "mailbox" })),
+        })
+        .await
+        .unwrap();
+        let overrides = db.receiver_retention_overrides().await.unwrap();
+        assert_eq!(overrides, vec![("02bb".to_string(), custom)]);
+        let info = db.get_receiver_info("02bb").await.unwrap().unwrap();
+        assert_eq!(info.metadata.unwrap()["auth_method"], "mailbox");
+    }
+
+    #[test]
+    fn test_only_outages_are_retried() {
+        assert!(is_transient(&AppError::ServiceUnavailable("down".into())));
+        assert!(is_transient(&AppError::UpstreamError {
+            status: 503,
+            body: String::new()
+        }));
+        assert!(!is_transient(&AppError::UpstreamError {
+            status: 400,
+            body: String::new()
+        }));
+        assert!(!is_transient(&AppError::ValidationError("bad".into())));
+
+        assert_eq!(
+            message_id(&serde_json::json!({ "message_id": "42" })),
+            Some(42)
+        );
+        assert_eq!(message_id(&serde_json::json!({ "message_id": 7 })), Some(7));
+        assert_eq!(message_id(&serde_json::json!({})), None);
+    }
+}
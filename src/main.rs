@@ -1,31 +1,91 @@
 use crate::{
+    canary::CanaryRouter,
+    cli::{Cli, Command},
     config::Config,
-    middleware::{ApiKeyAuth, RateLimiter, RequestIdMiddleware},
-    types::{BaseUrl, MacaroonHex},
-    websocket::{
-        connection_manager::WebSocketConnectionManager, proxy_handler::WebSocketProxyHandler,
+    gateway::Gateway,
+    middleware::{
+        ApiKeyAuth, ApiVersioning, AuditLog, BasePath, CanaryRouting, ColdWatchGuard,
+        CsrfProtection, FieldCaseNormalization, MaintenanceGuard, NetworkAclGuard,
+        PriorityQueueing, RateLimiter, Redaction, RequestIdMiddleware, RequestMetrics,
+        ShadowTraffic, ShedGuard, TenantRouting, UsageAccounting, WarmupGate,
     },
+    shadow::ShadowMirror,
 };
 use actix_cors::Cors;
 use actix_web::middleware::{DefaultHeaders, Logger};
 use actix_web::{web, App, HttpServer};
-use reqwest::Client;
+use clap::Parser;
 use std::fs;
+use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
 use tracing_subscriber::{fmt, EnvFilter};
 
-const MAX_PAYLOAD_SIZE: usize = 10 * 1024 * 1024;
-
+mod address_expiry;
+mod anomaly;
 mod api;
+mod api_version;
+mod audit;
+mod backend;
+mod cache_audit;
+mod canary;
+mod chain;
+mod channel_events;
+mod cli;
+mod coin_selection;
+mod cold_watch;
 mod config;
 pub mod connection_pool;
 pub mod crypto;
 pub mod database;
+mod destination_guard;
+mod env_migration;
 mod error;
+mod escrows;
+mod event_bus;
+mod faucet;
+mod feature_flags;
+mod field_case;
+mod gateway;
+mod indexer;
+mod inflight;
+mod latency;
+mod lifetime_stats;
+mod loadgen;
+mod lockout;
+mod macaroon;
+mod mailbox_outbox;
+mod maintenance;
 mod middleware;
+mod monitor;
 pub mod monitoring;
+mod network_acl;
+mod notifier;
+mod oidc;
+mod onion;
+mod outbound_proxy;
+mod payment_requests;
+mod pdf;
+mod priority;
+mod proof_cache;
+mod psbt_flows;
+mod rate_limit;
+mod receiver_keys;
+mod recurring_payments;
+mod redaction;
+mod route_groups;
+mod scheduler;
+mod sessions;
+mod shadow;
+mod shed;
+mod swaps;
+mod tenants;
+mod timeouts;
 mod types;
+mod universe_sync;
+mod usage;
+mod utxo_analytics;
+mod warmup;
+mod webhooks;
 mod websocket;
 
 #[actix_web::main]
@@ -38,39 +98,49 @@ async fn main() -> std::io::Result<()> {
 
     // Load environment configuration
     dotenv::from_filename(".env").ok();
+    env_migration::apply(Path::new(".env"));
+
+    match Cli::parse().command {
+        None | Some(Command::Serve) => serve().await,
+        Some(command) => cli::run(command).await,
+    }
+}
 
+async fn serve() -> std::io::Result<()> {
     // Load and validate configuration
     let config = Config::load().expect("Failed to load configuration");
 
-    // Read and encode macaroon for authentication
-    let macaroon_bytes = fs::read(&config.macaroon_path)?;
-    let macaroon_hex = hex::encode(macaroon_bytes);
-
-    // Build base URL for backend communication
-    let base_url = format!("https://{}", config.taproot_assets_host);
-
-    // Create HTTP client with security settings
-    let mut client_builder =
-        Client::builder().timeout(Duration::from_secs(config.request_timeout_secs));
+    // Backend client, WebSocket proxy, database and background tasks
+    let gateway = Gateway::builder().config(config.clone()).build().await?;
 
-    // Only disable TLS verification if explicitly configured (development only)
-    if !config.tls_verify {
-        tracing::warn!("TLS verification is disabled - this should only be used in development!");
-        client_builder = client_builder.danger_accept_invalid_certs(true);
-    }
-
-    let client = client_builder.build().expect("Failed to build HTTP client");
+    // Optional shadow backend receiving a sample of read-only traffic
+    let shadow = match (&config.shadow_backend_host, &config.shadow_macaroon_path) {
+        (Some(host), Some(path)) => {
+            let mirror = Arc::new(ShadowMirror::new(
+                gateway.client().clone(),
+                format!("https://{host}"),
+                hex::encode(fs::read(path)?),
+                config.shadow_sample_percent,
+            ));
+            mirror.log_startup();
+            Some(mirror)
+        }
+        _ => None,
+    };
 
-    // Create WebSocket infrastructure
-    let ws_base_url = base_url
-        .replace("https://", "wss://")
-        .replace("http://", "ws://");
-    let connection_manager = Arc::new(WebSocketConnectionManager::new(
-        BaseUrl(ws_base_url),
-        MacaroonHex(macaroon_hex.clone()),
-        config.tls_verify,
-    ));
-    let ws_proxy_handler = Arc::new(WebSocketProxyHandler::new(connection_manager));
+    // Optional canary backend for opted-in requests and API keys
+    let canary = match (&config.canary_backend_host, &config.canary_macaroon_path) {
+        (Some(host), Some(path)) => {
+            let router = Arc::new(CanaryRouter::new(
+                format!("https://{host}"),
+                hex::encode(fs::read(path)?),
+                config.canary_api_keys.clone(),
+            ));
+            router.log_startup();
+            Some(router)
+        }
+        _ => None,
+    };
 
     let api_key = std::env::var("API_KEY").ok();
     let allow_insecure = std::env::var("ALLOW_INSECURE_NO_AUTH")
@@ -78,6 +148,10 @@ async fn main() -> std::io::Result<()> {
         .unwrap_or(false);
     match (&api_key, allow_insecure) {
         (Some(_), _) => println!("🔑 API key authentication: enabled"),
+        (None, _) if config.oidc.is_some() => println!("🔑 API key authentication: OIDC only"),
+        (None, _) if gateway.sessions().is_some() && !config.session_login_keys.is_empty() => {
+            println!("🔑 API key authentication: session login keys only")
+        }
         (None, true) => {
             tracing::warn!(
                 "API_KEY not set and ALLOW_INSECURE_NO_AUTH=true - every route, including \
@@ -88,8 +162,8 @@ async fn main() -> std::io::Result<()> {
         (None, false) => {
             tracing::error!(
                 "API_KEY not set. The gateway proxies destructive and secret-exposing tapd \
-                 endpoints, so it refuses to start without authentication. Set API_KEY, or set \
-                 ALLOW_INSECURE_NO_AUTH=true to override in development."
+                 endpoints, so it refuses to start without authentication. Set API_KEY or \
+                 OIDC_ISSUER, or set ALLOW_INSECURE_NO_AUTH=true to override in development."
             );
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
@@ -98,6 +172,60 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
+    if let Some(key) = &api_key {
+        if config.role_api_keys.contains_key(key) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "ROLE_API_KEYS must not reuse API_KEY",
+            ));
+        }
+    }
+    if config.canary_api_keys.iter().any(|key| {
+        api_key.as_deref() != Some(key.as_str()) && !config.role_api_keys.contains_key(key)
+    }) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "CANARY_API_KEYS must only list API_KEY or ROLE_API_KEYS tokens",
+        ));
+    }
+    if let Some(key) = &api_key {
+        if config.tenants.iter().any(|tenant| &tenant.api_key == key) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "TENANTS_FILE must not reuse API_KEY",
+            ));
+        }
+    }
+    if let Some(tenants) = gateway.tenants() {
+        tenants.log_startup();
+        println!("🏢 Tenants: {}", config.tenants.len());
+    }
+    if !gateway.network_acl().is_empty() {
+        println!(
+            "🛡️  Network ACL: {} rules, {} trusted proxies",
+            gateway.network_acl().rules().len(),
+            config.trusted_proxies.len()
+        );
+    }
+    if let Some(oidc) = &config.oidc {
+        println!(
+            "🪪 OIDC bearer tokens: {} (audience {})",
+            oidc.issuer, oidc.audience
+        );
+    }
+    if gateway.sessions().is_some() {
+        println!(
+            "🍪 Browser sessions: enabled ({} login keys)",
+            config.session_login_keys.len()
+        );
+    }
+    if !config.role_api_keys.is_empty() {
+        println!(
+            "🕵️  Role API keys: {} (read-only, redacted)",
+            config.role_api_keys.len()
+        );
+    }
+
     if !config.tls_verify {
         tracing::warn!("TLS_VERIFY is false - TLS certificate verification is disabled. This should only be used in development!");
     }
@@ -107,7 +235,10 @@ async fn main() -> std::io::Result<()> {
     let rate_limit = config.rate_limit_per_minute;
 
     println!("🚀 Starting Taproot Assets API Proxy");
-    println!("📍 Server address: http://{server_address}");
+    println!(
+        "📍 Server address: http://{server_address}{}",
+        config.base_path
+    );
     println!("🔗 Backend: {}", config.taproot_assets_host);
     println!(
         "🔒 TLS verification: {}",
@@ -119,10 +250,40 @@ async fn main() -> std::io::Result<()> {
     );
     println!("🌐 CORS origins: {cors_origins:?}");
     println!("⏱️  Request timeout: {}s", config.request_timeout_secs);
+    if config.adaptive_timeouts_enabled {
+        println!(
+            "⏱️  Adaptive timeouts: enabled (floor {}s)",
+            config.adaptive_timeout_min_secs
+        );
+    }
     println!("🚦 Rate limit: {rate_limit} req/min per IP");
+    if config.public_api_enabled {
+        println!(
+            "🌍 Public explorer API: enabled ({} req/min per IP)",
+            config.public_rate_limit_per_minute
+        );
+    }
+    if config.maintenance_mode {
+        println!("🚧 Maintenance mode: enabled (read-only)");
+    }
+    println!(
+        "🗂️  Event indexer: {}",
+        if config.indexer_enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+
+    // Optional onion service for the listener, via an external tor
+    let onion = onion::publish(&config)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if let Some(onion) = &onion {
+        println!("🧅 Onion service: {}", onion.address());
+    }
 
     HttpServer::new({
-        let ws_proxy_handler = ws_proxy_handler.clone();
         let api_key = api_key.clone();
         move || {
             // Configure CORS with dynamic origins
@@ -132,18 +293,58 @@ async fn main() -> std::io::Result<()> {
                     actix_web::http::header::AUTHORIZATION,
                     actix_web::http::header::ACCEPT,
                     actix_web::http::header::CONTENT_TYPE,
+                    actix_web::http::header::HeaderName::from_static("x-canary"),
+                    actix_web::http::header::HeaderName::from_static("x-priority"),
+                    actix_web::http::header::HeaderName::from_static("x-allow-unsafe-destination"),
+                    actix_web::http::header::HeaderName::from_static("x-csrf-token"),
+                ])
+                .expose_headers(vec![
+                    "x-ratelimit-limit",
+                    "x-ratelimit-remaining",
+                    "x-ratelimit-reset",
+                    "retry-after",
                 ])
                 .max_age(3600);
 
-            // Add each configured origin
-            for origin in &cors_origins {
-                cors = cors.allowed_origin(origin);
+            // Add each configured origin. Session cookies are only sent
+            // cross-origin to an explicit list, never with a wildcard.
+            if cors_origins.iter().any(|origin| origin == "*") {
+                cors = cors.allow_any_origin();
+            } else {
+                for origin in &cors_origins {
+                    cors = cors.allowed_origin(origin);
+                }
+                cors = cors.supports_credentials();
             }
 
             App::new()
                 .wrap(cors)
-                .wrap(ApiKeyAuth::new(api_key.clone()))
-                .wrap(RateLimiter::new(rate_limit))
+                .wrap(PriorityQueueing::new(gateway.priority().cloned()))
+                .wrap(WarmupGate::new(gateway.warmup().clone()))
+                .wrap(MaintenanceGuard::new(gateway.maintenance().clone()))
+                .wrap(ColdWatchGuard::new(gateway.config().gateway_profile))
+                .wrap(ShedGuard::new(gateway.shed().cloned()))
+                .wrap(ShadowTraffic::new(shadow.clone()))
+                .wrap(CanaryRouting::new(canary.clone()))
+                .wrap(Redaction::new(config.redaction_profiles.clone()))
+                .wrap(ApiVersioning)
+                .wrap(FieldCaseNormalization::new(config.response_field_case))
+                .wrap(UsageAccounting::new(gateway.usage().cloned()))
+                .wrap(CsrfProtection::new(gateway.sessions().cloned()))
+                .wrap(
+                    ApiKeyAuth::new(api_key.clone(), config.role_api_keys.clone())
+                        .with_oidc(gateway.oidc().cloned())
+                        .with_sessions(gateway.sessions().cloned())
+                        .with_lockouts(gateway.lockouts().cloned())
+                        .with_faucet(gateway.faucet().cloned()),
+                )
+                .wrap(TenantRouting::new(gateway.tenants().cloned()))
+                .wrap(RateLimiter::shared(gateway.rate_limits().clone()))
+                .wrap(NetworkAclGuard::new(gateway.network_acl().clone()))
+                .wrap(AuditLog::new(
+                    gateway.audit().cloned(),
+                    config.trusted_proxies.clone(),
+                ))
                 .wrap(RequestIdMiddleware)
                 .wrap(
                     DefaultHeaders::new()
@@ -151,17 +352,17 @@ async fn main() -> std::io::Result<()> {
                         .add(("X-Frame-Options", "DENY"))
                         .add(("Cache-Control", "no-store")),
                 )
+                .wrap(RequestMetrics::new(gateway.monitor().stats()))
+                .wrap(BasePath::new(&config.base_path))
                 .wrap(Logger::new(
                     "%a \"%r\" %s %b \"%{Referer}i\" \"%{User-Agent}i\" %T",
                 ))
-                .app_data(web::PayloadConfig::new(MAX_PAYLOAD_SIZE))
-                .app_data(web::JsonConfig::default().limit(MAX_PAYLOAD_SIZE))
-                .app_data(web::Data::new(client.clone()))
-                .app_data(web::Data::new(BaseUrl(base_url.clone())))
-                .app_data(web::Data::new(MacaroonHex(macaroon_hex.clone())))
-                .app_data(web::Data::new(config.clone()))
-                .app_data(web::Data::new(ws_proxy_handler.clone()))
-                .configure(api::routes::configure)
+                .configure(|cfg| {
+                    if let Some(onion) = &onion {
+                        cfg.app_data(web::Data::new(onion.clone()));
+                    }
+                    gateway.configure(cfg)
+                })
         }
     })
     .workers(num_cpus())
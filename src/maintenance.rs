@@ -0,0 +1,92 @@
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+
+/// Message returned to rejected writes when no custom one is set.
+pub const DEFAULT_MAINTENANCE_MESSAGE: &str =
+    "The gateway is in maintenance mode; only read requests are accepted";
+
+/// Snapshot of the maintenance toggle, as reported by the admin and health
+/// endpoints.
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    pub message: String,
+    /// Unix seconds when maintenance was last switched on.
+    pub since: Option<i64>,
+}
+
+/// Read-only switch shared by the maintenance middleware, the admin API and
+/// the health endpoints. It starts from `MAINTENANCE_MODE` and is changed at
+/// runtime through the admin API; runtime changes do not survive a restart.
+#[derive(Debug)]
+pub struct MaintenanceMode {
+    status: RwLock<MaintenanceStatus>,
+}
+
+pub type SharedMaintenance = Arc<MaintenanceMode>;
+
+impl MaintenanceMode {
+    pub fn new(enabled: bool, message: Option<String>) -> Self {
+        let mode = Self {
+            status: RwLock::new(MaintenanceStatus {
+                enabled: false,
+                message: DEFAULT_MAINTENANCE_MESSAGE.to_string(),
+                since: None,
+            }),
+        };
+        mode.set(enabled, message);
+        mode
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        self.status
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.status
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .enabled
+    }
+
+    pub fn set(&self, enabled: bool, message: Option<String>) -> MaintenanceStatus {
+        let mut status = self.status.write().unwrap_or_else(|e| e.into_inner());
+        if enabled && !status.enabled {
+            status.since = Some(chrono::Utc::now().timestamp());
+        } else if !enabled {
+            status.since = None;
+        }
+        status.enabled = enabled;
+        status.message = message
+            .filter(|m| !m.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_MAINTENANCE_MESSAGE.to_string());
+        status.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle_tracks_since_and_message() {
+        let mode = MaintenanceMode::new(false, None);
+        assert!(!mode.is_enabled());
+
+        let status = mode.set(true, Some("Upgrading tapd".to_string()));
+        assert!(status.enabled);
+        assert_eq!(status.message, "Upgrading tapd");
+        let since = status.since.expect("since is set");
+
+        // Re-enabling keeps the original start time.
+        assert_eq!(mode.set(true, None).since, Some(since));
+        assert_eq!(mode.status().message, DEFAULT_MAINTENANCE_MESSAGE);
+
+        let status = mode.set(false, None);
+        assert!(!status.enabled);
+        assert!(status.since.is_none());
+    }
+}
@@ -1,80 +1,2076 @@
+use crate::api::admin::MAINTENANCE_PATH;
+use crate::api::proofs::PROOF_FILES_PATH;
+use crate::api::public::PUBLIC_PATH_PREFIX;
+use crate::api::rate_limit::RATE_LIMIT_PATH;
+use crate::api::sessions::SESSION_PUBLIC_PATHS;
+use crate::api::simulate::SIMULATE_PATH;
+use crate::api::{SIGNATURE_HEADER, SIGNATURE_KEY_HEADER, SIGNATURE_KEY_ID_HEADER};
+use crate::api_version::{self, ApiVersion};
+use crate::audit::{AuditEvent, SharedAuditExporter};
+use crate::canary::{CanaryRequest, SharedCanaryRouter};
+use crate::cold_watch::GatewayProfile;
+use crate::error::{classify_upstream, StatusMapping, UpstreamFailure, UPSTREAM_STATUS_HEADER};
+use crate::faucet::{SharedFaucet, FAUCET_PATH};
+use crate::field_case::{self, FieldCase};
+use crate::inflight::{self, Caller};
+use crate::lockout::{LockedOutError, SharedAuthLockouts};
+use crate::maintenance::SharedMaintenance;
+use crate::monitor::RequestStats;
+use crate::network_acl::{self, SharedNetworkAcl};
+use crate::oidc::{looks_like_jwt, SharedOidcAuthenticator, TokenRole};
+use crate::priority::{PriorityClass, PriorityRejection, SharedPriorityLimiter, PRIORITY_HEADER};
+use crate::rate_limit::{RateLimitStatus, RateLimits, SharedRateLimits};
+use crate::redaction::{redact, RedactionProfiles};
+use crate::sessions::{
+    SharedSessionManager, ACCESS_COOKIE, CSRF_COOKIE, CSRF_HEADER, REFRESH_COOKIE, SESSIONS_PATH,
+};
+use crate::shadow::SharedShadowMirror;
+use crate::shed::{SharedShed, SHED_RETRY_AFTER_SECS};
+use crate::tenants::{SharedTenantRouter, TenantRequest, OPERATOR_PATH_PREFIXES};
+use crate::timeouts::{self, SharedTimeoutTuner};
+use crate::usage::{self, SharedUsageMeter};
+use crate::warmup::SharedWarmup;
+use actix_web::body::{to_bytes, BodySize, EitherBody, MessageBody};
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
-use actix_web::http::header::{HeaderName, HeaderValue};
-use actix_web::http::StatusCode;
+use actix_web::http::header::{
+    HeaderMap, HeaderName, HeaderValue, TryIntoHeaderPair, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE,
+    TRANSFER_ENCODING, UPGRADE, VARY,
+};
+use actix_web::http::{Method, StatusCode};
 use actix_web::Error;
 use actix_web::HttpMessage;
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{web, HttpResponse, ResponseError};
 use futures::future::{ok, Ready};
+use ipnet::IpNet;
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::rc::Rc;
+use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::{Duration, Instant};
-use tracing::info_span;
+use std::time::Instant;
+use tracing::{info_span, Instrument};
 use uuid::Uuid;
 
 pub struct ApiKeyAuth {
     api_key: Option<String>,
+    role_keys: Arc<HashMap<String, String>>,
+    oidc: Option<SharedOidcAuthenticator>,
+    sessions: Option<SharedSessionManager>,
+    lockouts: Option<SharedAuthLockouts>,
+    faucet: Option<SharedFaucet>,
 }
 
 impl ApiKeyAuth {
-    pub fn new(api_key: Option<String>) -> Self {
-        Self { api_key }
+    pub fn new(api_key: Option<String>, role_keys: HashMap<String, String>) -> Self {
+        Self {
+            api_key,
+            role_keys: Arc::new(role_keys),
+            oidc: None,
+            sessions: None,
+            lockouts: None,
+            faucet: None,
+        }
+    }
+
+    /// Also accepts bearer JWTs the authenticator validates. Once set, every
+    /// route but the unauthenticated ones needs a key or a token, even
+    /// without `API_KEY`.
+    pub fn with_oidc(mut self, oidc: Option<SharedOidcAuthenticator>) -> Self {
+        self.oidc = oidc;
+        self
+    }
+
+    /// Also accepts session access cookies, for requests without an
+    /// `Authorization` header, and lets the session login routes through.
+    /// Like [`Self::with_oidc`], this makes authentication mandatory.
+    pub fn with_sessions(mut self, sessions: Option<SharedSessionManager>) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// Counts rejected credentials against the client's address and refuses
+    /// locked-out clients with 429 before checking anything.
+    pub fn with_lockouts(mut self, lockouts: Option<SharedAuthLockouts>) -> Self {
+        self.lockouts = lockouts;
+        self
+    }
+
+    /// Lets anonymous callers reach the faucet route while it is enabled.
+    pub fn with_faucet(mut self, faucet: Option<SharedFaucet>) -> Self {
+        self.faucet = faucet;
+        self
+    }
+}
+
+/// Role of a caller authenticated with one of the `ROLE_API_KEYS` tokens, a
+/// read-only OIDC token or a read-only session, stored in the request
+/// extensions. Absent for the primary API key and admin tokens.
+#[derive(Debug, Clone)]
+pub struct CallerRole(pub String);
+
+/// Session of a caller authenticated with a session cookie, stored in the
+/// request extensions.
+#[derive(Debug, Clone)]
+pub struct SessionCaller(pub String);
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ApiKeyAuthService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ApiKeyAuthService {
+            service: Rc::new(service),
+            api_key: self.api_key.clone(),
+            role_keys: self.role_keys.clone(),
+            oidc: self.oidc.clone(),
+            sessions: self.sessions.clone(),
+            lockouts: self.lockouts.clone(),
+            faucet: self.faucet.clone(),
+        })
+    }
+}
+
+pub struct ApiKeyAuthService<S> {
+    service: Rc<S>,
+    api_key: Option<String>,
+    role_keys: Arc<HashMap<String, String>>,
+    oidc: Option<SharedOidcAuthenticator>,
+    sessions: Option<SharedSessionManager>,
+    lockouts: Option<SharedAuthLockouts>,
+    faucet: Option<SharedFaucet>,
+}
+
+#[derive(Debug)]
+pub struct AuthError;
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unauthorized")
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Unauthorized"
+        }))
+    }
+}
+
+/// A valid OIDC token whose claims map to no gateway role.
+#[derive(Debug)]
+pub struct NoGatewayRoleError;
+
+impl std::fmt::Display for NoGatewayRoleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Token grants no gateway role")
+    }
+}
+
+impl ResponseError for NoGatewayRoleError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+/// A write by a read-only role; holds the role.
+#[derive(Debug)]
+pub struct ReadOnlyRoleError(pub String);
+
+impl std::fmt::Display for ReadOnlyRoleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Role API keys are read-only")
+    }
+}
+
+impl ResponseError for ReadOnlyRoleError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+/// Whether `path` serves content a redaction profile would strip in a form
+/// the redactor cannot inspect, such as raw proof files.
+fn is_unredactable_path(path: &str) -> bool {
+    path.strip_prefix(PROOF_FILES_PATH)
+        .is_some_and(|rest| rest.len() > 1 && rest.starts_with('/'))
+}
+
+/// Lets a read-only caller through with `role`, or refuses a write. Opening
+/// a session counts as a read: it grants nothing the caller lacks.
+fn authorize_role(req: &ServiceRequest, role: String) -> Result<(), Error> {
+    let read_only = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        || (req.method() == Method::POST && req.path() == SESSIONS_PATH);
+    if !read_only {
+        return Err(ReadOnlyRoleError(role).into());
+    }
+    if is_unredactable_path(req.path()) {
+        return Err(UnredactableError(role).into());
+    }
+    req.extensions_mut().insert(CallerRole(role));
+    Ok(())
+}
+
+fn is_session_public_path(path: &str) -> bool {
+    path.strip_prefix(SESSIONS_PATH)
+        .is_some_and(|rest| SESSION_PUBLIC_PATHS.contains(&rest))
+}
+
+impl<S, B> Service<ServiceRequest> for ApiKeyAuthService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Health checks and the anonymous explorer routes need no key.
+        if req.path() == "/health"
+            || req.path().starts_with(PUBLIC_PATH_PREFIX)
+            || (self.sessions.is_some() && is_session_public_path(req.path()))
+            || (self.faucet.is_some() && req.path() == FAUCET_PATH)
+        {
+            let fut = self.service.call(req);
+            return Box::pin(fut);
+        }
+
+        let Some(lockouts) = self.lockouts.clone() else {
+            return self.authenticate(req);
+        };
+        let (prefix, forgiving) = self.lockout_class(&req);
+        let key = lockouts.client_key(prefix, req.peer_addr().map(|addr| addr.ip()), req.headers());
+        if let Some(secs) = lockouts.retry_after(std::slice::from_ref(&key)) {
+            return Box::pin(async move { Err(LockedOutError(secs).into()) });
+        }
+        let fut = self.authenticate(req);
+        Box::pin(async move {
+            let res = fut.await;
+            match &res {
+                Ok(_) if forgiving => lockouts.record_success(std::slice::from_ref(&key)).await,
+                Ok(_) => {}
+                Err(e) if e.as_error::<AuthError>().is_some() => {
+                    lockouts.record_failure(&[key]).await
+                }
+                Err(_) => {}
+            }
+            res
+        })
+    }
+}
+
+impl<S, B> ApiKeyAuthService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    /// The lockout key prefix for the kind of credential `req` presents:
+    /// `ip` for bearer keys, `oidc` for OIDC tokens and `session` for
+    /// session cookies, and whether a success forgives that kind's failures.
+    /// Only the primary API key forgives bearer key failures, so holding a
+    /// role or tenant key, a token or a session buys no guesses at it.
+    fn lockout_class(&self, req: &ServiceRequest) -> (&'static str, bool) {
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        match token {
+            Some(token) if self.oidc.is_some() && looks_like_jwt(token) => ("oidc", true),
+            Some(token) => ("ip", self.api_key.as_deref() == Some(token)),
+            None if self.sessions.is_some() => ("session", true),
+            None => ("ip", false),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn authenticate(
+        &self,
+        req: ServiceRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<ServiceResponse<B>, Error>>>> {
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+
+        // Role keys only grant read access; their responses are redacted by
+        // the Redaction middleware.
+        if let Some(role) = token.and_then(|t| self.role_keys.get(t)) {
+            if let Err(e) = authorize_role(&req, role.clone()) {
+                return Box::pin(async { Err(e) });
+            }
+            let fut = self.service.call(req);
+            return Box::pin(fut);
+        }
+
+        // Tenant keys were matched by TenantRouting, which runs first.
+        if req.extensions().contains::<TenantRequest>() {
+            let fut = self.service.call(req);
+            return Box::pin(fut);
+        }
+
+        if let Some(ref expected_key) = self.api_key {
+            if token == Some(expected_key.as_str()) {
+                let fut = self.service.call(req);
+                return Box::pin(fut);
+            }
+        }
+
+        if let (Some(oidc), Some(token)) = (&self.oidc, token.filter(|t| looks_like_jwt(t))) {
+            let oidc = oidc.clone();
+            let token = token.to_string();
+            let service = self.service.clone();
+            return Box::pin(async move {
+                match oidc.authenticate(&token).await {
+                    Ok(Some(TokenRole::Admin)) => {}
+                    Ok(Some(TokenRole::ReadOnly(role))) => authorize_role(&req, role)?,
+                    Ok(None) => return Err(NoGatewayRoleError.into()),
+                    Err(e) => {
+                        tracing::debug!("Rejected bearer token: {}", e);
+                        return Err(AuthError.into());
+                    }
+                }
+                service.call(req).await
+            });
+        }
+
+        // Browsers send the session cookie instead of a header
+        if let (Some(sessions), None) = (&self.sessions, token) {
+            let access = req
+                .cookie(ACCESS_COOKIE)
+                .and_then(|cookie| sessions.verify_access(cookie.value()));
+            if let Some(access) = access {
+                if let TokenRole::ReadOnly(role) = access.role {
+                    if let Err(e) = authorize_role(&req, role) {
+                        return Box::pin(async { Err(e) });
+                    }
+                }
+                req.extensions_mut()
+                    .insert(SessionCaller(access.session_id));
+                let fut = self.service.call(req);
+                return Box::pin(fut);
+            }
+        }
+
+        if self.api_key.is_some() || self.oidc.is_some() || self.sessions.is_some() {
+            return Box::pin(async { Err(AuthError.into()) });
+        }
+
+        let fut = self.service.call(req);
+        Box::pin(fut)
+    }
+}
+
+/// Double-submit CSRF check for cookie-authenticated writes. Requests with
+/// a [`SessionCaller`] and methods other than `GET`, `HEAD` and `OPTIONS`
+/// must carry their session's CSRF token in [`CSRF_HEADER`]; refreshing and
+/// logging out, which only carry the refresh cookie, must repeat the CSRF
+/// cookie there. Bearer-authenticated requests are not checked: a browser
+/// never attaches those on its own. Wrap it inside [`ApiKeyAuth`].
+pub struct CsrfProtection {
+    sessions: Option<SharedSessionManager>,
+}
+
+impl CsrfProtection {
+    /// Checks nothing without sessions or with `SESSION_CSRF_PROTECTION`
+    /// off.
+    pub fn new(sessions: Option<SharedSessionManager>) -> Self {
+        Self {
+            sessions: sessions.filter(|s| s.csrf_protection()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CsrfError;
+
+impl std::fmt::Display for CsrfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Missing or invalid CSRF token")
+    }
+}
+
+impl ResponseError for CsrfError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfProtection
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CsrfProtectionService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CsrfProtectionService {
+            service,
+            sessions: self.sessions.clone(),
+        })
+    }
+}
+
+pub struct CsrfProtectionService<S> {
+    service: S,
+    sessions: Option<SharedSessionManager>,
+}
+
+impl<S> CsrfProtectionService<S> {
+    fn passes(&self, req: &ServiceRequest) -> bool {
+        let Some(sessions) = &self.sessions else {
+            return true;
+        };
+        if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+            return true;
+        }
+        let header = req
+            .headers()
+            .get(CSRF_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if let Some(caller) = req.extensions().get::<SessionCaller>() {
+            return sessions.verify_csrf(&caller.0, header);
+        }
+        let refresh_cookie_route = req
+            .path()
+            .strip_prefix(SESSIONS_PATH)
+            .is_some_and(|rest| rest == "/refresh" || rest == "/logout");
+        if refresh_cookie_route && req.cookie(REFRESH_COOKIE).is_some() {
+            return req
+                .cookie(CSRF_COOKIE)
+                .is_some_and(|cookie| !header.is_empty() && cookie.value() == header);
+        }
+        true
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfProtectionService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if !self.passes(&req) {
+            return Box::pin(async { Err(CsrfError.into()) });
+        }
+        let fut = self.service.call(req);
+        Box::pin(fut)
+    }
+}
+
+/// Refuses requests the network access rules do not allow with 403, before
+/// authentication or rate limiting see them. Wrap it inside [`BasePath`], so
+/// rules match paths relative to the mount point.
+pub struct NetworkAclGuard {
+    acl: SharedNetworkAcl,
+}
+
+impl NetworkAclGuard {
+    pub fn new(acl: SharedNetworkAcl) -> Self {
+        Self { acl }
+    }
+}
+
+#[derive(Debug)]
+pub struct NetworkAclError;
+
+impl std::fmt::Display for NetworkAclError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Forbidden by network policy")
+    }
+}
+
+impl ResponseError for NetworkAclError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for NetworkAclGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = NetworkAclGuardService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(NetworkAclGuardService {
+            service,
+            acl: self.acl.clone(),
+        })
+    }
+}
+
+pub struct NetworkAclGuardService<S> {
+    service: S,
+    acl: SharedNetworkAcl,
+}
+
+impl<S, B> Service<ServiceRequest> for NetworkAclGuardService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if !self.acl.is_empty() {
+            let ip = self
+                .acl
+                .client_ip(req.peer_addr().map(|addr| addr.ip()), req.headers());
+            if !self.acl.allows(req.path(), ip) {
+                tracing::warn!(
+                    "Network policy refused {} {} from {}",
+                    req.method(),
+                    req.path(),
+                    ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+                );
+                return Box::pin(async { Err(NetworkAclError.into()) });
+            }
+        }
+        let fut = self.service.call(req);
+        Box::pin(fut)
+    }
+}
+
+/// Records writes, admin calls and refused requests with the audit
+/// exporter. Wrap it outside the access checks it should see refuse
+/// requests ([`NetworkAclGuard`], [`RateLimiter`], [`ApiKeyAuth`]) and
+/// inside [`RequestIdMiddleware`], so events carry the request id.
+pub struct AuditLog {
+    exporter: Option<SharedAuditExporter>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+impl AuditLog {
+    pub fn new(exporter: Option<SharedAuditExporter>, trusted_proxies: Vec<IpNet>) -> Self {
+        Self {
+            exporter,
+            trusted_proxies,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AuditLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuditLogService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AuditLogService {
+            service,
+            exporter: self.exporter.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
+        })
+    }
+}
+
+pub struct AuditLogService<S> {
+    service: S,
+    exporter: Option<SharedAuditExporter>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+/// Who made the request, as far as the auth middleware got.
+fn audit_actor(extensions: &actix_web::dev::Extensions) -> String {
+    if let Some(tenant) = extensions.get::<TenantRequest>() {
+        format!("tenant:{}", tenant.0)
+    } else if let Some(role) = extensions.get::<CallerRole>() {
+        format!("role:{}", role.0)
+    } else {
+        "primary".to_string()
+    }
+}
+
+/// The caller behind a request refused by middleware, whose extensions are
+/// gone with it: `anonymous` when it was refused before its credentials
+/// were accepted.
+fn refused_actor(e: &Error) -> String {
+    if let Some(ReadOnlyRoleError(role)) = e.as_error::<ReadOnlyRoleError>() {
+        return format!("role:{role}");
+    }
+    if let Some(UnredactableError(role)) = e.as_error::<UnredactableError>() {
+        return format!("role:{role}");
+    }
+    let anonymous = e.as_error::<AuthError>().is_some()
+        || e.as_error::<LockedOutError>().is_some()
+        || e.as_error::<NetworkAclError>().is_some()
+        || e.as_error::<RateLimitError>().is_some()
+        || e.as_error::<NoGatewayRoleError>().is_some();
+    if anonymous { "anonymous" } else { "unknown" }.to_string()
+}
+
+impl<S, B> Service<ServiceRequest> for AuditLogService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let Some(exporter) = self.exporter.clone() else {
+            let fut = self.service.call(req);
+            return Box::pin(fut);
+        };
+        let client_ip = network_acl::client_ip(
+            &self.trusted_proxies,
+            req.peer_addr().map(|addr| addr.ip()),
+            req.headers(),
+        );
+        let request_id = req.extensions().get::<String>().cloned();
+        let (method, path) = (req.method().to_string(), req.path().to_string());
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let result = fut.await;
+            let (status, actor, session_id) = match &result {
+                Ok(res) => {
+                    let extensions = res.request().extensions();
+                    let actor = match res.status() {
+                        StatusCode::UNAUTHORIZED => "anonymous".to_string(),
+                        _ => audit_actor(&extensions),
+                    };
+                    let session_id = extensions.get::<SessionCaller>().map(|s| s.0.clone());
+                    (res.status(), actor, session_id)
+                }
+                Err(e) => (e.as_response_error().status_code(), refused_actor(e), None),
+            };
+            if AuditEvent::is_audited(&method, &path, status.as_u16()) {
+                exporter.record(AuditEvent {
+                    timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                    request_id,
+                    actor,
+                    session_id,
+                    client_ip: client_ip.map(|ip| ip.to_string()),
+                    method,
+                    path,
+                    status: status.as_u16(),
+                });
+            }
+            result
+        })
+    }
+}
+
+/// Strips the fields in the caller's redaction profile from JSON responses.
+/// Requests without a [`CallerRole`] pass through untouched. Role callers are
+/// refused what the redactor cannot inspect: WebSocket upgrades and non-empty
+/// non-JSON bodies (NDJSON streams, metrics), except on [`ROLE_OPAQUE_PATHS`].
+/// Proof file downloads are refused outright by [`ApiKeyAuth`], empty
+/// responses included.
+pub struct Redaction {
+    profiles: Arc<RedactionProfiles>,
+}
+
+impl Redaction {
+    pub fn new(profiles: RedactionProfiles) -> Self {
+        Self {
+            profiles: Arc::new(profiles),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Redaction
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RedactionService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RedactionService {
+            service,
+            profiles: self.profiles.clone(),
+        })
+    }
+}
+
+pub struct RedactionService<S> {
+    service: S,
+    profiles: Arc<RedactionProfiles>,
+}
+
+impl<S, B> Service<ServiceRequest> for RedactionService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let role = req.extensions().get::<CallerRole>().cloned();
+        if let Some(role) = &role {
+            if req.headers().contains_key(UPGRADE) {
+                let error = UnredactableError(role.0.clone());
+                return Box::pin(async move { Err(error.into()) });
+            }
+        }
+        let profiles = self.profiles.clone();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            let Some(role) = role else {
+                return Ok(res.map_into_left_body());
+            };
+            if !is_json_response(res.headers()) {
+                let empty = matches!(
+                    res.response().body().size(),
+                    BodySize::None | BodySize::Sized(0)
+                );
+                if empty || ROLE_OPAQUE_PATHS.contains(&res.request().path()) {
+                    return Ok(res.map_into_left_body());
+                }
+                return Err(UnredactableError(role.0).into());
+            }
+
+            let (req, res) = res.into_parts();
+            let (mut head, body) = res.into_parts();
+            let bytes = to_bytes(body).await.map_err(|_| {
+                actix_web::error::ErrorInternalServerError("Failed to read response")
+            })?;
+            let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+                Ok(mut value) => {
+                    redact(&mut value, &profiles.fields_for(&role.0));
+                    serde_json::to_vec(&value)
+                        .map_err(actix_web::error::ErrorInternalServerError)?
+                }
+                Err(_) => bytes.to_vec(),
+            };
+            head.headers_mut().remove(CONTENT_LENGTH);
+            // The redacted body no longer matches a response signature
+            head.headers_mut().remove(SIGNATURE_HEADER);
+            head.headers_mut().remove(SIGNATURE_KEY_HEADER);
+            head.headers_mut().remove(SIGNATURE_KEY_ID_HEADER);
+            let res = head
+                .set_body(body)
+                .map_into_boxed_body()
+                .map_into_right_body();
+            Ok(ServiceResponse::new(req, res))
+        })
+    }
+}
+
+/// Non-JSON routes role callers may still read: the static GraphQL
+/// playground page.
+pub const ROLE_OPAQUE_PATHS: &[&str] = &["/graphql"];
+
+/// A role caller asked for a response the redactor cannot inspect; holds
+/// the role.
+#[derive(Debug)]
+pub struct UnredactableError(pub String);
+
+impl std::fmt::Display for UnredactableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Role API keys can only read JSON responses")
+    }
+}
+
+impl ResponseError for UnredactableError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+/// Rewrites JSON response field names to one convention, after redaction has
+/// run on the names tapd uses. Without a configured case, and for non-JSON
+/// responses, it passes responses through untouched.
+pub struct FieldCaseNormalization {
+    case: Option<FieldCase>,
+}
+
+impl FieldCaseNormalization {
+    pub fn new(case: Option<FieldCase>) -> Self {
+        Self { case }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for FieldCaseNormalization
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = FieldCaseNormalizationService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(FieldCaseNormalizationService {
+            service,
+            case: self.case,
+        })
+    }
+}
+
+pub struct FieldCaseNormalizationService<S> {
+    service: S,
+    case: Option<FieldCase>,
+}
+
+impl<S, B> Service<ServiceRequest> for FieldCaseNormalizationService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let case = self.case;
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            let is_json = is_json_response(res.headers());
+            let Some(case) = case.filter(|_| is_json) else {
+                return Ok(res.map_into_left_body());
+            };
+
+            let (req, res) = res.into_parts();
+            let (mut head, body) = res.into_parts();
+            let bytes = to_bytes(body).await.map_err(|_| {
+                actix_web::error::ErrorInternalServerError("Failed to read response")
+            })?;
+            let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+                Ok(mut value) => {
+                    field_case::normalize(&mut value, case);
+                    serde_json::to_vec(&value)
+                        .map_err(actix_web::error::ErrorInternalServerError)?
+                }
+                Err(_) => bytes.to_vec(),
+            };
+            head.headers_mut().remove(CONTENT_LENGTH);
+            // The renamed body no longer matches a response signature
+            head.headers_mut().remove(SIGNATURE_HEADER);
+            head.headers_mut().remove(SIGNATURE_KEY_HEADER);
+            head.headers_mut().remove(SIGNATURE_KEY_ID_HEADER);
+            let res = head
+                .set_body(body)
+                .map_into_boxed_body()
+                .map_into_right_body();
+            Ok(ServiceResponse::new(req, res))
+        })
+    }
+}
+
+/// Negotiates the response version from `Accept` and rewrites JSON bodies
+/// into that version's shape. Handlers only ever build v1. Requests that
+/// accept nothing but unsupported gateway versions get 406.
+pub struct ApiVersioning;
+
+impl<S, B> Transform<S, ServiceRequest> for ApiVersioning
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ApiVersioningService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ApiVersioningService { service })
+    }
+}
+
+pub struct ApiVersioningService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiVersioningService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let accept = req.headers().get(ACCEPT).and_then(|v| v.to_str().ok());
+        let version = match api_version::negotiate(accept) {
+            Ok(version) => version,
+            Err(requested) => {
+                let supported: Vec<&str> = ApiVersion::ALL.iter().map(|v| v.media_type()).collect();
+                let response = HttpResponse::NotAcceptable().json(serde_json::json!({
+                    "error": format!("Unsupported response version: {requested}"),
+                    "type": "not_acceptable",
+                    "supported": supported,
+                }));
+                return Box::pin(
+                    async move { Ok(req.into_response(response).map_into_right_body()) },
+                );
+            }
+        };
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            res.headers_mut()
+                .append(VARY, HeaderValue::from_static("Accept"));
+            let Some(version) = version.filter(|_| is_json_response(res.headers())) else {
+                return Ok(res.map_into_left_body());
+            };
+            res.headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(version.media_type()));
+            if version == ApiVersion::V1 {
+                return Ok(res.map_into_left_body());
+            }
+
+            let (req, res) = res.into_parts();
+            let (mut head, body) = res.into_parts();
+            let bytes = to_bytes(body).await.map_err(|_| {
+                actix_web::error::ErrorInternalServerError("Failed to read response")
+            })?;
+            let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+                Ok(mut value) => {
+                    api_version::translate(&mut value, version);
+                    serde_json::to_vec(&value)
+                        .map_err(actix_web::error::ErrorInternalServerError)?
+                }
+                Err(_) => bytes.to_vec(),
+            };
+            head.headers_mut().remove(CONTENT_LENGTH);
+            // The translated body no longer matches a response signature
+            head.headers_mut().remove(SIGNATURE_HEADER);
+            head.headers_mut().remove(SIGNATURE_KEY_HEADER);
+            head.headers_mut().remove(SIGNATURE_KEY_ID_HEADER);
+            let res = head
+                .set_body(body)
+                .map_into_boxed_body()
+                .map_into_right_body();
+            Ok(ServiceResponse::new(req, res))
+        })
+    }
+}
+
+/// Serves each request with its gateway's [`SharedTimeoutTuner`] in scope,
+/// so the backend calls made for it get that gateway's route timeouts and
+/// feed its latency samples. Wrapped around a gateway's routes by
+/// [`crate::gateway::Gateway::configure`].
+pub struct BackendTimeouts;
+
+impl<S, B> Transform<S, ServiceRequest> for BackendTimeouts
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = BackendTimeoutsService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(BackendTimeoutsService { service })
+    }
+}
+
+pub struct BackendTimeoutsService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for BackendTimeoutsService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let tuner = req
+            .app_data::<web::Data<SharedTimeoutTuner>>()
+            .map(|tuner| tuner.get_ref().clone());
+        let fut = self.service.call(req);
+        match tuner {
+            Some(tuner) => Box::pin(timeouts::with_tuner(tuner, fut)),
+            None => Box::pin(fut),
+        }
+    }
+}
+
+/// Answers relayed tapd errors with a status matching their cause when the
+/// gateway's [`StatusMapping`] is strict, keeping tapd's status in
+/// `X-Upstream-Status`. Other responses pass through untouched. Wrapped
+/// around the gateway's route scopes, so every gateway in a process reads
+/// its own setting.
+pub struct StrictStatus;
+
+impl<S, B> Transform<S, ServiceRequest> for StrictStatus
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = StrictStatusService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(StrictStatusService { service })
+    }
+}
+
+pub struct StrictStatusService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for StrictStatusService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let strict = req
+            .app_data::<web::Data<StatusMapping>>()
+            .is_some_and(|mapping| *mapping.get_ref() == StatusMapping::Strict);
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if !strict {
+                return Ok(res);
+            }
+            let Some(failure) = res
+                .response()
+                .extensions()
+                .get::<UpstreamFailure>()
+                .cloned()
+            else {
+                return Ok(res);
+            };
+            let mapped = classify_upstream(failure.status, &failure.body);
+            if mapped != res.status() {
+                *res.response_mut().status_mut() = mapped;
+                if let Ok((name, value)) =
+                    (UPSTREAM_STATUS_HEADER, failure.status.to_string()).try_into_pair()
+                {
+                    res.headers_mut().insert(name, value);
+                }
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// `application/json`, or a `+json` media type such as a versioned gateway
+/// response.
+fn is_json_response(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| {
+            let v = v.trim();
+            v.starts_with("application/json") || v.ends_with("+json")
+        })
+        .unwrap_or(false)
+}
+
+/// Rejects mutating requests with 503 while maintenance mode is on. Reads,
+/// WebSocket upgrades (GET), operation simulation and the admin toggle itself
+/// keep working.
+pub struct MaintenanceGuard {
+    mode: SharedMaintenance,
+}
+
+impl MaintenanceGuard {
+    pub fn new(mode: SharedMaintenance) -> Self {
+        Self { mode }
+    }
+}
+
+#[derive(Debug)]
+pub struct MaintenanceError(String);
+
+impl std::fmt::Display for MaintenanceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl ResponseError for MaintenanceError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": self.0,
+            "maintenance": true
+        }))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for MaintenanceGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = MaintenanceGuardService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(MaintenanceGuardService {
+            service,
+            mode: self.mode.clone(),
+        })
+    }
+}
+
+pub struct MaintenanceGuardService<S> {
+    service: S,
+    mode: SharedMaintenance,
+}
+
+impl<S, B> Service<ServiceRequest> for MaintenanceGuardService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let read_only = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+        // Logging in and out writes only the gateway's own session table
+        let exempt = req.path() == MAINTENANCE_PATH
+            || req.path() == SIMULATE_PATH
+            || req.path().starts_with(SESSIONS_PATH);
+        if !read_only && !exempt && self.mode.is_enabled() {
+            let message = self.mode.status().message;
+            return Box::pin(async move { Err(MaintenanceError(message).into()) });
+        }
+        let fut = self.service.call(req);
+        Box::pin(fut)
+    }
+}
+
+/// Refuses every request the gateway profile does not serve with 403. Under
+/// the `full` profile every request passes straight through.
+pub struct ColdWatchGuard {
+    profile: GatewayProfile,
+}
+
+impl ColdWatchGuard {
+    pub fn new(profile: GatewayProfile) -> Self {
+        Self { profile }
+    }
+}
+
+#[derive(Debug)]
+pub struct ColdWatchError;
+
+impl std::fmt::Display for ColdWatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "This gateway is a read-only replica; only reads and verification are served"
+        )
+    }
+}
+
+impl ResponseError for ColdWatchError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": self.to_string(),
+            "type": "cold_watch"
+        }))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ColdWatchGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ColdWatchGuardService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ColdWatchGuardService {
+            service,
+            profile: self.profile,
+        })
+    }
+}
+
+pub struct ColdWatchGuardService<S> {
+    service: S,
+    profile: GatewayProfile,
+}
+
+impl<S, B> Service<ServiceRequest> for ColdWatchGuardService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let upgrade = req.headers().contains_key(UPGRADE);
+        if !self
+            .profile
+            .allows(req.method(), req.path(), req.query_string(), upgrade)
+        {
+            return Box::pin(async { Err(ColdWatchError.into()) });
+        }
+        let fut = self.service.call(req);
+        Box::pin(fut)
+    }
+}
+
+/// Paths shed mode never refuses, so operators can inspect and recover a
+/// shedding gateway.
+const SHED_EXEMPT_PREFIX: &str = "/v1/gateway/admin/";
+
+/// Refuses new WebSocket sessions and large request bodies with 503 while
+/// the gateway is in shed mode. Small requests keep working. Without shed
+/// limits every request passes straight through.
+pub struct ShedGuard {
+    shed: Option<SharedShed>,
+}
+
+impl ShedGuard {
+    pub fn new(shed: Option<SharedShed>) -> Self {
+        Self { shed }
+    }
+}
+
+#[derive(Debug)]
+pub struct ShedError {
+    upgrade: bool,
+}
+
+impl std::fmt::Display for ShedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.upgrade {
+            write!(
+                f,
+                "The gateway is shedding load and not accepting new WebSocket sessions"
+            )
+        } else {
+            write!(
+                f,
+                "The gateway is shedding load and not accepting large request bodies"
+            )
+        }
+    }
+}
+
+impl ResponseError for ShedError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::ServiceUnavailable()
+            .insert_header(("Retry-After", SHED_RETRY_AFTER_SECS.to_string()))
+            .json(serde_json::json!({
+                "error": self.to_string(),
+                "type": "shed"
+            }))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ShedGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ShedGuardService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ShedGuardService {
+            service,
+            shed: self.shed.clone(),
+        })
+    }
+}
+
+pub struct ShedGuardService<S> {
+    service: S,
+    shed: Option<SharedShed>,
+}
+
+impl<S, B> Service<ServiceRequest> for ShedGuardService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Some(shed) = self
+            .shed
+            .as_ref()
+            .filter(|_| !req.path().starts_with(SHED_EXEMPT_PREFIX))
+        {
+            let headers = req.headers();
+            let content_length = headers
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok());
+            let chunked = headers.contains_key(TRANSFER_ENCODING);
+            let upgrade = headers
+                .get(UPGRADE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+            if shed.should_shed(content_length, chunked, upgrade) {
+                return Box::pin(async move { Err(ShedError { upgrade }.into()) });
+            }
+        }
+        let fut = self.service.call(req);
+        Box::pin(fut)
+    }
+}
+
+/// Answers tapd-proxied routes with 503 and `Retry-After` while the startup
+/// warm-up is still waiting for the backend, instead of letting them fail
+/// against a tapd that is still booting.
+pub struct WarmupGate {
+    warmup: SharedWarmup,
+}
+
+impl WarmupGate {
+    pub fn new(warmup: SharedWarmup) -> Self {
+        Self { warmup }
+    }
+}
+
+/// Prefix of the routes proxied to tapd.
+const TAPD_ROUTE_PREFIX: &str = "/v1/taproot-assets";
+const WARMUP_RETRY_AFTER_SECS: &str = "5";
+
+#[derive(Debug)]
+pub struct WarmingUpError;
+
+impl std::fmt::Display for WarmingUpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The gateway is waiting for tapd to start")
+    }
+}
+
+impl ResponseError for WarmingUpError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::ServiceUnavailable()
+            .insert_header(("Retry-After", WARMUP_RETRY_AFTER_SECS))
+            .json(serde_json::json!({
+                "error": self.to_string()
+            }))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for WarmupGate
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = WarmupGateService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(WarmupGateService {
+            service,
+            warmup: self.warmup.clone(),
+        })
+    }
+}
+
+pub struct WarmupGateService<S> {
+    service: S,
+    warmup: SharedWarmup,
+}
+
+impl<S, B> Service<ServiceRequest> for WarmupGateService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if req.path().starts_with(TAPD_ROUTE_PREFIX) && self.warmup.is_warming() {
+            return Box::pin(async { Err(WarmingUpError.into()) });
+        }
+        let fut = self.service.call(req);
+        Box::pin(fut)
+    }
+}
+
+/// Sends canary requests to the alternate backend and tags their responses
+/// with `X-Canary: true`. Without a router every request passes through.
+pub struct CanaryRouting {
+    router: Option<SharedCanaryRouter>,
+}
+
+impl CanaryRouting {
+    pub fn new(router: Option<SharedCanaryRouter>) -> Self {
+        Self { router }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for CanaryRouting
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CanaryRoutingService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CanaryRoutingService {
+            service,
+            router: self.router.clone(),
+        })
+    }
+}
+
+pub struct CanaryRoutingService<S> {
+    service: S,
+    router: Option<SharedCanaryRouter>,
+}
+
+impl<S, B> Service<ServiceRequest> for CanaryRoutingService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let canary = match &self.router {
+            // Anonymous callers must not be able to opt into the canary.
+            Some(router)
+                if !req.path().starts_with(PUBLIC_PATH_PREFIX)
+                    && req.path() != FAUCET_PATH
+                    && !req.extensions().contains::<TenantRequest>()
+                    && router.is_canary(req.headers()) =>
+            {
+                router.route(&mut req);
+                true
+            }
+            _ => false,
+        };
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if canary {
+                res.headers_mut().insert(
+                    HeaderName::from_static("x-canary"),
+                    HeaderValue::from_static("true"),
+                );
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// Serves requests authenticated with a tenant's API key from that tenant's
+/// tapd and database, and keeps tenants off the operator routes. Without a
+/// router every request passes through.
+pub struct TenantRouting {
+    router: Option<SharedTenantRouter>,
+}
+
+impl TenantRouting {
+    pub fn new(router: Option<SharedTenantRouter>) -> Self {
+        Self { router }
+    }
+}
+
+#[derive(Debug)]
+pub struct TenantScopeError;
+
+impl std::fmt::Display for TenantScopeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tenant API keys cannot access operator routes")
+    }
+}
+
+impl ResponseError for TenantScopeError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for TenantRouting
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = TenantRoutingService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(TenantRoutingService {
+            service,
+            router: self.router.clone(),
+        })
+    }
+}
+
+pub struct TenantRoutingService<S> {
+    service: S,
+    router: Option<SharedTenantRouter>,
+}
+
+impl<S, B> Service<ServiceRequest> for TenantRoutingService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        // Anonymous routes always use the primary node.
+        let tenant = self
+            .router
+            .as_ref()
+            .filter(|_| !req.path().starts_with(PUBLIC_PATH_PREFIX) && req.path() != FAUCET_PATH)
+            .and_then(|router| Some((router, router.tenant_for(req.headers())?.clone())));
+        let Some((router, tenant)) = tenant else {
+            return Box::pin(self.service.call(req));
+        };
+        if OPERATOR_PATH_PREFIXES
+            .iter()
+            .any(|prefix| req.path().starts_with(prefix))
+        {
+            return Box::pin(async { Err(TenantScopeError.into()) });
+        }
+        router.route(&tenant, &mut req);
+        let span = info_span!("tenant", tenant = %tenant.name());
+        Box::pin(self.service.call(req).instrument(span))
+    }
+}
+
+/// Mirrors a sample of successful read-only tapd requests to the shadow
+/// backend, handing it the primary response to compare against. Without a
+/// mirror every request passes straight through.
+pub struct ShadowTraffic {
+    mirror: Option<SharedShadowMirror>,
+}
+
+impl ShadowTraffic {
+    pub fn new(mirror: Option<SharedShadowMirror>) -> Self {
+        Self { mirror }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ShadowTraffic
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ShadowTrafficService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ShadowTrafficService {
+            service,
+            mirror: self.mirror.clone(),
+        })
+    }
+}
+
+pub struct ShadowTrafficService<S> {
+    service: S,
+    mirror: Option<SharedShadowMirror>,
+}
+
+impl<S, B> Service<ServiceRequest> for ShadowTrafficService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // WebSocket upgrades are GETs too but cannot be replayed.
+        let mirror = self.mirror.clone().filter(|mirror| {
+            req.method() == Method::GET
+                && req.path().starts_with(TAPD_ROUTE_PREFIX)
+                && !req.headers().contains_key(UPGRADE)
+                && !req.extensions().contains::<CanaryRequest>()
+                && !req.extensions().contains::<TenantRequest>()
+                && mirror.should_sample()
+        });
+        let path_and_query = req
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str().to_string())
+            .unwrap_or_default();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            let mirror = match mirror {
+                Some(mirror) if res.status().is_success() => mirror,
+                _ => return Ok(res.map_into_left_body()),
+            };
+
+            let (req, res) = res.into_parts();
+            let (head, body) = res.into_parts();
+            let bytes = to_bytes(body).await.map_err(|_| {
+                actix_web::error::ErrorInternalServerError("Failed to read response")
+            })?;
+            if let Ok(primary) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+                mirror.mirror(path_and_query, primary);
+            }
+            let res = head
+                .set_body(bytes)
+                .map_into_boxed_body()
+                .map_into_right_body();
+            Ok(ServiceResponse::new(req, res))
+        })
+    }
+}
+
+/// Counts every response by status class for the gateway monitor, including
+/// requests rejected by other middleware.
+pub struct RequestMetrics {
+    stats: Arc<RequestStats>,
+}
+
+impl RequestMetrics {
+    pub fn new(stats: Arc<RequestStats>) -> Self {
+        Self { stats }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestMetricsService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestMetricsService {
+            service,
+            stats: self.stats.clone(),
+        })
+    }
+}
+
+pub struct RequestMetricsService<S> {
+    service: S,
+    stats: Arc<RequestStats>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let stats = self.stats.clone();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let result = fut.await;
+            match &result {
+                Ok(res) => stats.record(res.status()),
+                Err(e) => stats.record(e.as_response_error().status_code()),
+            }
+            result
+        })
+    }
+}
+
+/// Runs at most the limiter's number of requests at once, queueing the rest
+/// by [`PriorityClass`]. Requests that find their class's queue full, or wait
+/// too long, get 503. Without a limiter every request passes straight through.
+pub struct PriorityQueueing {
+    limiter: Option<SharedPriorityLimiter>,
+}
+
+impl PriorityQueueing {
+    pub fn new(limiter: Option<SharedPriorityLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+#[derive(Debug)]
+pub struct PriorityError {
+    class: PriorityClass,
+    rejection: PriorityRejection,
+}
+
+impl std::fmt::Display for PriorityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.rejection {
+            PriorityRejection::QueueFull => {
+                write!(f, "The {} request queue is full", self.class.as_str())
+            }
+            PriorityRejection::TimedOut => write!(
+                f,
+                "Timed out waiting in the {} request queue",
+                self.class.as_str()
+            ),
+        }
+    }
+}
+
+impl ResponseError for PriorityError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::ServiceUnavailable()
+            .insert_header(("Retry-After", "1"))
+            .json(serde_json::json!({
+                "error": self.to_string(),
+                "type": "overloaded",
+                "priority": self.class.as_str()
+            }))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for PriorityQueueing
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = PriorityQueueingService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(PriorityQueueingService {
+            service,
+            limiter: self.limiter.clone(),
+        })
+    }
+}
+
+pub struct PriorityQueueingService<S> {
+    service: S,
+    limiter: Option<SharedPriorityLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for PriorityQueueingService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let Some(limiter) = self.limiter.clone() else {
+            return Box::pin(self.service.call(req));
+        };
+        let header = req
+            .headers()
+            .get(PRIORITY_HEADER)
+            .and_then(|v| v.to_str().ok());
+        let class = PriorityClass::classify(req.path(), header);
+        // The handler does not run until the future is polled, after a slot
+        // is granted
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let permit = limiter
+                .acquire(class)
+                .await
+                .map_err(|rejection| PriorityError { class, rejection })?;
+            let res = fut.await;
+            drop(permit);
+            res
+        })
     }
 }
 
-impl<S, B> Transform<S, ServiceRequest> for ApiKeyAuth
+/// Counts authenticated requests per API key for usage reporting. Sits
+/// inside ApiKeyAuth, so rejected tokens are never counted. Streamed
+/// responses (WebSocket, SSE) count as zero response bytes.
+pub struct UsageAccounting {
+    meter: Option<SharedUsageMeter>,
+}
+
+impl UsageAccounting {
+    pub fn new(meter: Option<SharedUsageMeter>) -> Self {
+        Self { meter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for UsageAccounting
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
-    B: 'static,
+    B: MessageBody + 'static,
 {
     type Response = ServiceResponse<B>;
     type Error = Error;
     type InitError = ();
-    type Transform = ApiKeyAuthService<S>;
+    type Transform = UsageAccountingService<S>;
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(ApiKeyAuthService {
+        ok(UsageAccountingService {
             service,
-            api_key: self.api_key.clone(),
+            meter: self.meter.clone(),
         })
     }
 }
 
-pub struct ApiKeyAuthService<S> {
+pub struct UsageAccountingService<S> {
     service: S,
-    api_key: Option<String>,
-}
-
-#[derive(Debug)]
-pub struct AuthError;
-
-impl std::fmt::Display for AuthError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Unauthorized")
-    }
-}
-
-impl ResponseError for AuthError {
-    fn status_code(&self) -> StatusCode {
-        StatusCode::UNAUTHORIZED
-    }
-
-    fn error_response(&self) -> HttpResponse {
-        HttpResponse::Unauthorized().json(serde_json::json!({
-            "error": "Unauthorized"
-        }))
-    }
+    meter: Option<SharedUsageMeter>,
 }
 
-impl<S, B> Service<ServiceRequest> for ApiKeyAuthService<S>
+impl<S, B> Service<ServiceRequest> for UsageAccountingService<S>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
-    B: 'static,
+    B: MessageBody + 'static,
 {
     type Response = ServiceResponse<B>;
     type Error = Error;
@@ -85,27 +2081,57 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        if req.path() == "/health" {
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        // Routes that skip ApiKeyAuth would count unchecked tokens.
+        let unauthenticated = req.path() == "/health"
+            || req.path().starts_with(PUBLIC_PATH_PREFIX)
+            || req.path() == FAUCET_PATH;
+        let (Some(meter), Some(token), false) = (self.meter.clone(), token, unauthenticated) else {
             let fut = self.service.call(req);
             return Box::pin(fut);
-        }
-
-        if let Some(ref expected_key) = self.api_key {
-            let authorized = req
-                .headers()
-                .get("Authorization")
-                .and_then(|v| v.to_str().ok())
-                .and_then(|v| v.strip_prefix("Bearer "))
-                .map(|token| token == expected_key)
-                .unwrap_or(false);
+        };
 
-            if !authorized {
-                return Box::pin(async { Err(AuthError.into()) });
-            }
-        }
+        let key_id = usage::key_id(token);
+        let label = if let Some(tenant) = req.extensions().get::<TenantRequest>() {
+            format!("tenant:{}", tenant.0)
+        } else if let Some(role) = req.extensions().get::<CallerRole>() {
+            format!("role:{}", role.0)
+        } else {
+            "primary".to_string()
+        };
+        let request_bytes = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0);
 
         let fut = self.service.call(req);
-        Box::pin(fut)
+        Box::pin(async move {
+            let result = fut.await;
+            let (status, response_bytes) = match &result {
+                Ok(res) => (
+                    res.status(),
+                    match res.response().body().size() {
+                        BodySize::Sized(n) => n,
+                        _ => 0,
+                    },
+                ),
+                Err(e) => (e.as_response_error().status_code(), 0),
+            };
+            meter.record(
+                key_id,
+                label,
+                status.as_u16() >= 400,
+                request_bytes,
+                response_bytes,
+            );
+            result
+        })
     }
 }
 
@@ -150,6 +2176,11 @@ where
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let request_id = Uuid::new_v4().to_string();
         req.extensions_mut().insert(request_id.clone());
+        let caller = Caller {
+            request_id: request_id.clone(),
+            route: format!("{} {}", req.method(), req.path()),
+            client_ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+        };
 
         // Create tracing span for this request
         let span = info_span!("request",
@@ -159,7 +2190,7 @@ where
         );
         let _enter = span.enter();
 
-        let fut = self.service.call(req);
+        let fut = inflight::with_caller(caller, self.service.call(req));
         Box::pin(async move {
             let mut res = fut.await?;
             res.headers_mut().insert(
@@ -172,19 +2203,23 @@ where
 }
 
 // Simple Rate Limiting Middleware
+/// Limits requests per client IP over a sliding minute, and reports the
+/// caller's bucket in `X-RateLimit-Limit`, `X-RateLimit-Remaining` and
+/// `X-RateLimit-Reset` on every response it lets through. Refused requests
+/// get 429 with the same headers and `Retry-After`.
 pub struct RateLimiter {
-    requests_per_minute: usize,
-    cleanup_interval: Duration,
-    max_tracked_ips: usize,
+    limits: SharedRateLimits,
 }
 
 impl RateLimiter {
+    /// A limiter with its own buckets. Each worker calling this counts
+    /// separately; use [`RateLimiter::shared`] to count across workers.
     pub fn new(requests_per_minute: usize) -> Self {
-        Self {
-            requests_per_minute,
-            cleanup_interval: Duration::from_secs(60),
-            max_tracked_ips: 10_000,
-        }
+        Self::shared(Arc::new(RateLimits::new(requests_per_minute)))
+    }
+
+    pub fn shared(limits: SharedRateLimits) -> Self {
+        Self { limits }
     }
 }
 
@@ -194,8 +2229,6 @@ impl Default for RateLimiter {
     }
 }
 
-type RateLimitStore = Arc<Mutex<HashMap<String, Vec<Instant>>>>;
-
 impl<S, B> Transform<S, ServiceRequest> for RateLimiter
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
@@ -211,26 +2244,18 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ok(RateLimiterService {
             service,
-            store: Arc::new(Mutex::new(HashMap::new())),
-            requests_per_minute: self.requests_per_minute,
-            last_cleanup: Arc::new(Mutex::new(Instant::now())),
-            cleanup_interval: self.cleanup_interval,
-            max_tracked_ips: self.max_tracked_ips,
+            limits: self.limits.clone(),
         })
     }
 }
 
 pub struct RateLimiterService<S> {
     service: S,
-    store: RateLimitStore,
-    requests_per_minute: usize,
-    last_cleanup: Arc<Mutex<Instant>>,
-    cleanup_interval: Duration,
-    max_tracked_ips: usize,
+    limits: SharedRateLimits,
 }
 
 #[derive(Debug)]
-pub struct RateLimitError;
+pub struct RateLimitError(RateLimitStatus);
 
 impl std::fmt::Display for RateLimitError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -244,8 +2269,12 @@ impl ResponseError for RateLimitError {
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::TooManyRequests()
-            .insert_header(("Retry-After", "60"))
+        let mut response = HttpResponse::TooManyRequests();
+        for (name, value) in rate_limit_headers(&self.0) {
+            response.insert_header((name, value));
+        }
+        response
+            .insert_header(("Retry-After", self.0.retry_after_secs().to_string()))
             .json(serde_json::json!({
                 "error": "Rate limit exceeded",
                 "message": "Too many requests. Please try again later."
@@ -253,6 +2282,25 @@ impl ResponseError for RateLimitError {
     }
 }
 
+fn rate_limit_headers(status: &RateLimitStatus) -> [(&'static str, String); 3] {
+    [
+        ("x-ratelimit-limit", status.limit.to_string()),
+        ("x-ratelimit-remaining", status.remaining.to_string()),
+        ("x-ratelimit-reset", status.reset_secs.to_string()),
+    ]
+}
+
+/// The key a caller's requests are counted under: the client address, read
+/// through the gateway's `TRUSTED_PROXIES` when it has a network ACL.
+pub fn rate_limit_client(req: &actix_web::HttpRequest) -> String {
+    let peer = req.peer_addr().map(|addr| addr.ip());
+    let ip = match req.app_data::<web::Data<SharedNetworkAcl>>() {
+        Some(acl) => acl.client_ip(peer, req.headers()),
+        None => peer,
+    };
+    ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+}
+
 impl<S, B> Service<ServiceRequest> for RateLimiterService<S>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
@@ -268,49 +2316,1045 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        // Get client identifier (IP address or authenticated user)
-        let client_id = req
-            .peer_addr()
-            .map(|addr| addr.ip().to_string())
-            .unwrap_or_else(|| "unknown".to_string());
+        // Reading the bucket does not spend it, so throttled clients can
+        // still see when to come back
+        if req.path() == RATE_LIMIT_PATH {
+            return Box::pin(self.service.call(req));
+        }
 
-        let now = Instant::now();
-        let window_start = now - Duration::from_secs(60);
+        let client_id = rate_limit_client(req.request());
+        let status = match self.limits.check(&client_id, Instant::now()) {
+            Ok(status) => status,
+            Err(status) => return Box::pin(async move { Err(RateLimitError(status).into()) }),
+        };
 
-        // Clean up old entries periodically
-        {
-            let mut last_cleanup = self.last_cleanup.lock().unwrap_or_else(|e| e.into_inner());
-            if now.duration_since(*last_cleanup) > self.cleanup_interval {
-                let mut store = self.store.lock().unwrap_or_else(|e| e.into_inner());
-                store.retain(|_, timestamps| {
-                    timestamps.retain(|t| *t > window_start);
-                    !timestamps.is_empty()
-                });
-                *last_cleanup = now;
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            for (name, value) in rate_limit_headers(&status) {
+                if let Ok(value) = HeaderValue::from_str(&value) {
+                    res.headers_mut()
+                        .insert(HeaderName::from_static(name), value);
+                }
             }
+            Ok(res)
+        })
+    }
+}
+
+/// Request extension carrying the base path a request arrived under, so
+/// absolute URLs in responses can put it back.
+#[derive(Debug, Clone)]
+pub struct MountPath(pub String);
+
+/// Serves every route under `BASE_PATH`. The prefix is stripped before
+/// routing, so handlers and the other middleware see the same paths as
+/// without one; anything outside the prefix is answered with 404.
+pub struct BasePath {
+    prefix: Arc<str>,
+}
+
+impl BasePath {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.trim_end_matches('/').into(),
         }
+    }
+}
 
-        // Check rate limit
-        {
-            let mut store = self.store.lock().unwrap_or_else(|e| e.into_inner());
+/// `path` relative to `prefix`, or `None` when it lies outside it.
+fn strip_base_path<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    match path.strip_prefix(prefix)? {
+        "" => Some("/"),
+        rest if rest.starts_with('/') => Some(rest),
+        _ => None,
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for BasePath
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = BasePathService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(BasePathService {
+            service,
+            prefix: self.prefix.clone(),
+        })
+    }
+}
+
+pub struct BasePathService<S> {
+    service: S,
+    prefix: Arc<str>,
+}
+
+impl<S, B> Service<ServiceRequest> for BasePathService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        if self.prefix.is_empty() {
+            return Box::pin(self.service.call(req));
+        }
+        let Some(path) = strip_base_path(req.path(), &self.prefix) else {
+            return Box::pin(async {
+                Err(crate::error::AppError::NotFound("Not found".to_string()).into())
+            });
+        };
+        let path_and_query = match req.query_string() {
+            "" => path.to_string(),
+            query => format!("{path}?{query}"),
+        };
+        let mut parts = req.head().uri.clone().into_parts();
+        parts.path_and_query = path_and_query.parse().ok();
+        if let Ok(uri) = actix_web::http::Uri::from_parts(parts) {
+            req.match_info_mut().get_mut().update(&uri);
+            req.head_mut().uri = uri;
+        }
+        req.extensions_mut()
+            .insert(MountPath(self.prefix.to_string()));
+        Box::pin(self.service.call(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App};
+
+    async fn addr() -> HttpResponse {
+        HttpResponse::Ok().json(serde_json::json!({
+            "encoded": "taprt1...",
+            "internal_key": "02ab"
+        }))
+    }
+
+    fn role_keys() -> HashMap<String, String> {
+        HashMap::from([("support-token".to_string(), "support".to_string())])
+    }
 
-            if !store.contains_key(&client_id) && store.len() >= self.max_tracked_ips {
-                return Box::pin(async { Err(RateLimitError.into()) });
+    #[actix_rt::test]
+    async fn test_role_key_gets_redacted_read_only_access() {
+        let app = test::init_service(
+            App::new()
+                .wrap(Redaction::new(RedactionProfiles::default()))
+                .wrap(ApiKeyAuth::new(
+                    Some("admin-token".to_string()),
+                    role_keys(),
+                ))
+                .route("/addr", web::get().to(addr))
+                .route("/addr", web::post().to(addr)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/addr")
+            .insert_header(("Authorization", "Bearer support-token"))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body, serde_json::json!({ "encoded": "taprt1..." }));
+
+        let req = test::TestRequest::get()
+            .uri("/addr")
+            .insert_header(("Authorization", "Bearer admin-token"))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["internal_key"], "02ab");
+
+        let req = test::TestRequest::post()
+            .uri("/addr")
+            .insert_header(("Authorization", "Bearer support-token"))
+            .to_request();
+        let err = test::try_call_service(&app, req).await.unwrap_err();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn test_role_key_is_refused_unredactable_responses() {
+        async fn stream() -> HttpResponse {
+            HttpResponse::Ok()
+                .content_type("application/x-ndjson")
+                .body("{\"internal_key\":\"02ab\"}\n")
+        }
+        async fn empty() -> HttpResponse {
+            HttpResponse::NoContent().finish()
+        }
+        let app = test::init_service(
+            App::new()
+                .wrap(Redaction::new(RedactionProfiles::default()))
+                .wrap(ApiKeyAuth::new(
+                    Some("admin-token".to_string()),
+                    role_keys(),
+                ))
+                .route("/stream", web::get().to(stream))
+                .route("/empty", web::get().to(empty)),
+        )
+        .await;
+
+        let call = |uri: &str, token: &str, upgrade: bool| {
+            let mut req = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(("Authorization", format!("Bearer {token}")));
+            if upgrade {
+                req = req.insert_header((UPGRADE, "websocket"));
+            }
+            req.to_request()
+        };
+        for (uri, upgrade) in [("/stream", false), ("/empty", true)] {
+            let err = test::try_call_service(&app, call(uri, "support-token", upgrade))
+                .await
+                .unwrap_err();
+            assert_eq!(err.as_response_error().status_code(), StatusCode::FORBIDDEN);
+        }
+        let res = test::call_service(&app, call("/empty", "support-token", false)).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let res = test::call_service(&app, call("/stream", "admin-token", false)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn test_role_key_cannot_download_proof_files() {
+        async fn proof_file() -> HttpResponse {
+            HttpResponse::Ok()
+                .content_type("application/octet-stream")
+                .body(vec![0u8; 4])
+        }
+        let path = format!("{PROOF_FILES_PATH}/{}", "ab".repeat(32));
+        let app = test::init_service(
+            App::new()
+                .wrap(Redaction::new(RedactionProfiles::default()))
+                .wrap(ApiKeyAuth::new(
+                    Some("admin-token".to_string()),
+                    role_keys(),
+                ))
+                .route(&path, web::get().to(proof_file))
+                .route(&path, web::head().to(proof_file)),
+        )
+        .await;
+
+        for method in [Method::GET, Method::HEAD] {
+            let req = test::TestRequest::default()
+                .method(method)
+                .uri(&path)
+                .insert_header(("Authorization", "Bearer support-token"))
+                .to_request();
+            let err = test::try_call_service(&app, req).await.unwrap_err();
+            assert_eq!(err.as_response_error().status_code(), StatusCode::FORBIDDEN);
+        }
+        let req = test::TestRequest::get()
+            .uri(&path)
+            .insert_header(("Authorization", "Bearer admin-token"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn test_strict_status_is_per_gateway() {
+        async fn failing() -> HttpResponse {
+            crate::api::handle_result::<()>(Err(crate::error::AppError::UpstreamError {
+                status: 500,
+                body: r#"{"code":2,"message":"invalid asset ID length","details":[]}"#.to_string(),
+            }))
+        }
+        let app = |mapping: StatusMapping| {
+            App::new()
+                .app_data(web::Data::new(mapping))
+                .wrap(StrictStatus)
+                .route("/assets", web::get().to(failing))
+        };
+        let strict = test::init_service(app(StatusMapping::Strict)).await;
+        let relay = test::init_service(app(StatusMapping::Relay)).await;
+
+        let res = test::call_service(
+            &strict,
+            test::TestRequest::get().uri("/assets").to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers().get(UPSTREAM_STATUS_HEADER).unwrap(), "500");
+        let res =
+            test::call_service(&relay, test::TestRequest::get().uri("/assets").to_request()).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(res.headers().get(UPSTREAM_STATUS_HEADER).is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_backend_timeouts_are_per_gateway() {
+        use crate::timeouts::{RouteTimeouts, TimeoutPolicy, TimeoutTuner};
+        use std::time::Duration;
+
+        async fn timeout() -> HttpResponse {
+            let secs = timeouts::current()
+                .and_then(|tuner| tuner.timeout_for("/v1/taproot-assets/assets"))
+                .map(|timeout| timeout.as_secs());
+            HttpResponse::Ok().json(secs)
+        }
+        let app = |default_secs: u64| {
+            let tuner = Arc::new(TimeoutTuner::new());
+            tuner.install(TimeoutPolicy {
+                default: Duration::from_secs(default_secs),
+                routes: RouteTimeouts::default(),
+                adaptive_floor: None,
+            });
+            App::new()
+                .app_data(web::Data::new(tuner))
+                .wrap(BackendTimeouts)
+                .route("/timeout", web::get().to(timeout))
+        };
+        let fast = test::init_service(app(5)).await;
+        let slow = test::init_service(app(90)).await;
+
+        let req = || test::TestRequest::get().uri("/timeout").to_request();
+        let body: Option<u64> = test::call_and_read_body_json(&fast, req()).await;
+        assert_eq!(body, Some(5));
+        let body: Option<u64> = test::call_and_read_body_json(&slow, req()).await;
+        assert_eq!(body, Some(90));
+        assert!(timeouts::current().is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_enabled_faucet_skips_authentication() {
+        let faucet = Arc::new(crate::faucet::Faucet::new(
+            "ab".repeat(32),
+            100,
+            std::time::Duration::from_secs(60),
+            1,
+            1,
+        ));
+        for (faucet, expected) in [
+            (Some(faucet), StatusCode::OK),
+            (None, StatusCode::UNAUTHORIZED),
+        ] {
+            let app = test::init_service(
+                App::new()
+                    .wrap(
+                        ApiKeyAuth::new(Some("secret-key".to_string()), HashMap::new())
+                            .with_faucet(faucet),
+                    )
+                    .route(FAUCET_PATH, web::post().to(addr))
+                    .route("/addr", web::post().to(addr)),
+            )
+            .await;
+
+            let req = test::TestRequest::post().uri(FAUCET_PATH).to_request();
+            let status = match test::try_call_service(&app, req).await {
+                Ok(res) => res.status(),
+                Err(e) => e.as_response_error().status_code(),
+            };
+            assert_eq!(status, expected);
+            let req = test::TestRequest::post().uri("/addr").to_request();
+            assert!(test::try_call_service(&app, req).await.is_err());
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_oidc_only_requires_a_valid_token() {
+        let settings = crate::oidc::OidcSettings {
+            issuer: "https://sso.example".to_string(),
+            audience: "gateway".to_string(),
+            // Nothing listens here, so key lookups fail fast
+            jwks_url: Some("http://127.0.0.1:1/jwks".to_string()),
+            role_claim: "roles".to_string(),
+            role_map: HashMap::from([("ops".to_string(), "admin".to_string())]),
+            jwks_refresh_secs: 3600,
+        };
+        let oidc = Arc::new(crate::oidc::OidcAuthenticator::new(
+            settings,
+            reqwest::Client::new(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .wrap(ApiKeyAuth::new(None, HashMap::new()).with_oidc(Some(oidc)))
+                .route("/addr", web::get().to(addr))
+                .route("/health", web::get().to(addr)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        for auth in [
+            None,
+            Some("Bearer some-api-key"),
+            Some("Bearer e30.e30.c2ln"),
+        ] {
+            let mut req = test::TestRequest::get().uri("/addr");
+            if let Some(auth) = auth {
+                req = req.insert_header(("Authorization", auth));
             }
+            let err = test::try_call_service(&app, req.to_request())
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err.as_response_error().status_code(),
+                StatusCode::UNAUTHORIZED
+            );
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_session_cookies_stand_in_for_the_key() {
+        use crate::sessions::REFRESH_COOKIE;
+        let sessions = crate::sessions::open_test_sessions().await;
+        let app = test::init_service(
+            App::new()
+                .wrap(
+                    ApiKeyAuth::new(
+                        Some("secret-key".to_string()),
+                        HashMap::from([("support-key".to_string(), "support".to_string())]),
+                    )
+                    .with_sessions(Some(sessions.clone())),
+                )
+                .app_data(web::Data::new(sessions))
+                .service(web::scope("/v1/gateway").configure(crate::api::sessions::configure))
+                .route("/addr", web::get().to(addr))
+                .route("/addr", web::post().to(addr)),
+        )
+        .await;
+        let cookie = |res: &ServiceResponse, name: &str| {
+            res.response()
+                .cookies()
+                .find(|c| c.name() == name)
+                .map(|c| c.into_owned())
+                .unwrap()
+        };
+
+        // A read-only key opens a read-only session
+        let req = test::TestRequest::post()
+            .uri(SESSIONS_PATH)
+            .insert_header(("Authorization", "Bearer support-key"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let access = cookie(&res, ACCESS_COOKIE);
+        let refresh = cookie(&res, REFRESH_COOKIE);
+
+        let req = test::TestRequest::get()
+            .uri("/addr")
+            .cookie(access.clone())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::post()
+            .uri("/addr")
+            .cookie(access.clone())
+            .to_request();
+        let err = test::try_call_service(&app, req).await.unwrap_err();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::FORBIDDEN);
+        let req = test::TestRequest::get().uri("/addr").to_request();
+        let err = test::try_call_service(&app, req).await.unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        // A session cookie cannot open another session
+        let req = test::TestRequest::post()
+            .uri(SESSIONS_PATH)
+            .cookie(access)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        // Refreshing needs no credentials but the refresh cookie, once
+        let req = test::TestRequest::post()
+            .uri("/v1/gateway/sessions/refresh")
+            .cookie(refresh.clone())
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_ne!(cookie(&res, REFRESH_COOKIE).value(), refresh.value());
+        let req = test::TestRequest::post()
+            .uri("/v1/gateway/sessions/refresh")
+            .cookie(refresh)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(cookie(&res, ACCESS_COOKIE).value(), "");
+    }
+
+    #[actix_rt::test]
+    async fn test_cookie_writes_need_the_csrf_token() {
+        let sessions = crate::sessions::open_test_sessions().await;
+        let app = test::init_service(
+            App::new()
+                .wrap(CsrfProtection::new(Some(sessions.clone())))
+                .wrap(
+                    ApiKeyAuth::new(Some("secret-key".to_string()), HashMap::new())
+                        .with_sessions(Some(sessions.clone())),
+                )
+                .app_data(web::Data::new(sessions))
+                .service(web::scope("/v1/gateway").configure(crate::api::sessions::configure))
+                .route("/addr", web::post().to(addr)),
+        )
+        .await;
+        let cookie = |res: &ServiceResponse, name: &str| {
+            res.response()
+                .cookies()
+                .find(|c| c.name() == name)
+                .map(|c| c.into_owned())
+                .unwrap()
+        };
+        let status = |res: Result<ServiceResponse, Error>| match res {
+            Ok(res) => res.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+
+        let req = test::TestRequest::post()
+            .uri(SESSIONS_PATH)
+            .insert_header(("Authorization", "Bearer secret-key"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let (access, refresh, csrf) = (
+            cookie(&res, ACCESS_COOKIE),
+            cookie(&res, REFRESH_COOKIE),
+            cookie(&res, CSRF_COOKIE),
+        );
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["csrf_token"], csrf.value());
+
+        let req = test::TestRequest::post()
+            .uri("/addr")
+            .cookie(access.clone())
+            .to_request();
+        assert_eq!(
+            status(test::try_call_service(&app, req).await),
+            StatusCode::FORBIDDEN
+        );
+        let req = test::TestRequest::post()
+            .uri("/addr")
+            .cookie(access.clone())
+            .insert_header((CSRF_HEADER, csrf.value()))
+            .to_request();
+        assert_eq!(
+            status(test::try_call_service(&app, req).await),
+            StatusCode::OK
+        );
+        // Bearer callers are not cookie-authenticated, so need no token
+        let req = test::TestRequest::post()
+            .uri("/addr")
+            .insert_header(("Authorization", "Bearer secret-key"))
+            .to_request();
+        assert_eq!(
+            status(test::try_call_service(&app, req).await),
+            StatusCode::OK
+        );
 
-            let timestamps = store.entry(client_id.clone()).or_default();
+        let req = test::TestRequest::get()
+            .uri("/v1/gateway/sessions/csrf")
+            .cookie(access)
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["csrf_token"], csrf.value());
 
-            // Remove old timestamps
-            timestamps.retain(|t| *t > window_start);
+        let req = test::TestRequest::post()
+            .uri("/v1/gateway/sessions/refresh")
+            .cookie(refresh.clone())
+            .to_request();
+        assert_eq!(
+            status(test::try_call_service(&app, req).await),
+            StatusCode::FORBIDDEN
+        );
+        let req = test::TestRequest::post()
+            .uri("/v1/gateway/sessions/refresh")
+            .cookie(refresh)
+            .cookie(csrf.clone())
+            .insert_header((CSRF_HEADER, csrf.value()))
+            .to_request();
+        assert_eq!(
+            status(test::try_call_service(&app, req).await),
+            StatusCode::OK
+        );
+    }
 
-            if timestamps.len() >= self.requests_per_minute {
-                return Box::pin(async { Err(RateLimitError.into()) });
+    #[actix_rt::test]
+    async fn test_network_acl_guard_checks_the_forwarded_client() {
+        use crate::network_acl::{parse_rules, parse_trusted_proxies, NetworkAcl};
+        let acl = Arc::new(NetworkAcl::new(
+            parse_rules("allow /v1/gateway/admin=10.0.0.0/8").unwrap(),
+            parse_trusted_proxies("192.0.2.1").unwrap(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .wrap(NetworkAclGuard::new(acl))
+                .route("/addr", web::get().to(addr))
+                .route(MAINTENANCE_PATH, web::get().to(addr)),
+        )
+        .await;
+        let request = |path: &str, peer: &str, forwarded: Option<&str>| {
+            let mut req = test::TestRequest::get()
+                .uri(path)
+                .peer_addr(format!("{peer}:1000").parse().unwrap());
+            if let Some(forwarded) = forwarded {
+                req = req.insert_header(("X-Forwarded-For", forwarded));
             }
+            req.to_request()
+        };
+
+        let req = request("/addr", "203.0.113.7", None);
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = request(MAINTENANCE_PATH, "10.1.2.3", None);
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = request(MAINTENANCE_PATH, "192.0.2.1", Some("10.1.2.3"));
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        // Only a trusted proxy may vouch for a management address
+        for (peer, forwarded) in [
+            ("203.0.113.7", None),
+            ("203.0.113.7", Some("10.1.2.3")),
+            ("192.0.2.1", Some("10.1.2.3, 203.0.113.7")),
+        ] {
+            let err = test::try_call_service(&app, request(MAINTENANCE_PATH, peer, forwarded))
+                .await
+                .unwrap_err();
+            assert_eq!(err.as_response_error().status_code(), StatusCode::FORBIDDEN);
+        }
+    }
 
-            timestamps.push(now);
+    #[actix_rt::test]
+    async fn test_repeated_bad_keys_lock_the_client_out() {
+        let lockouts = crate::lockout::open_test_lockouts().await;
+        let app = test::init_service(
+            App::new()
+                .wrap(
+                    ApiKeyAuth::new(Some("secret-key".to_string()), role_keys())
+                        .with_lockouts(Some(lockouts.clone())),
+                )
+                .route("/addr", web::get().to(addr)),
+        )
+        .await;
+        let request = |key: &str, peer: &str| {
+            test::TestRequest::get()
+                .uri("/addr")
+                .peer_addr(format!("{peer}:1000").parse().unwrap())
+                .insert_header(("Authorization", format!("Bearer {key}")))
+                .to_request()
+        };
+        let status = |res: Result<ServiceResponse, Error>| match res {
+            Ok(res) => res.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+
+        for _ in 0..2 {
+            let res = test::try_call_service(&app, request("guess", "203.0.113.7")).await;
+            assert_eq!(status(res), StatusCode::UNAUTHORIZED);
         }
+        // Locked out even with the right key; other clients are unaffected
+        let res = test::try_call_service(&app, request("secret-key", "203.0.113.7")).await;
+        assert_eq!(status(res), StatusCode::TOO_MANY_REQUESTS);
+        let res = test::try_call_service(&app, request("secret-key", "198.51.100.1")).await;
+        assert_eq!(status(res), StatusCode::OK);
 
-        let fut = self.service.call(req);
-        Box::pin(fut)
+        assert!(lockouts.clear("ip:203.0.113.7").await.unwrap());
+        let res = test::try_call_service(&app, request("secret-key", "203.0.113.7")).await;
+        assert_eq!(status(res), StatusCode::OK);
+
+        // Only consecutive failures count: a success in between resets them
+        for (key, expected) in [
+            ("guess", StatusCode::UNAUTHORIZED),
+            ("secret-key", StatusCode::OK),
+            ("guess", StatusCode::UNAUTHORIZED),
+            ("secret-key", StatusCode::OK),
+        ] {
+            let res = test::try_call_service(&app, request(key, "192.0.2.9")).await;
+            assert_eq!(status(res), expected);
+        }
+
+        // A role key's successes do not forgive guesses at the API key
+        for (key, expected) in [
+            ("guess", StatusCode::UNAUTHORIZED),
+            ("support-token", StatusCode::OK),
+            ("guess", StatusCode::UNAUTHORIZED),
+            ("support-token", StatusCode::TOO_MANY_REQUESTS),
+            ("secret-key", StatusCode::TOO_MANY_REQUESTS),
+        ] {
+            let res = test::try_call_service(&app, request(key, "192.0.2.10")).await;
+            assert_eq!(status(res), expected);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_audit_log_records_writes_and_refusals() {
+        use crate::audit::{AuditExporter, AuditFormat, AuditTarget};
+        let path = std::env::temp_dir().join(format!("tapgw-audit-{}.log", Uuid::new_v4()));
+        let exporter =
+            AuditExporter::start(AuditTarget::File(path.clone()), AuditFormat::Syslog, 16);
+        let app = test::init_service(
+            App::new()
+                .wrap(ApiKeyAuth::new(Some("secret-key".to_string()), role_keys()))
+                .wrap(AuditLog::new(Some(exporter), Vec::new()))
+                .wrap(RequestIdMiddleware)
+                .route("/addr", web::get().to(addr))
+                .route("/addr", web::post().to(addr)),
+        )
+        .await;
+        let request = |method: Method, token: &str| {
+            test::TestRequest::default()
+                .method(method)
+                .uri("/addr")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request()
+        };
+
+        // Reads are not audited; writes and refusals are
+        let res = test::call_service(&app, request(Method::GET, "secret-key")).await;
+        assert!(res.status().is_success());
+        let res = test::call_service(&app, request(Method::POST, "secret-key")).await;
+        assert!(res.status().is_success());
+        assert!(test::try_call_service(&app, request(Method::GET, "guess"))
+            .await
+            .is_err());
+
+        let mut lines = Vec::new();
+        for _ in 0..50 {
+            lines = std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .lines()
+                .map(str::to_string)
+                .collect();
+            if lines.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("event=write actor=primary method=POST path=/addr status=200"));
+        assert!(lines[0].contains(" request_id="));
+        assert!(lines[1].contains("event=auth_failure actor=anonymous method=GET"));
+    }
+
+    #[actix_rt::test]
+    async fn test_maintenance_guard_only_blocks_writes() {
+        let mode = Arc::new(crate::maintenance::MaintenanceMode::new(true, None));
+        let app = test::init_service(
+            App::new()
+                .wrap(MaintenanceGuard::new(mode.clone()))
+                .route("/addr", web::get().to(addr))
+                .route("/addr", web::post().to(addr))
+                .route(MAINTENANCE_PATH, web::put().to(addr)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/addr").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        let req = test::TestRequest::post().uri("/addr").to_request();
+        let err = test::try_call_service(&app, req).await.unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        let req = test::TestRequest::put().uri(MAINTENANCE_PATH).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        mode.set(false, None);
+        let req = test::TestRequest::post().uri("/addr").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    #[actix_rt::test]
+    async fn test_shadow_traffic_leaves_primary_response_intact() {
+        let mirror = Arc::new(crate::shadow::ShadowMirror::new(
+            reqwest::Client::new(),
+            "https://127.0.0.1:1".to_string(),
+            String::new(),
+            100,
+        ));
+        let app = test::init_service(
+            App::new()
+                .wrap(ShadowTraffic::new(Some(mirror)))
+                .route("/v1/taproot-assets/addrs", web::get().to(addr)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/v1/taproot-assets/addrs?limit=1")
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["encoded"], "taprt1...");
+        assert_eq!(body["internal_key"], "02ab");
+    }
+
+    #[actix_rt::test]
+    async fn test_untransformed_responses_keep_streaming() {
+        async fn relayed() -> HttpResponse {
+            let chunks = ["{\"leaves\": [", "{\"proof\":  \"00ff\"}", "]}"]
+                .map(|chunk| Ok::<_, Error>(actix_web::web::Bytes::from_static(chunk.as_bytes())));
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .streaming(futures::stream::iter(chunks))
+        }
+
+        let app = test::init_service(
+            App::new()
+                .wrap(Redaction::new(RedactionProfiles::default()))
+                .wrap(FieldCaseNormalization::new(None))
+                .wrap(ApiVersioning)
+                .wrap(ShadowTraffic::new(None))
+                .route("/v1/taproot-assets/universe/leaves", web::get().to(relayed)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/v1/taproot-assets/universe/leaves")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.response().body().size(), BodySize::Stream);
+        assert!(!res.headers().contains_key(CONTENT_LENGTH));
+        assert_eq!(
+            test::read_body(res).await,
+            "{\"leaves\": [{\"proof\":  \"00ff\"}]}"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_canary_requests_see_alternate_backend() {
+        use crate::types::BaseUrl;
+
+        async fn backend(base_url: web::Data<BaseUrl>) -> HttpResponse {
+            HttpResponse::Ok().body(base_url.0.clone())
+        }
+
+        let router = Arc::new(crate::canary::CanaryRouter::new(
+            "https://canary:8289".to_string(),
+            "ab".to_string(),
+            ["canary-token".to_string()].into(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .wrap(CanaryRouting::new(Some(router)))
+                .app_data(web::Data::new(BaseUrl("https://primary:8289".to_string())))
+                .route("/backend", web::get().to(backend)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/backend").to_request();
+        let res = test::call_service(&app, req).await;
+        assert!(!res.headers().contains_key("x-canary"));
+        assert_eq!(test::read_body(res).await, "https://primary:8289");
+
+        let req = test::TestRequest::get()
+            .uri("/backend")
+            .insert_header(("X-Canary", "true"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.headers().get("x-canary").unwrap(), "true");
+        assert_eq!(test::read_body(res).await, "https://canary:8289");
+
+        let req = test::TestRequest::get()
+            .uri("/backend")
+            .insert_header(("Authorization", "Bearer canary-token"))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(body, "https://canary:8289");
+    }
+
+    #[actix_rt::test]
+    async fn test_accept_header_selects_response_version() {
+        async fn list() -> HttpResponse {
+            HttpResponse::Ok().json(serde_json::json!({
+                "items": [1, 2],
+                "next_cursor": "Mg",
+                "total_estimate": 3
+            }))
+        }
+
+        let app = test::init_service(
+            App::new()
+                .wrap(ApiVersioning)
+                .route("/list", web::get().to(list)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/list").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["next_cursor"], "Mg");
+
+        let req = test::TestRequest::get()
+            .uri("/list")
+            .insert_header((ACCEPT, api_version::MEDIA_TYPE_V2))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(
+            res.headers().get(CONTENT_TYPE).unwrap(),
+            api_version::MEDIA_TYPE_V2
+        );
+        assert_eq!(res.headers().get(VARY).unwrap(), "Accept");
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["page"]["next_cursor"], "Mg");
+        assert!(body.get("next_cursor").is_none());
+
+        let req = test::TestRequest::get()
+            .uri("/list")
+            .insert_header((ACCEPT, "application/vnd.tapgateway.v7+json"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[actix_rt::test]
+    async fn test_rate_limit_headers_and_retry_after() {
+        let limits = Arc::new(RateLimits::new(1));
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::shared(limits.clone()))
+                .app_data(web::Data::new(limits))
+                .route("/addr", web::get().to(addr))
+                .route(
+                    RATE_LIMIT_PATH,
+                    web::get().to(crate::api::rate_limit::get_rate_limit),
+                ),
+        )
+        .await;
+        let get = |uri: &str| {
+            test::TestRequest::get()
+                .uri(uri)
+                .peer_addr("10.0.0.1:1000".parse().unwrap())
+                .to_request()
+        };
+
+        let res = test::call_service(&app, get("/addr")).await;
+        assert_eq!(res.headers().get("x-ratelimit-limit").unwrap(), "1");
+        assert_eq!(res.headers().get("x-ratelimit-remaining").unwrap(), "0");
+        assert_eq!(res.headers().get("x-ratelimit-reset").unwrap(), "60");
+
+        let err = test::try_call_service(&app, get("/addr"))
+            .await
+            .unwrap_err();
+        let res = err.error_response();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get("retry-after").unwrap(), "60");
+        assert_eq!(res.headers().get("x-ratelimit-remaining").unwrap(), "0");
+
+        // The bucket stays readable while throttled
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, get(RATE_LIMIT_PATH)).await;
+        assert_eq!(body["remaining"], 0);
+        assert_eq!(body["limit"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_base_path_is_stripped_before_routing() {
+        async fn echo(req: actix_web::HttpRequest) -> HttpResponse {
+            let mount = req.extensions().get::<MountPath>().map(|m| m.0.clone());
+            HttpResponse::Ok().json(serde_json::json!({
+                "path": req.path(),
+                "query": req.query_string(),
+                "mount": mount,
+            }))
+        }
+
+        let app = test::init_service(
+            App::new()
+                .wrap(BasePath::new("/taproot/"))
+                .route("/health", web::get().to(echo))
+                .route("/", web::get().to(echo)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/taproot/health?x=1")
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["path"], "/health");
+        assert_eq!(body["query"], "x=1");
+        assert_eq!(body["mount"], "/taproot");
+
+        let req = test::TestRequest::get().uri("/taproot").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        for uri in ["/health", "/taprootx/health"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let err = test::try_call_service(&app, req).await.unwrap_err();
+            assert_eq!(err.as_response_error().status_code(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_tenant_keys_reach_their_own_backend() {
+        use crate::tenants::{Tenant, TenantRouter};
+        use crate::types::{BaseUrl, MacaroonHex};
+        use crate::websocket::connection_manager::WebSocketConnectionManager;
+
+        async fn backend(base_url: web::Data<BaseUrl>) -> HttpResponse {
+            HttpResponse::Ok().body(base_url.0.clone())
+        }
+
+        let manager = Arc::new(WebSocketConnectionManager::new(
+            BaseUrl("wss://acme:8089".to_string()),
+            MacaroonHex("cd".to_string()),
+            true,
+        ));
+        let router = Arc::new(TenantRouter::new([(
+            "acme-token".to_string(),
+            Tenant::new(
+                "acme".to_string(),
+                "https://acme:8089".to_string(),
+                "cd".to_string(),
+                manager,
+                Arc::new(crate::event_bus::EventBus::default()),
+                None,
+                None,
+            ),
+        )]));
+        let app = test::init_service(
+            App::new()
+                .wrap(ApiKeyAuth::new(
+                    Some("admin-token".to_string()),
+                    HashMap::new(),
+                ))
+                .wrap(TenantRouting::new(Some(router)))
+                .app_data(web::Data::new(BaseUrl("https://primary:8289".to_string())))
+                .route("/backend", web::post().to(backend))
+                .route("/v1/gateway/admin/websockets", web::get().to(backend)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/backend")
+            .insert_header(("Authorization", "Bearer acme-token"))
+            .to_request();
+        assert_eq!(
+            test::call_and_read_body(&app, req).await,
+            "https://acme:8089"
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/backend")
+            .insert_header(("Authorization", "Bearer admin-token"))
+            .to_request();
+        assert_eq!(
+            test::call_and_read_body(&app, req).await,
+            "https://primary:8289"
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/v1/gateway/admin/websockets")
+            .insert_header(("Authorization", "Bearer acme-token"))
+            .to_request();
+        let err = test::try_call_service(&app, req).await.unwrap_err();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::FORBIDDEN);
     }
 }
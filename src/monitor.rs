@@ -0,0 +1,616 @@
+//! Periodic gateway snapshots for ops dashboards: request counts and rates,
+//! open WebSocket sessions and backend health. A single background task
+//! samples every interval and publishes the snapshot on the event bus, so
+//! subscribers cost nothing extra on the backend.
+
+use crate::api::info;
+use crate::backend::BackendStatsSnapshot;
+use crate::cache_audit::AuditCounts;
+use crate::event_bus::SharedEventBus;
+use crate::latency::{EndpointLatency, BUCKETS_MS};
+use crate::maintenance::SharedMaintenance;
+use crate::priority::{ClassStats, PrioritySnapshot};
+use crate::proof_cache::{self, ProofCacheStatsSnapshot};
+use crate::warmup::{SharedWarmup, WarmupPhase};
+use crate::webhooks::WebhookStatsSnapshot;
+use crate::websocket::connection_manager::{ConnectionManagerState, WebSocketConnectionManager};
+use actix_web::http::StatusCode;
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Event bus topic carrying every snapshot.
+pub const TOPIC_SNAPSHOT: &str = "monitor.snapshot";
+
+/// Request counters updated by the request metrics middleware.
+#[derive(Debug, Default)]
+pub struct RequestStats {
+    total: AtomicU64,
+    client_errors: AtomicU64,
+    server_errors: AtomicU64,
+    auth_failures: AtomicU64,
+}
+
+impl RequestStats {
+    pub fn record(&self, status: StatusCode) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        if status.is_client_error() {
+            self.client_errors.fetch_add(1, Ordering::Relaxed);
+            if status == StatusCode::UNAUTHORIZED {
+                self.auth_failures.fetch_add(1, Ordering::Relaxed);
+            }
+        } else if status.is_server_error() {
+            self.server_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Requests refused for missing or bad credentials.
+    pub fn auth_failures(&self) -> u64 {
+        self.auth_failures.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn totals(&self) -> (u64, u64, u64) {
+        (
+            self.total.load(Ordering::Relaxed),
+            self.client_errors.load(Ordering::Relaxed),
+            self.server_errors.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestSnapshot {
+    pub total: u64,
+    pub client_errors: u64,
+    pub server_errors: u64,
+    /// Averaged over the last sampling interval.
+    pub per_second: f64,
+    pub errors_per_second: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSnapshot {
+    /// Proxied tapd subscriptions, one backend socket each.
+    pub backend_websockets: usize,
+    /// Subscribers of gateway-produced event streams (transfer events,
+    /// this monitor, ...).
+    pub event_subscribers: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendSnapshot {
+    pub healthy: bool,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorSnapshot {
+    pub timestamp: i64,
+    pub uptime_secs: u64,
+    pub interval_secs: u64,
+    pub requests: RequestSnapshot,
+    pub sessions: SessionSnapshot,
+    pub backend: BackendSnapshot,
+    /// Proof decode/verify cache counters since startup.
+    pub proof_cache: ProofCacheStatsSnapshot,
+    pub warmup: WarmupPhase,
+    pub maintenance: bool,
+}
+
+pub struct Monitor {
+    started: Instant,
+    interval: Duration,
+    stats: Arc<RequestStats>,
+    latest: RwLock<Option<MonitorSnapshot>>,
+}
+
+pub type SharedMonitor = Arc<Monitor>;
+
+/// Everything the sampler reads from.
+pub struct MonitorSources {
+    pub client: Client,
+    pub base_url: String,
+    pub macaroon_hex: String,
+    pub connection_manager: Arc<WebSocketConnectionManager>,
+    pub events: SharedEventBus,
+    pub warmup: SharedWarmup,
+    pub maintenance: SharedMaintenance,
+}
+
+impl Monitor {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            started: Instant::now(),
+            interval,
+            stats: Arc::new(RequestStats::default()),
+            latest: RwLock::new(None),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<RequestStats> {
+        self.stats.clone()
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The most recent snapshot, if the sampler has run yet.
+    pub fn latest(&self) -> Option<MonitorSnapshot> {
+        self.latest
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Samples every interval and publishes each snapshot on the event bus.
+    pub fn start(self: Arc<Self>, sources: MonitorSources) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            let mut previous = self.stats.totals();
+            loop {
+                ticker.tick().await;
+                let current = self.stats.totals();
+                let snapshot = self.sample(&sources, previous, current).await;
+                previous = current;
+                match serde_json::to_value(&snapshot) {
+                    Ok(data) => {
+                        sources.events.publish(TOPIC_SNAPSHOT, data);
+                    }
+                    Err(e) => warn!("Failed to serialize monitor snapshot: {}", e),
+                }
+                *self.latest.write().unwrap_or_else(|e| e.into_inner()) = Some(snapshot);
+            }
+        })
+    }
+
+    async fn sample(
+        &self,
+        sources: &MonitorSources,
+        previous: (u64, u64, u64),
+        current: (u64, u64, u64),
+    ) -> MonitorSnapshot {
+        let probe = Instant::now();
+        let backend =
+            match info::get_info(&sources.client, &sources.base_url, &sources.macaroon_hex).await {
+                Ok(_) => BackendSnapshot {
+                    healthy: true,
+                    latency_ms: Some(probe.elapsed().as_millis() as u64),
+                    error: None,
+                },
+                Err(e) => BackendSnapshot {
+                    healthy: false,
+                    latency_ms: None,
+                    error: Some(e.to_string()),
+                },
+            };
+
+        MonitorSnapshot {
+            timestamp: chrono::Utc::now().timestamp(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            interval_secs: self.interval.as_secs(),
+            requests: request_snapshot(previous, current, self.interval),
+            sessions: SessionSnapshot {
+                backend_websockets: sources.connection_manager.connection_count().await,
+                event_subscribers: sources.events.subscriber_count(),
+            },
+            backend,
+            proof_cache: proof_cache::stats().snapshot(),
+            warmup: sources.warmup.status().phase,
+            maintenance: sources.maintenance.is_enabled(),
+        }
+    }
+}
+
+fn request_snapshot(
+    previous: (u64, u64, u64),
+    current: (u64, u64, u64),
+    interval: Duration,
+) -> RequestSnapshot {
+    let secs = interval.as_secs_f64().max(f64::EPSILON);
+    let errors = |(_, client, server): (u64, u64, u64)| client + server;
+    RequestSnapshot {
+        total: current.0,
+        client_errors: current.1,
+        server_errors: current.2,
+        per_second: current.0.saturating_sub(previous.0) as f64 / secs,
+        errors_per_second: errors(current).saturating_sub(errors(previous)) as f64 / secs,
+    }
+}
+
+/// Renders gauges and counters in the Prometheus text exposition format.
+#[allow(clippy::too_many_arguments)]
+pub fn prometheus_metrics(
+    requests: Option<&RequestStats>,
+    backend: &BackendStatsSnapshot,
+    proof_cache: &ProofCacheStatsSnapshot,
+    webhooks: &WebhookStatsSnapshot,
+    websockets: &ConnectionManagerState,
+    priority: Option<&PrioritySnapshot>,
+    latency: &[EndpointLatency],
+    cache_audits: &BTreeMap<String, AuditCounts>,
+) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{name}{labels} {value}");
+        }
+    };
+    let single = |value: u64| vec![(String::new(), value.to_string())];
+
+    if let Some(requests) = requests {
+        let (total, client_errors, server_errors) = requests.totals();
+        metric(
+            "gateway_http_requests_total",
+            "counter",
+            "HTTP requests served.",
+            &single(total),
+        );
+        metric(
+            "gateway_http_errors_total",
+            "counter",
+            "HTTP responses with an error status, by class.",
+            &[
+                ("{class=\"4xx\"}".to_string(), client_errors.to_string()),
+                ("{class=\"5xx\"}".to_string(), server_errors.to_string()),
+            ],
+        );
+        metric(
+            "gateway_http_auth_failures_total",
+            "counter",
+            "HTTP requests refused for missing or bad credentials.",
+            &single(requests.auth_failures()),
+        );
+    }
+
+    metric(
+        "gateway_backend_requests_total",
+        "counter",
+        "REST requests sent to tapd.",
+        &single(backend.requests),
+    );
+    metric(
+        "gateway_backend_transport_errors_total",
+        "counter",
+        "REST requests to tapd that got no response.",
+        &single(backend.transport_errors),
+    );
+    metric(
+        "gateway_backend_upstream_errors_total",
+        "counter",
+        "REST responses from tapd with an error status.",
+        &single(backend.upstream_errors),
+    );
+    metric(
+        "gateway_backend_retries_total",
+        "counter",
+        "REST requests to tapd retried after a connection failure.",
+        &single(backend.retries),
+    );
+    metric(
+        "gateway_proof_cache_hits_total",
+        "counter",
+        "Proof decode/verify calls answered from the proof cache.",
+        &single(proof_cache.hits),
+    );
+    metric(
+        "gateway_proof_cache_misses_total",
+        "counter",
+        "Proof decode/verify calls sent to tapd after a cache miss.",
+        &single(proof_cache.misses),
+    );
+    metric(
+        "gateway_proof_cache_errors_total",
+        "counter",
+        "Proof cache reads or writes that failed.",
+        &single(proof_cache.errors),
+    );
+    metric(
+        "gateway_webhook_deliveries_total",
+        "counter",
+        "Webhook deliveries accepted by the callback.",
+        &single(webhooks.delivered),
+    );
+    metric(
+        "gateway_webhook_failed_attempts_total",
+        "counter",
+        "Webhook delivery attempts that failed.",
+        &single(webhooks.failed_attempts),
+    );
+    metric(
+        "gateway_webhook_dead_letters_total",
+        "counter",
+        "Webhook deliveries moved to the dead-letter queue.",
+        &single(webhooks.dead_lettered),
+    );
+    let audits: Vec<_> = cache_audits
+        .iter()
+        .flat_map(|(cache, counts)| {
+            [
+                ("matched", counts.matched),
+                ("diverged", counts.diverged),
+                ("failed", counts.failed),
+            ]
+            .map(|(result, count)| {
+                (
+                    format!("{{cache=\"{cache}\",result=\"{result}\"}}"),
+                    count.to_string(),
+                )
+            })
+        })
+        .collect();
+    metric(
+        "gateway_cache_audits_total",
+        "counter",
+        "Sampled cache hits compared with a fresh tapd response, by result.",
+        &audits,
+    );
+    metric(
+        "gateway_backend_websockets_open",
+        "gauge",
+        "Open WebSocket connections to tapd.",
+        &single(websockets.open as u64),
+    );
+    let by_endpoint: Vec<_> = websockets
+        .by_endpoint
+        .iter()
+        .map(|(endpoint, count)| {
+            (
+                format!("{{endpoint=\"{}\"}}", escape_label(endpoint)),
+                count.to_string(),
+            )
+        })
+        .collect();
+    metric(
+        "gateway_backend_websockets",
+        "gauge",
+        "Open WebSocket connections to tapd, by endpoint.",
+        &by_endpoint,
+    );
+    let max_idle = websockets.sockets.iter().map(|s| s.idle_secs).max();
+    metric(
+        "gateway_backend_websocket_max_idle_seconds",
+        "gauge",
+        "Idle time of the least recently active backend WebSocket.",
+        &single(max_idle.unwrap_or(0)),
+    );
+    metric(
+        "gateway_backend_websocket_connects_total",
+        "counter",
+        "Backend WebSocket connections opened.",
+        &single(websockets.opened_total),
+    );
+    metric(
+        "gateway_backend_websocket_connect_failures_total",
+        "counter",
+        "Backend WebSocket connection attempts that failed.",
+        &single(websockets.failed_total),
+    );
+    metric(
+        "gateway_backend_websocket_reconnect_attempts_total",
+        "counter",
+        "Backend WebSocket reconnection attempts.",
+        &single(websockets.reconnect_attempts_total),
+    );
+    metric(
+        "gateway_backend_websocket_stale_removed_total",
+        "counter",
+        "Backend WebSockets dropped by the health check for inactivity.",
+        &single(websockets.stale_removed_total),
+    );
+    metric(
+        "gateway_backend_websocket_messages_total",
+        "counter",
+        "Messages relayed over backend WebSockets, either way.",
+        &single(websockets.messages_total),
+    );
+
+    if let Some(priority) = priority {
+        metric(
+            "gateway_priority_in_flight",
+            "gauge",
+            "Requests holding a concurrency slot.",
+            &single(priority.in_flight as u64),
+        );
+        let by_class = |value: fn(&ClassStats) -> u64| -> Vec<(String, String)> {
+            priority
+                .classes
+                .iter()
+                .map(|c| {
+                    (
+                        format!("{{class=\"{}\"}}", c.class.as_str()),
+                        value(&c.stats).to_string(),
+                    )
+                })
+                .collect()
+        };
+        metric(
+            "gateway_priority_queue_depth",
+            "gauge",
+            "Requests waiting for a concurrency slot, by priority class.",
+            &by_class(|s| s.queued as u64),
+        );
+        metric(
+            "gateway_priority_queue_max_depth",
+            "gauge",
+            "Most requests ever waiting at once, by priority class.",
+            &by_class(|s| s.max_queued as u64),
+        );
+        metric(
+            "gateway_priority_admitted_total",
+            "counter",
+            "Requests given a concurrency slot, by priority class.",
+            &by_class(|s| s.admitted),
+        );
+        metric(
+            "gateway_priority_waited_total",
+            "counter",
+            "Admitted requests that queued first, by priority class.",
+            &by_class(|s| s.waited),
+        );
+        metric(
+            "gateway_priority_rejected_total",
+            "counter",
+            "Requests refused because their queue was full, by priority class.",
+            &by_class(|s| s.rejected),
+        );
+        metric(
+            "gateway_priority_timed_out_total",
+            "counter",
+            "Requests that gave up waiting for a slot, by priority class.",
+            &by_class(|s| s.timed_out),
+        );
+    }
+
+    let name = "gateway_backend_request_duration_seconds";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Latency of REST requests to tapd, by endpoint."
+    );
+    let _ = writeln!(out, "# TYPE {name} histogram");
+    for row in latency {
+        let endpoint = escape_label(&row.endpoint);
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS_MS.iter().zip(&row.buckets) {
+            cumulative += count;
+            let le = *bound as f64 / 1000.0;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{endpoint=\"{endpoint}\",le=\"{le}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{endpoint=\"{endpoint}\",le=\"+Inf\"}} {}",
+            row.count
+        );
+        let _ = writeln!(
+            out,
+            "{name}_sum{{endpoint=\"{endpoint}\"}} {}",
+            row.sum_ms as f64 / 1000.0
+        );
+        let _ = writeln!(out, "{name}_count{{endpoint=\"{endpoint}\"}} {}", row.count);
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_stats_classify_statuses() {
+        let stats = RequestStats::default();
+        stats.record(StatusCode::OK);
+        stats.record(StatusCode::NOT_FOUND);
+        stats.record(StatusCode::TOO_MANY_REQUESTS);
+        stats.record(StatusCode::BAD_GATEWAY);
+        assert_eq!(stats.totals(), (4, 2, 1));
+        assert_eq!(stats.auth_failures(), 0);
+        stats.record(StatusCode::UNAUTHORIZED);
+        assert_eq!(stats.auth_failures(), 1);
+    }
+
+    #[test]
+    fn test_prometheus_metrics_render_endpoint_gauges() {
+        let stats = RequestStats::default();
+        stats.record(StatusCode::OK);
+        stats.record(StatusCode::BAD_GATEWAY);
+        let websockets = ConnectionManagerState {
+            open: 2,
+            by_endpoint: [("/v1/taproot-assets/subscribe/send".to_string(), 2)].into(),
+            opened_total: 5,
+            failed_total: 1,
+            reconnect_attempts_total: 0,
+            stale_removed_total: 3,
+            messages_total: 12,
+            sockets: Vec::new(),
+        };
+        let backend = BackendStatsSnapshot {
+            requests: 7,
+            upstream_errors: 2,
+            ..Default::default()
+        };
+        let proof_cache = ProofCacheStatsSnapshot {
+            hits: 4,
+            misses: 1,
+            errors: 0,
+        };
+        let webhooks = WebhookStatsSnapshot {
+            delivered: 5,
+            failed_attempts: 2,
+            dead_lettered: 1,
+        };
+        let priority = crate::priority::PriorityLimiter::new(4, 10, Duration::from_secs(1));
+        let latency = crate::latency::LatencyHistograms::new();
+        latency.record("/v1/taproot-assets/getinfo", Duration::from_millis(30));
+        latency.record("/v1/taproot-assets/getinfo", Duration::from_millis(400));
+        let text = prometheus_metrics(
+            Some(&stats),
+            &backend,
+            &proof_cache,
+            &webhooks,
+            &websockets,
+            Some(&priority.snapshot()),
+            &latency.snapshot(),
+            &[(
+                "public".to_string(),
+                AuditCounts {
+                    matched: 3,
+                    diverged: 1,
+                    failed: 0,
+                },
+            )]
+            .into(),
+        );
+        assert!(
+            text.contains("gateway_cache_audits_total{cache=\"public\",result=\"diverged\"} 1\n")
+        );
+        assert!(text.contains("gateway_priority_queue_depth{class=\"bulk\"} 0\n"));
+        assert!(text.contains("# TYPE gateway_backend_websockets gauge\n"));
+        assert!(text.contains(
+            "gateway_backend_websockets{endpoint=\"/v1/taproot-assets/subscribe/send\"} 2\n"
+        ));
+        assert!(text.contains("gateway_http_errors_total{class=\"5xx\"} 1\n"));
+        assert!(text.contains("gateway_backend_websocket_stale_removed_total 3\n"));
+        assert!(text.contains("gateway_backend_websocket_messages_total 12\n"));
+        assert!(text.contains("gateway_backend_websocket_max_idle_seconds 0\n"));
+        assert!(text.contains("gateway_backend_requests_total 7\n"));
+        assert!(text.contains("gateway_backend_upstream_errors_total 2\n"));
+        assert!(text.contains("gateway_proof_cache_hits_total 4\n"));
+        assert!(text.contains("gateway_webhook_dead_letters_total 1\n"));
+        assert!(text.contains(
+            "gateway_backend_request_duration_seconds_bucket{endpoint=\"/v1/taproot-assets/getinfo\",le=\"0.05\"} 1\n"
+        ));
+        assert!(text.contains(
+            "gateway_backend_request_duration_seconds_bucket{endpoint=\"/v1/taproot-assets/getinfo\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(text.contains(
+            "gateway_backend_request_duration_seconds_sum{endpoint=\"/v1/taproot-assets/getinfo\"} 0.43\n"
+        ));
+        assert_eq!(escape_label("a\"b\\"), "a\\\"b\\\\");
+    }
+
+    #[test]
+    fn test_rates_use_delta_over_interval() {
+        let snapshot = request_snapshot((100, 4, 1), (150, 9, 1), Duration::from_secs(5));
+        assert_eq!(snapshot.total, 150);
+        assert_eq!(snapshot.per_second, 10.0);
+        assert_eq!(snapshot.errors_per_second, 1.0);
+    }
+}
@@ -0,0 +1,338 @@
+//! Network access rules: which client addresses may reach which routes.
+//! Rules come from `NETWORK_ACL` and from the admin API, which stores them
+//! in the database. A request is refused when a deny rule covering its path
+//! matches the client, or when the longest path prefix with allow rules has
+//! none matching it; so `allow /v1/gateway/admin=10.0.0.0/8` keeps the admin
+//! routes on the management network without affecting other routes.
+//!
+//! Behind a reverse proxy the peer is the proxy. Peers in `TRUSTED_PROXIES`
+//! may name the client in `X-Forwarded-For`; the header is read right to
+//! left and the first address that is not a trusted proxy is the client.
+//! Headers from other peers are ignored, so clients cannot spoof them.
+
+use crate::database::NetworkAclRecord;
+use crate::error::AppError;
+use actix_web::http::header::HeaderMap;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AclAction {
+    Allow,
+    Deny,
+}
+
+impl AclAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "allow" => Some(Self::Allow),
+            "deny" => Some(Self::Deny),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AclRule {
+    /// Set for rules added through the admin API; `NETWORK_ACL` rules have
+    /// none and cannot be removed at runtime.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub action: AclAction,
+    /// Path prefix the rule covers, relative to the gateway's mount point;
+    /// `/` covers every route.
+    pub path_prefix: String,
+    pub networks: Vec<IpNet>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl AclRule {
+    pub fn covers(&self, path: &str) -> bool {
+        let prefix = self.path_prefix.trim_end_matches('/');
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    fn matches(&self, ip: Option<IpAddr>) -> bool {
+        ip.is_some_and(|ip| self.networks.iter().any(|net| net.contains(&ip)))
+    }
+
+    /// Checks a rule from the admin API or `NETWORK_ACL`.
+    pub fn validate(&self) -> Result<(), AppError> {
+        if !self.path_prefix.starts_with('/') {
+            return Err(AppError::ValidationError(format!(
+                "Network ACL path prefix '{}' must start with /",
+                self.path_prefix
+            )));
+        }
+        if self.networks.is_empty() {
+            return Err(AppError::ValidationError(
+                "Network ACL rules need at least one network".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&NetworkAclRecord> for AclRule {
+    type Error = AppError;
+
+    fn try_from(record: &NetworkAclRecord) -> Result<Self, AppError> {
+        let rule = Self {
+            id: Some(record.id.clone()),
+            action: AclAction::parse(&record.action).ok_or_else(|| {
+                AppError::ValidationError(format!("Unknown ACL action '{}'", record.action))
+            })?,
+            path_prefix: record.path_prefix.clone(),
+            networks: record
+                .networks
+                .split(',')
+                .map(parse_network)
+                .collect::<Result<_, _>>()?,
+            note: record.note.clone(),
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+}
+
+/// Parses an address or CIDR; a bare address is a single-host network.
+pub fn parse_network(value: &str) -> Result<IpNet, AppError> {
+    let value = value.trim();
+    value
+        .parse::<IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+        .map(|net| net.trunc())
+        .map_err(|_| AppError::ValidationError(format!("Invalid network '{value}'")))
+}
+
+/// Parses `NETWORK_ACL`: `action /path/prefix=cidr,cidr;...`.
+pub fn parse_rules(value: &str) -> Result<Vec<AclRule>, AppError> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let invalid = || {
+                AppError::ValidationError(format!(
+                    "NETWORK_ACL entry '{entry}' must be 'allow|deny /path=cidr,...'"
+                ))
+            };
+            let (head, networks) = entry.split_once('=').ok_or_else(invalid)?;
+            let (action, path_prefix) = head.trim().split_once(' ').ok_or_else(invalid)?;
+            let rule = AclRule {
+                id: None,
+                action: AclAction::parse(action).ok_or_else(invalid)?,
+                path_prefix: path_prefix.trim().to_string(),
+                networks: networks
+                    .split(',')
+                    .filter(|n| !n.trim().is_empty())
+                    .map(parse_network)
+                    .collect::<Result<_, _>>()?,
+                note: None,
+            };
+            rule.validate()?;
+            Ok(rule)
+        })
+        .collect()
+}
+
+/// Parses `TRUSTED_PROXIES`: comma-separated addresses or CIDRs.
+pub fn parse_trusted_proxies(value: &str) -> Result<Vec<IpNet>, AppError> {
+    value
+        .split(',')
+        .filter(|n| !n.trim().is_empty())
+        .map(parse_network)
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct NetworkAcl {
+    configured: Vec<AclRule>,
+    runtime: RwLock<Vec<AclRule>>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+pub type SharedNetworkAcl = Arc<NetworkAcl>;
+
+impl NetworkAcl {
+    pub fn new(configured: Vec<AclRule>, trusted_proxies: Vec<IpNet>) -> Self {
+        Self {
+            configured,
+            runtime: RwLock::new(Vec::new()),
+            trusted_proxies,
+        }
+    }
+
+    /// Every rule, configured ones first.
+    pub fn rules(&self) -> Vec<AclRule> {
+        let runtime = self.runtime.read().unwrap_or_else(|e| e.into_inner());
+        self.configured
+            .iter()
+            .chain(runtime.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.configured.is_empty()
+            && self
+                .runtime
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .is_empty()
+    }
+
+    pub fn add(&self, rule: AclRule) {
+        self.runtime
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(rule);
+    }
+
+    /// Removes runtime rule `id`; false if there is none.
+    pub fn remove(&self, id: &str) -> bool {
+        let mut runtime = self.runtime.write().unwrap_or_else(|e| e.into_inner());
+        let before = runtime.len();
+        runtime.retain(|rule| rule.id.as_deref() != Some(id));
+        runtime.len() != before
+    }
+
+    /// The client address: the peer, or for a trusted proxy the address it
+    /// forwarded for. `None` when the peer is unknown.
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        client_ip(&self.trusted_proxies, peer, headers)
+    }
+
+    /// Whether a client at `ip` may reach `path`.
+    pub fn allows(&self, path: &str, ip: Option<IpAddr>) -> bool {
+        let runtime = self.runtime.read().unwrap_or_else(|e| e.into_inner());
+        evaluate(self.configured.iter().chain(runtime.iter()), path, ip)
+    }
+
+    /// Whether a client at `ip` could still reach `path` with `candidate`
+    /// added, so the admin API can refuse rules that lock out their author.
+    pub fn allows_with(&self, candidate: &AclRule, path: &str, ip: Option<IpAddr>) -> bool {
+        let runtime = self.runtime.read().unwrap_or_else(|e| e.into_inner());
+        let rules = self.configured.iter().chain(runtime.iter());
+        evaluate(rules.chain(std::iter::once(candidate)), path, ip)
+    }
+}
+
+/// The client address behind `peer`: the peer itself, or for a peer in
+/// `trusted_proxies` the address it forwarded for. `None` when the peer is
+/// unknown.
+pub fn client_ip(
+    trusted_proxies: &[IpNet],
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+) -> Option<IpAddr> {
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|net| net.contains(&ip));
+    let mut client = peer?;
+    if !is_trusted(client) {
+        return Some(client);
+    }
+    let hops = headers
+        .get_all("X-Forwarded-For")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    for hop in hops.into_iter().rev() {
+        let Ok(ip) = hop.parse::<IpAddr>() else {
+            // A garbled hop ends the chain we can vouch for
+            break;
+        };
+        client = ip;
+        if !is_trusted(ip) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+fn evaluate<'a>(rules: impl Iterator<Item = &'a AclRule>, path: &str, ip: Option<IpAddr>) -> bool {
+    let mut longest_allow: Option<(usize, bool)> = None;
+    for rule in rules.filter(|rule| rule.covers(path)) {
+        match rule.action {
+            AclAction::Deny if rule.matches(ip) => return false,
+            AclAction::Deny => {}
+            AclAction::Allow => {
+                let len = rule.path_prefix.trim_end_matches('/').len();
+                let matched = rule.matches(ip);
+                longest_allow = match longest_allow {
+                    Some((best, _)) if len > best => Some((len, matched)),
+                    Some((best, any)) if len == best => Some((best, any || matched)),
+                    None => Some((len, matched)),
+                    keep => keep,
+                };
+            }
+        }
+    }
+    longest_allow.is_none_or(|(_, matched)| matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_longest_allow_prefix_decides_and_deny_wins() {
+        let rules = parse_rules(
+            "allow /=192.168.0.0/16; allow /v1/gateway/admin=10.0.0.0/8,fd00::/8; \
+             deny /v1/gateway/admin/macaroons=10.9.9.9",
+        )
+        .unwrap();
+        let acl = NetworkAcl::new(rules, Vec::new());
+
+        assert!(acl.allows("/v1/taproot-assets/assets", ip("192.168.1.5")));
+        assert!(!acl.allows("/v1/taproot-assets/assets", ip("10.0.0.1")));
+        assert!(acl.allows("/v1/gateway/admin/jobs", ip("10.0.0.1")));
+        assert!(acl.allows("/v1/gateway/admin", ip("fd00::1")));
+        assert!(!acl.allows("/v1/gateway/admin/jobs", ip("192.168.1.5")));
+        assert!(!acl.allows("/v1/gateway/admin/macaroons", ip("10.9.9.9")));
+        assert!(!acl.allows("/v1/gateway/administer", ip("10.0.0.1")));
+        assert!(!acl.allows("/health", None));
+
+        assert!(parse_rules("permit /=10.0.0.0/8").is_err());
+        assert!(parse_rules("allow admin=10.0.0.0/8").is_err());
+        assert!(parse_rules("allow /=not-a-net").is_err());
+    }
+
+    #[test]
+    fn test_forwarded_for_is_only_trusted_from_proxies() {
+        let acl = NetworkAcl::new(Vec::new(), parse_trusted_proxies("10.0.0.0/24").unwrap());
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-forwarded-for"),
+            HeaderValue::from_static("1.2.3.4, 203.0.113.7, 10.0.0.2"),
+        );
+
+        // The spoofable left part is skipped once an untrusted hop appears
+        assert_eq!(acl.client_ip(ip("10.0.0.1"), &headers), ip("203.0.113.7"));
+        assert_eq!(
+            acl.client_ip(ip("198.51.100.1"), &headers),
+            ip("198.51.100.1")
+        );
+        assert_eq!(
+            acl.client_ip(ip("10.0.0.1"), &HeaderMap::new()),
+            ip("10.0.0.1")
+        );
+    }
+}
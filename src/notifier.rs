@@ -0,0 +1,480 @@
+//! Operator notifications. Gateway events worth a human's attention (the
+//! backend going down or coming back, unusually large transfers, sends
+//! flagged by [`crate::anomaly`] and fragmented asset outputs) are turned
+//! into notifications and fanned out to the configured sinks: generic JSON
+//! webhooks, Slack or Matrix chat webhooks, and email. Chat and webhook
+//! sinks are notified immediately; email is batched into a periodic digest
+//! so an alert storm does not become an inbox storm.
+//!
+//! Delivery is best effort. A failing sink is logged and never blocks the
+//! others or the gateway.
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::event_bus::{GatewayEvent, SharedEventBus};
+use crate::indexer::TOPIC_UPDATED;
+use crate::monitor::TOPIC_SNAPSHOT;
+use crate::utxo_analytics::TOPIC_FRAGMENTED;
+use lettre::message::Mailbox;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// Transfer ids remembered to avoid repeating a large-transfer alert on
+/// every status update; the set is reset when it grows past this.
+const MAX_REMEMBERED_TRANSFERS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    BackendDown,
+    BackendRecovered,
+    LargeTransfer,
+    Anomaly,
+    Fragmentation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub title: String,
+    pub message: String,
+    pub timestamp: i64,
+    /// The gateway event that raised the notification.
+    pub data: Value,
+}
+
+impl Notification {
+    pub(crate) fn new(kind: NotificationKind, title: String, message: String, data: Value) -> Self {
+        Self {
+            kind,
+            title,
+            message,
+            timestamp: chrono::Utc::now().timestamp(),
+            data,
+        }
+    }
+
+    /// One-line rendering for chat sinks and digests.
+    fn text(&self) -> String {
+        format!("{}: {}", self.title, self.message)
+    }
+}
+
+enum Sink {
+    /// Receives the notification as JSON.
+    Webhook(String),
+    /// Slack incoming webhooks and Matrix hookshot webhooks both accept
+    /// `{"text": ...}`.
+    Chat(String),
+    Email(Box<EmailDigest>),
+}
+
+struct EmailDigest {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    pending: Mutex<Vec<Notification>>,
+}
+
+impl EmailDigest {
+    fn from_config(config: &Config) -> Result<Option<Self>, AppError> {
+        let Some(url) = &config.notify_smtp_url else {
+            return Ok(None);
+        };
+        let invalid = |e: String| AppError::ValidationError(e);
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(|e| invalid(format!("Invalid NOTIFY_SMTP_URL: {e}")))?
+            .build();
+        let from = config
+            .notify_email_from
+            .as_deref()
+            .ok_or_else(|| invalid("NOTIFY_SMTP_URL requires NOTIFY_EMAIL_FROM".to_string()))?
+            .parse::<Mailbox>()
+            .map_err(|e| invalid(format!("Invalid NOTIFY_EMAIL_FROM: {e}")))?;
+        let to = config
+            .notify_email_to
+            .iter()
+            .map(|address| address.parse::<Mailbox>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| invalid(format!("Invalid NOTIFY_EMAIL_TO: {e}")))?;
+        if to.is_empty() {
+            return Err(invalid(
+                "NOTIFY_SMTP_URL requires NOTIFY_EMAIL_TO".to_string(),
+            ));
+        }
+        Ok(Some(Self {
+            transport,
+            from,
+            to,
+            pending: Mutex::new(Vec::new()),
+        }))
+    }
+
+    fn queue(&self, notification: Notification) {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(notification);
+    }
+
+    /// Sends everything queued as one email. Notifications are put back when
+    /// sending fails, so the next digest retries them.
+    async fn send(&self) -> Result<usize, AppError> {
+        let batch = std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()));
+        if batch.is_empty() {
+            return Ok(0);
+        }
+        let result = async {
+            let mut message = Message::builder()
+                .from(self.from.clone())
+                .subject(digest_subject(&batch));
+            for to in &self.to {
+                message = message.to(to.clone());
+            }
+            let message = message
+                .body(digest_body(&batch))
+                .map_err(|e| AppError::ValidationError(format!("Invalid digest email: {e}")))?;
+            self.transport
+                .send(message)
+                .await
+                .map_err(|e| AppError::ServiceUnavailable(format!("SMTP delivery failed: {e}")))
+        }
+        .await;
+        if let Err(e) = result {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            let newer = std::mem::replace(&mut *pending, batch);
+            pending.extend(newer);
+            return Err(e);
+        }
+        Ok(batch.len())
+    }
+}
+
+fn digest_subject(batch: &[Notification]) -> String {
+    match batch {
+        [only] => format!("[taproot-assets gateway] {}", only.title),
+        _ => format!("[taproot-assets gateway] {} notifications", batch.len()),
+    }
+}
+
+fn digest_body(batch: &[Notification]) -> String {
+    batch
+        .iter()
+        .map(|n| {
+            let time = chrono::DateTime::from_timestamp(n.timestamp, 0)
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_default();
+            format!("{time}  {}\n", n.text())
+        })
+        .collect()
+}
+
+pub struct Notifier {
+    client: Client,
+    sinks: Vec<Sink>,
+    large_transfer_amount: Option<u64>,
+}
+
+impl Notifier {
+    /// The notifier for the configured sinks, or `None` when none are set.
+    pub fn from_config(config: &Config, client: Client) -> Result<Option<Self>, AppError> {
+        let mut sinks: Vec<Sink> = config
+            .notify_webhook_urls
+            .iter()
+            .cloned()
+            .map(Sink::Webhook)
+            .collect();
+        sinks.extend(
+            [
+                &config.notify_slack_webhook_url,
+                &config.notify_matrix_webhook_url,
+            ]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .map(Sink::Chat),
+        );
+        if let Some(email) = EmailDigest::from_config(config)? {
+            sinks.push(Sink::Email(Box::new(email)));
+        }
+        if sinks.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            client,
+            sinks,
+            large_transfer_amount: config.notify_large_transfer_amount,
+        }))
+    }
+
+    /// Delivers to the webhook and chat sinks and queues for the email
+    /// digest.
+    pub async fn notify(&self, notification: Notification) {
+        info!("Notification: {}", notification.text());
+        let payload = match serde_json::to_value(&notification) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to serialize notification: {}", e);
+                return;
+            }
+        };
+        let chat = serde_json::json!({ "text": notification.text() });
+        for sink in &self.sinks {
+            let result = match sink {
+                Sink::Webhook(url) => self.post(url, &payload).await,
+                Sink::Chat(url) => self.post(url, &chat).await,
+                Sink::Email(digest) => {
+                    digest.queue(notification.clone());
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                warn!("Notification delivery failed: {}", e);
+            }
+        }
+    }
+
+    async fn post(&self, url: &str, body: &Value) -> Result<(), String> {
+        let response = self
+            .client
+            .post(url)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(format!("{url} returned {status}"))
+        }
+    }
+
+    /// Sends the pending email digest, if any.
+    pub async fn send_digest(&self) -> Result<usize, AppError> {
+        let mut sent = 0;
+        for sink in &self.sinks {
+            if let Sink::Email(digest) = sink {
+                sent += digest.send().await?;
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Watches `events` for notification-worthy changes and sends the email
+    /// digest every `digest_interval`.
+    pub fn start(
+        self: Arc<Self>,
+        events: SharedEventBus,
+        digest_interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut watch = EventWatch::new(self.large_transfer_amount);
+            let mut events = events.subscribe();
+            let mut ticker = tokio::time::interval(digest_interval);
+            ticker.tick().await;
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) => {
+                            if let Some(notification) = watch.observe(&event) {
+                                self.notify(notification).await;
+                            }
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Notifier lagged, {} events skipped", skipped);
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = ticker.tick() => {
+                        if let Err(e) = self.send_digest().await {
+                            warn!("Notification digest failed: {}", e);
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Derives notifications from gateway events, remembering just enough to
+/// report changes rather than states.
+struct EventWatch {
+    large_transfer_amount: Option<u64>,
+    backend_healthy: bool,
+    alerted_transfers: HashSet<String>,
+}
+
+impl EventWatch {
+    fn new(large_transfer_amount: Option<u64>) -> Self {
+        Self {
+            large_transfer_amount,
+            backend_healthy: true,
+            alerted_transfers: HashSet::new(),
+        }
+    }
+
+    fn observe(&mut self, event: &GatewayEvent) -> Option<Notification> {
+        match event.topic.as_str() {
+            TOPIC_SNAPSHOT => self.observe_backend(&event.data),
+            TOPIC_UPDATED => self.observe_transfer(&event.data),
+            TOPIC_FRAGMENTED => Self::observe_fragmentation(&event.data),
+            _ => None,
+        }
+    }
+
+    fn observe_backend(&mut self, snapshot: &Value) -> Option<Notification> {
+        let backend = snapshot.get("backend")?;
+        let healthy = backend.get("healthy")?.as_bool()?;
+        if healthy == self.backend_healthy {
+            return None;
+        }
+        self.backend_healthy = healthy;
+        let notification = if healthy {
+            Notification::new(
+                NotificationKind::BackendRecovered,
+                "tapd backend recovered".to_string(),
+                "The backend answers getinfo again".to_string(),
+                backend.clone(),
+            )
+        } else {
+            let error = backend
+                .get("error")
+                .and_then(Value::as_str)
+                .unwrap_or("no response");
+            Notification::new(
+                NotificationKind::BackendDown,
+                "tapd backend down".to_string(),
+                format!("getinfo failed: {error}"),
+                backend.clone(),
+            )
+        };
+        Some(notification)
+    }
+
+    fn observe_transfer(&mut self, transfer: &Value) -> Option<Notification> {
+        let threshold = self.large_transfer_amount?;
+        let amount = transfer.get("amount")?.as_u64()?;
+        if amount < threshold {
+            return None;
+        }
+        let id = transfer.get("id")?.as_str()?;
+        if self.alerted_transfers.len() >= MAX_REMEMBERED_TRANSFERS {
+            self.alerted_transfers.clear();
+        }
+        if !self.alerted_transfers.insert(id.to_string()) {
+            return None;
+        }
+        let kind = transfer
+            .get("kind")
+            .and_then(Value::as_str)
+            .unwrap_or("transfer");
+        let asset_id = transfer
+            .get("asset_id")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown asset");
+        Some(Notification::new(
+            NotificationKind::LargeTransfer,
+            format!("Large {kind}"),
+            format!("{kind} of {amount} units of {asset_id} (threshold {threshold})"),
+            transfer.clone(),
+        ))
+    }
+
+    /// The UTXO check publishes an asset only when it becomes fragmented,
+    /// so every event is worth a notification.
+    fn observe_fragmentation(summary: &Value) -> Option<Notification> {
+        let asset_id = summary.get("asset_id")?.as_str()?;
+        let alerts = summary
+            .get("alerts")?
+            .as_array()?
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join("; ");
+        Some(Notification::new(
+            NotificationKind::Fragmentation,
+            "Fragmented asset outputs".to_string(),
+            format!("{asset_id}: {alerts}"),
+            summary.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(topic: &str, data: Value) -> GatewayEvent {
+        GatewayEvent {
+            sequence: 0,
+            topic: topic.to_string(),
+            timestamp: 0,
+            data,
+        }
+    }
+
+    fn snapshot(healthy: bool) -> GatewayEvent {
+        event(
+            TOPIC_SNAPSHOT,
+            serde_json::json!({"backend": {"healthy": healthy, "error": "connection refused"}}),
+        )
+    }
+
+    #[test]
+    fn test_backend_transitions_notify_once() {
+        let mut watch = EventWatch::new(None);
+        assert!(watch.observe(&snapshot(true)).is_none());
+
+        let down = watch.observe(&snapshot(false)).unwrap();
+        assert_eq!(down.kind, NotificationKind::BackendDown);
+        assert!(down.message.contains("connection refused"));
+        assert!(watch.observe(&snapshot(false)).is_none());
+
+        let up = watch.observe(&snapshot(true)).unwrap();
+        assert_eq!(up.kind, NotificationKind::BackendRecovered);
+    }
+
+    #[test]
+    fn test_large_transfer_alerts_once_per_transfer() {
+        let mut watch = EventWatch::new(Some(1000));
+        let transfer = |id: &str, amount: u64| {
+            event(
+                TOPIC_UPDATED,
+                serde_json::json!({"id": id, "kind": "send", "asset_id": "aa", "amount": amount}),
+            )
+        };
+        assert!(watch.observe(&transfer("small", 999)).is_none());
+
+        let alert = watch.observe(&transfer("big", 1000)).unwrap();
+        assert_eq!(alert.kind, NotificationKind::LargeTransfer);
+        assert_eq!(alert.title, "Large send");
+        // Later status updates of the same transfer stay quiet
+        assert!(watch.observe(&transfer("big", 1000)).is_none());
+    }
+
+    #[test]
+    fn test_digest_subject_counts_notifications() {
+        let notification = Notification::new(
+            NotificationKind::BackendDown,
+            "tapd backend down".to_string(),
+            String::new(),
+            Value::Null,
+        );
+        assert_eq!(
+            digest_subject(std::slice::from_ref(&notification)),
+            "[taproot-assets gateway] tapd backend down"
+        );
+        assert_eq!(
+            digest_subject(&[notification.clone(), notification]),
+            "[taproot-assets gateway] 2 notifications"
+        );
+    }
+}
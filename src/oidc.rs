@@ -0,0 +1,378 @@
+//! Optional OpenID Connect authentication. Bearer JWTs issued by the
+//! operator's identity provider are accepted next to the gateway's own API
+//! keys, so access can be managed in the organisation's SSO.
+//!
+//! Signing keys come from the provider's JWKS, fetched on first use and
+//! again when a token names an unknown key or the set goes stale. A token's
+//! role claim is mapped onto gateway roles: [`ADMIN_ROLE`] grants what the
+//! primary API key grants, and any other role is read-only with that role's
+//! redaction profile, exactly like a `ROLE_API_KEYS` token.
+
+use crate::error::AppError;
+use jsonwebtoken::jwk::{AlgorithmParameters, Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Gateway role with the primary API key's access.
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Tokens naming an unknown key trigger at most one JWKS fetch per this
+/// interval, so garbage tokens cannot hammer the provider.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a valid token's role claim maps to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenRole {
+    Admin,
+    ReadOnly(String),
+}
+
+/// `OIDC_*` settings; present when `OIDC_ISSUER` is set.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct OidcSettings {
+    /// Expected `iss` claim.
+    pub issuer: String,
+    /// Expected `aud` claim.
+    pub audience: String,
+    /// Where the signing keys are; discovered from the issuer when unset.
+    pub jwks_url: Option<String>,
+    /// Dotted path to the claim holding the caller's roles.
+    pub role_claim: String,
+    /// Role claim values mapped to gateway roles.
+    pub role_map: HashMap<String, String>,
+    /// Seconds before the key set is fetched again.
+    pub jwks_refresh_secs: u64,
+}
+
+impl OidcSettings {
+    /// Parses `OIDC_ROLE_MAP` (`claim_value:role,claim_value:role`).
+    pub fn parse_role_map(value: &str) -> Result<HashMap<String, String>, AppError> {
+        let mut map = HashMap::new();
+        for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (claim, role) = entry
+                .rsplit_once(':')
+                .map(|(c, r)| (c.trim(), r.trim()))
+                .filter(|(c, r)| !c.is_empty() && !r.is_empty())
+                .ok_or_else(|| {
+                    AppError::ValidationError(
+                        "OIDC_ROLE_MAP entries must be claim_value:role".to_string(),
+                    )
+                })?;
+            if map.insert(claim.to_string(), role.to_string()).is_some() {
+                return Err(AppError::ValidationError(format!(
+                    "OIDC_ROLE_MAP maps '{claim}' twice"
+                )));
+            }
+        }
+        Ok(map)
+    }
+
+    /// Gateway roles tokens can be given, other than [`ADMIN_ROLE`].
+    pub fn read_only_roles(&self) -> impl Iterator<Item = &str> {
+        self.role_map
+            .values()
+            .map(String::as_str)
+            .filter(|role| *role != ADMIN_ROLE)
+    }
+}
+
+struct KeyCache {
+    keys: JwkSet,
+    fetched_at: Option<Instant>,
+    attempted_at: Option<Instant>,
+}
+
+pub struct OidcAuthenticator {
+    settings: OidcSettings,
+    client: Client,
+    cache: RwLock<KeyCache>,
+}
+
+pub type SharedOidcAuthenticator = Arc<OidcAuthenticator>;
+
+impl OidcAuthenticator {
+    /// `client` fetches the provider's documents and should verify TLS.
+    pub fn new(settings: OidcSettings, client: Client) -> Self {
+        Self {
+            settings,
+            client,
+            cache: RwLock::new(KeyCache {
+                keys: JwkSet { keys: Vec::new() },
+                fetched_at: None,
+                attempted_at: None,
+            }),
+        }
+    }
+
+    /// Validates `token`'s signature, issuer, audience and lifetime. `None`
+    /// is a valid token whose claims map to no gateway role.
+    pub async fn authenticate(&self, token: &str) -> Result<Option<TokenRole>, AppError> {
+        let header = jsonwebtoken::decode_header(token).map_err(invalid_token)?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(AppError::InvalidInput(
+                "Symmetric token algorithms are not accepted".to_string(),
+            ));
+        }
+
+        let kid = header.kid.as_deref();
+        if self.is_stale() || self.find_key(kid).is_none() {
+            self.refresh().await;
+        }
+        let jwk = self
+            .find_key(kid)
+            .ok_or_else(|| AppError::InvalidInput("Token signed by an unknown key".to_string()))?;
+        if jwk
+            .common
+            .key_algorithm
+            .is_some_and(|alg| alg.to_string() != format!("{:?}", header.alg))
+        {
+            return Err(AppError::InvalidInput(
+                "Token algorithm does not match its key".to_string(),
+            ));
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(invalid_token)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.settings.issuer]);
+        validation.set_audience(&[&self.settings.audience]);
+        validation.validate_nbf = true;
+        let claims = jsonwebtoken::decode::<Value>(token, &key, &validation)
+            .map_err(invalid_token)?
+            .claims;
+        Ok(self.role(&claims))
+    }
+
+    /// Maps the role claim, a string or an array of strings at a dotted
+    /// path, onto a gateway role; [`ADMIN_ROLE`] wins over any other.
+    fn role(&self, claims: &Value) -> Option<TokenRole> {
+        let claim = self
+            .settings
+            .role_claim
+            .split('.')
+            .try_fold(claims, |value, key| value.get(key))?;
+        let values: Vec<&str> = match claim {
+            Value::String(value) => vec![value.as_str()],
+            Value::Array(values) => values.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let roles: Vec<&String> = values
+            .into_iter()
+            .filter_map(|value| self.settings.role_map.get(value))
+            .collect();
+        if roles.iter().any(|role| *role == ADMIN_ROLE) {
+            return Some(TokenRole::Admin);
+        }
+        roles
+            .first()
+            .map(|role| TokenRole::ReadOnly(role.to_string()))
+    }
+
+    /// The verification key named `kid`; tokens without one use the set's
+    /// only key. Symmetric keys are never used.
+    fn find_key(&self, kid: Option<&str>) -> Option<Jwk> {
+        let cache = self.cache.read().unwrap_or_else(|e| e.into_inner());
+        let jwk = match kid {
+            Some(kid) => cache.keys.find(kid),
+            None if cache.keys.keys.len() == 1 => cache.keys.keys.first(),
+            None => None,
+        }?;
+        (!matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_))).then(|| jwk.clone())
+    }
+
+    fn is_stale(&self) -> bool {
+        let cache = self.cache.read().unwrap_or_else(|e| e.into_inner());
+        let max_age = Duration::from_secs(self.settings.jwks_refresh_secs);
+        cache
+            .fetched_at
+            .is_none_or(|fetched| fetched.elapsed() >= max_age)
+    }
+
+    /// Fetches the JWKS unless a fetch was tried within
+    /// [`MIN_REFRESH_INTERVAL`]. A failed fetch keeps the keys already held.
+    async fn refresh(&self) {
+        {
+            let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+            if cache
+                .attempted_at
+                .is_some_and(|at| at.elapsed() < MIN_REFRESH_INTERVAL)
+            {
+                return;
+            }
+            cache.attempted_at = Some(Instant::now());
+        }
+        match self.fetch_keys().await {
+            Ok(keys) => {
+                info!(
+                    "Loaded {} signing keys for {}",
+                    keys.keys.len(),
+                    self.settings.issuer
+                );
+                self.set_keys(keys);
+            }
+            Err(e) => warn!("Failed to refresh OIDC signing keys: {}", e),
+        }
+    }
+
+    fn set_keys(&self, keys: JwkSet) {
+        let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+        cache.keys = keys;
+        cache.fetched_at = Some(Instant::now());
+    }
+
+    /// `OIDC_JWKS_URL`, or the `jwks_uri` of the issuer's discovery
+    /// document.
+    async fn fetch_keys(&self) -> Result<JwkSet, AppError> {
+        let jwks_url = match &self.settings.jwks_url {
+            Some(url) => url.clone(),
+            None => {
+                let discovery = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.settings.issuer.trim_end_matches('/')
+                );
+                let document: Value = self.fetch_json(&discovery).await?;
+                document
+                    .get("jwks_uri")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .ok_or_else(|| {
+                        AppError::ServiceUnavailable(format!("{discovery} names no jwks_uri"))
+                    })?
+            }
+        };
+        debug!("Fetching OIDC signing keys from {}", jwks_url);
+        self.fetch_json(&jwks_url).await
+    }
+
+    async fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, AppError> {
+        let response = self
+            .client
+            .get(url)
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json().await?)
+    }
+}
+
+fn invalid_token(e: jsonwebtoken::errors::Error) -> AppError {
+    AppError::InvalidInput(format!("Invalid bearer token: {e}"))
+}
+
+/// Whether a bearer token is shaped like a JWT (three dot-separated parts)
+/// rather than an API key.
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.split('.').count() == 3 && !token.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    // The Ed25519 key from RFC 8037, appendix A
+    const SEED_HEX: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const PUBLIC_X: &str = "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo";
+
+    fn authenticator() -> OidcAuthenticator {
+        let settings = OidcSettings {
+            issuer: "https://sso.example".to_string(),
+            audience: "gateway".to_string(),
+            jwks_url: None,
+            role_claim: "realm_access.roles".to_string(),
+            role_map: OidcSettings::parse_role_map("gateway-admins:admin, auditors:auditor")
+                .unwrap(),
+            jwks_refresh_secs: 3600,
+        };
+        let oidc = OidcAuthenticator::new(settings, Client::new());
+        oidc.set_keys(
+            serde_json::from_value(json!({"keys": [{
+                "kty": "OKP", "crv": "Ed25519", "x": PUBLIC_X, "kid": "k1", "alg": "EdDSA"
+            }]}))
+            .unwrap(),
+        );
+        oidc
+    }
+
+    fn token(claims: Value) -> String {
+        // PKCS#8 v1 wrapping of the raw seed
+        let mut der = hex::decode("302e020100300506032b657004220420").unwrap();
+        der.extend(hex::decode(SEED_HEX).unwrap());
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some("k1".to_string());
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_ed_der(&der)).unwrap()
+    }
+
+    fn claims(roles: &[&str]) -> Value {
+        json!({
+            "iss": "https://sso.example",
+            "aud": "gateway",
+            "sub": "alice",
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "realm_access": {"roles": roles},
+        })
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_maps_role_claims() {
+        let oidc = authenticator();
+        let role = |roles: &[&str]| {
+            let token = token(claims(roles));
+            let oidc = &oidc;
+            async move { oidc.authenticate(&token).await.unwrap() }
+        };
+        assert_eq!(
+            role(&["auditors", "gateway-admins"]).await,
+            Some(TokenRole::Admin)
+        );
+        assert_eq!(
+            role(&["staff", "auditors"]).await,
+            Some(TokenRole::ReadOnly("auditor".to_string()))
+        );
+        assert_eq!(role(&["staff"]).await, None);
+        assert!(looks_like_jwt(&token(claims(&[]))));
+        assert!(!looks_like_jwt("plain-api-key"));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_rejects_bad_tokens() {
+        let oidc = authenticator();
+        let mut wrong_audience = claims(&["gateway-admins"]);
+        wrong_audience["aud"] = json!("other");
+        assert!(oidc.authenticate(&token(wrong_audience)).await.is_err());
+
+        let mut wrong_issuer = claims(&["gateway-admins"]);
+        wrong_issuer["iss"] = json!("https://evil.example");
+        assert!(oidc.authenticate(&token(wrong_issuer)).await.is_err());
+
+        let mut expired = claims(&["gateway-admins"]);
+        expired["exp"] = json!(chrono::Utc::now().timestamp() - 3600);
+        assert!(oidc.authenticate(&token(expired)).await.is_err());
+
+        let valid = token(claims(&["gateway-admins"]));
+        let (rest, signature) = valid.rsplit_once('.').unwrap();
+        let flipped = if signature.starts_with('A') { 'B' } else { 'A' };
+        let tampered = format!("{rest}.{flipped}{}", &signature[1..]);
+        assert!(oidc.authenticate(&tampered).await.is_err());
+
+        let hs256 = jsonwebtoken::encode(
+            &Header::default(),
+            &claims(&["gateway-admins"]),
+            &EncodingKey::from_secret(PUBLIC_X.as_bytes()),
+        )
+        .unwrap();
+        assert!(oidc.authenticate(&hs256).await.is_err());
+        assert!(OidcSettings::parse_role_map("admins").is_err());
+    }
+}
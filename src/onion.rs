@@ -0,0 +1,275 @@
+//! Publishes the gateway listener as a Tor onion service through an
+//! external tor's control port, so it can be reached without clearnet
+//! exposure.
+//!
+//! The service's key is kept in `TOR_ONION_KEY_PATH`, so the onion address
+//! survives restarts. The service is not detached: tor removes it when the
+//! control connection, held open for the gateway's lifetime, closes.
+
+use crate::config::Config;
+use crate::error::AppError;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+/// The published onion service, exposed in `getinfo`.
+#[derive(Debug, Clone)]
+pub struct OnionService {
+    address: String,
+}
+
+pub type SharedOnionService = Arc<OnionService>;
+
+impl OnionService {
+    /// `<service id>.onion:<port>`.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+/// Publishes the listener when `TOR_CONTROL_ADDR` is set.
+pub async fn publish(config: &Config) -> Result<Option<SharedOnionService>, AppError> {
+    let Some(control_addr) = &config.tor_control_addr else {
+        return Ok(None);
+    };
+    let target = local_target(&config.server_address);
+    let mut control = ControlConnection::connect(control_addr)
+        .await
+        .map_err(|e| tor_error(format!("Cannot reach tor control port {control_addr}: {e}")))?;
+    control
+        .authenticate(config.tor_control_password.as_deref())
+        .await?;
+
+    let key_path = Path::new(&config.tor_onion_key_path);
+    let stored_key = match std::fs::read_to_string(key_path) {
+        Ok(key) => Some(key.trim().to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(tor_error(format!("Cannot read TOR_ONION_KEY_PATH: {e}"))),
+    };
+    let (service_id, new_key) = control
+        .add_onion(stored_key.as_deref(), config.tor_onion_port, &target)
+        .await?;
+    if let Some(key) = new_key {
+        write_key(key_path, &key)
+            .map_err(|e| tor_error(format!("Cannot write TOR_ONION_KEY_PATH: {e}")))?;
+    }
+
+    let address = format!("{service_id}.onion:{}", config.tor_onion_port);
+    info!("Published onion service {} -> {}", address, target);
+    tokio::spawn(control.hold());
+    Ok(Some(Arc::new(OnionService { address })))
+}
+
+fn tor_error(message: String) -> AppError {
+    AppError::ServiceUnavailable(message)
+}
+
+/// Where tor should forward onion connections: the listener, with wildcard
+/// bind addresses replaced by loopback.
+fn local_target(server_address: &str) -> String {
+    match server_address.rsplit_once(':') {
+        Some(("0.0.0.0", port)) | Some(("[::]", port)) => format!("127.0.0.1:{port}"),
+        _ => server_address.to_string(),
+    }
+}
+
+fn write_key(path: &Path, key: &str) -> io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    io::Write::write_all(&mut options.open(path)?, key.as_bytes())
+}
+
+/// Minimal client for tor's line-based control protocol.
+struct ControlConnection {
+    stream: BufReader<TcpStream>,
+}
+
+impl ControlConnection {
+    async fn connect(addr: &str) -> io::Result<Self> {
+        Ok(Self {
+            stream: BufReader::new(TcpStream::connect(addr).await?),
+        })
+    }
+
+    /// Sends one command and returns its reply lines with the status code
+    /// and separator stripped. Non-250 replies are errors.
+    async fn command(&mut self, command: &str) -> Result<Vec<String>, AppError> {
+        let io_error = |e: io::Error| tor_error(format!("Tor control connection failed: {e}"));
+        self.stream
+            .get_mut()
+            .write_all(format!("{command}\r\n").as_bytes())
+            .await
+            .map_err(io_error)?;
+
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await.map_err(io_error)? == 0 {
+                return Err(tor_error("Tor closed the control connection".to_string()));
+            }
+            let line = line.trim_end();
+            if line.len() < 4 {
+                return Err(tor_error(format!("Malformed tor reply: {line}")));
+            }
+            let (code, rest) = line.split_at(3);
+            if code != "250" {
+                return Err(tor_error(format!("Tor refused the command: {line}")));
+            }
+            lines.push(rest[1..].to_string());
+            // "250 " ends the reply, "250-" continues it
+            if rest.starts_with(' ') {
+                return Ok(lines);
+            }
+        }
+    }
+
+    async fn authenticate(&mut self, password: Option<&str>) -> Result<(), AppError> {
+        let info = self.command("PROTOCOLINFO 1").await?;
+        let auth = info
+            .iter()
+            .find_map(|l| l.strip_prefix("AUTH "))
+            .unwrap_or_default();
+        let methods: Vec<&str> = auth
+            .split_whitespace()
+            .find_map(|f| f.strip_prefix("METHODS="))
+            .map(|m| m.split(',').collect())
+            .unwrap_or_default();
+
+        let command = if let Some(password) = password {
+            format!(
+                "AUTHENTICATE \"{}\"",
+                password.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        } else if methods.contains(&"NULL") {
+            "AUTHENTICATE".to_string()
+        } else if methods.contains(&"COOKIE") {
+            let cookie_file = auth
+                .split("COOKIEFILE=\"")
+                .nth(1)
+                .and_then(|rest| rest.split('"').next())
+                .ok_or_else(|| tor_error("Tor did not name its cookie file".to_string()))?;
+            let cookie = std::fs::read(cookie_file).map_err(|e| {
+                tor_error(format!("Cannot read tor cookie file {cookie_file}: {e}"))
+            })?;
+            format!("AUTHENTICATE {}", hex::encode(cookie))
+        } else {
+            return Err(tor_error(format!(
+                "Unsupported tor auth methods {methods:?}; set TOR_CONTROL_PASSWORD"
+            )));
+        };
+        self.command(&command).await.map(|_| ())
+    }
+
+    /// Returns the service id, and the private key when tor generated one.
+    async fn add_onion(
+        &mut self,
+        key: Option<&str>,
+        port: u16,
+        target: &str,
+    ) -> Result<(String, Option<String>), AppError> {
+        let command = match key {
+            Some(key) => format!("ADD_ONION {key} Flags=DiscardPK Port={port},{target}"),
+            None => format!("ADD_ONION NEW:ED25519-V3 Port={port},{target}"),
+        };
+        let reply = self.command(&command).await?;
+        let service_id = reply
+            .iter()
+            .find_map(|l| l.strip_prefix("ServiceID="))
+            .ok_or_else(|| tor_error("ADD_ONION reply lacks a ServiceID".to_string()))?
+            .to_string();
+        let new_key = reply
+            .iter()
+            .find_map(|l| l.strip_prefix("PrivateKey="))
+            .map(str::to_string);
+        Ok((service_id, new_key))
+    }
+
+    /// Keeps the connection, and with it the onion service, open.
+    async fn hold(mut self) {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.stream.read_line(&mut line).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+        }
+        warn!("Tor control connection closed; the onion service is no longer published");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Fake tor answering each expected command prefix with a canned reply.
+    async fn fake_tor(script: Vec<(&'static str, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            for (expected, reply) in script {
+                let mut line = String::new();
+                socket.read_line(&mut line).await.unwrap();
+                assert!(line.starts_with(expected), "unexpected command {line}");
+                socket.get_mut().write_all(reply.as_bytes()).await.unwrap();
+            }
+            // Hold the connection like tor does
+            let mut line = String::new();
+            let _ = socket.read_line(&mut line).await;
+        });
+        addr
+    }
+
+    #[test]
+    fn test_local_target_replaces_wildcards() {
+        assert_eq!(local_target("0.0.0.0:8080"), "127.0.0.1:8080");
+        assert_eq!(local_target("[::]:8080"), "127.0.0.1:8080");
+        assert_eq!(local_target("10.0.0.2:8080"), "10.0.0.2:8080");
+    }
+
+    #[tokio::test]
+    async fn test_add_onion_with_null_auth() {
+        let addr = fake_tor(vec![
+            (
+                "PROTOCOLINFO",
+                "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250-VERSION Tor=\"0.4.8\"\r\n250 OK\r\n",
+            ),
+            ("AUTHENTICATE\r\n", "250 OK\r\n"),
+            (
+                "ADD_ONION NEW:ED25519-V3 Port=80,127.0.0.1:8080",
+                "250-ServiceID=abcdef\r\n250-PrivateKey=ED25519-V3:c2VjcmV0\r\n250 OK\r\n",
+            ),
+        ])
+        .await;
+
+        let mut control = ControlConnection::connect(&addr).await.unwrap();
+        control.authenticate(None).await.unwrap();
+        let (id, key) = control.add_onion(None, 80, "127.0.0.1:8080").await.unwrap();
+        assert_eq!(id, "abcdef");
+        assert_eq!(key.as_deref(), Some("ED25519-V3:c2VjcmV0"));
+    }
+
+    #[tokio::test]
+    async fn test_refused_authentication_is_an_error() {
+        let addr = fake_tor(vec![
+            (
+                "PROTOCOLINFO",
+                "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=HASHEDPASSWORD\r\n250 OK\r\n",
+            ),
+            ("AUTHENTICATE \"wrong\"", "515 Authentication failed\r\n"),
+        ])
+        .await;
+
+        let mut control = ControlConnection::connect(&addr).await.unwrap();
+        let err = control.authenticate(Some("wrong")).await.unwrap_err();
+        assert!(err.to_string().contains("515"));
+    }
+}
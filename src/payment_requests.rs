@@ -0,0 +1,240 @@
+//! Invoice-like payment requests: each request gets a fresh address and is
+//! settled by summing the indexed receives to it. The tracker re-evaluates
+//! open requests whenever the indexer reports a change (and on a timer, so
+//! expiry happens without traffic), publishing `payment_request.<status>`
+//! events and queueing the request's webhook.
+
+use crate::database::{
+    ChainStatus, PaymentRequest, PaymentStatus, SharedDatabase, TransferKind, TransferQuery,
+    WebhookDelivery,
+};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::indexer::ReceivePolicy;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+/// Topic prefix for payment request events; the suffix is the new status.
+pub const TOPIC_PREFIX: &str = "payment_request.";
+
+/// How often open requests are re-evaluated without indexer activity
+const REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Upper bound on receives summed per request; far above any realistic
+/// number of payments to one address.
+const MAX_RECEIVES_PER_REQUEST: u32 = 1000;
+
+/// Status implied by the settled amount. A request that is fully paid stays
+/// paid even if the payment settled after expiry.
+pub fn evaluate(amount: u64, received: u64, expires_at: i64, now: i64) -> PaymentStatus {
+    match received {
+        r if r > amount => PaymentStatus::Overpaid,
+        r if r == amount => PaymentStatus::Paid,
+        _ if now >= expires_at => PaymentStatus::Expired,
+        0 => PaymentStatus::Pending,
+        _ => PaymentStatus::Partial,
+    }
+}
+
+#[derive(Clone)]
+pub struct PaymentRequestTracker {
+    database: SharedDatabase,
+    events: SharedEventBus,
+    policy: ReceivePolicy,
+}
+
+impl PaymentRequestTracker {
+    pub fn new(database: SharedDatabase, events: SharedEventBus, policy: ReceivePolicy) -> Self {
+        Self {
+            database,
+            events,
+            policy,
+        }
+    }
+
+    pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut events = self.events.subscribe();
+            let mut ticker = tokio::time::interval(REFRESH_INTERVAL);
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) if event.topic.starts_with("transfer.") => {}
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(_)) => {}
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = ticker.tick() => {}
+                }
+                if let Err(e) = self.refresh_open().await {
+                    warn!("Payment request refresh failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Re-evaluates every open request and returns how many changed.
+    pub async fn refresh_open(&self) -> Result<usize, AppError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut changed = 0;
+        for request in self.database.open_payment_requests(now).await? {
+            let before = (request.status, request.received, request.unconfirmed);
+            let after = self.refresh(request).await?;
+            if before != (after.status, after.received, after.unconfirmed) {
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Recomputes one request from the index, persisting and announcing any
+    /// change, and returns the up-to-date request.
+    pub async fn refresh(&self, mut request: PaymentRequest) -> Result<PaymentRequest, AppError> {
+        let receives = self
+            .database
+            .query_indexed_transfers(&TransferQuery {
+                kind: Some(TransferKind::Receive),
+                address: Some(request.address.clone()),
+                limit: Some(MAX_RECEIVES_PER_REQUEST),
+                ..Default::default()
+            })
+            .await?;
+
+        let (mut received, mut unconfirmed) = (0u64, 0u64);
+        for receive in &receives {
+            let amount = receive.amount.unwrap_or(0);
+            if self.policy.is_settled(receive) {
+                received += amount;
+            } else if receive.chain_status != Some(ChainStatus::Replaced) {
+                unconfirmed += amount;
+            }
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let status = evaluate(request.amount, received, request.expires_at, now);
+        if (status, received, unconfirmed)
+            == (request.status, request.received, request.unconfirmed)
+        {
+            return Ok(request);
+        }
+
+        let notify = status != request.status || received != request.received;
+        debug!(
+            "Payment request {} is now {} ({}/{})",
+            request.id,
+            status.as_str(),
+            received,
+            request.amount
+        );
+        self.database
+            .update_payment_request_progress(&request.id, status, received, unconfirmed)
+            .await?;
+        request.status = status;
+        request.received = received;
+        request.unconfirmed = unconfirmed;
+        request.updated_at = now;
+
+        let topic = format!("{TOPIC_PREFIX}{}", status.as_str());
+        let payload = serde_json::to_value(&request)?;
+        self.events.publish(&topic, payload.clone());
+
+        if let (true, Some(callback_url)) = (notify, request.callback_url.clone()) {
+            self.database
+                .enqueue_webhook(&WebhookDelivery::new(
+                    format!("{topic}:{}:{received}", request.id),
+                    &topic,
+                    callback_url,
+                    request.callback_secret.clone(),
+                    payload,
+                ))
+                .await?;
+        }
+
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{open_test_database, IndexedTransfer};
+    use crate::event_bus::EventBus;
+
+    #[test]
+    fn test_evaluate_covers_under_exact_and_over_payment() {
+        assert_eq!(evaluate(100, 0, 50, 10), PaymentStatus::Pending);
+        assert_eq!(evaluate(100, 40, 50, 10), PaymentStatus::Partial);
+        assert_eq!(evaluate(100, 100, 50, 10), PaymentStatus::Paid);
+        assert_eq!(evaluate(100, 150, 50, 10), PaymentStatus::Overpaid);
+        assert_eq!(evaluate(100, 40, 50, 50), PaymentStatus::Expired);
+        assert_eq!(evaluate(100, 100, 50, 60), PaymentStatus::Paid);
+    }
+
+    fn receive(id: &str, amount: u64, status: &str) -> IndexedTransfer {
+        IndexedTransfer {
+            id: id.to_string(),
+            kind: TransferKind::Receive,
+            asset_id: Some("aa".to_string()),
+            address: Some("taprt1pay".to_string()),
+            amount: Some(amount),
+            anchor_txid: None,
+            outpoint: None,
+            block_height: None,
+            status: status.to_string(),
+            timestamp: 1,
+            raw: serde_json::json!({}),
+            chain_status: None,
+            confirmations: None,
+            block_hash: None,
+            label: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_tracks_partial_payment_and_queues_webhook() {
+        let db = open_test_database().await;
+        let bus = Arc::new(EventBus::default());
+        let mut events = bus.subscribe();
+        let now = chrono::Utc::now().timestamp();
+        let request = PaymentRequest {
+            id: "pr1".to_string(),
+            asset_id: "aa".to_string(),
+            amount: 100,
+            address: "taprt1pay".to_string(),
+            status: PaymentStatus::Pending,
+            received: 0,
+            unconfirmed: 0,
+            expires_at: now + 3600,
+            callback_url: Some("https://shop.example/hook".to_string()),
+            callback_secret: None,
+            metadata: None,
+            created_at: now,
+            updated_at: now,
+        };
+        db.insert_payment_request(&request).await.unwrap();
+        db.upsert_indexed_transfers(&[
+            receive("r1", 40, "completed"),
+            receive("r2", 30, "transaction_detected"),
+        ])
+        .await
+        .unwrap();
+
+        let tracker = PaymentRequestTracker::new(db.clone(), bus, ReceivePolicy::new(1));
+        let refreshed = tracker.refresh(request).await.unwrap();
+        assert_eq!(refreshed.status, PaymentStatus::Partial);
+        assert_eq!(refreshed.received, 40);
+        assert_eq!(refreshed.unconfirmed, 30);
+        assert_eq!(
+            events.recv().await.unwrap().topic,
+            "payment_request.partial"
+        );
+        assert_eq!(db.due_webhooks(now + 1).await.unwrap().len(), 1);
+
+        // Nothing changed: no second event or webhook.
+        assert_eq!(tracker.refresh_open().await.unwrap(), 0);
+        assert_eq!(db.due_webhooks(now + 1).await.unwrap().len(), 1);
+    }
+}
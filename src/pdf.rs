@@ -0,0 +1,175 @@
+//! Minimal PDF writer for plain-text documents such as burn certificates:
+//! a bold title and Helvetica lines on A4 pages, without images, fonts to
+//! embed or compression, so no PDF library is needed. Characters outside
+//! printable ASCII are written as `?`.
+
+use std::fmt::Write;
+
+const PAGE_WIDTH: u32 = 595;
+const PAGE_HEIGHT: u32 = 842;
+const MARGIN: u32 = 50;
+const FONT_SIZE: u32 = 9;
+const LEADING: u32 = 12;
+/// Characters per line before wrapping; Helvetica averages about half the
+/// font size per character.
+const WRAP_AT: usize = 100;
+
+/// Renders `lines` under `title`, wrapping long lines and breaking pages as
+/// needed.
+pub fn text_document(title: &str, lines: &[String]) -> Vec<u8> {
+    let wrapped: Vec<String> = lines.iter().flat_map(|line| wrap(line)).collect();
+    let per_page = ((PAGE_HEIGHT - 2 * MARGIN - 2 * LEADING) / LEADING) as usize;
+    let pages: Vec<&[String]> = if wrapped.is_empty() {
+        vec![&[]]
+    } else {
+        wrapped.chunks(per_page).collect()
+    };
+
+    // Objects 1-4 are the catalog, page tree and fonts; each page then takes
+    // a page object and its content stream.
+    let page_ids: Vec<usize> = (0..pages.len()).map(|i| 5 + 2 * i).collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids
+                .iter()
+                .map(|id| format!("{id} 0 R"))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+    ];
+    for (index, page) in pages.iter().enumerate() {
+        let mut stream = String::from("BT\n");
+        let mut y = PAGE_HEIGHT - MARGIN;
+        if index == 0 {
+            let _ = writeln!(stream, "/F2 14 Tf {MARGIN} {y} Td ({}) Tj", escape(title));
+            y -= 2 * LEADING;
+            let _ = writeln!(stream, "/F1 {FONT_SIZE} Tf 0 {} Td", -(2 * LEADING as i64));
+        } else {
+            let _ = writeln!(stream, "/F1 {FONT_SIZE} Tf {MARGIN} {y} Td");
+        }
+        for line in page.iter() {
+            let _ = writeln!(stream, "({}) Tj 0 -{LEADING} Td", escape(line));
+        }
+        let _ = writeln!(
+            stream,
+            "/F1 8 Tf 0 -{} Td (Page {} of {}) Tj",
+            y.saturating_sub(MARGIN)
+                .saturating_sub(LEADING * page.len() as u32),
+            index + 1,
+            pages.len()
+        );
+        stream.push_str("ET");
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            page_ids[index] + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{stream}\nendstream",
+            stream.len()
+        ));
+    }
+    objects.push(format!(
+        "<< /Title ({}) /Producer (taproot-assets-rest-gateway) >>",
+        escape(title)
+    ));
+
+    let mut out = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        let _ = write!(out, "{} 0 obj\n{object}\nendobj\n", i + 1);
+    }
+    let xref = out.len();
+    let _ = write!(out, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(out, "{offset:010} 00000 n ");
+    }
+    let _ = write!(
+        out,
+        "trailer\n<< /Size {} /Root 1 0 R /Info {} 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1,
+        objects.len()
+    );
+    out.into_bytes()
+}
+
+/// Splits `line` into chunks of at most [`WRAP_AT`] characters, keeping its
+/// indentation on continuation lines.
+fn wrap(line: &str) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.len() <= WRAP_AT {
+        return vec![line.to_string()];
+    }
+    let indent = chars
+        .iter()
+        .take_while(|c| **c == ' ')
+        .count()
+        .min(WRAP_AT / 2);
+    let mut out = vec![chars[..WRAP_AT].iter().collect::<String>()];
+    let width = WRAP_AT - indent;
+    for chunk in chars[WRAP_AT..].chunks(width) {
+        out.push(format!(
+            "{}{}",
+            " ".repeat(indent),
+            chunk.iter().collect::<String>()
+        ));
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\\' | '(' | ')' => format!("\\{c}"),
+            ' '..='~' => c.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_has_valid_structure_and_breaks_pages() {
+        let lines: Vec<String> = (0..100).map(|i| format!("line {i} (x)")).collect();
+        let pdf = String::from_utf8(text_document("Proof of Burn", &lines)).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+        assert!(pdf.contains("/Count 2"));
+        assert!(pdf.contains("(line 99 \\(x\\)) Tj"));
+
+        // Every xref entry points at its object
+        let xref_at: usize = pdf
+            .rsplit("startxref\n")
+            .next()
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let offsets: Vec<usize> = pdf[xref_at..]
+            .lines()
+            .skip(3)
+            .take_while(|l| l.ends_with(" n "))
+            .map(|l| l[..10].parse().unwrap())
+            .collect();
+        assert_eq!(offsets.len(), 9);
+        for (i, offset) in offsets.iter().enumerate() {
+            assert!(pdf[*offset..].starts_with(&format!("{} 0 obj", i + 1)));
+        }
+
+        assert_eq!(wrap(&format!("  {}", "a".repeat(150))).len(), 2);
+        assert_eq!(escape("é"), "?");
+    }
+}
@@ -0,0 +1,368 @@
+//! Priority-aware concurrency limiting. At most `MAX_CONCURRENT_REQUESTS`
+//! requests run at once; the rest wait in one queue per [`PriorityClass`],
+//! and every freed slot goes to the oldest waiter of the highest class. Health
+//! checks and interactive reads therefore overtake bulk exports and syncs
+//! while the backend is saturated.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Request header a caller sets to pick its class.
+pub const PRIORITY_HEADER: &str = "x-priority";
+
+/// Always critical, whatever the header says: health checks, metrics and
+/// the admin API, so operators keep control of a saturated gateway.
+const CRITICAL_PATHS: &[&str] = &[
+    "/health",
+    "/readiness",
+    "/v1/gateway/metrics",
+    "/v1/gateway/admin/",
+];
+
+/// Bulk unless the caller says otherwise: exports, syncs and full listings.
+const BULK_PATHS: &[&str] = &[
+    "/v1/taproot-assets/universe/sync",
+    "/v1/taproot-assets/proofs/export",
+    "/v1/taproot-assets/wallet/backup/export",
+    "/v1/gateway/universe/leaves",
+    "/v1/gateway/transfers/query",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriorityClass {
+    Critical,
+    Interactive,
+    Bulk,
+}
+
+impl PriorityClass {
+    /// Highest first, the order waiters are served in.
+    pub const ALL: [PriorityClass; 3] = [
+        PriorityClass::Critical,
+        PriorityClass::Interactive,
+        PriorityClass::Bulk,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriorityClass::Critical => "critical",
+            PriorityClass::Interactive => "interactive",
+            PriorityClass::Bulk => "bulk",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// The class of a request to `path` with an optional priority header.
+    /// Callers may choose `interactive` or `bulk`; `critical` is reserved
+    /// for [`CRITICAL_PATHS`] and, like unknown values, is ignored.
+    pub fn classify(path: &str, header: Option<&str>) -> Self {
+        if CRITICAL_PATHS.iter().any(|p| path_matches(path, p)) {
+            return PriorityClass::Critical;
+        }
+        match header.map(|h| h.trim().to_ascii_lowercase()).as_deref() {
+            Some("interactive") => PriorityClass::Interactive,
+            Some("bulk") => PriorityClass::Bulk,
+            _ if BULK_PATHS.iter().any(|p| path_matches(path, p)) => PriorityClass::Bulk,
+            _ => PriorityClass::Interactive,
+        }
+    }
+}
+
+/// `prefix` ending in `/` matches everything under it; otherwise the path
+/// itself and its subpaths.
+fn path_matches(path: &str, prefix: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Why a request was not admitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriorityRejection {
+    /// Its class already had `PRIORITY_QUEUE_DEPTH` waiters.
+    QueueFull,
+    /// It waited `PRIORITY_QUEUE_TIMEOUT_SECS` without getting a slot.
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ClassStats {
+    /// Requests waiting now.
+    pub queued: usize,
+    /// Most requests ever waiting at once.
+    pub max_queued: usize,
+    pub admitted: u64,
+    /// Admitted requests that had to wait first.
+    pub waited: u64,
+    pub rejected: u64,
+    pub timed_out: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClassSnapshot {
+    pub class: PriorityClass,
+    #[serde(flatten)]
+    pub stats: ClassStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrioritySnapshot {
+    pub max_concurrent: usize,
+    pub in_flight: usize,
+    pub queue_depth: usize,
+    pub queue_timeout_secs: u64,
+    pub classes: Vec<ClassSnapshot>,
+}
+
+struct Waiter {
+    id: u64,
+    permit: oneshot::Sender<PriorityPermit>,
+}
+
+#[derive(Default)]
+struct State {
+    in_flight: usize,
+    next_id: u64,
+    queues: [VecDeque<Waiter>; 3],
+    stats: [ClassStats; 3],
+}
+
+pub struct PriorityLimiter {
+    max_concurrent: usize,
+    queue_depth: usize,
+    queue_timeout: Duration,
+    state: Mutex<State>,
+}
+
+pub type SharedPriorityLimiter = Arc<PriorityLimiter>;
+
+/// A running request's slot; dropping it hands the slot to the next waiter.
+pub struct PriorityPermit {
+    limiter: Option<SharedPriorityLimiter>,
+}
+
+impl Drop for PriorityPermit {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter.take() {
+            limiter.release();
+        }
+    }
+}
+
+impl PriorityLimiter {
+    pub fn new(max_concurrent: usize, queue_depth: usize, queue_timeout: Duration) -> Self {
+        Self {
+            max_concurrent,
+            queue_depth,
+            queue_timeout,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits for a slot for a request of `class`.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        class: PriorityClass,
+    ) -> Result<PriorityPermit, PriorityRejection> {
+        let i = class.index();
+        let (id, rx) = {
+            let mut state = self.lock();
+            if state.in_flight < self.max_concurrent {
+                state.in_flight += 1;
+                state.stats[i].admitted += 1;
+                return Ok(PriorityPermit {
+                    limiter: Some(self.clone()),
+                });
+            }
+            if state.queues[i].len() >= self.queue_depth {
+                state.stats[i].rejected += 1;
+                return Err(PriorityRejection::QueueFull);
+            }
+            let (tx, rx) = oneshot::channel();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.queues[i].push_back(Waiter { id, permit: tx });
+            let queued = state.queues[i].len();
+            let stats = &mut state.stats[i];
+            stats.queued = queued;
+            stats.max_queued = stats.max_queued.max(queued);
+            (id, rx)
+        };
+
+        // Leaves the queue if this request is dropped while waiting
+        let mut guard = QueuedGuard {
+            limiter: self,
+            class,
+            id,
+            done: false,
+        };
+        let result = tokio::time::timeout(self.queue_timeout, rx).await;
+        guard.done = true;
+        match result {
+            Ok(Ok(permit)) => {
+                let mut state = self.lock();
+                state.stats[i].admitted += 1;
+                state.stats[i].waited += 1;
+                Ok(permit)
+            }
+            // Timed out. A permit sent just as the wait ended was dropped with
+            // the channel, which passed the slot on.
+            _ => {
+                let mut state = self.lock();
+                state.queues[i].retain(|w| w.id != id);
+                state.stats[i].queued = state.queues[i].len();
+                state.stats[i].timed_out += 1;
+                Err(PriorityRejection::TimedOut)
+            }
+        }
+    }
+
+    /// Passes a freed slot to the highest waiting class, or frees it.
+    fn release(self: &Arc<Self>) {
+        let mut state = self.lock();
+        for class in PriorityClass::ALL {
+            let i = class.index();
+            while let Some(waiter) = state.queues[i].pop_front() {
+                state.stats[i].queued = state.queues[i].len();
+                let permit = PriorityPermit {
+                    limiter: Some(self.clone()),
+                };
+                match waiter.permit.send(permit) {
+                    Ok(()) => return,
+                    // The waiter gave up; take the slot back without
+                    // re-entering release
+                    Err(mut permit) => {
+                        permit.limiter = None;
+                    }
+                }
+            }
+        }
+        state.in_flight = state.in_flight.saturating_sub(1);
+    }
+
+    pub fn snapshot(&self) -> PrioritySnapshot {
+        let state = self.lock();
+        PrioritySnapshot {
+            max_concurrent: self.max_concurrent,
+            in_flight: state.in_flight,
+            queue_depth: self.queue_depth,
+            queue_timeout_secs: self.queue_timeout.as_secs(),
+            classes: PriorityClass::ALL
+                .iter()
+                .map(|class| ClassSnapshot {
+                    class: *class,
+                    stats: state.stats[class.index()],
+                })
+                .collect(),
+        }
+    }
+}
+
+struct QueuedGuard<'a> {
+    limiter: &'a PriorityLimiter,
+    class: PriorityClass,
+    id: u64,
+    done: bool,
+}
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let i = self.class.index();
+        let mut state = self.limiter.lock();
+        state.queues[i].retain(|w| w.id != self.id);
+        state.stats[i].queued = state.queues[i].len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_paths_and_header() {
+        assert_eq!(
+            PriorityClass::classify("/health", Some("bulk")),
+            PriorityClass::Critical
+        );
+        assert_eq!(
+            PriorityClass::classify("/v1/gateway/admin/maintenance", None),
+            PriorityClass::Critical
+        );
+        assert_eq!(
+            PriorityClass::classify("/v1/taproot-assets/proofs/export", None),
+            PriorityClass::Bulk
+        );
+        assert_eq!(
+            PriorityClass::classify("/v1/taproot-assets/proofs/export", Some("Interactive")),
+            PriorityClass::Interactive
+        );
+        assert_eq!(
+            PriorityClass::classify("/v1/taproot-assets/assets", Some("critical")),
+            PriorityClass::Interactive
+        );
+        assert_eq!(
+            PriorityClass::classify("/v1/taproot-assets/assets", Some("bulk")),
+            PriorityClass::Bulk
+        );
+        assert_eq!(
+            PriorityClass::classify("/healthz", None),
+            PriorityClass::Interactive
+        );
+    }
+
+    #[tokio::test]
+    async fn test_freed_slot_goes_to_highest_waiting_class() {
+        let limiter = Arc::new(PriorityLimiter::new(1, 1, Duration::from_secs(5)));
+        let running = limiter.acquire(PriorityClass::Bulk).await.unwrap();
+
+        let bulk = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(PriorityClass::Bulk).await.map(|_| ()) }
+        });
+        while limiter.snapshot().classes[2].stats.queued == 0 {
+            tokio::task::yield_now().await;
+        }
+        let interactive = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(PriorityClass::Interactive).await }
+        });
+        while limiter.snapshot().classes[1].stats.queued == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // A second bulk waiter exceeds the depth of 1
+        assert_eq!(
+            limiter.acquire(PriorityClass::Bulk).await.err(),
+            Some(PriorityRejection::QueueFull)
+        );
+
+        drop(running);
+        let permit = interactive.await.unwrap().unwrap();
+        assert!(!bulk.is_finished());
+        drop(permit);
+        bulk.await.unwrap().unwrap();
+
+        let snapshot = limiter.snapshot();
+        assert_eq!(snapshot.in_flight, 0);
+        let bulk_stats = snapshot.classes[2].stats;
+        assert_eq!(bulk_stats.admitted, 2);
+        assert_eq!(bulk_stats.waited, 1);
+        assert_eq!(bulk_stats.rejected, 1);
+        assert_eq!(snapshot.classes[1].stats.waited, 1);
+    }
+}
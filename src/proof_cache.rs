@@ -0,0 +1,145 @@
+//! Content-addressed cache of tapd's proof decode and verify responses,
+//! keyed by the SHA-256 of the raw proof. Repeated calls for the same proof
+//! are answered from the gateway database instead of sending multi-megabyte
+//! proof files to tapd again. Only successful responses are cached, and a
+//! failing cache never fails the request.
+
+use crate::database::Database;
+use crate::error::AppError;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+static STATS: ProofCacheStats = ProofCacheStats::new();
+
+/// Process-wide cache counters, exported on `/metrics` and in monitor
+/// snapshots.
+#[derive(Debug)]
+pub struct ProofCacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    errors: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ProofCacheStatsSnapshot {
+    pub hits: u64,
+    pub misses: u64,
+    /// Cache reads or writes that failed; the request went to tapd.
+    pub errors: u64,
+}
+
+impl ProofCacheStats {
+    const fn new() -> Self {
+        Self {
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> ProofCacheStatsSnapshot {
+        ProofCacheStatsSnapshot {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub fn stats() -> &'static ProofCacheStats {
+    &STATS
+}
+
+/// Hex SHA-256 of the raw proof as sent by the caller.
+pub fn proof_hash(raw_proof: &str) -> String {
+    hex::encode(Sha256::digest(raw_proof.as_bytes()))
+}
+
+/// Returns the cached response for `operation` on the proof, or runs
+/// `fetch` and caches its result. Without a database, or with a zero `ttl`,
+/// every call goes to tapd.
+pub async fn cached_or_fetch<F>(
+    database: Option<&Database>,
+    ttl: Duration,
+    raw_proof: &str,
+    operation: &str,
+    fetch: F,
+) -> Result<serde_json::Value, AppError>
+where
+    F: Future<Output = Result<serde_json::Value, AppError>>,
+{
+    let Some(database) = database.filter(|_| !ttl.is_zero()) else {
+        return fetch.await;
+    };
+    let hash = proof_hash(raw_proof);
+    let not_before = chrono::Utc::now().timestamp() - ttl.as_secs() as i64;
+
+    match database
+        .cached_proof_response(&hash, operation, not_before)
+        .await
+    {
+        Ok(Some(response)) => {
+            STATS.hits.fetch_add(1, Ordering::Relaxed);
+            debug!("Proof cache hit for {} ({})", hash, operation);
+            return Ok(response);
+        }
+        Ok(None) => {
+            STATS.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        Err(e) => {
+            STATS.errors.fetch_add(1, Ordering::Relaxed);
+            warn!("Proof cache read failed: {}", e);
+        }
+    }
+
+    let response = fetch.await?;
+    if let Err(e) = database
+        .store_proof_response(&hash, operation, &response, not_before)
+        .await
+    {
+        STATS.errors.fetch_add(1, Ordering::Relaxed);
+        warn!("Proof cache write failed: {}", e);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    #[tokio::test]
+    async fn test_second_call_served_from_cache() {
+        let db = open_test_database().await;
+        let ttl = Duration::from_secs(60);
+        let first = cached_or_fetch(Some(&db), ttl, "proof", "verify:g", async {
+            Ok(serde_json::json!({"valid": true}))
+        })
+        .await
+        .unwrap();
+        let second = cached_or_fetch(Some(&db), ttl, "proof", "verify:g", async {
+            Err(AppError::ServiceUnavailable(
+                "tapd should not be called".to_string(),
+            ))
+        })
+        .await
+        .unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_not_cached() {
+        let db = open_test_database().await;
+        let ttl = Duration::from_secs(60);
+        let failed = cached_or_fetch(Some(&db), ttl, "bad", "verify:g", async {
+            Err(AppError::InvalidInput("invalid proof".to_string()))
+        })
+        .await;
+        assert!(failed.is_err());
+        assert_eq!(db.proof_cache_entries().await.unwrap(), 0);
+    }
+}
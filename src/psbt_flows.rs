@@ -0,0 +1,226 @@
+//! Keeps concurrent virtual PSBT flows off each other's inputs. Every
+//! fund, sign, commit and anchor call reserves the anchor outpoints its
+//! PSBTs spend for one flow, so a second flow over the same outputs is
+//! refused (or held until the first releases them) with an error naming
+//! the flow in its way, instead of racing to a confusing tapd failure at
+//! sign or anchor time. Flows are named by the `X-Psbt-Flow-Id` header;
+//! fund responses carry the id the gateway assigned.
+
+use crate::config::Config;
+use crate::database::{FlowStage, PsbtReservation, SharedDatabase};
+use crate::error::AppError;
+use crate::types::Outpoint;
+use actix_web::{web, HttpRequest};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Request and response header naming a PSBT flow.
+pub const FLOW_ID_HEADER: &str = "X-Psbt-Flow-Id";
+
+/// How often a queued flow checks whether its inputs were released.
+const QUEUE_POLL: Duration = Duration::from_millis(500);
+
+/// `txid:vout` of a tapd `OutPoint`, whose txid is base64 in internal byte
+/// order.
+pub fn outpoint_key(outpoint: &Value) -> Option<String> {
+    Outpoint::from_any(outpoint)
+        .ok()
+        .map(|outpoint| outpoint.to_string())
+}
+
+/// Anchor outpoints the virtual PSBTs spend, deduplicated and sorted.
+pub fn psbt_outpoints(psbts: &[String]) -> Result<Vec<String>, AppError> {
+    let mut outpoints = BTreeSet::new();
+    for psbt in psbts {
+        for outpoint in crate::swaps::input_outpoints(psbt)? {
+            outpoints.insert(outpoint.to_string());
+        }
+    }
+    Ok(outpoints.into_iter().collect())
+}
+
+/// The flow `reservations` all belong to, when they belong to exactly one.
+fn sole_flow(reservations: &[PsbtReservation]) -> Option<&str> {
+    let first = reservations.first()?;
+    reservations
+        .iter()
+        .all(|r| r.flow_id == first.flow_id)
+        .then_some(first.flow_id.as_str())
+}
+
+fn conflict(flow_id: &str, conflicts: &[PsbtReservation]) -> AppError {
+    let held: Vec<String> = conflicts
+        .iter()
+        .map(|r| {
+            format!(
+                "{} ({} by flow {})",
+                r.outpoint,
+                r.stage.as_str(),
+                r.flow_id
+            )
+        })
+        .collect();
+    AppError::Conflict(format!(
+        "PSBT flow {flow_id} spends outputs another flow holds: {}",
+        held.join(", ")
+    ))
+}
+
+pub struct PsbtFlowGuard {
+    database: SharedDatabase,
+    ttl_secs: u64,
+    queue_secs: u64,
+}
+
+impl PsbtFlowGuard {
+    pub fn new(database: SharedDatabase, ttl_secs: u64, queue_secs: u64) -> Self {
+        Self {
+            database,
+            ttl_secs,
+            queue_secs,
+        }
+    }
+
+    /// The guard for `req`'s gateway; `None` unless PSBT flow tracking is
+    /// enabled and a database is configured.
+    pub fn from_request(req: &HttpRequest) -> Option<Self> {
+        let config = req.app_data::<web::Data<Config>>()?;
+        if !config.psbt_flow_guard_enabled {
+            return None;
+        }
+        let database = req.app_data::<web::Data<SharedDatabase>>()?;
+        Some(Self::new(
+            database.get_ref().clone(),
+            config.psbt_flow_ttl_secs,
+            config.psbt_flow_queue_secs,
+        ))
+    }
+
+    /// The flow `req` names, if any.
+    pub fn requested_flow(req: &HttpRequest) -> Option<String> {
+        req.headers()
+            .get(FLOW_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    }
+
+    /// Reserves `outpoints` for `flow_id` at `stage` and returns the flow's
+    /// id. Without one, the flow already holding all of them continues, or
+    /// a new flow starts. Outputs another flow holds are waited on for up
+    /// to the configured queue time, except spent ones which never free up.
+    pub async fn claim(
+        &self,
+        flow_id: Option<&str>,
+        outpoints: &[String],
+        stage: FlowStage,
+    ) -> Result<String, AppError> {
+        let now = chrono::Utc::now().timestamp();
+        let held = self.database.psbt_reservations_for(outpoints, now).await?;
+        if let Some(spent) = held.iter().find(|r| r.stage == FlowStage::Anchored) {
+            return Err(AppError::Conflict(format!(
+                "Output {} was already spent by PSBT flow {}",
+                spent.outpoint, spent.flow_id
+            )));
+        }
+        let flow_id = match flow_id {
+            Some(id) => id.to_string(),
+            None if held.len() == outpoints.len() => sole_flow(&held)
+                .map(str::to_string)
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            None => uuid::Uuid::new_v4().to_string(),
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(self.queue_secs);
+        loop {
+            let now = chrono::Utc::now().timestamp();
+            let conflicts = self
+                .database
+                .reserve_psbt_outpoints(&flow_id, outpoints, stage, now, self.expiry(now))
+                .await?;
+            if conflicts.is_empty() {
+                return Ok(flow_id);
+            }
+            if Instant::now() >= deadline
+                || conflicts.iter().any(|r| r.stage == FlowStage::Anchored)
+            {
+                return Err(conflict(&flow_id, &conflicts));
+            }
+            info!(
+                "PSBT flow {} queued behind {}",
+                flow_id, conflicts[0].flow_id
+            );
+            tokio::time::sleep(QUEUE_POLL).await;
+        }
+    }
+
+    /// Moves the flow to `stage`, extending its reservations.
+    pub async fn advance(&self, flow_id: &str, stage: FlowStage) -> Result<(), AppError> {
+        let now = chrono::Utc::now().timestamp();
+        self.database
+            .advance_psbt_flow(flow_id, stage, self.expiry(now))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn release(&self, flow_id: &str) -> Result<u64, AppError> {
+        self.database.release_psbt_flow(flow_id).await
+    }
+
+    pub async fn release_outpoint(&self, outpoint: &str) -> Result<bool, AppError> {
+        self.database.release_psbt_outpoint(outpoint).await
+    }
+
+    fn expiry(&self, now: i64) -> i64 {
+        now.saturating_add(self.ttl_secs as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use serde_json::json;
+
+    #[test]
+    fn test_outpoint_key_reads_internal_byte_order() {
+        let mut txid = [0u8; 32];
+        txid[0] = 1;
+        let outpoint = json!({ "txid": BASE64.encode(txid), "output_index": 2 });
+        assert_eq!(
+            outpoint_key(&outpoint).unwrap(),
+            format!("{}01:2", "00".repeat(31))
+        );
+        assert!(outpoint_key(&json!({ "txid": "bad", "output_index": 2 })).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_claim_continues_the_holding_flow_and_refuses_others() {
+        let guard = PsbtFlowGuard::new(open_test_database().await, 600, 0);
+        let inputs = vec!["aa:0".to_string(), "bb:1".to_string()];
+        let flow = guard.claim(None, &inputs, FlowStage::Funded).await.unwrap();
+
+        // Signing the same inputs without naming the flow continues it
+        let signed = guard.claim(None, &inputs, FlowStage::Signed).await.unwrap();
+        assert_eq!(signed, flow);
+
+        let err = guard
+            .claim(Some("other"), &inputs[1..], FlowStage::Funded)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::Conflict(ref m) if m.contains(&flow)));
+
+        guard.advance(&flow, FlowStage::Anchored).await.unwrap();
+        assert!(guard.claim(None, &inputs, FlowStage::Signed).await.is_err());
+        assert_eq!(guard.release(&flow).await.unwrap(), 2);
+        assert!(guard
+            .claim(Some("other"), &inputs[1..], FlowStage::Funded)
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,146 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The sliding window every limit counts requests over.
+pub const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Distinct clients tracked at once; new clients beyond it are refused until
+/// idle ones age out.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// A client's bucket, as sent in the `X-RateLimit-*` headers and by the
+/// rate limit endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RateLimitStatus {
+    pub limit: usize,
+    pub remaining: usize,
+    /// Seconds until the oldest request in the window expires and frees a
+    /// slot; 0 when the window is empty.
+    pub reset_secs: u64,
+    pub window_secs: u64,
+}
+
+impl RateLimitStatus {
+    /// Seconds a refused client should wait before retrying.
+    pub fn retry_after_secs(&self) -> u64 {
+        self.reset_secs.max(1)
+    }
+}
+
+/// Request timestamps per client over the last [`RATE_LIMIT_WINDOW`]. One
+/// instance is shared by every worker, so the limit applies per process
+/// rather than per worker and the reported bucket matches enforcement.
+#[derive(Debug)]
+pub struct RateLimits {
+    requests_per_minute: usize,
+    clients: Mutex<HashMap<String, Vec<Instant>>>,
+    last_cleanup: Mutex<Instant>,
+}
+
+pub type SharedRateLimits = Arc<RateLimits>;
+
+impl RateLimits {
+    pub fn new(requests_per_minute: usize) -> Self {
+        Self {
+            requests_per_minute,
+            clients: Mutex::new(HashMap::new()),
+            last_cleanup: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Counts a request from `client` if its bucket has room. Returns the
+    /// bucket after the request, or `Err` with the full bucket when refused.
+    pub fn check(&self, client: &str, now: Instant) -> Result<RateLimitStatus, RateLimitStatus> {
+        self.cleanup(now);
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if !clients.contains_key(client) && clients.len() >= MAX_TRACKED_CLIENTS {
+            return Err(RateLimitStatus {
+                limit: self.requests_per_minute,
+                remaining: 0,
+                reset_secs: RATE_LIMIT_WINDOW.as_secs(),
+                window_secs: RATE_LIMIT_WINDOW.as_secs(),
+            });
+        }
+        let timestamps = clients.entry(client.to_string()).or_default();
+        timestamps.retain(|t| now.duration_since(*t) < RATE_LIMIT_WINDOW);
+        if timestamps.len() >= self.requests_per_minute {
+            return Err(self.bucket(timestamps, now));
+        }
+        timestamps.push(now);
+        Ok(self.bucket(timestamps, now))
+    }
+
+    /// `client`'s bucket without counting a request.
+    pub fn status(&self, client: &str, now: Instant) -> RateLimitStatus {
+        let clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let mut timestamps = clients.get(client).cloned().unwrap_or_default();
+        timestamps.retain(|t| now.duration_since(*t) < RATE_LIMIT_WINDOW);
+        self.bucket(&timestamps, now)
+    }
+
+    fn bucket(&self, timestamps: &[Instant], now: Instant) -> RateLimitStatus {
+        let reset_secs = timestamps
+            .first()
+            .map(|oldest| {
+                let left = RATE_LIMIT_WINDOW.saturating_sub(now.duration_since(*oldest));
+                // Rounded up, so a client waiting this long always gets a slot
+                left.as_secs() + u64::from(left.subsec_nanos() > 0)
+            })
+            .unwrap_or(0);
+        RateLimitStatus {
+            limit: self.requests_per_minute,
+            remaining: self.requests_per_minute.saturating_sub(timestamps.len()),
+            reset_secs,
+            window_secs: RATE_LIMIT_WINDOW.as_secs(),
+        }
+    }
+
+    /// Drops clients idle for a whole window, at most once per window.
+    fn cleanup(&self, now: Instant) {
+        let mut last_cleanup = self.last_cleanup.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(*last_cleanup) <= RATE_LIMIT_WINDOW {
+            return;
+        }
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.retain(|_, timestamps| {
+            timestamps.retain(|t| now.duration_since(*t) < RATE_LIMIT_WINDOW);
+            !timestamps.is_empty()
+        });
+        *last_cleanup = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_counts_down_and_resets_with_window() {
+        let limits = RateLimits::new(2);
+        let start = Instant::now();
+
+        let status = limits.check("10.0.0.1", start).unwrap();
+        assert_eq!(status.remaining, 1);
+        assert_eq!(status.reset_secs, 60);
+        let status = limits
+            .check("10.0.0.1", start + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(status.remaining, 0);
+
+        let refused = limits
+            .check("10.0.0.1", start + Duration::from_secs(20))
+            .unwrap_err();
+        assert_eq!(refused.reset_secs, 40);
+        assert_eq!(refused.retry_after_secs(), 40);
+
+        // Peeking does not count, and other clients have their own bucket
+        let peek = limits.status("10.0.0.1", start + Duration::from_secs(61));
+        assert_eq!(peek.remaining, 1);
+        assert_eq!(limits.status("10.0.0.2", start).remaining, 2);
+        assert!(limits
+            .check("10.0.0.1", start + Duration::from_secs(61))
+            .is_ok());
+    }
+}
@@ -0,0 +1,109 @@
+//! Rotation of the public keys mailbox receivers authenticate with, for
+//! recovering from a suspected key compromise without changing the
+//! receiver id, so messages keep arriving in the same mailbox. The new key
+//! takes over at once; the old one keeps authenticating, deprecated, for an
+//! overlap window so a receiver that has not switched yet is not locked
+//! out. The window can be cut short once it has. The receiver is told
+//! through its own mailbox.
+
+use crate::api::mailbox::SendRequest;
+use crate::database::KeyRotation;
+use crate::error::AppError;
+use base64::Engine;
+use secp256k1::{PublicKey, XOnlyPublicKey};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// Longest overlap a rotation can ask for.
+pub const MAX_OVERLAP_SECS: u64 = 30 * 86_400;
+
+/// `type` of the notice sent to the receiver's mailbox.
+pub const NOTICE_TYPE: &str = "receiver_key_rotated";
+
+const MAX_REASON_CHARS: usize = 256;
+
+/// Accepts the key formats mailbox authentication verifies against: a
+/// compressed key for ECDSA or an x-only key for Schnorr signatures.
+pub fn validate_public_key(public_key: &str) -> Result<(), AppError> {
+    let valid = match public_key.len() {
+        66 => PublicKey::from_str(public_key).is_ok(),
+        64 => XOnlyPublicKey::from_str(public_key).is_ok(),
+        _ => false,
+    };
+    if !valid {
+        return Err(AppError::ValidationError(
+            "new_public_key must be a hex compressed (33-byte) or x-only (32-byte) key".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_reason(reason: Option<&str>) -> Result<(), AppError> {
+    if reason.is_some_and(|r| r.chars().count() > MAX_REASON_CHARS) {
+        return Err(AppError::ValidationError(format!(
+            "reason must be at most {MAX_REASON_CHARS} characters"
+        )));
+    }
+    Ok(())
+}
+
+/// What the receiver is told about `rotation`.
+pub fn rotation_notice(rotation: &KeyRotation) -> Value {
+    json!({
+        "type": NOTICE_TYPE,
+        "receiver_id": rotation.receiver_id,
+        "new_public_key": rotation.new_public_key,
+        "old_public_key": rotation.old_public_key,
+        "old_key_valid_until": rotation.deprecated_until,
+        "rotated_at": rotation.rotated_at,
+        "reason": rotation.reason,
+    })
+}
+
+/// The mailbox message carrying the notice. The gateway holds no key to
+/// encrypt to, so the payload is the notice's JSON; it carries nothing
+/// secret.
+pub fn notice_request(rotation: &KeyRotation) -> SendRequest {
+    SendRequest {
+        receiver_id: rotation.receiver_id.clone(),
+        encrypted_payload: base64::engine::general_purpose::STANDARD
+            .encode(rotation_notice(rotation).to_string()),
+        tx_proof: None,
+        expiry_block_height: None,
+        callback_url: None,
+        callback_secret: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_and_notice() {
+        let compressed = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        assert!(validate_public_key(compressed).is_ok());
+        assert!(validate_public_key(&compressed[2..]).is_ok());
+        assert!(validate_public_key(&format!("04{}", &compressed[2..])).is_err());
+        assert!(validate_public_key("unknown_receiver").is_err());
+        assert!(validate_reason(Some(&"x".repeat(257))).is_err());
+
+        let rotation = KeyRotation {
+            id: "r1".to_string(),
+            receiver_id: "receiver-1".to_string(),
+            old_public_key: "k1".to_string(),
+            new_public_key: compressed.to_string(),
+            reason: None,
+            rotated_at: 100,
+            deprecated_until: 200,
+        };
+        let request = notice_request(&rotation);
+        assert_eq!(request.receiver_id, "receiver-1");
+        let payload = base64::engine::general_purpose::STANDARD
+            .decode(request.encrypted_payload)
+            .unwrap();
+        let notice: Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(notice["type"], NOTICE_TYPE);
+        assert_eq!(notice["old_key_valid_until"], 200);
+    }
+}
@@ -0,0 +1,434 @@
+//! Recurring asset payments run by the scheduler. Each payment pays a fixed
+//! amount of one asset on a cadence, to a static address or to one fetched
+//! from the payer's address generator for every run.
+//!
+//! A run over the payment's spend limit, to a destination the destination
+//! guard flags, or flagged by an escalating anomaly rule is not sent but
+//! held as `awaiting_approval` until approved through the API. Missed runs
+//! are not caught up: after a run the next one is scheduled from now.
+
+use crate::anomaly::{self, AnomalyRules, SendCandidate};
+use crate::api::addresses::{decode_address, DecodeAddrRequest};
+use crate::api::send::{index_send, send_assets, SendRequest};
+use crate::database::{RecurringPayment, RecurringRun, RecurringStatus, RunStatus, SharedDatabase};
+use crate::destination_guard::{DestinationGuard, GuardMode};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::indexer::normalize_hex_id;
+use crate::scheduler::Schedule;
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{info, warn};
+
+/// Topic prefix for run events; the suffix is the run's status.
+pub const TOPIC_PREFIX: &str = "recurring_payment.";
+
+/// When a payment on `schedule` runs next after `now`, as a unix timestamp.
+pub fn next_run_after(schedule: &str, now: DateTime<Utc>) -> Result<Option<i64>, AppError> {
+    let schedule = Schedule::parse(schedule)?;
+    Ok(schedule.next_after(now).map(|t| t.timestamp()))
+}
+
+/// Whether sending `amount` more keeps a payment within its spend limit,
+/// given what it `spent` in the current window. Payments without a limit
+/// are always within it.
+pub fn within_limit(payment: &RecurringPayment, spent: u64, amount: u64) -> bool {
+    payment
+        .spend_limit_amount
+        .is_none_or(|limit| spent.saturating_add(amount) <= limit)
+}
+
+/// Problems with a decoded destination that must fail the run outright:
+/// it is for another asset or another amount.
+pub fn mismatch(
+    payment: &RecurringPayment,
+    asset_id: Option<&str>,
+    amount: Option<&str>,
+) -> Option<String> {
+    let asset_id = asset_id.map(normalize_hex_id);
+    if asset_id.as_deref() != Some(payment.asset_id.as_str()) {
+        return Some(format!(
+            "address is for asset {}, not {}",
+            asset_id.as_deref().unwrap_or("unknown"),
+            payment.asset_id
+        ));
+    }
+    if amount.and_then(|a| a.parse::<u64>().ok()) != Some(payment.amount) {
+        return Some(format!(
+            "address is for {} units, not {}",
+            amount.unwrap_or("unknown"),
+            payment.amount
+        ));
+    }
+    None
+}
+
+#[derive(Debug, Deserialize)]
+struct GeneratedAddress {
+    address: String,
+}
+
+pub struct RecurringPayer {
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+    database: SharedDatabase,
+    events: SharedEventBus,
+    guard: DestinationGuard,
+    rules: AnomalyRules,
+    generator: Option<Client>,
+}
+
+impl RecurringPayer {
+    pub fn new(
+        client: Client,
+        base_url: String,
+        macaroon_hex: String,
+        database: SharedDatabase,
+        events: SharedEventBus,
+        guard: DestinationGuard,
+        rules: AnomalyRules,
+    ) -> Self {
+        Self {
+            client,
+            base_url,
+            macaroon_hex,
+            database,
+            events,
+            guard,
+            rules,
+            generator: None,
+        }
+    }
+
+    /// Client for asking address generators; they are payee endpoints, so
+    /// unlike the tapd client it should verify TLS. Without one, payments
+    /// with an `address_url` fail their runs.
+    pub fn with_generator(mut self, generator: Client) -> Self {
+        self.generator = Some(generator);
+        self
+    }
+
+    /// Runs every due payment once. Returns how many runs were made.
+    pub async fn run_due(&self) -> Result<usize, AppError> {
+        let now = Utc::now();
+        let due = self
+            .database
+            .due_recurring_payments(now.timestamp())
+            .await?;
+        let mut ran = 0;
+        for payment in due {
+            match self.run(payment, now).await {
+                Ok(true) => ran += 1,
+                Ok(false) => {}
+                Err(e) => warn!("Recurring payment run failed: {}", e),
+            }
+        }
+        Ok(ran)
+    }
+
+    /// Makes one run of `payment`. The payment is rescheduled before anything
+    /// is sent, so a cancelled payment never pays and a crash mid-run never
+    /// pays twice. Returns false when the payment was cancelled meanwhile.
+    async fn run(
+        &self,
+        mut payment: RecurringPayment,
+        now: DateTime<Utc>,
+    ) -> Result<bool, AppError> {
+        payment.runs += 1;
+        payment.updated_at = now.timestamp();
+        match next_run_after(&payment.schedule, now)? {
+            Some(next) => payment.next_run_at = next,
+            None => payment.status = RecurringStatus::Completed,
+        }
+        if !self
+            .database
+            .advance_recurring_payment(&payment, RecurringStatus::Active)
+            .await?
+        {
+            return Ok(false);
+        }
+
+        let mut run = RecurringRun {
+            id: uuid::Uuid::new_v4().to_string(),
+            payment_id: payment.id.clone(),
+            sequence: payment.runs,
+            address: None,
+            amount: payment.amount,
+            status: RunStatus::Failed,
+            reason: None,
+            anchor_txid: None,
+            created_at: now.timestamp(),
+            updated_at: now.timestamp(),
+        };
+        match self.prepare(&payment, &mut run).await {
+            Ok(flags) if flags.is_empty() => {
+                let address = run.address.clone().unwrap_or_default();
+                self.send(&payment, &mut run, address).await;
+            }
+            Ok(flags) => {
+                run.status = RunStatus::AwaitingApproval;
+                run.reason = Some(flags.join("; "));
+            }
+            Err(e) => run.reason = Some(e.to_string()),
+        }
+        self.database.insert_recurring_run(&run).await?;
+        if run.status == RunStatus::Sent {
+            self.database
+                .record_recurring_payment_sent(&payment.id)
+                .await?;
+        }
+        info!(
+            "Recurring payment {} run {}: {}",
+            payment.id,
+            run.sequence,
+            run.status.as_str()
+        );
+        self.announce(&run);
+        Ok(true)
+    }
+
+    /// Sends a run held for approval, without checking it again.
+    pub async fn approve(&self, payment_id: &str, run_id: &str) -> Result<RecurringRun, AppError> {
+        let payment = self
+            .database
+            .get_recurring_payment(payment_id)
+            .await?
+            .ok_or_else(|| {
+                AppError::NotFound(format!("Recurring payment {payment_id} not found"))
+            })?;
+        let mut run = self
+            .database
+            .get_recurring_run(payment_id, run_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Run {run_id} not found")))?;
+        if payment.status == RecurringStatus::Cancelled {
+            return Err(AppError::ValidationError(format!(
+                "Recurring payment {payment_id} is cancelled"
+            )));
+        }
+        if run.status != RunStatus::AwaitingApproval {
+            return Err(AppError::ValidationError(format!(
+                "Run {run_id} is {}, not awaiting approval",
+                run.status.as_str()
+            )));
+        }
+        let Some(address) = run.address.clone() else {
+            return Err(AppError::ValidationError(format!(
+                "Run {run_id} has no destination to send to"
+            )));
+        };
+
+        // Claim the run first, so two approvals cannot both send
+        run.status = RunStatus::Sent;
+        run.updated_at = Utc::now().timestamp();
+        if !self
+            .database
+            .advance_recurring_run(&run, RunStatus::AwaitingApproval)
+            .await?
+        {
+            return Err(AppError::ValidationError(format!(
+                "Run {run_id} is no longer awaiting approval"
+            )));
+        }
+        self.send(&payment, &mut run, address).await;
+        run.updated_at = Utc::now().timestamp();
+        self.database
+            .advance_recurring_run(&run, RunStatus::Sent)
+            .await?;
+        if run.status == RunStatus::Sent {
+            self.database
+                .record_recurring_payment_sent(&payment.id)
+                .await?;
+        }
+        self.announce(&run);
+        Ok(run)
+    }
+
+    /// Resolves and checks the run's destination. Returns why the run must
+    /// be held for approval, empty when it can be sent; errors fail the run.
+    async fn prepare(
+        &self,
+        payment: &RecurringPayment,
+        run: &mut RecurringRun,
+    ) -> Result<Vec<String>, AppError> {
+        let address = self.destination(payment, run.sequence).await?;
+        run.address = Some(address.clone());
+
+        let request = DecodeAddrRequest {
+            addr: address.clone(),
+        };
+        let decoded =
+            decode_address(&self.client, &self.base_url, &self.macaroon_hex, request).await?;
+        if let Some(problem) = mismatch(
+            payment,
+            decoded.asset_id.as_deref(),
+            decoded.amount.as_deref(),
+        ) {
+            return Err(AppError::ValidationError(problem));
+        }
+
+        let mut flags = Vec::new();
+        if let Some(window) = payment.spend_limit_window_secs {
+            let since = run.created_at - window as i64;
+            let spent = self
+                .database
+                .recurring_spent_since(&payment.id, since)
+                .await?;
+            if !within_limit(payment, spent, payment.amount) {
+                flags.push(format!(
+                    "{} units would exceed the spend limit of {} per {window}s ({spent} sent)",
+                    payment.amount,
+                    payment.spend_limit_amount.unwrap_or_default()
+                ));
+            }
+        }
+
+        let problems = self.guard.assess(&address, &decoded);
+        match self.guard.mode() {
+            GuardMode::Off => {}
+            GuardMode::Override => flags.extend(problems),
+            GuardMode::Refuse => self.guard.enforce(&address, &problems, false)?,
+        }
+
+        if self
+            .rules
+            .rule_for(&payment.asset_id)
+            .is_some_and(|rule| rule.escalate)
+        {
+            let candidate = SendCandidate {
+                id: None,
+                asset_id: &payment.asset_id,
+                address: Some(&address),
+                amount: payment.amount,
+                timestamp: run.created_at,
+            };
+            let anomalies = anomaly::assess(&self.rules, &self.database, &candidate).await?;
+            flags.extend(anomalies.into_iter().map(|a| a.message));
+        }
+        Ok(flags)
+    }
+
+    /// The payment's static address, or a fresh one from its generator.
+    async fn destination(
+        &self,
+        payment: &RecurringPayment,
+        sequence: u64,
+    ) -> Result<String, AppError> {
+        if let Some(address) = &payment.address {
+            return Ok(address.clone());
+        }
+        let (Some(url), Some(generator)) = (&payment.address_url, &self.generator) else {
+            return Err(AppError::ServiceUnavailable(
+                "No address generator available".to_string(),
+            ));
+        };
+        let response = generator
+            .post(url)
+            .json(&json!({
+                "recurring_payment_id": payment.id,
+                "sequence": sequence,
+                "asset_id": payment.asset_id,
+                "amount": payment.amount,
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        let generated: GeneratedAddress = response.json().await?;
+        Ok(generated.address)
+    }
+
+    /// Sends the run to `address`, recording the outcome on `run`.
+    async fn send(&self, payment: &RecurringPayment, run: &mut RecurringRun, address: String) {
+        let request = SendRequest {
+            tap_addrs: vec![address.clone()],
+            fee_rate: None,
+            label: payment.label.clone(),
+            skip_proof_courier_ping_check: None,
+            tags: Vec::new(),
+        };
+        match send_assets(&self.client, &self.base_url, &self.macaroon_hex, request).await {
+            Ok(response) => {
+                run.status = RunStatus::Sent;
+                run.reason = None;
+                run.anchor_txid = index_send(
+                    &self.database,
+                    payment.label.as_deref(),
+                    &[],
+                    Some(address),
+                    &response,
+                )
+                .await;
+            }
+            Err(e) => {
+                run.status = RunStatus::Failed;
+                run.reason = Some(e.to_string());
+            }
+        }
+    }
+
+    fn announce(&self, run: &RecurringRun) {
+        let topic = format!("{TOPIC_PREFIX}{}", run.status.as_str());
+        match serde_json::to_value(run) {
+            Ok(payload) => {
+                self.events.publish(&topic, payload);
+            }
+            Err(e) => warn!("Failed to publish payment run {}: {}", run.id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(spend_limit_amount: Option<u64>) -> RecurringPayment {
+        RecurringPayment {
+            id: "p".to_string(),
+            asset_id: "aa".repeat(32),
+            amount: 10,
+            address: None,
+            address_url: Some("https://payee.example/addr".to_string()),
+            schedule: "@every 1h".to_string(),
+            status: RecurringStatus::Active,
+            spend_limit_amount,
+            spend_limit_window_secs: Some(86_400),
+            max_payments: None,
+            runs: 0,
+            payments_made: 0,
+            next_run_at: 0,
+            label: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_spend_limit() {
+        assert!(within_limit(&payment(None), u64::MAX, 10));
+        assert!(within_limit(&payment(Some(30)), 20, 10));
+        assert!(!within_limit(&payment(Some(25)), 20, 10));
+    }
+
+    #[test]
+    fn test_mismatched_destination() {
+        let p = payment(None);
+        let asset = "aa".repeat(32);
+        assert_eq!(mismatch(&p, Some(&asset), Some("10")), None);
+        assert!(mismatch(&p, Some(&"bb".repeat(32)), Some("10")).is_some());
+        assert!(mismatch(&p, Some(&asset), Some("11")).is_some());
+        assert!(mismatch(&p, None, Some("10")).is_some());
+    }
+
+    #[test]
+    fn test_next_run_is_not_caught_up() {
+        let now = DateTime::from_timestamp(1_000_000, 0).unwrap();
+        assert_eq!(
+            next_run_after("@every 1h", now).unwrap(),
+            Some(1_000_000 + 3600)
+        );
+        assert!(next_run_after("whenever", now).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::error::AppError;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Fields stripped for any role without an explicit profile: key material,
+/// PSBTs that could be signed or broadcast, and raw proof blobs.
+pub const DEFAULT_REDACTED_FIELDS: &[&str] = &[
+    "internal_key",
+    "raw_key_bytes",
+    "tapscript_sibling",
+    "psbt",
+    "funded_psbt",
+    "signed_psbt",
+    "final_psbt",
+    "anchor_psbt",
+    "virtual_psbts",
+    "passive_asset_psbts",
+    "raw_proof_file",
+    "raw_proof",
+    "proof_file",
+    "wallet_backup",
+];
+
+/// Maps a caller role to the JSON field names removed from its responses.
+/// Callers authenticated with the primary `API_KEY` have no role and see
+/// responses unchanged.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct RedactionProfiles {
+    profiles: HashMap<String, HashSet<String>>,
+}
+
+impl RedactionProfiles {
+    /// Parses `REDACTION_PROFILES`, e.g.
+    /// `support=internal_key,funded_psbt;auditor=raw_proof_file`.
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        let mut profiles = HashMap::new();
+        for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (role, fields) = entry.split_once('=').ok_or_else(|| {
+                AppError::ValidationError(format!(
+                    "REDACTION_PROFILES entry must be role=field,...: {entry}"
+                ))
+            })?;
+            let role = role.trim();
+            let fields: HashSet<String> = fields
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+                .collect();
+            if role.is_empty() || fields.is_empty() {
+                return Err(AppError::ValidationError(format!(
+                    "REDACTION_PROFILES entry needs a role and at least one field: {entry}"
+                )));
+            }
+            profiles.insert(role.to_string(), fields);
+        }
+        Ok(Self { profiles })
+    }
+
+    pub fn roles(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Fields to strip for `role`, falling back to [`DEFAULT_REDACTED_FIELDS`].
+    pub fn fields_for(&self, role: &str) -> HashSet<String> {
+        self.profiles.get(role).cloned().unwrap_or_else(|| {
+            DEFAULT_REDACTED_FIELDS
+                .iter()
+                .map(|f| f.to_string())
+                .collect()
+        })
+    }
+}
+
+/// Removes every object key in `fields`, at any depth.
+pub fn redact(value: &mut Value, fields: &HashSet<String>) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|key, _| !fields.contains(key));
+            map.values_mut().for_each(|v| redact(v, fields));
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| redact(v, fields)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redact_strips_nested_fields() {
+        let mut value = json!({
+            "addrs": [
+                { "encoded": "taprt1...", "internal_key": "02ab", "script_key": "02cd" }
+            ],
+            "transfer": { "funded_psbt": "cHNidP8...", "anchor_tx_hash": "ff" }
+        });
+        let fields = RedactionProfiles::default().fields_for("support");
+        redact(&mut value, &fields);
+        assert_eq!(
+            value,
+            json!({
+                "addrs": [ { "encoded": "taprt1...", "script_key": "02cd" } ],
+                "transfer": { "anchor_tx_hash": "ff" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_profiles_overrides_default() {
+        let profiles =
+            RedactionProfiles::parse("support = script_key, internal_key ; auditor=raw_proof_file")
+                .unwrap();
+        let support = profiles.fields_for("support");
+        assert!(support.contains("script_key"));
+        assert!(!support.contains("funded_psbt"));
+        assert_eq!(profiles.fields_for("auditor").len(), 1);
+        assert!(profiles.fields_for("other").contains("funded_psbt"));
+    }
+
+    #[test]
+    fn test_parse_profiles_rejects_malformed_entries() {
+        assert!(RedactionProfiles::parse("support").is_err());
+        assert!(RedactionProfiles::parse("support=").is_err());
+        assert!(RedactionProfiles::parse("=internal_key").is_err());
+        assert!(RedactionProfiles::parse("")
+            .unwrap()
+            .roles()
+            .next()
+            .is_none());
+    }
+}
@@ -0,0 +1,175 @@
+//! Route groups that can be switched off without a restart. A disabled
+//! group's paths stop matching for new requests, which then answer 404 as if
+//! the routes were never mounted; requests and WebSocket sessions already in
+//! flight are left to finish.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteGroup {
+    Universe,
+    Rfq,
+    Channels,
+    Mailbox,
+}
+
+impl RouteGroup {
+    pub const ALL: [RouteGroup; 4] = [Self::Universe, Self::Rfq, Self::Channels, Self::Mailbox];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Universe => "universe",
+            Self::Rfq => "rfq",
+            Self::Channels => "channels",
+            Self::Mailbox => "mailbox",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|group| group.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Path prefixes the group covers, relative to the gateway's mount point.
+    pub fn prefixes(self) -> &'static [&'static str] {
+        match self {
+            Self::Universe => &["/v1/taproot-assets/universe", "/v1/gateway/universe"],
+            Self::Rfq => &["/v1/taproot-assets/rfq"],
+            Self::Channels => &[
+                "/v1/taproot-assets/channels",
+                "/v1/gateway/channels",
+                "/v1/gateway/events/channels",
+            ],
+            Self::Mailbox => &["/v1/taproot-assets/mailbox"],
+        }
+    }
+
+    /// Whether `path`, as received and so possibly under a mount prefix,
+    /// belongs to the group.
+    pub fn covers(self, path: &str) -> bool {
+        let Some(start) = path.find("/v1/") else {
+            return false;
+        };
+        let path = &path[start..];
+        self.prefixes().iter().any(|prefix| {
+            path.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+/// State of one group, as reported by the admin API.
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteGroupStatus {
+    pub group: RouteGroup,
+    pub enabled: bool,
+    /// Unix seconds of the last change through the admin API.
+    pub updated_at: Option<i64>,
+    pub prefixes: &'static [&'static str],
+}
+
+/// Toggles shared by the route guard and the admin API. Groups start from
+/// `DISABLED_ROUTE_GROUPS`, then from the changes stored in the database.
+#[derive(Debug)]
+pub struct RouteGroups {
+    /// Enabled flag and last change time of groups changed at runtime.
+    overrides: RwLock<HashMap<RouteGroup, (bool, i64)>>,
+    disabled_at_startup: Vec<RouteGroup>,
+}
+
+pub type SharedRouteGroups = Arc<RouteGroups>;
+
+impl RouteGroups {
+    pub fn new(disabled: &[RouteGroup]) -> Self {
+        Self {
+            overrides: RwLock::new(HashMap::new()),
+            disabled_at_startup: disabled.to_vec(),
+        }
+    }
+
+    pub fn is_enabled(&self, group: RouteGroup) -> bool {
+        match self
+            .overrides
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&group)
+        {
+            Some((enabled, _)) => *enabled,
+            None => !self.disabled_at_startup.contains(&group),
+        }
+    }
+
+    /// Whether a new request for `path` may be served.
+    pub fn allows(&self, path: &str) -> bool {
+        RouteGroup::ALL
+            .into_iter()
+            .find(|group| group.covers(path))
+            .is_none_or(|group| self.is_enabled(group))
+    }
+
+    pub fn set(&self, group: RouteGroup, enabled: bool, updated_at: i64) -> RouteGroupStatus {
+        self.overrides
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(group, (enabled, updated_at));
+        self.status(group)
+    }
+
+    pub fn status(&self, group: RouteGroup) -> RouteGroupStatus {
+        let updated_at = self
+            .overrides
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&group)
+            .map(|(_, at)| *at);
+        RouteGroupStatus {
+            group,
+            enabled: self.is_enabled(group),
+            updated_at,
+            prefixes: group.prefixes(),
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<RouteGroupStatus> {
+        RouteGroup::ALL
+            .into_iter()
+            .map(|group| self.status(group))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups_cover_their_prefixes_under_a_mount_point() {
+        assert!(RouteGroup::Rfq.covers("/v1/taproot-assets/rfq/ntfs"));
+        assert!(RouteGroup::Universe.covers("/tap/v1/gateway/universe/syncs"));
+        assert!(RouteGroup::Channels.covers("/v1/gateway/events/channels/ws"));
+        assert!(!RouteGroup::Rfq.covers("/v1/taproot-assets/rfqx"));
+        assert!(!RouteGroup::Mailbox.covers("/v1/taproot-assets/assets"));
+        assert_eq!(RouteGroup::parse(" RFQ "), Some(RouteGroup::Rfq));
+        assert_eq!(RouteGroup::parse("wallet"), None);
+    }
+
+    #[test]
+    fn test_runtime_changes_override_startup_state() {
+        let groups = RouteGroups::new(&[RouteGroup::Mailbox]);
+        assert!(!groups.allows("/v1/taproot-assets/mailbox/info"));
+        assert!(groups.allows("/v1/taproot-assets/rfq/ntfs"));
+        assert!(groups.allows("/v1/taproot-assets/assets"));
+
+        groups.set(RouteGroup::Rfq, false, 100);
+        assert!(!groups.allows("/v1/taproot-assets/rfq/ntfs"));
+        let status = groups.set(RouteGroup::Mailbox, true, 200);
+        assert!(status.enabled);
+        assert_eq!(status.updated_at, Some(200));
+        assert!(groups.allows("/v1/taproot-assets/mailbox/info"));
+        assert!(groups.status(RouteGroup::Universe).updated_at.is_none());
+    }
+}
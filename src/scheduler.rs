@@ -0,0 +1,413 @@
+//! Runs the gateway's periodic background work (universe sync retries,
+//! usage counter flushes, cache pruning) on per-job schedules.
+//!
+//! A schedule is either `@every <n>s|m|h` or a cron expression, with five
+//! fields (minute resolution) or six to seven (leading seconds, trailing
+//! year), evaluated in UTC. `JOB_SCHEDULES` overrides the defaults, and
+//! `off` disables a job. A job never overlaps itself: a fire time that
+//! arrives while the previous run is still going is skipped and counted.
+//! Each job's last outcome is persisted when a database is configured, so
+//! `/v1/gateway/admin/jobs` reports health across restarts.
+
+use crate::database::{JobRecord, SharedDatabase};
+use crate::error::AppError;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+pub const JOB_UNIVERSE_SYNC_RETRY: &str = "universe_sync_retry";
+pub const JOB_USAGE_FLUSH: &str = "usage_flush";
+pub const JOB_PROOF_CACHE_PRUNE: &str = "proof_cache_prune";
+pub const JOB_ADDRESS_EXPIRY: &str = "address_expiry";
+pub const JOB_RESOURCE_CHECK: &str = "resource_check";
+pub const JOB_MAILBOX_OUTBOX: &str = "mailbox_outbox";
+pub const JOB_MAILBOX_GC: &str = "mailbox_gc";
+pub const JOB_SWAP_EXPIRY: &str = "swap_expiry";
+pub const JOB_RECURRING_PAYMENTS: &str = "recurring_payments";
+pub const JOB_UTXO_CHECK: &str = "utxo_check";
+pub const JOB_STATS_FLUSH: &str = "stats_flush";
+
+/// Jobs `JOB_SCHEDULES` may name.
+pub const JOB_NAMES: [&str; 11] = [
+    JOB_UNIVERSE_SYNC_RETRY,
+    JOB_USAGE_FLUSH,
+    JOB_PROOF_CACHE_PRUNE,
+    JOB_ADDRESS_EXPIRY,
+    JOB_RESOURCE_CHECK,
+    JOB_MAILBOX_OUTBOX,
+    JOB_MAILBOX_GC,
+    JOB_SWAP_EXPIRY,
+    JOB_RECURRING_PAYMENTS,
+    JOB_UTXO_CHECK,
+    JOB_STATS_FLUSH,
+];
+
+/// `JOB_SCHEDULES` value that disables a job.
+const DISABLED: &str = "off";
+
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    Every(Duration),
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Self, AppError> {
+        let expression = expression.trim();
+        let invalid = |reason: String| {
+            AppError::ValidationError(format!("Invalid schedule '{expression}': {reason}"))
+        };
+        if let Some(every) = expression.strip_prefix("@every") {
+            let every = every.trim();
+            let (value, unit) = every.split_at(every.len().saturating_sub(1));
+            let secs = match unit {
+                "s" => 1,
+                "m" => 60,
+                "h" => 3600,
+                _ => return Err(invalid("@every needs an s, m or h suffix".to_string())),
+            };
+            let value = value
+                .parse::<u64>()
+                .ok()
+                .filter(|v| *v > 0)
+                .ok_or_else(|| invalid("@every needs a positive interval".to_string()))?;
+            return Ok(Schedule::Every(Duration::from_secs(value * secs)));
+        }
+        // The cron crate wants a seconds field; classic five-field cron
+        // fires at second zero.
+        let expression = match expression.split_whitespace().count() {
+            5 => format!("0 {expression}"),
+            6 | 7 => expression.to_string(),
+            _ => return Err(invalid("expected 5 to 7 cron fields".to_string())),
+        };
+        cron::Schedule::from_str(&expression)
+            .map(|s| Schedule::Cron(Box::new(s)))
+            .map_err(|e| invalid(e.to_string()))
+    }
+
+    /// The first fire time strictly after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Every(interval) => chrono::Duration::from_std(*interval)
+                .ok()
+                .map(|d| after + d),
+            Schedule::Cron(schedule) => schedule.after(&after).next(),
+        }
+    }
+}
+
+/// Checks a `JOB_SCHEDULES` value without building the schedule.
+pub fn validate_expression(expression: &str) -> Result<(), AppError> {
+    if expression.trim() == DISABLED {
+        return Ok(());
+    }
+    Schedule::parse(expression).map(|_| ())
+}
+
+/// The schedule for `name`: its `JOB_SCHEDULES` override, else `default`.
+/// `None` when the job is switched off.
+pub fn configured(
+    overrides: &HashMap<String, String>,
+    name: &str,
+    default: &str,
+) -> Result<Option<(String, Schedule)>, AppError> {
+    let expression = overrides
+        .get(name)
+        .map(String::as_str)
+        .unwrap_or(default)
+        .trim();
+    if expression == DISABLED {
+        return Ok(None);
+    }
+    Ok(Some((expression.to_string(), Schedule::parse(expression)?)))
+}
+
+pub type JobFuture = Pin<Box<dyn Future<Output = Result<(), AppError>> + Send>>;
+
+type JobFn = Box<dyn Fn() -> JobFuture + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobHealth {
+    /// Has not run yet.
+    Pending,
+    Healthy,
+    /// The last run failed.
+    Failing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    #[serde(flatten)]
+    pub record: JobRecord,
+    pub health: JobHealth,
+    pub running: bool,
+    pub next_run_at: Option<i64>,
+}
+
+struct Job {
+    schedule: Schedule,
+    run: JobFn,
+    running: AtomicBool,
+    record: Mutex<JobRecord>,
+    next_run_at: Mutex<Option<i64>>,
+}
+
+impl Job {
+    fn record(&self) -> JobRecord {
+        self.record
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn update(&self, change: impl FnOnce(&mut JobRecord)) -> JobRecord {
+        let mut record = self.record.lock().unwrap_or_else(|e| e.into_inner());
+        change(&mut record);
+        record.clone()
+    }
+
+    /// Starts a run in the background unless one is still going. Returns
+    /// whether a run started.
+    fn fire(self: &Arc<Self>, database: Option<SharedDatabase>) -> bool {
+        if self.running.swap(true, Ordering::AcqRel) {
+            let record = self.update(|r| r.skipped += 1);
+            warn!(
+                "Job {} is still running, skipping this run ({} skipped so far)",
+                record.name, record.skipped
+            );
+            tokio::spawn(persist(database, record));
+            return false;
+        }
+        let job = self.clone();
+        tokio::spawn(async move {
+            let started_at = chrono::Utc::now().timestamp();
+            let started = Instant::now();
+            let result = (job.run)().await;
+            let record = job.update(|r| {
+                r.last_started_at = Some(started_at);
+                r.last_finished_at = Some(chrono::Utc::now().timestamp());
+                r.last_duration_ms = Some(started.elapsed().as_millis() as u64);
+                r.runs += 1;
+                match &result {
+                    Ok(()) => {
+                        r.last_status = Some("ok".to_string());
+                        r.last_error = None;
+                    }
+                    Err(e) => {
+                        r.last_status = Some("error".to_string());
+                        r.last_error = Some(e.to_string());
+                        r.failures += 1;
+                    }
+                }
+            });
+            job.running.store(false, Ordering::Release);
+            match &result {
+                Ok(()) => debug!("Job {} finished", record.name),
+                Err(e) => warn!("Job {} failed: {}", record.name, e),
+            }
+            persist(database, record).await;
+        });
+        true
+    }
+}
+
+async fn persist(database: Option<SharedDatabase>, record: JobRecord) {
+    if let Some(database) = database {
+        if let Err(e) = database.save_job_record(&record).await {
+            warn!("Failed to save status of job {}: {}", record.name, e);
+        }
+    }
+}
+
+pub struct Scheduler {
+    jobs: Vec<Arc<Job>>,
+    database: Option<SharedDatabase>,
+}
+
+pub type SharedScheduler = Arc<Scheduler>;
+
+impl Scheduler {
+    /// Job outcomes are persisted to `database` when one is given.
+    pub fn new(database: Option<SharedDatabase>) -> Self {
+        Self {
+            jobs: Vec::new(),
+            database,
+        }
+    }
+
+    /// Registers `run` under `name`. `source` is the expression `schedule`
+    /// was parsed from, reported back by the admin endpoint.
+    pub fn add<F, Fut>(&mut self, name: &str, source: &str, schedule: Schedule, run: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), AppError>> + Send + 'static,
+    {
+        self.jobs.push(Arc::new(Job {
+            schedule,
+            run: Box::new(move || Box::pin(run())),
+            running: AtomicBool::new(false),
+            record: Mutex::new(JobRecord {
+                name: name.to_string(),
+                schedule: source.to_string(),
+                ..Default::default()
+            }),
+            next_run_at: Mutex::new(None),
+        }));
+    }
+
+    /// Restores persisted outcomes, then runs every job on its schedule.
+    pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            self.restore().await;
+            for job in &self.jobs {
+                let job = job.clone();
+                let database = self.database.clone();
+                tokio::spawn(async move {
+                    loop {
+                        let now = Utc::now();
+                        let Some(next) = job.schedule.next_after(now) else {
+                            info!("Job {} has no further fire times", job.record().name);
+                            break;
+                        };
+                        *job.next_run_at.lock().unwrap_or_else(|e| e.into_inner()) =
+                            Some(next.timestamp());
+                        tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+                        job.fire(database.clone());
+                    }
+                });
+            }
+            info!("Scheduler started {} jobs", self.jobs.len());
+        })
+    }
+
+    async fn restore(&self) {
+        let Some(database) = &self.database else {
+            return;
+        };
+        let stored: HashMap<String, JobRecord> = match database.job_records().await {
+            Ok(records) => records.into_iter().map(|r| (r.name.clone(), r)).collect(),
+            Err(e) => {
+                warn!("Failed to load job status: {}", e);
+                return;
+            }
+        };
+        for job in &self.jobs {
+            job.update(|record| {
+                if let Some(stored) = stored.get(&record.name) {
+                    *record = JobRecord {
+                        schedule: record.schedule.clone(),
+                        ..stored.clone()
+                    };
+                }
+            });
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.jobs
+            .iter()
+            .map(|job| {
+                let record = job.record();
+                let health = match record.last_status.as_deref() {
+                    None => JobHealth::Pending,
+                    Some("ok") => JobHealth::Healthy,
+                    Some(_) => JobHealth::Failing,
+                };
+                JobStatus {
+                    record,
+                    health,
+                    running: job.running.load(Ordering::Acquire),
+                    next_run_at: *job.next_run_at.lock().unwrap_or_else(|e| e.into_inner()),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_five_field_cron_fires_on_the_minute() {
+        let schedule = Schedule::parse("*/5 * * * *").unwrap();
+        let after = Utc.with_ymd_and_hms(2024, 1, 1, 12, 1, 30).unwrap();
+        assert_eq!(
+            schedule.next_after(after),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 12, 5, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_every_schedule_and_invalid_expressions() {
+        let after = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let schedule = Schedule::parse("@every 90s").unwrap();
+        assert_eq!(
+            schedule.next_after(after),
+            Some(after + chrono::Duration::seconds(90))
+        );
+        assert!(Schedule::parse("@every 0m").is_err());
+        assert!(Schedule::parse("@every 5d").is_err());
+        assert!(Schedule::parse("* * *").is_err());
+        assert!(Schedule::parse("61 * * * *").is_err());
+        assert!(validate_expression("off").is_ok());
+    }
+
+    #[test]
+    fn test_configured_prefers_override_and_honours_off() {
+        let overrides = HashMap::from([
+            (JOB_USAGE_FLUSH.to_string(), "0 * * * *".to_string()),
+            (JOB_PROOF_CACHE_PRUNE.to_string(), "off".to_string()),
+        ]);
+        let (source, _) = configured(&overrides, JOB_USAGE_FLUSH, "@every 60s")
+            .unwrap()
+            .unwrap();
+        assert_eq!(source, "0 * * * *");
+        assert!(configured(&overrides, JOB_PROOF_CACHE_PRUNE, "@every 1h")
+            .unwrap()
+            .is_none());
+        let (source, _) = configured(&overrides, JOB_UNIVERSE_SYNC_RETRY, "@every 60s")
+            .unwrap()
+            .unwrap();
+        assert_eq!(source, "@every 60s");
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_run_is_skipped() {
+        let mut scheduler = Scheduler::new(None);
+        let (release, wait) = tokio::sync::watch::channel(false);
+        scheduler.add(
+            "slow",
+            "@every 1s",
+            Schedule::parse("@every 1s").unwrap(),
+            move || {
+                let mut wait = wait.clone();
+                async move {
+                    let _ = wait.wait_for(|done| *done).await;
+                    Ok(())
+                }
+            },
+        );
+        let job = scheduler.jobs[0].clone();
+        assert!(job.fire(None));
+        assert!(!job.fire(None));
+
+        release.send(true).unwrap();
+        while job.running.load(Ordering::Acquire) {
+            tokio::task::yield_now().await;
+        }
+        let status = &scheduler.statuses()[0];
+        assert_eq!(status.health, JobHealth::Healthy);
+        assert_eq!(status.record.runs, 1);
+        assert_eq!(status.record.skipped, 1);
+    }
+}
@@ -0,0 +1,411 @@
+//! Cookie sessions for browser frontends, so they need not embed a
+//! long-lived API key in JavaScript. A caller logs in with an API key, an
+//! OIDC token or a signed challenge and gets two HttpOnly cookies:
+//!
+//! - a short-lived access token, HMAC-signed and checked without a database
+//!   lookup by [`crate::middleware::ApiKeyAuth`];
+//! - a refresh token, stored hashed in the database and replaced on every
+//!   refresh, that only the `/v1/gateway/sessions` routes ever see.
+//!
+//! Logging out revokes the refresh token; an access token already issued
+//! stays valid until it expires.
+//!
+//! Cookies ride along on cross-site requests too, so unless CSRF protection
+//! is turned off, cookie-authenticated writes must repeat the session's CSRF
+//! token in [`CSRF_HEADER`]. The token is also set as a cookie scripts on
+//! the gateway's origin can read; see [`crate::middleware::CsrfProtection`].
+
+use crate::config::Config;
+use crate::crypto::hmac_sha256;
+use crate::database::{SessionRecord, SharedDatabase};
+use crate::error::AppError;
+use crate::oidc::{TokenRole, ADMIN_ROLE};
+use actix_web::cookie::{time, Cookie, SameSite};
+use base64::Engine;
+use secp256k1::rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Cookie holding the access token.
+pub const ACCESS_COOKIE: &str = "gw_access";
+/// Cookie holding the refresh token, scoped to [`SESSIONS_PATH`].
+pub const REFRESH_COOKIE: &str = "gw_refresh";
+/// Cookie holding the CSRF token; not `HttpOnly`, so scripts can copy it
+/// into [`CSRF_HEADER`].
+pub const CSRF_COOKIE: &str = "gw_csrf";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+pub const SESSIONS_PATH: &str = "/v1/gateway/sessions";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AccessClaims {
+    sid: String,
+    /// Read-only role; absent for full access.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    exp: i64,
+}
+
+/// A session just opened or refreshed, with the tokens to set as cookies.
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedSession {
+    pub session_id: String,
+    pub role: String,
+    pub access_expires_at: i64,
+    pub refresh_expires_at: i64,
+    /// Present when CSRF protection is on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csrf_token: Option<String>,
+    #[serde(skip)]
+    access_token: String,
+    #[serde(skip)]
+    refresh_token: String,
+}
+
+/// What a valid access token grants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionAccess {
+    pub session_id: String,
+    pub role: TokenRole,
+}
+
+pub struct SessionManager {
+    db: SharedDatabase,
+    secret: Vec<u8>,
+    access_ttl_secs: i64,
+    refresh_ttl_secs: i64,
+    secure_cookies: bool,
+    csrf_protection: bool,
+    /// `BASE_PATH` the gateway is mounted under, for cookie paths.
+    base_path: String,
+    /// Public keys allowed to log in by signing a challenge, with the role
+    /// each gets.
+    login_keys: HashMap<String, String>,
+}
+
+pub type SharedSessionManager = Arc<SessionManager>;
+
+impl SessionManager {
+    /// Signs access tokens with `SESSION_SECRET`, or with a random secret
+    /// when it is unset, in which case access tokens do not survive a
+    /// restart (refresh tokens do).
+    pub fn new(config: &Config, db: SharedDatabase) -> Result<Self, AppError> {
+        let secret = match &config.session_secret {
+            Some(secret) => hex::decode(secret.trim())
+                .map_err(|_| AppError::ValidationError("SESSION_SECRET must be hex".to_string()))?,
+            None => random_bytes().to_vec(),
+        };
+        Ok(Self {
+            db,
+            secret,
+            access_ttl_secs: config.session_access_ttl_secs as i64,
+            refresh_ttl_secs: config.session_refresh_ttl_secs as i64,
+            secure_cookies: config.session_cookie_secure,
+            csrf_protection: config.session_csrf_protection,
+            base_path: config.base_path.clone(),
+            login_keys: config.session_login_keys.clone(),
+        })
+    }
+
+    /// Whether cookie-authenticated writes need the CSRF token.
+    pub fn csrf_protection(&self) -> bool {
+        self.csrf_protection
+    }
+
+    /// The CSRF token of session `session_id`. It is derived from the
+    /// session, so it survives refreshes and needs no storage.
+    pub fn csrf_token(&self, session_id: &str) -> String {
+        hex::encode(hmac_sha256(
+            &self.secret,
+            format!("csrf:{session_id}").as_bytes(),
+        ))
+    }
+
+    /// Whether `token` is the CSRF token of session `session_id`.
+    pub fn verify_csrf(&self, session_id: &str, token: &str) -> bool {
+        constant_time_eq(self.csrf_token(session_id).as_bytes(), token.as_bytes())
+    }
+
+    /// The role a login key gets, if it may log in.
+    pub fn login_role(&self, public_key: &str) -> Option<TokenRole> {
+        self.login_keys
+            .get(&public_key.to_ascii_lowercase())
+            .map(|role| token_role(Some(role.as_str())))
+    }
+
+    /// Opens a session for `subject` with `role`'s access.
+    pub async fn open(&self, subject: &str, role: &TokenRole) -> Result<IssuedSession, AppError> {
+        let now = chrono::Utc::now().timestamp();
+        // Nothing else deletes dead sessions, and logins are rare enough
+        self.db.prune_sessions(now).await?;
+
+        let refresh_token = hex::encode(random_bytes());
+        let record = SessionRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            subject: subject.to_string(),
+            role: match role {
+                TokenRole::Admin => None,
+                TokenRole::ReadOnly(role) => Some(role.clone()),
+            },
+            created_at: now,
+            refreshed_at: now,
+            expires_at: now + self.refresh_ttl_secs,
+            revoked_at: None,
+        };
+        self.db
+            .insert_session(&record, &hash_token(&refresh_token))
+            .await?;
+        Ok(self.issue(&record, refresh_token, now))
+    }
+
+    /// Swaps `refresh_token` for new tokens; `None` if it is not the
+    /// current token of a live session.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<Option<IssuedSession>, AppError> {
+        let now = chrono::Utc::now().timestamp();
+        let new_token = hex::encode(random_bytes());
+        let record = self
+            .db
+            .rotate_session(
+                &hash_token(refresh_token),
+                &hash_token(&new_token),
+                now,
+                now + self.refresh_ttl_secs,
+            )
+            .await?;
+        Ok(record.map(|record| self.issue(&record, new_token, now)))
+    }
+
+    /// Revokes the session `refresh_token` belongs to.
+    pub async fn close(&self, refresh_token: &str) -> Result<bool, AppError> {
+        self.db
+            .revoke_session(&hash_token(refresh_token), chrono::Utc::now().timestamp())
+            .await
+    }
+
+    fn issue(&self, record: &SessionRecord, refresh_token: String, now: i64) -> IssuedSession {
+        let claims = AccessClaims {
+            sid: record.id.clone(),
+            role: record.role.clone(),
+            exp: now + self.access_ttl_secs,
+        };
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(serde_json::to_vec(&claims).unwrap_or_default());
+        let mac = hex::encode(hmac_sha256(&self.secret, payload.as_bytes()));
+        IssuedSession {
+            session_id: record.id.clone(),
+            role: record
+                .role
+                .clone()
+                .unwrap_or_else(|| ADMIN_ROLE.to_string()),
+            access_expires_at: claims.exp,
+            refresh_expires_at: record.expires_at,
+            csrf_token: self.csrf_protection.then(|| self.csrf_token(&record.id)),
+            access_token: format!("{payload}.{mac}"),
+            refresh_token,
+        }
+    }
+
+    /// The session and role an unexpired access token grants; `None` for
+    /// anything forged, expired or malformed.
+    pub fn verify_access(&self, token: &str) -> Option<SessionAccess> {
+        let (payload, mac) = token.rsplit_once('.')?;
+        let mac = hex::decode(mac).ok()?;
+        if !constant_time_eq(&hmac_sha256(&self.secret, payload.as_bytes()), &mac) {
+            return None;
+        }
+        let claims: AccessClaims = serde_json::from_slice(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .decode(payload)
+                .ok()?,
+        )
+        .ok()?;
+        (claims.exp > chrono::Utc::now().timestamp()).then(|| SessionAccess {
+            role: token_role(claims.role.as_deref()),
+            session_id: claims.sid,
+        })
+    }
+
+    /// The access, refresh and (with CSRF protection) CSRF cookies for
+    /// `session`.
+    pub fn cookies(&self, session: &IssuedSession) -> Vec<Cookie<'static>> {
+        let mut cookies = vec![
+            self.cookie(
+                ACCESS_COOKIE,
+                session.access_token.clone(),
+                self.access_ttl_secs,
+                "/",
+            ),
+            self.cookie(
+                REFRESH_COOKIE,
+                session.refresh_token.clone(),
+                self.refresh_ttl_secs,
+                SESSIONS_PATH,
+            ),
+        ];
+        if let Some(csrf_token) = &session.csrf_token {
+            cookies.push(self.csrf_cookie(csrf_token.clone(), self.refresh_ttl_secs));
+        }
+        cookies
+    }
+
+    /// The CSRF cookie for session `session_id`.
+    pub fn csrf_cookie_for(&self, session_id: &str) -> Cookie<'static> {
+        self.csrf_cookie(self.csrf_token(session_id), self.refresh_ttl_secs)
+    }
+
+    /// Cookies that clear the session's tokens from the browser.
+    pub fn removal_cookies(&self) -> Vec<Cookie<'static>> {
+        vec![
+            self.cookie(ACCESS_COOKIE, String::new(), 0, "/"),
+            self.cookie(REFRESH_COOKIE, String::new(), 0, SESSIONS_PATH),
+            self.csrf_cookie(String::new(), 0),
+        ]
+    }
+
+    fn csrf_cookie(&self, value: String, max_age: i64) -> Cookie<'static> {
+        let mut cookie = self.cookie(CSRF_COOKIE, value, max_age, "/");
+        cookie.set_http_only(false);
+        cookie
+    }
+
+    fn cookie(&self, name: &str, value: String, max_age: i64, path: &str) -> Cookie<'static> {
+        Cookie::build(name.to_string(), value)
+            .path(format!("{}{path}", self.base_path))
+            .http_only(true)
+            .secure(self.secure_cookies)
+            .same_site(SameSite::Strict)
+            .max_age(time::Duration::seconds(max_age))
+            .finish()
+    }
+}
+
+fn token_role(role: Option<&str>) -> TokenRole {
+    match role {
+        None | Some(ADMIN_ROLE) => TokenRole::Admin,
+        Some(role) => TokenRole::ReadOnly(role.to_string()),
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// 32 bytes from the operating system's CSPRNG, for secrets and tokens.
+fn random_bytes() -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    secp256k1::rand::rngs::OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Parses `SESSION_LOGIN_KEYS` (`role:public_key,...`) into a map from
+/// lowercase hex public key to role.
+pub fn parse_login_keys(value: &str) -> Result<HashMap<String, String>, AppError> {
+    let mut keys = HashMap::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (role, key) = entry
+            .split_once(':')
+            .map(|(r, k)| (r.trim(), k.trim().to_ascii_lowercase()))
+            .filter(|(r, k)| !r.is_empty() && !k.is_empty())
+            .ok_or_else(|| {
+                AppError::ValidationError(
+                    "SESSION_LOGIN_KEYS entries must be role:public_key".to_string(),
+                )
+            })?;
+        if crate::crypto::derive_public_key_from_receiver_id(&key)?.is_none() {
+            return Err(AppError::ValidationError(format!(
+                "SESSION_LOGIN_KEYS entry for role '{role}' is not a hex public key"
+            )));
+        }
+        if keys.insert(key, role.to_string()).is_some() {
+            return Err(AppError::ValidationError(
+                "SESSION_LOGIN_KEYS lists a key twice".to_string(),
+            ));
+        }
+    }
+    Ok(keys)
+}
+
+/// A session manager on a throwaway database for unit tests, with a fixed
+/// secret and no login keys.
+#[cfg(test)]
+pub(crate) async fn open_test_sessions() -> SharedSessionManager {
+    Arc::new(SessionManager {
+        db: crate::database::open_test_database().await,
+        secret: vec![7; 32],
+        access_ttl_secs: 900,
+        refresh_ttl_secs: 3600,
+        secure_cookies: true,
+        csrf_protection: true,
+        base_path: String::new(),
+        login_keys: HashMap::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sessions_issue_verify_and_refresh() {
+        let sessions = open_test_sessions().await;
+        let role = TokenRole::ReadOnly("support".to_string());
+        let issued = sessions.open("role_key:support", &role).await.unwrap();
+        assert_eq!(issued.role, "support");
+        let access = sessions.verify_access(&issued.access_token).unwrap();
+        assert_eq!(access.role, role);
+        assert_eq!(access.session_id, issued.session_id);
+
+        // Tampering with the payload breaks the MAC
+        let (_, mac) = issued.access_token.rsplit_once('.').unwrap();
+        let forged = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(br#"{"sid":"x","exp":99999999999}"#);
+        assert_eq!(sessions.verify_access(&format!("{forged}.{mac}")), None);
+
+        let refreshed = sessions
+            .refresh(&issued.refresh_token)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(refreshed.refresh_token, issued.refresh_token);
+        assert!(sessions
+            .refresh(&issued.refresh_token)
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(sessions.close(&refreshed.refresh_token).await.unwrap());
+        assert!(sessions
+            .refresh(&refreshed.refresh_token)
+            .await
+            .unwrap()
+            .is_none());
+
+        // The CSRF token stays with the session across refreshes
+        assert_eq!(refreshed.csrf_token, issued.csrf_token);
+        assert!(sessions.verify_csrf(&issued.session_id, issued.csrf_token.as_ref().unwrap()));
+        assert!(!sessions.verify_csrf("other", issued.csrf_token.as_ref().unwrap()));
+
+        let cookies = sessions.cookies(&refreshed);
+        let [access, refresh, csrf] = cookies.as_slice() else {
+            panic!("expected three cookies");
+        };
+        assert_eq!(access.path(), Some("/"));
+        assert_eq!(refresh.path(), Some(SESSIONS_PATH));
+        assert_eq!(access.http_only(), Some(true));
+        assert_eq!(csrf.http_only(), Some(false));
+    }
+
+    #[test]
+    fn test_parse_login_keys() {
+        let key = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let keys = parse_login_keys(&format!("admin:{}", key.to_ascii_uppercase())).unwrap();
+        assert_eq!(keys.get(key).map(String::as_str), Some("admin"));
+        assert!(parse_login_keys("admin:nothex").is_err());
+        assert!(parse_login_keys(&format!("admin:{key},support:{key}")).is_err());
+    }
+}
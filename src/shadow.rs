@@ -0,0 +1,207 @@
+//! Shadow traffic: mirrors a sample of read-only requests to a second tapd and
+//! logs where its responses differ from the primary's, to validate a backend
+//! upgrade before cutting over. Mirroring happens after the caller has been
+//! answered and never affects the primary response.
+
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+/// Differences reported per mirrored request; the rest are summarized.
+const MAX_REPORTED_DIFFS: usize = 20;
+
+pub struct ShadowMirror {
+    client: reqwest::Client,
+    base_url: String,
+    macaroon_hex: String,
+    percent: u8,
+    counter: AtomicU64,
+}
+
+pub type SharedShadowMirror = Arc<ShadowMirror>;
+
+impl ShadowMirror {
+    pub fn new(
+        client: reqwest::Client,
+        base_url: String,
+        macaroon_hex: String,
+        percent: u8,
+    ) -> Self {
+        Self {
+            client,
+            base_url,
+            macaroon_hex,
+            percent: percent.min(100),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Deterministic sampling: `percent` out of every 100 requests, spread
+    /// evenly rather than in bursts.
+    pub fn should_sample(&self) -> bool {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        let pct = u64::from(self.percent);
+        (n + 1) * pct / 100 != n * pct / 100
+    }
+
+    /// Replays `path_and_query` against the shadow backend in the background
+    /// and logs the differences from the primary response.
+    pub fn mirror(self: Arc<Self>, path_and_query: String, primary: Value) {
+        tokio::spawn(async move {
+            let url = format!("{}{}", self.base_url, path_and_query);
+            let response = match self
+                .client
+                .get(&url)
+                .header("Grpc-Metadata-macaroon", &self.macaroon_hex)
+                .send()
+                .await
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("Shadow request {} failed: {}", path_and_query, e);
+                    return;
+                }
+            };
+            let status = response.status();
+            let shadow = match response.json::<Value>().await {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!(
+                        "Shadow response for {} ({}) is not JSON: {}",
+                        path_and_query, status, e
+                    );
+                    return;
+                }
+            };
+
+            let mut diffs = Vec::new();
+            let total = json_diff("$", &primary, &shadow, &mut diffs);
+            if total == 0 {
+                debug!("Shadow response for {} matches", path_and_query);
+                return;
+            }
+            warn!(
+                "Shadow response for {} ({}) differs in {} place(s): {}{}",
+                path_and_query,
+                status,
+                total,
+                diffs.join("; "),
+                if total > diffs.len() { "; ..." } else { "" }
+            );
+        });
+    }
+
+    pub fn log_startup(&self) {
+        info!(
+            "Mirroring {}% of read-only traffic to {}",
+            self.percent, self.base_url
+        );
+    }
+}
+
+fn describe(value: &Value) -> String {
+    let text = value.to_string();
+    if text.chars().count() > 80 {
+        format!("{}...", text.chars().take(77).collect::<String>())
+    } else {
+        text
+    }
+}
+
+fn report(out: &mut Vec<String>, message: String) -> usize {
+    if out.len() < MAX_REPORTED_DIFFS {
+        out.push(message);
+    }
+    1
+}
+
+/// Collects up to [`MAX_REPORTED_DIFFS`] descriptions of where `primary` and
+/// `shadow` differ and returns the total number of differences.
+pub fn json_diff(path: &str, primary: &Value, shadow: &Value, out: &mut Vec<String>) -> usize {
+    match (primary, shadow) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut total = 0;
+            for (key, av) in a {
+                let child = format!("{path}.{key}");
+                total += match b.get(key) {
+                    Some(bv) => json_diff(&child, av, bv, out),
+                    None => report(out, format!("{child} missing in shadow")),
+                };
+            }
+            for key in b.keys().filter(|k| !a.contains_key(*k)) {
+                total += report(out, format!("{path}.{key} only in shadow"));
+            }
+            total
+        }
+        (Value::Array(a), Value::Array(b)) if a.len() != b.len() => report(
+            out,
+            format!("{path} has {} items, shadow has {}", a.len(), b.len()),
+        ),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .enumerate()
+            .map(|(i, (av, bv))| json_diff(&format!("{path}[{i}]"), av, bv, out))
+            .sum(),
+        (a, b) if a == b => 0,
+        (a, b) => report(out, format!("{path}: {} != {}", describe(a), describe(b))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn mirror(percent: u8) -> ShadowMirror {
+        ShadowMirror::new(
+            reqwest::Client::new(),
+            "https://127.0.0.1:1".to_string(),
+            String::new(),
+            percent,
+        )
+    }
+
+    #[test]
+    fn test_sampling_matches_percentage() {
+        for percent in [0u8, 1, 25, 50, 100] {
+            let m = mirror(percent);
+            let sampled = (0..1000).filter(|_| m.should_sample()).count();
+            assert_eq!(sampled, percent as usize * 10, "percent {percent}");
+        }
+    }
+
+    #[test]
+    fn test_json_diff_reports_paths() {
+        let primary = json!({
+            "assets": [{ "amount": "10", "name": "usd" }],
+            "version": "0.5.0",
+            "gone": true
+        });
+        let shadow = json!({
+            "assets": [{ "amount": "12", "name": "usd" }],
+            "version": "0.6.0",
+            "new": 1
+        });
+        let mut diffs = Vec::new();
+        let total = json_diff("$", &primary, &shadow, &mut diffs);
+        assert_eq!(total, 4);
+        assert!(diffs.contains(&"$.assets[0].amount: \"10\" != \"12\"".to_string()));
+        assert!(diffs.contains(&"$.gone missing in shadow".to_string()));
+        assert!(diffs.contains(&"$.new only in shadow".to_string()));
+
+        let mut diffs = Vec::new();
+        assert_eq!(json_diff("$", &primary, &primary, &mut diffs), 0);
+        assert!(diffs.is_empty());
+    }
+
+    #[test]
+    fn test_json_diff_caps_reported_entries() {
+        let primary = json!((0..50).collect::<Vec<_>>());
+        let shadow = json!((100..150).collect::<Vec<_>>());
+        let mut diffs = Vec::new();
+        assert_eq!(json_diff("$", &primary, &shadow, &mut diffs), 50);
+        assert_eq!(diffs.len(), MAX_REPORTED_DIFFS);
+    }
+}
@@ -0,0 +1,271 @@
+//! Memory self-limits. The `resource_check` job samples process RSS and the
+//! gateway's in-memory buffers (WebSocket proxy sessions, event subscribers,
+//! response caches); while any of them is over its threshold the gateway is
+//! in shed mode and [`crate::middleware::ShedGuard`] refuses new large
+//! uploads and WebSocket sessions, so load is dropped before the kernel
+//! kills the process. Shed mode ends once every figure is back under
+//! [`RECOVERY_RATIO`] of its threshold.
+
+use crate::config::Config;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+/// Share of each threshold usage must fall below to leave shed mode, so a
+/// figure hovering at its limit does not flap in and out.
+pub const RECOVERY_RATIO: f64 = 0.9;
+
+/// Seconds refused clients are told to wait.
+pub const SHED_RETRY_AFTER_SECS: u64 = 10;
+
+/// One sample of the resources shed mode watches.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ResourceUsage {
+    /// Resident set size; `None` where `/proc` is unavailable.
+    pub rss_bytes: Option<u64>,
+    pub proxy_sessions: usize,
+    pub backend_websockets: usize,
+    pub event_subscribers: usize,
+    /// Entries in the public explorer and asset supply caches.
+    pub cache_entries: usize,
+}
+
+impl ResourceUsage {
+    /// Client streams held open: proxied WebSockets and event subscribers.
+    pub fn stream_sessions(&self) -> usize {
+        self.proxy_sessions + self.event_subscribers
+    }
+}
+
+/// Limits from `SHED_*`; a zero limit is not checked.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ShedThresholds {
+    pub rss_bytes: u64,
+    pub stream_sessions: usize,
+    pub cache_entries: usize,
+    /// Bodies larger than this are refused while shedding.
+    pub large_payload_bytes: u64,
+}
+
+impl ShedThresholds {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            rss_bytes: config.shed_rss_mb * 1024 * 1024,
+            stream_sessions: config.shed_max_ws_sessions,
+            cache_entries: config.shed_max_cache_entries,
+            large_payload_bytes: config.shed_large_payload_bytes,
+        }
+    }
+
+    /// Whether any limit is set.
+    pub fn is_enabled(&self) -> bool {
+        self.rss_bytes > 0 || self.stream_sessions > 0 || self.cache_entries > 0
+    }
+
+    /// The limits `usage` is over once each is scaled by `ratio`.
+    fn exceeded(&self, usage: &ResourceUsage, ratio: f64) -> Vec<String> {
+        let over = |value: u64, limit: u64| limit > 0 && value as f64 >= limit as f64 * ratio;
+        let mut reasons = Vec::new();
+        if let Some(rss) = usage.rss_bytes.filter(|rss| over(*rss, self.rss_bytes)) {
+            reasons.push(format!(
+                "rss {} MiB of {} MiB",
+                rss / (1024 * 1024),
+                self.rss_bytes / (1024 * 1024)
+            ));
+        }
+        let sessions = usage.stream_sessions();
+        if over(sessions as u64, self.stream_sessions as u64) {
+            reasons.push(format!(
+                "{sessions} stream sessions of {}",
+                self.stream_sessions
+            ));
+        }
+        if over(usage.cache_entries as u64, self.cache_entries as u64) {
+            reasons.push(format!(
+                "{} cache entries of {}",
+                usage.cache_entries, self.cache_entries
+            ));
+        }
+        reasons
+    }
+}
+
+/// Snapshot reported by the admin endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShedStatus {
+    pub active: bool,
+    /// Limits over their threshold at the last check.
+    pub reasons: Vec<String>,
+    /// Unix seconds when shedding began.
+    pub since: Option<i64>,
+    /// Unix seconds of the last sample.
+    pub checked_at: Option<i64>,
+    pub usage: ResourceUsage,
+    pub thresholds: ShedThresholds,
+    /// Requests refused since startup.
+    pub shed_requests: u64,
+}
+
+#[derive(Debug)]
+pub struct ShedMode {
+    thresholds: ShedThresholds,
+    status: RwLock<ShedStatus>,
+    shed_requests: AtomicU64,
+}
+
+pub type SharedShed = Arc<ShedMode>;
+
+impl ShedMode {
+    pub fn new(thresholds: ShedThresholds) -> Self {
+        Self {
+            thresholds,
+            status: RwLock::new(ShedStatus {
+                active: false,
+                reasons: Vec::new(),
+                since: None,
+                checked_at: None,
+                usage: ResourceUsage::default(),
+                thresholds,
+                shed_requests: 0,
+            }),
+            shed_requests: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.status.read().unwrap_or_else(|e| e.into_inner()).active
+    }
+
+    pub fn status(&self) -> ShedStatus {
+        let mut status = self
+            .status
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        status.shed_requests = self.shed_requests.load(Ordering::Relaxed);
+        status
+    }
+
+    /// Records a sample, entering shed mode when a limit is reached and
+    /// leaving it once every figure is under [`RECOVERY_RATIO`] of its limit.
+    pub fn update(&self, usage: ResourceUsage) -> ShedStatus {
+        let now = chrono::Utc::now().timestamp();
+        {
+            let mut status = self.status.write().unwrap_or_else(|e| e.into_inner());
+            let ratio = if status.active { RECOVERY_RATIO } else { 1.0 };
+            let reasons = self.thresholds.exceeded(&usage, ratio);
+            let active = !reasons.is_empty();
+            if active && !status.active {
+                warn!("Entering shed mode: {}", reasons.join(", "));
+                status.since = Some(now);
+            } else if !active && status.active {
+                info!("Leaving shed mode; resource usage is back under its limits");
+                status.since = None;
+            }
+            status.active = active;
+            status.reasons = reasons;
+            status.checked_at = Some(now);
+            status.usage = usage;
+        }
+        self.status()
+    }
+
+    /// Whether a new request is refused: while shedding, WebSocket upgrades
+    /// and bodies over the payload limit, including chunked bodies of
+    /// unknown size.
+    pub fn should_shed(&self, content_length: Option<u64>, chunked: bool, upgrade: bool) -> bool {
+        if !self.is_active() {
+            return false;
+        }
+        let large = match content_length {
+            Some(len) => len > self.thresholds.large_payload_bytes,
+            None => chunked,
+        };
+        if upgrade || large {
+            self.shed_requests.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+}
+
+/// Resident set size of this process, from `/proc/self/status`.
+pub fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_vm_rss(&status)
+}
+
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find_map(|l| l.strip_prefix("VmRSS:"))?;
+    let kib = line.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thresholds() -> ShedThresholds {
+        ShedThresholds {
+            rss_bytes: 100 * 1024 * 1024,
+            stream_sessions: 10,
+            cache_entries: 0,
+            large_payload_bytes: 1024,
+        }
+    }
+
+    #[test]
+    fn test_shed_mode_enters_at_limit_and_recovers_below_ratio() {
+        let shed = ShedMode::new(thresholds());
+        let mut usage = ResourceUsage {
+            rss_bytes: Some(50 * 1024 * 1024),
+            proxy_sessions: 6,
+            event_subscribers: 4,
+            cache_entries: 5000,
+            ..Default::default()
+        };
+        let status = shed.update(usage);
+        assert!(status.active);
+        assert_eq!(status.reasons, vec!["10 stream sessions of 10".to_string()]);
+        assert!(status.since.is_some());
+
+        // Still shedding just under the limit, until below 90% of it
+        usage.proxy_sessions = 5;
+        assert!(shed.update(usage).active);
+        usage.proxy_sessions = 4;
+        let status = shed.update(usage);
+        assert!(!status.active);
+        assert!(status.since.is_none());
+
+        usage.rss_bytes = Some(120 * 1024 * 1024);
+        assert_eq!(
+            shed.update(usage).reasons,
+            vec!["rss 120 MiB of 100 MiB".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_should_shed_large_bodies_and_upgrades_only_while_active() {
+        let shed = ShedMode::new(thresholds());
+        assert!(!shed.should_shed(Some(1 << 20), false, true));
+
+        shed.update(ResourceUsage {
+            proxy_sessions: 10,
+            ..Default::default()
+        });
+        assert!(shed.should_shed(Some(1025), false, false));
+        assert!(shed.should_shed(None, true, false));
+        assert!(shed.should_shed(None, false, true));
+        assert!(!shed.should_shed(Some(1024), false, false));
+        assert!(!shed.should_shed(None, false, false));
+        assert_eq!(shed.status().shed_requests, 3);
+    }
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\tgateway\nVmPeak:\t  300000 kB\nVmRSS:\t   51200 kB\nThreads:\t8\n";
+        assert_eq!(parse_vm_rss(status), Some(50 * 1024 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tgateway\n"), None);
+    }
+}
@@ -0,0 +1,946 @@
+//! Two-party asset swaps coordinated through the tapd mailbox. Each party
+//! funds a virtual PSBT for the leg it gives; the initiator commits both
+//! legs to one anchor transaction, both lnd wallets sign it, and the
+//! initiator publishes it, so either both legs settle or neither does.
+//!
+//! Messages travel base64-encoded JSON in the mailbox payload; the party
+//! reading its mailbox hands each one to its gateway:
+//!
+//! 1. initiator → `proposal`: the terms, and the initiator's funded leg;
+//! 2. responder → `accept`: its signed leg, and an address for satoshis;
+//! 3. initiator → `sign_request`: the committed anchor, signed for its inputs;
+//! 4. responder → `signed`: the anchor, signed for its inputs too;
+//! 5. initiator → `settled`: the published anchor transaction.
+//!
+//! Either side may send `cancel` before settlement. A swap still open at its
+//! timeout expires, and the leases tapd holds on this side's funded inputs
+//! are released. Every status change is published as `swap.<status>` and
+//! sent to the swap's callback URL.
+
+use crate::api::backend;
+use crate::database::{SharedDatabase, Swap, SwapRole, SwapStatus, WebhookDelivery};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::mailbox_outbox::{is_transient, MailboxOutbox};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bitcoin::psbt::Psbt;
+use bitcoin::{absolute, transaction, Amount, OutPoint, ScriptBuf, Transaction, TxOut};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use tracing::{info, warn};
+
+/// Topic prefix for swap events; the suffix is the new status.
+pub const TOPIC_PREFIX: &str = "swap.";
+
+/// Confirmation target for the anchor transaction's fee.
+const ANCHOR_TARGET_CONF: u32 = 6;
+
+/// Value of each anchor output in the template; tapd sets the real one.
+const TEMPLATE_ANCHOR_SATS: u64 = 1_000;
+
+/// tappsbt key type of a virtual output's anchor output index.
+const PSBT_OUT_TAP_ANCHOR_OUTPUT_INDEX: u8 = 0x72;
+
+/// What one party gives: `amount` units of `asset_id`, or satoshis when
+/// there is no asset id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapLeg {
+    pub asset_id: Option<String>,
+    pub amount: u64,
+}
+
+/// A message between the two parties. Legs in a proposal are named from the
+/// initiator's side.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SwapMessage {
+    Proposal {
+        swap_id: String,
+        /// Mailbox receiver id the responder answers to.
+        reply_to: String,
+        offer: SwapLeg,
+        ask: SwapLeg,
+        /// The initiator's funded, unsigned leg, so the responder can pick
+        /// anchor outputs that do not collide with it.
+        funded_psbt: Option<String>,
+        expires_at: i64,
+    },
+    Accept {
+        swap_id: String,
+        signed_psbt: String,
+        #[serde(default)]
+        passive_asset_psbts: Vec<String>,
+        /// Where the initiator pays a satoshi offer.
+        btc_address: Option<String>,
+    },
+    SignRequest {
+        swap_id: String,
+        anchor_psbt: String,
+        virtual_psbts: Vec<String>,
+        #[serde(default)]
+        passive_asset_psbts: Vec<String>,
+    },
+    Signed {
+        swap_id: String,
+        anchor_psbt: String,
+    },
+    Settled {
+        swap_id: String,
+        anchor_psbt: String,
+        anchor_txid: String,
+    },
+    Cancel {
+        swap_id: String,
+    },
+}
+
+impl SwapMessage {
+    pub fn swap_id(&self) -> &str {
+        match self {
+            SwapMessage::Proposal { swap_id, .. }
+            | SwapMessage::Accept { swap_id, .. }
+            | SwapMessage::SignRequest { swap_id, .. }
+            | SwapMessage::Signed { swap_id, .. }
+            | SwapMessage::Settled { swap_id, .. }
+            | SwapMessage::Cancel { swap_id } => swap_id,
+        }
+    }
+
+    /// The message's `type`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SwapMessage::Proposal { .. } => "proposal",
+            SwapMessage::Accept { .. } => "accept",
+            SwapMessage::SignRequest { .. } => "sign_request",
+            SwapMessage::Signed { .. } => "signed",
+            SwapMessage::Settled { .. } => "settled",
+            SwapMessage::Cancel { .. } => "cancel",
+        }
+    }
+
+    /// The mailbox payload carrying this message.
+    pub fn encode(&self) -> Result<String, AppError> {
+        Ok(BASE64.encode(serde_json::to_vec(self)?))
+    }
+
+    pub fn decode(payload: &str) -> Result<Self, AppError> {
+        let bytes = BASE64
+            .decode(payload.trim())
+            .map_err(|e| AppError::InvalidInput(format!("Swap message is not base64: {e}")))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| AppError::InvalidInput(format!("Not a swap message: {e}")))
+    }
+}
+
+fn decode_psbt(psbt: &str) -> Result<Psbt, AppError> {
+    let bytes = BASE64
+        .decode(psbt.trim())
+        .map_err(|e| AppError::InvalidInput(format!("PSBT is not base64: {e}")))?;
+    Psbt::deserialize(&bytes).map_err(|e| AppError::InvalidInput(format!("Invalid PSBT: {e}")))
+}
+
+/// Outpoints a PSBT spends. For a virtual PSBT these are the anchor outputs
+/// holding its asset inputs, which tapd leases while the PSBT is funded.
+pub fn input_outpoints(psbt: &str) -> Result<Vec<OutPoint>, AppError> {
+    Ok(decode_psbt(psbt)?
+        .unsigned_tx
+        .input
+        .iter()
+        .map(|input| input.previous_output)
+        .collect())
+}
+
+/// The anchor transaction template `virtual-psbt/commit` expects: one
+/// placeholder output per anchor output index the virtual PSBTs use, then
+/// `payment` if a leg is paid in satoshis.
+pub fn anchor_template(
+    virtual_psbts: &[String],
+    payment: Option<(ScriptBuf, u64)>,
+) -> Result<String, AppError> {
+    let mut anchor_outputs = 0;
+    for psbt in virtual_psbts {
+        for output in decode_psbt(psbt)?.outputs {
+            let index = output
+                .unknown
+                .iter()
+                .find(|(key, _)| key.type_value == PSBT_OUT_TAP_ANCHOR_OUTPUT_INDEX)
+                .map(|(_, value)| match value.as_slice() {
+                    [a, b, c, d] => Ok(u32::from_be_bytes([*a, *b, *c, *d])),
+                    _ => Err(AppError::InvalidInput(
+                        "Virtual output has a malformed anchor output index".to_string(),
+                    )),
+                })
+                .transpose()?
+                .ok_or_else(|| {
+                    AppError::InvalidInput("Not a virtual PSBT: output has no anchor".to_string())
+                })?;
+            anchor_outputs = anchor_outputs.max(index as usize + 1);
+        }
+    }
+    // OP_1 <32 zero bytes>, tapd's placeholder taproot output
+    let mut placeholder = vec![0x51, 0x20];
+    placeholder.extend([0u8; 32]);
+    let mut output: Vec<TxOut> = (0..anchor_outputs)
+        .map(|_| TxOut {
+            value: Amount::from_sat(TEMPLATE_ANCHOR_SATS),
+            script_pubkey: ScriptBuf::from_bytes(placeholder.clone()),
+        })
+        .collect();
+    if let Some((script_pubkey, sats)) = payment {
+        output.push(TxOut {
+            value: Amount::from_sat(sats),
+            script_pubkey,
+        });
+    }
+    let psbt = Psbt::from_unsigned_tx(Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: Vec::new(),
+        output,
+    })
+    .map_err(|e| AppError::InvalidInput(format!("Invalid anchor template: {e}")))?;
+    Ok(BASE64.encode(psbt.serialize()))
+}
+
+fn address_script(address: &str) -> Result<ScriptBuf, AppError> {
+    bitcoin::Address::from_str(address.trim())
+        .map(|address| address.assume_checked().script_pubkey())
+        .map_err(|e| AppError::InvalidInput(format!("Invalid bitcoin address: {e}")))
+}
+
+/// Whether the anchor transaction pays at least `sats` to `address`.
+pub fn anchor_pays(anchor_psbt: &str, address: &str, sats: u64) -> Result<bool, AppError> {
+    let script = address_script(address)?;
+    Ok(decode_psbt(anchor_psbt)?
+        .unsigned_tx
+        .output
+        .iter()
+        .any(|output| output.script_pubkey == script && output.value.to_sat() >= sats))
+}
+
+fn anchor_txid(anchor_psbt: &str) -> Result<String, AppError> {
+    Ok(decode_psbt(anchor_psbt)?
+        .unsigned_tx
+        .compute_txid()
+        .to_string())
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, AppError> {
+    value
+        .get(name)
+        .ok_or_else(|| AppError::SerializationError(format!("Response has no {name}")))
+}
+
+fn string_field(value: &Value, name: &str) -> Result<String, AppError> {
+    field(value, name)?
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| AppError::SerializationError(format!("{name} is not a string")))
+}
+
+fn strings_field(value: &Value, name: &str) -> Result<Vec<String>, AppError> {
+    Ok(serde_json::from_value(
+        value.get(name).cloned().unwrap_or(json!([])),
+    )?)
+}
+
+#[derive(Clone)]
+pub struct SwapCoordinator {
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+    /// lnd REST URL and hex macaroon; its wallet signs the anchor inputs.
+    lnd: Option<(String, String)>,
+    database: SharedDatabase,
+    events: SharedEventBus,
+    outbox: Option<MailboxOutbox>,
+}
+
+impl SwapCoordinator {
+    pub fn new(
+        client: Client,
+        base_url: String,
+        macaroon_hex: String,
+        lnd: Option<(String, String)>,
+        database: SharedDatabase,
+        events: SharedEventBus,
+    ) -> Self {
+        Self {
+            client,
+            base_url,
+            macaroon_hex,
+            lnd,
+            database,
+            events,
+            outbox: None,
+        }
+    }
+
+    /// Sends messages through the mailbox outbox, so they are retried.
+    pub fn with_outbox(mut self, outbox: Option<MailboxOutbox>) -> Self {
+        self.outbox = outbox;
+        self
+    }
+
+    /// Records a new swap as initiator and sends the proposal.
+    pub async fn propose(&self, swap: Swap, reply_to: String) -> Result<Swap, AppError> {
+        self.database.insert_swap(&swap).await?;
+        let proposal = SwapMessage::Proposal {
+            swap_id: swap.id.clone(),
+            reply_to,
+            offer: SwapLeg {
+                asset_id: swap.offer_asset_id.clone(),
+                amount: swap.offer_amount,
+            },
+            ask: SwapLeg {
+                asset_id: swap.ask_asset_id.clone(),
+                amount: swap.ask_amount,
+            },
+            funded_psbt: swap.own_psbt.clone(),
+            expires_at: swap.expires_at,
+        };
+        if let Err(e) = self.send(&swap, &proposal).await {
+            return self.fail(swap, SwapStatus::Proposed, e).await;
+        }
+        self.announce(&swap).await?;
+        info!("Proposed swap {} to {}", swap.id, swap.counterparty_id);
+        Ok(swap)
+    }
+
+    /// Signs the responder's funded leg and accepts the proposal. `swap`
+    /// holds the proposal's terms from the responder's side.
+    pub async fn accept(&self, mut swap: Swap) -> Result<Swap, AppError> {
+        let funded = swap.own_psbt.clone().ok_or_else(|| {
+            AppError::ValidationError("funded_psbt is required to give an asset".to_string())
+        })?;
+        if swap.ask_asset_id.is_none() {
+            swap.btc_address = Some(self.new_btc_address().await?);
+        }
+        let signed = self
+            .tapd(
+                "/v1/taproot-assets/wallet/virtual-psbt/sign",
+                json!({ "funded_psbt": funded }),
+            )
+            .await?;
+        let signed_psbt = string_field(&signed, "signed_psbt")?;
+        self.database.insert_swap(&swap).await?;
+
+        let accept = SwapMessage::Accept {
+            swap_id: swap.id.clone(),
+            signed_psbt,
+            passive_asset_psbts: swap.passive_asset_psbts.clone(),
+            btc_address: swap.btc_address.clone(),
+        };
+        if let Err(e) = self.send(&swap, &accept).await {
+            return self.fail(swap, SwapStatus::Accepted, e).await;
+        }
+        self.announce(&swap).await?;
+        info!("Accepted swap {} from {}", swap.id, swap.counterparty_id);
+        Ok(swap)
+    }
+
+    /// Handles a message the counterparty sent about swap `id`, answering
+    /// it with the next step.
+    pub async fn receive(&self, id: &str, message: SwapMessage) -> Result<Swap, AppError> {
+        if message.swap_id() != id {
+            return Err(AppError::ValidationError(format!(
+                "Message is for swap {}, not {id}",
+                message.swap_id()
+            )));
+        }
+        let swap = self
+            .database
+            .get_swap(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Swap {id} not found")))?;
+        if let SwapMessage::Cancel { .. } = message {
+            return self.close(swap, SwapStatus::Cancelled, false).await;
+        }
+        if swap.status.is_open() && chrono::Utc::now().timestamp() >= swap.expires_at {
+            return Err(AppError::ValidationError(format!("Swap {id} has expired")));
+        }
+
+        let from = swap.status;
+        let step = match (swap.role, from, message) {
+            (
+                SwapRole::Initiator,
+                SwapStatus::Proposed,
+                SwapMessage::Accept {
+                    signed_psbt,
+                    passive_asset_psbts,
+                    btc_address,
+                    ..
+                },
+            ) => {
+                self.commit(swap.clone(), signed_psbt, passive_asset_psbts, btc_address)
+                    .await
+            }
+            (
+                SwapRole::Responder,
+                SwapStatus::Accepted,
+                SwapMessage::SignRequest {
+                    anchor_psbt,
+                    virtual_psbts,
+                    passive_asset_psbts,
+                    ..
+                },
+            ) => {
+                self.sign(
+                    swap.clone(),
+                    anchor_psbt,
+                    virtual_psbts,
+                    passive_asset_psbts,
+                )
+                .await
+            }
+            (
+                SwapRole::Initiator,
+                SwapStatus::Committed,
+                SwapMessage::Signed { anchor_psbt, .. },
+            ) => self.publish(swap.clone(), anchor_psbt).await,
+            (
+                SwapRole::Responder,
+                SwapStatus::Signed,
+                SwapMessage::Settled {
+                    anchor_psbt,
+                    anchor_txid,
+                    ..
+                },
+            ) => self.settled(swap.clone(), anchor_psbt, anchor_txid).await,
+            (role, status, message) => {
+                return Err(AppError::ValidationError(format!(
+                    "Swap {id} is {} as {} and does not take a {} message",
+                    status.as_str(),
+                    role.as_str(),
+                    message.kind()
+                )))
+            }
+        };
+        let (mut swap, reply) = match step {
+            Ok(step) => step,
+            // The counterparty's message can be handed in again
+            Err(e) if is_transient(&e) => return Err(e),
+            Err(e) => return self.fail(swap, from, e).await,
+        };
+
+        swap.updated_at = chrono::Utc::now().timestamp();
+        if !self.database.advance_swap(&swap, from).await? {
+            return Err(AppError::ValidationError(format!(
+                "Swap {id} changed concurrently"
+            )));
+        }
+        if let Some(reply) = reply {
+            if let Err(e) = self.send(&swap, &reply).await {
+                warn!("Failed to send {} for swap {}: {}", reply.kind(), id, e);
+                swap.last_error = Some(format!("sending {}: {e}", reply.kind()));
+                self.database.advance_swap(&swap, swap.status).await?;
+            }
+        }
+        self.announce(&swap).await?;
+        Ok(swap)
+    }
+
+    /// Initiator: signs its own leg and commits both to an anchor
+    /// transaction, which its lnd wallet signs for the inputs it funds.
+    async fn commit(
+        &self,
+        mut swap: Swap,
+        counterparty_psbt: String,
+        counterparty_passive_psbts: Vec<String>,
+        btc_address: Option<String>,
+    ) -> Result<(Swap, Option<SwapMessage>), AppError> {
+        let mut virtual_psbts = Vec::new();
+        if let Some(funded) = &swap.own_psbt {
+            let signed = self
+                .tapd(
+                    "/v1/taproot-assets/wallet/virtual-psbt/sign",
+                    json!({ "funded_psbt": funded }),
+                )
+                .await?;
+            virtual_psbts.push(string_field(&signed, "signed_psbt")?);
+        }
+        virtual_psbts.push(counterparty_psbt.clone());
+        let mut passive_asset_psbts = swap.passive_asset_psbts.clone();
+        passive_asset_psbts.extend(counterparty_passive_psbts);
+
+        let payment = match (&swap.offer_asset_id, btc_address) {
+            (Some(_), _) => None,
+            (None, Some(address)) => {
+                let script = address_script(&address)?;
+                swap.btc_address = Some(address);
+                Some((script, swap.offer_amount))
+            }
+            (None, None) => {
+                return Err(AppError::ValidationError(
+                    "The accept message has no btc_address to pay".to_string(),
+                ))
+            }
+        };
+        let template = anchor_template(&virtual_psbts, payment)?;
+        let mut anchor = self
+            .tapd(
+                "/v1/taproot-assets/wallet/virtual-psbt/commit",
+                json!({
+                    "virtual_psbts": virtual_psbts,
+                    "passive_asset_psbts": passive_asset_psbts,
+                    "anchor_psbt": template,
+                    "add": true,
+                    "target_conf": ANCHOR_TARGET_CONF,
+                }),
+            )
+            .await?;
+        let signed = self
+            .lnd(
+                "/v2/wallet/psbt/sign",
+                Some(json!({ "funded_psbt": string_field(&anchor, "anchor_psbt")? })),
+            )
+            .await?;
+        let anchor_psbt = string_field(&signed, "signed_psbt")?;
+        anchor["anchor_psbt"] = json!(anchor_psbt);
+
+        let reply = SwapMessage::SignRequest {
+            swap_id: swap.id.clone(),
+            anchor_psbt,
+            virtual_psbts: strings_field(&anchor, "virtual_psbts")?,
+            passive_asset_psbts: strings_field(&anchor, "passive_asset_psbts")?,
+        };
+        swap.counterparty_psbt = Some(counterparty_psbt);
+        swap.anchor = Some(anchor);
+        swap.status = SwapStatus::Committed;
+        Ok((swap, Some(reply)))
+    }
+
+    /// Responder: checks the anchor transaction spends its leg and pays any
+    /// satoshis it is owed, then signs its inputs.
+    async fn sign(
+        &self,
+        mut swap: Swap,
+        anchor_psbt: String,
+        virtual_psbts: Vec<String>,
+        passive_asset_psbts: Vec<String>,
+    ) -> Result<(Swap, Option<SwapMessage>), AppError> {
+        if swap.ask_asset_id.is_none() {
+            let address = swap.btc_address.as_deref().ok_or_else(|| {
+                AppError::ValidationError("Swap has no address for its satoshis".to_string())
+            })?;
+            if !anchor_pays(&anchor_psbt, address, swap.ask_amount)? {
+                return Err(AppError::ValidationError(format!(
+                    "Anchor transaction does not pay {} sats to {address}",
+                    swap.ask_amount
+                )));
+            }
+        }
+        let spent = input_outpoints(&anchor_psbt)?;
+        let own = swap.own_psbt.as_deref().unwrap_or_default();
+        if input_outpoints(own)?
+            .iter()
+            .any(|outpoint| !spent.contains(outpoint))
+        {
+            return Err(AppError::ValidationError(
+                "Anchor transaction does not spend this side's leg".to_string(),
+            ));
+        }
+
+        let signed = self
+            .lnd(
+                "/v2/wallet/psbt/sign",
+                Some(json!({ "funded_psbt": anchor_psbt })),
+            )
+            .await?;
+        if field(&signed, "signed_inputs")
+            .ok()
+            .and_then(Value::as_array)
+            .is_none_or(Vec::is_empty)
+        {
+            return Err(AppError::ValidationError(
+                "lnd signed none of the anchor transaction's inputs".to_string(),
+            ));
+        }
+        let anchor_psbt = string_field(&signed, "signed_psbt")?;
+        let reply = SwapMessage::Signed {
+            swap_id: swap.id.clone(),
+            anchor_psbt: anchor_psbt.clone(),
+        };
+        swap.anchor = Some(json!({
+            "anchor_psbt": anchor_psbt,
+            "virtual_psbts": virtual_psbts,
+            "passive_asset_psbts": passive_asset_psbts,
+        }));
+        swap.status = SwapStatus::Signed;
+        Ok((swap, Some(reply)))
+    }
+
+    /// Initiator: finalizes the fully signed anchor transaction and has
+    /// tapd publish it and record the transfer.
+    async fn publish(
+        &self,
+        mut swap: Swap,
+        anchor_psbt: String,
+    ) -> Result<(Swap, Option<SwapMessage>), AppError> {
+        let mut anchor = swap
+            .anchor
+            .clone()
+            .ok_or_else(|| AppError::DatabaseError("Committed swap has no anchor".to_string()))?;
+        let txid = anchor_txid(&anchor_psbt)?;
+        if txid != anchor_txid(&string_field(&anchor, "anchor_psbt")?)? {
+            return Err(AppError::ValidationError(
+                "Signed anchor transaction differs from the committed one".to_string(),
+            ));
+        }
+        let finalized = self
+            .lnd(
+                "/v2/wallet/psbt/finalize",
+                Some(json!({ "funded_psbt": anchor_psbt })),
+            )
+            .await?;
+        let final_psbt = string_field(&finalized, "signed_psbt")?;
+        self.tapd(
+            "/v1/taproot-assets/wallet/virtual-psbt/log-transfer",
+            json!({
+                "anchor_psbt": final_psbt,
+                "virtual_psbts": field(&anchor, "virtual_psbts")?,
+                "passive_asset_psbts": anchor.get("passive_asset_psbts").cloned().unwrap_or(json!([])),
+                "change_output_index": anchor.get("change_output_index").cloned().unwrap_or(json!(-1)),
+                "lnd_locked_utxos": anchor.get("lnd_locked_utxos").cloned().unwrap_or(json!([])),
+                "skip_anchor_tx_broadcast": false,
+            }),
+        )
+        .await?;
+        info!("Swap {} settled in {}", swap.id, txid);
+
+        anchor["anchor_psbt"] = json!(final_psbt);
+        let reply = SwapMessage::Settled {
+            swap_id: swap.id.clone(),
+            anchor_psbt: final_psbt,
+            anchor_txid: txid.clone(),
+        };
+        swap.anchor = Some(anchor);
+        swap.anchor_txid = Some(txid);
+        swap.status = SwapStatus::Settled;
+        Ok((swap, Some(reply)))
+    }
+
+    /// Responder: records the published transaction with its tapd, which
+    /// does not broadcast it again.
+    async fn settled(
+        &self,
+        mut swap: Swap,
+        anchor_psbt: String,
+        txid: String,
+    ) -> Result<(Swap, Option<SwapMessage>), AppError> {
+        let anchor = swap
+            .anchor
+            .clone()
+            .ok_or_else(|| AppError::DatabaseError("Signed swap has no anchor".to_string()))?;
+        let signed_txid = anchor_txid(&string_field(&anchor, "anchor_psbt")?)?;
+        if anchor_txid(&anchor_psbt)? != signed_txid || txid != signed_txid {
+            return Err(AppError::ValidationError(
+                "Settled transaction differs from the signed one".to_string(),
+            ));
+        }
+        // The transaction is out either way; a failure here only leaves
+        // tapd's view stale
+        if let Err(e) = self
+            .tapd(
+                "/v1/taproot-assets/wallet/virtual-psbt/log-transfer",
+                json!({
+                    "anchor_psbt": anchor_psbt,
+                    "virtual_psbts": field(&anchor, "virtual_psbts")?,
+                    "passive_asset_psbts": anchor.get("passive_asset_psbts").cloned().unwrap_or(json!([])),
+                    "change_output_index": -1,
+                    "lnd_locked_utxos": [],
+                    "skip_anchor_tx_broadcast": true,
+                }),
+            )
+            .await
+        {
+            warn!("Failed to log settled swap {}: {}", swap.id, e);
+            swap.last_error = Some(format!("logging transfer: {e}"));
+        }
+        swap.anchor_txid = Some(txid);
+        swap.status = SwapStatus::Settled;
+        Ok((swap, None))
+    }
+
+    /// Releases this side's leases on a swap that can no longer settle, and
+    /// tells the counterparty.
+    async fn close(
+        &self,
+        mut swap: Swap,
+        status: SwapStatus,
+        notify: bool,
+    ) -> Result<Swap, AppError> {
+        if !swap.status.is_open() {
+            return Err(AppError::ValidationError(format!(
+                "Swap {} is {} and cannot be closed",
+                swap.id,
+                swap.status.as_str()
+            )));
+        }
+        let from = swap.status;
+        swap.status = match self.release_leases(&swap).await {
+            Ok(0) => status,
+            Ok(_) => SwapStatus::Refunded,
+            Err(e) => {
+                warn!("Failed to release leases of swap {}: {}", swap.id, e);
+                swap.last_error = Some(format!("releasing leases: {e}"));
+                status
+            }
+        };
+        swap.updated_at = chrono::Utc::now().timestamp();
+        if !self.database.advance_swap(&swap, from).await? {
+            return Err(AppError::ValidationError(format!(
+                "Swap {} changed concurrently",
+                swap.id
+            )));
+        }
+        if notify {
+            let cancel = SwapMessage::Cancel {
+                swap_id: swap.id.clone(),
+            };
+            if let Err(e) = self.send(&swap, &cancel).await {
+                warn!("Failed to notify counterparty of swap {}: {}", swap.id, e);
+            }
+        }
+        self.announce(&swap).await?;
+        Ok(swap)
+    }
+
+    /// Cancels an open swap.
+    pub async fn cancel(&self, id: &str) -> Result<Swap, AppError> {
+        let swap = self
+            .database
+            .get_swap(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Swap {id} not found")))?;
+        self.close(swap, SwapStatus::Cancelled, true).await
+    }
+
+    /// Expires open swaps past their timeout and returns how many.
+    pub async fn expire_due(&self) -> Result<usize, AppError> {
+        let due = self
+            .database
+            .expired_swaps(chrono::Utc::now().timestamp())
+            .await?;
+        let mut expired = 0;
+        for swap in due {
+            let id = swap.id.clone();
+            match self.close(swap, SwapStatus::Expired, true).await {
+                Ok(_) => expired += 1,
+                Err(e) => warn!("Failed to expire swap {}: {}", id, e),
+            }
+        }
+        if expired > 0 {
+            info!("Expired {} swaps", expired);
+        }
+        Ok(expired)
+    }
+
+    /// Releases tapd's leases on the inputs of this side's funded leg.
+    async fn release_leases(&self, swap: &Swap) -> Result<usize, AppError> {
+        let Some(psbt) = &swap.own_psbt else {
+            return Ok(0);
+        };
+        let outpoints = input_outpoints(psbt)?;
+        for outpoint in &outpoints {
+            self.tapd(
+                "/v1/taproot-assets/wallet/utxo-lease/delete",
+                json!({
+                    "outpoint": {
+                        "txid": BASE64.encode(outpoint.txid.as_ref() as &[u8]),
+                        "output_index": outpoint.vout,
+                    }
+                }),
+            )
+            .await?;
+        }
+        Ok(outpoints.len())
+    }
+
+    /// Fails the swap and releases this side's leases.
+    async fn fail(
+        &self,
+        mut swap: Swap,
+        from: SwapStatus,
+        error: AppError,
+    ) -> Result<Swap, AppError> {
+        warn!("Swap {} failed: {}", swap.id, error);
+        swap.status = SwapStatus::Failed;
+        swap.last_error = Some(error.to_string());
+        swap.updated_at = chrono::Utc::now().timestamp();
+        if self.database.advance_swap(&swap, from).await? {
+            if let Err(e) = self.release_leases(&swap).await {
+                warn!("Failed to release leases of swap {}: {}", swap.id, e);
+            }
+            self.announce(&swap).await?;
+        }
+        Err(error)
+    }
+
+    /// Sends `message` to the counterparty's mailbox.
+    async fn send(&self, swap: &Swap, message: &SwapMessage) -> Result<(), AppError> {
+        let request = json!({
+            "receiver_id": swap.counterparty_id,
+            "encrypted_payload": message.encode()?,
+            "tx_proof": swap.tx_proof,
+        });
+        match &self.outbox {
+            Some(outbox) => {
+                outbox
+                    .enqueue(request, swap.counterparty_id.clone(), None, None)
+                    .await?;
+            }
+            None => {
+                backend(&self.client, &self.base_url, &self.macaroon_hex)
+                    .post("/v1/taproot-assets/mailbox/send")
+                    .json(&request)
+                    .fetch::<Value>()
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn tapd(&self, path: &str, body: Value) -> Result<Value, AppError> {
+        backend(&self.client, &self.base_url, &self.macaroon_hex)
+            .post(path)
+            .json(&body)
+            .fetch::<Value>()
+            .await
+    }
+
+    async fn lnd(&self, path: &str, body: Option<Value>) -> Result<Value, AppError> {
+        let Some((lnd_url, lnd_macaroon_hex)) = &self.lnd else {
+            return Err(AppError::ServiceUnavailable(
+                "Swaps require LND_URL to sign anchor transactions".to_string(),
+            ));
+        };
+        let request = match body {
+            Some(body) => self.client.post(format!("{lnd_url}{path}")).json(&body),
+            None => self.client.get(format!("{lnd_url}{path}")),
+        };
+        let response = request
+            .header("Grpc-Metadata-macaroon", lnd_macaroon_hex)
+            .send()
+            .await?;
+        crate::api::parse_upstream(response).await
+    }
+
+    async fn new_btc_address(&self) -> Result<String, AppError> {
+        let address = self.lnd("/v1/newaddress?type=TAPROOT_PUBKEY", None).await?;
+        string_field(&address, "address")
+    }
+
+    /// Publishes the swap's status and queues its callback.
+    async fn announce(&self, swap: &Swap) -> Result<(), AppError> {
+        let topic = format!("{TOPIC_PREFIX}{}", swap.status.as_str());
+        let payload = serde_json::to_value(swap)?;
+        self.events.publish(&topic, payload.clone());
+        if let Some(callback_url) = swap.callback_url.clone() {
+            self.database
+                .enqueue_webhook(&WebhookDelivery::new(
+                    format!("{topic}:{}", swap.id),
+                    &topic,
+                    callback_url,
+                    swap.callback_secret.clone(),
+                    payload,
+                ))
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::psbt::raw;
+    use bitcoin::{Sequence, TxIn, Txid, Witness};
+
+    /// A virtual PSBT spending `outpoint` with one output per anchor index.
+    fn virtual_psbt(outpoint: OutPoint, anchor_indexes: &[u32]) -> String {
+        let mut psbt = Psbt::from_unsigned_tx(Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: anchor_indexes
+                .iter()
+                .map(|_| TxOut {
+                    value: Amount::from_sat(1),
+                    script_pubkey: ScriptBuf::new(),
+                })
+                .collect(),
+        })
+        .unwrap();
+        for (output, index) in psbt.outputs.iter_mut().zip(anchor_indexes) {
+            output.unknown.insert(
+                raw::Key {
+                    type_value: PSBT_OUT_TAP_ANCHOR_OUTPUT_INDEX,
+                    key: Vec::new(),
+                },
+                index.to_be_bytes().to_vec(),
+            );
+        }
+        BASE64.encode(psbt.serialize())
+    }
+
+    fn outpoint(byte: u8, vout: u32) -> OutPoint {
+        OutPoint {
+            txid: Txid::from_str(&format!("{byte:02x}").repeat(32)).unwrap(),
+            vout,
+        }
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let message = SwapMessage::SignRequest {
+            swap_id: "s1".to_string(),
+            anchor_psbt: "cHNidP8=".to_string(),
+            virtual_psbts: vec!["a".to_string()],
+            passive_asset_psbts: Vec::new(),
+        };
+        let payload = message.encode().unwrap();
+        assert_eq!(SwapMessage::decode(&payload).unwrap(), message);
+        assert_eq!(message.kind(), "sign_request");
+        assert!(SwapMessage::decode("not base64!").is_err());
+        assert!(SwapMessage::decode(&BASE64.encode(b"{\"type\":\"nope\"}")).is_err());
+    }
+
+    #[test]
+    fn test_anchor_template_covers_both_legs_and_payment() {
+        let legs = vec![
+            virtual_psbt(outpoint(1, 0), &[0, 1]),
+            virtual_psbt(outpoint(2, 3), &[2]),
+        ];
+        let address = "bcrt1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqc8gma6";
+        let payment = Some((address_script(address).unwrap(), 5_000));
+        let template = anchor_template(&legs, payment).unwrap();
+
+        let tx = decode_psbt(&template).unwrap().unsigned_tx;
+        assert_eq!(tx.output.len(), 4);
+        assert!(tx.output[..3]
+            .iter()
+            .all(|o| o.value.to_sat() == TEMPLATE_ANCHOR_SATS));
+        assert!(anchor_pays(&template, address, 5_000).unwrap());
+        assert!(!anchor_pays(&template, address, 5_001).unwrap());
+
+        // An output without an anchor index is not a virtual PSBT
+        let plain = BASE64.encode(decode_psbt(&template).unwrap().serialize());
+        assert!(anchor_template(&[plain], None).is_err());
+    }
+
+    #[test]
+    fn test_input_outpoints() {
+        let psbt = virtual_psbt(outpoint(7, 2), &[0]);
+        assert_eq!(input_outpoints(&psbt).unwrap(), vec![outpoint(7, 2)]);
+        assert!(input_outpoints("cHNidP8=").is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! Multi-tenant mode: each tenant, identified by its API key, is served by
+//! its own tapd and its own gateway database, so one deployment can front
+//! several customers' nodes.
+//!
+//! Like canary routing, the router overrides the request's backend data
+//! (`BaseUrl`, `MacaroonHex`, the WebSocket proxy, the event bus and the
+//! database), so handlers need no tenant awareness. Each tenant's receivers,
+//! webhooks and transfer index live in its own database file rather than in
+//! shared tables.
+
+use crate::database::SharedDatabase;
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::types::{BaseUrl, LndNode, MacaroonHex};
+use crate::websocket::{
+    connection_manager::WebSocketConnectionManager, proxy_handler::WebSocketProxyHandler,
+};
+use actix_web::dev::{Extensions, ServiceRequest};
+use actix_web::http::header::HeaderMap;
+use actix_web::{web, HttpMessage};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::Arc;
+use tracing::info;
+
+/// Gateway routes that act on the deployment as a whole (maintenance,
+/// macaroon delegation, metrics of the primary node). Tenants are refused.
+pub const OPERATOR_PATH_PREFIXES: [&str; 4] = [
+    "/v1/gateway/admin",
+    "/v1/gateway/monitor",
+    "/v1/gateway/metrics",
+    "/v1/gateway/stats",
+];
+
+/// One entry of the `TENANTS_FILE` JSON array.
+#[derive(Clone, Deserialize)]
+pub struct TenantConfig {
+    pub name: String,
+    pub api_key: String,
+    /// tapd REST `host:port`.
+    pub host: String,
+    pub macaroon_path: String,
+    /// Required whenever the gateway itself has a database.
+    #[serde(default)]
+    pub database_url: Option<String>,
+    /// The tenant's lnd, for confirmation tracking and channel events.
+    #[serde(default)]
+    pub lnd_url: Option<String>,
+    #[serde(default)]
+    pub lnd_macaroon_path: Option<String>,
+}
+
+impl TenantConfig {
+    /// Reads and checks the tenants file.
+    pub fn load_file(path: &str) -> Result<Vec<TenantConfig>, AppError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            AppError::ValidationError(format!("Cannot read TENANTS_FILE {path}: {e}"))
+        })?;
+        let tenants: Vec<TenantConfig> = serde_json::from_str(&contents)
+            .map_err(|e| AppError::ValidationError(format!("Invalid TENANTS_FILE: {e}")))?;
+        validate_tenants(&tenants)?;
+        Ok(tenants)
+    }
+}
+
+fn validate_tenants(tenants: &[TenantConfig]) -> Result<(), AppError> {
+    let mut names = HashSet::new();
+    let mut keys = HashSet::new();
+    for tenant in tenants {
+        if tenant.name.is_empty()
+            || !tenant
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(AppError::ValidationError(format!(
+                "Tenant name '{}' must be non-empty and use only letters, digits, - and _",
+                tenant.name
+            )));
+        }
+        if !names.insert(tenant.name.as_str()) {
+            return Err(AppError::ValidationError(format!(
+                "Duplicate tenant name '{}'",
+                tenant.name
+            )));
+        }
+        if tenant.api_key.is_empty() || !keys.insert(tenant.api_key.as_str()) {
+            return Err(AppError::ValidationError(format!(
+                "Tenant '{}' needs its own non-empty api_key",
+                tenant.name
+            )));
+        }
+        if !tenant.host.contains(':') {
+            return Err(AppError::ValidationError(format!(
+                "Tenant '{}' host must include port (e.g., 10.0.0.5:8089)",
+                tenant.name
+            )));
+        }
+        if tenant.lnd_url.is_some() && tenant.lnd_macaroon_path.is_none() {
+            return Err(AppError::ValidationError(format!(
+                "Tenant '{}' sets lnd_url without lnd_macaroon_path",
+                tenant.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Request extension naming the tenant a request is served for.
+#[derive(Debug, Clone)]
+pub struct TenantRequest(pub String);
+
+/// A tenant's backend and storage, shared by its requests and background
+/// tasks.
+pub struct Tenant {
+    name: String,
+    base_url: web::Data<BaseUrl>,
+    macaroon_hex: web::Data<MacaroonHex>,
+    connection_manager: web::Data<Arc<WebSocketConnectionManager>>,
+    ws_proxy_handler: web::Data<Arc<WebSocketProxyHandler>>,
+    event_bus: web::Data<SharedEventBus>,
+    database: Option<web::Data<SharedDatabase>>,
+    lnd: web::Data<LndNode>,
+}
+
+impl Tenant {
+    pub fn new(
+        name: String,
+        base_url: String,
+        macaroon_hex: String,
+        connection_manager: Arc<WebSocketConnectionManager>,
+        event_bus: SharedEventBus,
+        database: Option<SharedDatabase>,
+        lnd: Option<(String, String)>,
+    ) -> Self {
+        Self {
+            name,
+            base_url: web::Data::new(BaseUrl(base_url)),
+            macaroon_hex: web::Data::new(MacaroonHex(macaroon_hex)),
+            ws_proxy_handler: web::Data::new(Arc::new(WebSocketProxyHandler::new(
+                connection_manager.clone(),
+            ))),
+            connection_manager: web::Data::new(connection_manager),
+            event_bus: web::Data::new(event_bus),
+            database: database.map(web::Data::new),
+            lnd: web::Data::new(LndNode(lnd)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+pub struct TenantRouter {
+    by_api_key: HashMap<String, Arc<Tenant>>,
+}
+
+pub type SharedTenantRouter = Arc<TenantRouter>;
+
+impl TenantRouter {
+    pub fn new(tenants: impl IntoIterator<Item = (String, Tenant)>) -> Self {
+        Self {
+            by_api_key: tenants
+                .into_iter()
+                .map(|(api_key, tenant)| (api_key, Arc::new(tenant)))
+                .collect(),
+        }
+    }
+
+    pub fn tenants(&self) -> impl Iterator<Item = &Arc<Tenant>> {
+        self.by_api_key.values()
+    }
+
+    /// The tenant whose API key the request carries as its bearer token.
+    pub fn tenant_for(&self, headers: &HeaderMap) -> Option<&Arc<Tenant>> {
+        headers
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .and_then(|token| self.by_api_key.get(token))
+    }
+
+    /// Points the request's backend data at the tenant. App data containers
+    /// added later take precedence over the application's own.
+    pub fn route(&self, tenant: &Tenant, req: &mut ServiceRequest) {
+        let mut data = Extensions::new();
+        data.insert(tenant.base_url.clone());
+        data.insert(tenant.macaroon_hex.clone());
+        data.insert(tenant.connection_manager.clone());
+        data.insert(tenant.ws_proxy_handler.clone());
+        data.insert(tenant.event_bus.clone());
+        // Always set, so a tenant without lnd never reaches the primary's
+        data.insert(tenant.lnd.clone());
+        if let Some(database) = &tenant.database {
+            data.insert(database.clone());
+        }
+        req.add_data_container(Rc::new(data));
+        req.extensions_mut()
+            .insert(TenantRequest(tenant.name.clone()));
+    }
+
+    pub fn log_startup(&self) {
+        for tenant in self.tenants() {
+            info!(
+                "Tenant {} is served by {}{}",
+                tenant.name,
+                tenant.base_url.0,
+                if tenant.database.is_some() {
+                    " with its own database"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenant(name: &str, api_key: &str) -> TenantConfig {
+        TenantConfig {
+            name: name.to_string(),
+            api_key: api_key.to_string(),
+            host: "tapd:8089".to_string(),
+            macaroon_path: "/dev/null".to_string(),
+            database_url: None,
+            lnd_url: None,
+            lnd_macaroon_path: None,
+        }
+    }
+
+    #[test]
+    fn test_tenants_need_unique_names_and_keys() {
+        assert!(validate_tenants(&[tenant("acme", "k1"), tenant("globex", "k2")]).is_ok());
+        assert!(validate_tenants(&[tenant("acme", "k1"), tenant("acme", "k2")]).is_err());
+        assert!(validate_tenants(&[tenant("acme", "k1"), tenant("globex", "k1")]).is_err());
+        assert!(validate_tenants(&[tenant("../acme", "k1")]).is_err());
+        assert!(validate_tenants(&[tenant("acme", "")]).is_err());
+    }
+
+    #[test]
+    fn test_tenant_selected_by_bearer_token() {
+        let manager = Arc::new(WebSocketConnectionManager::new(
+            BaseUrl("wss://tapd:8089".to_string()),
+            MacaroonHex("ab".to_string()),
+            true,
+        ));
+        let router = TenantRouter::new([(
+            "acme-token".to_string(),
+            Tenant::new(
+                "acme".to_string(),
+                "https://tapd:8089".to_string(),
+                "ab".to_string(),
+                manager,
+                Arc::new(crate::event_bus::EventBus::default()),
+                None,
+                None,
+            ),
+        )]);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            actix_web::http::header::AUTHORIZATION,
+            "Bearer acme-token".parse().unwrap(),
+        );
+        assert_eq!(router.tenant_for(&headers).unwrap().name(), "acme");
+        headers.insert(
+            actix_web::http::header::AUTHORIZATION,
+            "Bearer other".parse().unwrap(),
+        );
+        assert!(router.tenant_for(&headers).is_none());
+    }
+}
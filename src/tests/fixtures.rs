@@ -0,0 +1,453 @@
+//! Record-and-replay fixtures for the integration tests. With
+//! `TEST_FIXTURES=record`, [`super::setup`] routes tapd, lnd and bitcoind
+//! traffic through local servers that forward it to the real services and
+//! save every exchange; with `TEST_FIXTURES=replay` the same servers answer
+//! from the saved files, so the tests run in CI without a live stack.
+//!
+//! Exchanges are saved per test binary and service under
+//! `TEST_FIXTURES_DIR` (default `tests/fixtures`), e.g.
+//! `tests/fixtures/assets/tapd.json`. Request headers are never saved, and
+//! macaroons and RPC credentials are scrubbed from bodies.
+
+use actix_web::http::header::{CONTENT_LENGTH, CONTENT_TYPE, HOST};
+use actix_web::http::StatusCode;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Stand-in for secrets in saved bodies.
+pub const REDACTED: &str = "<redacted>";
+
+/// Macaroon the tests use in replay mode, where no credentials are read.
+pub const REPLAY_MACAROON: &str = "00";
+
+static FIXTURES: OnceLock<Option<Fixtures>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureMode {
+    Record,
+    Replay,
+}
+
+impl FixtureMode {
+    /// Parses `TEST_FIXTURES`; unset or `off` means live traffic.
+    pub fn from_env() -> Option<Self> {
+        match std::env::var("TEST_FIXTURES")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "off" => None,
+            "record" => Some(FixtureMode::Record),
+            "replay" => Some(FixtureMode::Replay),
+            other => panic!("TEST_FIXTURES must be 'record', 'replay' or 'off', got '{other}'"),
+        }
+    }
+}
+
+/// One saved request and its response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub method: String,
+    /// Path and query string.
+    pub path: String,
+    /// JSON bodies are saved as JSON, anything else as a string.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Value>,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+}
+
+/// The exchanges of one service, in the order they happened.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub exchanges: Vec<Exchange>,
+}
+
+impl Cassette {
+    fn load(path: &Path) -> Self {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .unwrap_or_else(|e| panic!("Invalid fixture file {}: {e}", path.display())),
+            Err(_) => {
+                warn!("No fixtures at {}; every request will miss", path.display());
+                Cassette::default()
+            }
+        }
+    }
+
+    /// The exchange to answer a request with. Repeated requests get the
+    /// recorded responses in order, then the last one again. Requests whose
+    /// body differs from every recording (generated names, fresh addresses)
+    /// fall back to the exchanges with the same method and path.
+    fn replay(
+        &self,
+        served: &mut HashMap<String, usize>,
+        method: &str,
+        path: &str,
+        request: &Option<Value>,
+    ) -> Option<Exchange> {
+        let same_route = |e: &&Exchange| e.method == method && e.path == path;
+        let exact: Vec<&Exchange> = self
+            .exchanges
+            .iter()
+            .filter(same_route)
+            .filter(|e| e.request == *request)
+            .collect();
+        let (candidates, key) = if exact.is_empty() {
+            let any: Vec<&Exchange> = self.exchanges.iter().filter(same_route).collect();
+            (any, format!("{method} {path}"))
+        } else {
+            let body = request.as_ref().map(Value::to_string).unwrap_or_default();
+            (exact, format!("{method} {path} {body}"))
+        };
+        let last = candidates.len().checked_sub(1)?;
+        let count = served.entry(key).or_default();
+        let exchange = candidates[(*count).min(last)].clone();
+        *count += 1;
+        Some(exchange)
+    }
+}
+
+/// Records or replays one service.
+struct Recorder {
+    mode: FixtureMode,
+    upstream: String,
+    path: PathBuf,
+    secrets: Vec<String>,
+    client: Client,
+    cassette: Mutex<Cassette>,
+    served: Mutex<HashMap<String, usize>>,
+}
+
+impl Recorder {
+    fn new(mode: FixtureMode, upstream: String, path: PathBuf, secrets: Vec<String>) -> Self {
+        let cassette = match mode {
+            // A recording run starts the file over
+            FixtureMode::Record => Cassette::default(),
+            FixtureMode::Replay => Cassette::load(&path),
+        };
+        // The services run with self-signed certificates
+        let client = Client::builder()
+            .timeout(Duration::from_secs(120))
+            .danger_accept_invalid_certs(true)
+            .build()
+            .expect("Failed to build fixture client");
+        Self {
+            mode,
+            upstream,
+            path,
+            secrets,
+            client,
+            cassette: Mutex::new(cassette),
+            served: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Saved form of a body: secrets scrubbed, JSON kept structured.
+    fn sanitize(&self, body: &[u8]) -> Option<Value> {
+        if body.is_empty() {
+            return None;
+        }
+        let mut text = String::from_utf8_lossy(body).into_owned();
+        for secret in self.secrets.iter().filter(|s| !s.is_empty()) {
+            text = text.replace(secret.as_str(), REDACTED);
+        }
+        Some(serde_json::from_str(&text).unwrap_or(Value::String(text)))
+    }
+
+    async fn record(&self, req: &HttpRequest, body: web::Bytes) -> Result<Exchange, String> {
+        let method = reqwest::Method::from_bytes(req.method().as_str().as_bytes())
+            .map_err(|e| e.to_string())?;
+        let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+        let url = format!("{}{path}", self.upstream.trim_end_matches('/'));
+        let mut upstream = self.client.request(method, url).body(body.to_vec());
+        for (name, value) in req.headers() {
+            if name != HOST && name != CONTENT_LENGTH {
+                upstream = upstream.header(name.as_str(), value.as_bytes());
+            }
+        }
+        let response = upstream.send().await.map_err(|e| e.to_string())?;
+        let status = response.status().as_u16();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let response = response.bytes().await.map_err(|e| e.to_string())?;
+        let exchange = Exchange {
+            method: req.method().to_string(),
+            path: path.to_string(),
+            request: self.sanitize(&body),
+            status,
+            content_type,
+            response: self.sanitize(&response),
+        };
+
+        let mut cassette = self.cassette.lock().unwrap_or_else(|e| e.into_inner());
+        cassette.exchanges.push(exchange.clone());
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_vec_pretty(&*cassette).map_err(|e| e.to_string())?;
+        fs::write(&self.path, json).map_err(|e| e.to_string())?;
+        Ok(exchange)
+    }
+
+    fn replay(&self, req: &HttpRequest, body: &[u8]) -> Option<Exchange> {
+        let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+        let request = self.sanitize(body);
+        let cassette = self.cassette.lock().unwrap_or_else(|e| e.into_inner());
+        let mut served = self.served.lock().unwrap_or_else(|e| e.into_inner());
+        cassette.replay(&mut served, req.method().as_str(), path, &request)
+    }
+}
+
+async fn serve(
+    req: HttpRequest,
+    body: web::Bytes,
+    recorder: web::Data<Arc<Recorder>>,
+) -> HttpResponse {
+    let exchange = match recorder.mode {
+        FixtureMode::Record => match recorder.record(&req, body).await {
+            Ok(exchange) => exchange,
+            Err(e) => {
+                return HttpResponse::BadGateway().json(serde_json::json!({
+                    "error": format!("Recording {} {} failed: {e}", req.method(), req.uri())
+                }))
+            }
+        },
+        FixtureMode::Replay => match recorder.replay(&req, &body) {
+            Some(exchange) => exchange,
+            None => {
+                return HttpResponse::NotImplemented().json(serde_json::json!({
+                    "error": format!("No recorded exchange for {} {}", req.method(), req.uri())
+                }))
+            }
+        },
+    };
+
+    let status = StatusCode::from_u16(exchange.status).unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut response = HttpResponse::build(status);
+    if let Some(content_type) = &exchange.content_type {
+        response.insert_header((CONTENT_TYPE, content_type.as_str()));
+    }
+    match exchange.response {
+        Some(Value::String(text)) => response.body(text),
+        Some(value) => response.body(value.to_string()),
+        None => response.finish(),
+    }
+}
+
+/// The local URLs tests talk to instead of the real services.
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    pub mode: FixtureMode,
+    pub tapd_url: String,
+    pub lnd_url: String,
+    pub bitcoind_url: String,
+}
+
+/// Starts the fixture servers once per test binary when `TEST_FIXTURES` is
+/// set, and points `LND_URL` and `BITCOIN_RPC_URL` at them. Returns `None`
+/// for live runs.
+pub fn install() -> Option<&'static Fixtures> {
+    FIXTURES.get_or_init(start).as_ref()
+}
+
+/// The running fixture servers, if [`install`] started them.
+pub fn fixtures() -> Option<&'static Fixtures> {
+    FIXTURES.get().and_then(Option::as_ref)
+}
+
+fn start() -> Option<Fixtures> {
+    let mode = FixtureMode::from_env()?;
+    let dir = PathBuf::from(
+        std::env::var("TEST_FIXTURES_DIR").unwrap_or_else(|_| "tests/fixtures".to_string()),
+    )
+    .join(binary_name());
+
+    let var =
+        |name: &str, default: &str| std::env::var(name).unwrap_or_else(|_| default.to_string());
+    let macaroon = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .and_then(|path| fs::read(path).ok())
+            .map(hex::encode)
+            .unwrap_or_default()
+    };
+    let secrets = vec![
+        macaroon("TAPD_MACAROON_PATH"),
+        macaroon("LND_MACAROON_PATH"),
+        var("BITCOIN_RPC_USER", ""),
+        var("BITCOIN_RPC_PASS", ""),
+    ];
+    let services = [
+        (
+            "tapd",
+            format!("https://{}", var("TAPROOT_ASSETS_HOST", "127.0.0.1:8289")),
+        ),
+        ("lnd", var("LND_URL", "https://127.0.0.1:8083")),
+        ("bitcoind", var("BITCOIN_RPC_URL", "http://127.0.0.1:18443")),
+    ];
+
+    let mut urls = Vec::new();
+    let mut servers = Vec::new();
+    for (name, upstream) in services {
+        let listener =
+            TcpListener::bind("127.0.0.1:0").expect("Failed to bind fixture server listener");
+        let addr = listener
+            .local_addr()
+            .expect("Fixture listener has no address");
+        urls.push(format!("http://{addr}"));
+        let path = dir.join(format!("{name}.json"));
+        info!(
+            "{:?}ing {} traffic at {} ({})",
+            mode,
+            name,
+            addr,
+            path.display()
+        );
+        let recorder = Arc::new(Recorder::new(mode, upstream, path, secrets.clone()));
+        servers.push((listener, recorder));
+    }
+
+    // Each test gets its own runtime, so the servers need one of their own
+    // that outlives them
+    std::thread::spawn(move || {
+        actix_rt::System::new().block_on(async move {
+            let mut running = Vec::new();
+            for (listener, recorder) in servers {
+                let server = HttpServer::new(move || {
+                    App::new()
+                        .app_data(web::Data::new(recorder.clone()))
+                        .app_data(web::PayloadConfig::new(64 * 1024 * 1024))
+                        .default_service(web::to(serve))
+                })
+                .workers(1)
+                .disable_signals()
+                .listen(listener)
+                .expect("Failed to start fixture server")
+                .run();
+                running.push(server);
+            }
+            futures::future::join_all(running).await;
+        });
+    });
+
+    let fixtures = Fixtures {
+        mode,
+        tapd_url: urls[0].clone(),
+        lnd_url: urls[1].clone(),
+        bitcoind_url: urls[2].clone(),
+    };
+    std::env::set_var("LND_URL", &fixtures.lnd_url);
+    std::env::set_var("BITCOIN_RPC_URL", &fixtures.bitcoind_url);
+    if mode == FixtureMode::Replay {
+        // Never checked by the replaying servers, but required by the tests
+        for name in ["BITCOIN_RPC_USER", "BITCOIN_RPC_PASS"] {
+            if std::env::var(name).is_err() {
+                std::env::set_var(name, "replay");
+            }
+        }
+    }
+    Some(fixtures)
+}
+
+/// The running test binary's name without cargo's hash suffix
+/// (`assets-1a2b3c4d5e6f7a8b` becomes `assets`).
+fn binary_name() -> String {
+    let exe = std::env::current_exe().unwrap_or_default();
+    let stem = exe
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "tests".to_string());
+    match stem.rsplit_once('-') {
+        Some((name, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            name.to_string()
+        }
+        _ => stem,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn exchange(path: &str, request: Option<Value>, response: Value) -> Exchange {
+        Exchange {
+            method: "POST".to_string(),
+            path: path.to_string(),
+            request,
+            status: 200,
+            content_type: Some("application/json".to_string()),
+            response: Some(response),
+        }
+    }
+
+    #[test]
+    fn test_replay_serves_repeats_in_order_and_falls_back_by_route() {
+        let cassette = Cassette {
+            exchanges: vec![
+                exchange("/v1/taproot-assets/assets", None, json!({ "assets": [] })),
+                exchange("/", Some(json!({ "method": "getblockcount" })), json!(100)),
+                exchange("/v1/taproot-assets/assets", None, json!({ "assets": [1] })),
+                exchange(
+                    "/",
+                    Some(json!({ "method": "getnewaddress" })),
+                    json!("bcrt1q"),
+                ),
+            ],
+        };
+        let mut served = HashMap::new();
+        let mut replay = |path: &str, request: Option<Value>| {
+            cassette
+                .replay(&mut served, "POST", path, &request)
+                .and_then(|e| e.response)
+        };
+
+        let assets = "/v1/taproot-assets/assets";
+        assert_eq!(replay(assets, None), Some(json!({ "assets": [] })));
+        assert_eq!(replay(assets, None), Some(json!({ "assets": [1] })));
+        assert_eq!(replay(assets, None), Some(json!({ "assets": [1] })));
+
+        let rpc = |method: &str| Some(json!({ "method": method }));
+        assert_eq!(replay("/", rpc("getnewaddress")), Some(json!("bcrt1q")));
+        assert_eq!(replay("/", rpc("getblockcount")), Some(json!(100)));
+        // Unknown body, known route
+        assert_eq!(replay("/", rpc("getbestblockhash")), Some(json!(100)));
+        assert_eq!(replay("/v1/taproot-assets/burn", None), None);
+    }
+
+    #[test]
+    fn test_sanitize_scrubs_secrets() {
+        let recorder = Recorder::new(
+            FixtureMode::Replay,
+            "https://127.0.0.1:8289".to_string(),
+            PathBuf::from("/nonexistent/tapd.json"),
+            vec!["0201036c6e64".to_string(), String::new()],
+        );
+        assert_eq!(
+            recorder.sanitize(br#"{"macaroon":"0201036c6e64","id":"x"}"#),
+            Some(json!({ "macaroon": REDACTED, "id": "x" }))
+        );
+        assert_eq!(
+            recorder.sanitize(b"not json"),
+            Some(Value::String("not json".to_string()))
+        );
+        assert_eq!(recorder.sanitize(b""), None);
+    }
+}
@@ -0,0 +1,390 @@
+//! Boots the `itest/` bitcoind + lnd + tapd regtest stack with docker compose
+//! so the integration suite runs against real daemons, instead of assuming
+//! they already listen on fixed ports. Every stack gets its own compose
+//! project and free host ports, so runs collide neither with each other nor
+//! with a local Polar network. Dropping a [`RegtestStack`] tears it down.
+//!
+//! `cargo run --example itest` boots a stack, runs the suite against it with
+//! [`run_suite`] and exits with the suite's status.
+
+use reqwest::blocking::Client;
+use std::ffi::OsStr;
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Test binaries left out of a full run: `stop_daemon` shuts tapd down
+/// mid-run, `benchmarks` and `performance` are load tests.
+pub const EXCLUDED_TESTS: [&str; 3] = ["stop_daemon", "benchmarks", "performance"];
+
+/// Seconds to wait for the tapd image build and for lnd to come up.
+const BOOT_TIMEOUT_SECS: u32 = 900;
+
+/// How often a block is mined while the suite runs, so mints, sends and
+/// funding confirm.
+const BLOCK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often lnd is sent a fresh on-chain output.
+const FUNDING_INTERVAL: Duration = Duration::from_secs(20);
+
+const RPC_USER: &str = "polaruser";
+const RPC_PASS: &str = "polarpass";
+
+/// Host ports the stack's services are published on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ports {
+    pub bitcoind: u16,
+    pub lnd: u16,
+    pub tapd: u16,
+}
+
+impl Ports {
+    /// Ports nothing listens on right now.
+    pub fn free() -> std::io::Result<Self> {
+        Ok(Self {
+            bitcoind: free_port()?,
+            lnd: free_port()?,
+            tapd: free_port()?,
+        })
+    }
+}
+
+fn free_port() -> std::io::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+/// A running regtest stack.
+pub struct RegtestStack {
+    services: Services,
+    stop: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// How to reach the stack's services; shared with the background workers.
+#[derive(Clone)]
+struct Services {
+    itest_dir: PathBuf,
+    project: String,
+    ports: Ports,
+    /// Extracted macaroons; removed with the stack.
+    artifacts: PathBuf,
+}
+
+impl RegtestStack {
+    /// Starts the stack defined in `itest_dir/docker-compose.yml` and waits
+    /// until tapd answers `getinfo`.
+    pub fn up(itest_dir: &Path) -> Result<Self, String> {
+        let ports = Ports::free().map_err(|e| format!("No free ports: {e}"))?;
+        let project = format!(
+            "tapgw-itest-{}",
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        );
+        let artifacts = std::env::temp_dir().join(&project);
+        fs::create_dir_all(&artifacts).map_err(|e| format!("{}: {e}", artifacts.display()))?;
+        // From here on, dropping the stack tears down whatever started
+        let stack = Self {
+            services: Services {
+                itest_dir: itest_dir.to_path_buf(),
+                project,
+                ports,
+                artifacts,
+            },
+            stop: Arc::new(AtomicBool::new(false)),
+            workers: Vec::new(),
+        };
+        let services = &stack.services;
+
+        println!(
+            "==> Building tapd and starting bitcoind + lnd ({})",
+            services.project
+        );
+        let timeout = BOOT_TIMEOUT_SECS.to_string();
+        services.compose([
+            "up",
+            "-d",
+            "--build",
+            "--wait",
+            "--wait-timeout",
+            &timeout,
+            "bitcoind",
+            "lnd",
+        ])?;
+
+        // lnd treats the genesis block's timestamp as "not synced"; fresh
+        // blocks flip it to synced, which is what lets tapd connect. 110 of
+        // them leave matured coinbases to fund lnd from.
+        println!("==> Loading a wallet and mining initial blocks");
+        if services.bitcoin_cli(["createwallet", "itest"]).is_err() {
+            services.bitcoin_cli(["loadwallet", "itest"]).ok();
+        }
+        services.mine(110)?;
+
+        println!("==> Starting tapd");
+        services.compose(["up", "-d", "--wait", "--wait-timeout", "300", "tapd"])?;
+        services.copy_out(
+            "tapd:/home/tap/.tapd/data/regtest/admin.macaroon",
+            "tapd.macaroon",
+        )?;
+        services.copy_out(
+            "lnd:/home/lnd/.lnd/data/chain/bitcoin/regtest/admin.macaroon",
+            "lnd.macaroon",
+        )?;
+        services.wait_for_tapd()?;
+        Ok(stack)
+    }
+
+    pub fn ports(&self) -> Ports {
+        self.services.ports
+    }
+
+    /// Environment the gateway and the test setup read to reach the stack.
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let Services {
+            ports, artifacts, ..
+        } = &self.services;
+        let path = |name: &str| artifacts.join(name).display().to_string();
+        vec![
+            ("TAPROOT_ASSETS_HOST", format!("127.0.0.1:{}", ports.tapd)),
+            ("TAPD_MACAROON_PATH", path("tapd.macaroon")),
+            ("LND_MACAROON_PATH", path("lnd.macaroon")),
+            ("LND_URL", format!("https://127.0.0.1:{}", ports.lnd)),
+            ("TLS_VERIFY", "false".to_string()),
+            ("REQUEST_TIMEOUT_SECS", "30".to_string()),
+            ("RATE_LIMIT_PER_MINUTE", "10000".to_string()),
+            (
+                "BITCOIN_RPC_URL",
+                format!("http://127.0.0.1:{}", ports.bitcoind),
+            ),
+            ("BITCOIN_RPC_USER", RPC_USER.to_string()),
+            ("BITCOIN_RPC_PASS", RPC_PASS.to_string()),
+        ]
+    }
+
+    /// Mines a block every few seconds and keeps lnd supplied with fresh,
+    /// unlocked outputs until the stack is dropped. Asset sends anchor with
+    /// lnd's wallet and the psbt tests lease UTXOs, so a single large
+    /// funding output would get locked mid-suite.
+    pub fn start_block_producer(&mut self) -> Result<(), String> {
+        let lnd = self.services.lnd_wallet()?;
+        for _ in 0..5 {
+            lnd.fund(&self.services);
+        }
+        let miner = self.services.clone();
+        let stop = self.stop.clone();
+        self.workers.push(thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                miner.mine(1).ok();
+                thread::sleep(BLOCK_INTERVAL);
+            }
+        }));
+        let funder = self.services.clone();
+        let stop = self.stop.clone();
+        self.workers.push(thread::spawn(move || {
+            let mut last = Instant::now();
+            while !stop.load(Ordering::Relaxed) {
+                if last.elapsed() >= FUNDING_INTERVAL {
+                    lnd.fund(&funder);
+                    last = Instant::now();
+                }
+                thread::sleep(Duration::from_secs(1));
+            }
+        }));
+        Ok(())
+    }
+}
+
+impl Services {
+    fn compose_command(&self) -> Command {
+        let mut command = Command::new("docker");
+        command
+            .arg("compose")
+            .arg("-f")
+            .arg(self.itest_dir.join("docker-compose.yml"))
+            .arg("-p")
+            .arg(&self.project)
+            .env("BITCOIND_HOST_PORT", self.ports.bitcoind.to_string())
+            .env("LND_HOST_PORT", self.ports.lnd.to_string())
+            .env("TAPD_HOST_PORT", self.ports.tapd.to_string());
+        command
+    }
+
+    fn compose<I, S>(&self, args: I) -> Result<(), String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let status = self
+            .compose_command()
+            .args(args)
+            .status()
+            .map_err(|e| format!("Failed to run docker compose: {e}"))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("docker compose exited with {status}"))
+        }
+    }
+
+    fn bitcoin_cli<I, S>(&self, args: I) -> Result<String, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let output = self
+            .compose_command()
+            .args(["exec", "-T", "bitcoind", "bitcoin-cli", "-regtest"])
+            .arg(format!("-rpcuser={RPC_USER}"))
+            .arg(format!("-rpcpassword={RPC_PASS}"))
+            .args(args)
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|e| format!("Failed to run bitcoin-cli: {e}"))?;
+        if !output.status.success() {
+            return Err(format!("bitcoin-cli exited with {}", output.status));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn mine(&self, blocks: u32) -> Result<(), String> {
+        let address = self.bitcoin_cli(["getnewaddress"])?;
+        self.bitcoin_cli(["generatetoaddress", &blocks.to_string(), &address])
+            .map(|_| ())
+    }
+
+    fn copy_out(&self, source: &str, name: &str) -> Result<(), String> {
+        let target = self.artifacts.join(name);
+        self.compose([OsStr::new("cp"), OsStr::new(source), target.as_os_str()])
+    }
+
+    fn macaroon_hex(&self, name: &str) -> Result<String, String> {
+        fs::read(self.artifacts.join(name))
+            .map(hex::encode)
+            .map_err(|e| format!("Failed to read {name}: {e}"))
+    }
+
+    fn wait_for_tapd(&self) -> Result<(), String> {
+        let client = insecure_client()?;
+        let macaroon = self.macaroon_hex("tapd.macaroon")?;
+        let url = format!(
+            "https://127.0.0.1:{}/v1/taproot-assets/getinfo",
+            self.ports.tapd
+        );
+        for _ in 0..60 {
+            let ready = client
+                .get(&url)
+                .header("Grpc-Metadata-macaroon", &macaroon)
+                .send()
+                .is_ok_and(|res| res.status().is_success());
+            if ready {
+                println!("    tapd is up on port {}", self.ports.tapd);
+                return Ok(());
+            }
+            thread::sleep(Duration::from_secs(2));
+        }
+        Err("tapd never became reachable".to_string())
+    }
+
+    fn lnd_wallet(&self) -> Result<LndWallet, String> {
+        Ok(LndWallet {
+            client: insecure_client()?,
+            url: format!("https://127.0.0.1:{}/v1/newaddress", self.ports.lnd),
+            macaroon: self.macaroon_hex("lnd.macaroon")?,
+        })
+    }
+}
+
+impl Drop for RegtestStack {
+    /// Stops the workers, saves the stack logs to `itest/compose.log` and
+    /// removes the containers and their volumes.
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+        let services = &self.services;
+        if let Ok(logs) = services
+            .compose_command()
+            .args(["logs", "--no-color"])
+            .output()
+        {
+            fs::write(services.itest_dir.join("compose.log"), logs.stdout).ok();
+        }
+        services.compose(["down", "-v", "--remove-orphans"]).ok();
+        fs::remove_dir_all(&services.artifacts).ok();
+    }
+}
+
+/// Sends lnd fresh on-chain outputs.
+struct LndWallet {
+    client: Client,
+    url: String,
+    macaroon: String,
+}
+
+impl LndWallet {
+    fn fund(&self, services: &Services) {
+        let address = self
+            .client
+            .get(&self.url)
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .send()
+            .and_then(|res| res.json::<serde_json::Value>())
+            .ok()
+            .and_then(|json| json["address"].as_str().map(str::to_string));
+        if let Some(address) = address {
+            services.bitcoin_cli(["sendtoaddress", &address, "5"]).ok();
+        }
+    }
+}
+
+/// The stack's daemons use self-signed certificates.
+fn insecure_client() -> Result<Client, String> {
+    Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {e}"))
+}
+
+/// The `tests/` binaries of a full run, as `--test <name>` arguments.
+pub fn suite_targets(tests_dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(tests_dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+        .filter_map(|path| path.file_stem()?.to_str().map(str::to_string))
+        .filter(|name| !EXCLUDED_TESTS.contains(&name.as_str()))
+        .collect();
+    names.sort();
+    Ok(names
+        .into_iter()
+        .flat_map(|name| ["--test".to_string(), name])
+        .collect())
+}
+
+/// Runs `cargo test` against the stack. With no `args` it runs every test
+/// binary but [`EXCLUDED_TESTS`], one test at a time.
+pub fn run_suite(
+    stack: &RegtestStack,
+    repo_dir: &Path,
+    args: &[String],
+) -> std::io::Result<ExitStatus> {
+    let mut command = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()));
+    command.current_dir(repo_dir).arg("test").envs(stack.env());
+    if args.is_empty() {
+        command.args(suite_targets(&repo_dir.join("tests"))?).args([
+            "--no-fail-fast",
+            "--",
+            "--test-threads=1",
+        ]);
+    } else {
+        command.args(args);
+    }
+    println!("==> Running integration suite");
+    command.status()
+}
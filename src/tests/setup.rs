@@ -1,3 +1,4 @@
+use super::fixtures::{self, FixtureMode, REPLAY_MACAROON};
 use crate::api::assets::{MintAsset, MintAssetRequest};
 use crate::config::Config;
 use crate::error::AppError;
@@ -38,6 +39,9 @@ fn init_test_env() {
         if std::env::var("TLS_VERIFY").is_err() {
             std::env::set_var("TLS_VERIFY", "false");
         }
+
+        // TEST_FIXTURES=record|replay routes backend traffic through fixtures
+        fixtures::install();
     });
 }
 
@@ -436,21 +440,39 @@ async fn setup_internal() -> (
     String,
 ) {
     init_test_env();
-
-    let config = Config::load().expect("Failed to load test configuration");
-
-    let macaroon_bytes = fs::read(&config.macaroon_path).expect("Failed to read tapd macaroon");
-    let macaroon_hex = hex::encode(macaroon_bytes);
-
-    let lnd_macaroon_bytes =
-        fs::read(&config.lnd_macaroon_path).expect("Failed to read LND macaroon");
-    let lnd_macaroon_hex = hex::encode(lnd_macaroon_bytes);
-
-    let base_url = format!("https://{}", config.taproot_assets_host);
+    let fixtures = fixtures::fixtures();
+
+    // Replayed runs never reach tapd or lnd, so need no config or macaroons
+    let (macaroon_hex, lnd_macaroon_hex, base_url, tls_verify) =
+        match fixtures.filter(|f| f.mode == FixtureMode::Replay) {
+            Some(fixtures) => (
+                REPLAY_MACAROON.to_string(),
+                REPLAY_MACAROON.to_string(),
+                fixtures.tapd_url.clone(),
+                true,
+            ),
+            None => {
+                let config = Config::load().expect("Failed to load test configuration");
+
+                let macaroon_bytes =
+                    fs::read(&config.macaroon_path).expect("Failed to read tapd macaroon");
+                let macaroon_hex = hex::encode(macaroon_bytes);
+
+                let lnd_macaroon_bytes =
+                    fs::read(&config.lnd_macaroon_path).expect("Failed to read LND macaroon");
+                let lnd_macaroon_hex = hex::encode(lnd_macaroon_bytes);
+
+                let base_url = match fixtures {
+                    Some(fixtures) => fixtures.tapd_url.clone(),
+                    None => format!("https://{}", config.taproot_assets_host),
+                };
+                (macaroon_hex, lnd_macaroon_hex, base_url, config.tls_verify)
+            }
+        };
 
     let mut client_builder = Client::builder().timeout(Duration::from_secs(60));
 
-    if !config.tls_verify {
+    if !tls_verify {
         client_builder = client_builder.danger_accept_invalid_certs(true);
     }
 
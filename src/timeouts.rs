@@ -0,0 +1,390 @@
+//! Per-route timeouts for calls to tapd. Proof export legitimately takes a
+//! minute while getinfo should fail within seconds, so each tapd path gets
+//! the timeout of its longest configured prefix, falling back to
+//! `REQUEST_TIMEOUT_SECS`. With adaptive timeouts on, a route's timeout
+//! tightens to a multiple of its observed p99 latency, never below
+//! `ADAPTIVE_TIMEOUT_MIN_SECS` and never above the configured value.
+//!
+//! Each gateway has its own [`TimeoutTuner`], put in scope for the requests
+//! it serves by [`crate::middleware::BackendTimeouts`]. Backend calls made
+//! outside a request, by background jobs, use the HTTP client's
+//! `REQUEST_TIMEOUT_SECS`.
+
+use crate::config::Config;
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Routes that differ from `REQUEST_TIMEOUT_SECS` unless `ROUTE_TIMEOUTS`
+/// says otherwise.
+const DEFAULT_ROUTES: [(&str, u64); 2] = [
+    ("/v1/taproot-assets/getinfo", 5),
+    ("/v1/taproot-assets/proofs/export", 60),
+];
+
+/// Upper bound on any configured timeout, matching `REQUEST_TIMEOUT_SECS`.
+pub const MAX_TIMEOUT_SECS: u64 = 300;
+
+/// Latencies kept per route.
+const SAMPLE_WINDOW: usize = 200;
+
+/// Samples a route needs before its timeout is tuned.
+const MIN_SAMPLES: usize = 50;
+
+/// New samples between recomputations of a route's p99.
+const RETUNE_EVERY: u32 = 10;
+
+/// Tuned timeout as a multiple of the observed p99.
+const P99_HEADROOM: u32 = 3;
+
+/// Routes tracked separately; further paths share the default's budget
+/// and are not tuned.
+const MAX_TRACKED_ROUTES: usize = 256;
+
+tokio::task_local! {
+    static TUNER: SharedTimeoutTuner;
+}
+
+/// Configured timeouts keyed by tapd path prefix.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RouteTimeouts {
+    /// Longest prefix first, so the most specific route wins.
+    routes: Vec<(String, u64)>,
+}
+
+impl Default for RouteTimeouts {
+    fn default() -> Self {
+        Self::parse("").expect("built-in route timeouts are valid")
+    }
+}
+
+impl RouteTimeouts {
+    /// Parses `ROUTE_TIMEOUTS`, e.g.
+    /// `/v1/taproot-assets/proofs=120;/v1/taproot-assets/getinfo=3`, on top
+    /// of the built-in routes.
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        let mut routes: BTreeMap<String, u64> = DEFAULT_ROUTES
+            .iter()
+            .map(|(path, secs)| (path.to_string(), *secs))
+            .collect();
+        let mut seen = Vec::new();
+        for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (path, secs) = entry
+                .split_once('=')
+                .map(|(p, s)| (p.trim().trim_end_matches('/'), s.trim()))
+                .filter(|(p, _)| p.starts_with('/'))
+                .ok_or_else(|| {
+                    AppError::ValidationError(format!(
+                        "ROUTE_TIMEOUTS entry must be /path=seconds: {entry}"
+                    ))
+                })?;
+            let secs = secs
+                .parse::<u64>()
+                .ok()
+                .filter(|s| (1..=MAX_TIMEOUT_SECS).contains(s))
+                .ok_or_else(|| {
+                    AppError::ValidationError(format!(
+                        "ROUTE_TIMEOUTS for {path} must be 1-{MAX_TIMEOUT_SECS} seconds"
+                    ))
+                })?;
+            if seen.contains(&path) {
+                return Err(AppError::ValidationError(format!(
+                    "ROUTE_TIMEOUTS lists {path} more than once"
+                )));
+            }
+            seen.push(path);
+            routes.insert(path.to_string(), secs);
+        }
+        let mut routes: Vec<_> = routes.into_iter().collect();
+        routes.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+        Ok(Self { routes })
+    }
+
+    /// The longest configured prefix of `path` on a segment boundary, and
+    /// its timeout in seconds.
+    pub fn resolve(&self, path: &str) -> Option<(&str, u64)> {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .map(|(prefix, secs)| (prefix.as_str(), *secs))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeoutPolicy {
+    pub default: Duration,
+    pub routes: RouteTimeouts,
+    /// Floor for tuned timeouts; `None` disables tuning.
+    pub adaptive_floor: Option<Duration>,
+}
+
+impl TimeoutPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            default: Duration::from_secs(config.request_timeout_secs),
+            routes: config.route_timeouts.clone(),
+            adaptive_floor: config
+                .adaptive_timeouts_enabled
+                .then(|| Duration::from_secs(config.adaptive_timeout_min_secs)),
+        }
+    }
+
+    /// The tracking key and configured timeout for a tapd path.
+    fn route(&self, path: &str) -> (String, Duration) {
+        let path = path.split('?').next().unwrap_or_default();
+        match self.routes.resolve(path) {
+            Some((prefix, secs)) => (prefix.to_string(), Duration::from_secs(secs)),
+            None => (normalize_path(path), self.default),
+        }
+    }
+}
+
+/// What `GET /admin/timeouts` reports per route.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RouteTimeoutStatus {
+    pub route: String,
+    pub configured_ms: u64,
+    pub effective_ms: u64,
+    pub samples: usize,
+    pub p99_ms: Option<u64>,
+    pub tuned: bool,
+}
+
+#[derive(Debug, Default)]
+struct RouteLatency {
+    configured: Duration,
+    samples_ms: VecDeque<u64>,
+    since_tune: u32,
+    p99_ms: Option<u64>,
+    tuned: Option<Duration>,
+}
+
+impl RouteLatency {
+    fn record(&mut self, elapsed: Duration, floor: Option<Duration>) {
+        if self.samples_ms.len() == SAMPLE_WINDOW {
+            self.samples_ms.pop_front();
+        }
+        self.samples_ms.push_back(elapsed.as_millis() as u64);
+        self.since_tune += 1;
+        if self.samples_ms.len() < MIN_SAMPLES || self.since_tune < RETUNE_EVERY {
+            return;
+        }
+        self.since_tune = 0;
+
+        let mut sorted: Vec<_> = self.samples_ms.iter().copied().collect();
+        sorted.sort_unstable();
+        let p99 = sorted[(sorted.len() * 99).div_ceil(100) - 1];
+        self.p99_ms = Some(p99);
+        self.tuned = floor.map(|floor| {
+            (Duration::from_millis(p99) * P99_HEADROOM)
+                .clamp(floor.min(self.configured), self.configured)
+        });
+    }
+
+    fn effective(&self) -> Duration {
+        self.tuned.unwrap_or(self.configured)
+    }
+}
+
+pub type SharedTimeoutTuner = Arc<TimeoutTuner>;
+
+/// Resolves timeouts and tracks latency per route.
+#[derive(Debug)]
+pub struct TimeoutTuner {
+    policy: RwLock<Option<TimeoutPolicy>>,
+    routes: Mutex<BTreeMap<String, RouteLatency>>,
+}
+
+impl TimeoutTuner {
+    pub const fn new() -> Self {
+        Self {
+            policy: RwLock::new(None),
+            routes: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Replaces the policy and forgets observed latencies.
+    pub fn install(&self, policy: TimeoutPolicy) {
+        *self.policy.write().unwrap_or_else(|e| e.into_inner()) = Some(policy);
+        self.routes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// The timeout for a call to `path`; `None` before a policy is
+    /// installed, leaving the HTTP client's own timeout in force.
+    pub fn timeout_for(&self, path: &str) -> Option<Duration> {
+        let policy = self.policy.read().unwrap_or_else(|e| e.into_inner());
+        let (key, configured) = policy.as_ref()?.route(path);
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        Some(routes.get(&key).map_or(configured, RouteLatency::effective))
+    }
+
+    /// Records how long a call to `path` took to answer, or to time out.
+    pub fn record(&self, path: &str, elapsed: Duration) {
+        let policy = self.policy.read().unwrap_or_else(|e| e.into_inner());
+        let Some(policy) = policy.as_ref() else {
+            return;
+        };
+        let (key, configured) = policy.route(path);
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        if !routes.contains_key(&key) && routes.len() >= MAX_TRACKED_ROUTES {
+            return;
+        }
+        let route = routes.entry(key).or_insert_with(|| RouteLatency {
+            configured,
+            ..Default::default()
+        });
+        route.record(elapsed, policy.adaptive_floor);
+    }
+
+    pub fn statuses(&self) -> Vec<RouteTimeoutStatus> {
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        routes
+            .iter()
+            .map(|(route, latency)| RouteTimeoutStatus {
+                route: route.clone(),
+                configured_ms: latency.configured.as_millis() as u64,
+                effective_ms: latency.effective().as_millis() as u64,
+                samples: latency.samples_ms.len(),
+                p99_ms: latency.p99_ms,
+                tuned: latency.tuned.is_some(),
+            })
+            .collect()
+    }
+}
+
+impl Default for TimeoutTuner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs `fut` with `tuner` resolving the timeouts of the backend calls it
+/// makes.
+pub fn with_tuner<F: Future>(tuner: SharedTimeoutTuner, fut: F) -> impl Future<Output = F::Output> {
+    TUNER.scope(tuner, fut)
+}
+
+/// The tuner [`crate::backend::BackendClient`] consults: the one of the
+/// gateway whose request is being served.
+pub fn current() -> Option<SharedTimeoutTuner> {
+    TUNER.try_with(Arc::clone).ok()
+}
+
+/// Collapses identifier segments (hashes, keys, numbers, outpoints) so one
+/// route is tracked once rather than per asset or transfer.
+pub(crate) fn normalize_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            let is_id = segment.len() >= 16
+                || segment.contains(':')
+                || (!segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()));
+            if is_id {
+                "*"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(routes: &str, floor: Option<u64>) -> TimeoutPolicy {
+        TimeoutPolicy {
+            default: Duration::from_secs(30),
+            routes: RouteTimeouts::parse(routes).unwrap(),
+            adaptive_floor: floor.map(Duration::from_secs),
+        }
+    }
+
+    #[test]
+    fn test_longest_prefix_wins_on_segment_boundaries() {
+        let tuner = TimeoutTuner::new();
+        tuner.install(policy(
+            "/v1/taproot-assets/proofs=120;/v1/taproot-assets/getinfo=2",
+            None,
+        ));
+        let timeout = |path| tuner.timeout_for(path).unwrap().as_secs();
+        assert_eq!(timeout("/v1/taproot-assets/proofs/export"), 60);
+        assert_eq!(timeout("/v1/taproot-assets/proofs/verify"), 120);
+        assert_eq!(timeout("/v1/taproot-assets/getinfo?x=1"), 2);
+        assert_eq!(timeout("/v1/taproot-assets/proofsx"), 30);
+        assert_eq!(timeout("/v1/taproot-assets/assets"), 30);
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_entries() {
+        assert!(RouteTimeouts::parse("proofs=10").is_err());
+        assert!(RouteTimeouts::parse("/v1/x=0").is_err());
+        assert!(RouteTimeouts::parse("/v1/x=301").is_err());
+        assert!(RouteTimeouts::parse("/v1/x=5;/v1/x/=6").is_err());
+    }
+
+    #[test]
+    fn test_adaptive_timeout_tracks_p99_within_bounds() {
+        let tuner = TimeoutTuner::new();
+        tuner.install(policy("", Some(1)));
+        let path = "/v1/taproot-assets/assets";
+        for _ in 0..MIN_SAMPLES - 1 {
+            tuner.record(path, Duration::from_millis(400));
+        }
+        assert_eq!(tuner.timeout_for(path), Some(Duration::from_secs(30)));
+
+        tuner.record(path, Duration::from_millis(400));
+        assert_eq!(tuner.timeout_for(path), Some(Duration::from_millis(1200)));
+
+        // Fast routes are held at the floor
+        let fast = "/v1/taproot-assets/wallet/ownership/verify";
+        for _ in 0..MIN_SAMPLES {
+            tuner.record(fast, Duration::from_millis(10));
+        }
+        assert_eq!(tuner.timeout_for(fast), Some(Duration::from_secs(1)));
+
+        // and slow ones at the configured timeout
+        let slow = "/v1/taproot-assets/getinfo";
+        for _ in 0..MIN_SAMPLES {
+            tuner.record(slow, Duration::from_secs(4));
+        }
+        assert_eq!(tuner.timeout_for(slow), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_identifier_segments_share_a_route() {
+        let tuner = TimeoutTuner::new();
+        tuner.install(policy("", None));
+        tuner.record(
+            &format!(
+                "/v1/taproot-assets/assets/meta/asset-id/{}",
+                "ab".repeat(32)
+            ),
+            Duration::from_millis(5),
+        );
+        tuner.record(
+            &format!(
+                "/v1/taproot-assets/assets/meta/asset-id/{}",
+                "cd".repeat(32)
+            ),
+            Duration::from_millis(5),
+        );
+        let statuses = tuner.statuses();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(
+            statuses[0].route,
+            "/v1/taproot-assets/assets/meta/asset-id/*"
+        );
+        assert_eq!(statuses[0].samples, 2);
+        assert!(!statuses[0].tuned);
+    }
+}
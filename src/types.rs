@@ -1,2 +1,305 @@
+use crate::error::AppError;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE as BASE64_URL};
+use base64::Engine;
+use bitcoin::hashes::Hash;
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::str::FromStr;
+
 pub struct BaseUrl(pub String);
 pub struct MacaroonHex(pub String);
+/// REST URL and hex-encoded macaroon of the lnd node tapd runs against, if
+/// configured.
+pub struct LndNode(pub Option<(String, String)>);
+
+/// Decodes tapd's base64 byte fields, which come URL-safe in some paths.
+fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    BASE64
+        .decode(value)
+        .or_else(|_| BASE64_URL.decode(value))
+        .ok()
+}
+
+/// Byte strings tapd identifies things by, held as lowercase hex. Parsing
+/// from `str` takes hex, as in paths and queries; `from_base64` reads
+/// tapd's JSON fields. Only the encoding and length are checked; whether a
+/// key is on the curve is left to tapd.
+macro_rules! hex_bytes {
+    ($(#[$doc:meta])* $name:ident, $what:literal, $hex_lens:literal, $lens:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+        #[serde(try_from = "String", into = "String")]
+        pub struct $name(String);
+
+        impl $name {
+            fn from_bytes(bytes: &[u8], shown: &str) -> Result<Self, AppError> {
+                if !$lens.contains(&bytes.len()) {
+                    return Err(AppError::InvalidInput(format!(
+                        "Invalid {}: expected {} hex characters, got {}",
+                        $what,
+                        $hex_lens,
+                        shown.len()
+                    )));
+                }
+                Ok(Self(hex::encode(bytes)))
+            }
+
+            /// Reads a base64 field of a tapd response.
+            pub fn from_base64(value: &str) -> Result<Self, AppError> {
+                let bytes = decode_base64(value).ok_or_else(|| {
+                    AppError::InvalidInput(format!("Invalid {}: {value} is not base64", $what))
+                })?;
+                Self::from_bytes(&bytes, &hex::encode(&bytes))
+            }
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            pub fn to_bytes(&self) -> Vec<u8> {
+                hex::decode(&self.0).expect("held as valid hex")
+            }
+
+            /// The form tapd's JSON requests take.
+            pub fn to_base64(&self) -> String {
+                BASE64.encode(self.to_bytes())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = AppError;
+
+            fn from_str(value: &str) -> Result<Self, AppError> {
+                let bytes = hex::decode(value).map_err(|_| {
+                    AppError::InvalidInput(format!("Invalid {}: {value} is not hex", $what))
+                })?;
+                Self::from_bytes(&bytes, value)
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = AppError;
+
+            fn try_from(value: String) -> Result<Self, AppError> {
+                value.parse()
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> String {
+                value.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+    };
+}
+
+hex_bytes!(
+    /// A 32-byte asset id.
+    AssetId,
+    "asset ID",
+    "64",
+    [32]
+);
+
+hex_bytes!(
+    /// An asset script key: a compressed or x-only public key.
+    ScriptKey,
+    "script key",
+    "64 or 66",
+    [32, 33]
+);
+
+hex_bytes!(
+    /// An asset group key. tapd accepts it as either a 32-byte x-only or a
+    /// 33-byte compressed public key.
+    GroupKey,
+    "group key",
+    "64 or 66",
+    [32, 33]
+);
+
+/// A transaction output, written `txid:vout` with the txid in display
+/// (reversed) byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Outpoint {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+impl Outpoint {
+    /// Reads an outpoint in whichever form it was sent: a `txid:vout`
+    /// string, or an object shaped like tapd's `OutPoint`. In an object,
+    /// `txid` holds the raw txid bytes (internal order) as base64 or hex,
+    /// and `txid_str` the display-order hex used in `txid:vout` strings.
+    /// The index is read from `output_index` or `vout`; tapd leaves it out
+    /// when it is 0.
+    pub fn from_any(value: &Value) -> Result<Self, AppError> {
+        if let Some(text) = value.as_str() {
+            return text.parse();
+        }
+        let invalid = || {
+            AppError::InvalidInput(format!(
+                "Invalid outpoint: {value}, expected <txid>:<output index> or an object with txid and output_index"
+            ))
+        };
+        let txid = match value["txid_str"].as_str() {
+            Some(display) if display.len() == 64 => {
+                Txid::from_str(display).map_err(|_| invalid())?
+            }
+            Some(_) => return Err(invalid()),
+            None => {
+                let raw = value["txid"].as_str().ok_or_else(invalid)?;
+                // base64 of 32 bytes is 44 characters, so 64 can only be hex
+                let bytes = if raw.len() == 64 {
+                    hex::decode(raw).ok()
+                } else {
+                    decode_base64(raw)
+                };
+                Txid::from_slice(&bytes.ok_or_else(invalid)?).map_err(|_| invalid())?
+            }
+        };
+        let vout = match value.get("output_index").or_else(|| value.get("vout")) {
+            None => 0,
+            Some(index) => index
+                .as_u64()
+                .and_then(|index| u32::try_from(index).ok())
+                .ok_or_else(invalid)?,
+        };
+        Ok(Self { txid, vout })
+    }
+
+    /// Rewrites an outpoint sent in any form accepted by
+    /// [`Self::from_any`] to the `OutPoint` JSON tapd's REST API takes.
+    pub fn normalize(value: &Value) -> Result<Value, AppError> {
+        Self::from_any(value).map(Self::to_tapd)
+    }
+
+    /// The outpoint as a tapd `OutPoint`.
+    pub fn to_tapd(self) -> Value {
+        json!({
+            "txid": BASE64.encode(self.txid.as_byte_array()),
+            "output_index": self.vout,
+        })
+    }
+}
+
+impl FromStr for Outpoint {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, AppError> {
+        let invalid = || {
+            AppError::InvalidInput(format!(
+                "Invalid outpoint: {value}, expected <txid>:<output index>"
+            ))
+        };
+        let (txid, vout) = value.rsplit_once(':').ok_or_else(invalid)?;
+        if txid.len() != 64 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            txid: Txid::from_str(txid).map_err(|_| invalid())?,
+            vout: vout.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl TryFrom<String> for Outpoint {
+    type Error = AppError;
+
+    fn try_from(value: String) -> Result<Self, AppError> {
+        value.parse()
+    }
+}
+
+impl From<Outpoint> for String {
+    fn from(value: Outpoint) -> String {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for Outpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.vout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The secp256k1 generator, compressed.
+    const G: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    #[test]
+    fn test_keys_check_encoding_and_length() {
+        let id: AssetId = "AB".repeat(32).parse().unwrap();
+        assert_eq!(id.as_str(), "ab".repeat(32));
+        assert_eq!(AssetId::from_base64(&id.to_base64()).unwrap(), id);
+        assert!("ab".repeat(31).parse::<AssetId>().is_err());
+        assert!("../../getinfo".parse::<AssetId>().is_err());
+
+        let key: ScriptKey = G.parse().unwrap();
+        assert_eq!(G[2..].parse::<GroupKey>().unwrap().as_str(), &G[2..]);
+        assert_eq!(ScriptKey::from_base64(&key.to_base64()).unwrap(), key);
+        assert!(G[4..].parse::<ScriptKey>().is_err());
+        assert!(format!("{G}00").parse::<GroupKey>().is_err());
+
+        let json: AssetId = serde_json::from_value(json!("cd".repeat(32))).unwrap();
+        assert_eq!(serde_json::to_value(&json).unwrap(), json!("cd".repeat(32)));
+        assert!(serde_json::from_value::<AssetId>(json!("zz")).is_err());
+    }
+
+    #[test]
+    fn test_outpoint_round_trips_through_tapd_form() {
+        let text = format!("{}:1", "0a".repeat(32));
+        let outpoint: Outpoint = text.parse().unwrap();
+        assert_eq!(outpoint.to_string(), text);
+        assert_eq!(Outpoint::from_any(&outpoint.to_tapd()).unwrap(), outpoint);
+
+        assert!("0a0a:1".parse::<Outpoint>().is_err());
+        assert!(format!("{}:x", "0a".repeat(32))
+            .parse::<Outpoint>()
+            .is_err());
+        assert!("0a".repeat(32).parse::<Outpoint>().is_err());
+    }
+
+    #[test]
+    fn test_outpoint_forms_normalize_alike() {
+        // Raw txid bytes 01 00 .. 00, displayed reversed
+        let mut raw = [0u8; 32];
+        raw[0] = 1;
+        let display = format!("{}01", "00".repeat(31));
+        let expected = json!({ "txid": BASE64.encode(raw), "output_index": 2 });
+
+        for form in [
+            json!(format!("{display}:2")),
+            json!({ "txid_str": display, "output_index": 2 }),
+            json!({ "txid": hex::encode(raw), "vout": 2 }),
+            json!({ "txid": BASE64.encode(raw), "output_index": 2 }),
+            json!({ "txid": BASE64_URL.encode(raw), "output_index": 2 }),
+        ] {
+            assert_eq!(Outpoint::normalize(&form).unwrap(), expected, "{form}");
+        }
+
+        let zero = Outpoint::from_any(&json!({ "txid": BASE64.encode(raw) })).unwrap();
+        assert_eq!(zero.vout, 0);
+        assert!(Outpoint::normalize(&json!({ "txid": "ab", "output_index": 1 })).is_err());
+        assert!(Outpoint::normalize(&json!({ "output_index": 1 })).is_err());
+        assert!(Outpoint::normalize(&json!(7)).is_err());
+    }
+}
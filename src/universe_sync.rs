@@ -0,0 +1,271 @@
+//! Resumable universe syncs. A `universe/sync` request with several targets
+//! is sent to tapd one target at a time and each target's outcome is stored,
+//! so a sync that fails partway (tapd restart, timeout) continues with the
+//! remaining targets instead of starting over. Interrupted syncs are retried
+//! in the background until they complete or run out of attempts, and can be
+//! resumed by hand at any time.
+
+use crate::api::universe::{sync_universe, SyncRequest};
+use crate::database::{
+    SharedDatabase, SyncTargetStatus, UniverseSync, UniverseSyncStatus, UniverseSyncTarget,
+};
+use crate::error::AppError;
+use reqwest::Client;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Response header carrying the id of a tracked sync.
+pub const SYNC_ID_HEADER: &str = "X-Universe-Sync-Id";
+
+/// Runs per sync (the first included) before background retries stop.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+#[derive(Clone)]
+pub struct UniverseSyncRunner {
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+    database: SharedDatabase,
+    max_attempts: u32,
+}
+
+impl UniverseSyncRunner {
+    pub fn new(
+        client: Client,
+        base_url: String,
+        macaroon_hex: String,
+        database: SharedDatabase,
+        max_attempts: u32,
+    ) -> Self {
+        Self {
+            client,
+            base_url,
+            macaroon_hex,
+            database,
+            max_attempts: max_attempts.max(1),
+        }
+    }
+
+    /// Records a new sync and runs it. The returned sync says how far it
+    /// got; a failed target leaves it `partial` (or `failed`) for resuming.
+    pub async fn begin(&self, request: SyncRequest) -> Result<UniverseSync, AppError> {
+        let sync = new_sync(request);
+        self.database.insert_universe_sync(&sync).await?;
+        info!(
+            "Universe sync {} with {} started ({} targets)",
+            sync.id,
+            sync.universe_host,
+            sync.targets.len()
+        );
+        self.resume(&sync.id).await
+    }
+
+    /// Runs the targets of a partial or failed sync that are not synced yet.
+    pub async fn resume(&self, id: &str) -> Result<UniverseSync, AppError> {
+        if !self.database.claim_universe_sync(id).await? {
+            return match self.database.get_universe_sync(id).await? {
+                Some(sync) => Err(AppError::ValidationError(format!(
+                    "Universe sync {id} is {} and cannot be resumed",
+                    sync.status.as_str()
+                ))),
+                None => Err(AppError::NotFound(format!("Universe sync {id} not found"))),
+            };
+        }
+        let sync = self
+            .database
+            .get_universe_sync(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Universe sync {id} not found")))?;
+
+        let mut failure = None;
+        for target in sync
+            .targets
+            .iter()
+            .filter(|t| t.status != SyncTargetStatus::Synced)
+        {
+            match self.sync_target(&sync, target).await {
+                Ok(synced) => {
+                    self.database
+                        .update_universe_sync_target(
+                            id,
+                            target.position,
+                            SyncTargetStatus::Synced,
+                            Some(&synced),
+                            None,
+                        )
+                        .await?;
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    warn!(
+                        "Universe sync {} stopped at target {}: {}",
+                        id, target.position, error
+                    );
+                    self.database
+                        .update_universe_sync_target(
+                            id,
+                            target.position,
+                            SyncTargetStatus::Failed,
+                            None,
+                            Some(&error),
+                        )
+                        .await?;
+                    failure = Some(error);
+                    break;
+                }
+            }
+        }
+
+        let status = match &failure {
+            None => UniverseSyncStatus::Completed,
+            Some(_) if sync.attempts >= self.max_attempts => UniverseSyncStatus::Failed,
+            Some(_) => UniverseSyncStatus::Partial,
+        };
+        self.database
+            .update_universe_sync_status(id, status, failure.as_deref())
+            .await?;
+        debug!("Universe sync {} is now {}", id, status.as_str());
+
+        self.database
+            .get_universe_sync(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Universe sync {id} not found")))
+    }
+
+    async fn sync_target(
+        &self,
+        sync: &UniverseSync,
+        target: &UniverseSyncTarget,
+    ) -> Result<Value, AppError> {
+        let request = SyncRequest {
+            universe_host: sync.universe_host.clone(),
+            sync_mode: sync.sync_mode.clone(),
+            sync_targets: match &target.target {
+                Value::Null => Vec::new(),
+                t => vec![t.clone()],
+            },
+        };
+        let mut response =
+            sync_universe(&self.client, &self.base_url, &self.macaroon_hex, request).await?;
+        Ok(response
+            .get_mut("synced_universes")
+            .map(Value::take)
+            .unwrap_or_else(|| Value::Array(Vec::new())))
+    }
+
+    /// Resets syncs a previous process left running, so the next
+    /// [`retry_partial`](Self::retry_partial) resumes them.
+    pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            match self.database.reset_interrupted_universe_syncs().await {
+                Ok(0) => {}
+                Ok(n) => info!("{} interrupted universe syncs will be resumed", n),
+                Err(e) => warn!("Failed to reset interrupted universe syncs: {}", e),
+            }
+        })
+    }
+
+    /// Resumes every partial sync with attempts left. Run by the
+    /// `universe_sync_retry` scheduled job.
+    pub async fn retry_partial(&self) -> Result<(), AppError> {
+        for id in self
+            .database
+            .resumable_universe_syncs(self.max_attempts)
+            .await?
+        {
+            match self.resume(&id).await {
+                Ok(sync) => info!(
+                    "Retried universe sync {}: {}",
+                    sync.id,
+                    sync.status.as_str()
+                ),
+                // Claimed by a manual resume in the meantime
+                Err(AppError::ValidationError(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn new_sync(request: SyncRequest) -> UniverseSync {
+    let now = chrono::Utc::now().timestamp();
+    let targets = if request.sync_targets.is_empty() {
+        vec![Value::Null]
+    } else {
+        request.sync_targets
+    };
+    UniverseSync {
+        id: Uuid::new_v4().to_string(),
+        universe_host: request.universe_host,
+        sync_mode: request.sync_mode,
+        status: UniverseSyncStatus::Partial,
+        attempts: 0,
+        last_error: None,
+        created_at: now,
+        updated_at: now,
+        targets: targets
+            .into_iter()
+            .enumerate()
+            .map(|(position, target)| UniverseSyncTarget {
+                position: position as u32,
+                target,
+                status: SyncTargetStatus::Pending,
+                synced_universes: None,
+                error: None,
+                updated_at: now,
+            })
+            .collect(),
+    }
+}
+
+/// The `universe/sync` response for a completed sync: every target's
+/// `synced_universes`, in target order.
+pub fn sync_response(sync: &UniverseSync) -> Value {
+    let synced: Vec<Value> = sync
+        .targets
+        .iter()
+        .filter_map(|t| t.synced_universes.as_ref())
+        .flat_map(|s| s.as_array().cloned().unwrap_or_default())
+        .collect();
+    serde_json::json!({ "synced_universes": synced })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(targets: Vec<Value>) -> SyncRequest {
+        SyncRequest {
+            universe_host: "universe.example:10029".to_string(),
+            sync_mode: "SYNC_FULL".to_string(),
+            sync_targets: targets,
+        }
+    }
+
+    #[test]
+    fn test_sync_without_targets_is_one_unit() {
+        let sync = new_sync(request(Vec::new()));
+        assert_eq!(sync.targets.len(), 1);
+        assert!(sync.targets[0].target.is_null());
+
+        let sync = new_sync(request(vec![
+            serde_json::json!({ "id": 1 }),
+            serde_json::json!({ "id": 2 }),
+        ]));
+        assert_eq!(sync.targets[1].position, 1);
+        assert_eq!(sync.targets[1].target["id"], 2);
+    }
+
+    #[test]
+    fn test_sync_response_merges_targets_in_order() {
+        let mut sync = new_sync(request(vec![Value::Null, Value::Null]));
+        sync.targets[0].synced_universes = Some(serde_json::json!([{ "n": 1 }]));
+        sync.targets[1].synced_universes = Some(serde_json::json!([{ "n": 2 }, { "n": 3 }]));
+        let response = sync_response(&sync);
+        assert_eq!(response["synced_universes"].as_array().unwrap().len(), 3);
+        assert_eq!(response["synced_universes"][2]["n"], 3);
+    }
+}
@@ -0,0 +1,139 @@
+//! Usage accounting per API key, for usage-based billing. Requests are
+//! counted in memory by the `UsageAccounting` middleware and flushed to the
+//! gateway database periodically, so accounting costs no database write per
+//! request. Counts still in memory when the process stops are lost.
+//!
+//! Keys are stored as a truncated SHA-256 of the token, never the token.
+
+use crate::database::{SharedDatabase, UsageRecord};
+use crate::error::AppError;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Hex characters of the token hash kept as the key id.
+const KEY_ID_LEN: usize = 16;
+
+pub struct UsageMeter {
+    database: SharedDatabase,
+    pending: Mutex<HashMap<(String, String), UsageRecord>>,
+}
+
+pub type SharedUsageMeter = Arc<UsageMeter>;
+
+/// Stable, non-reversible id of an API key.
+pub fn key_id(token: &str) -> String {
+    let mut id = hex::encode(Sha256::digest(token.as_bytes()));
+    id.truncate(KEY_ID_LEN);
+    id
+}
+
+/// Today's UTC date, the granularity usage is kept at.
+pub fn today() -> String {
+    chrono::Utc::now().format("%Y-%m-%d").to_string()
+}
+
+impl UsageMeter {
+    pub fn new(database: SharedDatabase) -> Self {
+        Self {
+            database,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts one request against `key_id`.
+    pub fn record(
+        &self,
+        key_id: String,
+        label: String,
+        error: bool,
+        request_bytes: u64,
+        response_bytes: u64,
+    ) {
+        let day = today();
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = pending
+            .entry((key_id.clone(), day.clone()))
+            .or_insert_with(|| UsageRecord {
+                key_id,
+                day,
+                ..Default::default()
+            });
+        entry.label = label;
+        entry.add(&UsageRecord {
+            requests: 1,
+            errors: error as u64,
+            request_bytes,
+            response_bytes,
+            ..Default::default()
+        });
+    }
+
+    /// Writes the counts gathered since the last flush. On failure they are
+    /// kept for the next attempt.
+    pub async fn flush(&self) -> Result<(), AppError> {
+        let records: Vec<UsageRecord> = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            std::mem::take(&mut *pending).into_values().collect()
+        };
+        if records.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = self.database.record_usage(&records).await {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            for record in records {
+                let key = (record.key_id.clone(), record.day.clone());
+                match pending.get_mut(&key) {
+                    Some(newer) => newer.add(&record),
+                    None => {
+                        pending.insert(key, record);
+                    }
+                }
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Daily usage from `from` to `to`, including counts not flushed yet.
+    pub async fn report(
+        &self,
+        key_id: Option<&str>,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<UsageRecord>, AppError> {
+        self.flush().await?;
+        self.database.query_usage(key_id, from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_test_database;
+
+    #[test]
+    fn test_key_id_hides_token() {
+        let id = key_id("secret-token");
+        assert_eq!(id.len(), KEY_ID_LEN);
+        assert_eq!(id, key_id("secret-token"));
+        assert_ne!(id, key_id("other-token"));
+        assert!(!id.contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_report_includes_unflushed_counts() {
+        let meter = UsageMeter::new(open_test_database().await);
+        meter.record("k".to_string(), "primary".to_string(), false, 10, 200);
+        meter.record("k".to_string(), "primary".to_string(), true, 0, 50);
+        meter.flush().await.unwrap();
+        meter.record("k".to_string(), "primary".to_string(), false, 5, 5);
+
+        let usage = meter.report(Some("k"), &today(), &today()).await.unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].requests, 3);
+        assert_eq!(usage[0].errors, 1);
+        assert_eq!(usage[0].request_bytes, 15);
+        assert_eq!(usage[0].response_bytes, 255);
+    }
+}
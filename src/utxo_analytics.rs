@@ -0,0 +1,339 @@
+//! How the wallet's asset outputs are spread: per asset, how many there
+//! are, how their sizes are distributed, and how much is dust, leased or
+//! locked to script paths. With thresholds configured, a scheduler job
+//! publishes [`TOPIC_FRAGMENTED`] when an asset crosses one, which the
+//! notifier forwards to operators.
+
+use crate::api::assets::get_utxos;
+use crate::coin_selection::{wallet_outputs, WalletOutput};
+use crate::config::Config;
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+use tracing::info;
+
+/// Published when an asset's outputs cross a fragmentation threshold.
+pub const TOPIC_FRAGMENTED: &str = "utxo.fragmented";
+
+/// When an asset counts as fragmented; 0 disables a threshold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FragmentationThresholds {
+    pub max_outputs: usize,
+    pub max_dust: usize,
+}
+
+impl FragmentationThresholds {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_outputs: config.utxo_alert_max_outputs,
+            max_dust: config.utxo_alert_max_dust,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_outputs > 0 || self.max_dust > 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Holding {
+    pub count: usize,
+    pub amount: u64,
+}
+
+impl Holding {
+    fn add(&mut self, amount: u64) {
+        self.count += 1;
+        self.amount += amount;
+    }
+}
+
+/// Outputs holding `min..=max` units; sizes are bucketed by decade.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SizeBucket {
+    pub min: u64,
+    pub max: u64,
+    pub count: usize,
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AssetUtxoSummary {
+    pub asset_id: String,
+    pub outputs: usize,
+    pub total_amount: u64,
+    pub spendable: Holding,
+    /// Leased to transfers in progress.
+    pub leased: Holding,
+    /// Locked to script paths tapd cannot sign for on its own.
+    pub script_locked: Holding,
+    /// Outputs below the dust threshold.
+    pub dust: Holding,
+    pub smallest: u64,
+    pub largest: u64,
+    pub median: u64,
+    pub distribution: Vec<SizeBucket>,
+    /// Thresholds the asset crosses.
+    pub alerts: Vec<String>,
+}
+
+/// The decade bucket of `amount`: 0, 1-9, 10-99, ...
+fn bucket_bounds(amount: u64) -> (u64, u64) {
+    if amount == 0 {
+        return (0, 0);
+    }
+    let min = 10u64.pow(amount.ilog10());
+    (min, min.checked_mul(10).map_or(u64::MAX, |next| next - 1))
+}
+
+/// Summarizes `outputs` per asset, in asset id order. Outputs below
+/// `dust_below` units count as dust.
+pub fn analyze(
+    outputs: &[WalletOutput],
+    dust_below: u64,
+    thresholds: FragmentationThresholds,
+) -> Vec<AssetUtxoSummary> {
+    let mut by_asset: BTreeMap<&str, Vec<&WalletOutput>> = BTreeMap::new();
+    for output in outputs {
+        by_asset
+            .entry(output.coin.asset_id.as_str())
+            .or_default()
+            .push(output);
+    }
+
+    by_asset
+        .into_iter()
+        .map(|(asset_id, outputs)| {
+            let (mut spendable, mut leased, mut script_locked, mut dust) = (
+                Holding::default(),
+                Holding::default(),
+                Holding::default(),
+                Holding::default(),
+            );
+            let mut buckets: BTreeMap<u64, SizeBucket> = BTreeMap::new();
+            let mut amounts = Vec::with_capacity(outputs.len());
+            for output in &outputs {
+                let amount = output.coin.amount;
+                amounts.push(amount);
+                if output.leased {
+                    leased.add(amount);
+                } else if output.script_path {
+                    script_locked.add(amount);
+                } else {
+                    spendable.add(amount);
+                }
+                if amount < dust_below {
+                    dust.add(amount);
+                }
+                let (min, max) = bucket_bounds(amount);
+                let bucket = buckets.entry(min).or_insert(SizeBucket {
+                    min,
+                    max,
+                    count: 0,
+                    amount: 0,
+                });
+                bucket.count += 1;
+                bucket.amount += amount;
+            }
+            amounts.sort_unstable();
+
+            let mut alerts = Vec::new();
+            if thresholds.max_outputs > 0 && outputs.len() > thresholds.max_outputs {
+                alerts.push(format!(
+                    "{} outputs, more than {}",
+                    outputs.len(),
+                    thresholds.max_outputs
+                ));
+            }
+            if thresholds.max_dust > 0 && dust.count > thresholds.max_dust {
+                alerts.push(format!(
+                    "{} dust outputs below {dust_below} units, more than {}",
+                    dust.count, thresholds.max_dust
+                ));
+            }
+
+            AssetUtxoSummary {
+                asset_id: asset_id.to_string(),
+                outputs: outputs.len(),
+                total_amount: amounts.iter().sum(),
+                spendable,
+                leased,
+                script_locked,
+                dust,
+                smallest: amounts.first().copied().unwrap_or(0),
+                largest: amounts.last().copied().unwrap_or(0),
+                median: amounts.get(amounts.len() / 2).copied().unwrap_or(0),
+                distribution: buckets.into_values().collect(),
+                alerts,
+            }
+        })
+        .collect()
+}
+
+/// Fetches the wallet's outputs from tapd and summarizes them.
+pub async fn fetch(
+    client: &Client,
+    base_url: &str,
+    macaroon_hex: &str,
+    dust_below: u64,
+    thresholds: FragmentationThresholds,
+) -> Result<Vec<AssetUtxoSummary>, AppError> {
+    let utxos = get_utxos(client, base_url, macaroon_hex, "").await?;
+    let outputs = wallet_outputs(&utxos, chrono::Utc::now().timestamp());
+    Ok(analyze(&outputs, dust_below, thresholds))
+}
+
+/// Periodically checks the thresholds and publishes an asset once when it
+/// becomes fragmented, and again only after it recovered in between.
+pub struct FragmentationWatch {
+    client: Client,
+    base_url: String,
+    macaroon_hex: String,
+    events: SharedEventBus,
+    dust_below: u64,
+    thresholds: FragmentationThresholds,
+    fragmented: Mutex<HashSet<String>>,
+}
+
+impl FragmentationWatch {
+    pub fn new(
+        client: Client,
+        base_url: String,
+        macaroon_hex: String,
+        events: SharedEventBus,
+        dust_below: u64,
+        thresholds: FragmentationThresholds,
+    ) -> Self {
+        Self {
+            client,
+            base_url,
+            macaroon_hex,
+            events,
+            dust_below,
+            thresholds,
+            fragmented: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Returns how many assets newly crossed a threshold.
+    pub async fn check(&self) -> Result<usize, AppError> {
+        let summaries = fetch(
+            &self.client,
+            &self.base_url,
+            &self.macaroon_hex,
+            self.dust_below,
+            self.thresholds,
+        )
+        .await?;
+        let newly = {
+            let mut fragmented = self.fragmented.lock().unwrap_or_else(|e| e.into_inner());
+            let now: HashSet<String> = summaries
+                .iter()
+                .filter(|s| !s.alerts.is_empty())
+                .map(|s| s.asset_id.clone())
+                .collect();
+            let newly: Vec<&AssetUtxoSummary> = summaries
+                .iter()
+                .filter(|s| now.contains(&s.asset_id) && !fragmented.contains(&s.asset_id))
+                .collect();
+            *fragmented = now;
+            newly
+        };
+        for summary in &newly {
+            info!(
+                "Asset {} is fragmented: {}",
+                summary.asset_id,
+                summary.alerts.join("; ")
+            );
+            match serde_json::to_value(summary) {
+                Ok(payload) => {
+                    self.events.publish(TOPIC_FRAGMENTED, payload);
+                }
+                Err(e) => tracing::warn!("Failed to publish {}: {}", summary.asset_id, e),
+            }
+        }
+        Ok(newly.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coin_selection::Coin;
+
+    fn output(asset: &str, amount: u64, leased: bool, script_path: bool) -> WalletOutput {
+        WalletOutput {
+            coin: Coin {
+                outpoint: "ab:0".to_string(),
+                asset_id: asset.to_string(),
+                script_key: "02cc".to_string(),
+                amount,
+                block_height: 1,
+            },
+            leased,
+            script_path,
+        }
+    }
+
+    #[test]
+    fn test_bucket_bounds() {
+        assert_eq!(bucket_bounds(0), (0, 0));
+        assert_eq!(bucket_bounds(7), (1, 9));
+        assert_eq!(bucket_bounds(10), (10, 99));
+        assert_eq!(bucket_bounds(4_250), (1_000, 9_999));
+        assert_eq!(bucket_bounds(u64::MAX).1, u64::MAX);
+    }
+
+    #[test]
+    fn test_analyze_per_asset() {
+        let outputs = [
+            output("bb", 5, false, false),
+            output("aa", 3, false, false),
+            output("aa", 40, true, false),
+            output("aa", 1_000, false, true),
+            output("aa", 8, false, false),
+        ];
+        let thresholds = FragmentationThresholds {
+            max_outputs: 3,
+            max_dust: 0,
+        };
+        let summaries = analyze(&outputs, 10, thresholds);
+        assert_eq!(summaries.len(), 2);
+
+        let aa = &summaries[0];
+        assert_eq!(aa.asset_id, "aa");
+        assert_eq!(aa.outputs, 4);
+        assert_eq!(aa.total_amount, 1_051);
+        assert_eq!(
+            aa.spendable,
+            Holding {
+                count: 2,
+                amount: 11
+            }
+        );
+        assert_eq!(
+            aa.leased,
+            Holding {
+                count: 1,
+                amount: 40
+            }
+        );
+        assert_eq!(aa.script_locked.amount, 1_000);
+        assert_eq!(
+            aa.dust,
+            Holding {
+                count: 2,
+                amount: 11
+            }
+        );
+        assert_eq!((aa.smallest, aa.median, aa.largest), (3, 40, 1_000));
+        let buckets: Vec<(u64, usize)> = aa.distribution.iter().map(|b| (b.min, b.count)).collect();
+        assert_eq!(buckets, [(1, 2), (10, 1), (1_000, 1)]);
+        assert_eq!(aa.alerts.len(), 1);
+
+        assert!(summaries[1].alerts.is_empty());
+    }
+}
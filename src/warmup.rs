@@ -0,0 +1,235 @@
+//! Startup warm-up: waits for tapd to answer `getinfo` with exponential
+//! backoff, then opens and closes one backend WebSocket so the first proxied
+//! subscription does not pay for (or fail on) the initial TLS handshake.
+//! Progress is exposed through the readiness endpoint.
+
+use crate::api::backend;
+use crate::error::AppError;
+use crate::websocket::connection_manager::WebSocketConnectionManager;
+use futures_util::SinkExt;
+use reqwest::Client;
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{error, info, warn};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+/// Endpoint used to pre-warm the WebSocket path; the stream is closed before
+/// any request is sent on it.
+const WARMUP_WS_ENDPOINT: &str = "/v1/taproot-assets/events/asset-mint?method=POST";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmupPhase {
+    WaitingForBackend,
+    WarmingWebsocket,
+    Ready,
+    /// The deadline passed without tapd answering. Requests are no longer
+    /// held back, and the backend keeps being polled until it responds.
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmupStatus {
+    pub phase: WarmupPhase,
+    pub attempts: u32,
+    pub elapsed_secs: u64,
+    pub deadline_secs: u64,
+    pub websocket_ready: bool,
+    pub last_error: Option<String>,
+}
+
+/// Shared warm-up progress, read by the readiness endpoint and the warm-up
+/// gate middleware.
+#[derive(Debug)]
+pub struct Warmup {
+    started: Instant,
+    deadline: Duration,
+    state: RwLock<WarmupState>,
+}
+
+#[derive(Debug)]
+struct WarmupState {
+    phase: WarmupPhase,
+    attempts: u32,
+    websocket_ready: bool,
+    last_error: Option<String>,
+}
+
+pub type SharedWarmup = Arc<Warmup>;
+
+impl Warmup {
+    /// A zero deadline disables warm-up and reports ready immediately.
+    pub fn new(deadline: Duration) -> Self {
+        let phase = if deadline.is_zero() {
+            WarmupPhase::Ready
+        } else {
+            WarmupPhase::WaitingForBackend
+        };
+        Self {
+            started: Instant::now(),
+            deadline,
+            state: RwLock::new(WarmupState {
+                phase,
+                attempts: 0,
+                websocket_ready: false,
+                last_error: None,
+            }),
+        }
+    }
+
+    pub fn status(&self) -> WarmupStatus {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        WarmupStatus {
+            phase: state.phase,
+            attempts: state.attempts,
+            elapsed_secs: self.started.elapsed().as_secs(),
+            deadline_secs: self.deadline.as_secs(),
+            websocket_ready: state.websocket_ready,
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.phase() == WarmupPhase::Ready
+    }
+
+    /// Requests are only held back while the backend is still expected to
+    /// come up.
+    pub fn is_warming(&self) -> bool {
+        matches!(
+            self.phase(),
+            WarmupPhase::WaitingForBackend | WarmupPhase::WarmingWebsocket
+        )
+    }
+
+    fn phase(&self) -> WarmupPhase {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).phase
+    }
+
+    fn update(&self, f: impl FnOnce(&mut WarmupState)) {
+        f(&mut self.state.write().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    /// Runs the warm-up in the background.
+    pub fn start(
+        self: Arc<Self>,
+        client: Client,
+        base_url: String,
+        macaroon_hex: String,
+        connection_manager: Arc<WebSocketConnectionManager>,
+    ) {
+        if self.is_ready() {
+            return;
+        }
+        tokio::spawn(async move {
+            self.wait_for_backend(&client, &base_url, &macaroon_hex)
+                .await;
+            self.update(|s| s.phase = WarmupPhase::WarmingWebsocket);
+            let websocket_ready = match prewarm_websocket(&connection_manager).await {
+                Ok(()) => true,
+                Err(e) => {
+                    // Subscriptions connect on demand anyway; a failed
+                    // pre-warm only loses the head start.
+                    warn!("WebSocket pre-warm failed: {}", e);
+                    false
+                }
+            };
+            self.update(|s| {
+                s.phase = WarmupPhase::Ready;
+                s.websocket_ready = websocket_ready;
+            });
+            info!(
+                "Backend warm-up complete after {:.1}s",
+                self.started.elapsed().as_secs_f64()
+            );
+        });
+    }
+
+    async fn wait_for_backend(&self, client: &Client, base_url: &str, macaroon_hex: &str) {
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            // This loop backs off on its own, so no per-request retries
+            let result = backend(client, base_url, macaroon_hex)
+                .retries(0)
+                .get("/v1/taproot-assets/getinfo")
+                .fetch::<serde_json::Value>()
+                .await;
+            let timed_out = self.started.elapsed() >= self.deadline;
+            match result {
+                Ok(_) => {
+                    self.update(|s| {
+                        s.attempts += 1;
+                        s.last_error = None;
+                    });
+                    return;
+                }
+                Err(e) => self.update(|s| {
+                    s.attempts += 1;
+                    s.last_error = Some(e.to_string());
+                    if timed_out && s.phase != WarmupPhase::TimedOut {
+                        s.phase = WarmupPhase::TimedOut;
+                        error!(
+                            "tapd did not respond within {}s of startup: {}",
+                            self.deadline.as_secs(),
+                            e
+                        );
+                    }
+                }),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = next_backoff(backoff);
+        }
+    }
+}
+
+fn next_backoff(current: Duration) -> Duration {
+    (current * 2).min(MAX_BACKOFF)
+}
+
+async fn prewarm_websocket(manager: &WebSocketConnectionManager) -> Result<(), AppError> {
+    let (connection_id, mut sink, _stream) = manager.connect_to_backend(WARMUP_WS_ENDPOINT).await?;
+    let _ = sink.send(Message::Close(None)).await;
+    manager.remove_connection(connection_id).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_cap() {
+        assert_eq!(next_backoff(INITIAL_BACKOFF), Duration::from_secs(1));
+        assert_eq!(next_backoff(Duration::from_secs(8)), MAX_BACKOFF);
+        assert_eq!(next_backoff(MAX_BACKOFF), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_zero_deadline_is_ready_immediately() {
+        let warmup = Warmup::new(Duration::ZERO);
+        assert!(warmup.is_ready());
+        assert!(!warmup.is_warming());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_backend_times_out_but_keeps_polling() {
+        let warmup = Warmup::new(Duration::from_millis(1));
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let client = Client::new();
+        tokio::time::timeout(
+            Duration::from_millis(200),
+            warmup.wait_for_backend(&client, "http://127.0.0.1:1", ""),
+        )
+        .await
+        .expect_err("keeps retrying after the deadline");
+
+        let status = warmup.status();
+        assert_eq!(status.phase, WarmupPhase::TimedOut);
+        assert!(status.attempts >= 1);
+        assert!(status.last_error.is_some());
+        assert!(!warmup.is_warming());
+    }
+}
@@ -0,0 +1,274 @@
+//! Outbound webhook delivery. Producers (address receive callbacks, payment
+//! requests, ...) enqueue deliveries in the database outbox; the dispatcher
+//! wakes on gateway events and on a timer, posts everything due and retries
+//! failures with exponential backoff until the attempt budget is spent.
+
+use crate::api::{SIGNATURE_HEADER, SIGNATURE_KEY_ID_HEADER};
+use crate::crypto::{hmac_sha256_hex, ResponseSigner, SharedResponseSigner};
+use crate::database::{
+    AddressWebhook, DeliveryStatus, IndexedTransfer, SharedDatabase, WebhookDelivery,
+    ADDRESS_RECEIVED_PREFIX,
+};
+use crate::error::AppError;
+use crate::event_bus::SharedEventBus;
+use crate::indexer::ReceivePolicy;
+use reqwest::Client;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+
+/// Event name sent in the `X-Gateway-Event` header and payload.
+pub const EVENT_ADDRESS_RECEIVED: &str = "address.received";
+
+/// Gateway events after which new deliveries may be due
+const WAKE_TOPIC_PREFIXES: [&str; 3] = ["transfer.", "payment_request.", "address."];
+
+/// How often pending deliveries are retried even without new events
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// First retry delay (in seconds); doubles per failed attempt
+const INITIAL_RETRY_DELAY_SECS: i64 = 30;
+
+/// Maximum retry delay (in seconds) - caps exponential backoff
+const MAX_RETRY_DELAY_SECS: i64 = 3600;
+
+static STATS: WebhookStats = WebhookStats::new();
+
+/// Process-wide delivery counters, exported on `/metrics`.
+#[derive(Debug)]
+pub struct WebhookStats {
+    delivered: AtomicU64,
+    failed_attempts: AtomicU64,
+    dead_lettered: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct WebhookStatsSnapshot {
+    pub delivered: u64,
+    pub failed_attempts: u64,
+    /// Deliveries that spent their attempt budget.
+    pub dead_lettered: u64,
+}
+
+impl WebhookStats {
+    const fn new() -> Self {
+        Self {
+            delivered: AtomicU64::new(0),
+            failed_attempts: AtomicU64::new(0),
+            dead_lettered: AtomicU64::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> WebhookStatsSnapshot {
+        WebhookStatsSnapshot {
+            delivered: self.delivered.load(Ordering::Relaxed),
+            failed_attempts: self.failed_attempts.load(Ordering::Relaxed),
+            dead_lettered: self.dead_lettered.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub fn stats() -> &'static WebhookStats {
+    &STATS
+}
+
+pub struct WebhookDispatcher {
+    client: Client,
+    database: SharedDatabase,
+    events: SharedEventBus,
+    policy: ReceivePolicy,
+    max_attempts: u32,
+    signer: Option<SharedResponseSigner>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(
+        client: Client,
+        database: SharedDatabase,
+        events: SharedEventBus,
+        policy: ReceivePolicy,
+        max_attempts: u32,
+    ) -> Self {
+        Self {
+            client,
+            database,
+            events,
+            policy,
+            max_attempts: max_attempts.max(1),
+            signer: None,
+        }
+    }
+
+    /// Also signs every delivery with the gateway's active signing key.
+    pub fn with_signer(mut self, signer: Option<SharedResponseSigner>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut events = self.events.subscribe();
+            let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) if WAKE_TOPIC_PREFIXES.iter().any(|p| event.topic.starts_with(p)) => {}
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(_)) => {}
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = ticker.tick() => {}
+                }
+                if let Err(e) = self.sweep().await {
+                    warn!("Webhook sweep failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Enqueues newly settled address receives, then delivers everything due.
+    /// Returns how many deliveries succeeded.
+    pub async fn sweep(&self) -> Result<usize, AppError> {
+        for (webhook, transfer) in self.database.unnotified_address_receives().await? {
+            if self.policy.is_settled(&transfer) {
+                self.database
+                    .enqueue_webhook(&address_received(&webhook, &transfer))
+                    .await?;
+            }
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let mut delivered = 0;
+        for delivery in self.database.due_webhooks(now).await? {
+            let (status, error, next_attempt_at) =
+                match deliver(&self.client, &delivery, self.signer.as_deref()).await {
+                    Ok(()) => {
+                        delivered += 1;
+                        STATS.delivered.fetch_add(1, Ordering::Relaxed);
+                        (DeliveryStatus::Delivered, None, now)
+                    }
+                    Err(e) => {
+                        let attempts = delivery.attempts + 1;
+                        warn!(
+                            "Webhook delivery {} failed (attempt {}/{}): {}",
+                            delivery.id, attempts, self.max_attempts, e
+                        );
+                        STATS.failed_attempts.fetch_add(1, Ordering::Relaxed);
+                        let status = if attempts >= self.max_attempts {
+                            STATS.dead_lettered.fetch_add(1, Ordering::Relaxed);
+                            DeliveryStatus::Failed
+                        } else {
+                            DeliveryStatus::Pending
+                        };
+                        (status, Some(e), now + retry_delay_secs(attempts))
+                    }
+                };
+            self.database
+                .record_webhook_attempt(&delivery.id, status, error.as_deref(), next_attempt_at)
+                .await?;
+        }
+        if delivered > 0 {
+            info!("Delivered {} webhooks", delivered);
+        }
+        Ok(delivered)
+    }
+}
+
+/// POSTs one delivery, HMAC-signed when it has a secret and Schnorr-signed
+/// when the gateway has signing keys. Any non-2xx answer is an error.
+pub async fn deliver(
+    client: &Client,
+    delivery: &WebhookDelivery,
+    signer: Option<&ResponseSigner>,
+) -> Result<(), String> {
+    let body = delivery.payload.to_string();
+    let timestamp = chrono::Utc::now().timestamp().to_string();
+
+    let mut request = client
+        .post(&delivery.callback_url)
+        .header("Content-Type", "application/json")
+        .header("X-Gateway-Event", &delivery.event)
+        .header("X-Gateway-Delivery", &delivery.id)
+        .header("X-Gateway-Timestamp", &timestamp);
+    if let Some(secret) = &delivery.secret {
+        request = request.header("X-Gateway-Signature", signature(secret, &timestamp, &body));
+    }
+    if let Some(signer) = signer {
+        request = request
+            .header(
+                SIGNATURE_HEADER,
+                signer.sign(&format!("{timestamp}.{body}")),
+            )
+            .header(SIGNATURE_KEY_ID_HEADER, signer.kid());
+    }
+
+    let response = request.body(body).send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    debug!("Webhook {} answered {}", delivery.callback_url, status);
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(format!("callback returned {status}"))
+    }
+}
+
+/// `sha256=<hex>` HMAC over `"{timestamp}.{body}"`. Binding the timestamp
+/// lets receivers reject replays.
+pub fn signature(secret: &str, timestamp: &str, body: &str) -> String {
+    let message = format!("{timestamp}.{body}");
+    format!(
+        "sha256={}",
+        hmac_sha256_hex(secret.as_bytes(), message.as_bytes())
+    )
+}
+
+fn address_received(webhook: &AddressWebhook, transfer: &IndexedTransfer) -> WebhookDelivery {
+    WebhookDelivery::new(
+        format!("{ADDRESS_RECEIVED_PREFIX}{}", transfer.id),
+        EVENT_ADDRESS_RECEIVED,
+        webhook.callback_url.clone(),
+        webhook.secret.clone(),
+        serde_json::json!({
+            "event": EVENT_ADDRESS_RECEIVED,
+            "address": webhook.address,
+            "transfer_id": transfer.id,
+            "asset_id": transfer.asset_id,
+            "amount": transfer.amount,
+            "outpoint": transfer.outpoint,
+            "anchor_txid": transfer.anchor_txid,
+            "confirmations": ReceivePolicy::confirmations(transfer),
+            "timestamp": transfer.timestamp,
+        }),
+    )
+}
+
+/// Seconds to wait after `attempts` failed attempts: doubling from
+/// `INITIAL_RETRY_DELAY_SECS` up to `MAX_RETRY_DELAY_SECS`.
+pub(crate) fn retry_delay_secs(attempts: u32) -> i64 {
+    let exponent = attempts.saturating_sub(1).min(16);
+    (INITIAL_RETRY_DELAY_SECS << exponent).min(MAX_RETRY_DELAY_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_binds_timestamp() {
+        let a = signature("secret", "100", "{}");
+        let b = signature("secret", "101", "{}");
+        assert!(a.starts_with("sha256="));
+        assert_eq!(a.len(), "sha256=".len() + 64);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off_and_caps() {
+        assert_eq!(retry_delay_secs(1), 30);
+        assert_eq!(retry_delay_secs(2), 60);
+        assert_eq!(retry_delay_secs(20), MAX_RETRY_DELAY_SECS);
+    }
+}
@@ -27,17 +27,20 @@
 //! ```
 
 use crate::error::AppError;
+use crate::outbound_proxy::OutboundProxy;
 use crate::types::{BaseUrl, MacaroonHex};
 use futures_util::StreamExt;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 use tokio::time::interval;
 use tokio_tungstenite::{
-    connect_async_tls_with_config, tungstenite::protocol::Message, Connector, MaybeTlsStream,
-    WebSocketStream,
+    client_async_tls_with_config, connect_async_tls_with_config, tungstenite::protocol::Message,
+    Connector, MaybeTlsStream, WebSocketStream,
 };
 use tracing::{debug, error, info, warn};
 use url::Url;
@@ -70,7 +73,19 @@ pub struct WebSocketConnectionManager {
     backend_url: String,
     macaroon_hex: String,
     tls_verify: bool,
+    proxy: Option<OutboundProxy>,
     connections: Arc<Mutex<HashMap<Uuid, BackendConnection>>>,
+    counters: Arc<ConnectionCounters>,
+}
+
+/// Lifetime counters, shared by every clone of the manager.
+#[derive(Debug, Default)]
+struct ConnectionCounters {
+    opened: AtomicU64,
+    failed: AtomicU64,
+    reconnects: AtomicU64,
+    stale_removed: AtomicU64,
+    messages: AtomicU64,
 }
 
 /// Represents a tracked WebSocket connection to the backend
@@ -88,7 +103,9 @@ impl Clone for WebSocketConnectionManager {
             backend_url: self.backend_url.clone(),
             macaroon_hex: self.macaroon_hex.clone(),
             tls_verify: self.tls_verify,
+            proxy: self.proxy.clone(),
             connections: self.connections.clone(),
+            counters: self.counters.clone(),
         }
     }
 }
@@ -99,10 +116,18 @@ impl WebSocketConnectionManager {
             backend_url: backend_url.0,
             macaroon_hex: macaroon_hex.0,
             tls_verify,
+            proxy: None,
             connections: Arc::new(Mutex::new(HashMap::new())),
+            counters: Arc::new(ConnectionCounters::default()),
         }
     }
 
+    /// Tunnels backend connections through `proxy`.
+    pub fn with_proxy(mut self, proxy: Option<OutboundProxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
     /// Establish a WebSocket connection to the tapd backend
     pub async fn connect_to_backend(
         &self,
@@ -117,18 +142,17 @@ impl WebSocketConnectionManager {
         debug!("Connecting to backend WebSocket: {}", url);
 
         // Extract host from URL using proper URL parsing
-        let host = Url::parse(&self.backend_url)
-            .map_err(|e| AppError::WebSocketProxyError(format!("Invalid backend URL: {e}")))?
-            .host_str()
-            .unwrap_or("localhost")
-            .to_string();
+        let backend_url = Url::parse(&self.backend_url)
+            .map_err(|e| AppError::WebSocketProxyError(format!("Invalid backend URL: {e}")))?;
+        let host = backend_url.host_str().unwrap_or("localhost").to_string();
+        let port = backend_url.port_or_known_default().unwrap_or(443);
 
         // Build request with macaroon authentication
         let request = tokio_tungstenite::tungstenite::http::Request::builder()
             .uri(&url)
             .header("Grpc-Metadata-macaroon", &self.macaroon_hex)
             .header("Sec-WebSocket-Protocol", "Grpc-Metadata-macaroon")
-            .header("Host", host)
+            .header("Host", &host)
             .header("User-Agent", "taproot-assets-rest-gateway/0.0.1")
             .body(())
             .map_err(|e| AppError::WebSocketProxyError(format!("Failed to build request: {e}")))?;
@@ -148,11 +172,26 @@ impl WebSocketConnectionManager {
             )
         };
 
-        // Connect to the backend
-        let (ws_stream, _response) =
-            connect_async_tls_with_config(request, None, false, Some(connector))
+        // Connect to the backend, through the outbound proxy if one is set
+        let connected = match &self.proxy {
+            Some(proxy) => match proxy.connect(&host, port).await {
+                Ok(stream) => client_async_tls_with_config(request, stream, None, Some(connector))
+                    .await
+                    .map_err(|e| format!("Failed to connect: {e}")),
+                Err(e) => Err(format!(
+                    "Failed to connect through proxy {}: {e}",
+                    proxy.display()
+                )),
+            },
+            None => connect_async_tls_with_config(request, None, false, Some(connector))
                 .await
-                .map_err(|e| AppError::WebSocketProxyError(format!("Failed to connect: {e}")))?;
+                .map_err(|e| format!("Failed to connect: {e}")),
+        };
+        let (ws_stream, _response) = connected.map_err(|e| {
+            self.counters.failed.fetch_add(1, Ordering::Relaxed);
+            AppError::WebSocketProxyError(e)
+        })?;
+        self.counters.opened.fetch_add(1, Ordering::Relaxed);
 
         info!("Successfully connected to backend WebSocket: {endpoint}");
 
@@ -198,8 +237,10 @@ impl WebSocketConnectionManager {
         })
     }
 
-    /// Update last activity timestamp for a connection
+    /// Update last activity timestamp for a connection. Called once per
+    /// message relayed over it, in either direction, which is counted.
     pub async fn update_activity(&self, connection_id: Uuid) {
+        self.counters.messages.fetch_add(1, Ordering::Relaxed);
         let connections = self.connections.lock().await;
         if let Some(conn) = connections.get(&connection_id) {
             let mut last_activity = conn.last_activity.lock().await;
@@ -231,6 +272,9 @@ impl WebSocketConnectionManager {
             connections.remove(id);
             removed.push(*id);
         }
+        self.counters
+            .stale_removed
+            .fetch_add(removed.len() as u64, Ordering::Relaxed);
 
         removed
     }
@@ -256,6 +300,7 @@ impl WebSocketConnectionManager {
         let mut delay = Duration::from_secs(INITIAL_RECONNECT_DELAY_SECS);
 
         loop {
+            self.counters.reconnects.fetch_add(1, Ordering::Relaxed);
             match self.connect_to_backend(&endpoint).await {
                 Ok((new_id, sink, stream)) => {
                     info!(
@@ -367,6 +412,38 @@ impl WebSocketConnectionManager {
         }
     }
 
+    /// Snapshot of the open backend sockets and lifetime counters, for
+    /// finding leaked subscriptions.
+    pub async fn introspect(&self) -> ConnectionManagerState {
+        let connections = self.connections.lock().await;
+        let mut sockets = Vec::with_capacity(connections.len());
+        let mut by_endpoint = BTreeMap::new();
+        for conn in connections.values() {
+            let last_activity = *conn.last_activity.lock().await;
+            *by_endpoint
+                .entry(endpoint_path(&conn.endpoint).to_string())
+                .or_insert(0) += 1;
+            sockets.push(SocketState {
+                id: conn.id.to_string(),
+                endpoint: conn.endpoint.clone(),
+                age_secs: conn.created_at.elapsed().as_secs(),
+                idle_secs: last_activity.elapsed().as_secs(),
+            });
+        }
+        sockets.sort_by_key(|s| std::cmp::Reverse(s.idle_secs));
+
+        ConnectionManagerState {
+            open: sockets.len(),
+            by_endpoint,
+            opened_total: self.counters.opened.load(Ordering::Relaxed),
+            failed_total: self.counters.failed.load(Ordering::Relaxed),
+            reconnect_attempts_total: self.counters.reconnects.load(Ordering::Relaxed),
+            stale_removed_total: self.counters.stale_removed.load(Ordering::Relaxed),
+            messages_total: self.counters.messages.load(Ordering::Relaxed),
+            sockets,
+        }
+    }
+
     /// Gracefully shutdown all connections
     pub async fn shutdown_all(&self) -> Vec<Uuid> {
         let mut connections = self.connections.lock().await;
@@ -379,6 +456,34 @@ impl WebSocketConnectionManager {
     }
 }
 
+/// Endpoint without its query string, so sockets group by subscription.
+fn endpoint_path(endpoint: &str) -> &str {
+    endpoint.split('?').next().unwrap_or(endpoint)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SocketState {
+    pub id: String,
+    pub endpoint: String,
+    pub age_secs: u64,
+    pub idle_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionManagerState {
+    pub open: usize,
+    /// Open sockets per endpoint path.
+    pub by_endpoint: BTreeMap<String, usize>,
+    pub opened_total: u64,
+    pub failed_total: u64,
+    pub reconnect_attempts_total: u64,
+    pub stale_removed_total: u64,
+    /// Messages relayed over backend sockets, either way.
+    pub messages_total: u64,
+    /// Longest idle first.
+    pub sockets: Vec<SocketState>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ConnectionInfo {
     pub id: Uuid,
@@ -532,6 +637,45 @@ mod tests {
         let _ = handle.await;
     }
 
+    #[tokio::test]
+    async fn test_introspect_groups_by_endpoint_and_counts_stale() {
+        let manager = create_test_manager();
+        {
+            let mut connections = manager.connections.lock().await;
+            for endpoint in [
+                "/v1/taproot-assets/events/asset-receive?method=POST",
+                "/v1/taproot-assets/events/asset-receive?method=POST",
+                "/v1/taproot-assets/subscribe/send",
+            ] {
+                let id = Uuid::new_v4();
+                let stale = Instant::now() - Duration::from_secs(120);
+                connections.insert(
+                    id,
+                    BackendConnection {
+                        id,
+                        endpoint: endpoint.to_string(),
+                        created_at: stale,
+                        last_activity: Arc::new(Mutex::new(stale)),
+                    },
+                );
+            }
+        }
+
+        let state = manager.introspect().await;
+        assert_eq!(state.open, 3);
+        assert_eq!(
+            state.by_endpoint["/v1/taproot-assets/events/asset-receive"],
+            2
+        );
+        assert_eq!(state.by_endpoint["/v1/taproot-assets/subscribe/send"], 1);
+        assert!(state.sockets.iter().all(|s| s.idle_secs >= 120));
+
+        assert_eq!(manager.cleanup_stale_connections(60).await.len(), 3);
+        let state = manager.clone().introspect().await;
+        assert_eq!(state.open, 0);
+        assert_eq!(state.stale_removed_total, 3);
+    }
+
     #[tokio::test]
     async fn test_shutdown_all() {
         let manager = create_test_manager();
@@ -0,0 +1,337 @@
+//! Control messages clients send the gateway over WebSocket sessions, as
+//! opposed to requests forwarded to tapd. A control message is a JSON
+//! object with a `type`, the protocol version in `v` and optionally an `id`
+//! the reply echoes:
+//!
+//! | `type` | Fields | Reply |
+//! |--------|--------|-------|
+//! | `subscribe` | `request`: the stream's subscription body | `ack` |
+//! | `filter` | `asset_ids`, `min_amount`, `event_types` | `ack` |
+//! | `auth` | `token` | `error`; sessions authenticate on upgrade |
+//! | `ping` | | `pong` |
+//!
+//! Malformed messages, and messages a stream does not take, are answered
+//! with an `error` frame carrying an [`ErrorCode`] rather than dropped or
+//! forwarded to tapd.
+
+use super::filter::EventFilter;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::RwLock;
+
+/// Version of the control message schema, sent as `v`.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+fn empty_object() -> Value {
+    json!({})
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ControlMessage {
+    /// Starts the stream with `request` as its subscription body.
+    Subscribe {
+        #[serde(default = "empty_object")]
+        request: Value,
+    },
+    /// Replaces the session's event filter; an empty one passes everything.
+    Filter {
+        #[serde(default)]
+        asset_ids: Vec<String>,
+        min_amount: Option<u64>,
+        #[serde(default)]
+        event_types: Vec<String>,
+    },
+    Auth {
+        token: String,
+    },
+    Ping,
+}
+
+impl ControlMessage {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ControlMessage::Subscribe { .. } => "subscribe",
+            ControlMessage::Filter { .. } => "filter",
+            ControlMessage::Auth { .. } => "auth",
+            ControlMessage::Ping => "ping",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlFrame {
+    /// Echoed in the reply so clients can match it up.
+    pub id: Option<Value>,
+    pub message: ControlMessage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The frame is not JSON.
+    InvalidJson,
+    /// `v` is missing or names a version the gateway does not speak.
+    UnsupportedVersion,
+    /// The frame does not match the schema of its `type`.
+    InvalidMessage,
+    /// A valid message the stream does not take.
+    Unsupported,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub id: Option<Value>,
+}
+
+impl ControlError {
+    pub fn new(code: ErrorCode, message: impl Into<String>, id: Option<Value>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            id,
+        }
+    }
+
+    pub fn unsupported(frame: &ControlFrame, reason: &str) -> Self {
+        Self::new(
+            ErrorCode::Unsupported,
+            format!("{} is not supported here: {reason}", frame.message.kind()),
+            frame.id.clone(),
+        )
+    }
+
+    /// The `error` frame sent to the client.
+    pub fn to_frame(&self) -> String {
+        reply(
+            "error",
+            self.id.as_ref(),
+            json!({ "code": self.code, "message": self.message }),
+        )
+    }
+}
+
+fn reply(kind: &str, id: Option<&Value>, fields: Value) -> String {
+    let mut frame = Map::new();
+    frame.insert("v".to_string(), json!(PROTOCOL_VERSION));
+    frame.insert("type".to_string(), json!(kind));
+    if let Some(id) = id {
+        frame.insert("id".to_string(), id.clone());
+    }
+    if let Value::Object(fields) = fields {
+        frame.extend(fields);
+    }
+    Value::Object(frame).to_string()
+}
+
+/// Reads a client text frame. A JSON object without a `type` is not a
+/// control message and yields `None`, so proxied sessions keep forwarding
+/// tapd requests as they are.
+pub fn parse(text: &str) -> Result<Option<ControlFrame>, ControlError> {
+    let value: Value = serde_json::from_str(text).map_err(|e| {
+        ControlError::new(
+            ErrorCode::InvalidJson,
+            format!("Frame is not JSON: {e}"),
+            None,
+        )
+    })?;
+    let Value::Object(mut object) = value else {
+        return Err(ControlError::new(
+            ErrorCode::InvalidMessage,
+            "Frame must be a JSON object",
+            None,
+        ));
+    };
+    if !object.contains_key("type") {
+        return Ok(None);
+    }
+    let id = object.remove("id");
+    match object.remove("v") {
+        Some(v) if v.as_u64() == Some(PROTOCOL_VERSION) => {}
+        Some(v) => {
+            return Err(ControlError::new(
+                ErrorCode::UnsupportedVersion,
+                format!("Protocol version {v} is not supported, expected {PROTOCOL_VERSION}"),
+                id,
+            ))
+        }
+        None => {
+            return Err(ControlError::new(
+                ErrorCode::UnsupportedVersion,
+                format!("Control messages need \"v\": {PROTOCOL_VERSION}"),
+                id,
+            ))
+        }
+    }
+    match serde_json::from_value(Value::Object(object)) {
+        Ok(message) => Ok(Some(ControlFrame { id, message })),
+        Err(e) => Err(ControlError::new(
+            ErrorCode::InvalidMessage,
+            e.to_string(),
+            id,
+        )),
+    }
+}
+
+/// [`parse`] for streams with no backend to forward to, where every frame
+/// must be a control message.
+pub fn parse_control(text: &str) -> Result<ControlFrame, ControlError> {
+    parse(text)?.ok_or_else(|| {
+        ControlError::new(
+            ErrorCode::InvalidMessage,
+            "Expected a control message with a \"type\"",
+            None,
+        )
+    })
+}
+
+/// What a session does with a control message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Send the frame to the client.
+    Reply(String),
+    /// Send `request` to the backend, then `reply` to the client.
+    Subscribe { request: String, reply: String },
+}
+
+/// Handles `frame` for a session whose events pass `filter`. `subscribe`
+/// is only taken when `can_subscribe`, i.e. the session has a backend
+/// stream that was not started yet.
+pub fn handle(frame: ControlFrame, filter: &RwLock<EventFilter>, can_subscribe: bool) -> Action {
+    let ack = reply(
+        "ack",
+        frame.id.as_ref(),
+        json!({ "of": frame.message.kind() }),
+    );
+    match &frame.message {
+        ControlMessage::Ping => Action::Reply(reply("pong", frame.id.as_ref(), json!({}))),
+        ControlMessage::Filter {
+            asset_ids,
+            min_amount,
+            event_types,
+        } => match EventFilter::from_parts(asset_ids, *min_amount, event_types) {
+            Ok(parsed) => {
+                *filter.write().unwrap_or_else(|e| e.into_inner()) = parsed;
+                Action::Reply(ack)
+            }
+            Err(e) => Action::Reply(
+                ControlError::new(ErrorCode::InvalidMessage, e.to_string(), frame.id).to_frame(),
+            ),
+        },
+        ControlMessage::Subscribe { request } if can_subscribe => {
+            if !request.is_object() {
+                return Action::Reply(
+                    ControlError::new(
+                        ErrorCode::InvalidMessage,
+                        "subscribe request must be a JSON object",
+                        frame.id,
+                    )
+                    .to_frame(),
+                );
+            }
+            Action::Subscribe {
+                request: request.to_string(),
+                reply: ack,
+            }
+        }
+        ControlMessage::Subscribe { .. } => Action::Reply(
+            ControlError::unsupported(&frame, "the stream is already subscribed").to_frame(),
+        ),
+        ControlMessage::Auth { .. } => Action::Reply(
+            ControlError::unsupported(
+                &frame,
+                "sessions authenticate with the upgrade request's credentials",
+            )
+            .to_frame(),
+        ),
+    }
+}
+
+/// The reply to a client frame on a stream without a backend, where every
+/// frame must be a control message and `subscribe` is not taken.
+pub fn answer(text: &str, filter: &RwLock<EventFilter>) -> String {
+    match parse_control(text) {
+        Ok(frame) => match handle(frame, filter, false) {
+            Action::Reply(reply) | Action::Subscribe { reply, .. } => reply,
+        },
+        Err(e) => e.to_frame(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_code(result: Result<Option<ControlFrame>, ControlError>) -> ErrorCode {
+        result.unwrap_err().code
+    }
+
+    #[test]
+    fn test_parse_validates_the_schema() {
+        assert_eq!(parse(r#"{"filter_addr":"taprt1"}"#).unwrap(), None);
+        let frame = parse(r#"{"v":1,"type":"ping","id":7}"#).unwrap().unwrap();
+        assert_eq!(frame.message, ControlMessage::Ping);
+        assert_eq!(frame.id, Some(json!(7)));
+
+        assert_eq!(error_code(parse("not json")), ErrorCode::InvalidJson);
+        assert_eq!(error_code(parse("[1]")), ErrorCode::InvalidMessage);
+        assert_eq!(
+            error_code(parse(r#"{"type":"ping"}"#)),
+            ErrorCode::UnsupportedVersion
+        );
+        assert_eq!(
+            error_code(parse(r#"{"v":2,"type":"ping"}"#)),
+            ErrorCode::UnsupportedVersion
+        );
+        assert_eq!(
+            error_code(parse(r#"{"v":1,"type":"unsubscribe"}"#)),
+            ErrorCode::InvalidMessage
+        );
+        assert_eq!(
+            error_code(parse(r#"{"v":1,"type":"filter","asset_id":"aa"}"#)),
+            ErrorCode::InvalidMessage
+        );
+        assert!(parse_control(r#"{"filter_addr":"taprt1"}"#).is_err());
+    }
+
+    #[test]
+    fn test_handle_replies_and_updates_the_filter() {
+        let filter = RwLock::new(EventFilter::default());
+        let asset = "ab".repeat(32);
+        let frame = parse(&format!(
+            r#"{{"v":1,"type":"filter","id":"f","asset_ids":["{asset}"],"min_amount":5}}"#
+        ))
+        .unwrap()
+        .unwrap();
+        let Action::Reply(ack) = handle(frame, &filter, false) else {
+            panic!("filter is answered directly");
+        };
+        let ack: Value = serde_json::from_str(&ack).unwrap();
+        assert_eq!(
+            ack,
+            json!({"v": 1, "type": "ack", "id": "f", "of": "filter"})
+        );
+        assert_eq!(filter.read().unwrap().min_amount, Some(5));
+
+        let bad = parse(r#"{"v":1,"type":"filter","asset_ids":["zz"]}"#)
+            .unwrap()
+            .unwrap();
+        let Action::Reply(error) = handle(bad, &filter, false) else {
+            panic!("bad filters are answered directly");
+        };
+        assert!(error.contains("invalid_message"));
+        assert_eq!(filter.read().unwrap().min_amount, Some(5));
+
+        let subscribe = parse_control(r#"{"v":1,"type":"subscribe","request":{"a":1}}"#).unwrap();
+        assert!(matches!(
+            handle(subscribe.clone(), &filter, true),
+            Action::Subscribe { ref request, .. } if request == r#"{"a":1}"#
+        ));
+        let Action::Reply(refused) = handle(subscribe, &filter, false) else {
+            panic!("subscribed streams refuse another subscribe");
+        };
+        assert!(refused.contains("unsupported"));
+    }
+}
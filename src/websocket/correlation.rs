@@ -1,7 +1,11 @@
 #![allow(dead_code)]
 
+use crate::error::AppError;
+use serde::Deserialize;
+use serde_json::value::RawValue;
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use tracing::{debug, warn};
@@ -11,6 +15,159 @@ pub(crate) const CORRELATION_TIMEOUT: std::time::Duration = std::time::Duration:
 pub(crate) const CORRELATION_CLEANUP_INTERVAL: std::time::Duration =
     std::time::Duration::from_secs(30);
 
+/// Field the injecting strategies write correlation IDs into unless
+/// configured otherwise.
+pub const DEFAULT_CORRELATION_FIELD: &str = "_correlation_id";
+
+/// How a proxied WebSocket pairs client requests with backend responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CorrelationStrategy {
+    /// Frames pass through untouched.
+    Off,
+    /// Request frames get a generated ID in the correlation field; responses
+    /// echoing it are matched.
+    InjectField,
+    /// Nothing is injected; each backend frame answers the oldest pending
+    /// request. For streams that reply once per request, in order.
+    Sequence,
+    /// Like `InjectField`, but IDs are derived from the upgrade request's
+    /// `X-Request-ID` so backend logs line up with the gateway's.
+    RequestIdHeader,
+    /// Clients frame requests as `{"id": ..., "payload": ...}`; the gateway
+    /// forwards the payload byte for byte and wraps each backend frame in an
+    /// envelope carrying the ID of the oldest pending request.
+    Envelope,
+}
+
+impl FromStr for CorrelationStrategy {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "inject-field" => Ok(Self::InjectField),
+            "sequence" => Ok(Self::Sequence),
+            "request-id-header" => Ok(Self::RequestIdHeader),
+            "envelope" => Ok(Self::Envelope),
+            other => Err(AppError::ValidationError(format!(
+                "Unknown correlation strategy '{other}', expected off, sequence, envelope, inject-field or request-id-header"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CorrelationConfig {
+    pub strategy: CorrelationStrategy,
+    /// JSON field carrying the correlation ID.
+    pub field: String,
+}
+
+impl CorrelationConfig {
+    pub fn new(strategy: CorrelationStrategy) -> Self {
+        Self {
+            strategy,
+            field: DEFAULT_CORRELATION_FIELD.to_string(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.strategy != CorrelationStrategy::Off
+    }
+}
+
+/// Per-route overrides of each WebSocket route's built-in strategy, keyed
+/// by the gateway path the client connects to.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct CorrelationRoutes {
+    routes: HashMap<String, CorrelationConfig>,
+    field: Option<String>,
+}
+
+impl CorrelationRoutes {
+    /// Parses `WS_CORRELATION`, e.g.
+    /// `/v1/taproot-assets/mailbox/receive=sequence;/v1/taproot-assets/channels/send-payment=inject-field:payment_ref`.
+    /// `field` replaces the default correlation field for every route
+    /// without one of its own.
+    pub fn parse(value: &str, field: Option<String>) -> Result<Self, AppError> {
+        let check_field = |field: &str| {
+            if field.is_empty() || field.chars().any(char::is_whitespace) {
+                Err(AppError::ValidationError(format!(
+                    "Invalid correlation field name '{field}'"
+                )))
+            } else {
+                Ok(())
+            }
+        };
+        if let Some(field) = &field {
+            check_field(field)?;
+        }
+
+        let mut routes = HashMap::new();
+        for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (path, spec) = entry
+                .split_once('=')
+                .map(|(p, s)| (p.trim(), s.trim()))
+                .filter(|(p, _)| p.starts_with('/'))
+                .ok_or_else(|| {
+                    AppError::ValidationError(format!(
+                        "WS_CORRELATION entry must be /path=strategy[:field]: {entry}"
+                    ))
+                })?;
+            let (strategy, route_field) = match spec.split_once(':') {
+                Some((strategy, route_field)) => {
+                    let route_field = route_field.trim();
+                    check_field(route_field)?;
+                    (strategy, Some(route_field.to_string()))
+                }
+                None => (spec, None),
+            };
+            let config = CorrelationConfig {
+                strategy: strategy.parse()?,
+                field: route_field
+                    .or_else(|| field.clone())
+                    .unwrap_or_else(|| DEFAULT_CORRELATION_FIELD.to_string()),
+            };
+            if routes.insert(path.to_string(), config).is_some() {
+                return Err(AppError::ValidationError(format!(
+                    "WS_CORRELATION lists {path} more than once"
+                )));
+            }
+        }
+        Ok(Self { routes, field })
+    }
+
+    /// The configuration for `path`, falling back to the route's `default`
+    /// strategy.
+    pub fn resolve(&self, path: &str, default: CorrelationStrategy) -> CorrelationConfig {
+        self.routes.get(path).cloned().unwrap_or_else(|| {
+            let mut config = CorrelationConfig::new(default);
+            if let Some(field) = &self.field {
+                config.field = field.clone();
+            }
+            config
+        })
+    }
+}
+
+/// Client-leg framing of the `Envelope` strategy. Both fields are kept raw
+/// so the payload reaches the backend and the ID returns to the client
+/// exactly as written.
+#[derive(Deserialize)]
+struct ClientEnvelope<'a> {
+    #[serde(borrow, default)]
+    id: Option<&'a RawValue>,
+    #[serde(borrow)]
+    payload: &'a RawValue,
+}
+
+#[derive(serde::Serialize)]
+struct BackendEnvelope<'a> {
+    id: Option<&'a RawValue>,
+    payload: &'a RawValue,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct PendingRequest {
     pub correlation_id: String,
@@ -24,24 +181,123 @@ pub(crate) struct CorrelationTracker {
     pending_requests: HashMap<String, PendingRequest>,
     next_correlation_id: AtomicU64,
     session_id: Uuid,
+    config: CorrelationConfig,
+    /// The upgrade request's ID, for `RequestIdHeader`.
+    request_id: Option<String>,
+    /// Pending IDs in send order, for `Sequence`.
+    send_order: VecDeque<String>,
 }
 
 impl CorrelationTracker {
     pub fn new(session_id: Uuid) -> Self {
+        Self::with_config(
+            session_id,
+            CorrelationConfig::new(CorrelationStrategy::InjectField),
+            None,
+        )
+    }
+
+    pub fn with_config(
+        session_id: Uuid,
+        config: CorrelationConfig,
+        request_id: Option<String>,
+    ) -> Self {
         Self {
             pending_requests: HashMap::new(),
             next_correlation_id: AtomicU64::new(1),
             session_id,
+            config,
+            request_id,
+            send_order: VecDeque::new(),
         }
     }
 
     pub fn generate_correlation_id(&self) -> String {
         let id = self.next_correlation_id.fetch_add(1, Ordering::Relaxed);
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis();
-        format!("corr_{}_{}_{}", self.session_id, id, timestamp)
+        match (&self.config.strategy, &self.request_id) {
+            (CorrelationStrategy::RequestIdHeader, Some(request_id)) => {
+                format!("{request_id}-{id}")
+            }
+            (CorrelationStrategy::Sequence | CorrelationStrategy::Envelope, _) => {
+                format!("seq_{}_{}", self.session_id, id)
+            }
+            _ => {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                format!("corr_{}_{}_{}", self.session_id, id, timestamp)
+            }
+        }
+    }
+
+    /// Applies the strategy to a client frame on its way to the backend,
+    /// returning the frame to send.
+    pub fn track_outbound(&mut self, message: String) -> String {
+        match self.config.strategy {
+            CorrelationStrategy::Off => message,
+            CorrelationStrategy::Sequence => {
+                let correlation_id = self.generate_correlation_id();
+                self.send_order.push_back(correlation_id.clone());
+                self.add_pending_request(correlation_id, message.clone());
+                message
+            }
+            CorrelationStrategy::Envelope => {
+                let Ok(envelope) = serde_json::from_str::<ClientEnvelope>(&message) else {
+                    debug!("Forwarding unframed client message unchanged");
+                    return message;
+                };
+                // A string payload carries a non-JSON frame
+                let payload = match serde_json::from_str::<String>(envelope.payload.get()) {
+                    Ok(text) => text,
+                    Err(_) => envelope.payload.get().to_string(),
+                };
+                let correlation_id = match envelope.id {
+                    Some(id) => id.get().to_string(),
+                    None => json!(self.generate_correlation_id()).to_string(),
+                };
+                self.send_order.push_back(correlation_id.clone());
+                self.add_pending_request(correlation_id, payload.clone());
+                payload
+            }
+            CorrelationStrategy::InjectField | CorrelationStrategy::RequestIdHeader => {
+                if !MessageProcessor::is_request_message(&message) {
+                    return message;
+                }
+                let correlation_id = self.generate_correlation_id();
+                match MessageProcessor::inject_field(&message, &self.config.field, &correlation_id)
+                {
+                    Ok(modified) => {
+                        self.add_pending_request(correlation_id, message);
+                        modified
+                    }
+                    Err(e) => {
+                        warn!("Failed to inject correlation ID: {}, sending original", e);
+                        message
+                    }
+                }
+            }
+        }
+    }
+
+    /// Matches a backend frame to the request it answers, if any.
+    pub fn match_inbound(&mut self, message: &str) -> Option<PendingRequest> {
+        match self.config.strategy {
+            CorrelationStrategy::Off => None,
+            CorrelationStrategy::Sequence | CorrelationStrategy::Envelope => {
+                // IDs of expired requests are skipped
+                while let Some(correlation_id) = self.send_order.pop_front() {
+                    if let Some(request) = self.remove_pending_request(&correlation_id) {
+                        return Some(request);
+                    }
+                }
+                None
+            }
+            CorrelationStrategy::InjectField | CorrelationStrategy::RequestIdHeader => {
+                let correlation_id = MessageProcessor::extract_field(message, &self.config.field)?;
+                self.remove_pending_request(&correlation_id)
+            }
+        }
     }
 
     pub fn add_pending_request(&mut self, correlation_id: String, original_message: String) {
@@ -66,6 +322,28 @@ impl CorrelationTracker {
         request
     }
 
+    /// Frames a backend message for the client. Only `Envelope` changes it,
+    /// wrapping it with the ID of the request it answers (`null` if none).
+    pub fn frame_inbound(&self, message: String, matched: Option<&PendingRequest>) -> String {
+        if self.config.strategy != CorrelationStrategy::Envelope {
+            return message;
+        }
+        // Non-JSON frames travel as a JSON string, as on the client leg
+        let payload = match serde_json::from_str::<&RawValue>(&message) {
+            Ok(raw) => raw.to_owned(),
+            Err(_) => match RawValue::from_string(json!(message).to_string()) {
+                Ok(raw) => raw,
+                Err(_) => return message,
+            },
+        };
+        let id = matched.and_then(|m| RawValue::from_string(m.correlation_id.clone()).ok());
+        let framed = BackendEnvelope {
+            id: id.as_deref(),
+            payload: &payload,
+        };
+        serde_json::to_string(&framed).unwrap_or_else(|_| payload.get().to_string())
+    }
+
     pub fn cleanup_expired_requests(&mut self) -> Vec<PendingRequest> {
         let now = Instant::now();
         let mut expired = Vec::new();
@@ -79,6 +357,10 @@ impl CorrelationTracker {
                 true
             }
         });
+        if !expired.is_empty() {
+            let pending = &self.pending_requests;
+            self.send_order.retain(|id| pending.contains_key(id));
+        }
 
         expired
     }
@@ -94,20 +376,30 @@ impl MessageProcessor {
     pub fn inject_correlation_id(
         message: &str,
         correlation_id: &str,
+    ) -> Result<String, serde_json::Error> {
+        Self::inject_field(message, DEFAULT_CORRELATION_FIELD, correlation_id)
+    }
+
+    /// Writes `correlation_id` into `field`, wrapping frames that are not
+    /// JSON objects.
+    pub fn inject_field(
+        message: &str,
+        field: &str,
+        correlation_id: &str,
     ) -> Result<String, serde_json::Error> {
         match serde_json::from_str::<Value>(message) {
             Ok(mut json) => {
                 if let Some(obj) = json.as_object_mut() {
-                    obj.insert("_correlation_id".to_string(), json!(correlation_id));
+                    obj.insert(field.to_string(), json!(correlation_id));
                     debug!(
                         "Injected correlation ID {} into JSON message",
                         correlation_id
                     );
                 } else {
-                    json = json!({
-                        "_correlation_id": correlation_id,
-                        "_original_message": json
-                    });
+                    let mut wrapped = serde_json::Map::new();
+                    wrapped.insert(field.to_string(), json!(correlation_id));
+                    wrapped.insert("_original_message".to_string(), json);
+                    json = Value::Object(wrapped);
                     debug!(
                         "Wrapped non-object JSON with correlation ID {}",
                         correlation_id
@@ -116,21 +408,26 @@ impl MessageProcessor {
                 serde_json::to_string(&json)
             }
             Err(_) => {
-                let wrapped = json!({
-                    "_correlation_id": correlation_id,
-                    "_original_text": message,
-                    "_wrapped": true
-                });
+                let mut wrapped = serde_json::Map::new();
+                wrapped.insert(field.to_string(), json!(correlation_id));
+                wrapped.insert("_original_text".to_string(), json!(message));
+                wrapped.insert("_wrapped".to_string(), json!(true));
                 serde_json::to_string(&wrapped)
             }
         }
     }
 
     pub fn extract_correlation_id(message: &str) -> Option<String> {
+        Self::extract_field(message, DEFAULT_CORRELATION_FIELD)
+    }
+
+    /// Reads the correlation ID from `field`, falling back to the common
+    /// `correlation_id` and `request_id` response fields.
+    pub fn extract_field(message: &str, field: &str) -> Option<String> {
         match serde_json::from_str::<Value>(message) {
             Ok(json) => {
                 if let Some(obj) = json.as_object() {
-                    if let Some(corr_id) = obj.get("_correlation_id") {
+                    if let Some(corr_id) = obj.get(field) {
                         if let Some(id_str) = corr_id.as_str() {
                             debug!("Extracted correlation ID {} from response", id_str);
                             return Some(id_str.to_string());
@@ -272,6 +569,131 @@ mod tests {
         assert!(!MessageProcessor::is_response_message(other_message));
     }
 
+    fn tracker(strategy: &str, field: Option<&str>) -> CorrelationTracker {
+        let routes =
+            CorrelationRoutes::parse(&format!("/ws={strategy}"), field.map(str::to_string))
+                .unwrap();
+        CorrelationTracker::with_config(
+            Uuid::new_v4(),
+            routes.resolve("/ws", CorrelationStrategy::Off),
+            Some("req-42".to_string()),
+        )
+    }
+
+    #[test]
+    fn test_off_strategy_passes_frames_through() {
+        let mut tracker = tracker("off", None);
+        let request = r#"{"method": "send"}"#.to_string();
+        assert_eq!(tracker.track_outbound(request.clone()), request);
+        assert_eq!(tracker.pending_count(), 0);
+        assert!(tracker
+            .match_inbound(r#"{"_correlation_id": "x", "result": {}}"#)
+            .is_none());
+    }
+
+    #[test]
+    fn test_inject_field_strategy_uses_custom_field() {
+        let mut tracker = tracker("inject-field", Some("ref"));
+        let sent = tracker.track_outbound(r#"{"method": "send"}"#.to_string());
+        let sent: Value = serde_json::from_str(&sent).unwrap();
+        let id = sent["ref"].as_str().unwrap().to_string();
+        assert!(sent.get("_correlation_id").is_none());
+
+        // Notifications that do not look like requests are left alone
+        let notification = r#"{"notification": "update"}"#.to_string();
+        assert_eq!(tracker.track_outbound(notification.clone()), notification);
+
+        let response = json!({ "ref": id, "result": {} }).to_string();
+        let matched = tracker.match_inbound(&response).unwrap();
+        assert_eq!(matched.original_message, r#"{"method": "send"}"#);
+        assert_eq!(tracker.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_sequence_strategy_matches_in_send_order() {
+        let mut tracker = tracker("sequence", None);
+        for message in ["first", "second"] {
+            // Frames go out unchanged, whatever they look like
+            assert_eq!(tracker.track_outbound(message.to_string()), message);
+        }
+        assert_eq!(
+            tracker.match_inbound("any").unwrap().original_message,
+            "first"
+        );
+        assert_eq!(
+            tracker.match_inbound("{}").unwrap().original_message,
+            "second"
+        );
+        assert!(tracker.match_inbound("unsolicited").is_none());
+    }
+
+    #[test]
+    fn test_request_id_header_strategy_derives_ids() {
+        let mut tracker = tracker("request-id-header", None);
+        let sent = tracker.track_outbound(r#"{"method": "send"}"#.to_string());
+        let id = MessageProcessor::extract_correlation_id(&sent).unwrap();
+        assert_eq!(id, "req-42-1");
+        let response = json!({ "_correlation_id": id, "result": {} }).to_string();
+        assert!(tracker.match_inbound(&response).is_some());
+    }
+
+    #[test]
+    fn test_envelope_strategy_keeps_backend_payload_byte_exact() {
+        let mut tracker = tracker("envelope", None);
+        let sent =
+            tracker.track_outbound(r#"{"id": 7, "payload": {"b": 1,  "a": [2, 1]}}"#.to_string());
+        assert_eq!(sent, r#"{"b": 1,  "a": [2, 1]}"#);
+        let sent = tracker.track_outbound(r#"{"id": "x", "payload": "plain text"}"#.to_string());
+        assert_eq!(sent, "plain text");
+
+        let response = r#"{"z": 0, "a": 1}"#.to_string();
+        let matched = tracker.match_inbound(&response);
+        assert_eq!(
+            tracker.frame_inbound(response, matched.as_ref()),
+            r#"{"id":7,"payload":{"z": 0, "a": 1}}"#
+        );
+        let matched = tracker.match_inbound("ok");
+        assert_eq!(
+            tracker.frame_inbound("ok".to_string(), matched.as_ref()),
+            r#"{"id":"x","payload":"ok"}"#
+        );
+        let matched = tracker.match_inbound("{}");
+        assert!(matched.is_none());
+        assert_eq!(
+            tracker.frame_inbound("{}".to_string(), None),
+            r#"{"id":null,"payload":{}}"#
+        );
+
+        // Unframed messages pass through untracked
+        assert_eq!(tracker.track_outbound("raw".to_string()), "raw");
+        assert_eq!(tracker.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_correlation_routes_parse_and_resolve() {
+        let routes = CorrelationRoutes::parse(
+            "/a=sequence; /b=inject-field:ref",
+            Some("trace".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            routes.resolve("/a", CorrelationStrategy::Off),
+            CorrelationConfig {
+                strategy: CorrelationStrategy::Sequence,
+                field: "trace".to_string()
+            }
+        );
+        assert_eq!(routes.resolve("/b", CorrelationStrategy::Off).field, "ref");
+        let fallback = routes.resolve("/c", CorrelationStrategy::InjectField);
+        assert_eq!(fallback.strategy, CorrelationStrategy::InjectField);
+        assert_eq!(fallback.field, "trace");
+
+        assert!(CorrelationRoutes::parse("/a=fuzzy", None).is_err());
+        assert!(CorrelationRoutes::parse("a=off", None).is_err());
+        assert!(CorrelationRoutes::parse("/a=off;/a=sequence", None).is_err());
+        assert!(CorrelationRoutes::parse("/a=inject-field:", None).is_err());
+    }
+
     #[tokio::test]
     async fn test_correlation_timeout_cleanup() {
         let session_id = Uuid::new_v4();
@@ -0,0 +1,233 @@
+//! Subscriber-side filters for tapd event streams. Clients pass them as
+//! query parameters on the subscription and the gateway drops events that
+//! do not match before forwarding, so a client following a handful of
+//! assets does not receive the whole node's traffic.
+
+use crate::error::AppError;
+use crate::indexer::normalize_hex_id;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Fields that carry an event's state in tapd's mint, receive and send
+/// events.
+const STATE_FIELDS: &[&str] = &["batch_state", "status", "send_state"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    /// Events must name one of these assets (lowercase hex).
+    pub asset_ids: HashSet<String>,
+    /// Events must move at least this many units in one output or address.
+    pub min_amount: Option<u64>,
+    /// Events must be in one of these states, compared case-insensitively
+    /// either in full (`SEND_STATE_COMPLETED`) or by suffix (`completed`).
+    pub event_types: HashSet<String>,
+}
+
+impl EventFilter {
+    /// Splits `query` into the filter and the query string left for tapd.
+    /// `asset_id` and `event_type` may repeat or hold comma-separated lists.
+    pub fn from_query(query: &str) -> Result<(Self, String), AppError> {
+        let mut filter = Self::default();
+        let mut forwarded = url::form_urlencoded::Serializer::new(String::new());
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            let values = value.split(',').map(str::trim).filter(|v| !v.is_empty());
+            match key.as_ref() {
+                "asset_id" => {
+                    for asset_id in values {
+                        filter.asset_ids.insert(parse_asset_id(asset_id)?);
+                    }
+                }
+                "min_amount" => {
+                    filter.min_amount = Some(value.trim().parse().map_err(|_| {
+                        AppError::ValidationError(format!(
+                            "min_amount filter must be a non-negative integer, got '{value}'"
+                        ))
+                    })?);
+                }
+                "event_type" => {
+                    filter
+                        .event_types
+                        .extend(values.map(str::to_ascii_lowercase));
+                }
+                _ => {
+                    forwarded.append_pair(&key, &value);
+                }
+            }
+        }
+        Ok((filter, forwarded.finish()))
+    }
+
+    /// A filter from its parts, as a WebSocket `filter` control message
+    /// carries them.
+    pub fn from_parts(
+        asset_ids: &[String],
+        min_amount: Option<u64>,
+        event_types: &[String],
+    ) -> Result<Self, AppError> {
+        Ok(Self {
+            asset_ids: asset_ids
+                .iter()
+                .map(|id| parse_asset_id(id.trim()))
+                .collect::<Result<_, _>>()?,
+            min_amount,
+            event_types: event_types
+                .iter()
+                .map(|t| t.trim().to_ascii_lowercase())
+                .filter(|t| !t.is_empty())
+                .collect(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.asset_ids.is_empty() && self.min_amount.is_none() && self.event_types.is_empty()
+    }
+
+    /// Whether `event` passes. The grpc-gateway `{"result": ...}` wrapper is
+    /// looked through, and error frames always pass so subscribers learn
+    /// their stream failed.
+    pub fn matches(&self, event: &Value) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        if event.get("error").is_some_and(Value::is_object) {
+            return true;
+        }
+        let event = event.get("result").unwrap_or(event);
+
+        if !self.event_types.is_empty() {
+            let state = STATE_FIELDS
+                .iter()
+                .find_map(|field| event.get(*field).and_then(Value::as_str))
+                .map(str::to_ascii_lowercase);
+            let wanted = state.is_some_and(|state| {
+                self.event_types
+                    .iter()
+                    .any(|t| state == *t || state.ends_with(&format!("_{t}")))
+            });
+            if !wanted {
+                return false;
+            }
+        }
+
+        let mut asset_ids = HashSet::new();
+        let mut max_amount = None;
+        collect(event, &mut asset_ids, &mut max_amount);
+        if !self.asset_ids.is_empty() && self.asset_ids.is_disjoint(&asset_ids) {
+            return false;
+        }
+        match self.min_amount {
+            Some(min) => max_amount.is_some_and(|amount| amount >= min),
+            None => true,
+        }
+    }
+
+    /// [`EventFilter::matches`] for a text frame; frames that are not JSON
+    /// are passed on untouched.
+    pub fn matches_text(&self, text: &str) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        serde_json::from_str::<Value>(text).map_or(true, |event| self.matches(&event))
+    }
+}
+
+fn parse_asset_id(asset_id: &str) -> Result<String, AppError> {
+    if asset_id.len() != 64 || !asset_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AppError::ValidationError(format!(
+            "asset_id filter {asset_id} must be 32 bytes of hex"
+        )));
+    }
+    Ok(asset_id.to_ascii_lowercase())
+}
+
+/// Every `asset_id` and the largest `amount` anywhere in `value`. tapd
+/// renders ids as hex or base64 and amounts as strings.
+fn collect(value: &Value, asset_ids: &mut HashSet<String>, max_amount: &mut Option<u64>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match (key.as_str(), value) {
+                    ("asset_id", Value::String(id)) if !id.is_empty() => {
+                        asset_ids.insert(normalize_hex_id(id));
+                    }
+                    ("amount", _) => {
+                        let amount = match value {
+                            Value::String(s) => s.parse().ok(),
+                            other => other.as_u64(),
+                        };
+                        if let Some(amount) = amount {
+                            *max_amount = Some(max_amount.map_or(amount, |m: u64| m.max(amount)));
+                        }
+                    }
+                    _ => collect(value, asset_ids, max_amount),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect(item, asset_ids, max_amount);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ASSET: &str = "aa00000000000000000000000000000000000000000000000000000000000001";
+
+    #[test]
+    fn test_from_query_strips_filter_params() {
+        let (filter, forwarded) = EventFilter::from_query(&format!(
+            "filter_label=x&asset_id={}&event_type=completed,SEND_STATE_BROADCAST&min_amount=5",
+            ASSET.to_ascii_uppercase()
+        ))
+        .unwrap();
+        assert_eq!(forwarded, "filter_label=x");
+        assert!(filter.asset_ids.contains(ASSET));
+        assert_eq!(filter.min_amount, Some(5));
+        assert_eq!(filter.event_types.len(), 2);
+
+        assert!(EventFilter::from_query("asset_id=abcd").is_err());
+        assert!(EventFilter::from_query("min_amount=-1").is_err());
+        assert!(EventFilter::from_query("").unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_matches_assets_amounts_and_states() {
+        let (filter, _) = EventFilter::from_query(&format!(
+            "asset_id={ASSET}&min_amount=100&event_type=completed"
+        ))
+        .unwrap();
+        let receive = |asset_id: &str, amount: &str, status: &str| {
+            json!({"result": {
+                "status": status,
+                "address": {"asset_id": asset_id, "amount": amount}
+            }})
+        };
+        assert!(filter.matches(&receive(ASSET, "150", "ADDR_EVENT_STATUS_COMPLETED")));
+        assert!(!filter.matches(&receive(ASSET, "50", "ADDR_EVENT_STATUS_COMPLETED")));
+        assert!(!filter.matches(&receive(
+            ASSET,
+            "150",
+            "ADDR_EVENT_STATUS_TRANSACTION_DETECTED"
+        )));
+        assert!(!filter.matches(&receive(
+            &"bb".repeat(32),
+            "150",
+            "ADDR_EVENT_STATUS_COMPLETED"
+        )));
+
+        // tapd's base64 rendering of the same id
+        let base64_id = "qgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAE=";
+        assert!(filter.matches(&receive(base64_id, "150", "ADDR_EVENT_STATUS_COMPLETED")));
+
+        let error = json!({"error": {"code": 14, "message": "unavailable"}});
+        assert!(filter.matches(&error));
+        assert!(filter.matches_text("not json"));
+        assert!(EventFilter::default().matches(&json!({})));
+    }
+}
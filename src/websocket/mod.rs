@@ -1,3 +1,7 @@
 pub mod connection_manager;
+pub mod control;
 pub mod correlation;
+pub mod filter;
+pub mod policy;
 pub mod proxy_handler;
+pub mod topics;
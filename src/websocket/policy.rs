@@ -0,0 +1,163 @@
+//! Per-session limits for WebSocket routes. Each endpoint group has its own
+//! policy so chatty mailbox clients and long-lived event subscriptions can
+//! be tuned independently.
+
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Proxied WebSocket routes, grouped by the limits they share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointGroup {
+    Mailbox,
+    Channels,
+    Events,
+}
+
+impl EndpointGroup {
+    pub const ALL: [EndpointGroup; 3] = [Self::Mailbox, Self::Channels, Self::Events];
+
+    /// Prefix of the group's environment variables, e.g. `WS_MAILBOX`.
+    pub fn env_prefix(self) -> &'static str {
+        match self {
+            Self::Mailbox => "WS_MAILBOX",
+            Self::Channels => "WS_CHANNELS",
+            Self::Events => "WS_EVENTS",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct WsPolicy {
+    /// Client messages allowed per minute before the session is closed.
+    pub messages_per_minute: u32,
+    /// Largest client frame accepted, in bytes.
+    pub max_message_bytes: usize,
+    /// The session closes after this long without traffic.
+    pub idle_timeout_secs: u64,
+}
+
+impl WsPolicy {
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
+
+    pub fn rate_limiter(&self) -> MessageRateLimiter {
+        MessageRateLimiter::new(self.messages_per_minute)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct WsPolicies {
+    pub mailbox: WsPolicy,
+    pub channels: WsPolicy,
+    pub events: WsPolicy,
+}
+
+impl Default for WsPolicies {
+    fn default() -> Self {
+        Self {
+            mailbox: Self::default_for(EndpointGroup::Mailbox),
+            channels: Self::default_for(EndpointGroup::Channels),
+            events: Self::default_for(EndpointGroup::Events),
+        }
+    }
+}
+
+impl WsPolicies {
+    /// Limits used when nothing is configured.
+    pub fn default_for(group: EndpointGroup) -> WsPolicy {
+        match group {
+            EndpointGroup::Mailbox | EndpointGroup::Events => WsPolicy {
+                messages_per_minute: 60,
+                max_message_bytes: 64 * 1024,
+                idle_timeout_secs: 300,
+            },
+            // Payment requests can carry large custom records
+            EndpointGroup::Channels => WsPolicy {
+                messages_per_minute: 60,
+                max_message_bytes: 1024 * 1024,
+                idle_timeout_secs: 300,
+            },
+        }
+    }
+
+    pub fn get(&self, group: EndpointGroup) -> WsPolicy {
+        match group {
+            EndpointGroup::Mailbox => self.mailbox,
+            EndpointGroup::Channels => self.channels,
+            EndpointGroup::Events => self.events,
+        }
+    }
+
+    pub fn set(&mut self, group: EndpointGroup, policy: WsPolicy) {
+        match group {
+            EndpointGroup::Mailbox => self.mailbox = policy,
+            EndpointGroup::Channels => self.channels = policy,
+            EndpointGroup::Events => self.events = policy,
+        }
+    }
+}
+
+/// Fixed one-minute window counter of client messages.
+#[derive(Debug)]
+pub struct MessageRateLimiter {
+    limit: u32,
+    count: u32,
+    window_start: Instant,
+}
+
+impl MessageRateLimiter {
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            count: 0,
+            window_start: Instant::now(),
+        }
+    }
+
+    /// Counts a message; false once the window's limit is exceeded.
+    pub fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        if now.duration_since(self.window_start) >= Duration::from_secs(60) {
+            self.count = 0;
+            self.window_start = now;
+        }
+        self.count += 1;
+        self.count <= self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_resets_each_minute() {
+        let mut limiter = MessageRateLimiter::new(2);
+        assert!(limiter.allow());
+        assert!(limiter.allow());
+        assert!(!limiter.allow());
+
+        limiter.window_start = Instant::now() - Duration::from_secs(61);
+        assert!(limiter.allow());
+    }
+
+    #[test]
+    fn test_policies_are_kept_per_group() {
+        let mut policies = WsPolicies::default();
+        assert_eq!(
+            policies.get(EndpointGroup::Mailbox).max_message_bytes,
+            64 * 1024
+        );
+        policies.set(
+            EndpointGroup::Events,
+            WsPolicy {
+                messages_per_minute: 5,
+                max_message_bytes: 128,
+                idle_timeout_secs: 30,
+            },
+        );
+        assert_eq!(policies.get(EndpointGroup::Events).messages_per_minute, 5);
+        assert_eq!(policies.get(EndpointGroup::Mailbox).messages_per_minute, 60);
+    }
+}
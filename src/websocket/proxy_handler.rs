@@ -1,10 +1,10 @@
 #![allow(dead_code)]
-use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web::{web, Error, HttpMessage, HttpRequest, HttpResponse};
 use actix_ws::{Message as WsMessage, MessageStream, Session};
 use futures_util::{SinkExt, StreamExt};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex;
 use tokio::time::{timeout, Duration};
@@ -13,12 +13,16 @@ use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 use super::connection_manager::WebSocketConnectionManager;
-use super::correlation::{CorrelationTracker, MessageProcessor, CORRELATION_CLEANUP_INTERVAL};
+use super::control::{self, Action};
+use super::correlation::{
+    CorrelationConfig, CorrelationStrategy, CorrelationTracker, CORRELATION_CLEANUP_INTERVAL,
+};
+use super::filter::EventFilter;
+use super::policy::{EndpointGroup, MessageRateLimiter, WsPolicies, WsPolicy};
+use crate::config::Config;
 use crate::error::AppError;
 
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(300);
 const MESSAGE_TIMEOUT: Duration = Duration::from_secs(30);
-const MAX_MESSAGE_SIZE: usize = 10 * 1024 * 1024;
 
 pub struct WebSocketProxyHandler {
     connection_manager: Arc<WebSocketConnectionManager>,
@@ -47,15 +51,103 @@ impl WebSocketProxyHandler {
         }
     }
 
-    /// Handles incoming WebSocket connection requests
+    /// Handles incoming WebSocket connection requests. `default_correlation`
+    /// is the route's strategy unless `WS_CORRELATION` overrides it, and
+    /// `group` selects the session limits.
     pub async fn handle_websocket(
         &self,
         req: HttpRequest,
         stream: web::Payload,
         backend_endpoint: &str,
-        correlation_required: bool,
+        default_correlation: CorrelationStrategy,
+        group: EndpointGroup,
+    ) -> Result<HttpResponse, Error> {
+        self.open_session(
+            req,
+            stream,
+            backend_endpoint,
+            default_correlation,
+            group,
+            None,
+            EventFilter::default(),
+        )
+        .await
+    }
+
+    /// Proxies an event stream, dropping backend text frames that do not
+    /// pass the subscriber's `filter`.
+    pub async fn handle_filtered_websocket(
+        &self,
+        req: HttpRequest,
+        stream: web::Payload,
+        backend_endpoint: &str,
+        group: EndpointGroup,
+        filter: EventFilter,
+    ) -> Result<HttpResponse, Error> {
+        self.open_session(
+            req,
+            stream,
+            backend_endpoint,
+            CorrelationStrategy::Off,
+            group,
+            None,
+            filter,
+        )
+        .await
+    }
+
+    /// Proxies a server-streaming subscription whose request the gateway
+    /// builds itself: `request` is sent to the backend before any client
+    /// frame, so the client only receives the filtered stream.
+    pub async fn handle_subscription(
+        &self,
+        req: HttpRequest,
+        stream: web::Payload,
+        backend_endpoint: &str,
+        request: String,
+        group: EndpointGroup,
+        filter: EventFilter,
+    ) -> Result<HttpResponse, Error> {
+        self.open_session(
+            req,
+            stream,
+            backend_endpoint,
+            CorrelationStrategy::Off,
+            group,
+            Some(request),
+            filter,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn open_session(
+        &self,
+        req: HttpRequest,
+        stream: web::Payload,
+        backend_endpoint: &str,
+        default_correlation: CorrelationStrategy,
+        group: EndpointGroup,
+        initial_request: Option<String>,
+        filter: EventFilter,
     ) -> Result<HttpResponse, Error> {
         let session_id = Uuid::new_v4();
+        let policy = req.app_data::<web::Data<Config>>().map_or_else(
+            || WsPolicies::default_for(group),
+            |c| c.ws_policies.get(group),
+        );
+        let correlation = req.app_data::<web::Data<Config>>().map_or_else(
+            || CorrelationConfig::new(default_correlation),
+            |c| c.ws_correlation.resolve(req.path(), default_correlation),
+        );
+        let correlation_required = correlation.is_enabled();
+        // A caller-supplied X-Request-ID wins over the one the gateway assigned
+        let request_id = req
+            .headers()
+            .get("X-Request-ID")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .or_else(|| req.extensions().get::<String>().cloned());
         let client_addr = req
             .peer_addr()
             .map(|addr| addr.to_string())
@@ -69,8 +161,10 @@ impl WebSocketProxyHandler {
         // Upgrade to WebSocket
         let (response, session, msg_stream) = actix_ws::handle(&req, stream)?;
 
+        let can_subscribe = initial_request.is_none();
+
         // Create backend connection
-        let (backend_conn_id, backend_sink, backend_stream) = self
+        let (backend_conn_id, mut backend_sink, backend_stream) = self
             .connection_manager
             .connect_to_backend(backend_endpoint)
             .await
@@ -79,13 +173,40 @@ impl WebSocketProxyHandler {
                 actix_web::error::ErrorInternalServerError(format!("WebSocket proxy error: {e}"))
             })?;
 
+        if let Some(request) = initial_request {
+            if let Err(e) = timeout(
+                MESSAGE_TIMEOUT,
+                backend_sink.send(TungsteniteMessage::Text(request.into())),
+            )
+            .await
+            .map_err(|_| "timed out".to_string())
+            .and_then(|r| r.map_err(|e| e.to_string()))
+            {
+                error!("Failed to send subscription request to backend: {}", e);
+                self.connection_manager
+                    .remove_connection(backend_conn_id)
+                    .await;
+                return Err(actix_web::error::ErrorBadGateway(
+                    "WebSocket proxy error: backend rejected the subscription",
+                ));
+            }
+        }
+
         // Store proxy session
         let current_epoch = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
         let correlation_tracker = if correlation_required {
-            Some(Arc::new(Mutex::new(CorrelationTracker::new(session_id))))
+            debug!(
+                "Correlating {} with {:?} on field {}",
+                backend_endpoint, correlation.strategy, correlation.field
+            );
+            Some(Arc::new(Mutex::new(CorrelationTracker::with_config(
+                session_id,
+                correlation,
+                request_id,
+            ))))
         } else {
             None
         };
@@ -118,6 +239,9 @@ impl WebSocketProxyHandler {
                     backend_stream,
                     backend_conn_id,
                     correlation_required,
+                    policy,
+                    filter,
+                    can_subscribe,
                 )
                 .await
             {
@@ -151,9 +275,14 @@ impl WebSocketProxyHandler {
         >,
         backend_conn_id: Uuid,
         _correlation_required: bool,
+        policy: WsPolicy,
+        filter: EventFilter,
+        can_subscribe: bool,
     ) -> Result<(), AppError> {
         let client_sink = Arc::new(Mutex::new(client_session));
         let backend_sink = Arc::new(Mutex::new(backend_sink));
+        // Control messages replace the filter while the session runs
+        let filter = Arc::new(RwLock::new(filter));
 
         // Get correlation tracker if enabled
         let correlation_tracker = if _correlation_required {
@@ -176,15 +305,36 @@ impl WebSocketProxyHandler {
 
         // Spawn task to forward client -> backend
         let client_to_backend = {
+            let client_sink = client_sink.clone();
             let backend_sink = backend_sink.clone();
             let connection_manager = self.connection_manager.clone();
             let activity_tracker = activity_tracker.clone();
             let correlation_tracker_clone = correlation_tracker.clone();
+            let filter = filter.clone();
 
             actix_web::rt::spawn(async move {
                 let mut client_stream = client_stream;
+                let mut rate_limiter = policy.rate_limiter();
+                let mut can_subscribe = can_subscribe;
+
+                loop {
+                    let msg = match timeout(policy.idle_timeout(), client_stream.next()).await {
+                        Ok(Some(msg)) => msg,
+                        Ok(None) => break,
+                        // Backend traffic keeps the session alive too
+                        Err(_) if !is_idle(&activity_tracker, policy.idle_timeout()) => continue,
+                        Err(_) => {
+                            warn!("Closing idle WebSocket session {}", session_id);
+                            close_client(
+                                &client_sink,
+                                actix_ws::CloseCode::Normal,
+                                "Connection idle timeout",
+                            )
+                            .await;
+                            break;
+                        }
+                    };
 
-                while let Ok(Some(msg)) = timeout(CLIENT_TIMEOUT, client_stream.next()).await {
                     // Update activity atomically
                     let current_epoch = SystemTime::now()
                         .duration_since(UNIX_EPOCH)
@@ -196,47 +346,50 @@ impl WebSocketProxyHandler {
                         Ok(WsMessage::Text(text)) => {
                             debug!("Forwarding text message from client: {} bytes", text.len());
 
-                            // Check message size
-                            if text.len() > MAX_MESSAGE_SIZE {
-                                error!("Message too large: {} bytes", text.len());
+                            if !check_client_frame(
+                                &client_sink,
+                                &mut rate_limiter,
+                                &policy,
+                                text.len(),
+                            )
+                            .await
+                            {
                                 break;
                             }
 
-                            // Handle correlation tracking if enabled
-                            let final_message = if let Some(ref tracker) = correlation_tracker_clone
-                            {
-                                let text_str = text.to_string();
-
-                                // Check if this is a request message that needs correlation
-                                if MessageProcessor::is_request_message(&text_str) {
-                                    let mut tracker_guard = tracker.lock().await;
-                                    let correlation_id = tracker_guard.generate_correlation_id();
-
-                                    match MessageProcessor::inject_correlation_id(
-                                        &text_str,
-                                        &correlation_id,
-                                    ) {
-                                        Ok(modified_message) => {
-                                            tracker_guard.add_pending_request(
-                                                correlation_id.clone(),
-                                                text_str,
-                                            );
-                                            debug!(
-                                                "Added correlation tracking for request: {}",
-                                                correlation_id
-                                            );
-                                            modified_message
+                            // Control messages are answered here; anything
+                            // else that is JSON goes to the backend
+                            let (outbound, reply) = match control::parse(&text) {
+                                Ok(None) => (text.to_string(), None),
+                                Ok(Some(frame)) => {
+                                    match control::handle(frame, &filter, can_subscribe) {
+                                        Action::Reply(reply) => {
+                                            if !send_client_text(&client_sink, reply).await {
+                                                break;
+                                            }
+                                            continue;
                                         }
-                                        Err(e) => {
-                                            warn!("Failed to inject correlation ID: {}, sending original", e);
-                                            text_str
+                                        Action::Subscribe { request, reply } => {
+                                            can_subscribe = false;
+                                            (request, Some(reply))
                                         }
                                     }
-                                } else {
-                                    text_str
                                 }
+                                Err(e) => {
+                                    debug!("Rejecting client frame: {}", e.message);
+                                    if !send_client_text(&client_sink, e.to_frame()).await {
+                                        break;
+                                    }
+                                    continue;
+                                }
+                            };
+
+                            // Handle correlation tracking if enabled
+                            let final_message = if let Some(ref tracker) = correlation_tracker_clone
+                            {
+                                tracker.lock().await.track_outbound(outbound)
                             } else {
-                                text.to_string()
+                                outbound
                             };
 
                             let tungstenite_msg = TungsteniteMessage::Text(final_message.into());
@@ -251,6 +404,12 @@ impl WebSocketProxyHandler {
                                 let _ = sink.close().await;
                                 break;
                             }
+                            drop(sink);
+                            if let Some(reply) = reply {
+                                if !send_client_text(&client_sink, reply).await {
+                                    break;
+                                }
+                            }
 
                             // Update connection activity
                             connection_manager.update_activity(backend_conn_id).await;
@@ -261,9 +420,14 @@ impl WebSocketProxyHandler {
                                 data.len()
                             );
 
-                            // Check message size
-                            if data.len() > MAX_MESSAGE_SIZE {
-                                error!("Message too large: {} bytes", data.len());
+                            if !check_client_frame(
+                                &client_sink,
+                                &mut rate_limiter,
+                                &policy,
+                                data.len(),
+                            )
+                            .await
+                            {
                                 break;
                             }
 
@@ -320,12 +484,13 @@ impl WebSocketProxyHandler {
             let connection_manager = self.connection_manager.clone();
             let activity_tracker = activity_tracker.clone();
             let correlation_tracker_clone = correlation_tracker.clone();
+            let filter = filter.clone();
 
             actix_web::rt::spawn(async move {
                 let mut backend_stream = backend_stream;
 
                 loop {
-                    let msg = timeout(CLIENT_TIMEOUT, backend_stream.next()).await;
+                    let msg = timeout(policy.idle_timeout(), backend_stream.next()).await;
 
                     match msg {
                         Ok(Some(Ok(msg))) => {
@@ -338,6 +503,16 @@ impl WebSocketProxyHandler {
 
                             let client_msg = match msg {
                                 TungsteniteMessage::Text(text) => {
+                                    let wanted = filter
+                                        .read()
+                                        .unwrap_or_else(|e| e.into_inner())
+                                        .matches_text(&text);
+                                    if !wanted {
+                                        debug!(
+                                            "Dropping backend event the subscriber filtered out"
+                                        );
+                                        continue;
+                                    }
                                     debug!(
                                         "Forwarding text message from backend: {} bytes",
                                         text.len()
@@ -347,29 +522,21 @@ impl WebSocketProxyHandler {
                                     let final_text =
                                         if let Some(ref tracker) = correlation_tracker_clone {
                                             let text_str = text.to_string();
-
-                                            // Check if this is a response with correlation ID
-                                            if let Some(correlation_id) =
-                                                MessageProcessor::extract_correlation_id(&text_str)
-                                            {
-                                                let mut tracker_guard = tracker.lock().await;
-                                                if let Some(original_request) = tracker_guard
-                                                    .remove_pending_request(&correlation_id)
-                                                {
-                                                    info!(
+                                            let mut tracker_guard = tracker.lock().await;
+                                            let matched = tracker_guard.match_inbound(&text_str);
+                                            if let Some(original_request) = &matched {
+                                                info!(
                                                     "Matched response to request {} (took {:?})",
-                                                    correlation_id,
+                                                    original_request.correlation_id,
                                                     original_request.sent_at.elapsed()
                                                 );
-                                                    // Could add request/response logging here
-                                                    debug!(
-                                                        "Original request: {}",
-                                                        original_request.original_message
-                                                    );
-                                                    debug!("Response: {}", text_str);
-                                                }
+                                                debug!(
+                                                    "Original request: {}",
+                                                    original_request.original_message
+                                                );
+                                                debug!("Response: {}", text_str);
                                             }
-                                            text_str
+                                            tracker_guard.frame_inbound(text_str, matched.as_ref())
                                         } else {
                                             text.to_string()
                                         };
@@ -451,6 +618,7 @@ impl WebSocketProxyHandler {
                             info!("Backend WebSocket stream ended");
                             break;
                         }
+                        Err(_) if !is_idle(&activity_tracker, policy.idle_timeout()) => continue,
                         Err(_) => {
                             warn!("Backend connection timeout");
                             break;
@@ -608,6 +776,64 @@ impl WebSocketProxyHandler {
     }
 }
 
+/// True when neither side of the session has sent anything for `idle`.
+fn is_idle(last_activity_epoch: &AtomicU64, idle: Duration) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    now.saturating_sub(last_activity_epoch.load(Ordering::Relaxed)) >= idle.as_secs()
+}
+
+async fn close_client(client: &Mutex<Session>, code: actix_ws::CloseCode, description: &str) {
+    let session = client.lock().await.clone();
+    let _ = session
+        .close(Some(actix_ws::CloseReason {
+            code,
+            description: Some(description.to_string()),
+        }))
+        .await;
+}
+
+/// Sends a gateway-generated text frame; false when the client is gone.
+async fn send_client_text(client: &Mutex<Session>, text: String) -> bool {
+    let mut session = client.lock().await;
+    match timeout(MESSAGE_TIMEOUT, session.text(text)).await {
+        Ok(Ok(())) => true,
+        _ => {
+            error!("Failed to send control reply to client");
+            false
+        }
+    }
+}
+
+/// Applies the session policy to a client frame, closing the session when
+/// it is violated.
+async fn check_client_frame(
+    client: &Mutex<Session>,
+    rate_limiter: &mut MessageRateLimiter,
+    policy: &WsPolicy,
+    len: usize,
+) -> bool {
+    if !rate_limiter.allow() {
+        warn!(
+            "WebSocket client exceeded {} messages per minute",
+            policy.messages_per_minute
+        );
+        close_client(client, actix_ws::CloseCode::Policy, "Rate limit exceeded").await;
+        return false;
+    }
+    if len > policy.max_message_bytes {
+        error!(
+            "Message too large: {} bytes, max: {} bytes",
+            len, policy.max_message_bytes
+        );
+        close_client(client, actix_ws::CloseCode::Size, "Message too large").await;
+        return false;
+    }
+    true
+}
+
 impl Clone for WebSocketProxyHandler {
     fn clone(&self) -> Self {
         Self {
@@ -733,6 +959,18 @@ mod tests {
         assert_eq!(handler.active_session_count().await, 0);
     }
 
+    #[tokio::test]
+    async fn test_idle_check_uses_last_activity() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let recent = AtomicU64::new(now);
+        let stale = AtomicU64::new(now - 120);
+        assert!(!is_idle(&recent, Duration::from_secs(60)));
+        assert!(is_idle(&stale, Duration::from_secs(60)));
+    }
+
     #[tokio::test]
     async fn test_proxy_session_with_correlation() {
         let manager = Arc::new(WebSocketConnectionManager::new(
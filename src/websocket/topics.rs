@@ -0,0 +1,173 @@
+//! Topics of the generic `GET /v1/taproot-assets/subscribe?topic=` route:
+//! one WebSocket entry point for every tapd stream the gateway proxies.
+//! Each topic names the backend endpoint the session is connected to
+//! through the connection manager, and the session limits and correlation
+//! of the stream's dedicated route.
+
+use super::correlation::CorrelationStrategy;
+use super::filter::EventFilter;
+use super::policy::EndpointGroup;
+use crate::error::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topic {
+    AssetMint,
+    AssetReceive,
+    AssetSend,
+    MailboxReceive,
+    SendPayment,
+    Rfq,
+}
+
+impl Topic {
+    pub const ALL: [Topic; 6] = [
+        Self::AssetMint,
+        Self::AssetReceive,
+        Self::AssetSend,
+        Self::MailboxReceive,
+        Self::SendPayment,
+        Self::Rfq,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::AssetMint => "asset-mint",
+            Self::AssetReceive => "asset-receive",
+            Self::AssetSend => "asset-send",
+            Self::MailboxReceive => "mailbox-receive",
+            Self::SendPayment => "send-payment",
+            Self::Rfq => "rfq",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|topic| topic.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// The gateway's dedicated route for the stream, which route filters
+    /// and route groups are checked against.
+    pub fn route(self) -> &'static str {
+        match self {
+            Self::AssetMint => "/v1/taproot-assets/events/asset-mint",
+            Self::AssetReceive => "/v1/taproot-assets/events/asset-receive",
+            Self::AssetSend => "/v1/taproot-assets/events/asset-send",
+            Self::MailboxReceive => "/v1/taproot-assets/mailbox/receive",
+            Self::SendPayment => "/v1/taproot-assets/channels/send-payment",
+            Self::Rfq => "/v1/taproot-assets/rfq/ntfs",
+        }
+    }
+
+    /// The backend WebSocket endpoint.
+    pub fn backend_endpoint(self) -> &'static str {
+        match self {
+            Self::AssetMint => "/v1/taproot-assets/events/asset-mint?method=POST",
+            Self::AssetReceive => "/v1/taproot-assets/events/asset-receive?method=POST",
+            Self::AssetSend => "/v1/taproot-assets/events/asset-send?method=POST",
+            Self::MailboxReceive => "/v1/taproot-assets/mailbox/receive?stream=true",
+            Self::SendPayment => "/v1/taproot-assets/channels/send-payment?stream=true",
+            Self::Rfq => "/v1/taproot-assets/rfq/ntfs?method=POST",
+        }
+    }
+
+    pub fn group(self) -> EndpointGroup {
+        match self {
+            Self::MailboxReceive => EndpointGroup::Mailbox,
+            Self::SendPayment => EndpointGroup::Channels,
+            Self::AssetMint | Self::AssetReceive | Self::AssetSend | Self::Rfq => {
+                EndpointGroup::Events
+            }
+        }
+    }
+
+    /// Correlation of request/response streams; event streams have none,
+    /// and take event filters instead.
+    pub fn correlation(self) -> Option<CorrelationStrategy> {
+        match self {
+            Self::MailboxReceive | Self::SendPayment => Some(CorrelationStrategy::Sequence),
+            Self::AssetMint | Self::AssetReceive | Self::AssetSend | Self::Rfq => None,
+        }
+    }
+}
+
+/// A subscription resolved from the query of the subscribe route.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subscription {
+    pub topic: Topic,
+    pub filter: EventFilter,
+    /// The backend endpoint, with the query parameters the gateway does
+    /// not consume.
+    pub endpoint: String,
+}
+
+impl Subscription {
+    /// Reads `topic` and, for event topics, the event filter from `query`;
+    /// other parameters are passed on to the backend.
+    pub fn from_query(query: &str) -> Result<Self, AppError> {
+        let mut topic = None;
+        let mut rest = url::form_urlencoded::Serializer::new(String::new());
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            if key == "topic" {
+                topic = Some(Topic::parse(&value).ok_or_else(|| {
+                    let known: Vec<&str> = Topic::ALL.iter().map(|t| t.name()).collect();
+                    AppError::ValidationError(format!(
+                        "Unknown topic '{value}', expected one of {}",
+                        known.join(", ")
+                    ))
+                })?);
+            } else {
+                rest.append_pair(&key, &value);
+            }
+        }
+        let topic = topic.ok_or_else(|| {
+            AppError::ValidationError("The topic query parameter is required".to_string())
+        })?;
+
+        let (filter, forwarded) = EventFilter::from_query(&rest.finish())?;
+        if !filter.is_empty() && topic.correlation().is_some() {
+            return Err(AppError::ValidationError(format!(
+                "Event filters do not apply to the {} topic",
+                topic.name()
+            )));
+        }
+        let endpoint = if forwarded.is_empty() {
+            topic.backend_endpoint().to_string()
+        } else {
+            format!("{}&{forwarded}", topic.backend_endpoint())
+        };
+        Ok(Self {
+            topic,
+            filter,
+            endpoint,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription_from_query() {
+        let asset = "ab".repeat(32);
+        let sub = Subscription::from_query(&format!(
+            "topic=asset-send&asset_id={asset}&filter_label=payroll"
+        ))
+        .unwrap();
+        assert_eq!(sub.topic, Topic::AssetSend);
+        assert!(sub.filter.asset_ids.contains(&asset));
+        assert_eq!(
+            sub.endpoint,
+            "/v1/taproot-assets/events/asset-send?method=POST&filter_label=payroll"
+        );
+
+        let mailbox = Subscription::from_query("topic=MAILBOX-RECEIVE").unwrap();
+        assert_eq!(mailbox.topic.group(), EndpointGroup::Mailbox);
+        assert_eq!(mailbox.endpoint, Topic::MailboxReceive.backend_endpoint());
+
+        assert!(Subscription::from_query("").is_err());
+        assert!(Subscription::from_query("topic=asset-burn").is_err());
+        assert!(Subscription::from_query("topic=send-payment&min_amount=5").is_err());
+    }
+}
@@ -35,6 +35,9 @@ async fn test_generate_new_address() {
         proof_courier_addr: None,
         asset_version: None,
         address_version: None,
+        callback_url: None,
+        callback_secret: None,
+        ttl_secs: None,
     };
     let req = test::TestRequest::post()
         .uri("/v1/taproot-assets/addrs")
@@ -91,6 +94,9 @@ async fn test_decode_address() {
         proof_courier_addr: None,
         asset_version: None,
         address_version: None,
+        callback_url: None,
+        callback_secret: None,
+        ttl_secs: None,
     };
     let app = test::init_service(
         App::new()
@@ -159,6 +165,9 @@ async fn test_address_creation_with_custom_parameters() {
         proof_courier_addr: None,
         asset_version: Some("ASSET_VERSION_V0".to_string()),
         address_version: Some("ADDR_VERSION_V0".to_string()),
+        callback_url: None,
+        callback_secret: None,
+        ttl_secs: None,
     };
 
     let req = test::TestRequest::post()
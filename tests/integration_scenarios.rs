@@ -41,6 +41,9 @@ async fn test_complete_asset_lifecycle() {
         proof_courier_addr: None,
         asset_version: None,
         address_version: None,
+        callback_url: None,
+        callback_secret: None,
+        ttl_secs: None,
     };
     let addr_resp = test::call_service(
         &app,
@@ -66,6 +69,7 @@ async fn test_complete_asset_lifecycle() {
         fee_rate: Some(300),
         label: None,
         skip_proof_courier_ping_check: Some(true),
+        tags: Vec::new(),
     };
     let send_resp = test::call_service(
         &app,
@@ -36,6 +36,9 @@ async fn test_send_assets_basic() {
         proof_courier_addr: None,
         asset_version: None,
         address_version: None,
+        callback_url: None,
+        callback_secret: None,
+        ttl_secs: None,
     };
     let addr_resp = test::call_service(
         &app,
@@ -58,6 +61,7 @@ async fn test_send_assets_basic() {
         fee_rate: Some(300),
         label: None,
         skip_proof_courier_ping_check: Some(true),
+        tags: Vec::new(),
     };
     let req = test::TestRequest::post()
         .uri("/v1/taproot-assets/send")
@@ -107,6 +111,9 @@ async fn test_send_with_custom_fee_rate() {
         proof_courier_addr: None,
         asset_version: None,
         address_version: None,
+        callback_url: None,
+        callback_secret: None,
+        ttl_secs: None,
     };
     let addr_resp = test::call_service(
         &app,
@@ -133,6 +140,7 @@ async fn test_send_with_custom_fee_rate() {
         fee_rate: Some(500),
         label: Some("High priority transfer".to_string()),
         skip_proof_courier_ping_check: Some(true),
+        tags: Vec::new(),
     };
     let req = test::TestRequest::post()
         .uri("/v1/taproot-assets/send")
@@ -182,6 +190,9 @@ async fn test_send_multiple_outputs() {
             proof_courier_addr: None,
             asset_version: None,
             address_version: None,
+            callback_url: None,
+            callback_secret: None,
+            ttl_secs: None,
         };
         let addr_resp = test::call_service(
             &app,
@@ -219,6 +230,7 @@ async fn test_send_multiple_outputs() {
         fee_rate: Some(300),
         label: Some("Multi-output send".to_string()),
         skip_proof_courier_ping_check: Some(true),
+        tags: Vec::new(),
     };
     let req = test::TestRequest::post()
         .uri("/v1/taproot-assets/send")
@@ -267,6 +279,9 @@ async fn test_send_with_proof_courier() {
         proof_courier_addr: Some("https://127.0.0.1:8289".to_string()), // Updated to REST host
         asset_version: None,
         address_version: None,
+        callback_url: None,
+        callback_secret: None,
+        ttl_secs: None,
     };
     let addr_resp = test::call_service(
         &app,
@@ -297,6 +312,7 @@ async fn test_send_with_proof_courier() {
         fee_rate: Some(300),
         label: None,
         skip_proof_courier_ping_check: Some(false),
+        tags: Vec::new(),
     };
     let req = test::TestRequest::post()
         .uri("/v1/taproot-assets/send")
@@ -324,6 +340,7 @@ async fn test_send_validation_errors() {
         fee_rate: Some(300),
         label: None,
         skip_proof_courier_ping_check: Some(true),
+        tags: Vec::new(),
     };
     let empty_req = test::TestRequest::post()
         .uri("/v1/taproot-assets/send")
@@ -344,6 +361,7 @@ async fn test_send_validation_errors() {
         fee_rate: Some(300),
         label: None,
         skip_proof_courier_ping_check: Some(true),
+        tags: Vec::new(),
     };
     let invalid_req = test::TestRequest::post()
         .uri("/v1/taproot-assets/send")
@@ -387,6 +405,9 @@ async fn test_send_response_structure() {
         proof_courier_addr: None,
         asset_version: None,
         address_version: None,
+        callback_url: None,
+        callback_secret: None,
+        ttl_secs: None,
     };
     let addr_resp = test::call_service(
         &app,
@@ -403,6 +424,7 @@ async fn test_send_response_structure() {
         fee_rate: Some(300),
         label: Some("Test send".to_string()),
         skip_proof_courier_ping_check: Some(true),
+        tags: Vec::new(),
     };
     let req = test::TestRequest::post()
         .uri("/v1/taproot-assets/send")
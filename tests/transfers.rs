@@ -42,6 +42,9 @@ async fn test_complete_transfer_workflow() {
         proof_courier_addr: None,
         asset_version: None,
         address_version: None,
+        callback_url: None,
+        callback_secret: None,
+        ttl_secs: None,
     };
     let addr_resp = test::call_service(
         &app,
@@ -60,6 +63,7 @@ async fn test_complete_transfer_workflow() {
         fee_rate: Some(300),
         label: Some("Transfer test".to_string()),
         skip_proof_courier_ping_check: Some(true),
+        tags: Vec::new(),
     };
     let send_body = serde_json::to_value(&send_req).expect("serialize send request");
 
@@ -351,6 +355,9 @@ async fn test_transfer_output_types() {
         proof_courier_addr: None,
         asset_version: None,
         address_version: None,
+        callback_url: None,
+        callback_secret: None,
+        ttl_secs: None,
     };
     let addr_resp = test::call_service(
         &app,
@@ -380,6 +387,7 @@ async fn test_transfer_output_types() {
         fee_rate: Some(300),
         label: Some("Split test".to_string()),
         skip_proof_courier_ping_check: Some(true),
+        tags: Vec::new(),
     };
     let send_resp = test::call_service(
         &app,